@@ -0,0 +1,174 @@
+//! Cohere embeddings for llm-chain.
+//!
+//! Cohere's embed v3 models embed a text differently depending on its `input_type`. `Embeddings` embeds documents
+//! with `embed_texts` as `search_document` and queries with `embed_query` as `search_query`, so it works as expected in
+//! vector stores and retrievers. Other purposes, such as classification, are available through
+//! `Embeddings::embed_texts_for`.
+use async_trait::async_trait;
+use llm_chain::traits::{self, EmbeddingPurpose, EmbeddingsError, TruncationPolicy};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+const EMBED_URL: &str = "https://api.cohere.ai/v1/embed";
+/// The maximum number of texts the API accepts in one request.
+const MAX_BATCH_SIZE: usize = 96;
+
+#[derive(Debug, Error)]
+pub enum CohereEmbeddingsError {
+    #[error("The COHERE_API_KEY environment variable is not set")]
+    MissingApiKey,
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+    #[error("The API returned {returned} embeddings for {expected} texts")]
+    UnexpectedResponse { expected: usize, returned: usize },
+}
+
+impl EmbeddingsError for CohereEmbeddingsError {}
+
+#[derive(Serialize)]
+struct EmbedRequest<'a> {
+    model: &'a str,
+    texts: &'a [String],
+    input_type: &'static str,
+    truncate: &'static str,
+}
+
+#[derive(Deserialize)]
+struct EmbedResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+fn input_type(purpose: EmbeddingPurpose) -> &'static str {
+    match purpose {
+        EmbeddingPurpose::Document => "search_document",
+        EmbeddingPurpose::Query => "search_query",
+        EmbeddingPurpose::Classification => "classification",
+        EmbeddingPurpose::Clustering => "clustering",
+    }
+}
+
+fn truncate(policy: TruncationPolicy) -> &'static str {
+    match policy {
+        TruncationPolicy::Error => "NONE",
+        TruncationPolicy::End => "END",
+        TruncationPolicy::Start => "START",
+    }
+}
+
+/// Returns the length of the vectors of Cohere's embedding models.
+fn model_dimensions(model: &str) -> Option<usize> {
+    match model {
+        "embed-english-v3.0" | "embed-multilingual-v3.0" | "embed-english-light-v2.0" => Some(1024),
+        "embed-english-light-v3.0" | "embed-multilingual-light-v3.0" => Some(384),
+        "embed-english-v2.0" => Some(4096),
+        "embed-multilingual-v2.0" => Some(768),
+        _ => None,
+    }
+}
+
+/// Embeddings computed with Cohere's embed API.
+///
+/// Inputs longer than the model accepts are truncated at the end by default; see `with_truncation_policy`.
+pub struct Embeddings {
+    client: reqwest::Client,
+    api_key: String,
+    model: String,
+    truncation_policy: TruncationPolicy,
+}
+
+impl Embeddings {
+    /// Creates embeddings using `embed-english-v3.0`.
+    pub fn new(api_key: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            api_key,
+            model: "embed-english-v3.0".to_string(),
+            truncation_policy: TruncationPolicy::End,
+        }
+    }
+
+    /// Creates embeddings with the API key in the `COHERE_API_KEY` environment variable.
+    pub fn from_env() -> Result<Self, CohereEmbeddingsError> {
+        std::env::var("COHERE_API_KEY")
+            .map(Self::new)
+            .map_err(|_| CohereEmbeddingsError::MissingApiKey)
+    }
+
+    /// Sets the model to use, such as `embed-multilingual-v3.0`.
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.model = model.to_string();
+        self
+    }
+
+    /// Sets what the API does with inputs longer than the model accepts.
+    pub fn with_truncation_policy(mut self, truncation_policy: TruncationPolicy) -> Self {
+        self.truncation_policy = truncation_policy;
+        self
+    }
+
+    async fn embed_batch(
+        &self,
+        texts: &[String],
+        purpose: EmbeddingPurpose,
+    ) -> Result<Vec<Vec<f32>>, CohereEmbeddingsError> {
+        let response = self
+            .client
+            .post(EMBED_URL)
+            .bearer_auth(&self.api_key)
+            .json(&EmbedRequest {
+                model: &self.model,
+                texts,
+                input_type: input_type(purpose),
+                truncate: truncate(self.truncation_policy),
+            })
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<EmbedResponse>()
+            .await?;
+        if response.embeddings.len() != texts.len() {
+            return Err(CohereEmbeddingsError::UnexpectedResponse {
+                expected: texts.len(),
+                returned: response.embeddings.len(),
+            });
+        }
+        Ok(response.embeddings)
+    }
+}
+
+#[async_trait]
+impl traits::Embeddings for Embeddings {
+    type Error = CohereEmbeddingsError;
+
+    async fn embed_texts(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Self::Error> {
+        self.embed_texts_for(texts, EmbeddingPurpose::Document)
+            .await
+    }
+
+    async fn embed_query(&self, query: String) -> Result<Vec<f32>, Self::Error> {
+        let mut embeddings = self
+            .embed_texts_for(vec![query], EmbeddingPurpose::Query)
+            .await?;
+        Ok(embeddings.remove(0))
+    }
+
+    async fn embed_texts_for(
+        &self,
+        texts: Vec<String>,
+        purpose: EmbeddingPurpose,
+    ) -> Result<Vec<Vec<f32>>, Self::Error> {
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(MAX_BATCH_SIZE) {
+            embeddings.extend(self.embed_batch(batch, purpose).await?);
+        }
+        Ok(embeddings)
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        model_dimensions(&self.model)
+    }
+
+    fn truncation_policy(&self) -> Option<TruncationPolicy> {
+        Some(self.truncation_policy)
+    }
+}
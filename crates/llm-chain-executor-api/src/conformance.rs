@@ -0,0 +1,136 @@
+//! Checks every executor should pass.
+//!
+//! The checks panic with a message describing the first problem they find, so they can be called directly from
+//! tests. `check_executor` doesn't invoke the model and can run anywhere; `check_execution` sends a short prompt to
+//! the model.
+use crate::{Executor, Output, Prompt, TextSplitter, Tokenizer};
+
+const SAMPLES: &[&str] = &[
+    "Hello, world!",
+    "The quick brown fox jumps over the lazy dog.",
+    "Ünïcödé text, emoji 🦀 and numbers 12345.",
+    "  leading and trailing whitespace  ",
+];
+
+const LONG_TEXT: &str = "Large language models are trained on huge amounts of text. They predict the next token of \
+    a text given the tokens before it, which lets them complete prompts, answer questions and follow instructions. \
+    Chains combine several prompts, passing the output of one step as the input of the next, so that tasks too large \
+    for a single prompt can still be completed.";
+
+fn non_whitespace(text: &str) -> String {
+    text.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+/// Checks that `tokenizer` turns every sample into tokens and back into the same text, ignoring whitespace.
+pub fn check_tokenizer<T: Clone, K: Tokenizer<T>>(tokenizer: &K, samples: &[&str]) {
+    for sample in samples {
+        let tokens = tokenizer
+            .tokenize_str(sample)
+            .unwrap_or_else(|e| panic!("tokenizing {:?} failed: {:?}", sample, e));
+        assert!(
+            !tokens.is_empty() || sample.trim().is_empty(),
+            "tokenizing {:?} returned no tokens",
+            sample
+        );
+        let text = tokenizer.to_string(tokens).unwrap_or_else(|e| {
+            panic!("converting the tokens of {:?} back failed: {:?}", sample, e)
+        });
+        assert_eq!(
+            non_whitespace(&text),
+            non_whitespace(sample),
+            "converting the tokens of {:?} back returned {:?}",
+            sample,
+            text
+        );
+    }
+}
+
+/// Checks that `splitter` splits `text` into non-empty chunks of at most `max_tokens` tokens, which together contain
+/// all of `text`.
+pub fn check_text_splitter<T: Clone, S: TextSplitter<T>>(
+    splitter: &S,
+    text: &str,
+    max_tokens: usize,
+) {
+    let chunks = splitter
+        .split_text(text, max_tokens, 0)
+        .unwrap_or_else(|e| panic!("splitting the text failed: {:?}", e));
+    for chunk in &chunks {
+        let tokens = splitter
+            .tokenize_str(chunk)
+            .unwrap_or_else(|e| panic!("tokenizing the chunk {:?} failed: {:?}", chunk, e));
+        assert!(!tokens.is_empty(), "the splitter returned an empty chunk");
+        assert!(
+            tokens.len() <= max_tokens,
+            "the chunk {:?} has {} tokens, more than the maximum of {}",
+            chunk,
+            tokens.len(),
+            max_tokens
+        );
+    }
+    assert_eq!(
+        non_whitespace(&chunks.concat()),
+        non_whitespace(text),
+        "the chunks don't add up to the text"
+    );
+}
+
+/// Checks the methods of `executor` that don't invoke the model: token counting, the tokenizer and the text
+/// splitter, with the default invocation options.
+pub fn check_executor<E: Executor>(executor: &E) {
+    let max_tokens = executor.max_tokens_allowed(None);
+    assert!(max_tokens > 0, "max_tokens_allowed returned {}", max_tokens);
+
+    let short = executor
+        .tokens_used(None, &Prompt::text("Hello".to_string()))
+        .unwrap_or_else(|e| panic!("counting the tokens of a prompt failed: {:?}", e));
+    let long = executor
+        .tokens_used(None, &Prompt::text(LONG_TEXT.to_string()))
+        .unwrap_or_else(|e| panic!("counting the tokens of a prompt failed: {:?}", e));
+    assert!(short.tokens_used() > 0, "a prompt counted as 0 tokens");
+    assert!(
+        long.tokens_used() > short.tokens_used(),
+        "a longer prompt didn't count as more tokens"
+    );
+    assert_eq!(
+        short.tokens_used() + short.tokens_remaining(),
+        max_tokens,
+        "tokens_used doesn't count against max_tokens_allowed"
+    );
+
+    let tokenizer = executor
+        .get_tokenizer(None)
+        .unwrap_or_else(|e| panic!("creating the tokenizer failed: {:?}", e));
+    check_tokenizer(&tokenizer, SAMPLES);
+
+    let splitter = executor
+        .get_text_splitter(None)
+        .unwrap_or_else(|e| panic!("creating the text splitter failed: {:?}", e));
+    check_text_splitter(&splitter, LONG_TEXT, 16);
+}
+
+/// Sends a short prompt to the model and checks that `executor` returns a textual answer.
+pub async fn check_execution<E: Executor>(executor: &E) {
+    let prompt = Prompt::text("Reply with the word hello.".to_string());
+    let output = executor
+        .execute(None, &prompt, None)
+        .await
+        .unwrap_or_else(|e| panic!("executing a prompt failed: {:?}", e));
+    let text = output.primary_textual_output().await;
+    assert!(
+        text.is_some_and(|text| !text.trim().is_empty()),
+        "the output has no text"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_text_splitter, check_tokenizer, LONG_TEXT, SAMPLES};
+    use llm_chain::NaiveWhitespaceSplitter;
+
+    #[test]
+    fn whitespace_splitter_conforms() {
+        check_tokenizer(&NaiveWhitespaceSplitter, SAMPLES);
+        check_text_splitter(&NaiveWhitespaceSplitter, LONG_TEXT, 16);
+    }
+}
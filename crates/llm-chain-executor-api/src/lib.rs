@@ -0,0 +1,37 @@
+//! The API for implementing llm-chain executors.
+//!
+//! Crates providing executors for new models only need a small part of `llm-chain`: the `Executor` trait and the
+//! types it uses. This crate re-exports exactly that part under fixed paths, so that provider crates don't depend on
+//! how the modules of `llm-chain` are organized. The items are defined in `llm-chain` and this crate is released
+//! with it, at the same version: a breaking change of an item here is a breaking change of `llm-chain`.
+//!
+//! The `conformance` module contains checks every executor should pass, to be called from the tests of a provider
+//! crate.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use llm_chain_executor_api::{Executor, ExecutorCreationError, Prompt};
+//!
+//! struct MyExecutor { /* ... */ }
+//!
+//! #[async_trait::async_trait]
+//! impl Executor for MyExecutor {
+//!     // ...
+//! }
+//!
+//! #[test]
+//! fn conforms() {
+//!     llm_chain_executor_api::conformance::check_executor(&MyExecutor::new().unwrap());
+//! }
+//! ```
+pub mod conformance;
+
+pub use llm_chain::options::{FromPreset, Preset, ProviderFamily, Sampling};
+pub use llm_chain::output::Output;
+pub use llm_chain::prompt::{
+    ChatMessage, ChatMessageCollection, ChatRole, Data, ImagePart, Prompt,
+};
+pub use llm_chain::text_splitter::TextSplitter;
+pub use llm_chain::tokens::{PromptTokensError, TokenCount, Tokenizer, TokenizerError};
+pub use llm_chain::traits::{Executor, ExecutorCreationError, ExecutorError, Options};
@@ -0,0 +1,26 @@
+use llm_chain::{schema::Document, traits::VectorStore, vectorstores::InMemoryVectorStore};
+use llm_chain_fastembed::Embeddings;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    // The model is downloaded on first use; everything else runs locally.
+    let embeddings = Embeddings::new().unwrap();
+    let store: InMemoryVectorStore<Embeddings> = InMemoryVectorStore::new(embeddings);
+
+    store
+        .add_documents(vec![
+            Document::new("The dog is a domesticated descendant of the wolf.".to_string()),
+            Document::new("The Rust programming language emphasizes memory safety.".to_string()),
+            Document::new("Sourdough bread is leavened by wild yeast.".to_string()),
+        ])
+        .await
+        .unwrap();
+
+    let results = store
+        .similarity_search("Which animals are related to wolves?".to_string(), 1)
+        .await
+        .unwrap();
+    for document in results {
+        println!("{}", document.page_content);
+    }
+}
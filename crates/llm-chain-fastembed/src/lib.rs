@@ -0,0 +1,133 @@
+//! Local embeddings for llm-chain using fastembed.
+//!
+//! `Embeddings` runs ONNX sentence-transformer models on the CPU, so documents can be embedded without sending them to
+//! an API. Paired with a local executor such as `llm-chain-llama`, retrieval-augmented chains can run fully offline.
+//!
+//! Models are downloaded from Hugging Face on first use and cached; set `InitOptions::cache_dir` to a directory that
+//! already contains the model to avoid network access entirely.
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use llm_chain::traits::{self, EmbeddingsError, TruncationPolicy};
+use thiserror::Error;
+
+pub use fastembed::{EmbeddingModel, InitOptions};
+
+#[derive(Debug, Error)]
+pub enum FastEmbedError {
+    #[error("Error running the embedding model: {0}")]
+    Model(Box<dyn std::error::Error + Send + Sync>),
+    #[error("The embedding task panicked or was cancelled: {0}")]
+    Join(#[from] tokio::task::JoinError),
+    #[error("The embedding model returned no embedding")]
+    EmptyResponse,
+}
+
+impl EmbeddingsError for FastEmbedError {}
+
+impl From<anyhow::Error> for FastEmbedError {
+    fn from(error: anyhow::Error) -> Self {
+        Self::Model(error.into())
+    }
+}
+
+/// Embeddings computed locally with a fastembed model.
+///
+/// Embedding is CPU-bound, so it runs on tokio's blocking thread pool to keep the async runtime responsive.
+///
+/// Some models, like the E5 family, expect inputs to be marked as queries or passages. Use `with_query_prefix` and
+/// `with_document_prefix` to add the prefixes the model was trained with.
+#[derive(Clone)]
+pub struct Embeddings {
+    model: Arc<fastembed::TextEmbedding>,
+    dimensions: Option<usize>,
+    batch_size: Option<usize>,
+    query_prefix: String,
+    document_prefix: String,
+}
+
+impl Embeddings {
+    /// Loads the default model, `BAAI/bge-small-en-v1.5`.
+    pub fn new() -> Result<Self, FastEmbedError> {
+        Self::try_new(InitOptions::default())
+    }
+
+    /// Loads the given model with the default options.
+    pub fn for_model(model: EmbeddingModel) -> Result<Self, FastEmbedError> {
+        Self::try_new(InitOptions {
+            model_name: model,
+            ..Default::default()
+        })
+    }
+
+    /// Loads a model with the given options.
+    pub fn try_new(options: InitOptions) -> Result<Self, FastEmbedError> {
+        let dimensions = fastembed::TextEmbedding::list_supported_models()
+            .into_iter()
+            .find(|info| info.model == options.model_name)
+            .map(|info| info.dim);
+        let model = fastembed::TextEmbedding::try_new(options)?;
+        Ok(Self {
+            model: Arc::new(model),
+            dimensions,
+            batch_size: None,
+            query_prefix: String::new(),
+            document_prefix: String::new(),
+        })
+    }
+
+    /// Sets the number of texts run through the model at once. Defaults to fastembed's batch size.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = Some(batch_size);
+        self
+    }
+
+    /// Sets a prefix added to queries before embedding them, such as `"query: "`.
+    pub fn with_query_prefix<S: Into<String>>(mut self, prefix: S) -> Self {
+        self.query_prefix = prefix.into();
+        self
+    }
+
+    /// Sets a prefix added to documents before embedding them, such as `"passage: "`.
+    pub fn with_document_prefix<S: Into<String>>(mut self, prefix: S) -> Self {
+        self.document_prefix = prefix.into();
+        self
+    }
+
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, FastEmbedError> {
+        let model = self.model.clone();
+        let batch_size = self.batch_size;
+        let embeddings =
+            tokio::task::spawn_blocking(move || model.embed(texts, batch_size)).await??;
+        Ok(embeddings)
+    }
+}
+
+#[async_trait]
+impl traits::Embeddings for Embeddings {
+    type Error = FastEmbedError;
+
+    async fn embed_texts(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Self::Error> {
+        let texts = texts
+            .into_iter()
+            .map(|text| format!("{}{}", self.document_prefix, text))
+            .collect();
+        self.embed(texts).await
+    }
+
+    async fn embed_query(&self, query: String) -> Result<Vec<f32>, Self::Error> {
+        self.embed(vec![format!("{}{}", self.query_prefix, query)])
+            .await?
+            .pop()
+            .ok_or(FastEmbedError::EmptyResponse)
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        self.dimensions
+    }
+
+    /// fastembed cuts inputs off at the model's maximum length.
+    fn truncation_policy(&self) -> Option<TruncationPolicy> {
+        Some(TruncationPolicy::End)
+    }
+}
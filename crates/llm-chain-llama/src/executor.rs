@@ -7,6 +7,7 @@ use crate::LLamaTextSplitter;
 use crate::output::Output;
 use async_trait::async_trait;
 
+use llm_chain::output::{FinishReason, Usage};
 use llm_chain::prompt::{ChatRole, Prompt};
 
 use llm_chain::tokens::{PromptTokensError, TokenCount};
@@ -14,9 +15,12 @@ use llm_chain::tokens::{Tokenizer, TokenizerError};
 use llm_chain::traits::{Executor as ExecutorTrait, ExecutorCreationError, ExecutorError};
 
 use llm_chain_llama_sys::llama_context_params;
+use std::sync::{RwLock, RwLockReadGuard};
+
 /// Executor is responsible for running the LLAMA model and managing its context.
 pub struct Executor {
-    context: LLamaContext,
+    /// The context of the model, `None` once the executor is shut down.
+    context: RwLock<Option<LLamaContext>>,
     options: Option<PerExecutor>,
     callback: Option<fn(&Output)>,
     invocation_options: Option<PerInvocation>,
@@ -35,20 +39,22 @@ impl Executor {
         ContextParams::or_default(cp)
     }
 
-    pub(crate) fn get_context(&self) -> &LLamaContext {
-        &self.context
+    pub(crate) fn get_context(&self) -> RwLockReadGuard<'_, Option<LLamaContext>> {
+        self.context.read().expect("context lock poisoned")
     }
 }
 
 impl Executor {
     // Run the LLAMA model with the provided input and generate output.
     // Executes the model with the provided input and context parameters.
-    fn run_model(&self, input: LlamaInvocation) -> Output {
+    fn run_model(&self, input: LlamaInvocation) -> Result<Output, Error> {
+        let context = self.get_context();
+        let context = context.as_ref().ok_or(Error::ShutDown)?;
         // Tokenize the stop sequence and input prompt.
         let context_params = self.context_params();
 
         let tokenized_stop_prompt = tokenize(
-            &self.context,
+            context,
             input.stop_sequence.as_str(),
             context_params.n_ctx as usize,
             false,
@@ -57,7 +63,7 @@ impl Executor {
 
         let prompt_text = input.prompt.to_text();
         let tokenized_input = tokenize(
-            &self.context,
+            context,
             prompt_text.as_str(),
             context_params.n_ctx as usize,
             true,
@@ -67,7 +73,7 @@ impl Executor {
         let mut embd = tokenized_input.clone();
 
         // Evaluate the prompt in full.
-        self.context
+        context
             .llama_eval(
                 tokenized_input.as_slice(),
                 tokenized_input.len() as i32,
@@ -80,14 +86,14 @@ impl Executor {
         let mut n_used = tokenized_input.len() - 1;
         if let Some(prefix) = self.answer_prefix(&input.prompt) {
             let tokenized_answer_prefix = tokenize(
-                &self.context,
+                context,
                 prefix.as_str(),
                 context_params.n_ctx as usize,
                 false,
             )
             .unwrap();
             // Evaluate the answer prefix (the role -- should be Assistant: )
-            self.context
+            context
                 .llama_eval(
                     tokenized_answer_prefix.as_slice(),
                     tokenized_answer_prefix.len() as i32,
@@ -100,20 +106,20 @@ impl Executor {
             embd.extend(tokenized_answer_prefix);
         }
         embd.resize(context_params.n_ctx as usize, 0);
+        let n_prompt = n_used + 1;
         let token_eos = llama_token_eos();
         let mut stop_sequence_i = 0;
+        // Generation stops at the end of the context unless something else stops it first.
+        let mut finish_reason = FinishReason::Length;
         // Generate remaining tokens.
         while n_remaining > 0 {
-            let tok = self.context.llama_sample(
-                context_params.n_ctx,
-                embd.as_slice(),
-                n_used as i32,
-                &input,
-            );
+            let tok =
+                context.llama_sample(context_params.n_ctx, embd.as_slice(), n_used as i32, &input);
             n_used += 1;
             n_remaining -= 1;
             embd[n_used] = tok;
             if tok == token_eos {
+                finish_reason = FinishReason::Stop;
                 break;
             }
             if input.n_tok_predict != 0 && n_used > input.n_tok_predict + tokenized_input.len() - 1
@@ -123,24 +129,27 @@ impl Executor {
             if tok == tokenized_stop_prompt[stop_sequence_i] {
                 stop_sequence_i += 1;
                 if stop_sequence_i >= tokenized_stop_prompt.len() {
+                    finish_reason = FinishReason::Stop;
                     break;
                 }
             } else {
                 stop_sequence_i = 0;
             }
-            self.context
+            context
                 .llama_eval(&embd[n_used..], 1, n_used as i32, &input)
                 .unwrap();
 
             if let Some(callback) = self.callback {
-                let output = self.context.llama_token_to_str(&embd[n_used]);
+                let output = context.llama_token_to_str(&embd[n_used]);
                 callback(&output.into());
             }
         }
-        embedding_to_output(
-            &self.context,
+        let usage = Usage::new(n_prompt as u32, (n_used + 1 - n_prompt) as u32);
+        Ok(embedding_to_output(
+            context,
             &embd[tokenized_input.len()..n_used + 1 - stop_sequence_i],
         )
+        .with_metadata(usage, finish_reason))
     }
 }
 
@@ -148,6 +157,8 @@ impl Executor {
 pub enum Error {
     #[error("unable to tokenize prompt")]
     PromptTokensError(PromptTokensError),
+    #[error("the executor has been shut down")]
+    ShutDown,
 }
 
 impl ExecutorError for Error {}
@@ -178,7 +189,10 @@ impl ExecutorTrait for Executor {
                 "model_path, ensure to provide the parameter or set `LLAMA_MODEL_PATH` environment variable ".to_string(),
             ))?;
         Ok(Self {
-            context: LLamaContext::from_file_and_params(&model_path, context_params.as_ref()),
+            context: RwLock::new(Some(LLamaContext::from_file_and_params(
+                &model_path,
+                context_params.as_ref(),
+            ))),
             options: executor_options,
             callback: None,
             invocation_options,
@@ -196,7 +210,7 @@ impl ExecutorTrait for Executor {
             None => self.invocation_options.clone().unwrap_or_default(),
         };
         let invocation = config.to_invocation(prompt);
-        Ok(self.run_model(invocation))
+        self.run_model(invocation)
     }
 
     fn tokens_used(
@@ -255,28 +269,52 @@ impl ExecutorTrait for Executor {
     ) -> Result<Self::TextSplitter<'_>, Self::Error> {
         Ok(LLamaTextSplitter::new(self))
     }
+
+    /// Frees the context of the model, after the running invocations and the tokenizers and text splitters in use
+    /// are dropped. Later invocations fail with `Error::ShutDown`.
+    async fn shutdown(&self) -> Result<(), Self::Error> {
+        self.context.write().expect("context lock poisoned").take();
+        Ok(())
+    }
 }
 
 pub struct LLamaTokenizer<'a> {
-    context: &'a LLamaContext,
+    context: RwLockReadGuard<'a, Option<LLamaContext>>,
 }
 
 impl<'a> LLamaTokenizer<'a> {
     pub fn new(executor: &'a Executor) -> Self {
         LLamaTokenizer {
-            context: &executor.context,
+            context: executor.get_context(),
         }
     }
 }
 
 impl Tokenizer<i32> for LLamaTokenizer<'_> {
     fn tokenize_str(&self, doc: &str) -> Result<Vec<i32>, TokenizerError> {
-        let tokenized = llama_tokenize_helper(self.context, doc, true);
+        let context = self
+            .context
+            .as_ref()
+            .ok_or(TokenizerError::TokenizationError)?;
+        let tokenized = llama_tokenize_helper(context, doc, true);
         Ok(tokenized)
     }
 
     fn to_string(&self, tokens: Vec<i32>) -> Result<String, TokenizerError> {
-        let output = embedding_to_output(self.context, &tokens);
+        let context = self.context.as_ref().ok_or(TokenizerError::ToStringError)?;
+        let output = embedding_to_output(context, &tokens);
         Ok(output.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[ignore = "needs a model at `LLAMA_MODEL_PATH`"]
+    fn conforms() {
+        let executor = Executor::new_with_options(None, None).unwrap();
+        llm_chain_executor_api::conformance::check_executor(&executor);
+    }
+}
@@ -1,3 +1,4 @@
+use llm_chain::options::{FromPreset, Preset, ProviderFamily};
 use llm_chain::prompt::Prompt;
 use llm_chain::traits::Options;
 use serde::{Deserialize, Serialize};
@@ -92,6 +93,19 @@ impl PerInvocation {
     }
 }
 
+impl FromPreset for PerInvocation {
+    fn preset(preset: Preset) -> Self {
+        let sampling = preset.sampling(ProviderFamily::Local);
+        Self {
+            temp: Some(sampling.temperature),
+            top_p: Some(sampling.top_p),
+            top_k: sampling.top_k.map(|k| k as i32),
+            repeat_penalty: sampling.repeat_penalty,
+            ..Self::default()
+        }
+    }
+}
+
 /// `PerExecutor` represents a collection of configuration parameters for the executor of the LLAMA model.
 /// It contains optional fields for the model path and context parameters.
 ///
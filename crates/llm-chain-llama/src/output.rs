@@ -1,11 +1,13 @@
 use async_trait::async_trait;
-use llm_chain::output;
+use llm_chain::output::{self, FinishReason, Usage};
 use std::fmt::{Display, Formatter};
 
 /// Represents the output from the LLAMA model.
 #[derive(Debug, Clone)]
 pub struct Output {
     output: String,
+    usage: Option<Usage>,
+    finish_reason: Option<FinishReason>,
 }
 
 impl Output {
@@ -19,11 +21,24 @@ impl Output {
     ///
     /// A new `Output` instance with the combined string.
     pub fn combine(&self, other: &Output) -> Output {
+        let usage = match (self.usage, other.usage) {
+            (Some(usage), Some(other)) => Some(usage + other),
+            (usage, other) => usage.or(other),
+        };
         Output {
             output: format!("{}\n{}", &self.output, &other.output),
+            usage,
+            finish_reason: other.finish_reason.clone(),
         }
     }
 
+    /// Sets the usage and finish reason of the invocation that produced the output.
+    pub(crate) fn with_metadata(mut self, usage: Usage, finish_reason: FinishReason) -> Output {
+        self.usage = Some(usage);
+        self.finish_reason = Some(finish_reason);
+        self
+    }
+
     /// Returns the string slice representation of the output.
     pub fn as_str(&self) -> &str {
         &self.output
@@ -47,16 +62,18 @@ impl From<Output> for String {
 /// Implements the `From<String> for Output` conversion trait.
 impl From<String> for Output {
     fn from(output: String) -> Self {
-        Output { output }
+        Output {
+            output,
+            usage: None,
+            finish_reason: None,
+        }
     }
 }
 
 /// Implements the `From<&str> for Output` conversion trait.
 impl From<&str> for Output {
     fn from(output: &str) -> Self {
-        Output {
-            output: output.to_string(),
-        }
+        Output::from(output.to_string())
     }
 }
 
@@ -74,4 +91,12 @@ impl output::Output for Output {
     async fn primary_textual_output_choices(&self) -> Vec<String> {
         vec![self.output.clone()]
     }
+
+    async fn finish_reason(&self) -> Option<FinishReason> {
+        self.finish_reason.clone()
+    }
+
+    async fn usage(&self) -> Option<Usage> {
+        self.usage
+    }
 }
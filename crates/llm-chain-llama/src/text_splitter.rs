@@ -1,6 +1,8 @@
 use llm_chain::text_splitter::TextSplitter;
 use llm_chain::tokens::{Tokenizer, TokenizerError};
 
+use std::sync::RwLockReadGuard;
+
 use crate::Executor;
 use crate::{
     context::LLamaContext,
@@ -8,7 +10,7 @@ use crate::{
 };
 
 pub struct LLamaTextSplitter<'a> {
-    context: &'a LLamaContext,
+    context: RwLockReadGuard<'a, Option<LLamaContext>>,
 }
 
 impl<'a> LLamaTextSplitter<'a> {
@@ -21,11 +23,16 @@ impl<'a> LLamaTextSplitter<'a> {
 
 impl<'a> Tokenizer<i32> for LLamaTextSplitter<'a> {
     fn tokenize_str(&self, doc: &str) -> Result<Vec<i32>, TokenizerError> {
-        Ok(llama_tokenize_helper(self.context, doc, true))
+        let context = self
+            .context
+            .as_ref()
+            .ok_or(TokenizerError::TokenizationError)?;
+        Ok(llama_tokenize_helper(context, doc, true))
     }
 
     fn to_string(&self, tokens: Vec<i32>) -> Result<String, TokenizerError> {
-        let output = embedding_to_output(self.context, &tokens);
+        let context = self.context.as_ref().ok_or(TokenizerError::ToStringError)?;
+        let output = embedding_to_output(context, &tokens);
         Ok(output.to_string())
     }
 }
@@ -2,6 +2,7 @@ use std::convert::Infallible;
 use std::env::var;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::{RwLock, RwLockReadGuard};
 
 use crate::options::{PerExecutor, PerInvocation};
 use crate::output::Output;
@@ -19,12 +20,13 @@ use thiserror::Error;
 
 /// Executor is responsible for running the LLM and managing its context.
 pub struct Executor {
-    llm: Box<dyn Model>,
+    /// The model, `None` once the executor is shut down.
+    llm: RwLock<Option<Box<dyn Model>>>,
 }
 
 impl Executor {
-    pub(crate) fn get_llm(&self) -> &dyn Model {
-        self.llm.as_ref()
+    pub(crate) fn get_llm(&self) -> RwLockReadGuard<'_, Option<Box<dyn Model>>> {
+        self.llm.read().expect("model lock poisoned")
     }
 }
 
@@ -34,6 +36,8 @@ pub enum Error {
     PromptTokensError(PromptTokensError),
     #[error("unable to create executor: {0}")]
     InnerError(#[from] Box<dyn std::error::Error>),
+    #[error("the executor has been shut down")]
+    ShutDown,
 }
 
 impl ExecutorError for Error {}
@@ -79,7 +83,9 @@ impl llm_chain::traits::Executor for Executor {
         )
         .map_err(|e| ExecutorCreationError::InnerError(Box::new(e)))?;
 
-        Ok(Executor { llm })
+        Ok(Executor {
+            llm: RwLock::new(Some(llm)),
+        })
     }
 
     async fn execute(
@@ -107,11 +113,13 @@ impl llm_chain::traits::Executor for Executor {
                 repetition_penalty_last_n: opts.repeat_penalty_last_n.unwrap_or(512),
             },
         };
-        let session = &mut self.llm.start_session(Default::default());
+        let llm = self.get_llm();
+        let llm = llm.as_ref().ok_or(Error::ShutDown)?;
+        let session = &mut llm.start_session(Default::default());
         let mut output = String::new();
         session
             .infer::<Infallible>(
-                self.llm.as_ref(),
+                llm.as_ref(),
                 &mut rand::thread_rng(),
                 &InferenceRequest {
                     prompt: prompt.to_text().as_str(),
@@ -150,7 +158,10 @@ impl llm_chain::traits::Executor for Executor {
     }
 
     fn max_tokens_allowed(&self, _: Option<&Self::PerInvocationOptions>) -> i32 {
-        self.llm.n_context_tokens().try_into().unwrap_or(2048)
+        self.get_llm()
+            .as_ref()
+            .and_then(|llm| llm.n_context_tokens().try_into().ok())
+            .unwrap_or(2048)
     }
 
     fn answer_prefix(&self, _prompt: &Prompt) -> Option<String> {
@@ -170,36 +181,43 @@ impl llm_chain::traits::Executor for Executor {
     ) -> Result<Self::TextSplitter<'_>, Self::Error> {
         Ok(LocalLlmTextSplitter::new(self))
     }
+
+    /// Frees the model, after the running invocations and the tokenizers and text splitters in use are dropped.
+    /// Later invocations fail with `Error::ShutDown`.
+    async fn shutdown(&self) -> Result<(), Self::Error> {
+        self.llm.write().expect("model lock poisoned").take();
+        Ok(())
+    }
 }
 
 pub struct LocalLlmTokenizer<'a> {
-    llm: &'a dyn Model,
+    llm: RwLockReadGuard<'a, Option<Box<dyn Model>>>,
 }
 
 impl<'a> LocalLlmTokenizer<'a> {
     pub fn new(executor: &'a Executor) -> Self {
         LocalLlmTokenizer {
-            llm: executor.llm.as_ref(),
+            llm: executor.get_llm(),
         }
     }
 }
 
 impl Tokenizer<llm::TokenId> for LocalLlmTokenizer<'_> {
     fn tokenize_str(&self, doc: &str) -> Result<Vec<llm::TokenId>, TokenizerError> {
-        match &self.llm.vocabulary().tokenize(doc, false) {
+        let llm = self.llm.as_ref().ok_or(TokenizerError::TokenizationError)?;
+        match &llm.vocabulary().tokenize(doc, false) {
             Ok(tokens) => Ok(tokens.into_iter().map(|t| t.1).collect()),
             Err(_) => Err(TokenizerError::TokenizationError),
         }
     }
 
     fn to_string(&self, tokens: Vec<TokenId>) -> Result<String, TokenizerError> {
+        let llm = self.llm.as_ref().ok_or(TokenizerError::ToStringError)?;
         let mut res = String::new();
         let mut token_utf8_buf = TokenUtf8Buffer::new();
         for token_id in tokens {
             // Buffer the token until it's valid UTF-8, then call the callback.
-            if let Some(tokens) =
-                token_utf8_buf.push(self.llm.vocabulary().token(token_id as usize))
-            {
+            if let Some(tokens) = token_utf8_buf.push(llm.vocabulary().token(token_id as usize)) {
                 res.push_str(&tokens)
             }
         }
@@ -1,6 +1,7 @@
 use std::path::PathBuf;
 
 use llm::{InferenceParameters, ModelParameters};
+use llm_chain::options::{FromPreset, Preset, ProviderFamily};
 use llm_chain::traits::Options;
 use serde::{Deserialize, Serialize};
 
@@ -26,6 +27,19 @@ pub struct PerInvocation {
 
 impl Options for PerInvocation {}
 
+impl FromPreset for PerInvocation {
+    fn preset(preset: Preset) -> Self {
+        let sampling = preset.sampling(ProviderFamily::Local);
+        Self {
+            temp: Some(sampling.temperature),
+            top_p: Some(sampling.top_p),
+            top_k: sampling.top_k.map(|k| k as usize),
+            repeat_penalty: sampling.repeat_penalty,
+            ..Self::default()
+        }
+    }
+}
+
 impl Into<ModelParameters> for PerInvocation {
     fn into(self) -> ModelParameters {
         let inference_parameters = InferenceParameters {
@@ -33,7 +47,7 @@ impl Into<ModelParameters> for PerInvocation {
             n_batch: self.n_batch.unwrap_or(8),
             top_k: self.top_k.unwrap_or(40),
             top_p: self.top_p.unwrap_or(0.95),
-            repeat_penalty: self.temp.unwrap_or(1.3),
+            repeat_penalty: self.repeat_penalty.unwrap_or(1.3),
             repetition_penalty_last_n: self.repeat_penalty_last_n.unwrap_or(512),
             temperature: self.temp.unwrap_or(0.8),
             bias_tokens: Default::default(),
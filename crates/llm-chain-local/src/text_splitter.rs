@@ -1,11 +1,12 @@
 use llm::{Model, TokenId, TokenUtf8Buffer};
 use llm_chain::text_splitter::TextSplitter;
 use llm_chain::tokens::{Tokenizer, TokenizerError};
+use std::sync::RwLockReadGuard;
 
 use crate::Executor;
 
 pub struct LocalLlmTextSplitter<'a> {
-    llm: &'a dyn Model,
+    llm: RwLockReadGuard<'a, Option<Box<dyn Model>>>,
 }
 
 impl<'a> LocalLlmTextSplitter<'a> {
@@ -18,20 +19,20 @@ impl<'a> LocalLlmTextSplitter<'a> {
 
 impl<'a> Tokenizer<TokenId> for LocalLlmTextSplitter<'a> {
     fn tokenize_str(&self, doc: &str) -> Result<Vec<TokenId>, TokenizerError> {
-        match &self.llm.vocabulary().tokenize(doc, false) {
+        let llm = self.llm.as_ref().ok_or(TokenizerError::TokenizationError)?;
+        match &llm.vocabulary().tokenize(doc, false) {
             Ok(tokens) => Ok(tokens.into_iter().map(|t| t.1).collect()),
             Err(_) => Err(TokenizerError::TokenizationError),
         }
     }
 
     fn to_string(&self, tokens: Vec<TokenId>) -> Result<String, TokenizerError> {
+        let llm = self.llm.as_ref().ok_or(TokenizerError::ToStringError)?;
         let mut res = String::new();
         let mut token_utf8_buf = TokenUtf8Buffer::new();
         for token_id in tokens {
             // Buffer the token until it's valid UTF-8, then call the callback.
-            if let Some(tokens) =
-                token_utf8_buf.push(self.llm.vocabulary().token(token_id as usize))
-            {
+            if let Some(tokens) = token_utf8_buf.push(llm.vocabulary().token(token_id as usize)) {
                 res.push_str(&tokens)
             }
         }
@@ -1,14 +1,19 @@
 use super::options::PerInvocation;
-use super::output::Output;
+use super::output::{finish_reason, Output};
 use super::prompt::create_chat_completion_request;
-use super::prompt::format_chat_messages;
+use super::prompt::format_messages_for_counting;
 use super::Model;
 use super::OpenAITextSplitter;
+use async_openai::config::OpenAIConfig;
 use async_openai::error::OpenAIError;
-use llm_chain::prompt::Prompt;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use llm_chain::json_schema::JsonSchema;
+use llm_chain::output::{OutputStream, StreamChunk};
+use llm_chain::prompt::{ImageError, Prompt};
 
 use llm_chain::tokens::PromptTokensError;
 use llm_chain::tokens::{Tokenizer, TokenizerError};
+use llm_chain::tools::ToolDefinition;
 use llm_chain::traits;
 use llm_chain::traits::{ExecutorCreationError, ExecutorError};
 
@@ -16,7 +21,7 @@ use super::options::PerExecutor;
 use async_trait::async_trait;
 use llm_chain::tokens::TokenCount;
 
-use tiktoken_rs::async_openai::num_tokens_from_messages;
+use tiktoken_rs::num_tokens_from_messages;
 
 use std::sync::Arc;
 
@@ -24,7 +29,7 @@ use std::sync::Arc;
 #[derive(Clone, Default)]
 pub struct Executor {
     /// The client used to communicate with the OpenAI API.
-    client: Arc<async_openai::Client>,
+    client: Arc<async_openai::Client<OpenAIConfig>>,
     /// The per-invocation options for this executor.
     per_invocation_options: Option<PerInvocation>,
 }
@@ -32,7 +37,7 @@ pub struct Executor {
 impl Executor {
     /// Creates a new `Executor` with the given client.
     pub fn for_client(
-        client: async_openai::Client,
+        client: async_openai::Client<OpenAIConfig>,
         per_invocation_options: Option<PerInvocation>,
     ) -> Self {
         use llm_chain::traits::Executor as _;
@@ -52,6 +57,7 @@ impl Executor {
 #[error(transparent)]
 pub enum Error {
     OpenAIError(#[from] OpenAIError),
+    ImageError(#[from] ImageError),
 }
 impl ExecutorError for Error {}
 
@@ -73,16 +79,16 @@ impl traits::Executor for Executor {
         executor_options: Option<Self::PerExecutorOptions>,
         invocation_options: Option<Self::PerInvocationOptions>,
     ) -> Result<Self, ExecutorCreationError> {
-        let mut client = async_openai::Client::new();
+        let mut config = OpenAIConfig::new();
         if let Some(executor_options) = executor_options {
             if let Some(api_key) = executor_options.api_key {
-                client = client.with_api_key(api_key)
+                config = config.with_api_key(api_key)
             }
         }
         if let Ok(org_id) = std::env::var("OPENAI_ORG_ID") {
-            client = client.with_org_id(org_id);
+            config = config.with_org_id(org_id);
         }
-        let client = Arc::new(client);
+        let client = Arc::new(async_openai::Client::with_config(config));
         Ok(Self {
             client,
             per_invocation_options: invocation_options,
@@ -97,7 +103,8 @@ impl traits::Executor for Executor {
     ) -> Result<Self::Output, Self::Error> {
         let client = self.client.clone();
         let model = self.get_model_from_invocation_options(opts);
-        let input = create_chat_completion_request(&model, prompt, is_streaming).unwrap();
+        let options = opts.or(self.per_invocation_options.as_ref());
+        let input = create_chat_completion_request(&model, options, prompt, is_streaming)?;
         if let Some(true) = is_streaming {
             let res = async move { client.chat().create_stream(input).await }.await?;
             Ok(res.into())
@@ -107,13 +114,44 @@ impl traits::Executor for Executor {
         }
     }
 
+    /// Streams the deltas of the first choice as the API sends them.
+    fn execute_stream<'a>(
+        &'a self,
+        opts: Option<&PerInvocation>,
+        prompt: &Prompt,
+    ) -> OutputStream<'a, Self::Error>
+    where
+        Self: Sync,
+    {
+        let client = self.client.clone();
+        let model = self.get_model_from_invocation_options(opts);
+        let options = opts.or(self.per_invocation_options.as_ref());
+        let input = match create_chat_completion_request(&model, options, prompt, Some(true)) {
+            Ok(input) => input,
+            Err(e) => return stream::once(async move { Err(Error::from(e)) }).boxed(),
+        };
+        stream::once(async move { client.chat().create_stream(input).await })
+            .try_flatten()
+            .map(|response| -> Result<StreamChunk, Error> {
+                let Some(choice) = response?.choices.into_iter().next() else {
+                    return Ok(StreamChunk::default());
+                };
+                let chunk = StreamChunk::new(choice.delta.content.unwrap_or_default());
+                Ok(match choice.finish_reason {
+                    Some(reason) => chunk.with_finish_reason(finish_reason(reason)),
+                    None => chunk,
+                })
+            })
+            .boxed()
+    }
+
     fn tokens_used(
         &self,
         opts: Option<&PerInvocation>,
         prompt: &Prompt,
     ) -> Result<TokenCount, PromptTokensError> {
         let model = self.get_model_from_invocation_options(opts);
-        let messages = format_chat_messages(prompt.to_chat())?;
+        let messages = format_messages_for_counting(prompt.to_chat());
         let tokens_used = num_tokens_from_messages(&model.to_string(), &messages)
             .map_err(|_| PromptTokensError::NotAvailable)?;
 
@@ -134,6 +172,35 @@ impl traits::Executor for Executor {
         None
     }
 
+    /// Images are sent to the model, which must support them, such as `gpt-4o`.
+    fn supports_images(&self) -> bool {
+        true
+    }
+
+    fn json_schema_options(
+        &self,
+        options: Option<&PerInvocation>,
+        schema: &JsonSchema,
+    ) -> Option<PerInvocation> {
+        let options = options
+            .or(self.per_invocation_options.as_ref())
+            .cloned()
+            .unwrap_or_default();
+        Some(options.with_json_schema(schema.clone()))
+    }
+
+    fn tool_options(
+        &self,
+        options: Option<&PerInvocation>,
+        tools: &[ToolDefinition],
+    ) -> Option<PerInvocation> {
+        let options = options
+            .or(self.per_invocation_options.as_ref())
+            .cloned()
+            .unwrap_or_default();
+        Some(options.with_tools(tools.to_vec()))
+    }
+
     fn get_tokenizer(
         &self,
         options: Option<&PerInvocation>,
@@ -189,3 +256,29 @@ impl Tokenizer<usize> for OpenAITokenizer {
         Ok(res)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use llm_chain::prompt::{ChatMessage, ChatMessageCollection, ImagePart};
+    use llm_chain::traits::Executor as _;
+
+    #[test]
+    fn streams_request_errors() {
+        let executor = Executor::new_with_options(None, None).unwrap();
+        let prompt = Prompt::Chat(ChatMessageCollection::for_vector(vec![ChatMessage::user(
+            "What's this?".to_string(),
+        )
+        .with_image(ImagePart::file("/does/not/exist.png"))]));
+        let results: Vec<_> =
+            futures::executor::block_on(executor.execute_stream(None, &prompt).collect());
+        assert_eq!(results.len(), 1);
+        assert!(matches!(results[0], Err(Error::ImageError(_))));
+    }
+
+    #[test]
+    fn conforms() {
+        let executor = Executor::new_with_options(None, None).unwrap();
+        llm_chain_executor_api::conformance::check_executor(&executor);
+    }
+}
@@ -1,3 +1,6 @@
+use llm_chain::json_schema::JsonSchema;
+use llm_chain::options::{FromPreset, Preset, ProviderFamily};
+use llm_chain::tools::ToolDefinition;
 use llm_chain::traits;
 use serde::{Deserialize, Serialize};
 
@@ -53,10 +56,26 @@ impl From<String> for Model {
 }
 
 /// The `PerInvocation` struct contains options that can be specified for each ChatGPT invocation.
-/// Currently, it only supports specifying a `Model`.
+/// It supports specifying a `Model` and the sampling options sent with the request; options that are not set use the
+/// defaults of the OpenAI API.
+///
+/// # Example
+///
+/// ```
+/// use llm_chain::options::{FromPreset, Preset};
+/// use llm_chain_openai::chatgpt::{Model, PerInvocation};
+///
+/// let options = PerInvocation::preset(Preset::Precise).for_model(Model::GPT4);
+/// ```
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct PerInvocation {
     pub(crate) model: Option<Model>,
+    pub(crate) temperature: Option<f32>,
+    pub(crate) top_p: Option<f32>,
+    pub(crate) frequency_penalty: Option<f32>,
+    pub(crate) presence_penalty: Option<f32>,
+    pub(crate) tools: Option<Vec<ToolDefinition>>,
+    pub(crate) json_schema: Option<JsonSchema>,
 }
 
 impl PerInvocation {
@@ -66,7 +85,54 @@ impl PerInvocation {
     }
     /// Sets the `Model` for the `PerInvocation` struct.
     pub fn for_model(self, model: Model) -> Self {
-        Self { model: Some(model) }
+        Self {
+            model: Some(model),
+            ..self
+        }
+    }
+    /// Sets the sampling temperature, between `0.0` and `2.0`.
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+    /// Sets the nucleus sampling probability mass.
+    pub fn with_top_p(mut self, top_p: f32) -> Self {
+        self.top_p = Some(top_p);
+        self
+    }
+    /// Sets the penalty for tokens proportional to how often they already appeared, between `-2.0` and `2.0`.
+    pub fn with_frequency_penalty(mut self, frequency_penalty: f32) -> Self {
+        self.frequency_penalty = Some(frequency_penalty);
+        self
+    }
+    /// Sets the penalty for tokens that already appeared, between `-2.0` and `2.0`.
+    pub fn with_presence_penalty(mut self, presence_penalty: f32) -> Self {
+        self.presence_penalty = Some(presence_penalty);
+        self
+    }
+    /// Sets the tools the model may call. Its calls are returned by `Output::tool_calls`.
+    pub fn with_tools(mut self, tools: Vec<ToolDefinition>) -> Self {
+        self.tools = Some(tools);
+        self
+    }
+    /// Constrains the output to JSON matching `schema`, with the structured output mode of the API.
+    pub fn with_json_schema(mut self, json_schema: JsonSchema) -> Self {
+        self.json_schema = Some(json_schema);
+        self
+    }
+}
+
+impl FromPreset for PerInvocation {
+    fn preset(preset: Preset) -> Self {
+        let sampling = preset.sampling(ProviderFamily::Api);
+        Self {
+            model: None,
+            temperature: Some(sampling.temperature),
+            top_p: Some(sampling.top_p),
+            frequency_penalty: sampling.frequency_penalty,
+            presence_penalty: sampling.presence_penalty,
+            ..Self::default()
+        }
     }
 }
 
@@ -21,12 +21,38 @@
 //!
 mod stream;
 
-use async_openai::types::{ChatCompletionResponseStream, CreateChatCompletionResponse};
+use async_openai::types::{
+    ChatCompletionResponseMessage, ChatCompletionResponseStream, CreateChatCompletionResponse,
+};
 use async_trait::async_trait;
-use llm_chain::output;
+use llm_chain::output::{self, Choice, FinishReason, ToolCall, Usage};
 use std::fmt;
 use stream::{ResponseStream, StreamWrapper};
 
+/// Converts the finish reason of the API.
+pub(crate) fn finish_reason(reason: async_openai::types::FinishReason) -> FinishReason {
+    use async_openai::types::FinishReason as Reason;
+    match reason {
+        Reason::Stop => FinishReason::Stop,
+        Reason::Length => FinishReason::Length,
+        Reason::ToolCalls | Reason::FunctionCall => FinishReason::ToolCalls,
+        Reason::ContentFilter => FinishReason::ContentFilter,
+    }
+}
+
+/// Returns the tools a message of the API asks to call.
+fn tool_calls(message: &ChatCompletionResponseMessage) -> Vec<ToolCall> {
+    message
+        .tool_calls
+        .iter()
+        .flatten()
+        .map(|call| {
+            ToolCall::new(call.function.name.clone(), call.function.arguments.clone())
+                .with_id(call.id.clone())
+        })
+        .collect()
+}
+
 /// Represents the output of a CreateChatCompletionResponse from OpenAI.
 #[derive(Clone, Debug)]
 pub enum OutputInner {
@@ -71,7 +97,11 @@ impl fmt::Display for Output {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self.0 {
             OutputInner::Response(response) => {
-                write!(f, "{}", response.choices[0].message.content)
+                let content = response
+                    .choices
+                    .first()
+                    .and_then(|c| c.message.content.as_ref());
+                write!(f, "{}", content.map(String::as_str).unwrap_or_default())
             }
             OutputInner::Stream(_) => {
                 write!(
@@ -91,11 +121,78 @@ impl output::Output for Output {
             OutputInner::Response(response) => response
                 .choices
                 .iter()
-                .map(|choice| choice.message.content.clone())
+                .map(|choice| choice.message.content.clone().unwrap_or_default())
                 .collect(),
             OutputInner::Stream(stream) => stream.primary_textual_output_choices().await,
         }
     }
+
+    /// Returns the tools the first choice asks to call.
+    async fn tool_calls(&self) -> Vec<ToolCall> {
+        match &self.0 {
+            OutputInner::Response(response) => response
+                .choices
+                .first()
+                .map(|choice| tool_calls(&choice.message))
+                .unwrap_or_default(),
+            OutputInner::Stream(stream) => stream.tool_calls().await,
+        }
+    }
+
+    /// Returns the finish reason of the first choice of a response. Streams don't report it.
+    async fn finish_reason(&self) -> Option<FinishReason> {
+        match &self.0 {
+            OutputInner::Response(response) => {
+                response.choices.first()?.finish_reason.map(finish_reason)
+            }
+            OutputInner::Stream(_) => None,
+        }
+    }
+
+    async fn choices(&self) -> Vec<Choice> {
+        match &self.0 {
+            OutputInner::Response(response) => response
+                .choices
+                .iter()
+                .map(|choice| {
+                    let output = Choice::new(choice.message.content.clone().unwrap_or_default())
+                        .with_tool_calls(tool_calls(&choice.message));
+                    match choice.finish_reason {
+                        Some(reason) => output.with_finish_reason(finish_reason(reason)),
+                        None => output,
+                    }
+                })
+                .collect(),
+            OutputInner::Stream(stream) => {
+                let tool_calls = stream.tool_calls().await;
+                stream
+                    .primary_textual_output_choices()
+                    .await
+                    .into_iter()
+                    .map(|text| Choice::new(text).with_tool_calls(tool_calls.clone()))
+                    .collect()
+            }
+        }
+    }
+
+    /// Returns the usage of a response. Streams don't report it.
+    async fn usage(&self) -> Option<Usage> {
+        match &self.0 {
+            OutputInner::Response(response) => {
+                let usage = response.usage.as_ref()?;
+                Some(Usage::new(usage.prompt_tokens, usage.completion_tokens))
+            }
+            OutputInner::Stream(_) => None,
+        }
+    }
+
+    /// Returns the model of a response. Streams don't report it.
+    async fn model(&self) -> Option<String> {
+        match &self.0 {
+            OutputInner::Response(response) => Some(response.model.clone()),
+            OutputInner::Stream(_) => None,
+        }
+    }
 }
 
 /// Implement From trait to allow conversion from OutputInner to Output.
@@ -118,3 +215,84 @@ impl From<ChatCompletionResponseStream> for Output {
         Self(stream.into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_openai::types::CreateChatCompletionStreamResponse;
+    use futures::executor::block_on;
+    use llm_chain::output::Output as _;
+
+    /// A response of the API asking to call a tool, as recorded.
+    const TOOL_CALLS_RESPONSE: &str = r#"{
+        "id": "chatcmpl-9xKq0",
+        "object": "chat.completion",
+        "created": 1723958400,
+        "model": "gpt-4o-2024-08-06",
+        "choices": [{
+            "index": 0,
+            "message": {
+                "role": "assistant",
+                "content": null,
+                "tool_calls": [{
+                    "id": "call_Vq3xJ0",
+                    "type": "function",
+                    "function": { "name": "get_weather", "arguments": "{\"city\":\"Oslo\"}" }
+                }],
+                "refusal": null
+            },
+            "logprobs": null,
+            "finish_reason": "tool_calls"
+        }],
+        "usage": { "prompt_tokens": 82, "completion_tokens": 17, "total_tokens": 99 },
+        "system_fingerprint": "fp_2a322c9ffc"
+    }"#;
+
+    #[test]
+    fn decodes_tool_calls() {
+        let response: CreateChatCompletionResponse =
+            serde_json::from_str(TOOL_CALLS_RESPONSE).unwrap();
+        let output = Output::from(response);
+        let calls = block_on(output.tool_calls());
+        assert_eq!(
+            calls,
+            vec![ToolCall::new("get_weather", r#"{"city":"Oslo"}"#).with_id("call_Vq3xJ0")]
+        );
+        assert_eq!(
+            block_on(output.finish_reason()),
+            Some(FinishReason::ToolCalls)
+        );
+        assert_eq!(
+            block_on(output.primary_textual_output()),
+            Some(String::new())
+        );
+        assert_eq!(block_on(output.usage()), Some(Usage::new(82, 17)));
+        assert_eq!(block_on(output.choices())[0].tool_calls, calls);
+    }
+
+    #[test]
+    fn assembles_streamed_tool_calls() {
+        let chunk = |delta: &str| -> CreateChatCompletionStreamResponse {
+            serde_json::from_str(&format!(
+                r#"{{"id": "chatcmpl-9xKq1", "object": "chat.completion.chunk", "created": 1723958400,
+                    "model": "gpt-4o", "choices": [{{"index": 0, "delta": {}, "finish_reason": null}}]}}"#,
+                delta
+            ))
+            .unwrap()
+        };
+        let chunks = vec![
+            chunk(
+                r#"{"role": "assistant", "tool_calls": [{"index": 0, "id": "call_1", "type": "function", "function": {"name": "get_weather", "arguments": ""}}]}"#,
+            ),
+            chunk(r#"{"tool_calls": [{"index": 0, "function": {"arguments": "{\"city\":"}}]}"#),
+            chunk(r#"{"tool_calls": [{"index": 0, "function": {"arguments": "\"Oslo\"}"}}]}"#),
+        ];
+        let stream: ChatCompletionResponseStream =
+            Box::pin(futures::stream::iter(chunks.into_iter().map(Ok)));
+        let output = Output::from(stream);
+        let expected = vec![ToolCall::new("get_weather", r#"{"city":"Oslo"}"#).with_id("call_1")];
+        assert_eq!(block_on(output.tool_calls()), expected);
+        // The stream can be read again.
+        assert_eq!(block_on(output.tool_calls()), expected);
+    }
+}
@@ -1,5 +1,6 @@
-use async_openai::{error::OpenAIError, types::ChatCompletionResponseStream};
+use async_openai::types::{ChatCompletionResponseStream, CreateChatCompletionStreamResponse};
 use futures::stream::StreamExt;
+use llm_chain::output::ToolCall;
 use std::{
     fmt::{self, Debug, Formatter},
     sync::Arc,
@@ -16,40 +17,59 @@ impl StreamWrapper {
         Self(Arc::new(Mutex::new(stream)))
     }
 
+    /// Reads the rest of the stream, and replaces it with the responses read, so that it can be read again. Failed
+    /// responses are skipped.
+    async fn responses(&self) -> Vec<CreateChatCompletionStreamResponse> {
+        let mut stream = self.0.lock().await;
+        let responses: Vec<_> = stream
+            .by_ref()
+            .filter_map(|result| async move { result.ok() })
+            .collect()
+            .await;
+        *stream = Box::pin(futures::stream::iter(responses.clone().into_iter().map(Ok)));
+        responses
+    }
+
     pub async fn primary_textual_output_choices(&self) -> Vec<String> {
-        let stream = self.inner();
-        let temp_cache = Arc::new(Mutex::new(Vec::new()));
-        let shared_temp_cache = temp_cache.clone();
-        let stream_clone = futures::stream::unfold(stream.clone(), move |state| {
-            let shared_temp_cache = shared_temp_cache.clone();
-            let state = state.clone();
-            async move {
-                let mut state_guard = state.lock().await;
-                while let Some(result) = state_guard.next().await {
-                    if let Ok(response) = result {
-                        for chat_choice in &response.choices {
-                            if let Some(content) = &chat_choice.delta.content {
-                                let mut cache = shared_temp_cache.lock().await;
-                                cache.push(content.clone());
-                            }
-                        }
-                        return Some((Ok::<_, OpenAIError>(response), state.clone()));
-                    }
+        let text: String = self
+            .responses()
+            .await
+            .iter()
+            .flat_map(|response| &response.choices)
+            .filter_map(|choice| choice.delta.content.as_deref())
+            .collect();
+        vec![text]
+    }
+
+    /// Assembles the tool calls of the first choice, whose names and arguments are streamed in pieces.
+    pub async fn tool_calls(&self) -> Vec<ToolCall> {
+        let mut calls: Vec<(u32, ToolCall)> = Vec::new();
+        let responses = self.responses().await;
+        let chunks = responses
+            .iter()
+            .flat_map(|response| &response.choices)
+            .filter(|choice| choice.index == 0)
+            .flat_map(|choice| choice.delta.tool_calls.iter().flatten());
+        for chunk in chunks {
+            let position = match calls.iter().position(|(index, _)| *index == chunk.index) {
+                Some(position) => position,
+                None => {
+                    calls.push((chunk.index, ToolCall::new("", "")));
+                    calls.len() - 1
                 }
-                None
+            };
+            let call = &mut calls[position].1;
+            if let Some(id) = &chunk.id {
+                call.id = Some(id.clone());
+            }
+            if let Some(function) = &chunk.function {
+                call.name
+                    .push_str(function.name.as_deref().unwrap_or_default());
+                call.arguments
+                    .push_str(function.arguments.as_deref().unwrap_or_default());
             }
-        });
-        let stream_clone_results = stream_clone.collect::<Vec<_>>().await;
-        let new_stream = Box::pin(futures::stream::iter(stream_clone_results));
-        let stream = self.inner();
-        {
-            let mut stream_guard = stream.lock().await;
-            *stream_guard = new_stream;
-        }
-        {
-            let temp_cache = temp_cache.lock().await;
-            vec![temp_cache.join("")]
         }
+        calls.into_iter().map(|(_, call)| call).collect()
     }
 
     pub fn inner(&self) -> ResponseStream {
@@ -1,56 +1,271 @@
-use async_openai::types::{ChatCompletionRequestMessage, CreateChatCompletionRequest, Role};
-use llm_chain::{
-    prompt::StringTemplateError,
-    prompt::{self, Prompt},
+use async_openai::types::{
+    ChatCompletionMessageToolCall, ChatCompletionRequestAssistantMessage,
+    ChatCompletionRequestAssistantMessageContent, ChatCompletionRequestMessage,
+    ChatCompletionRequestMessageContentPartImage, ChatCompletionRequestMessageContentPartText,
+    ChatCompletionRequestSystemMessage, ChatCompletionRequestSystemMessageContent,
+    ChatCompletionRequestToolMessage, ChatCompletionRequestToolMessageContent,
+    ChatCompletionRequestUserMessage, ChatCompletionRequestUserMessageContent,
+    ChatCompletionRequestUserMessageContentPart, ChatCompletionTool, ChatCompletionToolType,
+    CreateChatCompletionRequest, FunctionCall, FunctionObject, ImageUrl, ResponseFormat,
+    ResponseFormatJsonSchema,
 };
+use llm_chain::prompt::{self, ImageError, Prompt};
 
-use super::Model;
+use super::{Model, PerInvocation};
 
-fn convert_role(role: &prompt::ChatRole) -> Role {
-    match role {
-        prompt::ChatRole::User => Role::User,
-        prompt::ChatRole::Assistant => Role::Assistant,
-        prompt::ChatRole::System => Role::System,
-        prompt::ChatRole::Other(_s) => Role::User, // other roles are not supported by OpenAI
+fn user_message(content: String) -> ChatCompletionRequestMessage {
+    ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
+        content: ChatCompletionRequestUserMessageContent::Text(content),
+        name: None,
+    })
+}
+
+/// Formats a user message, whose images are sent after its text. Images in files are read.
+fn user_message_with_images(
+    message: &prompt::ChatMessage<String>,
+) -> Result<ChatCompletionRequestMessage, ImageError> {
+    let content = message.body().to_string();
+    if message.images().is_empty() {
+        return Ok(user_message(content));
     }
+    let mut parts = vec![ChatCompletionRequestUserMessageContentPart::Text(
+        ChatCompletionRequestMessageContentPartText { text: content },
+    )];
+    for image in message.images() {
+        parts.push(ChatCompletionRequestUserMessageContentPart::ImageUrl(
+            ChatCompletionRequestMessageContentPartImage {
+                image_url: ImageUrl {
+                    url: image.to_url()?,
+                    detail: None,
+                },
+            },
+        ));
+    }
+    Ok(ChatCompletionRequestMessage::User(
+        ChatCompletionRequestUserMessage {
+            content: ChatCompletionRequestUserMessageContent::Array(parts),
+            name: None,
+        },
+    ))
 }
 
 fn format_chat_message(
     message: &prompt::ChatMessage<String>,
-) -> Result<ChatCompletionRequestMessage, StringTemplateError> {
-    let role = convert_role(message.role());
+) -> Result<ChatCompletionRequestMessage, ImageError> {
     let content = message.body().to_string();
-    Ok(ChatCompletionRequestMessage {
-        role,
-        content,
-        name: None,
+    Ok(match message.role() {
+        prompt::ChatRole::System => {
+            ChatCompletionRequestMessage::System(ChatCompletionRequestSystemMessage {
+                content: ChatCompletionRequestSystemMessageContent::Text(content),
+                name: None,
+            })
+        }
+        prompt::ChatRole::Assistant => {
+            let tool_calls: Vec<ChatCompletionMessageToolCall> = message
+                .tool_calls()
+                .iter()
+                .map(|call| ChatCompletionMessageToolCall {
+                    id: call.id.clone().unwrap_or_default(),
+                    r#type: ChatCompletionToolType::Function,
+                    function: FunctionCall {
+                        name: call.name.clone(),
+                        arguments: call.arguments.clone(),
+                    },
+                })
+                .collect();
+            #[allow(deprecated)]
+            ChatCompletionRequestMessage::Assistant(ChatCompletionRequestAssistantMessage {
+                // Messages calling tools may have no text.
+                content: (!content.is_empty() || tool_calls.is_empty())
+                    .then_some(ChatCompletionRequestAssistantMessageContent::Text(content)),
+                tool_calls: (!tool_calls.is_empty()).then_some(tool_calls),
+                ..Default::default()
+            })
+        }
+        prompt::ChatRole::Tool => match message.tool_call_id() {
+            Some(id) => ChatCompletionRequestMessage::Tool(ChatCompletionRequestToolMessage {
+                content: ChatCompletionRequestToolMessageContent::Text(content),
+                tool_call_id: id.to_string(),
+            }),
+            // The API needs the call a result answers, so results without one are sent as user messages.
+            None => user_message(content),
+        },
+        // other roles are not supported by the API
+        prompt::ChatRole::User | prompt::ChatRole::Other(_) => user_message_with_images(message)?,
     })
 }
 
 pub fn format_chat_messages(
     messages: prompt::ChatMessageCollection<String>,
-) -> Result<Vec<ChatCompletionRequestMessage>, StringTemplateError> {
+) -> Result<Vec<ChatCompletionRequestMessage>, ImageError> {
     messages.iter().map(format_chat_message).collect()
 }
 
+/// Formats the messages of a chat for counting their tokens with `tiktoken`.
+pub fn format_messages_for_counting(
+    messages: prompt::ChatMessageCollection<String>,
+) -> Vec<tiktoken_rs::ChatCompletionRequestMessage> {
+    messages
+        .iter()
+        .map(|message| tiktoken_rs::ChatCompletionRequestMessage {
+            role: match message.role() {
+                prompt::ChatRole::System => "system",
+                prompt::ChatRole::Assistant => "assistant",
+                prompt::ChatRole::Tool => "tool",
+                prompt::ChatRole::User | prompt::ChatRole::Other(_) => "user",
+            }
+            .to_string(),
+            content: Some(message.body().to_string()),
+            name: None,
+            function_call: None,
+        })
+        .collect()
+}
+
 pub fn create_chat_completion_request(
     model: &Model,
+    options: Option<&PerInvocation>,
     prompt: &Prompt,
     is_streaming: Option<bool>,
-) -> Result<CreateChatCompletionRequest, StringTemplateError> {
+) -> Result<CreateChatCompletionRequest, ImageError> {
     let messages = format_chat_messages(prompt.to_chat())?;
+    let options = options.cloned().unwrap_or_default();
+    let tools = options.tools.map(|tools| {
+        tools
+            .into_iter()
+            .map(|tool| ChatCompletionTool {
+                r#type: ChatCompletionToolType::Function,
+                function: FunctionObject {
+                    name: tool.name,
+                    description: Some(tool.description),
+                    parameters: Some(tool.parameters),
+                    strict: None,
+                },
+            })
+            .collect()
+    });
+    let response_format = options
+        .json_schema
+        .map(|json_schema| ResponseFormat::JsonSchema {
+            json_schema: ResponseFormatJsonSchema {
+                description: None,
+                name: json_schema.name().to_string(),
+                schema: Some(json_schema.schema().clone()),
+                strict: None,
+            },
+        });
     Ok(CreateChatCompletionRequest {
         model: model.to_string(),
         messages,
-        temperature: None,
-        top_p: None,
+        temperature: options.temperature,
+        top_p: options.top_p,
         n: Some(1),
         stream: is_streaming,
-        stop: None,
-        max_tokens: None, // We should consider something here
-        presence_penalty: None,
-        frequency_penalty: None,
-        logit_bias: None,
-        user: None,
+        presence_penalty: options.presence_penalty,
+        frequency_penalty: options.frequency_penalty,
+        tools,
+        response_format,
+        ..Default::default()
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chatgpt::Executor;
+    use llm_chain::json_schema::JsonSchema;
+    use llm_chain::output::ToolCall;
+    use llm_chain::prompt::{ChatMessage, ChatMessageCollection, ImagePart};
+    use llm_chain::tools::ToolDefinition;
+    use llm_chain::traits::Executor as _;
+
+    #[test]
+    fn advertises_tools() {
+        let tool = ToolDefinition {
+            name: "get_weather".to_string(),
+            description: "Gets the weather of a city".to_string(),
+            parameters: serde_json::json!({ "type": "object", "properties": { "city": { "type": "string" } } }),
+        };
+        let executor =
+            Executor::new_with_options(None, Some(PerInvocation::new().for_model(Model::GPT4)))
+                .unwrap();
+        let options = executor.tool_options(None, &[tool]).unwrap();
+        // The options of the executor are kept.
+        assert_eq!(options.model.as_ref().unwrap().to_string(), "gpt-4");
+        let prompt = Prompt::Text("What's the weather in Oslo?".to_string());
+        let request =
+            create_chat_completion_request(&Model::GPT4, Some(&options), &prompt, None).unwrap();
+        let request = serde_json::to_value(request).unwrap();
+        assert_eq!(request["tools"][0]["type"], "function");
+        assert_eq!(request["tools"][0]["function"]["name"], "get_weather");
+        assert_eq!(
+            request["tools"][0]["function"]["parameters"]["properties"]["city"]["type"],
+            "string"
+        );
+    }
+
+    #[test]
+    fn constrains_output_to_schemas() {
+        let schema = JsonSchema::new(
+            "review",
+            serde_json::json!({ "type": "object", "properties": { "score": { "type": "integer" } } }),
+        );
+        let executor = Executor::new_with_options(None, None).unwrap();
+        let options = executor.json_schema_options(None, &schema).unwrap();
+        let prompt = Prompt::Text("Review this film.".to_string());
+        let request =
+            create_chat_completion_request(&Model::GPT4, Some(&options), &prompt, None).unwrap();
+        let request = serde_json::to_value(request).unwrap();
+        assert_eq!(request["response_format"]["type"], "json_schema");
+        assert_eq!(request["response_format"]["json_schema"]["name"], "review");
+        assert_eq!(
+            request["response_format"]["json_schema"]["schema"]["properties"]["score"]["type"],
+            "integer"
+        );
+    }
+
+    #[test]
+    fn sends_images_after_the_text() {
+        let messages =
+            ChatMessageCollection::for_vector(vec![ChatMessage::user("What's this?".to_string())
+                .with_image(ImagePart::url("https://example.com/cat.png"))
+                .with_image(ImagePart::from_bytes("image/png", b"png"))]);
+        let formatted = serde_json::to_value(format_chat_messages(messages).unwrap()).unwrap();
+        assert_eq!(
+            formatted[0]["content"],
+            serde_json::json!([
+                { "type": "text", "text": "What's this?" },
+                { "type": "image_url", "image_url": { "url": "https://example.com/cat.png", "detail": null } },
+                { "type": "image_url", "image_url": { "url": "data:image/png;base64,cG5n", "detail": null } },
+            ])
+        );
+        let executor = Executor::new_with_options(None, None).unwrap();
+        assert!(executor.supports_images());
+    }
+
+    #[test]
+    fn formats_tool_calls_and_results() {
+        let messages = ChatMessageCollection::for_vector(vec![
+            ChatMessage::user("What's the weather in Oslo?".to_string()),
+            ChatMessage::assistant(String::new()).with_tool_calls(vec![ToolCall::new(
+                "get_weather",
+                r#"{"city":"Oslo"}"#,
+            )
+            .with_id("call_1")]),
+            ChatMessage::tool_result(Some("call_1".to_string()), "Sunny".to_string()),
+        ]);
+        let formatted = serde_json::to_value(format_chat_messages(messages).unwrap()).unwrap();
+        assert_eq!(
+            formatted,
+            serde_json::json!([
+                { "role": "user", "content": "What's the weather in Oslo?" },
+                { "role": "assistant", "tool_calls": [{
+                    "id": "call_1",
+                    "type": "function",
+                    "function": { "name": "get_weather", "arguments": "{\"city\":\"Oslo\"}" }
+                }]},
+                { "role": "tool", "content": "Sunny", "tool_call_id": "call_1" },
+            ])
+        );
+    }
+}
@@ -1,16 +1,57 @@
-use std::sync::Arc;
+use std::future::Future;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 
 use async_openai::{
+    config::OpenAIConfig,
     error::OpenAIError,
-    types::{CreateEmbeddingRequest, EmbeddingInput},
+    types::{CreateEmbeddingRequest, Embedding, EmbeddingInput},
 };
 use async_trait::async_trait;
-use llm_chain::traits::{self, EmbeddingsError};
+use futures::{StreamExt, TryStreamExt};
+use llm_chain::traits::{self, EmbeddingsError, TruncationPolicy};
 use thiserror::Error;
 
+/// The maximum number of inputs the OpenAI API accepts in one embeddings request.
+const MAX_BATCH_SIZE: usize = 2048;
+/// The maximum number of tokens in one input of the OpenAI embedding models.
+const MAX_INPUT_TOKENS: usize = 8191;
+/// The maximum number of tokens, summed over the inputs, the OpenAI API accepts in one embeddings request.
+const MAX_BATCH_TOKENS: usize = 300_000;
+
+/// Returns the tokenizer of the OpenAI embedding models, which is built on first use.
+fn cl100k_base() -> Result<&'static tiktoken_rs::CoreBPE, OpenAIEmbeddingsError> {
+    static BPE: OnceLock<Result<tiktoken_rs::CoreBPE, String>> = OnceLock::new();
+    BPE.get_or_init(|| tiktoken_rs::cl100k_base().map_err(|e| e.to_string()))
+        .as_ref()
+        .map_err(|e| OpenAIEmbeddingsError::Tokenizer(e.clone()))
+}
+
+/// Returns the length of the vectors of the OpenAI embedding models.
+fn model_dimensions(model: &str) -> Option<usize> {
+    match model {
+        "text-embedding-ada-002" | "text-embedding-3-small" => Some(1536),
+        "text-embedding-3-large" => Some(3072),
+        _ => None,
+    }
+}
+
+/// Embeddings computed with the OpenAI API.
+///
+/// Large inputs are split into batches of at most `batch_size` texts and `max_batch_tokens` tokens, which are sent
+/// concurrently, at most `max_concurrency` at a time. Requests that fail with a transient error, such as a dropped
+/// connection, are retried with exponential backoff. The embeddings are returned in the order of the input texts.
+///
+/// The API rejects inputs longer than 8191 tokens. `with_truncation_policy` truncates them before sending instead.
 pub struct Embeddings {
-    client: Arc<async_openai::Client>,
+    client: Arc<async_openai::Client<OpenAIConfig>>,
     model: String,
+    batch_size: usize,
+    max_batch_tokens: usize,
+    max_concurrency: usize,
+    max_retries: u32,
+    retry_delay: Duration,
+    truncation_policy: TruncationPolicy,
 }
 
 #[derive(Debug, Error)]
@@ -18,8 +59,10 @@ pub struct Embeddings {
 pub enum OpenAIEmbeddingsError {
     #[error(transparent)]
     Client(#[from] OpenAIError),
-    #[error("Request to OpenAI embeddings API was successful but response is empty")]
+    #[error("Request to OpenAI embeddings API was successful but response is empty or incomplete")]
     EmptyResponse,
+    #[error("Error truncating an input: {0}")]
+    Tokenizer(String),
 }
 
 impl EmbeddingsError for OpenAIEmbeddingsError {}
@@ -29,48 +72,274 @@ impl traits::Embeddings for Embeddings {
     type Error = OpenAIEmbeddingsError;
 
     async fn embed_texts(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Self::Error> {
-        self.client
-            .embeddings()
-            .create(CreateEmbeddingRequest {
-                model: self.model.clone(),
-                user: None,
-                input: EmbeddingInput::from(texts),
-            })
-            .await
-            .map(|r| r.data.into_iter().map(|e| e.embedding).collect())
-            .map_err(|e| e.into())
+        let texts = self.truncate(texts)?;
+        let bpe = cl100k_base()?;
+        let tokens: Vec<usize> = texts
+            .iter()
+            .map(|text| bpe.encode_with_special_tokens(text).len())
+            .collect();
+        let batches = batches(texts, &tokens, self.batch_size, self.max_batch_tokens);
+        let embeddings: Vec<Vec<Vec<f32>>> = futures::stream::iter(batches)
+            .map(|batch| self.embed_batch(batch))
+            .buffered(self.max_concurrency)
+            .try_collect()
+            .await?;
+        Ok(embeddings.into_iter().flatten().collect())
     }
 
     async fn embed_query(&self, query: String) -> Result<Vec<f32>, Self::Error> {
-        self.client
-            .embeddings()
-            .create(CreateEmbeddingRequest {
-                model: self.model.clone(),
-                user: None,
-                input: EmbeddingInput::from(query),
-            })
-            .await
-            .map(|r| r.data.into_iter())?
-            .map(|e| e.embedding)
-            .last()
+        let query = self.truncate(vec![query])?;
+        self.embed_batch(query)
+            .await?
+            .pop()
             .ok_or(OpenAIEmbeddingsError::EmptyResponse)
     }
+
+    fn dimensions(&self) -> Option<usize> {
+        model_dimensions(&self.model)
+    }
+
+    fn truncation_policy(&self) -> Option<TruncationPolicy> {
+        Some(self.truncation_policy)
+    }
 }
 
 impl Default for Embeddings {
     fn default() -> Self {
-        Self {
-            client: async_openai::Client::default().into(),
-            model: "text-embedding-ada-002".to_string(),
-        }
+        Self::for_client(async_openai::Client::default(), "text-embedding-ada-002")
     }
 }
 
 impl Embeddings {
-    pub fn for_client(client: async_openai::Client, model: &str) -> Self {
+    pub fn for_client(client: async_openai::Client<OpenAIConfig>, model: &str) -> Self {
         Self {
             client: client.into(),
             model: model.to_string(),
+            batch_size: MAX_BATCH_SIZE,
+            max_batch_tokens: MAX_BATCH_TOKENS,
+            max_concurrency: 4,
+            max_retries: 3,
+            retry_delay: Duration::from_millis(500),
+            truncation_policy: TruncationPolicy::Error,
+        }
+    }
+
+    /// Sets what happens to inputs longer than the model accepts. Defaults to `TruncationPolicy::Error`, which sends
+    /// them as they are and lets the API reject them.
+    pub fn with_truncation_policy(mut self, truncation_policy: TruncationPolicy) -> Self {
+        self.truncation_policy = truncation_policy;
+        self
+    }
+
+    /// Shortens over-length texts according to the truncation policy.
+    fn truncate(&self, texts: Vec<String>) -> Result<Vec<String>, OpenAIEmbeddingsError> {
+        if self.truncation_policy == TruncationPolicy::Error {
+            return Ok(texts);
+        }
+        let bpe = cl100k_base()?;
+        texts
+            .into_iter()
+            .map(|text| {
+                let tokens = bpe.encode_with_special_tokens(&text);
+                if tokens.len() <= MAX_INPUT_TOKENS {
+                    return Ok(text);
+                }
+                // A token boundary may fall inside a multi-byte character, in which case one more token is dropped.
+                let mut keep = MAX_INPUT_TOKENS;
+                loop {
+                    let kept = match self.truncation_policy {
+                        TruncationPolicy::Start => tokens[tokens.len() - keep..].to_vec(),
+                        _ => tokens[..keep].to_vec(),
+                    };
+                    match bpe.decode(kept) {
+                        Ok(text) => return Ok(text),
+                        Err(_) if keep > MAX_INPUT_TOKENS - 4 => keep -= 1,
+                        Err(e) => return Err(OpenAIEmbeddingsError::Tokenizer(e.to_string())),
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Sets the maximum number of texts sent in one request. Values above the API limit of 2048 are capped.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.clamp(1, MAX_BATCH_SIZE);
+        self
+    }
+
+    /// Sets the maximum number of tokens, summed over the texts, sent in one request. Values above the API limit of
+    /// 300,000 are capped. A text longer than the limit is sent on its own.
+    pub fn with_max_batch_tokens(mut self, max_batch_tokens: usize) -> Self {
+        self.max_batch_tokens = max_batch_tokens.clamp(1, MAX_BATCH_TOKENS);
+        self
+    }
+
+    /// Sets the maximum number of requests in flight at the same time.
+    pub fn with_max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = max_concurrency.max(1);
+        self
+    }
+
+    /// Sets how many times a request is retried after a transient error, and the delay before the first retry. The
+    /// delay doubles with every retry.
+    pub fn with_retries(mut self, max_retries: u32, retry_delay: Duration) -> Self {
+        self.max_retries = max_retries;
+        self.retry_delay = retry_delay;
+        self
+    }
+
+    /// Embeds one batch of texts, retrying transient failures.
+    async fn embed_batch(
+        &self,
+        texts: Vec<String>,
+    ) -> Result<Vec<Vec<f32>>, OpenAIEmbeddingsError> {
+        let expected = texts.len();
+        let response = retry(self.max_retries, self.retry_delay, || {
+            let request = CreateEmbeddingRequest {
+                model: self.model.clone(),
+                input: EmbeddingInput::from(texts.clone()),
+                ..Default::default()
+            };
+            async move { self.client.embeddings().create(request).await }
+        })
+        .await?;
+        in_input_order(response.data, expected)
+    }
+}
+
+/// Splits `texts`, of `tokens` tokens each, into consecutive batches of at most `batch_size` texts and `max_tokens`
+/// tokens. A text longer than `max_tokens` gets a batch of its own.
+fn batches(
+    texts: Vec<String>,
+    tokens: &[usize],
+    batch_size: usize,
+    max_tokens: usize,
+) -> Vec<Vec<String>> {
+    let mut batches: Vec<Vec<String>> = Vec::new();
+    let mut batch_tokens = 0;
+    for (text, tokens) in texts.into_iter().zip(tokens) {
+        match batches.last_mut() {
+            Some(batch) if batch.len() < batch_size && batch_tokens + tokens <= max_tokens => {
+                batch_tokens += tokens;
+                batch.push(text);
+            }
+            _ => {
+                batch_tokens = *tokens;
+                batches.push(vec![text]);
+            }
+        }
+    }
+    batches
+}
+
+/// Returns the vectors of `data` in the order of the inputs they embed, checking that there are `expected` of them.
+fn in_input_order(
+    mut data: Vec<Embedding>,
+    expected: usize,
+) -> Result<Vec<Vec<f32>>, OpenAIEmbeddingsError> {
+    if data.len() != expected {
+        return Err(OpenAIEmbeddingsError::EmptyResponse);
+    }
+    data.sort_by_key(|e| e.index);
+    Ok(data.into_iter().map(|e| e.embedding).collect())
+}
+
+/// Runs `request`, retrying it up to `max_retries` times while it fails with a transient error. The delay before
+/// the first retry is `delay`, and doubles with every retry.
+async fn retry<T, F, Fut>(
+    max_retries: u32,
+    delay: Duration,
+    mut request: F,
+) -> Result<T, OpenAIError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, OpenAIError>>,
+{
+    let mut delay = delay;
+    let mut attempt = 0;
+    loop {
+        match request().await {
+            Err(e) if is_transient(&e) && attempt < max_retries => {
+                attempt += 1;
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+            result => return result,
         }
     }
 }
+
+/// Returns `true` for errors that may go away when the request is repeated.
+fn is_transient(error: &OpenAIError) -> bool {
+    matches!(error, OpenAIError::Reqwest(_) | OpenAIError::StreamError(_))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batches_by_count_and_tokens() {
+        let texts: Vec<String> = ["a", "b", "c", "d", "e"].map(String::from).to_vec();
+        assert_eq!(
+            batches(texts.clone(), &[1, 1, 1, 1, 1], 2, 100),
+            vec![vec!["a", "b"], vec!["c", "d"], vec!["e"]]
+        );
+        assert_eq!(
+            batches(texts.clone(), &[4, 4, 3, 12, 1], 10, 10),
+            vec![vec!["a", "b"], vec!["c"], vec!["d"], vec!["e"]]
+        );
+        assert!(batches(Vec::new(), &[], 2, 10).is_empty());
+    }
+
+    #[test]
+    fn orders_embeddings_by_input() {
+        let embedding = |index: u32| Embedding {
+            index,
+            object: "embedding".to_string(),
+            embedding: vec![index as f32],
+        };
+        let data = vec![embedding(2), embedding(0), embedding(1)];
+        assert_eq!(
+            in_input_order(data.clone(), 3).unwrap(),
+            vec![vec![0.0], vec![1.0], vec![2.0]]
+        );
+        assert!(matches!(
+            in_input_order(data, 4),
+            Err(OpenAIEmbeddingsError::EmptyResponse)
+        ));
+    }
+
+    #[test]
+    fn retries_transient_errors() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let attempts = std::cell::Cell::new(0);
+        let failing_twice = || {
+            attempts.set(attempts.get() + 1);
+            let attempt = attempts.get();
+            async move {
+                match attempt {
+                    1 | 2 => Err(OpenAIError::StreamError("connection reset".to_string())),
+                    _ => Ok(attempt),
+                }
+            }
+        };
+        let delay = Duration::from_millis(1);
+        assert_eq!(runtime.block_on(retry(3, delay, failing_twice)).unwrap(), 3);
+
+        attempts.set(0);
+        assert!(runtime.block_on(retry(1, delay, failing_twice)).is_err());
+        assert_eq!(attempts.get(), 2);
+
+        attempts.set(0);
+        let invalid = || {
+            attempts.set(attempts.get() + 1);
+            async { Err::<(), _>(OpenAIError::InvalidArgument("no input".to_string())) }
+        };
+        assert!(runtime.block_on(retry(3, delay, invalid)).is_err());
+        assert_eq!(attempts.get(), 1);
+    }
+}
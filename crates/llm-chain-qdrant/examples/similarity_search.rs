@@ -10,7 +10,7 @@ use llm_chain_qdrant::Qdrant;
 use llm_chain_openai::embeddings::Embeddings;
 use qdrant_client::{
     prelude::{QdrantClient, QdrantClientConfig},
-    qdrant::{CreateCollection, Distance, VectorParams, VectorsConfig},
+    qdrant::Distance,
 };
 
 #[tokio::main(flavor = "current_thread")]
@@ -19,31 +19,7 @@ async fn main() {
     let config = QdrantClientConfig::from_url("http://localhost:6334");
     let client = Arc::new(QdrantClient::new(Some(config)).await.unwrap());
     let collection_name = "my-collection".to_string();
-    let embedding_size = 1536;
 
-    if !client
-        .has_collection(collection_name.clone())
-        .await
-        .unwrap()
-    {
-        client
-            .create_collection(&CreateCollection {
-                collection_name: collection_name.clone(),
-                vectors_config: Some(VectorsConfig {
-                    config: Some(qdrant_client::qdrant::vectors_config::Config::Params(
-                        VectorParams {
-                            size: embedding_size,
-                            distance: Distance::Cosine.into(),
-                            hnsw_config: None,
-                            quantization_config: None,
-                        },
-                    )),
-                }),
-                ..Default::default()
-            })
-            .await
-            .unwrap();
-    }
     println!("OPENAI KEY: {}", std::env::var("OPENAI_API_KEY").unwrap());
     let embeddings = llm_chain_openai::embeddings::Embeddings::default();
 
@@ -55,6 +31,11 @@ async fn main() {
         None,
         None,
     );
+    // The collection is sized for the vectors of the embedding model.
+    qdrant
+        .create_collection_if_missing(Distance::Cosine)
+        .await
+        .unwrap();
 
     let doc_dog_definition = r#"The dog (Canis familiaris[4][5] or Canis lupus familiaris[5]) is a domesticated descendant of the wolf. Also called the domestic dog, it is derived from the extinct Pleistocene wolf,[6][7] and the modern wolf is the dog's nearest living relative.[8] Dogs were the first species to be domesticated[9][8] by hunter-gatherers over 15,000 years ago[7] before the development of agriculture.[1] Due to their long association with humans, dogs have expanded to a large number of domestic individuals[10] and gained the ability to thrive on a starch-rich diet that would be inadequate for other canids.[11]
 
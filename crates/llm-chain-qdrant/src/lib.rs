@@ -4,15 +4,19 @@ use async_trait::async_trait;
 use qdrant_client::{
     prelude::QdrantClient,
     qdrant::{
-        value::Kind, with_payload_selector::SelectorOptions, PayloadIncludeSelector, PointId,
-        PointStruct, ScoredPoint, SearchPoints, Value, Vectors, WithPayloadSelector,
+        condition::ConditionOneOf, point_id::PointIdOptions, points_selector::PointsSelectorOneOf,
+        r#match::MatchValue, value::Kind, vectors::VectorsOptions, vectors_config,
+        with_payload_selector::SelectorOptions, with_vectors_selector, Condition, CreateCollection,
+        Distance, FieldCondition, Filter, Match, PayloadIncludeSelector, PointId, PointStruct,
+        PointsIdsList, PointsSelector, SearchPoints, Value, VectorParams, Vectors, VectorsConfig,
+        WithPayloadSelector, WithVectorsSelector,
     },
 };
 use thiserror::Error;
 use uuid::Uuid;
 
 use llm_chain::{
-    schema::Document,
+    schema::{Document, MetadataFilter, Provenance, ScoredDocument},
     traits::{Embeddings, EmbeddingsError, VectorStore, VectorStoreError},
 };
 
@@ -20,6 +24,7 @@ use serde::{de::DeserializeOwned, Serialize};
 
 const DEFAULT_CONTENT_PAYLOAD_KEY: &str = "page_content";
 const DEFAULT_METADATA_PAYLOAD_KEY: &str = "metadata";
+const PROVENANCE_PAYLOAD_KEY: &str = "provenance";
 
 pub struct Qdrant<E, M>
 where
@@ -58,11 +63,49 @@ where
         }
     }
 
-    fn try_document_from_scored_point(
+    /// Creates the collection if it doesn't exist yet, with vectors of the size the embeddings produce.
+    ///
+    /// Returns `true` if the collection was created.
+    pub async fn create_collection_if_missing(
         &self,
-        scored_point: ScoredPoint,
+        distance: Distance,
+    ) -> Result<bool, QdrantError<E::Error>>
+    where
+        E: Sync,
+    {
+        if self
+            .client
+            .has_collection(self.collection_name.clone())
+            .await
+            .map_err(QdrantError::Client)?
+        {
+            return Ok(false);
+        }
+        let size = self.embeddings.detect_dimensions().await?;
+        self.client
+            .create_collection(&CreateCollection {
+                collection_name: self.collection_name.clone(),
+                vectors_config: Some(VectorsConfig {
+                    config: Some(vectors_config::Config::Params(VectorParams {
+                        size: size as u64,
+                        distance: distance.into(),
+                        hnsw_config: None,
+                        quantization_config: None,
+                    })),
+                }),
+                ..Default::default()
+            })
+            .await
+            .map_err(QdrantError::Client)?;
+        Ok(true)
+    }
+
+    fn try_document_from_payload(
+        &self,
+        point_id: Option<PointId>,
+        payload: HashMap<String, Value>,
     ) -> Result<Document<M>, QdrantError<E::Error>> {
-        let metadata = scored_point.payload.get(&self.metadata_payload_key);
+        let metadata = payload.get(&self.metadata_payload_key);
         let metadata: Option<M> = match metadata.cloned() {
             Some(val) => {
                 let j = serde_json::to_value(val).map_err(QdrantError::Serde)?;
@@ -70,13 +113,19 @@ where
             }
             None => None,
         };
-        let page_content = scored_point
-            .payload
+        let provenance: Option<Provenance> = match payload.get(PROVENANCE_PAYLOAD_KEY) {
+            Some(Value { kind: None }) | None => None,
+            Some(val) => {
+                let j = serde_json::to_value(val.clone()).map_err(QdrantError::Serde)?;
+                Some(serde_json::from_value(j).map_err(QdrantError::Serde)?)
+            }
+        };
+        let page_content = payload
             .get(&self.content_payload_key)
             .ok_or::<QdrantError<E::Error>>(
                 ConversionError::PayloadKeyNotFound {
                     payload_key: self.content_payload_key.clone(),
-                    point_id: scored_point.id.clone(),
+                    point_id: point_id.clone(),
                 }
                 .into(),
             )?
@@ -84,22 +133,209 @@ where
             .clone()
             .ok_or::<QdrantError<E::Error>>(
                 ConversionError::InvalidPageContent {
-                    point_id: scored_point.id.clone(),
+                    point_id: point_id.clone(),
                 }
                 .into(),
             )?;
         if let Kind::StringValue(page_content) = page_content {
             Ok(Document {
+                id: point_id.and_then(point_id_to_string),
                 page_content,
                 metadata,
+                provenance,
             })
         } else {
-            Err(ConversionError::InvalidPageContent {
-                point_id: scored_point.id,
-            }
-            .into())
+            Err(ConversionError::InvalidPageContent { point_id }.into())
         }
     }
+
+    /// Searches the points closest to `vector`, with their vectors if `with_vectors` is set. Points are returned with
+    /// an empty vector otherwise.
+    async fn search(
+        &self,
+        vector: Vec<f32>,
+        limit: u32,
+        score_threshold: Option<f32>,
+        with_vectors: bool,
+    ) -> Result<Vec<(ScoredDocument<M>, Vec<f32>)>, QdrantError<E::Error>> {
+        let res = self
+            .client
+            .search_points(&SearchPoints {
+                collection_name: self.collection_name.clone(),
+                vector,
+                filter: None,
+                limit: limit.into(),
+                with_payload: Some(WithPayloadSelector {
+                    selector_options: Some(SelectorOptions::Include(PayloadIncludeSelector {
+                        fields: vec![
+                            self.content_payload_key.clone(),
+                            self.metadata_payload_key.clone(),
+                            PROVENANCE_PAYLOAD_KEY.to_string(),
+                        ],
+                    })),
+                }),
+                params: None,
+                score_threshold,
+                offset: None,
+                vector_name: None,
+                with_vectors: Some(WithVectorsSelector {
+                    selector_options: Some(with_vectors_selector::SelectorOptions::Enable(
+                        with_vectors,
+                    )),
+                }),
+                read_consistency: None,
+            })
+            .await
+            .map_err(QdrantError::Client)?;
+
+        let mut out = vec![];
+        for r in res.result.into_iter() {
+            let score = r.score;
+            let vector = match r.vectors.and_then(|vectors| vectors.vectors_options) {
+                Some(VectorsOptions::Vector(vector)) => vector.data,
+                _ => Vec::new(),
+            };
+            let document = self.try_document_from_payload(r.id, r.payload)?;
+            out.push((ScoredDocument { document, score }, vector));
+        }
+        Ok(out)
+    }
+
+    fn points_for_documents(
+        &self,
+        documents: Vec<Document<M>>,
+        embedding_vecs: Vec<Vec<f32>>,
+        ids: &[String],
+    ) -> Result<Vec<PointStruct>, QdrantError<E::Error>> {
+        embedding_vecs
+            .into_iter()
+            .zip(documents)
+            .zip(ids.iter())
+            .map(|((vec, document), id)| {
+                let mut payload: HashMap<String, Value> = HashMap::new();
+
+                if let Some(metadata) = document.metadata {
+                    let val = serde_json::to_value(metadata).map_err(QdrantError::Serde)?;
+                    payload.insert(self.metadata_payload_key.clone(), val.into());
+                } else {
+                    payload.insert(self.metadata_payload_key.clone(), Value { kind: None });
+                }
+                if let Some(provenance) = document.provenance {
+                    let val = serde_json::to_value(provenance).map_err(QdrantError::Serde)?;
+                    payload.insert(PROVENANCE_PAYLOAD_KEY.to_string(), val.into());
+                }
+                payload.insert(
+                    self.content_payload_key.clone(),
+                    document.page_content.into(),
+                );
+                Ok(PointStruct {
+                    id: Some(id.clone().into()),
+                    payload,
+                    vectors: Some(Vectors::from(vec)),
+                })
+            })
+            .collect()
+    }
+
+    /// Converts a metadata filter to a Qdrant filter on the metadata payload.
+    fn qdrant_filter(&self, filter: &MetadataFilter) -> Result<Filter, ConversionError> {
+        let filter = match filter {
+            MetadataFilter::Equals { key, value } => Filter {
+                must: vec![self.field_condition(key, value)?],
+                ..Default::default()
+            },
+            MetadataFilter::In { key, values } => Filter {
+                should: values
+                    .iter()
+                    .map(|value| self.field_condition(key, value))
+                    .collect::<Result<_, _>>()?,
+                ..Default::default()
+            },
+            MetadataFilter::And(filters) => Filter {
+                must: self.nested_conditions(filters)?,
+                ..Default::default()
+            },
+            MetadataFilter::Or(filters) => Filter {
+                should: self.nested_conditions(filters)?,
+                ..Default::default()
+            },
+            MetadataFilter::Not(filter) => Filter {
+                must_not: self.nested_conditions(std::slice::from_ref(filter.as_ref()))?,
+                ..Default::default()
+            },
+        };
+        Ok(filter)
+    }
+
+    fn nested_conditions(
+        &self,
+        filters: &[MetadataFilter],
+    ) -> Result<Vec<Condition>, ConversionError> {
+        filters
+            .iter()
+            .map(|filter| {
+                Ok(Condition {
+                    condition_one_of: Some(ConditionOneOf::Filter(self.qdrant_filter(filter)?)),
+                })
+            })
+            .collect()
+    }
+
+    fn field_condition(
+        &self,
+        key: &str,
+        value: &serde_json::Value,
+    ) -> Result<Condition, ConversionError> {
+        let match_value = match value {
+            serde_json::Value::String(s) => MatchValue::Keyword(s.clone()),
+            serde_json::Value::Bool(b) => MatchValue::Boolean(*b),
+            serde_json::Value::Number(n) if n.is_i64() => {
+                MatchValue::Integer(n.as_i64().expect("checked is_i64"))
+            }
+            _ => {
+                return Err(ConversionError::UnsupportedFilterValue {
+                    key: key.to_string(),
+                })
+            }
+        };
+        Ok(Condition {
+            condition_one_of: Some(ConditionOneOf::Field(FieldCondition {
+                key: format!("{}.{}", self.metadata_payload_key, key),
+                r#match: Some(Match {
+                    match_value: Some(match_value),
+                }),
+                ..Default::default()
+            })),
+        })
+    }
+}
+
+/// Returns `id` in the form Qdrant returns it: UUIDs are dashed and lowercase, and other ids are kept as they are.
+/// Ids given to the store are normalized, so they match the ids of the documents it returns.
+fn normalize_id(id: &str) -> String {
+    match Uuid::parse_str(id) {
+        Ok(uuid) => uuid.hyphenated().to_string(),
+        Err(_) => id.to_string(),
+    }
+}
+
+fn point_id(id: &str) -> PointId {
+    normalize_id(id).into()
+}
+
+fn point_id_to_string(point_id: PointId) -> Option<String> {
+    match point_id.point_id_options? {
+        PointIdOptions::Uuid(uuid) => Some(normalize_id(&uuid)),
+        PointIdOptions::Num(num) => Some(num.to_string()),
+    }
+}
+
+fn points_selector(ids: &[String]) -> PointsSelector {
+    PointsSelector {
+        points_selector_one_of: Some(PointsSelectorOneOf::Points(PointsIdsList {
+            ids: ids.iter().map(|id| point_id(id)).collect(),
+        })),
+    }
 }
 
 #[derive(Debug, Error)]
@@ -113,6 +349,8 @@ pub enum ConversionError {
     InvalidPageContent { point_id: Option<PointId> },
     #[error("Could not convert metadata. Point ID: {point_id:?}")]
     InvalidMetadata { point_id: Option<PointId> },
+    #[error("Qdrant can only filter {key:?} on strings, integers and booleans")]
+    UnsupportedFilterValue { key: String },
 }
 
 #[derive(Debug, Error)]
@@ -178,32 +416,7 @@ where
             .map(|_| Uuid::new_v4().to_string())
             .collect::<Vec<String>>();
 
-        let points: Result<Vec<PointStruct>, Self::Error> = embedding_vecs
-            .into_iter()
-            .zip(documents.into_iter())
-            .zip(ids.iter())
-            .map(|((vec, document), uuid)| {
-                let mut payload: HashMap<String, Value> = HashMap::new();
-
-                if let Some(metadata) = document.metadata {
-                    let val = serde_json::to_value(metadata).map_err(Self::Error::Serde)?;
-                    payload.insert(self.metadata_payload_key.clone(), val.into());
-                } else {
-                    payload.insert(self.metadata_payload_key.clone(), Value { kind: None });
-                }
-                payload.insert(
-                    self.content_payload_key.clone(),
-                    document.page_content.clone().into(),
-                );
-                Ok(PointStruct {
-                    id: Some(uuid.to_string().into()),
-                    payload,
-                    vectors: Some(Vectors::from(vec)),
-                })
-            })
-            .collect();
-
-        let points = points?;
+        let points = self.points_for_documents(documents, embedding_vecs, &ids)?;
 
         self.client
             .upsert_points(self.collection_name.clone(), points, None)
@@ -213,42 +426,118 @@ where
         Ok(ids)
     }
 
-    async fn similarity_search(
+    async fn similarity_search_with_scores(
         &self,
         query: String,
         limit: u32,
-    ) -> Result<Vec<Document<M>>, Self::Error> {
+        score_threshold: Option<f32>,
+    ) -> Result<Vec<ScoredDocument<M>>, Self::Error> {
         let embedded_query = self.embeddings.embed_query(query).await?;
+        Ok(self
+            .search(embedded_query, limit, score_threshold, false)
+            .await?
+            .into_iter()
+            .map(|(scored, _)| scored)
+            .collect())
+    }
+
+    async fn similarity_search_by_vector(
+        &self,
+        query: Vec<f32>,
+        limit: u32,
+        score_threshold: Option<f32>,
+    ) -> Result<Vec<(ScoredDocument<M>, Vec<f32>)>, Self::Error> {
+        self.search(query, limit, score_threshold, true).await
+    }
+
+    /// Upserts documents. Qdrant only accepts UUIDs and unsigned integers as point ids, so document ids must be
+    /// UUIDs. They are returned dashed and lowercase, the form Qdrant returns them in from searches.
+    async fn upsert_documents(
+        &self,
+        documents: Vec<Document<M>>,
+    ) -> Result<Vec<String>, Self::Error> {
+        let texts = documents.iter().map(|d| d.page_content.clone()).collect();
+        let embedding_vecs = self.embeddings.embed_texts(texts).await?;
+
+        let ids = documents
+            .iter()
+            .map(|d| {
+                d.id.as_deref()
+                    .map(normalize_id)
+                    .unwrap_or_else(|| Uuid::new_v4().to_string())
+            })
+            .collect::<Vec<String>>();
+
+        let points = self.points_for_documents(documents, embedding_vecs, &ids)?;
+
+        self.client
+            .upsert_points(self.collection_name.clone(), points, None)
+            .await
+            .map_err(QdrantError::Client)?;
+
+        Ok(ids)
+    }
+
+    async fn get_by_ids(&self, ids: &[String]) -> Result<Vec<Document<M>>, Self::Error> {
+        let point_ids: Vec<PointId> = ids.iter().map(|id| point_id(id)).collect();
         let res = self
             .client
-            .search_points(&SearchPoints {
-                collection_name: self.collection_name.clone(),
-                vector: embedded_query,
-                filter: None,
-                limit: limit.into(),
-                with_payload: Some(WithPayloadSelector {
-                    selector_options: Some(SelectorOptions::Include(PayloadIncludeSelector {
-                        fields: vec![
-                            self.content_payload_key.clone(),
-                            self.metadata_payload_key.clone(),
-                        ],
-                    })),
-                }),
-                params: None,
-                score_threshold: None,
-                offset: None,
-                vector_name: None,
-                with_vectors: None,
-                read_consistency: None,
-            })
+            .get_points(
+                self.collection_name.clone(),
+                &point_ids,
+                Some(false),
+                Some(true),
+                None,
+            )
             .await
             .map_err(QdrantError::Client)?;
 
-        let mut out = vec![];
-        for r in res.result.into_iter() {
-            let val = self.try_document_from_scored_point(r)?;
-            out.push(val);
-        }
-        Ok(out)
+        res.result
+            .into_iter()
+            .map(|point| self.try_document_from_payload(point.id, point.payload))
+            .collect()
+    }
+
+    async fn delete_by_ids(&self, ids: &[String]) -> Result<(), Self::Error> {
+        self.client
+            .delete_points(self.collection_name.clone(), &points_selector(ids), None)
+            .await
+            .map_err(QdrantError::Client)?;
+        Ok(())
+    }
+
+    async fn delete_by_filter(&self, filter: &MetadataFilter) -> Result<(), Self::Error> {
+        let selector = PointsSelector {
+            points_selector_one_of: Some(PointsSelectorOneOf::Filter(self.qdrant_filter(filter)?)),
+        };
+        self.client
+            .delete_points(self.collection_name.clone(), &selector, None)
+            .await
+            .map_err(QdrantError::Client)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize_id, point_id, point_id_to_string};
+    use qdrant_client::qdrant::PointId;
+
+    #[test]
+    fn ids_round_trip_in_the_form_qdrant_returns() {
+        let undashed = "6C62272E07BB014262B821756295C58D";
+        let normalized = normalize_id(undashed);
+        assert_eq!(normalized, "6c62272e-07bb-0142-62b8-21756295c58d");
+        assert_eq!(
+            point_id_to_string(point_id(undashed)),
+            Some(normalized.clone())
+        );
+        // Qdrant returns UUIDs dashed, whatever form they were upserted in.
+        assert_eq!(
+            point_id_to_string(PointId::from(normalized.clone())),
+            Some(normalized.clone())
+        );
+        assert_eq!(normalize_id(&normalized), normalized);
+        assert_eq!(normalize_id("not-a-uuid"), "not-a-uuid");
     }
 }
@@ -0,0 +1,198 @@
+//! Redis-backed shared state for llm-chain.
+//!
+//! `RedisBudgetStore` keeps the buckets of a `Budget` in Redis. Every replica of a service connected to the same Redis
+//! server draws from the same buckets, so they enforce one budget between them instead of one each.
+//!
+//! ```no_run
+//! use llm_chain::budget::{Budget, BucketLimit};
+//! use llm_chain_redis::RedisBudgetStore;
+//!
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let store = RedisBudgetStore::connect("redis://127.0.0.1/").await?;
+//! let budget = Budget::new(store, "my-org").with_token_limit(BucketLimit::per_minute(90_000.0));
+//! budget.consume(1_000).await?;
+//! # Ok(())
+//! # }
+//! ```
+use async_trait::async_trait;
+use llm_chain::budget::{BoxedBudgetStoreError, BudgetDecision, BudgetStore, Charge};
+use redis::aio::ConnectionManager;
+use redis::{RedisError, Script};
+
+/// Refills the buckets in `KEYS`, then takes from each the amount of its charge. `ARGV[1]` is the mode: `try` takes
+/// nothing unless every bucket holds enough, `adjust` takes the amounts unconditionally, giving back up to the
+/// capacity for negative ones. The limit and the amount of the charge to `KEYS[i]` follow, as `capacity`,
+/// `refill_per_second` and `amount`.
+///
+/// Returns the index, from 1, of the first bucket that doesn't hold enough, or 0, then the levels of the buckets.
+///
+/// The time is read from the Redis server so that the clocks of the replicas don't matter. Buckets expire once they
+/// would be full again, which is the same as not existing.
+const CONSUME_SCRIPT: &str = r#"
+local adjust = ARGV[1] == 'adjust'
+local time = redis.call('TIME')
+local now = tonumber(time[1]) + tonumber(time[2]) / 1000000
+local levels = {}
+local denied = 0
+for i, key in ipairs(KEYS) do
+  local capacity = tonumber(ARGV[3 * i - 1])
+  local rate = tonumber(ARGV[3 * i])
+  local amount = tonumber(ARGV[3 * i + 1])
+  local state = redis.call('HMGET', key, 'level', 'updated')
+  local level = tonumber(state[1]) or capacity
+  local updated = tonumber(state[2]) or now
+  levels[i] = math.min(capacity, level + math.max(0, now - updated) * rate)
+  if not adjust and amount > levels[i] and denied == 0 then
+    denied = i
+  end
+end
+local result = {tostring(denied)}
+for i, key in ipairs(KEYS) do
+  local capacity = tonumber(ARGV[3 * i - 1])
+  local rate = tonumber(ARGV[3 * i])
+  local amount = tonumber(ARGV[3 * i + 1])
+  if denied == 0 then
+    levels[i] = math.min(capacity, levels[i] - amount)
+  end
+  redis.call('HSET', key, 'level', tostring(levels[i]), 'updated', tostring(now))
+  if rate > 0 then
+    redis.call('EXPIRE', key, math.ceil((capacity - levels[i]) / rate) + 1)
+  end
+  result[i + 1] = tostring(levels[i])
+end
+return result
+"#;
+
+/// A `BudgetStore` keeping buckets in Redis, shared by every process using the same server.
+///
+/// Buckets are stored as hashes under the key `<prefix><budget key>`. The prefix defaults to `llm-chain:budget:`.
+#[derive(Clone)]
+pub struct RedisBudgetStore {
+    connection: ConnectionManager,
+    prefix: String,
+    script: Script,
+}
+
+impl RedisBudgetStore {
+    /// Connects to the Redis server at `url`, such as `redis://127.0.0.1/`. The connection is re-established
+    /// automatically if it is lost.
+    pub async fn connect(url: &str) -> Result<Self, RedisError> {
+        let client = redis::Client::open(url)?;
+        Ok(Self::new(ConnectionManager::new(client).await?))
+    }
+
+    pub fn new(connection: ConnectionManager) -> Self {
+        Self {
+            connection,
+            prefix: "llm-chain:budget:".to_string(),
+            script: Script::new(CONSUME_SCRIPT),
+        }
+    }
+
+    /// Sets the prefix of the keys of the buckets.
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    /// Runs the script in `mode` and returns the index of the first bucket short, if any, and the levels of the
+    /// buckets.
+    async fn run(
+        &self,
+        mode: &str,
+        charges: &[Charge<'_>],
+    ) -> Result<(Option<usize>, Vec<f64>), BoxedBudgetStoreError> {
+        let mut invocation = self.script.prepare_invoke();
+        invocation.arg(mode);
+        for charge in charges {
+            invocation
+                .key(format!("{}{}", self.prefix, charge.key))
+                .arg(charge.limit.capacity)
+                .arg(charge.limit.refill_per_second)
+                .arg(charge.amount);
+        }
+        let mut connection = self.connection.clone();
+        let result: Vec<String> = invocation.invoke_async(&mut connection).await?;
+        let denied: usize = result[0].parse()?;
+        let levels = result[1..]
+            .iter()
+            .map(|level| level.parse())
+            .collect::<Result<_, _>>()?;
+        Ok((denied.checked_sub(1), levels))
+    }
+}
+
+#[async_trait]
+impl BudgetStore for RedisBudgetStore {
+    async fn try_consume(
+        &self,
+        charges: &[Charge<'_>],
+    ) -> Result<BudgetDecision, BoxedBudgetStoreError> {
+        Ok(match self.run("try", charges).await? {
+            (None, remaining) => BudgetDecision::Granted { remaining },
+            (Some(bucket), levels) => BudgetDecision::Denied {
+                bucket,
+                retry_after: charges
+                    .iter()
+                    .zip(levels)
+                    .filter(|(charge, level)| charge.amount > *level)
+                    .map(|(charge, level)| charge.limit.time_until(level, charge.amount))
+                    .max()
+                    .unwrap_or_default(),
+            },
+        })
+    }
+
+    async fn adjust(&self, charges: &[Charge<'_>]) -> Result<(), BoxedBudgetStoreError> {
+        self.run("adjust", charges).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RedisBudgetStore;
+    use llm_chain::budget::{BucketLimit, Budget, BudgetError};
+
+    /// Connects to the server at `REDIS_URL`, with keys of its own for `test`.
+    async fn store(test: &str) -> RedisBudgetStore {
+        let url = std::env::var("REDIS_URL").expect("REDIS_URL is not set");
+        RedisBudgetStore::connect(&url)
+            .await
+            .unwrap()
+            .with_prefix(&format!("llm-chain-test:{}:{}:", std::process::id(), test))
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Redis server at `REDIS_URL`"]
+    async fn charges_every_limit_or_none() {
+        let store = store("charges_every_limit_or_none").await;
+        let budget = Budget::new(store.clone(), "test")
+            .with_token_limit(BucketLimit::per_hour(1000.0))
+            .with_cost_limit(BucketLimit::per_hour(1.0), 2.0);
+        match budget.consume(600).await {
+            Err(BudgetError::Exceeded { budget, .. }) => assert_eq!(budget, "cost"),
+            other => panic!("unexpected result {:?}", other),
+        }
+        let budget = Budget::new(store, "test").with_token_limit(BucketLimit::per_hour(1000.0));
+        budget.consume(1000).await.unwrap();
+        assert!(budget.consume(1).await.is_err());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Redis server at `REDIS_URL`"]
+    async fn adjusts_charges_to_the_tokens_used() {
+        let store = store("adjusts_charges_to_the_tokens_used").await;
+        let budget = Budget::new(store, "test").with_token_limit(BucketLimit::per_hour(1000.0));
+        budget.consume(500).await.unwrap();
+        budget.adjust(-300).await.unwrap();
+        budget.consume(800).await.unwrap();
+        budget.adjust(100).await.unwrap();
+        match budget.consume(1).await {
+            Err(BudgetError::Exceeded { retry_after, .. }) => {
+                assert!(retry_after.as_secs() > 360)
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
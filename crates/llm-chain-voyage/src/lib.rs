@@ -0,0 +1,202 @@
+//! Voyage AI embeddings for llm-chain.
+//!
+//! Voyage's models embed a text differently depending on whether it is a query or a document. `Embeddings` embeds
+//! documents with `embed_texts` and queries with `embed_query` with the matching `input_type`, so it works as expected
+//! in vector stores and retrievers. Texts embedded for other purposes are sent without an input type.
+//!
+//! For searching source code, use `voyage-code-3`:
+//!
+//! ```no_run
+//! let embeddings = llm_chain_voyage::Embeddings::from_env()
+//!     .unwrap()
+//!     .with_model("voyage-code-3");
+//! ```
+use async_trait::async_trait;
+use llm_chain::traits::{self, EmbeddingPurpose, EmbeddingsError, TruncationPolicy};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+const EMBEDDINGS_URL: &str = "https://api.voyageai.com/v1/embeddings";
+/// The maximum number of texts the API accepts in one request.
+const MAX_BATCH_SIZE: usize = 128;
+
+#[derive(Debug, Error)]
+pub enum VoyageEmbeddingsError {
+    #[error("The VOYAGE_API_KEY environment variable is not set")]
+    MissingApiKey,
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+    #[error("The API returned {returned} embeddings for {expected} texts")]
+    UnexpectedResponse { expected: usize, returned: usize },
+}
+
+impl EmbeddingsError for VoyageEmbeddingsError {}
+
+#[derive(Serialize)]
+struct EmbeddingsRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+    input_type: Option<&'static str>,
+    truncation: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_dimension: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingsResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    embedding: Vec<f32>,
+    index: usize,
+}
+
+fn input_type(purpose: EmbeddingPurpose) -> Option<&'static str> {
+    match purpose {
+        EmbeddingPurpose::Document => Some("document"),
+        EmbeddingPurpose::Query => Some("query"),
+        EmbeddingPurpose::Classification | EmbeddingPurpose::Clustering => None,
+    }
+}
+
+/// Returns the default length of the vectors of Voyage's embedding models.
+fn model_dimensions(model: &str) -> Option<usize> {
+    match model {
+        "voyage-3"
+        | "voyage-3-large"
+        | "voyage-code-3"
+        | "voyage-finance-2"
+        | "voyage-law-2"
+        | "voyage-multilingual-2" => Some(1024),
+        "voyage-3-lite" => Some(512),
+        "voyage-code-2" => Some(1536),
+        _ => None,
+    }
+}
+
+/// Embeddings computed with Voyage AI's embeddings API.
+///
+/// Inputs longer than the model accepts are truncated at the end by default. Voyage can only truncate the end of an
+/// input, so `TruncationPolicy::Start` behaves like `TruncationPolicy::End`.
+pub struct Embeddings {
+    client: reqwest::Client,
+    api_key: String,
+    model: String,
+    truncate: bool,
+    output_dimension: Option<usize>,
+}
+
+impl Embeddings {
+    /// Creates embeddings using `voyage-3`.
+    pub fn new(api_key: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            api_key,
+            model: "voyage-3".to_string(),
+            truncate: true,
+            output_dimension: None,
+        }
+    }
+
+    /// Creates embeddings with the API key in the `VOYAGE_API_KEY` environment variable.
+    pub fn from_env() -> Result<Self, VoyageEmbeddingsError> {
+        std::env::var("VOYAGE_API_KEY")
+            .map(Self::new)
+            .map_err(|_| VoyageEmbeddingsError::MissingApiKey)
+    }
+
+    /// Sets the model to use, such as `voyage-code-3` or `voyage-3-lite`.
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.model = model.to_string();
+        self
+    }
+
+    /// Sets what the API does with inputs longer than the model accepts.
+    pub fn with_truncation_policy(mut self, truncation_policy: TruncationPolicy) -> Self {
+        self.truncate = truncation_policy != TruncationPolicy::Error;
+        self
+    }
+
+    /// Requests vectors of `dimension` dimensions instead of the model's default. Only `voyage-3-large` and
+    /// `voyage-code-3` support this, with 256, 512, 1024 or 2048 dimensions.
+    pub fn with_output_dimension(mut self, dimension: usize) -> Self {
+        self.output_dimension = Some(dimension);
+        self
+    }
+
+    async fn embed_batch(
+        &self,
+        texts: &[String],
+        purpose: EmbeddingPurpose,
+    ) -> Result<Vec<Vec<f32>>, VoyageEmbeddingsError> {
+        let response = self
+            .client
+            .post(EMBEDDINGS_URL)
+            .bearer_auth(&self.api_key)
+            .json(&EmbeddingsRequest {
+                model: &self.model,
+                input: texts,
+                input_type: input_type(purpose),
+                truncation: self.truncate,
+                output_dimension: self.output_dimension,
+            })
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<EmbeddingsResponse>()
+            .await?;
+        let mut data = response.data;
+        if data.len() != texts.len() {
+            return Err(VoyageEmbeddingsError::UnexpectedResponse {
+                expected: texts.len(),
+                returned: data.len(),
+            });
+        }
+        data.sort_by_key(|d| d.index);
+        Ok(data.into_iter().map(|d| d.embedding).collect())
+    }
+}
+
+#[async_trait]
+impl traits::Embeddings for Embeddings {
+    type Error = VoyageEmbeddingsError;
+
+    async fn embed_texts(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Self::Error> {
+        self.embed_texts_for(texts, EmbeddingPurpose::Document)
+            .await
+    }
+
+    async fn embed_query(&self, query: String) -> Result<Vec<f32>, Self::Error> {
+        let mut embeddings = self
+            .embed_texts_for(vec![query], EmbeddingPurpose::Query)
+            .await?;
+        Ok(embeddings.remove(0))
+    }
+
+    async fn embed_texts_for(
+        &self,
+        texts: Vec<String>,
+        purpose: EmbeddingPurpose,
+    ) -> Result<Vec<Vec<f32>>, Self::Error> {
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(MAX_BATCH_SIZE) {
+            embeddings.extend(self.embed_batch(batch, purpose).await?);
+        }
+        Ok(embeddings)
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        self.output_dimension
+            .or_else(|| model_dimensions(&self.model))
+    }
+
+    fn truncation_policy(&self) -> Option<TruncationPolicy> {
+        Some(if self.truncate {
+            TruncationPolicy::End
+        } else {
+            TruncationPolicy::Error
+        })
+    }
+}
@@ -0,0 +1,225 @@
+//! A scripted executor and a tool for the tests of the agents.
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::task::Poll;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::output::{Output, ToolCall};
+use crate::prompt::Prompt;
+use crate::tokens::{PromptTokensError, TokenCount, Tokenizer, TokenizerError};
+use crate::tools::{Tool, ToolDefinition, ToolDescription, ToolError};
+use crate::traits::{Executor, ExecutorCreationError, ExecutorError, Options};
+use crate::NaiveWhitespaceSplitter;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MockOptions;
+
+impl Options for MockOptions {}
+
+#[derive(Clone)]
+pub struct MockOutput(pub Option<String>, pub Vec<ToolCall>);
+
+impl MockOutput {
+    pub fn text(text: &str) -> Self {
+        Self(Some(text.to_string()), Vec::new())
+    }
+}
+
+#[async_trait]
+impl Output for MockOutput {
+    async fn primary_textual_output_choices(&self) -> Vec<String> {
+        self.0.iter().cloned().collect()
+    }
+
+    async fn tool_calls(&self) -> Vec<ToolCall> {
+        self.1.clone()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("mock error")]
+pub struct MockError;
+
+impl ExecutorError for MockError {}
+
+impl ToolError for MockError {}
+
+impl From<serde_yaml::Error> for MockError {
+    fn from(_: serde_yaml::Error) -> Self {
+        Self
+    }
+}
+
+/// An executor answering with scripted outputs, and recording the prompts and the tools it was given. It fails once
+/// the outputs run out, and on the prompts containing one of `failing_prompts`, without using an output.
+///
+/// With `yielding`, every call yields once before returning, so calls made together overlap; `max_running` records
+/// how many ran at the same time.
+///
+/// Tokens are the words of the text, separated by whitespace, and the context window holds `max_tokens` of them.
+pub struct MockExecutor {
+    pub outputs: Mutex<Vec<MockOutput>>,
+    pub prompts: Mutex<Vec<Prompt>>,
+    pub tools: Mutex<Vec<String>>,
+    pub failing_prompts: Vec<String>,
+    pub running: AtomicUsize,
+    pub max_running: AtomicUsize,
+    pub yields: bool,
+    pub max_tokens: i32,
+}
+
+impl MockExecutor {
+    pub fn new(outputs: Vec<MockOutput>) -> Self {
+        Self {
+            outputs: Mutex::new(outputs),
+            prompts: Mutex::new(Vec::new()),
+            tools: Mutex::new(Vec::new()),
+            failing_prompts: Vec::new(),
+            running: AtomicUsize::new(0),
+            max_running: AtomicUsize::new(0),
+            yields: false,
+            max_tokens: 1000,
+        }
+    }
+
+    /// Fails the calls whose prompt contains `text`.
+    pub fn with_failing_prompt(mut self, text: &str) -> Self {
+        self.failing_prompts.push(text.to_string());
+        self
+    }
+
+    /// Yields once in every call before returning.
+    pub fn yielding(mut self) -> Self {
+        self.yields = true;
+        self
+    }
+}
+
+#[async_trait]
+impl Executor for MockExecutor {
+    type PerInvocationOptions = MockOptions;
+    type PerExecutorOptions = MockOptions;
+    type Output = MockOutput;
+    type Error = MockError;
+    type Token = String;
+    type StepTokenizer<'a> = NaiveWhitespaceSplitter;
+    type TextSplitter<'a> = NaiveWhitespaceSplitter;
+
+    fn new_with_options(
+        _: Option<MockOptions>,
+        _: Option<MockOptions>,
+    ) -> Result<Self, ExecutorCreationError> {
+        unimplemented!()
+    }
+
+    async fn execute(
+        &self,
+        _: Option<&MockOptions>,
+        prompt: &Prompt,
+        _: Option<bool>,
+    ) -> Result<MockOutput, MockError> {
+        self.prompts.lock().unwrap().push(prompt.clone());
+        let text = prompt.to_string();
+        let output = if self
+            .failing_prompts
+            .iter()
+            .any(|f| text.contains(f.as_str()))
+        {
+            None
+        } else {
+            let mut outputs = self.outputs.lock().unwrap();
+            (!outputs.is_empty()).then(|| outputs.remove(0))
+        };
+        let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_running.fetch_max(running, Ordering::SeqCst);
+        let mut yielded = !self.yields;
+        futures::future::poll_fn(|cx| {
+            if yielded {
+                Poll::Ready(())
+            } else {
+                yielded = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        })
+        .await;
+        self.running.fetch_sub(1, Ordering::SeqCst);
+        output.ok_or(MockError)
+    }
+
+    fn tokens_used(
+        &self,
+        _: Option<&MockOptions>,
+        prompt: &Prompt,
+    ) -> Result<TokenCount, PromptTokensError> {
+        let tokens = NaiveWhitespaceSplitter
+            .tokenize_str(&prompt.to_string())
+            .map_err(|_| PromptTokensError::UnableToCompute)?;
+        Ok(TokenCount::new(self.max_tokens, tokens.len() as i32))
+    }
+
+    fn max_tokens_allowed(&self, _: Option<&MockOptions>) -> i32 {
+        self.max_tokens
+    }
+
+    fn answer_prefix(&self, _: &Prompt) -> Option<String> {
+        None
+    }
+
+    fn tool_options(
+        &self,
+        _: Option<&MockOptions>,
+        tools: &[ToolDefinition],
+    ) -> Option<MockOptions> {
+        *self.tools.lock().unwrap() = tools.iter().map(|tool| tool.name.clone()).collect();
+        Some(MockOptions)
+    }
+
+    fn get_tokenizer(
+        &self,
+        _: Option<&MockOptions>,
+    ) -> Result<NaiveWhitespaceSplitter, TokenizerError> {
+        Ok(NaiveWhitespaceSplitter)
+    }
+
+    fn get_text_splitter(
+        &self,
+        _: Option<&MockOptions>,
+    ) -> Result<NaiveWhitespaceSplitter, MockError> {
+        Ok(NaiveWhitespaceSplitter)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct AddInput {
+    a: i64,
+    b: i64,
+}
+
+/// A tool adding two numbers.
+pub struct AddTool;
+
+#[async_trait]
+impl Tool for AddTool {
+    type Input = AddInput;
+    type Output = i64;
+    type Error = MockError;
+
+    async fn invoke_typed(&self, input: &AddInput) -> Result<i64, MockError> {
+        Ok(input.a + input.b)
+    }
+
+    fn description(&self) -> ToolDescription {
+        ToolDescription::new(
+            "add",
+            "Adds two numbers",
+            "",
+            [("a", "a number"), ("b", "another number")]
+                .map(Into::into)
+                .into(),
+            [("sum", "the sum")].map(Into::into).into(),
+        )
+    }
+}
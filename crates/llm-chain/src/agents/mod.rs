@@ -1 +1,47 @@
+//! Agents use a model to decide which tools to call, and when to answer.
+//!
+//! - `react` prompts the model to alternate thoughts, actions and observations in text, and works with any model.
+//! - `tool_calling` relies on the native tool calling of the model, advertising the tools with JSON Schemas.
+//! - `plan_and_execute` writes a plan first, then carries out its steps with any of the above, revising the plan
+//!   when a step fails.
+//! - `self_ask_with_search` answers questions by asking follow-up questions to a search tool.
+use crate::tools::{ToolError, ToolUseError};
+
+#[cfg(test)]
+pub(crate) mod mock;
+pub mod plan_and_execute;
+pub mod react;
 pub mod self_ask_with_search;
+pub mod tool_calling;
+
+/// Formats the output of a tool for the model: strings as they are, and other values as YAML.
+pub(crate) fn observation_text(observation: &serde_yaml::Value) -> String {
+    match observation {
+        serde_yaml::Value::String(observation) => observation.clone(),
+        observation => serde_yaml::to_string(observation)
+            .unwrap_or_default()
+            .trim_end()
+            .to_string(),
+    }
+}
+
+/// Returns the output of a call of `tool`, or its error described for the model to correct the call.
+pub(crate) fn observe<E>(
+    result: Result<serde_yaml::Value, ToolUseError<E>>,
+    tool: &str,
+    tool_names: &[String],
+) -> serde_yaml::Value
+where
+    E: ToolError + std::fmt::Debug + std::error::Error,
+{
+    match result {
+        Ok(observation) => observation,
+        Err(ToolUseError::ToolNotFound) => format!(
+            "Error: there is no tool named `{}`. The tools are: {}.",
+            tool,
+            tool_names.join(", ")
+        )
+        .into(),
+        Err(error) => format!("Error: {}", error).into(),
+    }
+}
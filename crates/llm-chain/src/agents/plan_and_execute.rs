@@ -0,0 +1,449 @@
+//! An agent that plans the steps reaching an objective before carrying them out, one at a time.
+//!
+//! The model first writes the plan, a numbered list of steps. Each step is then given to a `StepRunner` along with
+//! the results of the previous steps: by default the model carries out the steps itself, but a `ReActAgent` or a
+//! `ToolCallingAgent` can be used to carry them out with tools, or a sequential chain to run a sub-chain. When a step
+//! fails, the model revises the rest of the plan knowing the error, within the budget of revisions given to the
+//! agent. Once every step is done, the model answers the objective from their results.
+//!
+//! Every version of the plan and the result of every step are kept in a `PlanTrace`, returned with the answer, or
+//! with the error when the agent gives up, to see how the agent reached it.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut tools = ToolCollection::new();
+//! tools.add_tool(BingSearch::new(api_key));
+//! let agent = PlanAndExecuteAgent::new(executor.clone())
+//!     .with_runner(ReActAgent::new(executor, tools))
+//!     .with_max_revisions(1);
+//! let output = agent.run("Compare the populations of the capitals of Norway and Sweden").await?;
+//! for (i, plan) in output.trace.plans.iter().enumerate() {
+//!     println!("Plan {}: {:?}", i, plan);
+//! }
+//! println!("{}", output.answer);
+//! ```
+use async_trait::async_trait;
+use thiserror::Error;
+
+use super::react::ReActAgent;
+use super::tool_calling::ToolCallingAgent;
+use crate::{
+    chains::sequential,
+    output::Output,
+    output_parser::{ListParser, OutputParser, OutputParserError},
+    parameters,
+    prompt::{Prompt, PromptTemplate, StringTemplateError},
+    tools::Tool,
+    traits::{Executor, ExecutorError},
+    Parameters,
+};
+
+const PLAN_PROMPT: &str = "Let's first understand the objective and devise a plan to reach it. Write the plan as a numbered list of steps, one per line, and nothing else. Each step should be a self-contained task whose result helps reach the objective; the result of the last step should be what the objective asks for. Don't add superfluous steps.
+
+Objective: {{objective}}";
+
+const REPLAN_PROMPT: &str = "You are carrying out a plan to reach an objective.
+
+Objective: {{objective}}
+
+Plan:
+{{plan}}
+
+Steps done so far, with their results:
+{{results}}
+
+The step \"{{step}}\" failed with the following error:
+{{error}}
+
+Revise the rest of the plan to reach the objective despite the error, without repeating the steps already done. Write the remaining steps as a numbered list, one per line, and nothing else.";
+
+const ANSWER_PROMPT: &str = "Objective: {{objective}}
+
+The following steps were carried out to reach the objective, with their results:
+{{results}}
+
+Using these results, answer the objective.";
+
+/// The error type returned by step runners.
+pub type StepRunnerError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Carries out the steps of the plans of a `PlanAndExecuteAgent`.
+#[async_trait]
+pub trait StepRunner<E: Executor>: Send + Sync {
+    /// Carries out `task`, returning its result. The task describes the objective, the results of the previous
+    /// steps, and the step to carry out.
+    async fn run_step(&self, task: &str, executor: &E) -> Result<String, StepRunnerError>;
+}
+
+/// A step runner asking the model of the agent to carry out the steps, without tools.
+#[derive(Debug, Clone, Default)]
+pub struct ModelStepRunner;
+
+#[async_trait]
+impl<E> StepRunner<E> for ModelStepRunner
+where
+    E: Executor + Sync,
+    E::Error: Send + Sync + 'static,
+{
+    async fn run_step(&self, task: &str, executor: &E) -> Result<String, StepRunnerError> {
+        let output = executor
+            .execute(None, &Prompt::text(task.to_string()), None)
+            .await?;
+        output
+            .primary_textual_output()
+            .await
+            .ok_or_else(|| "Model response was empty or contained no choices".into())
+    }
+}
+
+/// Runs the chain with the task in `text`, returning the text of its output.
+#[async_trait]
+impl<E> StepRunner<E> for sequential::Chain<E>
+where
+    E: Executor + Sync,
+    E::Error: Send + Sync + 'static,
+    E::Output: Send,
+    E::PerInvocationOptions: Sync,
+{
+    async fn run_step(&self, task: &str, executor: &E) -> Result<String, StepRunnerError> {
+        let output = self.run(Parameters::new_with_text(task), executor).await?;
+        output
+            .primary_textual_output()
+            .await
+            .ok_or_else(|| "The chain returned no text".into())
+    }
+}
+
+/// Answers the task as a question with tools, using the executor of the ReAct agent.
+#[async_trait]
+impl<E, A, T> StepRunner<E> for ReActAgent<A, T>
+where
+    E: Executor + Sync,
+    A: Executor + Send + Sync,
+    A::Error: Send + Sync + 'static,
+    A::Output: Send,
+    T: Tool + Send + Sync,
+{
+    async fn run_step(&self, task: &str, _executor: &E) -> Result<String, StepRunnerError> {
+        let (finish, _) = self.run(task).await?;
+        Ok(finish.return_values.get("output").unwrap_or_default())
+    }
+}
+
+/// Answers the task with tools, using the executor of the tool calling agent.
+#[async_trait]
+impl<E, A, T> StepRunner<E> for ToolCallingAgent<A, T>
+where
+    E: Executor + Sync,
+    A: Executor + Send + Sync,
+    A::Error: Send + Sync + 'static,
+    A::Output: Send,
+    A::PerInvocationOptions: Sync,
+    T: Tool + Send + Sync,
+{
+    async fn run_step(&self, task: &str, _executor: &E) -> Result<String, StepRunnerError> {
+        let (output, _) = self.run(task).await?;
+        output
+            .primary_textual_output()
+            .await
+            .ok_or_else(|| "Model response was empty or contained no choices".into())
+    }
+}
+
+/// A step of a plan that was carried out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepTrace {
+    /// The step, as written in the plan.
+    pub step: String,
+    /// The index in `PlanTrace::plans` of the plan the step belongs to.
+    pub plan: usize,
+    /// The result of the step, or its error.
+    pub result: Result<String, String>,
+}
+
+/// The plans written by a `PlanAndExecuteAgent` and the steps it carried out.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PlanTrace {
+    /// The initial plan, followed by the revisions written after failed steps. A revision only holds the steps
+    /// remaining after the failed step.
+    pub plans: Vec<Vec<String>>,
+    /// The steps carried out, in order, including the ones that failed.
+    pub steps: Vec<StepTrace>,
+}
+
+impl PlanTrace {
+    /// Formats the steps that succeeded and their results as a numbered list, for prompts.
+    fn results(&self) -> String {
+        let results: Vec<String> = self
+            .steps
+            .iter()
+            .filter_map(|trace| trace.result.as_ref().ok().map(|result| (trace, result)))
+            .enumerate()
+            .map(|(i, (trace, result))| {
+                format!("{}. {}\nResult: {}", i + 1, trace.step, result.trim())
+            })
+            .collect();
+        if results.is_empty() {
+            "None".to_string()
+        } else {
+            results.join("\n\n")
+        }
+    }
+}
+
+/// The output of `PlanAndExecuteAgent::run`.
+#[derive(Debug, Clone)]
+pub struct PlanAndExecuteOutput {
+    /// The answer of the model to the objective.
+    pub answer: String,
+    /// How the agent reached the answer.
+    pub trace: PlanTrace,
+}
+
+/// The error returned by `PlanAndExecuteAgent::run`.
+#[derive(Debug, Error)]
+pub enum PlanAndExecuteError<E: ExecutorError> {
+    #[error(transparent)]
+    Executor(E),
+    #[error(transparent)]
+    StringTemplate(#[from] StringTemplateError),
+    #[error("Model response was empty or contained no choices")]
+    NoChoicesReturned,
+    /// The model didn't answer with a list of steps.
+    #[error("The plan couldn't be parsed: {0}")]
+    InvalidPlan(OutputParserError),
+    /// A step failed after the plan was revised as many times as allowed.
+    #[error("The step \"{step}\" failed: {error}")]
+    StepFailed {
+        step: String,
+        error: String,
+        trace: PlanTrace,
+    },
+    /// The plans had more steps than the budget of steps.
+    #[error("The objective wasn't reached after {steps} steps")]
+    MaxStepsExceeded { steps: usize, trace: PlanTrace },
+}
+
+/// An agent writing a plan and carrying out its steps. See the module documentation.
+pub struct PlanAndExecuteAgent<E: Executor> {
+    executor: E,
+    runner: Box<dyn StepRunner<E>>,
+    max_steps: usize,
+    max_revisions: usize,
+    parser: ListParser,
+}
+
+impl<E> PlanAndExecuteAgent<E>
+where
+    E: Executor + Sync + 'static,
+    E::Error: Send + Sync + 'static,
+{
+    /// Creates an agent with `executor` writing the plans and the answer, and carrying out the steps.
+    pub fn new(executor: E) -> Self {
+        Self {
+            executor,
+            runner: Box::new(ModelStepRunner),
+            max_steps: 10,
+            max_revisions: 2,
+            parser: ListParser::new().with_deduplication(false),
+        }
+    }
+
+    /// Sets the runner carrying out the steps, such as an agent with tools or a chain.
+    pub fn with_runner<R: StepRunner<E> + 'static>(mut self, runner: R) -> Self {
+        self.runner = Box::new(runner);
+        self
+    }
+
+    /// Sets the maximum number of steps carried out, including the ones that fail. Defaults to 10.
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Sets how many times the plan may be revised after a step fails. Defaults to 2.
+    pub fn with_max_revisions(mut self, max_revisions: usize) -> Self {
+        self.max_revisions = max_revisions;
+        self
+    }
+
+    /// Plans and carries out the steps reaching `objective`, returning the answer of the model along with the trace
+    /// of the plans and steps.
+    pub async fn run(
+        &self,
+        objective: &str,
+    ) -> Result<PlanAndExecuteOutput, PlanAndExecuteError<E::Error>> {
+        let mut trace = PlanTrace::default();
+        let plan = self
+            .ask(PLAN_PROMPT, parameters!("objective" => objective))
+            .await?;
+        let mut remaining = self.parse_plan(&plan)?;
+        trace.plans.push(remaining.clone());
+        remaining.reverse();
+        while let Some(step) = remaining.pop() {
+            if trace.steps.len() == self.max_steps {
+                return Err(PlanAndExecuteError::MaxStepsExceeded {
+                    steps: self.max_steps,
+                    trace,
+                });
+            }
+            let task = format!(
+                "Objective: {}\n\nSteps done so far, with their results:\n{}\n\nCarry out the following step only, and answer with its result: {}",
+                objective,
+                trace.results(),
+                step
+            );
+            let result = self
+                .runner
+                .run_step(&task, &self.executor)
+                .await
+                .map_err(|error| error.to_string());
+            let failed = result.as_ref().err().cloned();
+            trace.steps.push(StepTrace {
+                step: step.clone(),
+                plan: trace.plans.len() - 1,
+                result,
+            });
+            let Some(error) = failed else {
+                continue;
+            };
+            if trace.plans.len() > self.max_revisions {
+                return Err(PlanAndExecuteError::StepFailed { step, error, trace });
+            }
+            let plan = trace
+                .plans
+                .last()
+                .expect("the initial plan is written first");
+            let revision = self
+                .ask(
+                    REPLAN_PROMPT,
+                    parameters!(
+                        "objective" => objective,
+                        "plan" => format_plan(plan),
+                        "results" => trace.results(),
+                        "step" => step,
+                        "error" => error
+                    ),
+                )
+                .await?;
+            remaining = self.parse_plan(&revision)?;
+            trace.plans.push(remaining.clone());
+            remaining.reverse();
+        }
+        let answer = self
+            .ask(
+                ANSWER_PROMPT,
+                parameters!("objective" => objective, "results" => trace.results()),
+            )
+            .await?;
+        Ok(PlanAndExecuteOutput { answer, trace })
+    }
+
+    fn parse_plan(&self, text: &str) -> Result<Vec<String>, PlanAndExecuteError<E::Error>> {
+        self.parser
+            .parse(text)
+            .map_err(PlanAndExecuteError::InvalidPlan)
+    }
+
+    /// Formats `template` with `parameters` and returns the text the model answers with.
+    async fn ask(
+        &self,
+        template: &str,
+        parameters: Parameters,
+    ) -> Result<String, PlanAndExecuteError<E::Error>> {
+        let prompt = PromptTemplate::Text(template.into()).format(&parameters)?;
+        let output = self
+            .executor
+            .execute(None, &prompt, None)
+            .await
+            .map_err(PlanAndExecuteError::Executor)?;
+        output
+            .primary_textual_output()
+            .await
+            .ok_or(PlanAndExecuteError::NoChoicesReturned)
+    }
+}
+
+/// Formats the steps of a plan as a numbered list.
+fn format_plan(plan: &[String]) -> String {
+    plan.iter()
+        .enumerate()
+        .map(|(i, step)| format!("{}. {}", i + 1, step))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::agents::mock::{MockExecutor, MockOutput};
+
+    /// A runner failing the steps mentioning "fail", and recording the tasks it was given.
+    struct FlakyRunner(Mutex<Vec<String>>);
+
+    #[async_trait]
+    impl<E: Executor + Sync> StepRunner<E> for FlakyRunner {
+        async fn run_step(&self, task: &str, _: &E) -> Result<String, StepRunnerError> {
+            self.0.lock().unwrap().push(task.to_string());
+            let step = task.rsplit(": ").next().unwrap();
+            if step.contains("fail") {
+                return Err("the step failed".into());
+            }
+            Ok(format!("did {}", step))
+        }
+    }
+
+    #[test]
+    fn carries_out_and_revises_plans() {
+        let executor = MockExecutor::new(vec![
+            MockOutput::text("Here is the plan:\n1. look up\n2. fail here\n3. never run"),
+            MockOutput::text("1. work around"),
+            MockOutput::text("done"),
+        ]);
+        let agent = PlanAndExecuteAgent::new(executor).with_runner(FlakyRunner(Mutex::default()));
+        let output = futures::executor::block_on(agent.run("objective")).unwrap();
+        assert_eq!(output.answer, "done");
+        assert_eq!(
+            output.trace.plans,
+            [
+                vec!["look up", "fail here", "never run"],
+                vec!["work around"]
+            ]
+        );
+        let steps: Vec<_> = output
+            .trace
+            .steps
+            .iter()
+            .map(|trace| (trace.step.as_str(), trace.plan, trace.result.is_ok()))
+            .collect();
+        assert_eq!(
+            steps,
+            [
+                ("look up", 0, true),
+                ("fail here", 0, false),
+                ("work around", 1, true)
+            ]
+        );
+        let prompts = agent.executor.prompts.lock().unwrap();
+        let revision = prompts[1].to_text();
+        assert!(revision.contains("1. look up\nResult: did look up"));
+        assert!(revision.contains("failed with the following error:\nthe step failed"));
+    }
+
+    #[test]
+    fn gives_up_after_max_revisions() {
+        let executor = MockExecutor::new(vec![MockOutput::text("1. fail")]);
+        let agent = PlanAndExecuteAgent::new(executor)
+            .with_runner(FlakyRunner(Mutex::default()))
+            .with_max_revisions(0);
+        let error = futures::executor::block_on(agent.run("objective")).unwrap_err();
+        let PlanAndExecuteError::StepFailed { step, trace, .. } = error else {
+            panic!("expected the step to fail");
+        };
+        assert_eq!(step, "fail");
+        assert_eq!(trace.steps.len(), 1);
+    }
+}
@@ -0,0 +1,336 @@
+//! An agent answering questions with the tools of a `ToolCollection`, following the ReAct pattern.
+//!
+//! The model reasons in `Thought:` lines and picks the tools to call with `Action:` and `Action Input:` lines. Each
+//! call is made by the agent, and its result is given back to the model as an `Observation:`, until the model writes
+//! a `Final Answer:`. Outputs that don't follow the format, and tool calls that fail, are reported to the model as
+//! observations so that it can correct itself, within the budget of steps given to the agent.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut tools = ToolCollection::new();
+//! tools.add_tool(BingSearch::new(api_key));
+//! let agent = ReActAgent::new(executor, tools).with_max_steps(5);
+//! let (finish, steps) = agent.run("Who directed the highest grossing film of 1997?").await?;
+//! println!("{}", finish.return_values.get("output").unwrap());
+//! ```
+use thiserror::Error;
+
+use super::self_ask_with_search::{
+    AgentAction, AgentDecision, AgentFinish, AgentIntermediateStep, AgentOutputParser,
+};
+use super::{observation_text, observe};
+use crate::{
+    output::Output,
+    parameters,
+    prompt::{PromptTemplate, StringTemplateError},
+    tools::{Tool, ToolCollection},
+    traits::{Executor, ExecutorError},
+};
+
+const PROMPT: &str =
+    "Answer the following question as best you can. You have access to the following tools:
+
+{{tools}}
+Use the following format:
+
+Question: the input question you must answer
+Thought: you should always think about what to do
+Action: the action to take, one of [{{tool_names}}]
+Action Input: the input of the action, in YAML
+Observation: the result of the action
+... (this Thought/Action/Action Input/Observation can repeat N times)
+Thought: I now know the final answer
+Final Answer: the final answer to the original question
+
+Begin!
+
+Question: {{question}}
+Thought:{{scratchpad}}";
+
+/// The tool name of the steps recording an output that couldn't be parsed.
+const INVALID_FORMAT_TOOL: &str = "_invalid_format";
+
+/// The error returned by `ReActAgent::run`.
+#[derive(Debug, Error)]
+pub enum ReActAgentError<E: ExecutorError> {
+    #[error(transparent)]
+    Executor(E),
+    #[error(transparent)]
+    StringTemplate(#[from] StringTemplateError),
+    #[error("The tools couldn't be described: {0}")]
+    ToolDescription(String),
+    #[error("Model response was empty or contained no choices")]
+    NoChoicesReturned,
+    /// The model didn't reach a final answer within the budget of steps.
+    #[error("No final answer after {steps} steps")]
+    MaxStepsExceeded { steps: usize },
+}
+
+/// The error returned when an output follows neither the action format nor the final answer format.
+#[derive(Debug, Error)]
+#[error("Invalid format: write `Action:` and `Action Input:` lines to use a tool, or a `Final Answer:` line to answer")]
+pub struct ReActParseError {
+    /// The output of the model.
+    pub text: String,
+}
+
+/// Parses the outputs of the model into actions and final answers.
+///
+/// Labels are matched ignoring case and markdown emphasis, as in `**Action:**`. Anything the model wrote after an
+/// `Observation:` label is dropped, since observations come from the tools. The action input is parsed as YAML,
+/// which includes JSON, and may be wrapped in a code block; input that isn't valid YAML is passed as a string. When
+/// an output contains both an action and a final answer, the one written first is used.
+#[derive(Debug, Clone, Default)]
+pub struct ReActOutputParser;
+
+/// The labels of the lines of the format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Label {
+    Thought,
+    Action,
+    ActionInput,
+    FinalAnswer,
+    Observation,
+}
+
+/// Returns the label starting `line`, if any, and the text after it.
+fn split_label(line: &str) -> Option<(Label, &str)> {
+    let line = line.trim_start_matches(|c: char| c.is_whitespace() || c == '*' || c == '#');
+    let (name, rest) = line.split_once(':')?;
+    let label = match name.trim_end_matches('*').trim().to_lowercase().as_str() {
+        "thought" => Label::Thought,
+        "action" => Label::Action,
+        "action input" => Label::ActionInput,
+        "final answer" => Label::FinalAnswer,
+        "observation" => Label::Observation,
+        _ => return None,
+    };
+    Some((label, rest.trim_start_matches('*')))
+}
+
+/// Parses an action input, dropping the code block around it.
+fn parse_action_input(input: &str) -> serde_yaml::Value {
+    let input = input.trim();
+    let input = match input.strip_prefix("```") {
+        Some(rest) => {
+            let rest = rest.trim_end().trim_end_matches("```");
+            rest.split_once('\n').map_or(rest, |(_, code)| code)
+        }
+        None => input,
+    };
+    let input = input.trim();
+    if input.is_empty() {
+        return serde_yaml::Value::Null;
+    }
+    serde_yaml::from_str(input).unwrap_or_else(|_| serde_yaml::Value::String(input.to_string()))
+}
+
+impl AgentOutputParser for ReActOutputParser {
+    type Error = ReActParseError;
+
+    fn parse(&self, text: String) -> Result<AgentDecision, Self::Error> {
+        let mut sections: Vec<(Label, String)> = Vec::new();
+        let mut log = Vec::new();
+        for line in text.lines() {
+            match split_label(line) {
+                Some((Label::Observation, _)) => break,
+                Some((label, rest)) => sections.push((label, rest.trim().to_string())),
+                None => {
+                    if let Some((_, section)) = sections.last_mut() {
+                        section.push('\n');
+                        section.push_str(line);
+                    }
+                }
+            }
+            log.push(line);
+        }
+        let log = log.join("\n").trim_end().to_string();
+        let position = |label| sections.iter().position(|(l, _)| *l == label);
+        let action = position(Label::Action);
+        let final_answer = position(Label::FinalAnswer);
+        match (action, final_answer) {
+            (Some(action), final_answer) if !matches!(final_answer, Some(answer) if answer < action) =>
+            {
+                let tool = sections[action]
+                    .1
+                    .trim()
+                    .trim_matches(|c| c == '`' || c == '"' || c == '\'' || c == '*')
+                    .to_string();
+                let tool_input = match sections.get(action + 1) {
+                    Some((Label::ActionInput, input)) => parse_action_input(input),
+                    _ => serde_yaml::Value::Null,
+                };
+                if tool.is_empty() {
+                    return Err(ReActParseError { text });
+                }
+                Ok(AgentDecision::Action(AgentAction {
+                    tool,
+                    tool_input,
+                    log,
+                }))
+            }
+            (_, Some(answer)) => Ok(AgentDecision::Finish(AgentFinish {
+                return_values: parameters!("output" => sections[answer].1.trim()),
+                log,
+            })),
+            _ => Err(ReActParseError { text }),
+        }
+    }
+}
+
+/// Converts the steps taken so far into the text following the first `Thought:` of the prompt.
+pub fn build_scratchpad(steps: &[AgentIntermediateStep]) -> String {
+    let mut scratchpad = String::new();
+    for step in steps {
+        scratchpad += &format!(
+            " {}\nObservation: {}\nThought:",
+            step.action.log.trim(),
+            observation_text(&step.observation)
+        );
+    }
+    scratchpad
+}
+
+/// An agent answering questions with tools, alternating thoughts, actions and observations. See the module
+/// documentation.
+pub struct ReActAgent<E, T> {
+    executor: E,
+    tools: ToolCollection<T>,
+    max_steps: usize,
+    output_parser: ReActOutputParser,
+}
+
+impl<E, T> ReActAgent<E, T>
+where
+    E: Executor,
+    T: Tool + Send + Sync,
+{
+    pub fn new(executor: E, tools: ToolCollection<T>) -> Self {
+        Self {
+            executor,
+            tools,
+            max_steps: 10,
+            output_parser: ReActOutputParser,
+        }
+    }
+
+    /// Sets the maximum number of calls to the model, including the one giving the final answer. Defaults to 10.
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Answers `question`, returning the final answer under `output` in the return values, along with the steps
+    /// taken to reach it.
+    pub async fn run(
+        &self,
+        question: &str,
+    ) -> Result<(AgentFinish, Vec<AgentIntermediateStep>), ReActAgentError<E::Error>> {
+        let mut steps = Vec::new();
+        for _ in 0..self.max_steps {
+            let text = self.plan(&steps, question).await?;
+            let step = match self.output_parser.parse(text) {
+                Ok(AgentDecision::Finish(finish)) => return Ok((finish, steps)),
+                Ok(AgentDecision::Action(action)) => {
+                    let observation = self.act(&action).await;
+                    AgentIntermediateStep {
+                        action,
+                        observation,
+                    }
+                }
+                Err(error) => AgentIntermediateStep {
+                    observation: error.to_string().into(),
+                    action: AgentAction {
+                        tool: INVALID_FORMAT_TOOL.to_string(),
+                        tool_input: serde_yaml::Value::Null,
+                        log: error.text,
+                    },
+                },
+            };
+            steps.push(step);
+        }
+        Err(ReActAgentError::MaxStepsExceeded {
+            steps: self.max_steps,
+        })
+    }
+
+    /// Calls the tool of `action`, returning its output, or the error as a string for the model to correct.
+    async fn act(&self, action: &AgentAction) -> serde_yaml::Value {
+        let result = self.tools.invoke(&action.tool, &action.tool_input).await;
+        observe(result, &action.tool, &self.tools.names())
+    }
+
+    /// Asks the model for its next thought and action, or for its final answer.
+    async fn plan(
+        &self,
+        steps: &[AgentIntermediateStep],
+        question: &str,
+    ) -> Result<String, ReActAgentError<E::Error>> {
+        let template_parameters = parameters!(
+            "tools" => self
+                .tools
+                .describe()
+                .map_err(|error| ReActAgentError::ToolDescription(error.to_string()))?,
+            "tool_names" => self.tools.names().join(", "),
+            "question" => question,
+            "scratchpad" => build_scratchpad(steps)
+        );
+        let prompt = PromptTemplate::Text(PROMPT.into()).format(&template_parameters)?;
+        let output = self
+            .executor
+            .execute(None, &prompt, None)
+            .await
+            .map_err(ReActAgentError::Executor)?;
+        output
+            .primary_textual_output()
+            .await
+            .ok_or(ReActAgentError::NoChoicesReturned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_actions_and_answers() {
+        let parser = ReActOutputParser;
+        let text = "I should search.\n**Action:** `search`\nAction Input:\n```json\n{\"query\": \"rust\"}\n```\nObservation: made up";
+        let AgentDecision::Action(action) = parser.parse(text.to_string()).unwrap() else {
+            panic!("expected an action");
+        };
+        assert_eq!(action.tool, "search");
+        assert_eq!(action.tool_input["query"], "rust");
+        assert!(!action.log.contains("made up"));
+
+        let text = "I know it now.\nfinal answer: 42\nand more";
+        let AgentDecision::Finish(finish) = parser.parse(text.to_string()).unwrap() else {
+            panic!("expected a final answer");
+        };
+        assert_eq!(finish.return_values.get("output").unwrap(), "42\nand more");
+
+        let text = "Final Answer: maybe\nAction: search";
+        assert!(matches!(
+            parser.parse(text.to_string()).unwrap(),
+            AgentDecision::Finish(_)
+        ));
+        assert!(parser.parse("I don't know".to_string()).is_err());
+    }
+
+    #[test]
+    fn builds_scratchpad() {
+        let step = AgentIntermediateStep {
+            action: AgentAction {
+                tool: "search".into(),
+                tool_input: "rust".into(),
+                log: "I should search.\nAction: search\nAction Input: rust".into(),
+            },
+            observation: "A language".into(),
+        };
+        assert_eq!(
+            build_scratchpad(&[step]),
+            " I should search.\nAction: search\nAction Input: rust\nObservation: A language\nThought:"
+        );
+    }
+}
@@ -0,0 +1,169 @@
+//! An agent answering with the tools of a `ToolCollection` through the native tool calling of the model.
+//!
+//! Instead of prompting the model to describe tool calls in text, as the `react` agent does, the tools are
+//! advertised to the model with JSON Schemas through `Executor::tool_options`, and the model answers with structured
+//! tool calls. The agent makes the calls, appends their results to the conversation as messages of the `Tool` role,
+//! and asks the model again, until it answers without calling tools. Calls that fail are reported to the model in
+//! their results, so that it can correct them.
+//!
+//! The executor must support native tool calling, as the OpenAI executor of `llm-chain-openai` does: `run` fails with `ToolCallingAgentError::Unsupported` otherwise.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut tools = ToolCollection::new();
+//! tools.add_tool(BingSearch::new(api_key));
+//! let agent = ToolCallingAgent::new(executor, tools).with_system_prompt("Answer in one sentence.");
+//! let (output, messages) = agent.run("Who directed the highest grossing film of 1997?").await?;
+//! println!("{}", output.primary_textual_output().await.unwrap());
+//! ```
+use thiserror::Error;
+
+use super::{observation_text, observe};
+use crate::{
+    output::Output,
+    prompt::{ChatMessage, ChatMessageCollection, Prompt},
+    tools::{Tool, ToolCollection},
+    traits::{Executor, ExecutorError},
+};
+
+/// The error returned by `ToolCallingAgent::run`.
+#[derive(Debug, Error)]
+pub enum ToolCallingAgentError<E: ExecutorError> {
+    #[error(transparent)]
+    Executor(E),
+    #[error("The executor doesn't support native tool calling")]
+    Unsupported,
+    #[error("Model response was empty or contained no choices")]
+    NoChoicesReturned,
+    /// The model was still calling tools when the budget of steps ran out.
+    #[error("No final answer after {steps} steps")]
+    MaxStepsExceeded { steps: usize },
+}
+
+/// An agent calling tools through the native tool calling of the model. See the module documentation.
+pub struct ToolCallingAgent<E, T> {
+    executor: E,
+    tools: ToolCollection<T>,
+    max_steps: usize,
+    system_prompt: Option<String>,
+}
+
+impl<E, T> ToolCallingAgent<E, T>
+where
+    E: Executor,
+    T: Tool + Send + Sync,
+{
+    pub fn new(executor: E, tools: ToolCollection<T>) -> Self {
+        Self {
+            executor,
+            tools,
+            max_steps: 10,
+            system_prompt: None,
+        }
+    }
+
+    /// Sets the maximum number of calls to the model, including the one giving the final answer. Defaults to 10.
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Sets the system message starting the conversations of `run`.
+    pub fn with_system_prompt<S: Into<String>>(mut self, system_prompt: S) -> Self {
+        self.system_prompt = Some(system_prompt.into());
+        self
+    }
+
+    /// Answers `question`, returning the final output of the model along with the whole conversation, including
+    /// the tool calls and their results.
+    pub async fn run(
+        &self,
+        question: &str,
+    ) -> Result<(E::Output, ChatMessageCollection<String>), ToolCallingAgentError<E::Error>> {
+        let mut messages = ChatMessageCollection::new();
+        if let Some(system_prompt) = &self.system_prompt {
+            messages = messages.with_system(system_prompt.clone());
+        }
+        self.run_messages(messages.with_user(question.to_string()))
+            .await
+    }
+
+    /// Continues the conversation `messages`, calling tools until the model answers without calling any.
+    pub async fn run_messages(
+        &self,
+        mut messages: ChatMessageCollection<String>,
+    ) -> Result<(E::Output, ChatMessageCollection<String>), ToolCallingAgentError<E::Error>> {
+        let options = self
+            .executor
+            .tool_options(None, &self.tools.definitions())
+            .ok_or(ToolCallingAgentError::Unsupported)?;
+        let tool_names = self.tools.names();
+        for _ in 0..self.max_steps {
+            let output = self
+                .executor
+                .execute(Some(&options), &Prompt::Chat(messages.clone()), None)
+                .await
+                .map_err(ToolCallingAgentError::Executor)?;
+            let text = output.primary_textual_output().await;
+            let calls = output.tool_calls().await;
+            if calls.is_empty() {
+                let text = text.ok_or(ToolCallingAgentError::NoChoicesReturned)?;
+                messages.add_message(ChatMessage::assistant(text));
+                return Ok((output, messages));
+            }
+            messages.add_message(
+                ChatMessage::assistant(text.unwrap_or_default()).with_tool_calls(calls.clone()),
+            );
+            for call in calls {
+                let result = self.tools.invoke_call(&call).await;
+                let observation = observe(result, &call.name, &tool_names);
+                messages.add_message(ChatMessage::tool_result(
+                    call.id,
+                    observation_text(&observation),
+                ));
+            }
+        }
+        Err(ToolCallingAgentError::MaxStepsExceeded {
+            steps: self.max_steps,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::mock::{AddTool, MockExecutor, MockOutput};
+    use crate::output::ToolCall;
+    use crate::prompt::ChatRole;
+
+    #[test]
+    fn calls_tools_until_final_answer() {
+        let executor = MockExecutor::new(vec![
+            MockOutput(
+                None,
+                vec![
+                    ToolCall::new("add", r#"{"a": 2, "b": 3}"#).with_id("1"),
+                    ToolCall::new("mul", "{}").with_id("2"),
+                ],
+            ),
+            MockOutput::text("5"),
+        ]);
+        let mut tools = ToolCollection::new();
+        tools.add_tool(AddTool);
+        let agent = ToolCallingAgent::new(executor, tools);
+        let (output, messages) = futures::executor::block_on(agent.run("What is 2 + 3?")).unwrap();
+        assert_eq!(output.0.as_deref(), Some("5"));
+        assert_eq!(*agent.executor.tools.lock().unwrap(), ["add"]);
+        let results: Vec<_> = messages
+            .iter()
+            .filter(|message| message.role() == &ChatRole::Tool)
+            .map(|message| (message.tool_call_id().unwrap(), message.body().as_str()))
+            .collect();
+        assert_eq!(results[0], ("1", "5"));
+        assert!(results[1]
+            .1
+            .starts_with("Error: there is no tool named `mul`"));
+        assert_eq!(agent.executor.prompts.lock().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,589 @@
+//! Token and cost budgets.
+//!
+//! A `Budget` limits how many tokens, and how much money, can be spent over time. Each limit is a token bucket: it
+//! holds up to `capacity` units and refills at a steady rate, so short bursts are allowed while the long-term rate is
+//! capped. Invocations that don't fit in the bucket are rejected with `BudgetError::Exceeded`, which says when to
+//! retry.
+//!
+//! The state of the buckets lives in a `BudgetStore`. `LocalBudgetStore` keeps it in memory, which limits a single
+//! process. Services running several replicas should use a shared store, such as the Redis store of the
+//! `llm-chain-redis` crate, so that all replicas draw from one organization-wide budget instead of each enforcing
+//! its own.
+//!
+//! `BudgetedExecutor` charges every invocation of an executor to a budget, and corrects the charge once the output
+//! reports the tokens actually used.
+//!
+//! # Example
+//!
+//! ```
+//! use llm_chain::budget::{Budget, BucketLimit, LocalBudgetStore};
+//!
+//! let budget = Budget::new(LocalBudgetStore::new(), "my-org")
+//!     .with_token_limit(BucketLimit::per_minute(90_000.0))
+//!     // At $0.002 per 1000 tokens, spend at most $50 a day.
+//!     .with_cost_limit(BucketLimit::per_day(50.0), 0.002);
+//! futures::executor::block_on(budget.consume(1_000)).unwrap();
+//! ```
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::cancellation::CancellationReason;
+use crate::json_schema::JsonSchema;
+use crate::output::{Output, OutputStream};
+use crate::prompt::Prompt;
+use crate::tokens::{PromptTokensError, TokenCount, TokenizerError};
+use crate::tools::ToolDefinition;
+use crate::traits::{self, ExecutorCreationError, ExecutorError};
+
+/// The error type returned by budget stores.
+pub type BoxedBudgetStoreError = Box<dyn Error + Send + Sync>;
+
+/// The size and refill rate of a token bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BucketLimit {
+    /// The most the bucket can hold, which is the largest burst allowed.
+    pub capacity: f64,
+    /// How much is added back to the bucket every second.
+    pub refill_per_second: f64,
+}
+
+impl BucketLimit {
+    pub fn new(capacity: f64, refill_per_second: f64) -> Self {
+        Self {
+            capacity,
+            refill_per_second,
+        }
+    }
+
+    /// A limit of `amount` per minute, all of which may be spent at once.
+    pub fn per_minute(amount: f64) -> Self {
+        Self::new(amount, amount / 60.0)
+    }
+
+    /// A limit of `amount` per hour, all of which may be spent at once.
+    pub fn per_hour(amount: f64) -> Self {
+        Self::new(amount, amount / 3600.0)
+    }
+
+    /// A limit of `amount` per day, all of which may be spent at once.
+    pub fn per_day(amount: f64) -> Self {
+        Self::new(amount, amount / 86400.0)
+    }
+
+    /// Returns how long it takes for a bucket holding `level` to hold `amount`.
+    pub fn time_until(&self, level: f64, amount: f64) -> Duration {
+        if amount > self.capacity || self.refill_per_second <= 0.0 {
+            return Duration::MAX;
+        }
+        Duration::from_secs_f64(((amount - level) / self.refill_per_second).max(0.0))
+    }
+}
+
+/// An amount to take from a bucket.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Charge<'a> {
+    /// The key of the bucket.
+    pub key: &'a str,
+    pub amount: f64,
+    pub limit: BucketLimit,
+}
+
+/// The answer of a budget store to a request to consume from buckets.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BudgetDecision {
+    /// The amounts were taken from the buckets, which hold `remaining` afterwards, in the order of the charges.
+    Granted { remaining: Vec<f64> },
+    /// The bucket of the charge at index `bucket`, the first of those that don't hold enough, is short. Nothing was
+    /// taken from any bucket. `retry_after` is the longest wait among the buckets that are short; `Duration::MAX`
+    /// means an amount is larger than its bucket.
+    Denied {
+        bucket: usize,
+        retry_after: Duration,
+    },
+}
+
+/// Where the state of budget buckets is kept.
+///
+/// Buckets are refilled for the time passed since they were last used before each operation. Buckets that haven't
+/// been used yet are full. Stores shared between processes must check and update the buckets of an operation
+/// atomically.
+#[async_trait]
+pub trait BudgetStore: Send + Sync {
+    /// Takes the amount of every charge from its bucket if all the buckets hold enough, and nothing otherwise.
+    async fn try_consume(
+        &self,
+        charges: &[Charge<'_>],
+    ) -> Result<BudgetDecision, BoxedBudgetStoreError>;
+
+    /// Takes the amount of every charge from its bucket unconditionally, to correct earlier charges. Positive amounts
+    /// can leave a bucket below zero, delaying later requests until it refills. Negative amounts give back to the
+    /// bucket, up to its capacity.
+    async fn adjust(&self, charges: &[Charge<'_>]) -> Result<(), BoxedBudgetStoreError>;
+}
+
+struct Bucket {
+    level: f64,
+    updated: Instant,
+}
+
+/// Keeps budget buckets in memory, limiting a single process.
+#[derive(Default)]
+pub struct LocalBudgetStore {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl LocalBudgetStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Returns the bucket `key`, refilled until `now`.
+fn refill<'a>(
+    buckets: &'a mut HashMap<String, Bucket>,
+    key: &str,
+    limit: BucketLimit,
+    now: Instant,
+) -> &'a mut Bucket {
+    let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+        level: limit.capacity,
+        updated: now,
+    });
+    let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+    bucket.level = (bucket.level + elapsed * limit.refill_per_second).min(limit.capacity);
+    bucket.updated = now;
+    bucket
+}
+
+#[async_trait]
+impl BudgetStore for LocalBudgetStore {
+    async fn try_consume(
+        &self,
+        charges: &[Charge<'_>],
+    ) -> Result<BudgetDecision, BoxedBudgetStoreError> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().expect("budget lock poisoned");
+        let levels: Vec<f64> = charges
+            .iter()
+            .map(|charge| refill(&mut buckets, charge.key, charge.limit, now).level)
+            .collect();
+        let short: Vec<usize> = (0..charges.len())
+            .filter(|&i| charges[i].amount > levels[i])
+            .collect();
+        if let Some(&bucket) = short.first() {
+            let retry_after = short
+                .iter()
+                .map(|&i| charges[i].limit.time_until(levels[i], charges[i].amount))
+                .max()
+                .unwrap_or_default();
+            return Ok(BudgetDecision::Denied {
+                bucket,
+                retry_after,
+            });
+        }
+        let remaining = charges
+            .iter()
+            .map(|charge| {
+                let bucket = buckets.get_mut(charge.key).expect("bucket refilled above");
+                bucket.level -= charge.amount;
+                bucket.level
+            })
+            .collect();
+        Ok(BudgetDecision::Granted { remaining })
+    }
+
+    async fn adjust(&self, charges: &[Charge<'_>]) -> Result<(), BoxedBudgetStoreError> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().expect("budget lock poisoned");
+        for charge in charges {
+            let bucket = refill(&mut buckets, charge.key, charge.limit, now);
+            bucket.level = (bucket.level - charge.amount).min(charge.limit.capacity);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum BudgetError {
+    #[error("The {budget} budget is exhausted, retry after {retry_after:?}")]
+    Exceeded {
+        budget: String,
+        retry_after: Duration,
+    },
+    #[error("Error accessing the budget store: {0}")]
+    Store(BoxedBudgetStoreError),
+}
+
+impl BudgetError {
+    /// Returns the reason to report when a run is cancelled because of this error.
+    pub fn cancellation_reason(&self) -> CancellationReason {
+        CancellationReason::Budget(self.to_string())
+    }
+}
+
+#[derive(Clone, Copy)]
+struct CostLimit {
+    limit: BucketLimit,
+    cost_per_1k_tokens: f64,
+}
+
+/// Limits on the tokens and the cost spent, kept in a `BudgetStore`.
+///
+/// Budgets are cheap to clone; clones share the same store. Budgets with the same namespace and store share their
+/// buckets, so every replica of a service should use the same namespace.
+#[derive(Clone)]
+pub struct Budget {
+    store: Arc<dyn BudgetStore>,
+    namespace: String,
+    tokens: Option<BucketLimit>,
+    cost: Option<CostLimit>,
+}
+
+impl Budget {
+    /// Creates a budget without limits, keeping its buckets in `store` under `namespace`.
+    pub fn new<S: BudgetStore + 'static, N: Into<String>>(store: S, namespace: N) -> Self {
+        Self {
+            store: Arc::new(store),
+            namespace: namespace.into(),
+            tokens: None,
+            cost: None,
+        }
+    }
+
+    /// Limits the number of tokens spent.
+    pub fn with_token_limit(mut self, limit: BucketLimit) -> Self {
+        self.tokens = Some(limit);
+        self
+    }
+
+    /// Limits the cost spent, with tokens costing `cost_per_1k_tokens` per thousand. The limit is in the same currency
+    /// as the price.
+    pub fn with_cost_limit(mut self, limit: BucketLimit, cost_per_1k_tokens: f64) -> Self {
+        self.cost = Some(CostLimit {
+            limit,
+            cost_per_1k_tokens,
+        });
+        self
+    }
+
+    /// Returns the names and keys of the buckets of the budget, with the amounts `tokens` cost in each.
+    fn charges(&self, tokens: f64) -> Vec<(&'static str, String, f64, BucketLimit)> {
+        let mut charges = Vec::new();
+        if let Some(limit) = self.tokens {
+            charges.push(("tokens", tokens, limit));
+        }
+        if let Some(cost) = self.cost {
+            charges.push((
+                "cost",
+                tokens / 1000.0 * cost.cost_per_1k_tokens,
+                cost.limit,
+            ));
+        }
+        charges
+            .into_iter()
+            .map(|(name, amount, limit)| {
+                (name, format!("{}:{}", self.namespace, name), amount, limit)
+            })
+            .collect()
+    }
+
+    /// Charges `tokens` to the budget, or fails with `BudgetError::Exceeded` if a limit doesn't allow it. Either every
+    /// limit is charged, or none is.
+    pub async fn consume(&self, tokens: usize) -> Result<(), BudgetError> {
+        let charges = self.charges(tokens as f64);
+        if charges.is_empty() {
+            return Ok(());
+        }
+        let decision = self
+            .store
+            .try_consume(&as_charges(&charges))
+            .await
+            .map_err(BudgetError::Store)?;
+        match decision {
+            BudgetDecision::Granted { .. } => Ok(()),
+            BudgetDecision::Denied {
+                bucket,
+                retry_after,
+            } => Err(BudgetError::Exceeded {
+                budget: charges[bucket].0.to_string(),
+                retry_after,
+            }),
+        }
+    }
+
+    /// Corrects an earlier charge by `tokens`: positive when more tokens were used than charged, negative when fewer
+    /// were. Corrections are never rejected, so the budget may be overdrawn for a while.
+    pub async fn adjust(&self, tokens: i64) -> Result<(), BudgetError> {
+        let charges = self.charges(tokens as f64);
+        if charges.is_empty() || tokens == 0 {
+            return Ok(());
+        }
+        self.store
+            .adjust(&as_charges(&charges))
+            .await
+            .map_err(BudgetError::Store)
+    }
+}
+
+fn as_charges<'a>(charges: &'a [(&'static str, String, f64, BucketLimit)]) -> Vec<Charge<'a>> {
+    charges
+        .iter()
+        .map(|(_, key, amount, limit)| Charge {
+            key,
+            amount: *amount,
+            limit: *limit,
+        })
+        .collect()
+}
+
+#[derive(Debug, Error)]
+pub enum BudgetedExecutorError<E: Error> {
+    #[error(transparent)]
+    Executor(E),
+    #[error(transparent)]
+    Budget(#[from] BudgetError),
+    #[error("Unable to count the tokens of the prompt: {0}")]
+    PromptTokens(#[from] PromptTokensError),
+}
+
+impl<E: Error> ExecutorError for BudgetedExecutorError<E> {}
+
+/// An executor that charges every invocation to a `Budget` before running it.
+///
+/// The tokens of the prompt are counted with the executor's tokenizer. The length of the completion isn't known in
+/// advance; `with_completion_estimate` adds a fixed number of tokens for it to every charge. When the output of
+/// `execute` reports its usage, the charge is then corrected to the tokens actually used. Streams don't report it, so
+/// their charge stays an estimate.
+pub struct BudgetedExecutor<E> {
+    executor: E,
+    budget: Budget,
+    completion_estimate: usize,
+}
+
+impl<E> BudgetedExecutor<E> {
+    pub fn new(executor: E, budget: Budget) -> Self {
+        Self {
+            executor,
+            budget,
+            completion_estimate: 0,
+        }
+    }
+
+    /// Sets the number of tokens charged for the completion of every invocation. Defaults to 0.
+    pub fn with_completion_estimate(mut self, tokens: usize) -> Self {
+        self.completion_estimate = tokens;
+        self
+    }
+
+    pub fn budget(&self) -> &Budget {
+        &self.budget
+    }
+}
+
+#[async_trait]
+impl<E> traits::Executor for BudgetedExecutor<E>
+where
+    E: traits::Executor + Send + Sync,
+    E::Error: Send,
+{
+    type PerInvocationOptions = E::PerInvocationOptions;
+    type PerExecutorOptions = E::PerExecutorOptions;
+    type Output = E::Output;
+    type Error = BudgetedExecutorError<E::Error>;
+    type Token = E::Token;
+    type StepTokenizer<'a>
+        = E::StepTokenizer<'a>
+    where
+        Self: 'a;
+    type TextSplitter<'a>
+        = E::TextSplitter<'a>
+    where
+        Self: 'a;
+
+    /// A budget can't be created from executor options, so this always fails. Use `BudgetedExecutor::new`.
+    fn new_with_options(
+        _executor_options: Option<Self::PerExecutorOptions>,
+        _invocation_options: Option<Self::PerInvocationOptions>,
+    ) -> Result<Self, ExecutorCreationError> {
+        Err(ExecutorCreationError::FieldRequiredError(
+            "budget".to_string(),
+        ))
+    }
+
+    async fn execute(
+        &self,
+        options: Option<&Self::PerInvocationOptions>,
+        prompt: &Prompt,
+        is_streaming: Option<bool>,
+    ) -> Result<Self::Output, Self::Error> {
+        let prompt_tokens = self.executor.tokens_used(options, prompt)?.tokens_used();
+        let charged = prompt_tokens.max(0) as usize + self.completion_estimate;
+        self.budget.consume(charged).await?;
+        let output = self
+            .executor
+            .execute(options, prompt, is_streaming)
+            .await
+            .map_err(BudgetedExecutorError::Executor)?;
+        if let Some(usage) = output.usage().await {
+            let used = usage.prompt_tokens as i64 + usage.completion_tokens as i64;
+            self.budget.adjust(used - charged as i64).await?;
+        }
+        Ok(output)
+    }
+
+    /// Charges the budget before the stream starts, as for `execute`.
+    fn execute_stream<'a>(
+        &'a self,
+        options: Option<&Self::PerInvocationOptions>,
+        prompt: &Prompt,
+    ) -> OutputStream<'a, Self::Error>
+    where
+        Self: Sync,
+    {
+        let options = options.cloned();
+        let prompt = prompt.clone();
+        stream::once(async move {
+            let prompt_tokens = self
+                .executor
+                .tokens_used(options.as_ref(), &prompt)?
+                .tokens_used();
+            self.budget
+                .consume(prompt_tokens.max(0) as usize + self.completion_estimate)
+                .await?;
+            Ok::<_, Self::Error>(
+                self.executor
+                    .execute_stream(options.as_ref(), &prompt)
+                    .map_err(BudgetedExecutorError::Executor),
+            )
+        })
+        .try_flatten()
+        .boxed()
+    }
+
+    fn tokens_used(
+        &self,
+        options: Option<&Self::PerInvocationOptions>,
+        prompt: &Prompt,
+    ) -> Result<TokenCount, PromptTokensError> {
+        self.executor.tokens_used(options, prompt)
+    }
+
+    fn max_tokens_allowed(&self, options: Option<&Self::PerInvocationOptions>) -> i32 {
+        self.executor.max_tokens_allowed(options)
+    }
+
+    fn answer_prefix(&self, prompt: &Prompt) -> Option<String> {
+        self.executor.answer_prefix(prompt)
+    }
+
+    fn supports_images(&self) -> bool {
+        self.executor.supports_images()
+    }
+
+    fn json_schema_options(
+        &self,
+        options: Option<&Self::PerInvocationOptions>,
+        schema: &JsonSchema,
+    ) -> Option<Self::PerInvocationOptions> {
+        self.executor.json_schema_options(options, schema)
+    }
+
+    fn tool_options(
+        &self,
+        options: Option<&Self::PerInvocationOptions>,
+        tools: &[ToolDefinition],
+    ) -> Option<Self::PerInvocationOptions> {
+        self.executor.tool_options(options, tools)
+    }
+
+    fn get_tokenizer(
+        &self,
+        options: Option<&Self::PerInvocationOptions>,
+    ) -> Result<Self::StepTokenizer<'_>, TokenizerError> {
+        self.executor.get_tokenizer(options)
+    }
+
+    fn get_text_splitter(
+        &self,
+        options: Option<&Self::PerInvocationOptions>,
+    ) -> Result<Self::TextSplitter<'_>, Self::Error> {
+        self.executor
+            .get_text_splitter(options)
+            .map_err(BudgetedExecutorError::Executor)
+    }
+
+    async fn shutdown(&self) -> Result<(), Self::Error> {
+        self.executor
+            .shutdown()
+            .await
+            .map_err(BudgetedExecutorError::Executor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BucketLimit, Budget, BudgetError, LocalBudgetStore};
+    use futures::executor::block_on;
+
+    #[test]
+    fn rejects_what_does_not_fit() {
+        let budget = Budget::new(LocalBudgetStore::new(), "test")
+            .with_token_limit(BucketLimit::per_hour(1000.0))
+            .with_cost_limit(BucketLimit::per_hour(1.0), 1.0);
+        block_on(budget.consume(600)).unwrap();
+        match block_on(budget.consume(600)) {
+            Err(BudgetError::Exceeded {
+                budget,
+                retry_after,
+            }) => {
+                assert_eq!(budget, "tokens");
+                assert!(retry_after.as_secs() > 600);
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        // The clone shares the buckets.
+        let clone = budget.clone();
+        assert!(block_on(clone.consume(400)).is_ok());
+        assert!(block_on(clone.consume(1)).is_err());
+    }
+
+    #[test]
+    fn charges_every_limit_or_none() {
+        let budget = Budget::new(LocalBudgetStore::new(), "test")
+            .with_token_limit(BucketLimit::per_hour(1000.0))
+            .with_cost_limit(BucketLimit::per_hour(1.0), 2.0);
+        // The tokens fit, but not their cost.
+        match block_on(budget.consume(600)) {
+            Err(BudgetError::Exceeded { budget, .. }) => assert_eq!(budget, "cost"),
+            other => panic!("unexpected result {:?}", other),
+        }
+        // So no token was taken.
+        let budget = budget.with_cost_limit(BucketLimit::per_hour(1.0), 0.0);
+        assert!(block_on(budget.consume(1000)).is_ok());
+    }
+
+    #[test]
+    fn adjusts_charges_to_the_tokens_used() {
+        let budget = Budget::new(LocalBudgetStore::new(), "test")
+            .with_token_limit(BucketLimit::per_hour(1000.0));
+        block_on(budget.consume(500)).unwrap();
+        // Fewer tokens were used than charged.
+        block_on(budget.adjust(-300)).unwrap();
+        block_on(budget.consume(800)).unwrap();
+        // More tokens were used than charged, overdrawing the budget.
+        block_on(budget.adjust(100)).unwrap();
+        match block_on(budget.consume(1)) {
+            Err(BudgetError::Exceeded { retry_after, .. }) => {
+                assert!(retry_after.as_secs() > 360)
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,210 @@
+//! Cancelling chain runs and reporting why they stopped.
+//!
+//! A `CancellationToken` is handed to a chain run and can be cancelled from anywhere else: a timer, a cost budget, a
+//! guardrail that flagged an output, or a user pressing stop. The run stops at the next opportunity and, instead of a
+//! generic error, returns a `Cancelled` value carrying the typed `CancellationReason` and the outputs of the steps
+//! that completed, so that callers can render partial progress.
+//!
+//! Sequential chains take a token in `run_with_cancellation`, map-reduce chains in `run_with_cancellation`, and
+//! conversations in `send_message_with_cancellation`.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let token = CancellationToken::new();
+//! let stop_button = token.clone();
+//! // Elsewhere: stop_button.cancel(CancellationReason::UserAbort);
+//! match chain.run_with_cancellation(parameters, &executor, &token).await {
+//!     Ok(output) => println!("{}", output),
+//!     Err(SequentialChainError::Cancelled(cancelled)) => {
+//!         println!("Stopped ({}) after {} steps", cancelled.reason, cancelled.partial_outputs.len());
+//!     }
+//!     Err(e) => return Err(e.into()),
+//! }
+//! ```
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use futures::channel::oneshot;
+use futures::future::{self, Either, Future, FutureExt, Shared};
+use serde::{Deserialize, Serialize};
+
+/// Why a run was cancelled.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", content = "detail", rename_all = "snake_case")]
+pub enum CancellationReason {
+    /// The run took longer than it was allowed to.
+    Timeout,
+    /// A budget, such as tokens or cost, was exhausted.
+    Budget(String),
+    /// A guardrail rejected an input or output.
+    Guardrail(String),
+    /// The user stopped the run.
+    UserAbort,
+    /// Any other reason.
+    Other(String),
+}
+
+impl fmt::Display for CancellationReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Timeout => write!(f, "timed out"),
+            Self::Budget(detail) => write!(f, "budget exhausted: {}", detail),
+            Self::Guardrail(detail) => write!(f, "stopped by a guardrail: {}", detail),
+            Self::UserAbort => write!(f, "aborted by the user"),
+            Self::Other(detail) => write!(f, "{}", detail),
+        }
+    }
+}
+
+/// The result of a cancelled run: why it was cancelled and what it completed before.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cancelled {
+    pub reason: CancellationReason,
+    /// The text outputs of the steps that completed before the run was cancelled, in order.
+    pub partial_outputs: Vec<String>,
+}
+
+struct Inner {
+    reason: Mutex<Option<CancellationReason>>,
+    sender: Mutex<Option<oneshot::Sender<()>>>,
+    receiver: Shared<oneshot::Receiver<()>>,
+}
+
+/// A handle for cancelling a run.
+///
+/// Clones share the same state: cancelling any clone cancels them all. Only the first cancellation counts; its reason
+/// is the one reported.
+#[derive(Clone)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("reason", &self.reason())
+            .finish()
+    }
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        let (sender, receiver) = oneshot::channel();
+        Self {
+            inner: Arc::new(Inner {
+                reason: Mutex::new(None),
+                sender: Mutex::new(Some(sender)),
+                receiver: receiver.shared(),
+            }),
+        }
+    }
+
+    /// Cancels the runs using this token. Returns `false` if the token was already cancelled.
+    pub fn cancel(&self, reason: CancellationReason) -> bool {
+        let mut current = self
+            .inner
+            .reason
+            .lock()
+            .expect("cancellation lock poisoned");
+        if current.is_some() {
+            return false;
+        }
+        *current = Some(reason);
+        if let Some(sender) = self
+            .inner
+            .sender
+            .lock()
+            .expect("cancellation lock poisoned")
+            .take()
+        {
+            let _ = sender.send(());
+        }
+        true
+    }
+
+    /// Returns why the token was cancelled, or `None` if it wasn't.
+    pub fn reason(&self) -> Option<CancellationReason> {
+        self.inner
+            .reason
+            .lock()
+            .expect("cancellation lock poisoned")
+            .clone()
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.reason().is_some()
+    }
+
+    /// Completes with the reason once the token is cancelled.
+    pub async fn cancelled(&self) -> CancellationReason {
+        // The sender is only dropped after sending, since `self` keeps it alive.
+        let _ = self.inner.receiver.clone().await;
+        self.reason()
+            .expect("the token is cancelled once the receiver completes")
+    }
+
+    /// Runs `future` until it completes or the token is cancelled, whichever happens first.
+    pub async fn run_until_cancelled<F: Future>(
+        &self,
+        future: F,
+    ) -> Result<F::Output, CancellationReason> {
+        if let Some(reason) = self.reason() {
+            return Err(reason);
+        }
+        match future::select(Box::pin(future), Box::pin(self.cancelled())).await {
+            Either::Left((output, _)) => Ok(output),
+            Either::Right((reason, _)) => Err(reason),
+        }
+    }
+
+    /// Cancels the token with `CancellationReason::Timeout` once `duration` has passed. The returned future has to be
+    /// awaited or spawned alongside the run.
+    #[cfg(feature = "async")]
+    pub async fn cancel_after(&self, duration: std::time::Duration) {
+        if self
+            .run_until_cancelled(tokio::time::sleep(duration))
+            .await
+            .is_ok()
+        {
+            self.cancel(CancellationReason::Timeout);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CancellationReason, CancellationToken};
+    use futures::executor::block_on;
+    use futures::future::pending;
+
+    #[test]
+    fn first_cancellation_wins() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        let (run, _) = block_on(futures::future::join(
+            token.run_until_cancelled(pending::<()>()),
+            async { clone.cancel(CancellationReason::Timeout) },
+        ));
+        assert_eq!(run, Err(CancellationReason::Timeout));
+
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(clone.cancel(CancellationReason::Budget("100 tokens".to_string())));
+        assert!(!token.cancel(CancellationReason::UserAbort));
+        assert_eq!(
+            block_on(token.run_until_cancelled(pending::<()>())),
+            Err(CancellationReason::Budget("100 tokens".to_string()))
+        );
+        assert_eq!(
+            token.reason().unwrap().to_string(),
+            "budget exhausted: 100 tokens"
+        );
+    }
+}
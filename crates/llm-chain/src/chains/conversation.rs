@@ -2,21 +2,281 @@
 //! It manages the conversation state and provides methods for sending messages and receiving responses.
 //!
 //! It relies on the `traits::Executor` trait to execute prompts and handle LLM interactions.
+//!
+//! What the chain remembers of the conversation is decided by its memory, an implementation of
+//! [`ConversationMemory`]. By default the chain keeps the whole conversation in a [`BufferMemory`];
+//! [`WindowMemory`] keeps only the latest messages, and [`SummaryMemory`] folds older messages into a running
+//! summary written by the model.
+//!
+//! The history is sent as messages before the prompt of every step, unless the template of the step uses the
+//! `history` parameter: the history is then formatted into that parameter, one line per message, so the template
+//! decides where it goes and how it is introduced. How each message is formatted is set with
+//! `Chain::with_turn_formatter`.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let memory = SummaryMemory::new(6).with_messages(
+//!     ChatMessageCollection::new().with_system("You are a helpful assistant.".to_string()),
+//! );
+//! let mut chain = Chain::with_memory(memory);
+//! let output = chain
+//!     .send_message(Step::for_prompt_template(prompt!(user: "Hi!")), &parameters!(), &exec)
+//!     .await?;
+//! ```
+//!
+//! `Chain::send_message_stream` streams the response instead, recording it in the memory once the stream ends.
+//! `Chain::send_message_with_cancellation` stops waiting for the response when a `CancellationToken` is cancelled.
 
-use crate::output::Output;
-use crate::prompt::{ChatMessage, ChatMessageCollection, Prompt, PromptTemplate};
+use crate::cancellation::{CancellationToken, Cancelled};
+use crate::output::{Output, OutputStream};
+use crate::prompt::{ChatMessage, ChatMessageCollection, ChatRole, Prompt, PromptTemplate};
 use crate::step::Step;
 use crate::tokens::{PromptTokensError, TokenizerError};
 use crate::traits::{self, ExecutorError};
 use crate::{parameters, Parameters};
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt};
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// The parameter a `Chain` fills with the formatted history of the conversation, when the template of a step uses it.
+pub const HISTORY_KEY: &str = "history";
+
+/// Formats a message of the history for the `history` parameter.
+pub type TurnFormatter = Arc<dyn Fn(&ChatMessage<String>) -> String + Send + Sync>;
+
+/// Formats messages as `Role: body`.
+fn default_turn_formatter() -> TurnFormatter {
+    Arc::new(|message| message.to_string())
+}
+
+/// The memory of a conversational `Chain`, deciding which messages are sent to the model along with a new prompt.
+#[async_trait]
+pub trait ConversationMemory<E: traits::Executor>: Send + Sync {
+    /// Returns the messages to send before the next prompt.
+    ///
+    /// The chain may drop messages from the front of the returned collection to fit the context window of the
+    /// model.
+    fn history(&self) -> ChatMessageCollection<String>;
+
+    /// Records an exchange: the messages of the prompt, followed by the response of the model.
+    async fn record(
+        &mut self,
+        exchange: ChatMessageCollection<String>,
+        executor: &E,
+    ) -> Result<(), Error<E::Error>>;
+}
+
+/// A memory keeping the whole conversation. This is the default memory of a `Chain`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct BufferMemory {
+    messages: ChatMessageCollection<String>,
+}
+
+impl BufferMemory {
+    /// Creates a memory starting out with `messages`.
+    pub fn new(messages: ChatMessageCollection<String>) -> Self {
+        Self { messages }
+    }
+}
+
+#[async_trait]
+impl<E: traits::Executor + Sync> ConversationMemory<E> for BufferMemory {
+    fn history(&self) -> ChatMessageCollection<String> {
+        self.messages.clone()
+    }
+
+    async fn record(
+        &mut self,
+        exchange: ChatMessageCollection<String>,
+        _executor: &E,
+    ) -> Result<(), Error<E::Error>> {
+        self.messages.append(exchange);
+        Ok(())
+    }
+}
+
+/// Appends `exchange` to `messages`, then removes the oldest messages other than system messages until at most
+/// `window` of them are left. Returns the removed messages, oldest first.
+fn append_windowed(
+    messages: &mut ChatMessageCollection<String>,
+    exchange: ChatMessageCollection<String>,
+    window: usize,
+) -> Vec<ChatMessage<String>> {
+    messages.append(exchange);
+    let mut excess = messages
+        .iter()
+        .filter(|message| *message.role() != ChatRole::System)
+        .count()
+        .saturating_sub(window);
+    let mut kept = Vec::with_capacity(messages.len());
+    let mut removed = Vec::with_capacity(excess);
+    for message in messages.iter() {
+        if excess > 0 && *message.role() != ChatRole::System {
+            excess -= 1;
+            removed.push(message.clone());
+        } else {
+            kept.push(message.clone());
+        }
+    }
+    *messages = ChatMessageCollection::for_vector(kept);
+    removed
+}
+
+/// A memory keeping only the latest messages of the conversation.
+///
+/// System messages, such as the instructions the conversation started with, are always kept and don't count
+/// towards the window.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WindowMemory {
+    window: usize,
+    messages: ChatMessageCollection<String>,
+}
+
+impl WindowMemory {
+    /// Creates a memory keeping the latest `window` messages.
+    pub fn new(window: usize) -> Self {
+        Self {
+            window,
+            messages: ChatMessageCollection::new(),
+        }
+    }
+
+    /// Starts the memory out with `messages`.
+    pub fn with_messages(mut self, messages: ChatMessageCollection<String>) -> Self {
+        self.messages = ChatMessageCollection::new();
+        append_windowed(&mut self.messages, messages, self.window);
+        self
+    }
+}
+
+#[async_trait]
+impl<E: traits::Executor + Sync> ConversationMemory<E> for WindowMemory {
+    fn history(&self) -> ChatMessageCollection<String> {
+        self.messages.clone()
+    }
+
+    async fn record(
+        &mut self,
+        exchange: ChatMessageCollection<String>,
+        _executor: &E,
+    ) -> Result<(), Error<E::Error>> {
+        append_windowed(&mut self.messages, exchange, self.window);
+        Ok(())
+    }
+}
+
+const DEFAULT_SUMMARY_PROMPT: &str = "Progressively summarize the lines of conversation provided, adding onto the previous summary and returning a new summary.\n\nCurrent summary:\n{{summary}}\n\nNew lines of conversation:\n{{text}}\n\nNew summary:";
+
+/// A memory keeping the latest messages of the conversation, and a summary of the older ones.
+///
+/// Once the conversation holds more than `window` messages, the oldest ones are passed to the summary step, along
+/// with the current summary in `summary`, and the text of its output becomes the new summary. The summary is sent
+/// to the model as a system message, after the system messages the conversation started with, which are always
+/// kept.
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct SummaryMemory<E: traits::Executor> {
+    step: Step<E>,
+    window: usize,
+    summary: Option<String>,
+    messages: ChatMessageCollection<String>,
+}
+
+impl<E: traits::Executor> SummaryMemory<E> {
+    /// Creates a memory keeping the latest `window` messages and summarizing older ones with a default prompt.
+    pub fn new(window: usize) -> Self {
+        Self {
+            step: Step::for_prompt_template(crate::prompt!(DEFAULT_SUMMARY_PROMPT)),
+            window,
+            summary: None,
+            messages: ChatMessageCollection::new(),
+        }
+    }
+
+    /// Sets the step writing the summary. Its prompt receives the current summary in `summary`, which is empty at
+    /// first, and the messages to add to it in `text`.
+    pub fn with_step(mut self, step: Step<E>) -> Self {
+        self.step = step;
+        self
+    }
+
+    /// Starts the memory out with `messages`. Messages beyond the window are summarized on the next exchange.
+    pub fn with_messages(mut self, messages: ChatMessageCollection<String>) -> Self {
+        self.messages = messages;
+        self
+    }
+
+    /// Returns the summary of the messages no longer kept, if any have been summarized.
+    pub fn summary(&self) -> Option<&str> {
+        self.summary.as_deref()
+    }
+}
+
+#[async_trait]
+impl<E> ConversationMemory<E> for SummaryMemory<E>
+where
+    E: traits::Executor + Sync,
+    E::Error: Send + Sync + 'static,
+    E::Output: Send,
+    E::PerInvocationOptions: Sync,
+{
+    fn history(&self) -> ChatMessageCollection<String> {
+        let (system, rest): (Vec<_>, Vec<_>) = self
+            .messages
+            .iter()
+            .cloned()
+            .partition(|message| *message.role() == ChatRole::System);
+        let mut history = ChatMessageCollection::for_vector(system);
+        if let Some(summary) = &self.summary {
+            history.add_message(ChatMessage::system(format!(
+                "Summary of the earlier conversation:\n{}",
+                summary
+            )));
+        }
+        history.append(ChatMessageCollection::for_vector(rest));
+        history
+    }
+
+    async fn record(
+        &mut self,
+        exchange: ChatMessageCollection<String>,
+        executor: &E,
+    ) -> Result<(), Error<E::Error>> {
+        let removed = append_windowed(&mut self.messages, exchange, self.window);
+        if removed.is_empty() {
+            return Ok(());
+        }
+        let text = ChatMessageCollection::for_vector(removed).to_string();
+        let parameters = parameters!()
+            .with("summary", self.summary.clone().unwrap_or_default())
+            .with_text(text);
+        let prompt = self.step.format(&parameters)?;
+        let output = executor
+            .execute(self.step.options(), &prompt, self.step.is_streaming())
+            .await?;
+        let summary = output
+            .primary_textual_output()
+            .await
+            .ok_or(Error::NoModelOutput)?;
+        self.summary = Some(summary.trim().to_string());
+        Ok(())
+    }
+}
 
 /// `Chain` represents a conversation between an entity and an LLM.
 ///
-/// It holds the conversation state and provides methods for sending messages and receiving responses.
+/// It holds the conversation state and provides methods for sending messages and receiving responses. The state is
+/// kept by the memory `M`, a [`BufferMemory`] keeping the whole conversation unless another memory is given with
+/// `Chain::with_memory`.
 #[derive(Serialize, Deserialize)]
-pub struct Chain<E: traits::Executor> {
-    state: ChatMessageCollection<String>,
+pub struct Chain<E: traits::Executor, M = BufferMemory> {
+    #[serde(rename = "state")]
+    memory: M,
+    #[serde(skip, default = "default_turn_formatter")]
+    turn_formatter: TurnFormatter,
     _phantom: std::marker::PhantomData<E>,
 }
 
@@ -27,7 +287,8 @@ where
     /// Constructs a new `Chain` with an empty conversation state.
     fn default() -> Self {
         Self {
-            state: ChatMessageCollection::new(),
+            memory: BufferMemory::default(),
+            turn_formatter: default_turn_formatter(),
             _phantom: std::marker::PhantomData,
         }
     }
@@ -43,7 +304,8 @@ impl<E: traits::Executor> Chain<E> {
             .format(&parameters!())
             .map(|state| state.to_chat())
             .map(|state| Self {
-                state,
+                memory: BufferMemory::new(state),
+                turn_formatter: default_turn_formatter(),
                 _phantom: std::marker::PhantomData,
             })?)
     }
@@ -54,14 +316,78 @@ impl<E: traits::Executor> Chain<E> {
     /// * `state` - The initial prompt state to use.
     pub fn new_with_message_collection(state: &ChatMessageCollection<String>) -> Chain<E> {
         Self {
-            state: state.clone(),
+            memory: BufferMemory::new(state.clone()),
+            turn_formatter: default_turn_formatter(),
             _phantom: std::marker::PhantomData,
         }
     }
+}
+
+impl<E: traits::Executor, M> Chain<E, M> {
+    /// Constructs a new `Chain` remembering the conversation with `memory`.
+    ///
+    /// # Arguments
+    /// * `memory` - The memory to use, which may already hold messages.
+    pub fn with_memory(memory: M) -> Chain<E, M> {
+        Self {
+            memory,
+            turn_formatter: default_turn_formatter(),
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
+    /// Sets how messages are formatted into the `history` parameter. Defaults to `Role: body`; messages are
+    /// separated by line breaks.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use llm_chain::chains::conversation::Chain;
+    /// use llm_chain::prompt::ChatRole;
+    /// use llm_chain::traits::Executor;
+    ///
+    /// fn questions_and_answers<E: Executor>(chain: Chain<E>) -> Chain<E> {
+    ///     chain.with_turn_formatter(|message| match message.role() {
+    ///         ChatRole::User => format!("Q: {}", message.body()),
+    ///         _ => format!("A: {}", message.body()),
+    ///     })
+    /// }
+    /// ```
+    pub fn with_turn_formatter<F>(mut self, formatter: F) -> Self
+    where
+        F: Fn(&ChatMessage<String>) -> String + Send + Sync + 'static,
+    {
+        self.turn_formatter = Arc::new(formatter);
+        self
+    }
+
+    /// Formats `history` for the `history` parameter.
+    fn format_history(&self, history: &ChatMessageCollection<String>) -> String {
+        history
+            .iter()
+            .map(|message| (self.turn_formatter)(message))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Returns the memory of the chain.
+    pub fn memory(&self) -> &M {
+        &self.memory
+    }
+
+    /// Returns the memory of the chain mutably, for example to clear it.
+    pub fn memory_mut(&mut self) -> &mut M {
+        &mut self.memory
+    }
+}
 
+impl<E: traits::Executor, M: ConversationMemory<E>> Chain<E, M> {
     /// Sends a message to the LLM and returns the response.
     ///
-    /// This method sends a message to the LLM, adding it and the response to the internal state.
+    /// This method sends a message to the LLM, recording it and the response in the memory.
+    ///
+    /// If the template of the step uses the `history` parameter, the history is formatted into it instead of being
+    /// sent as messages before the prompt. The prompt is then recorded as formatted with an empty history.
     ///
     /// # Arguments
     /// * `step` - The step to send.
@@ -76,14 +402,123 @@ impl<E: traits::Executor> Chain<E> {
         parameters: &Parameters,
         exec: &E,
     ) -> Result<E::Output, Error<E::Error>> {
-        let fmt = step.format(parameters)?;
-        self.send_message_raw(step.options(), &fmt, step.is_streaming(), exec)
+        self.send_message_with_cancellation(step, parameters, exec, &CancellationToken::new())
             .await
     }
 
+    /// Sends a message to the LLM like `send_message`, stopping when `token` is cancelled.
+    ///
+    /// A cancelled message returns `Error::Cancelled` and leaves the memory unchanged, so the message can be sent
+    /// again.
+    pub async fn send_message_with_cancellation(
+        &mut self,
+        step: Step<E>,
+        parameters: &Parameters,
+        exec: &E,
+        token: &CancellationToken,
+    ) -> Result<E::Output, Error<E::Error>> {
+        let (prompt, recorded) = self.prepare(&step, parameters, exec)?;
+        self.execute_and_record(
+            step.options(),
+            &prompt,
+            &recorded,
+            step.is_streaming(),
+            exec,
+            token,
+        )
+        .await
+    }
+
+    /// Sends a message to the LLM like `send_message`, streaming the response as it is generated.
+    ///
+    /// The chain is borrowed until the stream ends, at which point the message and the streamed response are recorded
+    /// in the memory. An error while recording is the last item of the stream; a stream dropped before its end
+    /// leaves the memory unchanged.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut stream = chain.send_message_stream(Step::for_prompt_template(prompt!(user: "Hi!")), &parameters!(), &exec)?;
+    /// while let Some(chunk) = stream.next().await {
+    ///     print!("{}", chunk?.delta);
+    /// }
+    /// ```
+    pub fn send_message_stream<'a>(
+        &'a mut self,
+        step: Step<E>,
+        parameters: &Parameters,
+        exec: &'a E,
+    ) -> Result<OutputStream<'a, Error<E::Error>>, Error<E::Error>>
+    where
+        E: Send + Sync,
+        E::Error: Send,
+    {
+        let (prompt, recorded) = self.prepare(&step, parameters, exec)?;
+        if prompt.has_images() && !exec.supports_images() {
+            return Err(Error::UnsupportedImages);
+        }
+        let chunks = exec.execute_stream(step.options(), &prompt);
+        let state = Some((chunks, String::new(), recorded.to_chat(), self));
+        Ok(stream::unfold(state, move |state| async move {
+            let (mut chunks, mut text, mut exchange, chain) = state?;
+            match chunks.next().await {
+                Some(Ok(chunk)) => {
+                    text.push_str(&chunk.delta);
+                    Some((Ok(chunk), Some((chunks, text, exchange, chain))))
+                }
+                Some(Err(err)) => Some((Err(Error::Executor(err)), None)),
+                None => {
+                    exchange.add_message(ChatMessage::new(ChatRole::Assistant, text));
+                    match chain.memory.record(exchange, exec).await {
+                        Ok(()) => None,
+                        Err(err) => Some((Err(err), None)),
+                    }
+                }
+            }
+        })
+        .boxed())
+    }
+
+    /// Returns the prompt to send for `step`, with the history of the conversation, and the prompt to record.
+    fn prepare(
+        &self,
+        step: &Step<E>,
+        parameters: &Parameters,
+        exec: &E,
+    ) -> Result<(Prompt, Prompt), Error<E::Error>> {
+        let options = step.options();
+        if !step.prompt().variables().iter().any(|v| v == HISTORY_KEY) {
+            let prompt = step.format(parameters)?;
+            return Ok((self.with_history(options, &prompt, exec)?, prompt));
+        }
+        let without_history = step.format(&parameters.with(HISTORY_KEY, ""))?;
+        let tokens_remaining = exec
+            .tokens_used(options, &without_history)?
+            .tokens_remaining();
+        let mut history = self.memory.history();
+        history.trim_context(&exec.get_tokenizer(options)?, tokens_remaining)?;
+        let prompt = step.format(&parameters.with(HISTORY_KEY, self.format_history(&history)))?;
+        Ok((prompt, without_history))
+    }
+
+    /// Combines the conversation history, trimmed to fit the context window along with `prompt`, with `prompt`.
+    fn with_history(
+        &self,
+        options: Option<&<E as traits::Executor>::PerInvocationOptions>,
+        prompt: &Prompt,
+        exec: &E,
+    ) -> Result<Prompt, Error<E::Error>> {
+        let tok = exec.tokens_used(options, prompt)?;
+        let tokens_remaining = tok.tokens_remaining();
+        let tokenizer = exec.get_tokenizer(options)?;
+        let mut history = self.memory.history();
+        history.trim_context(&tokenizer, tokens_remaining)?;
+        Ok(Prompt::Chat(history).combine(prompt))
+    }
+
     /// Sends a message to the LLM and returns the response.
     ///
-    /// This method takes a ready prompt and options and sends it to the LLM, adding it and the response to the internal state.
+    /// This method takes a ready prompt and options and sends it to the LLM, recording it and the response in the memory.
     ///
     /// # Arguments
     /// * `options` - The options to use when executing the prompt.
@@ -99,20 +534,45 @@ impl<E: traits::Executor> Chain<E> {
         is_streaming: Option<bool>,
         exec: &E,
     ) -> Result<E::Output, Error<E::Error>> {
-        let tok = exec.tokens_used(options, prompt)?;
-        let tokens_remaining = tok.tokens_remaining();
-        let tokenizer = exec.get_tokenizer(options)?;
-        self.state.trim_context(&tokenizer, tokens_remaining)?;
+        let prompt_with_history = self.with_history(options, prompt, exec)?;
+        self.execute_and_record(
+            options,
+            &prompt_with_history,
+            prompt,
+            is_streaming,
+            exec,
+            &CancellationToken::new(),
+        )
+        .await
+    }
 
-        // Combine the conversation history with the new prompt.
-        let prompt_with_history = Prompt::Chat(self.state.clone()).combine(prompt);
+    /// Sends `prompt` to the LLM, and records `recorded` and the response in the memory, unless `token` is cancelled
+    /// first.
+    async fn execute_and_record(
+        &mut self,
+        options: Option<&<E as traits::Executor>::PerInvocationOptions>,
+        prompt: &Prompt,
+        recorded: &Prompt,
+        is_streaming: Option<bool>,
+        exec: &E,
+        token: &CancellationToken,
+    ) -> Result<E::Output, Error<E::Error>> {
+        if prompt.has_images() && !exec.supports_images() {
+            return Err(Error::UnsupportedImages);
+        }
 
         // Execute the prompt and retrieve the LLM's response.
-        let res = exec
-            .execute(options, &prompt_with_history, is_streaming)
-            .await?;
+        let res = token
+            .run_until_cancelled(exec.execute(options, prompt, is_streaming))
+            .await
+            .map_err(|reason| {
+                Error::Cancelled(Cancelled {
+                    reason,
+                    partial_outputs: Vec::new(),
+                })
+            })??;
 
-        // Create a ChatMessage from the response and add it to the conversation state.
+        // Create a ChatMessage from the response and record it in the memory along with the prompt.
         let response_message = ChatMessage::new(
             res.get_chat_role()
                 .await
@@ -121,8 +581,9 @@ impl<E: traits::Executor> Chain<E> {
                 .await
                 .ok_or(Error::NoModelOutput)?,
         );
-        self.state = prompt_with_history.to_chat();
-        self.state.add_message(response_message);
+        let mut exchange = recorded.to_chat();
+        exchange.add_message(response_message);
+        self.memory.record(exchange, exec).await?;
 
         Ok(res)
     }
@@ -141,4 +602,68 @@ pub enum Error<E: ExecutorError> {
     NoModelOutput,
     #[error("StringTemplateError: {0}")]
     StringTemplate(#[from] crate::prompt::StringTemplateError),
+    #[error("The conversation contains images, but the executor only supports text")]
+    UnsupportedImages,
+    #[error("The message was cancelled: {}", .0.reason)]
+    Cancelled(Cancelled),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::mock::{MockExecutor, MockOutput};
+    use crate::cancellation::CancellationReason;
+    use futures::executor::block_on;
+
+    #[test]
+    fn cancelled_messages_are_not_recorded() {
+        let executor = MockExecutor::new(vec![MockOutput::text("Hello!")]);
+        let mut chain: Chain<MockExecutor> = Chain::default();
+        let step = || Step::for_prompt_template(crate::prompt!(user: "Hi!"));
+        let history = |chain: &Chain<MockExecutor>| {
+            ConversationMemory::<MockExecutor>::history(chain.memory())
+        };
+        let token = CancellationToken::new();
+        token.cancel(CancellationReason::Timeout);
+        let result = block_on(chain.send_message_with_cancellation(
+            step(),
+            &parameters!(),
+            &executor,
+            &token,
+        ));
+        assert!(matches!(
+            result,
+            Err(Error::Cancelled(cancelled)) if cancelled.reason == CancellationReason::Timeout
+        ));
+        assert!(history(&chain).is_empty());
+
+        block_on(chain.send_message(step(), &parameters!(), &executor)).unwrap();
+        let history = history(&chain);
+        let bodies: Vec<_> = history.iter().map(|m| m.body().as_str()).collect();
+        assert_eq!(bodies, ["Hi!", "Hello!"]);
+    }
+
+    #[test]
+    fn window_keeps_system_messages_and_latest_messages() {
+        let mut messages = ChatMessageCollection::new().with_system("Be brief.".to_string());
+        let exchange = ChatMessageCollection::new()
+            .with_user("1".to_string())
+            .with_assistant("2".to_string())
+            .with_user("3".to_string());
+        let removed = append_windowed(&mut messages, exchange, 2);
+        assert_eq!(
+            removed
+                .iter()
+                .map(|m| m.body().as_str())
+                .collect::<Vec<_>>(),
+            ["1"]
+        );
+        assert_eq!(
+            messages
+                .iter()
+                .map(|m| m.body().as_str())
+                .collect::<Vec<_>>(),
+            ["Be brief.", "2", "3"]
+        );
+    }
 }
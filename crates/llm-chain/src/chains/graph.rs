@@ -0,0 +1,410 @@
+//! The `graph` module contains a `Chain` running steps in the order of their dependencies.
+//!
+//! Every node of a graph chain is a step that declares the parameters it reads and the parameter its output is
+//! written to. A node depends on the nodes producing its inputs, and runs as soon as they have all finished, so
+//! independent branches run concurrently. This expresses fan-out and fan-in workflows, such as summarizing a text
+//! several ways and merging the summaries, that a sequential chain can't.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let chain = graph::Chain::new()
+//!     .with_node(Node::new("pros", Step::for_prompt_template(prompt!("List the pros of {{text}}"))).with_inputs(["text"]))
+//!     .with_node(Node::new("cons", Step::for_prompt_template(prompt!("List the cons of {{text}}"))).with_inputs(["text"]))
+//!     .with_node(
+//!         Node::new("verdict", Step::for_prompt_template(prompt!("Pros:\n{{pros}}\n\nCons:\n{{cons}}\n\nVerdict:")))
+//!             .with_inputs(["pros", "cons"]),
+//!     );
+//! let output = chain.run(parameters!("remote work"), &executor).await?;
+//! println!("{}", output.parameters.get("verdict").unwrap());
+//! ```
+use std::collections::{BTreeMap, HashMap};
+
+use futures::stream::{FuturesUnordered, StreamExt};
+use thiserror::Error;
+
+use super::sequential::ChainStep;
+use crate::{
+    frame::{FormatAndExecuteError, Frame},
+    output::Output,
+    step::CustomStepError,
+    traits::{Executor, ExecutorError},
+    Parameters,
+};
+
+/// The `GraphChainError` enum represents errors that can occur when building or executing a graph chain.
+#[derive(Error, Debug)]
+pub enum GraphChainError<Err: ExecutorError> {
+    #[error("Two nodes are named {0}")]
+    DuplicateNode(String),
+    #[error("Two nodes write their output to the parameter {0}")]
+    DuplicateOutput(String),
+    #[error("The nodes {0:?} are part of a dependency cycle or depend on one")]
+    Cycle(Vec<String>),
+    #[error("The input {input} of node {node} is neither a parameter of the chain nor the output of a node")]
+    MissingInput { node: String, input: String },
+    #[error("Node {node} failed: {source}")]
+    FormatAndExecute {
+        node: String,
+        #[source]
+        source: FormatAndExecuteError<Err>,
+    },
+    #[error("Custom step of node {node} failed: {source}")]
+    CustomStep {
+        node: String,
+        #[source]
+        source: CustomStepError,
+    },
+    #[error("Node {0} produced no output")]
+    NoOutput(String),
+}
+
+/// A step of a graph chain, along with the parameters it reads and the parameter its output is written to.
+pub struct Node<E: Executor> {
+    name: String,
+    step: ChainStep<E>,
+    inputs: Vec<String>,
+    output: String,
+}
+
+impl<E: Executor> Node<E> {
+    /// Creates a node named `name` running `step`, without inputs. Its output is written to the parameter named like
+    /// the node.
+    pub fn new<N: Into<String>, S: Into<ChainStep<E>>>(name: N, step: S) -> Self {
+        let name = name.into();
+        Self {
+            output: name.clone(),
+            name,
+            step: step.into(),
+            inputs: Vec::new(),
+        }
+    }
+
+    /// Sets the parameters read by the step. The node runs after the nodes producing them; the others must be
+    /// parameters of the chain.
+    pub fn with_inputs<I, S>(mut self, inputs: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.inputs = inputs.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the parameter the output of the step is written to.
+    pub fn with_output<S: Into<String>>(mut self, output: S) -> Self {
+        self.output = output.into();
+        self
+    }
+
+    /// Returns the name of the node.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// The output of a graph chain.
+#[derive(Debug, Clone)]
+pub struct GraphOutput<O> {
+    /// The parameters of the chain, along with the output of every node.
+    pub parameters: Parameters,
+    /// The outputs of the executor, by the name of the node that produced them. Custom steps that don't invoke the
+    /// model have no output here.
+    pub outputs: BTreeMap<String, O>,
+}
+
+/// The dependencies between the nodes of a graph, by index.
+struct Plan {
+    /// The nodes that depend on each node.
+    dependents: Vec<Vec<usize>>,
+    /// The number of nodes each node depends on.
+    dependencies: Vec<usize>,
+}
+
+/// Computes the dependencies between nodes described by their `(name, inputs, output)`, checking that the graph has
+/// no cycles.
+fn plan(nodes: &[(&str, &[String], &str)]) -> Result<Plan, PlanError> {
+    let mut producers = HashMap::new();
+    let mut names = HashMap::new();
+    for (i, (name, _, output)) in nodes.iter().enumerate() {
+        if names.insert(*name, i).is_some() {
+            return Err(PlanError::DuplicateNode(name.to_string()));
+        }
+        if producers.insert(*output, i).is_some() {
+            return Err(PlanError::DuplicateOutput(output.to_string()));
+        }
+    }
+    let mut dependents = vec![Vec::new(); nodes.len()];
+    let mut dependencies = vec![0; nodes.len()];
+    for (i, (_, inputs, _)) in nodes.iter().enumerate() {
+        for input in inputs.iter() {
+            if let Some(&producer) = producers.get(input.as_str()) {
+                if !dependents[producer].contains(&i) {
+                    dependents[producer].push(i);
+                    dependencies[i] += 1;
+                }
+            }
+        }
+    }
+
+    // Kahn's algorithm: the nodes that are never ready are part of a cycle or depend on one.
+    let mut remaining = dependencies.clone();
+    let mut ready: Vec<usize> = (0..nodes.len()).filter(|&i| remaining[i] == 0).collect();
+    let mut visited = 0;
+    while let Some(i) = ready.pop() {
+        visited += 1;
+        for &dependent in &dependents[i] {
+            remaining[dependent] -= 1;
+            if remaining[dependent] == 0 {
+                ready.push(dependent);
+            }
+        }
+    }
+    if visited < nodes.len() {
+        return Err(PlanError::Cycle(
+            (0..nodes.len())
+                .filter(|&i| remaining[i] > 0)
+                .map(|i| nodes[i].0.to_string())
+                .collect(),
+        ));
+    }
+    Ok(Plan {
+        dependents,
+        dependencies,
+    })
+}
+
+enum PlanError {
+    DuplicateNode(String),
+    DuplicateOutput(String),
+    Cycle(Vec<String>),
+}
+
+impl<Err: ExecutorError> From<PlanError> for GraphChainError<Err> {
+    fn from(error: PlanError) -> Self {
+        match error {
+            PlanError::DuplicateNode(name) => GraphChainError::DuplicateNode(name),
+            PlanError::DuplicateOutput(name) => GraphChainError::DuplicateOutput(name),
+            PlanError::Cycle(names) => GraphChainError::Cycle(names),
+        }
+    }
+}
+
+/// A chain running its nodes in the order of their dependencies, running independent nodes concurrently.
+///
+/// Each node receives the parameters of the chain and the outputs of the nodes it depends on. When a node has a
+/// single input, it is also available as `text`. The textual output of a prompt step is written to the output
+/// parameter of its node. A custom step's output is the text of the model output it returns, or else the value of
+/// the node's output parameter, or of `text`, in the parameters it returns.
+pub struct Chain<E: Executor> {
+    nodes: Vec<Node<E>>,
+}
+
+impl<E: Executor> Default for Chain<E> {
+    fn default() -> Self {
+        Self { nodes: Vec::new() }
+    }
+}
+
+impl<E: Executor> Chain<E> {
+    /// Creates a chain without nodes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a node to the chain.
+    pub fn with_node(mut self, node: Node<E>) -> Self {
+        self.nodes.push(node);
+        self
+    }
+
+    /// Returns the nodes of the chain, in the order they were added.
+    pub fn nodes(&self) -> &[Node<E>] {
+        &self.nodes
+    }
+
+    fn plan(&self) -> Result<Plan, PlanError> {
+        let nodes: Vec<(&str, &[String], &str)> = self
+            .nodes
+            .iter()
+            .map(|node| {
+                (
+                    node.name.as_str(),
+                    node.inputs.as_slice(),
+                    node.output.as_str(),
+                )
+            })
+            .collect();
+        plan(&nodes)
+    }
+
+    /// Checks that the node names and outputs are unique and that the nodes don't depend on each other in a cycle.
+    pub fn validate(&self) -> Result<(), GraphChainError<E::Error>> {
+        self.plan()?;
+        Ok(())
+    }
+
+    /// Runs the node at `index` with the parameters of the chain and the outputs of its dependencies.
+    async fn run_node(
+        &self,
+        index: usize,
+        parameters: Parameters,
+        executor: &E,
+    ) -> (
+        usize,
+        Result<(String, Option<E::Output>), GraphChainError<E::Error>>,
+    ) {
+        let node = &self.nodes[index];
+        let result = match &node.step {
+            ChainStep::Prompt(step) => {
+                match Frame::new(executor, step)
+                    .format_and_execute(&parameters)
+                    .await
+                {
+                    Ok(output) => match output.primary_textual_output().await {
+                        Some(text) => Ok((text, Some(output))),
+                        None => Err(GraphChainError::NoOutput(node.name.clone())),
+                    },
+                    Err(source) => Err(GraphChainError::FormatAndExecute {
+                        node: node.name.clone(),
+                        source,
+                    }),
+                }
+            }
+            ChainStep::Custom(step) => match step.run(&parameters, executor).await {
+                Ok(outcome) => {
+                    let text = match &outcome.output {
+                        Some(output) => output.primary_textual_output().await,
+                        None => outcome
+                            .parameters
+                            .get(&node.output)
+                            .or_else(|| outcome.parameters.get_text()),
+                    };
+                    text.map(|text| (text, outcome.output))
+                        .ok_or_else(|| GraphChainError::NoOutput(node.name.clone()))
+                }
+                Err(source) => Err(GraphChainError::CustomStep {
+                    node: node.name.clone(),
+                    source,
+                }),
+            },
+        };
+        (index, result)
+    }
+
+    /// Returns the parameters given to the node at `index`.
+    fn node_parameters(
+        &self,
+        index: usize,
+        parameters: &Parameters,
+        values: &HashMap<&str, String>,
+    ) -> Parameters {
+        let node = &self.nodes[index];
+        let mut node_parameters = parameters.clone();
+        for input in &node.inputs {
+            if let Some(value) = values.get(input.as_str()) {
+                node_parameters = node_parameters.with(input.as_str(), value.as_str());
+            }
+        }
+        if let [input] = node.inputs.as_slice() {
+            if let Some(value) = node_parameters.get(input) {
+                node_parameters = node_parameters.with_text(value);
+            }
+        }
+        node_parameters
+    }
+
+    /// Runs the nodes of the chain in the order of their dependencies, starting each node as soon as the nodes it
+    /// depends on have finished.
+    ///
+    /// The chain is validated first, and fails without running any node when an input is neither a parameter of
+    /// the chain nor the output of a node. When a node fails, the nodes still running are abandoned.
+    pub async fn run(
+        &self,
+        parameters: Parameters,
+        executor: &E,
+    ) -> Result<GraphOutput<E::Output>, GraphChainError<E::Error>> {
+        let Plan {
+            dependents,
+            mut dependencies,
+        } = self.plan()?;
+        for node in &self.nodes {
+            for input in &node.inputs {
+                let produced = self.nodes.iter().any(|n| &n.output == input);
+                if !produced && parameters.get(input).is_none() {
+                    return Err(GraphChainError::MissingInput {
+                        node: node.name.clone(),
+                        input: input.clone(),
+                    });
+                }
+            }
+        }
+
+        let mut values: HashMap<&str, String> = HashMap::new();
+        let mut outputs = BTreeMap::new();
+        let mut running = FuturesUnordered::new();
+        for (index, _) in dependencies.iter().enumerate().filter(|(_, &d)| d == 0) {
+            let node_parameters = self.node_parameters(index, &parameters, &values);
+            running.push(self.run_node(index, node_parameters, executor));
+        }
+        while let Some((index, result)) = running.next().await {
+            let (text, output) = result?;
+            let node = &self.nodes[index];
+            values.insert(node.output.as_str(), text);
+            if let Some(output) = output {
+                outputs.insert(node.name.clone(), output);
+            }
+            for &dependent in &dependents[index] {
+                dependencies[dependent] -= 1;
+                if dependencies[dependent] == 0 {
+                    let node_parameters = self.node_parameters(dependent, &parameters, &values);
+                    running.push(self.run_node(dependent, node_parameters, executor));
+                }
+            }
+        }
+
+        let parameters = values
+            .into_iter()
+            .fold(parameters, |parameters, (key, value)| {
+                parameters.with(key, value)
+            });
+        Ok(GraphOutput {
+            parameters,
+            outputs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{plan, PlanError};
+
+    fn inputs(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn plans_fan_out_and_fan_in() {
+        let (none, text, both) = (inputs(&[]), inputs(&["text"]), inputs(&["pros", "cons"]));
+        let nodes = [
+            ("verdict", both.as_slice(), "verdict"),
+            ("pros", text.as_slice(), "pros"),
+            ("cons", none.as_slice(), "cons"),
+        ];
+        let Ok(fan_in) = plan(&nodes) else {
+            panic!("the graph is valid");
+        };
+        assert_eq!(fan_in.dependencies, vec![2, 0, 0]);
+        assert_eq!(fan_in.dependents, vec![vec![], vec![0], vec![0]]);
+
+        let (a, b) = (inputs(&["b"]), inputs(&["a"]));
+        let nodes = [
+            ("a", a.as_slice(), "a"),
+            ("b", b.as_slice(), "b"),
+            ("c", b.as_slice(), "c"),
+        ];
+        assert!(matches!(plan(&nodes), Err(PlanError::Cycle(names)) if names == ["a", "b", "c"]));
+        let nodes = [("a", none.as_slice(), "x"), ("b", none.as_slice(), "x")];
+        assert!(matches!(plan(&nodes), Err(PlanError::DuplicateOutput(name)) if name == "x"));
+    }
+}
@@ -0,0 +1,262 @@
+//! The `loop_` module contains a `Chain` that runs a sub-chain repeatedly, feeding each output back into it.
+//!
+//! A loop chain runs its sub-chain until a stop condition holds, or until it has run a maximum number of times or
+//! generated a maximum number of tokens. The output of every iteration replaces the `text` parameter of the next
+//! one, which makes loops the building block of iterative refinement, such as rewriting a draft until a critic
+//! approves it, and of agent loops.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let refine = Step::for_prompt_template(prompt!(
+//!     "Improve this draft, or answer DONE followed by the draft if it can't be improved:\n\n{{text}}"
+//! ));
+//! let chain = loop_::Chain::from_step(refine)
+//!     .with_stop_phrase("DONE")
+//!     .with_max_iterations(4);
+//! let output = chain.run(parameters!(draft), &executor).await?;
+//! println!("{} after {} iterations", output.text, output.iterations);
+//! ```
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use super::sequential::{self, SequentialChainError};
+use crate::{
+    output::Output,
+    step::Step,
+    tokens::{Tokenizer, TokenizerError},
+    traits::{Executor, ExecutorError},
+    Parameters,
+};
+
+const DEFAULT_MAX_ITERATIONS: usize = 5;
+
+/// The `LoopChainError` enum represents errors that can occur when executing a loop chain.
+#[derive(Error, Debug)]
+pub enum LoopChainError<Err: ExecutorError> {
+    #[error("Iteration {iteration} failed: {source}")]
+    Iteration {
+        iteration: usize,
+        #[source]
+        source: SequentialChainError<Err>,
+    },
+    #[error("Iteration {0} returned no text")]
+    NoTextOutput(usize),
+    #[error("Unable to count the generated tokens: {0}")]
+    Tokenizer(#[from] TokenizerError),
+}
+
+/// The condition ending a loop chain, evaluated over the parameters after every iteration.
+pub type StopCondition = Arc<dyn Fn(&Parameters) -> bool + Send + Sync>;
+
+/// Why a loop chain stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The stop condition held.
+    Condition,
+    /// The chain ran the maximum number of iterations.
+    MaxIterations,
+    /// The iterations generated at least the maximum number of tokens.
+    TokenBudget,
+}
+
+/// The output of a loop chain.
+#[derive(Debug, Clone)]
+pub struct LoopOutput<O> {
+    /// The output of the last iteration.
+    pub output: O,
+    /// The text of the last output.
+    pub text: String,
+    /// The number of iterations that ran.
+    pub iterations: usize,
+    /// Why the loop stopped.
+    pub stop_reason: StopReason,
+    /// The parameters after the last iteration.
+    pub parameters: Parameters,
+}
+
+/// A chain running a sub-chain repeatedly until a stop condition holds or a budget is exhausted.
+///
+/// Every iteration receives the parameters of the chain, `iteration`, the number of the iteration starting at 1, and
+/// from the second iteration on, the output of the previous iteration in `text`. After every iteration the stop
+/// condition is evaluated over the same parameters, with `text` set to the new output.
+pub struct Chain<E: Executor> {
+    body: sequential::Chain<E>,
+    stop: Option<StopCondition>,
+    max_iterations: usize,
+    max_tokens: Option<usize>,
+}
+
+impl<E: Executor> Chain<E> {
+    /// Creates a loop running `body` up to 5 times, without a stop condition.
+    pub fn new(body: sequential::Chain<E>) -> Self {
+        Self {
+            body,
+            stop: None,
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            max_tokens: None,
+        }
+    }
+
+    /// Creates a loop running a single step.
+    pub fn from_step(step: Step<E>) -> Self {
+        Self::new(sequential::Chain::of_one(step))
+    }
+
+    /// Stops the loop after the first iteration for which `stop` returns true.
+    pub fn with_stop_when<F>(mut self, stop: F) -> Self
+    where
+        F: Fn(&Parameters) -> bool + Send + Sync + 'static,
+    {
+        self.stop = Some(Arc::new(stop));
+        self
+    }
+
+    /// Stops the loop after the first iteration whose output contains `phrase`.
+    pub fn with_stop_phrase<S: Into<String>>(self, phrase: S) -> Self {
+        let phrase = phrase.into();
+        self.with_stop_when(move |parameters| {
+            parameters
+                .get_text()
+                .is_some_and(|text| text.contains(&phrase))
+        })
+    }
+
+    /// Sets the maximum number of iterations. Defaults to 5; at least one iteration always runs.
+    pub fn with_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations.max(1);
+        self
+    }
+
+    /// Stops the loop once the outputs of its iterations add up to `max_tokens` tokens of the executor's tokenizer.
+    pub fn with_max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    /// Runs the loop with the given parameters and executor.
+    pub async fn run(
+        &self,
+        parameters: Parameters,
+        executor: &E,
+    ) -> Result<LoopOutput<E::Output>, LoopChainError<E::Error>> {
+        let mut parameters = parameters;
+        let mut tokens = 0;
+        let mut iteration = 0;
+        loop {
+            iteration += 1;
+            parameters = parameters.with("iteration", iteration.to_string());
+            let output = self
+                .body
+                .run(parameters.clone(), executor)
+                .await
+                .map_err(|source| LoopChainError::Iteration { iteration, source })?;
+            let text = output
+                .primary_textual_output()
+                .await
+                .ok_or(LoopChainError::NoTextOutput(iteration))?;
+            parameters = parameters.with_text(text.as_str());
+            let over_budget = match self.max_tokens {
+                Some(max_tokens) => {
+                    tokens += executor.get_tokenizer(None)?.tokenize_str(&text)?.len();
+                    tokens >= max_tokens
+                }
+                None => false,
+            };
+            let stop_reason = if self.stop.as_ref().is_some_and(|stop| stop(&parameters)) {
+                StopReason::Condition
+            } else if over_budget {
+                StopReason::TokenBudget
+            } else if iteration >= self.max_iterations {
+                StopReason::MaxIterations
+            } else {
+                continue;
+            };
+            return Ok(LoopOutput {
+                output,
+                text,
+                iterations: iteration,
+                stop_reason,
+                parameters,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Chain, LoopChainError, StopReason};
+    use crate::agents::mock::{MockExecutor, MockOutput};
+    use crate::step::Step;
+    use crate::{prompt, Parameters};
+    use futures::executor::block_on;
+
+    fn refine() -> Chain<MockExecutor> {
+        Chain::from_step(Step::for_prompt_template(prompt!(
+            "Draft {{iteration}}: {{text}}"
+        )))
+    }
+
+    #[test]
+    fn stops_when_the_condition_holds() {
+        let executor = MockExecutor::new(vec![
+            MockOutput::text("better"),
+            MockOutput::text("DONE best"),
+            MockOutput::text("unused"),
+        ]);
+        let chain = refine().with_stop_phrase("DONE").with_max_iterations(5);
+        let output = block_on(chain.run(Parameters::new_with_text("first"), &executor)).unwrap();
+        assert_eq!(output.text, "DONE best");
+        assert_eq!(output.iterations, 2);
+        assert_eq!(output.stop_reason, StopReason::Condition);
+        // Every iteration receives the output of the previous one.
+        let prompts: Vec<_> = executor
+            .prompts
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|prompt| prompt.to_string())
+            .collect();
+        assert_eq!(prompts, vec!["Draft 1: first", "Draft 2: better"]);
+    }
+
+    #[test]
+    fn stops_after_the_maximum_iterations() {
+        let executor = MockExecutor::new(vec![
+            MockOutput::text("one"),
+            MockOutput::text("two"),
+            MockOutput::text("three"),
+        ]);
+        let chain = refine().with_stop_phrase("DONE").with_max_iterations(2);
+        let output = block_on(chain.run(Parameters::new_with_text("draft"), &executor)).unwrap();
+        assert_eq!(output.text, "two");
+        assert_eq!(output.iterations, 2);
+        assert_eq!(output.stop_reason, StopReason::MaxIterations);
+        assert_eq!(output.parameters.get("iteration").as_deref(), Some("2"));
+        assert_eq!(executor.prompts.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn stops_once_the_token_budget_is_spent() {
+        let executor = MockExecutor::new(vec![
+            MockOutput::text("two words"),
+            MockOutput::text("three more words"),
+            MockOutput::text("unused"),
+        ]);
+        let chain = refine().with_max_tokens(4).with_max_iterations(5);
+        let output = block_on(chain.run(Parameters::new_with_text("draft"), &executor)).unwrap();
+        assert_eq!(output.iterations, 2);
+        assert_eq!(output.stop_reason, StopReason::TokenBudget);
+    }
+
+    #[test]
+    fn reports_the_failing_iteration() {
+        let executor = MockExecutor::new(vec![MockOutput::text("one")]);
+        let result = block_on(refine().run(Parameters::new_with_text("draft"), &executor));
+        assert!(matches!(
+            result,
+            Err(LoopChainError::Iteration { iteration: 2, .. })
+        ));
+    }
+}
@@ -6,8 +6,21 @@
 //!
 //! The `Chain` struct is generic over the type of the `Step` and provides a convenient way
 //! to execute map-reduce operations using a provided `Executor`.
+//!
+//! Intermediate outputs are packed into as few reduce calls as fit the context window, largest outputs first.
+//! `Chain::run_with_trace` reports how many reduce calls each round took, and how many it would have taken by
+//! combining outputs in order.
+//!
+//! By default every map and reduce call of a round runs at once, and the run fails as soon as a map call fails.
+//! `Chain::with_max_concurrency` limits the number of calls running at the same time, and
+//! `Chain::with_failure_policy` lets the run go on without the documents that failed to map, or with a placeholder
+//! in their place.
+//!
+//! `Chain::run_with_cancellation` stops the run when a `CancellationToken` is cancelled, abandoning the calls that
+//! are running.
 
 use crate::{
+    cancellation::{CancellationReason, CancellationToken, Cancelled},
     frame::Frame,
     output::Output,
     serialization::StorableEntity,
@@ -18,7 +31,7 @@ use crate::{
     traits::{Executor, ExecutorError},
     Parameters,
 };
-use futures::future::join_all;
+use futures::stream::{self, StreamExt};
 use serde::de::{Deserializer, MapAccess};
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 use serde::Deserialize;
@@ -41,6 +54,111 @@ pub enum MapReduceChainError<Err: ExecutorError> {
     InputEmpty,
     #[error("Error templating: {0}")]
     StringTemplate(#[from] crate::prompt::StringTemplateError),
+    #[error("Error shutting down the executor: {0}")]
+    Shutdown(Err),
+    #[error("The chain was cancelled: {}", .0.reason)]
+    Cancelled(Cancelled),
+}
+
+/// The estimated number of tokens taken by the newline joining two intermediate outputs.
+const JOINER_TOKENS: usize = 1;
+
+/// What a map-reduce chain does when the map call of a document fails.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum MapFailurePolicy {
+    /// Fails the run with the error of the document, abandoning the map calls still running.
+    #[default]
+    FailFast,
+    /// Leaves the document out of the reduce step.
+    Skip,
+    /// Reduces the given text in place of the output of the document, e.g. "(this section could not be
+    /// processed)", so the reduce step knows something is missing.
+    Placeholder(String),
+}
+
+/// Statistics about one round of reduce calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReduceRound {
+    /// The number of intermediate outputs going into the round.
+    pub inputs: usize,
+    /// The number of reduce calls made in the round.
+    pub reduce_calls: usize,
+    /// The number of reduce calls the round would have needed combining outputs in order.
+    pub unpacked_reduce_calls: usize,
+}
+
+/// A trace of a map-reduce run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MapReduceTrace {
+    /// The number of map calls made.
+    pub map_calls: usize,
+    /// The reduce rounds, in order.
+    pub reduce_rounds: Vec<ReduceRound>,
+    /// The indices of the input documents whose map call failed and were skipped or replaced by a placeholder.
+    pub failed_documents: Vec<usize>,
+}
+
+impl MapReduceTrace {
+    /// The total number of reduce calls made.
+    pub fn reduce_calls(&self) -> usize {
+        self.reduce_rounds.iter().map(|r| r.reduce_calls).sum()
+    }
+
+    /// The number of reduce calls saved by packing intermediate outputs by size.
+    pub fn reduce_calls_saved(&self) -> usize {
+        self.reduce_rounds
+            .iter()
+            .map(|r| r.unpacked_reduce_calls.saturating_sub(r.reduce_calls))
+            .sum()
+    }
+}
+
+/// Packs items of the given sizes into as few bins of `capacity` as possible using first-fit decreasing.
+///
+/// Returns the indices of the items in each bin. Items within a bin, and the bins themselves, keep the original
+/// order of the items. An item larger than `capacity` gets a bin of its own.
+pub(crate) fn pack_by_size(sizes: &[usize], capacity: usize, joiner: usize) -> Vec<Vec<usize>> {
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by(|&a, &b| sizes[b].cmp(&sizes[a]));
+    let mut bins: Vec<(usize, Vec<usize>)> = Vec::new();
+    for i in order {
+        let fitting_bin = bins
+            .iter_mut()
+            .find(|(used, _)| used + joiner + sizes[i] <= capacity);
+        match fitting_bin {
+            Some((used, items)) => {
+                *used += joiner + sizes[i];
+                items.push(i);
+            }
+            None => bins.push((sizes[i], vec![i])),
+        }
+    }
+    let mut bins: Vec<Vec<usize>> = bins
+        .into_iter()
+        .map(|(_, mut items)| {
+            items.sort_unstable();
+            items
+        })
+        .collect();
+    bins.sort_by_key(|items| items[0]);
+    bins
+}
+
+/// Counts the bins needed to pack items of the given sizes in order, starting a new bin whenever the next item
+/// doesn't fit.
+pub(crate) fn count_sequential_bins(sizes: &[usize], capacity: usize, joiner: usize) -> usize {
+    let mut bins = 0;
+    let mut used: Option<usize> = None;
+    for &size in sizes {
+        used = match used {
+            Some(u) if u + joiner + size <= capacity => Some(u + joiner + size),
+            _ => {
+                bins += 1;
+                Some(size)
+            }
+        };
+    }
+    bins
 }
 
 /// The `Chain` struct represents a map-reduce chain, consisting of a `map` step and a `reduce` step.
@@ -50,6 +168,8 @@ pub enum MapReduceChainError<Err: ExecutorError> {
 pub struct Chain<E: Executor> {
     map: Step<E>,
     reduce: Step<E>,
+    max_concurrency: Option<usize>,
+    failure_policy: MapFailurePolicy,
 }
 
 impl<E: Executor> Chain<E> {
@@ -57,7 +177,31 @@ impl<E: Executor> Chain<E> {
     ///
     /// The `new` function takes two instances of `Step` and returns a new `Chain` instance.
     pub fn new(map: Step<E>, reduce: Step<E>) -> Chain<E> {
-        Chain { map, reduce }
+        Chain {
+            map,
+            reduce,
+            max_concurrency: None,
+            failure_policy: MapFailurePolicy::default(),
+        }
+    }
+
+    /// Runs at most `max_concurrency` map or reduce calls at the same time. By default all the calls of a round run
+    /// at once.
+    pub fn with_max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = Some(max_concurrency.max(1));
+        self
+    }
+
+    /// Sets what happens when the map call of a document fails. Defaults to `MapFailurePolicy::FailFast`. When the
+    /// map calls of all the documents fail, the run fails with the last error whatever the policy.
+    pub fn with_failure_policy(mut self, failure_policy: MapFailurePolicy) -> Self {
+        self.failure_policy = failure_policy;
+        self
+    }
+
+    /// Returns the number of calls to run at the same time out of `calls`.
+    fn concurrency(&self, calls: usize) -> usize {
+        self.max_concurrency.unwrap_or(calls).max(1)
     }
 
     /// Executes the map-reduce chain using the provided `Executor`.
@@ -73,6 +217,80 @@ impl<E: Executor> Chain<E> {
         base_parameters: Parameters,
         executor: &E,
     ) -> Result<E::Output, MapReduceChainError<E::Error>> {
+        self.run_with_trace(documents, base_parameters, executor)
+            .await
+            .map(|(output, _)| output)
+    }
+
+    /// Executes the map-reduce chain like `run`, then shuts down the executor whether or not the chain succeeded.
+    ///
+    /// An error from the chain takes precedence over an error from shutting down.
+    pub async fn run_and_shutdown(
+        &self,
+        documents: Vec<Parameters>,
+        base_parameters: Parameters,
+        executor: &E,
+    ) -> Result<E::Output, MapReduceChainError<E::Error>>
+    where
+        E: Sync,
+    {
+        let result = self.run(documents, base_parameters, executor).await;
+        let shutdown = executor.shutdown().await;
+        let output = result?;
+        shutdown.map_err(MapReduceChainError::Shutdown)?;
+        Ok(output)
+    }
+
+    /// Executes the map-reduce chain like `run`, and also returns a trace of the calls that were made.
+    pub async fn run_with_trace(
+        &self,
+        documents: Vec<Parameters>,
+        base_parameters: Parameters,
+        executor: &E,
+    ) -> Result<(E::Output, MapReduceTrace), MapReduceChainError<E::Error>> {
+        self.run_traced(
+            documents,
+            base_parameters,
+            executor,
+            &CancellationToken::new(),
+        )
+        .await
+    }
+
+    /// Executes the map-reduce chain like `run`, stopping when `token` is cancelled.
+    ///
+    /// The calls running when the token is cancelled are abandoned. A cancelled run returns
+    /// `MapReduceChainError::Cancelled` with the reason and the text outputs of the map calls that completed, or, once
+    /// reducing has started, the outputs of the last reduce round.
+    pub async fn run_with_cancellation(
+        &self,
+        documents: Vec<Parameters>,
+        base_parameters: Parameters,
+        executor: &E,
+        token: &CancellationToken,
+    ) -> Result<E::Output, MapReduceChainError<E::Error>> {
+        self.run_traced(documents, base_parameters, executor, token)
+            .await
+            .map(|(output, _)| output)
+    }
+
+    async fn run_traced(
+        &self,
+        documents: Vec<Parameters>,
+        base_parameters: Parameters,
+        executor: &E,
+        token: &CancellationToken,
+    ) -> Result<(E::Output, MapReduceTrace), MapReduceChainError<E::Error>> {
+        let cancelled = |reason: CancellationReason, partial_outputs: &[String]| {
+            MapReduceChainError::Cancelled(Cancelled {
+                reason,
+                partial_outputs: partial_outputs.to_vec(),
+            })
+        };
+        if let Some(reason) = token.reason() {
+            return Err(cancelled(reason, &[]));
+        }
+        let mut trace = MapReduceTrace::default();
         if documents.is_empty() {
             return Err(MapReduceChainError::InputEmpty);
         }
@@ -89,17 +307,49 @@ impl<E: Executor> Chain<E> {
         // Execute the `map` step for each document, combining the base parameters with each document's parameters.
         let chunked_docs_with_base_parameters: Vec<_> = chunked_docs
             .iter()
-            .map(|doc| base_parameters.combine(doc))
-            .collect();
-        let futures: Vec<_> = chunked_docs_with_base_parameters
-            .iter()
-            .map(|doc| map_frame.format_and_execute(doc))
+            .map(|(document, doc)| (*document, base_parameters.combine(doc)))
             .collect();
-        let mapped_documents = join_all(futures).await;
-        let mapped_documents = mapped_documents.into_iter().collect::<Result<_, _>>()?;
+        trace.map_calls = chunked_docs_with_base_parameters.len();
+        let mut mapped_documents = stream::iter(chunked_docs_with_base_parameters.iter().map(
+            |(document, doc)| {
+                let map_frame = &map_frame;
+                async move { (*document, map_frame.format_and_execute(doc).await) }
+            },
+        ))
+        .buffered(self.concurrency(trace.map_calls));
+        let mut texts = Vec::new();
+        let mut succeeded = 0;
+        let mut last_error = None;
+        while let Some((document, result)) = token
+            .run_until_cancelled(mapped_documents.next())
+            .await
+            .map_err(|reason| cancelled(reason, &texts))?
+        {
+            let error = match result {
+                Ok(output) => {
+                    succeeded += 1;
+                    texts.extend(output.primary_textual_output().await);
+                    continue;
+                }
+                Err(error) => error,
+            };
+            match &self.failure_policy {
+                MapFailurePolicy::FailFast => return Err(error.into()),
+                MapFailurePolicy::Skip => {}
+                MapFailurePolicy::Placeholder(placeholder) => texts.push(placeholder.clone()),
+            }
+            if !trace.failed_documents.contains(&document) {
+                trace.failed_documents.push(document);
+            }
+            last_error = Some(error);
+        }
+        // Placeholders alone are nothing to reduce.
+        if let (0, Some(error)) = (succeeded, last_error) {
+            return Err(error.into());
+        }
 
         let mut documents = self
-            .combine_documents_up_to(executor, mapped_documents, &base_parameters)
+            .combine_documents_up_to(executor, texts, &base_parameters, &mut trace)
             .await?;
 
         if documents.is_empty() {
@@ -111,59 +361,95 @@ impl<E: Executor> Chain<E> {
                 .iter()
                 .map(|doc| base_parameters.with_text(doc))
                 .collect();
-            let futures = tasks.iter().map(|p| reduce_frame.format_and_execute(p));
-            let new_docs = join_all(futures).await;
-            let new_docs = new_docs.into_iter().collect::<Result<Vec<_>, _>>()?;
-            let n_new_docs = new_docs.len();
-            if n_new_docs == 1 {
-                return Ok(new_docs[0].clone());
+            let reduced = stream::iter(tasks.iter().map(|p| reduce_frame.format_and_execute(p)))
+                .buffered(self.concurrency(tasks.len()))
+                .collect::<Vec<_>>();
+            let new_docs = token
+                .run_until_cancelled(reduced)
+                .await
+                .map_err(|reason| cancelled(reason, &documents))?;
+            let mut new_docs = new_docs.into_iter().collect::<Result<Vec<_>, _>>()?;
+            if new_docs.len() == 1 {
+                return Ok((new_docs.remove(0), trace));
+            }
+            let mut texts = Vec::with_capacity(new_docs.len());
+            for output in new_docs {
+                texts.extend(output.primary_textual_output().await);
             }
             documents = self
-                .combine_documents_up_to(executor, new_docs, &base_parameters)
+                .combine_documents_up_to(executor, texts, &base_parameters, &mut trace)
                 .await?;
         }
     }
 
+    /// Combines intermediate outputs into as few reduce inputs as fit the context window and records the round in
+    /// `trace`.
     async fn combine_documents_up_to(
         &self,
         executor: &E,
-        mut v: Vec<<E as Executor>::Output>,
+        texts: Vec<String>,
         parameters: &Parameters,
+        trace: &mut MapReduceTrace,
     ) -> Result<Vec<String>, MapReduceChainError<E::Error>> {
+        let empty_remaining = self.reduce_tokens_remaining(executor, parameters, "")?;
+        let sizes = texts
+            .iter()
+            .map(|text| {
+                let remaining = self.reduce_tokens_remaining(executor, parameters, text)?;
+                Ok((empty_remaining - remaining).max(0) as usize)
+            })
+            .collect::<Result<Vec<_>, PromptTokensError>>()?;
+        let capacity = (empty_remaining - 1).max(0) as usize;
+
+        // The sizes are estimates, so every packed input is checked against the real prompt size and split up
+        // further if it doesn't fit.
         let mut new_outputs = Vec::new();
-        while let Some(current) = v.pop() {
-            let mut current_doc = current.primary_textual_output().await.unwrap_or_default();
-            while let Some(next) = v.last() {
-                let next_doc = next.primary_textual_output().await;
-                if next_doc.is_none() {
-                    continue;
-                }
-                let mut new_doc = current_doc.clone();
-                new_doc.push('\n');
-                new_doc.push_str(&next.primary_textual_output().await.unwrap_or_default());
-
-                let params = parameters.with_text(new_doc.clone());
-                let prompt = self.reduce.format(&params)?;
-                let count = executor.tokens_used(self.reduce.options(), &prompt)?;
-                if count.has_tokens_remaining() {
-                    current_doc = new_doc;
-                    v.pop();
-                } else {
-                    break;
-                }
+        for bin in pack_by_size(&sizes, capacity, JOINER_TOKENS) {
+            let mut current: Option<String> = None;
+            for i in bin {
+                current = match current {
+                    None => Some(texts[i].clone()),
+                    Some(current_doc) => {
+                        let new_doc = format!("{}\n{}", current_doc, texts[i]);
+                        if self.reduce_tokens_remaining(executor, parameters, &new_doc)? > 0 {
+                            Some(new_doc)
+                        } else {
+                            new_outputs.push(current_doc);
+                            Some(texts[i].clone())
+                        }
+                    }
+                };
             }
-            new_outputs.push(current_doc);
+            new_outputs.extend(current);
         }
+
+        trace.reduce_rounds.push(ReduceRound {
+            inputs: texts.len(),
+            reduce_calls: new_outputs.len(),
+            unpacked_reduce_calls: count_sequential_bins(&sizes, capacity, JOINER_TOKENS),
+        });
         Ok(new_outputs)
     }
 
+    fn reduce_tokens_remaining(
+        &self,
+        executor: &E,
+        parameters: &Parameters,
+        text: &str,
+    ) -> Result<i32, PromptTokensError> {
+        let prompt = self.reduce.format(&parameters.with_text(text))?;
+        Ok(executor
+            .tokens_used(self.reduce.options(), &prompt)?
+            .tokens_remaining())
+    }
+
     fn chunk_documents<'a>(
         &self,
         v: Vec<Parameters>,
         base_parameters: Parameters,
         executor: &E,
         step: &Step<E>,
-    ) -> Result<Vec<Parameters>, PromptTokensError>
+    ) -> Result<Vec<(usize, Parameters)>, PromptTokensError>
     where
         E: Executor + 'a,
     {
@@ -177,7 +463,12 @@ impl<E: Executor> Chain<E> {
                 >>::split_to_fit(executor, step, x, &base_parameters, None)
             })
             .collect();
-        let data = data?.iter().flatten().cloned().collect();
+        // Every chunk is tagged with the index of the document it was split from.
+        let data = data?
+            .into_iter()
+            .enumerate()
+            .flat_map(|(i, chunks)| chunks.into_iter().map(move |chunk| (i, chunk)))
+            .collect();
         Ok(data)
     }
 }
@@ -232,7 +523,7 @@ impl<'de, E: Executor> serde::de::Visitor<'de> for ChainVisitor<E> {
         let map = map_field.ok_or_else(|| serde::de::Error::missing_field("map"))?;
         let reduce = reduce_field.ok_or_else(|| serde::de::Error::missing_field("reduce"))?;
 
-        Ok(Chain { map, reduce })
+        Ok(Chain::new(map, reduce))
     }
 }
 
@@ -268,3 +559,171 @@ where
         base
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        count_sequential_bins, pack_by_size, Chain, MapFailurePolicy, MapReduceChainError,
+    };
+    use crate::agents::mock::{MockExecutor, MockOutput};
+    use crate::cancellation::{CancellationReason, CancellationToken};
+    use crate::{prompt, step::Step, Parameters};
+    use futures::executor::block_on;
+    use std::sync::atomic::Ordering;
+
+    fn chain() -> Chain<MockExecutor> {
+        Chain::new(
+            Step::for_prompt_template(prompt!("Summarize: {{text}}")),
+            Step::for_prompt_template(prompt!("Combine: {{text}}")),
+        )
+    }
+
+    fn documents(texts: &[&str]) -> Vec<Parameters> {
+        texts
+            .iter()
+            .map(|text| Parameters::new_with_text(*text))
+            .collect()
+    }
+
+    fn last_prompt(executor: &MockExecutor) -> String {
+        executor.prompts.lock().unwrap().last().unwrap().to_string()
+    }
+
+    #[test]
+    fn packing_by_size_needs_fewer_bins_than_packing_in_order() {
+        let sizes = [7, 5, 3, 5];
+        assert_eq!(count_sequential_bins(&sizes, 10, 0), 3);
+        let bins = pack_by_size(&sizes, 10, 0);
+        assert_eq!(bins, vec![vec![0, 2], vec![1, 3]]);
+    }
+
+    #[test]
+    fn oversized_items_get_their_own_bin() {
+        let bins = pack_by_size(&[12, 1, 1], 10, 1);
+        assert_eq!(bins, vec![vec![0], vec![1, 2]]);
+    }
+
+    #[test]
+    fn cancelled_runs_stop_before_calling_the_model() {
+        let chain = Chain::new(
+            Step::for_prompt_template(prompt!("Summarize: {{text}}")),
+            Step::for_prompt_template(prompt!("Combine: {{text}}")),
+        );
+        let executor = MockExecutor::new(vec![MockOutput::text("summary")]);
+        let documents = vec![Parameters::new_with_text("a b c")];
+        let token = CancellationToken::new();
+        token.cancel(CancellationReason::UserAbort);
+        let result = block_on(chain.run_with_cancellation(
+            documents.clone(),
+            Parameters::new(),
+            &executor,
+            &token,
+        ));
+        assert!(matches!(
+            result,
+            Err(MapReduceChainError::Cancelled(cancelled))
+                if cancelled.reason == CancellationReason::UserAbort && cancelled.partial_outputs.is_empty()
+        ));
+        assert!(executor.prompts.lock().unwrap().is_empty());
+
+        let executor =
+            MockExecutor::new(vec![MockOutput::text("summary"), MockOutput::text("all")]);
+        let output = block_on(chain.run_with_cancellation(
+            documents,
+            Parameters::new(),
+            &executor,
+            &CancellationToken::new(),
+        ))
+        .unwrap();
+        assert_eq!(output.0.as_deref(), Some("all"));
+    }
+
+    #[test]
+    fn fails_fast_by_default() {
+        let executor = MockExecutor::new(vec![MockOutput::text("A"), MockOutput::text("G")])
+            .with_failing_prompt("beta");
+        let result = block_on(chain().run(
+            documents(&["alpha", "beta", "gamma"]),
+            Parameters::new(),
+            &executor,
+        ));
+        assert!(matches!(
+            result,
+            Err(MapReduceChainError::FormatAndExecuteError(_))
+        ));
+        assert!(!last_prompt(&executor).starts_with("Combine"));
+    }
+
+    #[test]
+    fn skips_the_documents_that_fail() {
+        let executor = MockExecutor::new(vec![
+            MockOutput::text("A"),
+            MockOutput::text("G"),
+            MockOutput::text("all"),
+        ])
+        .with_failing_prompt("beta");
+        let chain = chain().with_failure_policy(MapFailurePolicy::Skip);
+        let (output, trace) = block_on(chain.run_with_trace(
+            documents(&["alpha", "beta", "gamma"]),
+            Parameters::new(),
+            &executor,
+        ))
+        .unwrap();
+        assert_eq!(output.0.as_deref(), Some("all"));
+        assert_eq!(trace.failed_documents, vec![1]);
+        assert_eq!(last_prompt(&executor), "Combine: A\nG");
+    }
+
+    #[test]
+    fn reduces_placeholders_in_place_of_the_documents_that_fail() {
+        let executor = MockExecutor::new(vec![
+            MockOutput::text("A"),
+            MockOutput::text("G"),
+            MockOutput::text("all"),
+        ])
+        .with_failing_prompt("beta");
+        let chain = chain().with_failure_policy(MapFailurePolicy::Placeholder("(missing)".into()));
+        let (_, trace) = block_on(chain.run_with_trace(
+            documents(&["alpha", "beta", "gamma"]),
+            Parameters::new(),
+            &executor,
+        ))
+        .unwrap();
+        assert_eq!(trace.failed_documents, vec![1]);
+        assert_eq!(last_prompt(&executor), "Combine: A\n(missing)\nG");
+    }
+
+    #[test]
+    fn fails_when_every_map_call_fails_whatever_the_policy() {
+        for policy in [
+            MapFailurePolicy::Skip,
+            MapFailurePolicy::Placeholder("(missing)".into()),
+        ] {
+            let executor =
+                MockExecutor::new(vec![MockOutput::text("all")]).with_failing_prompt("Summarize");
+            let chain = chain().with_failure_policy(policy);
+            let result =
+                block_on(chain.run(documents(&["alpha", "beta"]), Parameters::new(), &executor));
+            assert!(matches!(
+                result,
+                Err(MapReduceChainError::FormatAndExecuteError(_))
+            ));
+            // Nothing was reduced.
+            assert_eq!(executor.prompts.lock().unwrap().len(), 2);
+        }
+    }
+
+    #[test]
+    fn limits_the_calls_running_at_once() {
+        let outputs = |n| (0..n).map(|_| MockOutput::text("A")).collect::<Vec<_>>();
+        let texts = ["a", "b", "c", "d"];
+        let executor = MockExecutor::new(outputs(5)).yielding();
+        block_on(chain().run(documents(&texts), Parameters::new(), &executor)).unwrap();
+        assert_eq!(executor.max_running.load(Ordering::SeqCst), 4);
+
+        let executor = MockExecutor::new(outputs(5)).yielding();
+        let chain = chain().with_max_concurrency(2);
+        block_on(chain.run(documents(&texts), Parameters::new(), &executor)).unwrap();
+        assert_eq!(executor.max_running.load(Ordering::SeqCst), 2);
+    }
+}
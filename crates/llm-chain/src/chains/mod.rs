@@ -8,8 +8,22 @@
 //! 1. **Sequential**: This chain type executes the steps one after another in a linear sequence. It's perfect for tasks that need a clear and simple order of execution.
 //! 2. **MapReduce**: This chain type follows the MapReduce paradigm, where the steps are divided into mapping and reducing phases. It's great for tasks that require parallel processing and data aggregation.
 //! 3. **Converstation**: This chain type models a conversation between the LLM and some other entity. It's great for tasks that require a back-and-forth between the LLM and the user.
+//! 4. **RAG**: This chain type retrieves documents relevant to a question and answers it from them, with citations. It's great for question answering over your own data.
+//! 5. **Router**: This chain type classifies the input and sends it to the best suited of several named sub-chains. It's great for handling different kinds of requests with specialized prompts.
+//! 6. **Graph**: This chain type runs steps in the order of the parameters they depend on, running independent steps concurrently. It's great for fan-out and fan-in workflows.
+//! 7. **Loop**: This chain type runs a sub-chain repeatedly, feeding each output back into it, until a stop condition holds or a budget is exhausted. It's great for iterative refinement and agent loops.
+//! 8. **Self-consistency**: This chain type samples the same step several times and keeps the answer given most often, or the one picked by an adjudication step. It's great for improving the accuracy of reasoning tasks.
+//! 9. **Tree of thoughts** (experimental): This chain type explores several lines of reasoning, scoring them with an evaluator and keeping the best at every step. It's great for problems that need search or backtracking.
+//! 10. **Refine**: This chain type goes through documents one at a time, refining a running answer with each of them. It's great for summarizing long texts without losing details.
 //! Stay tuned for more chain types, and feel free to contribute your own! 🎉
 
 pub mod conversation;
+pub mod graph;
+pub mod loop_;
 pub mod map_reduce;
+pub mod rag;
+pub mod refine;
+pub mod router;
+pub mod self_consistency;
 pub mod sequential;
+pub mod tree_of_thoughts;
@@ -0,0 +1,382 @@
+//! The `rag` module contains a `Chain` for retrieval-augmented question answering.
+//!
+//! A RAG chain retrieves the documents relevant to a question, formats them into a numbered list of sources, and
+//! asks the model to answer the question from those sources, citing them as `[1]`, `[2]`, ... The citations in the
+//! answer are then resolved back to the documents they refer to.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let chain = rag::Chain::from_vector_store(qdrant, embeddings, 4);
+//! let answer = chain.run("What is the capital of Sweden?".to_string(), &executor).await?;
+//! println!("{}", answer.text);
+//! for source in answer.cited_sources() {
+//!     println!("- {}", source.page_content);
+//! }
+//! ```
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+use crate::{
+    frame::{FormatAndExecuteError, Frame},
+    output::Output,
+    parameters, prompt,
+    prompt::{StringTemplate, StringTemplateError},
+    retrieval::{Retriever, VectorStoreRetriever},
+    schema::{Document, EmptyMetadata},
+    step::Step,
+    traits::{Embeddings, Executor, ExecutorError, VectorStore},
+    Parameters,
+};
+
+const DEFAULT_DOCUMENT_TEMPLATE: &str = "[{{index}}] {{content}}";
+const DEFAULT_DOCUMENT_SEPARATOR: &str = "\n\n";
+const DEFAULT_SYSTEM_PROMPT: &str = "You are a helpful assistant answering questions using only the sources provided by the user. Cite every source you use by its number in square brackets, like [1]. If the sources don't contain the answer, say that you don't know.";
+const DEFAULT_USER_PROMPT: &str = "Sources:\n\n{{context}}\n\nQuestion: {{question}}";
+
+/// The `RagChainError` enum represents errors that can occur when executing a RAG chain.
+#[derive(Error, Debug)]
+pub enum RagChainError<Err: ExecutorError, R: std::error::Error> {
+    #[error("Retrieval failed: {0}")]
+    Retrieval(R),
+    #[error("Error formatting documents: {0}")]
+    DocumentFormat(#[from] StringTemplateError),
+    #[error("FormatAndExecuteError: {0}")]
+    FormatAndExecuteError(#[from] FormatAndExecuteError<Err>),
+    #[error("The model returned no text")]
+    NoTextOutput,
+}
+
+/// Returns the structured description of a retrieved document given to prompt templates.
+///
+/// The value is an object with the `index` of the document (starting at 1), its `id`, `content`, `metadata` (as an
+/// object), the `source`, `page`, `start` and `end` of its provenance, and its relevance `score`. Fields that aren't
+/// known are `null`, so templates can test them with `{% if doc.page %}`.
+///
+/// ```
+/// use llm_chain::chains::rag::document_value;
+/// use llm_chain::schema::{Document, Provenance};
+///
+/// let doc: Document = Document::new("Stockholm".to_string()).with_provenance(Provenance::new("foo.pdf", 9).with_page(3));
+/// let value = document_value(1, &doc, Some(0.8));
+/// assert_eq!(value["source"], "foo.pdf");
+/// assert_eq!(value["page"], 3);
+/// assert!(value["metadata"].is_null());
+/// ```
+pub fn document_value<M: Serialize + DeserializeOwned>(
+    index: usize,
+    document: &Document<M>,
+    score: Option<f32>,
+) -> serde_json::Value {
+    let provenance = document.provenance.as_ref();
+    serde_json::json!({
+        "index": index,
+        "id": document.id,
+        "content": document.page_content,
+        "metadata": document
+            .metadata
+            .as_ref()
+            .and_then(|m| serde_json::to_value(m).ok()),
+        "source": provenance.map(|p| p.source_id.as_str()),
+        "page": provenance.and_then(|p| p.page),
+        "start": provenance.map(|p| p.start),
+        "end": provenance.map(|p| p.end),
+        "score": score,
+    })
+}
+
+/// Formats retrieved documents into the `context` parameter of the answering prompt.
+///
+/// Each document is rendered with a template that can use the `index` (starting at 1), `content` and `metadata`
+/// (serialized as JSON) parameters. Documents with a provenance also provide the `source`, `start` and `end`
+/// parameters, and `page` if it is known, and documents retrieved with a score provide `score`. The `document`
+/// parameter holds all of these as a structured value (see `document_value`), so the template can also access the
+/// metadata fields, e.g. `[source: {{document.metadata.title}} p.{{document.page}}]`. The rendered documents are
+/// joined with a separator.
+#[derive(Debug, Clone)]
+pub struct DocumentFormatter {
+    template: StringTemplate,
+    separator: String,
+}
+
+impl Default for DocumentFormatter {
+    fn default() -> Self {
+        Self {
+            template: StringTemplate::tera(DEFAULT_DOCUMENT_TEMPLATE),
+            separator: DEFAULT_DOCUMENT_SEPARATOR.to_string(),
+        }
+    }
+}
+
+impl DocumentFormatter {
+    /// Creates a formatter rendering each document with `template`.
+    pub fn new(template: StringTemplate) -> Self {
+        Self {
+            template,
+            ..Default::default()
+        }
+    }
+
+    /// Sets the separator placed between documents.
+    pub fn with_separator<S: Into<String>>(mut self, separator: S) -> Self {
+        self.separator = separator.into();
+        self
+    }
+
+    /// Formats `documents`, numbering them from 1.
+    pub fn format<M>(&self, documents: &[Document<M>]) -> Result<String, StringTemplateError>
+    where
+        M: Serialize + DeserializeOwned,
+    {
+        self.format_documents(documents.iter().map(|doc| (doc, None)))
+    }
+
+    /// Formats `documents` along with their relevance scores, numbering them from 1.
+    pub fn format_scored<M>(
+        &self,
+        documents: &[(Document<M>, Option<f32>)],
+    ) -> Result<String, StringTemplateError>
+    where
+        M: Serialize + DeserializeOwned,
+    {
+        self.format_documents(documents.iter().map(|(doc, score)| (doc, *score)))
+    }
+
+    fn format_documents<'a, M, I>(&self, documents: I) -> Result<String, StringTemplateError>
+    where
+        M: Serialize + DeserializeOwned + 'a,
+        I: Iterator<Item = (&'a Document<M>, Option<f32>)>,
+    {
+        let rendered = documents
+            .enumerate()
+            .map(|(i, (doc, score))| {
+                let metadata = doc
+                    .metadata
+                    .as_ref()
+                    .and_then(|m| serde_json::to_string(m).ok())
+                    .unwrap_or_default();
+                let mut parameters = parameters! {
+                    "index" => (i + 1).to_string(),
+                    "content" => doc.page_content.as_str(),
+                    "metadata" => metadata,
+                }
+                .with_value("document", document_value(i + 1, doc, score));
+                if let Some(provenance) = &doc.provenance {
+                    parameters = parameters
+                        .with("source", provenance.source_id.as_str())
+                        .with("start", provenance.start.to_string())
+                        .with("end", provenance.end.to_string());
+                    if let Some(page) = provenance.page {
+                        parameters = parameters.with("page", page.to_string());
+                    }
+                }
+                if let Some(score) = score {
+                    parameters = parameters.with("score", score.to_string());
+                }
+                self.template.format(&parameters)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rendered.join(&self.separator))
+    }
+}
+
+/// The answer produced by a RAG chain.
+#[derive(Debug, Clone)]
+pub struct Answer<O, M = EmptyMetadata>
+where
+    M: Serialize + DeserializeOwned,
+{
+    /// The raw output of the executor, or `None` if generation was skipped because no sources were found.
+    pub output: Option<O>,
+    /// The textual answer.
+    pub text: String,
+    /// The documents that were given to the model, in the order they were numbered.
+    pub sources: Vec<Document<M>>,
+    /// Indices into `sources` of the documents cited in the answer, in order of first citation.
+    pub citations: Vec<usize>,
+}
+
+impl<O, M> Answer<O, M>
+where
+    M: Serialize + DeserializeOwned,
+{
+    /// Returns the documents cited in the answer.
+    pub fn cited_sources(&self) -> impl Iterator<Item = &Document<M>> {
+        self.citations.iter().map(|&i| &self.sources[i])
+    }
+}
+
+/// Finds the citations like `[1]` or `[1, 3]` in `text` and returns them as indices into a list of `source_count`
+/// sources. Citations that don't refer to a source are ignored.
+pub fn parse_citations(text: &str, source_count: usize) -> Vec<usize> {
+    let mut citations = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('[') {
+        rest = &rest[start + 1..];
+        let Some(end) = rest.find(']') else {
+            break;
+        };
+        let numbers: Option<Vec<usize>> = rest[..end]
+            .split(',')
+            .map(|n| n.trim().parse().ok())
+            .collect();
+        for n in numbers.into_iter().flatten() {
+            if (1..=source_count).contains(&n) && !citations.contains(&(n - 1)) {
+                citations.push(n - 1);
+            }
+        }
+    }
+    citations
+}
+
+/// A retrieval-augmented question answering chain.
+///
+/// The answering step receives the `question` (also available as `text`) and the formatted `context` parameters,
+/// as well as `documents`, the list of the retrieved documents as structured values (see `document_value`). Prompts
+/// can loop over it to lay out the sources themselves, e.g.
+/// `{% for doc in documents %}[{{doc.index}}] {{doc.content}} [source: {{doc.source}} p.{{doc.page}}]{% endfor %}`.
+pub struct Chain<E, R, M = EmptyMetadata>
+where
+    E: Executor,
+    R: Retriever<M>,
+    M: Serialize + DeserializeOwned,
+{
+    retriever: R,
+    formatter: DocumentFormatter,
+    step: Step<E>,
+    no_sources_answer: Option<String>,
+    _marker: std::marker::PhantomData<fn() -> M>,
+}
+
+impl<E, R, M> Chain<E, R, M>
+where
+    E: Executor,
+    R: Retriever<M>,
+    M: Serialize + DeserializeOwned,
+{
+    /// Creates a chain answering questions from the documents found by `retriever`, using the default prompt and
+    /// document formatter.
+    pub fn new(retriever: R) -> Self {
+        Self {
+            retriever,
+            formatter: DocumentFormatter::default(),
+            step: Step::for_prompt_template(prompt!(DEFAULT_SYSTEM_PROMPT, DEFAULT_USER_PROMPT)),
+            no_sources_answer: None,
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Sets the formatter used to render the retrieved documents.
+    pub fn with_formatter(mut self, formatter: DocumentFormatter) -> Self {
+        self.formatter = formatter;
+        self
+    }
+
+    /// Sets the step answering the question. Its prompt should use the `question` and `context` parameters and ask
+    /// for citations in the `[n]` form.
+    pub fn with_step(mut self, step: Step<E>) -> Self {
+        self.step = step;
+        self
+    }
+
+    /// Answers with `answer` instead of invoking the model when the retriever finds no documents, e.g. because
+    /// none passed its score threshold.
+    pub fn with_no_sources_answer<S: Into<String>>(mut self, answer: S) -> Self {
+        self.no_sources_answer = Some(answer.into());
+        self
+    }
+
+    /// Returns the retriever used by this chain.
+    pub fn retriever(&self) -> &R {
+        &self.retriever
+    }
+
+    /// Answers `question` from the retrieved documents.
+    pub async fn run(
+        &self,
+        question: String,
+        executor: &E,
+    ) -> Result<Answer<E::Output, M>, RagChainError<E::Error, R::Error>> {
+        let scored = self
+            .retriever
+            .retrieve_with_scores(question.clone())
+            .await
+            .map_err(RagChainError::Retrieval)?;
+        if scored.is_empty() {
+            if let Some(text) = &self.no_sources_answer {
+                return Ok(Answer {
+                    output: None,
+                    text: text.clone(),
+                    sources: vec![],
+                    citations: vec![],
+                });
+            }
+        }
+        let context = self.formatter.format_scored(&scored)?;
+        let documents = scored
+            .iter()
+            .enumerate()
+            .map(|(i, (doc, score))| document_value(i + 1, doc, *score))
+            .collect();
+        let parameters = Parameters::new_with_text(question.clone())
+            .with("question", question)
+            .with("context", context)
+            .with_value("documents", serde_json::Value::Array(documents));
+        let sources: Vec<Document<M>> = scored.into_iter().map(|(doc, _)| doc).collect();
+        let output = Frame::new(executor, &self.step)
+            .format_and_execute(&parameters)
+            .await?;
+        let text = output
+            .primary_textual_output()
+            .await
+            .ok_or(RagChainError::NoTextOutput)?;
+        let citations = parse_citations(&text, sources.len());
+        Ok(Answer {
+            output: Some(output),
+            text,
+            sources,
+            citations,
+        })
+    }
+}
+
+impl<E, Emb, V, M> Chain<E, VectorStoreRetriever<Emb, M, V>, M>
+where
+    E: Executor,
+    Emb: Embeddings + Send + Sync,
+    V: VectorStore<Emb, M> + Send + Sync,
+    V::Error: Send,
+    M: Serialize + DeserializeOwned + Send + Sync,
+{
+    /// Creates a chain retrieving the `limit` documents most similar to the question from `store`.
+    pub fn from_vector_store(store: V, embeddings: Emb, limit: u32) -> Self {
+        Self::new(VectorStoreRetriever::new(store, embeddings, limit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_citations, DocumentFormatter};
+    use crate::prompt::StringTemplate;
+    use crate::schema::{Document, Provenance};
+
+    #[test]
+    fn parses_single_and_grouped_citations() {
+        let text = "Stockholm [2]. It has been the capital since 1634 [1, 2] [7] [see above].";
+        assert_eq!(parse_citations(text, 3), vec![1, 0]);
+    }
+
+    #[test]
+    fn exposes_document_metadata_to_templates() {
+        let doc = Document::new("Stockholm is the capital.".to_string())
+            .with_metadata(serde_json::json!({"title": "Sweden"}))
+            .with_provenance(Provenance::new("foo.pdf", 25).with_page(3));
+        let formatter = DocumentFormatter::new(StringTemplate::tera(
+            "[{{index}}] {{content}} [source: {{document.metadata.title}}, {{source}} p.{{page}}, score {{document.score}}]",
+        ));
+        assert_eq!(
+            formatter.format_scored(&[(doc, Some(0.5))]).unwrap(),
+            "[1] Stockholm is the capital. [source: Sweden, foo.pdf p.3, score 0.5]"
+        );
+    }
+}
@@ -0,0 +1,317 @@
+//! The `refine` module contains the `Chain` struct, which represents a refine chain.
+//!
+//! A refine chain processes documents one at a time. The `initial` step answers from the first document, and the
+//! `refine` step then receives the running answer along with each following document, and improves the answer with
+//! what the document adds. Unlike a map-reduce chain, which summarizes documents independently and then
+//! concatenates the summaries, every call sees the full text of a document and the whole answer so far, so details
+//! are less likely to be lost. The calls can't run concurrently, though.
+//!
+//! Documents too long to fit in a prompt along with the answer are split, and their parts refined one after
+//! another.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let chain = refine::Chain::new(
+//!     Step::for_prompt_template(prompt!("Summarize this text:\n\n{{text}}")),
+//!     Step::for_prompt_template(prompt!(
+//!         "Here is a summary:\n\n{{existing_answer}}\n\nRefine it with this additional text, keeping the summary unchanged if the text adds nothing:\n\n{{text}}"
+//!     )),
+//! );
+//! let output = chain.run(documents, parameters!(), &executor).await?;
+//! ```
+
+use std::collections::VecDeque;
+
+use crate::{
+    frame::Frame,
+    output::Output,
+    serialization::StorableEntity,
+    step::Step,
+    tokens::{ExecutorTokenCountExt, PromptTokensError},
+    traits::{Executor, ExecutorError},
+    Parameters,
+};
+use serde::de::{Deserializer, MapAccess};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use serde::Deserialize;
+
+use thiserror::Error;
+
+/// The parameter holding the running answer in the prompt of the `refine` step.
+pub const EXISTING_ANSWER_KEY: &str = "existing_answer";
+
+/// The `RefineChainError` enum represents errors that can occur when executing a refine chain.
+#[derive(Error, Debug)]
+pub enum RefineChainError<Err: ExecutorError> {
+    /// An error relating to the operation of the Executor.
+    #[error("FormatAndExecuteError: {0}")]
+    FormatAndExecuteError(#[from] crate::frame::FormatAndExecuteError<Err>),
+    /// An error relating to tokenizing the inputs.
+    #[error("TokenizeError: {0}")]
+    TokenizeError(#[from] PromptTokensError),
+    #[error("The vector of input documents was empty")]
+    InputEmpty,
+    #[error("The model returned no text")]
+    NoTextOutput,
+}
+
+/// The `Chain` struct represents a refine chain, consisting of an `initial` step and a `refine` step.
+pub struct Chain<E: Executor> {
+    initial: Step<E>,
+    refine: Step<E>,
+}
+
+impl<E: Executor> Chain<E> {
+    /// Constructs a new `Chain` answering from the first document with `initial`, and refining the answer with every
+    /// following document with `refine`. The prompt of `refine` receives the answer so far in `existing_answer`.
+    pub fn new(initial: Step<E>, refine: Step<E>) -> Chain<E> {
+        Chain { initial, refine }
+    }
+
+    /// Executes the refine chain using the provided `Executor`.
+    ///
+    /// Every document's parameters are combined with `base_parameters`; the text of the document is expected in the
+    /// `text` parameter. Returns the output of the last call.
+    pub async fn run(
+        &self,
+        documents: Vec<Parameters>,
+        base_parameters: Parameters,
+        executor: &E,
+    ) -> Result<E::Output, RefineChainError<E::Error>> {
+        self.run_with_answers(documents, base_parameters, executor)
+            .await
+            .map(|(output, _)| output)
+    }
+
+    /// Executes the refine chain like `run`, and also returns the answer after every call, in order.
+    pub async fn run_with_answers(
+        &self,
+        documents: Vec<Parameters>,
+        base_parameters: Parameters,
+        executor: &E,
+    ) -> Result<(E::Output, Vec<String>), RefineChainError<E::Error>> {
+        if documents.is_empty() {
+            return Err(RefineChainError::InputEmpty);
+        }
+        let mut pending: VecDeque<Parameters> = documents.into();
+        let mut answers: Vec<String> = Vec::new();
+        let mut output = None;
+        while let Some(document) = pending.pop_front() {
+            let (step, parameters) = match answers.last() {
+                None => (&self.initial, base_parameters.clone()),
+                Some(answer) => (
+                    &self.refine,
+                    base_parameters.with(EXISTING_ANSWER_KEY, answer.as_str()),
+                ),
+            };
+            let mut parts = self
+                .fit(step, &document, &parameters, executor)?
+                .into_iter();
+            let Some(part) = parts.next() else {
+                continue;
+            };
+            // The other parts are fitted again once the answer has changed.
+            for rest in parts.rev() {
+                pending.push_front(rest);
+            }
+            output = Some(
+                self.call(step, &parameters.combine(&part), executor, &mut answers)
+                    .await?,
+            );
+        }
+        let output = output.ok_or(RefineChainError::InputEmpty)?;
+        Ok((output, answers))
+    }
+
+    /// Runs `step` and records the text of its output in `answers`.
+    async fn call(
+        &self,
+        step: &Step<E>,
+        parameters: &Parameters,
+        executor: &E,
+        answers: &mut Vec<String>,
+    ) -> Result<E::Output, RefineChainError<E::Error>> {
+        let output = Frame::new(executor, step)
+            .format_and_execute(parameters)
+            .await?;
+        let answer = output
+            .primary_textual_output()
+            .await
+            .ok_or(RefineChainError::NoTextOutput)?;
+        answers.push(answer);
+        Ok(output)
+    }
+
+    /// Splits `document` into parts that fit in the prompt of `step` formatted with `base_parameters`.
+    fn fit<'a>(
+        &self,
+        step: &Step<E>,
+        document: &Parameters,
+        base_parameters: &Parameters,
+        executor: &E,
+    ) -> Result<Vec<Parameters>, PromptTokensError>
+    where
+        E: 'a,
+    {
+        let parts =
+            <E as ExecutorTokenCountExt<E::Output, E::Token, E::StepTokenizer<'a>>>::split_to_fit(
+                executor,
+                step,
+                document,
+                base_parameters,
+                None,
+            )?;
+        // Splitting only keeps the text, so the other parameters of the document are added back to every part.
+        Ok(parts.iter().map(|part| document.combine(part)).collect())
+    }
+}
+
+// Your custom Serialize implementation for Chain
+impl<E: Executor> Serialize for Chain<E> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut s = serializer.serialize_struct("Chain", 2)?;
+        s.serialize_field("initial", &self.initial)?;
+        s.serialize_field("refine", &self.refine)?;
+        s.end()
+    }
+}
+
+struct ChainVisitor<E: Executor>(std::marker::PhantomData<E>);
+
+impl<'de, E: Executor> serde::de::Visitor<'de> for ChainVisitor<E> {
+    type Value = Chain<E>;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a struct containing initial and refine fields")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut initial: Option<Step<E>> = None;
+        let mut refine: Option<Step<E>> = None;
+
+        while let Some(key) = map.next_key()? {
+            match key {
+                "initial" => {
+                    if initial.is_some() {
+                        return Err(serde::de::Error::duplicate_field("initial"));
+                    }
+                    initial = Some(map.next_value()?);
+                }
+                "refine" => {
+                    if refine.is_some() {
+                        return Err(serde::de::Error::duplicate_field("refine"));
+                    }
+                    refine = Some(map.next_value()?);
+                }
+                _ => return Err(serde::de::Error::unknown_field(key, FIELDS)),
+            }
+        }
+
+        let initial = initial.ok_or_else(|| serde::de::Error::missing_field("initial"))?;
+        let refine = refine.ok_or_else(|| serde::de::Error::missing_field("refine"))?;
+        Ok(Chain::new(initial, refine))
+    }
+}
+
+impl<'de, E: Executor> Deserialize<'de> for Chain<E> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_struct("Chain", FIELDS, ChainVisitor(std::marker::PhantomData))
+    }
+}
+
+const FIELDS: &[&str] = &["initial", "refine"];
+
+impl<E: Executor> StorableEntity for Chain<E> {
+    fn get_metadata() -> Vec<(String, String)> {
+        vec![(
+            "chain-type".to_string(),
+            "llm-chain::chains::refine::Chain".to_string(),
+        )]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Chain, RefineChainError};
+    use crate::agents::mock::{MockExecutor, MockOutput};
+    use crate::{prompt, step::Step, Parameters};
+    use futures::executor::block_on;
+
+    fn chain() -> Chain<MockExecutor> {
+        Chain::new(
+            Step::for_prompt_template(prompt!("Summarize: {{text}}")),
+            Step::for_prompt_template(prompt!("Refine {{existing_answer}} with {{text}}")),
+        )
+    }
+
+    fn prompts(executor: &MockExecutor) -> Vec<String> {
+        executor
+            .prompts
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|prompt| prompt.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn refines_the_answer_with_every_document() {
+        let executor = MockExecutor::new(vec![
+            MockOutput::text("cats"),
+            MockOutput::text("cats and dogs"),
+            MockOutput::text("cats, dogs and birds"),
+        ]);
+        let documents = ["Cats purr", "Dogs bark", "Birds sing"]
+            .into_iter()
+            .map(Parameters::new_with_text)
+            .collect();
+        let (output, answers) =
+            block_on(chain().run_with_answers(documents, Parameters::new(), &executor)).unwrap();
+        assert_eq!(output.0.as_deref(), Some("cats, dogs and birds"));
+        assert_eq!(
+            answers,
+            vec!["cats", "cats and dogs", "cats, dogs and birds"]
+        );
+        assert_eq!(
+            prompts(&executor),
+            vec![
+                "Summarize: Cats purr",
+                "Refine cats with Dogs bark",
+                "Refine cats and dogs with Birds sing"
+            ]
+        );
+    }
+
+    #[test]
+    fn refines_the_parts_of_long_documents_in_turn() {
+        let mut executor =
+            MockExecutor::new(vec![MockOutput::text("one"), MockOutput::text("two")]);
+        // Only five words of the document fit in the prompt.
+        executor.max_tokens = 6;
+        let documents = vec![Parameters::new_with_text("a b c d e f g h")];
+        let (_, answers) =
+            block_on(chain().run_with_answers(documents, Parameters::new(), &executor)).unwrap();
+        assert_eq!(
+            prompts(&executor),
+            vec!["Summarize: a b c d e", "Refine one with f g h"]
+        );
+        assert_eq!(answers, vec!["one", "two"]);
+    }
+
+    #[test]
+    fn fails_without_documents() {
+        let executor = MockExecutor::new(vec![]);
+        let result = block_on(chain().run(vec![], Parameters::new(), &executor));
+        assert!(matches!(result, Err(RefineChainError::InputEmpty)));
+    }
+}
@@ -0,0 +1,292 @@
+//! The `router` module contains a `Chain` that dispatches its input to one of several sub-chains.
+//!
+//! A router chain has named routes, each with a description and a sequential chain handling the inputs sent to it.
+//! The input is classified first, either by asking the model which route fits it best or with a closure, and then
+//! the chain of the chosen route is run with the input. The output tells which route was taken.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let chain = router::Chain::new()
+//!     .with_route("math", "Questions about mathematics", math_chain)
+//!     .with_route("history", "Questions about historical events", history_chain)
+//!     .with_default_route("history");
+//! let output = chain.run(parameters!("What is 2 + 2?"), &executor).await?;
+//! println!("{}: {:?}", output.route, output.output.primary_textual_output().await);
+//! ```
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use super::sequential::{self, SequentialChainError};
+use crate::{
+    frame::{FormatAndExecuteError, Frame},
+    output::Output,
+    prompt,
+    step::Step,
+    traits::{Executor, ExecutorError},
+    Parameters,
+};
+
+const DEFAULT_SYSTEM_PROMPT: &str = "You are a classifier routing inputs to the destination best suited to handle them. Answer with the name of one destination only, without any explanation.";
+const DEFAULT_USER_PROMPT: &str = "Destinations:\n\n{{routes}}\n\nInput: {{text}}\n\nDestination:";
+
+/// The `RouterChainError` enum represents errors that can occur when executing a router chain.
+#[derive(Error, Debug)]
+pub enum RouterChainError<Err: ExecutorError> {
+    #[error("Error classifying the input: {0}")]
+    Classification(#[from] FormatAndExecuteError<Err>),
+    #[error("The classifier returned no text")]
+    NoTextOutput,
+    #[error("The classifier chose no known route: {0:?}")]
+    NoRoute(String),
+    #[error("The chain of route {route} failed: {source}")]
+    Route {
+        route: String,
+        #[source]
+        source: SequentialChainError<Err>,
+    },
+}
+
+/// A closure choosing the route of an input by its name, or returning `None` when no route fits.
+pub type RouteSelector = Arc<dyn Fn(&Parameters) -> Option<String> + Send + Sync>;
+
+/// How a router chain chooses the route of an input.
+pub enum Classifier<E: Executor> {
+    /// Asks the model with a step whose prompt receives the input parameters and `routes`, the list of the route
+    /// names and descriptions, one per line. The answer is matched against the route names with `parse_route`.
+    Step(Step<E>),
+    /// Chooses the route with a closure.
+    Selector(RouteSelector),
+}
+
+impl<E: Executor> Default for Classifier<E> {
+    fn default() -> Self {
+        Classifier::Step(Step::for_prompt_template(prompt!(
+            DEFAULT_SYSTEM_PROMPT,
+            DEFAULT_USER_PROMPT
+        )))
+    }
+}
+
+struct Route<E: Executor> {
+    name: String,
+    description: String,
+    chain: sequential::Chain<E>,
+}
+
+/// The output of a router chain.
+#[derive(Debug, Clone)]
+pub struct RouterOutput<O> {
+    /// The name of the route the input was sent to.
+    pub route: String,
+    /// The output of the chain of that route.
+    pub output: O,
+}
+
+/// Finds the route chosen in the answer of a classifier, among `routes`.
+///
+/// An answer that is a route name, ignoring case, surrounding whitespace and punctuation, chooses that route.
+/// Otherwise the first route name found as a word in the answer is chosen, so that answers like
+/// "The best destination is math." still work.
+///
+/// ```
+/// use llm_chain::chains::router::parse_route;
+/// let routes = ["math", "history"];
+/// assert_eq!(parse_route("History", &routes), Some("history"));
+/// assert_eq!(parse_route("It's a math question.", &routes), Some("math"));
+/// assert_eq!(parse_route("mathematics", &routes), None);
+/// ```
+pub fn parse_route<'a, S: AsRef<str>>(answer: &str, routes: &'a [S]) -> Option<&'a str> {
+    let is_punctuation = |c: char| !c.is_alphanumeric() && c != '_' && c != '-';
+    let trimmed = answer.trim().trim_matches(is_punctuation);
+    if let Some(route) = routes
+        .iter()
+        .find(|route| route.as_ref().eq_ignore_ascii_case(trimmed))
+    {
+        return Some(route.as_ref());
+    }
+    let is_boundary = |c: Option<char>| match c {
+        Some(c) => is_punctuation(c),
+        None => true,
+    };
+    let answer = answer.to_lowercase();
+    routes
+        .iter()
+        .filter_map(|route| {
+            let name = route.as_ref().to_lowercase();
+            answer
+                .match_indices(&name)
+                .find(|&(i, _)| {
+                    let before = answer[..i].chars().next_back();
+                    let after = answer[i + name.len()..].chars().next();
+                    // The name must not be part of a longer word.
+                    is_boundary(before) && is_boundary(after)
+                })
+                .map(|(i, _)| (i, route.as_ref()))
+        })
+        .min_by_key(|&(i, _)| i)
+        .map(|(_, route)| route)
+}
+
+/// A chain sending its input to the sub-chain best suited to handle it.
+///
+/// Each route has a name, a description used by the default classifier, and a sequential chain that is run with the
+/// input parameters when the route is chosen. Inputs that don't fit any route go to the default route if there is
+/// one, and fail with `RouterChainError::NoRoute` otherwise.
+pub struct Chain<E: Executor> {
+    classifier: Classifier<E>,
+    routes: Vec<Route<E>>,
+    default_route: Option<String>,
+}
+
+impl<E: Executor> Default for Chain<E> {
+    fn default() -> Self {
+        Self {
+            classifier: Classifier::default(),
+            routes: Vec::new(),
+            default_route: None,
+        }
+    }
+}
+
+impl<E: Executor> Chain<E> {
+    /// Creates a router without routes, classifying inputs with the model and a default prompt.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a route named `name`, running `chain` for the inputs that fit `description`. A route with the same name
+    /// is replaced.
+    pub fn with_route<N: Into<String>, D: Into<String>>(
+        mut self,
+        name: N,
+        description: D,
+        chain: sequential::Chain<E>,
+    ) -> Self {
+        let route = Route {
+            name: name.into(),
+            description: description.into(),
+            chain,
+        };
+        self.routes.retain(|r| r.name != route.name);
+        self.routes.push(route);
+        self
+    }
+
+    /// Sends the inputs that don't fit any route to the route named `name`.
+    pub fn with_default_route<N: Into<String>>(mut self, name: N) -> Self {
+        self.default_route = Some(name.into());
+        self
+    }
+
+    /// Classifies inputs with `step` instead of the default prompt. Its prompt receives the input parameters and
+    /// `routes`, the list of the route names and descriptions, and should ask for the name of one route.
+    pub fn with_classifier_step(mut self, step: Step<E>) -> Self {
+        self.classifier = Classifier::Step(step);
+        self
+    }
+
+    /// Classifies inputs with `selector` instead of the model.
+    pub fn with_selector<F>(mut self, selector: F) -> Self
+    where
+        F: Fn(&Parameters) -> Option<String> + Send + Sync + 'static,
+    {
+        self.classifier = Classifier::Selector(Arc::new(selector));
+        self
+    }
+
+    /// Returns the names of the routes, in the order they were added.
+    pub fn route_names(&self) -> impl Iterator<Item = &str> {
+        self.routes.iter().map(|route| route.name.as_str())
+    }
+
+    /// Returns the name of the route `parameters` should be sent to.
+    pub async fn classify(
+        &self,
+        parameters: &Parameters,
+        executor: &E,
+    ) -> Result<String, RouterChainError<E::Error>> {
+        let names: Vec<&str> = self.route_names().collect();
+        let (answer, chosen) = match &self.classifier {
+            Classifier::Step(step) => {
+                let routes = self
+                    .routes
+                    .iter()
+                    .map(|route| format!("{}: {}", route.name, route.description))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                let output = Frame::new(executor, step)
+                    .format_and_execute(&parameters.with("routes", routes))
+                    .await?;
+                let answer = output
+                    .primary_textual_output()
+                    .await
+                    .ok_or(RouterChainError::NoTextOutput)?;
+                let chosen = parse_route(&answer, &names).map(str::to_string);
+                (answer, chosen)
+            }
+            Classifier::Selector(selector) => {
+                let answer = selector(parameters).unwrap_or_default();
+                let chosen = names
+                    .iter()
+                    .find(|&&name| name == answer)
+                    .map(|name| name.to_string());
+                (answer, chosen)
+            }
+        };
+        chosen
+            .or_else(|| {
+                self.default_route
+                    .clone()
+                    .filter(|name| names.contains(&name.as_str()))
+            })
+            .ok_or(RouterChainError::NoRoute(answer))
+    }
+
+    /// Classifies `parameters` and runs the chain of the chosen route with them.
+    pub async fn run(
+        &self,
+        parameters: Parameters,
+        executor: &E,
+    ) -> Result<RouterOutput<E::Output>, RouterChainError<E::Error>> {
+        let name = self.classify(&parameters, executor).await?;
+        let route = self
+            .routes
+            .iter()
+            .find(|route| route.name == name)
+            .expect("classify only returns names of routes");
+        let output = route
+            .chain
+            .run(parameters, executor)
+            .await
+            .map_err(|source| RouterChainError::Route {
+                route: name.clone(),
+                source,
+            })?;
+        Ok(RouterOutput {
+            route: name,
+            output,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_route;
+
+    #[test]
+    fn matches_route_names_in_answers() {
+        let routes = ["math", "world-history", "physics"];
+        assert_eq!(
+            parse_route("  World-History.\n", &routes),
+            Some("world-history")
+        );
+        assert_eq!(
+            parse_route("Physics, or maybe math", &routes),
+            Some("physics")
+        );
+        assert_eq!(parse_route("aftermath", &routes), None);
+        assert_eq!(parse_route("", &routes), None);
+    }
+}
@@ -0,0 +1,248 @@
+//! The `self_consistency` module contains a `Chain` that samples several answers and keeps the most consistent one.
+//!
+//! Self-consistency runs the same step several times with a nonzero temperature, so that the model takes different
+//! reasoning paths, and aggregates the answers. Answers are aggregated by majority vote over their final answers,
+//! or by an adjudication step asking the model to pick the best answer. This improves the accuracy of reasoning
+//! tasks such as arithmetic and multi-step questions.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let chain = self_consistency::Chain::from_prompt(
+//!     prompt!("Think step by step, then give the final answer on a line starting with 'Answer:'.\n\n{{text}}"),
+//!     5,
+//! );
+//! let output = chain.run(parameters!(question), &executor).await?;
+//! println!("{} ({} of 5 votes)", output.answer, output.votes[0].1);
+//! ```
+use std::sync::Arc;
+
+use futures::future::try_join_all;
+use thiserror::Error;
+
+use crate::{
+    frame::{FormatAndExecuteError, Frame},
+    options::{FromPreset, Preset},
+    output::Output,
+    prompt::PromptTemplate,
+    step::Step,
+    traits::{Executor, ExecutorError},
+    Parameters,
+};
+
+/// The `SelfConsistencyError` enum represents errors that can occur when executing a self-consistency chain.
+#[derive(Error, Debug)]
+pub enum SelfConsistencyError<Err: ExecutorError> {
+    #[error("FormatAndExecuteError: {0}")]
+    FormatAndExecuteError(#[from] FormatAndExecuteError<Err>),
+    #[error("The model returned no text")]
+    NoTextOutput,
+    #[error("No sample contained an answer")]
+    NoAnswer,
+}
+
+/// A closure extracting the final answer from the text of a sample, or returning `None` if it has none.
+pub type AnswerExtractor = Arc<dyn Fn(&str) -> Option<String> + Send + Sync>;
+
+/// How a self-consistency chain aggregates the sampled answers.
+pub enum Aggregation<E: Executor> {
+    /// Picks the final answer given by the most samples. Ties go to the answer given first.
+    MajorityVote,
+    /// Asks the model to pick or synthesize the best answer with a step whose prompt receives the chain's
+    /// parameters and `answers`, the texts of the samples numbered from 1. Its output is the answer.
+    Adjudicator(Step<E>),
+}
+
+/// Returns the final answer of a sample: what follows the last `Answer:` if there is one, and its last non-empty
+/// line otherwise.
+///
+/// ```
+/// use llm_chain::chains::self_consistency::extract_answer;
+/// assert_eq!(extract_answer("3 + 4 = 7, so\nAnswer: 7").as_deref(), Some("7"));
+/// assert_eq!(extract_answer("Let me think.\n\nIt's 7.\n").as_deref(), Some("It's 7."));
+/// ```
+pub fn extract_answer(text: &str) -> Option<String> {
+    // ASCII lowercasing keeps the byte offsets of `text`.
+    let lowercase = text.to_ascii_lowercase();
+    let answer = match lowercase.rfind("answer:") {
+        Some(i) => text[i + "answer:".len()..].lines().next().unwrap_or(""),
+        None => text.lines().rev().find(|line| !line.trim().is_empty())?,
+    };
+    Some(answer.trim().to_string()).filter(|answer| !answer.is_empty())
+}
+
+/// Returns the key answers are compared by: lowercase, with whitespace collapsed and without trailing punctuation.
+fn vote_key(answer: &str) -> String {
+    answer
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_end_matches(['.', '!', '?', ','])
+        .to_lowercase()
+}
+
+/// Counts the votes for every distinct answer, most votes first. Answers differing only by case, whitespace or
+/// trailing punctuation count as the same, and are represented by the first of them. Ties keep the order of the
+/// answers.
+pub fn majority_vote<S: AsRef<str>>(answers: &[S]) -> Vec<(String, usize)> {
+    let mut tallies: Vec<(String, String, usize)> = Vec::new();
+    for answer in answers {
+        let key = vote_key(answer.as_ref());
+        match tallies.iter_mut().find(|(k, _, _)| *k == key) {
+            Some((_, _, count)) => *count += 1,
+            None => tallies.push((key, answer.as_ref().to_string(), 1)),
+        }
+    }
+    // The sort is stable, so ties stay in the order the answers were first given.
+    tallies.sort_by_key(|(_, _, count)| std::cmp::Reverse(*count));
+    tallies
+        .into_iter()
+        .map(|(_, answer, count)| (answer, count))
+        .collect()
+}
+
+/// The output of a self-consistency chain.
+#[derive(Debug, Clone)]
+pub struct SelfConsistencyOutput<O> {
+    /// The aggregated answer.
+    pub answer: String,
+    /// The texts of the samples, in the order they were requested.
+    pub samples: Vec<String>,
+    /// The number of samples giving each final answer, most votes first.
+    pub votes: Vec<(String, usize)>,
+    /// The output of the adjudication step, if the answers were adjudicated.
+    pub adjudication: Option<O>,
+}
+
+/// A chain sampling a step several times and aggregating the answers.
+///
+/// The samples run concurrently. The step should sample with a nonzero temperature, or every sample will be the
+/// same; `from_prompt` creates a step with the options of the `Creative` preset.
+pub struct Chain<E: Executor> {
+    step: Step<E>,
+    samples: usize,
+    aggregation: Aggregation<E>,
+    extractor: AnswerExtractor,
+}
+
+impl<E: Executor> Chain<E> {
+    /// Creates a chain running `step` `samples` times and picking the answer by majority vote.
+    pub fn new(step: Step<E>, samples: usize) -> Self {
+        Self {
+            step,
+            samples: samples.max(1),
+            aggregation: Aggregation::MajorityVote,
+            extractor: Arc::new(extract_answer),
+        }
+    }
+
+    /// Creates a chain sampling `prompt` `samples` times with the options of the `Creative` preset.
+    pub fn from_prompt(prompt: PromptTemplate, samples: usize) -> Self
+    where
+        E::PerInvocationOptions: FromPreset,
+    {
+        let options = E::PerInvocationOptions::preset(Preset::Creative);
+        Self::new(Step::for_prompt_and_options(prompt, options), samples)
+    }
+
+    /// Sets how the answers are aggregated. Defaults to `Aggregation::MajorityVote`.
+    pub fn with_aggregation(mut self, aggregation: Aggregation<E>) -> Self {
+        self.aggregation = aggregation;
+        self
+    }
+
+    /// Sets how the final answer is extracted from a sample for the majority vote. Defaults to `extract_answer`.
+    pub fn with_answer_extractor<F>(mut self, extractor: F) -> Self
+    where
+        F: Fn(&str) -> Option<String> + Send + Sync + 'static,
+    {
+        self.extractor = Arc::new(extractor);
+        self
+    }
+
+    /// Samples the step with `parameters` and aggregates the answers.
+    pub async fn run(
+        &self,
+        parameters: Parameters,
+        executor: &E,
+    ) -> Result<SelfConsistencyOutput<E::Output>, SelfConsistencyError<E::Error>> {
+        let frame = Frame::new(executor, &self.step);
+        let outputs =
+            try_join_all((0..self.samples).map(|_| frame.format_and_execute(&parameters))).await?;
+        let mut samples = Vec::with_capacity(outputs.len());
+        for output in &outputs {
+            samples.push(
+                output
+                    .primary_textual_output()
+                    .await
+                    .ok_or(SelfConsistencyError::NoTextOutput)?,
+            );
+        }
+        let answers: Vec<String> = samples
+            .iter()
+            .filter_map(|sample| (self.extractor)(sample))
+            .collect();
+        let votes = majority_vote(&answers);
+
+        match &self.aggregation {
+            Aggregation::MajorityVote => {
+                let answer = votes
+                    .first()
+                    .map(|(answer, _)| answer.clone())
+                    .ok_or(SelfConsistencyError::NoAnswer)?;
+                Ok(SelfConsistencyOutput {
+                    answer,
+                    samples,
+                    votes,
+                    adjudication: None,
+                })
+            }
+            Aggregation::Adjudicator(step) => {
+                let numbered = samples
+                    .iter()
+                    .enumerate()
+                    .map(|(i, sample)| format!("{}. {}", i + 1, sample.trim()))
+                    .collect::<Vec<_>>()
+                    .join("\n\n");
+                let output = Frame::new(executor, step)
+                    .format_and_execute(&parameters.with("answers", numbered))
+                    .await?;
+                let answer = output
+                    .primary_textual_output()
+                    .await
+                    .ok_or(SelfConsistencyError::NoTextOutput)?;
+                Ok(SelfConsistencyOutput {
+                    answer,
+                    samples,
+                    votes,
+                    adjudication: Some(output),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extract_answer, majority_vote};
+
+    #[test]
+    fn votes_for_the_most_common_final_answer() {
+        let samples = [
+            "6 * 7 = 42.\nAnswer: 42",
+            "Six sevens are 41.\nAnswer: 41",
+            "It's 6 times 7.\nANSWER:  42.",
+            "I'm not sure.",
+        ];
+        let answers: Vec<String> = samples.iter().filter_map(|s| extract_answer(s)).collect();
+        assert_eq!(answers, ["42", "41", "42.", "I'm not sure."]);
+        assert_eq!(
+            majority_vote(&answers),
+            vec![
+                ("42".to_string(), 2),
+                ("41".to_string(), 1),
+                ("I'm not sure.".to_string(), 1)
+            ]
+        );
+    }
+}
@@ -24,16 +24,57 @@
 //! let result = chain.run(parameters, &executor).await;
 //! ```
 //!
+//! Besides prompt steps, a chain can contain custom steps implementing `CustomStep`, which transform the
+//! parameters passed to the following steps. Chains containing custom steps can't be serialized. Branching is
+//! expressed with `ConditionalStep`, a custom step running another step only when a condition over the parameters
+//! holds, such as summarizing the text only when it's too long. `ParallelSteps` runs several steps concurrently and
+//! joins their outputs under distinct parameters for the following step.
+//!
+//! Chains can also be composed with combinators: `step.then(next)` creates a chain of two steps, `chain.then(step)`
+//! appends a step, and `chain.pipe(other)` appends the steps of another chain. When the steps name their inputs
+//! differently, `chain.remap([("text", "summary")])` makes the output available under the name the next step
+//! expects.
+//!
+//! ```ignore
+//! let summarize = Step::for_prompt_template(prompt!("Summarize this text: {{text}}"));
+//! let tweet = Chain::of_one(Step::for_prompt_template(prompt!("Write a tweet about this summary: {{summary}}")));
+//! let chain = summarize.to_chain().remap([("text", "summary")]).pipe(tweet);
+//! ```
+//!
+//! A sequential chain can be nested in another one with `SubChain`, or `Chain::into_step`, which runs the whole chain
+//! as a single step and can write its output to a named parameter. The other kinds of chains can't be nested this way.
+//!
+//! `Chain::run_all` runs a chain over many sets of parameters with bounded concurrency, returning the result of
+//! every run. `Chain::run_all_into` sends the results to a `ResultSink` as the runs finish instead, so that large
+//! batches don't keep them in memory.
+//!
+//! `Chain::run_with_parameters` also returns the parameters after the last step, with what the steps recorded about
+//! the run, such as the prompt versions selected by `RegistryStep`.
+//!
+//! Runs can be cancelled with a `CancellationToken` passed to `run_with_cancellation`. A cancelled run returns
+//! `SequentialChainError::Cancelled` with the reason and the outputs of the steps completed so far.
+//!
+//! `Chain::run_stream` streams the output of the last step to the caller as it is generated, for interactive
+//! applications.
+//!
 //! This module also provides serialization and deserialization support for the `Chain` struct, allowing you to store and load chains using formats like JSON, YAML, or others.
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt};
 use serde::de::{Deserializer, MapAccess};
 use serde::ser::{SerializeMap, Serializer};
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
+use crate::cancellation::{CancellationToken, Cancelled};
 use crate::frame::FormatAndExecuteError;
+#[cfg(feature = "async")]
+use crate::sinks::{BatchResult, ResultSink, SinkError};
 use crate::{
     frame::Frame,
+    output::{Output, OutputStream, StreamChunk},
+    prompt::ParameterValidationError,
     serialization::StorableEntity,
-    step::Step,
+    step::{CustomStep, CustomStepError, RemapParameters, Step, StepOutcome},
     traits::{Executor, ExecutorError},
     Parameters,
 };
@@ -49,12 +90,76 @@ pub enum SequentialChainError<Err: ExecutorError> {
     FormatAndExecuteError(#[from] FormatAndExecuteError<Err>),
     #[error("The vector of steps was empty")]
     NoSteps,
+    #[error("Custom step failed: {0}")]
+    CustomStep(CustomStepError),
+    #[error("No step in the chain produced an output")]
+    NoOutput,
+    #[error("Error shutting down the executor: {0}")]
+    Shutdown(Err),
+    #[error("The chain was cancelled: {}", .0.reason)]
+    Cancelled(Cancelled),
+}
+
+/// The error returned by `Chain::validate_declared` when a prompt step uses parameters that weren't declared.
+#[derive(Debug, thiserror::Error)]
+#[error("Step {step} uses undeclared parameters: {source}")]
+pub struct ChainParameterError {
+    /// The index of the step in the chain.
+    pub step: usize,
+    /// The parameters the step uses that weren't declared, and the declared parameters no step uses.
+    #[source]
+    pub source: ParameterValidationError,
+}
+
+/// A single step of a sequential chain.
+pub enum ChainStep<E: Executor> {
+    /// A prompt sent to the executor.
+    Prompt(Step<E>),
+    /// A step with custom behavior.
+    Custom(Arc<dyn CustomStep<E>>),
+}
+
+impl<E: Executor> ChainStep<E> {
+    /// Wraps a custom step so it can be added to a chain.
+    pub fn custom<S: CustomStep<E> + 'static>(step: S) -> Self {
+        ChainStep::Custom(Arc::new(step))
+    }
+}
+
+impl<E: Executor> From<Step<E>> for ChainStep<E> {
+    fn from(step: Step<E>) -> Self {
+        ChainStep::Prompt(step)
+    }
+}
+
+impl<E: Executor> Clone for ChainStep<E>
+where
+    Step<E>: Clone,
+{
+    fn clone(&self) -> Self {
+        match self {
+            ChainStep::Prompt(step) => ChainStep::Prompt(step.clone()),
+            ChainStep::Custom(step) => ChainStep::Custom(step.clone()),
+        }
+    }
+}
+
+impl<E: Executor> std::fmt::Debug for ChainStep<E>
+where
+    Step<E>: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChainStep::Prompt(step) => f.debug_tuple("Prompt").field(step).finish(),
+            ChainStep::Custom(_) => f.debug_tuple("Custom").finish_non_exhaustive(),
+        }
+    }
 }
 
 /// A sequential chain is a chain where each step is executed in order, with the output of the previous step being available to the next step.
 #[derive(Clone, Debug)]
 pub struct Chain<E: Executor> {
-    steps: Vec<Step<E>>,
+    steps: Vec<ChainStep<E>>,
 }
 
 impl<E: Executor> Chain<E> {
@@ -64,6 +169,17 @@ impl<E: Executor> Chain<E> {
     ///
     /// * `steps` - A vector of `Step<E>` objects that define the sequence of steps for the chain.
     pub fn new(steps: Vec<Step<E>>) -> Chain<E> {
+        Chain {
+            steps: steps.into_iter().map(ChainStep::Prompt).collect(),
+        }
+    }
+
+    /// Creates a new `Chain` instance from a sequence of prompt and custom steps.
+    ///
+    /// # Arguments
+    ///
+    /// * `steps` - A vector of `ChainStep<E>` objects that define the sequence of steps for the chain.
+    pub fn from_steps(steps: Vec<ChainStep<E>>) -> Chain<E> {
         Chain { steps }
     }
 
@@ -73,13 +189,117 @@ impl<E: Executor> Chain<E> {
     ///
     /// * `step` - A `Step<E>` object that defines the single step for the chain.
     pub fn of_one(step: Step<E>) -> Chain<E> {
-        Chain { steps: vec![step] }
+        Chain {
+            steps: vec![ChainStep::Prompt(step)],
+        }
+    }
+
+    /// Appends `step` to the chain. It receives the output of the previous step in `text`.
+    ///
+    /// # Arguments
+    ///
+    /// * `step` - A prompt step, or a custom step wrapped with `ChainStep::custom`.
+    pub fn then<S: Into<ChainStep<E>>>(mut self, step: S) -> Chain<E> {
+        self.steps.push(step.into());
+        self
+    }
+
+    /// Appends the steps of `other`, so that the first step of `other` receives the output of this chain.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The chain to run after this one.
+    pub fn pipe(mut self, other: Chain<E>) -> Chain<E> {
+        self.steps.extend(other.steps);
+        self
+    }
+
+    /// Makes parameters available to the following steps under other names, for every `(from, to)` pair of
+    /// `mapping`. This is typically used between `pipe`d chains whose prompts name their inputs differently.
+    ///
+    /// # Arguments
+    ///
+    /// * `mapping` - The pairs of parameter names to remap, applied in order.
+    pub fn remap<I, F, T>(self, mapping: I) -> Chain<E>
+    where
+        I: IntoIterator<Item = (F, T)>,
+        F: Into<String>,
+        T: Into<String>,
+        E: Sync,
+    {
+        let remap = mapping
+            .into_iter()
+            .fold(RemapParameters::new(), |remap, (from, to)| {
+                remap.with_mapping(from, to)
+            });
+        self.then(ChainStep::custom(remap))
+    }
+
+    /// Wraps the chain in a `SubChain` step, so it can be nested in another chain. Only sequential chains can be
+    /// nested this way; see `SubChain` for the other chains.
+    pub fn into_step(self) -> ChainStep<E>
+    where
+        E: Sync + 'static,
+        E::Error: Send + Sync + 'static,
+        E::Output: Send,
+        E::PerInvocationOptions: Sync,
+    {
+        ChainStep::custom(SubChain::new(self))
+    }
+
+    /// Checks that the prompt steps of the chain only use the `declared` parameters and `text`, which holds the input
+    /// of the chain and then the output of the previous step. Call it once the chain is built, so that a misspelt
+    /// variable fails before anything runs. Parameters set by custom steps, such as those of `remap`, must be
+    /// declared too.
+    ///
+    /// The error reports the first step using undeclared parameters, along with the declared parameters that no step
+    /// uses.
+    pub fn validate_declared<S: AsRef<str>>(
+        &self,
+        declared: &[S],
+    ) -> Result<(), ChainParameterError> {
+        let mut names: Vec<&str> = declared.iter().map(AsRef::as_ref).collect();
+        names.push("text");
+        let prompts: Vec<(usize, &Step<E>)> = self
+            .steps
+            .iter()
+            .enumerate()
+            .filter_map(|(index, step)| match step {
+                ChainStep::Prompt(step) => Some((index, step)),
+                ChainStep::Custom(_) => None,
+            })
+            .collect();
+        for (index, step) in &prompts {
+            if let Err(mut source) = step.prompt().validate_declared(&names) {
+                let used: Vec<String> = prompts
+                    .iter()
+                    .flat_map(|(_, step)| step.prompt().variables())
+                    .collect();
+                source.unused = declared
+                    .iter()
+                    .map(|name| name.as_ref().to_string())
+                    .filter(|name| !used.contains(name))
+                    .collect();
+                source.unused.sort();
+                source.unused.dedup();
+                return Err(ChainParameterError {
+                    step: *index,
+                    source,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the steps of the chain, in the order they are executed.
+    pub fn steps(&self) -> &[ChainStep<E>] {
+        &self.steps
     }
 
     /// Executes the chain with the given parameters and executor.
     ///
     /// This method runs each step in the chain in sequence, passing the output of the previous step to the next step.
-    /// If the chain is empty, an error is returned.
+    /// If the chain is empty, or none of its steps produced an output, an error is returned.
     ///
     /// # Arguments
     ///
@@ -95,22 +315,308 @@ impl<E: Executor> Chain<E> {
         parameters: Parameters,
         executor: &E,
     ) -> Result<E::Output, SequentialChainError<E::Error>> {
+        self.run_with_cancellation(parameters, executor, &CancellationToken::new())
+            .await
+    }
+
+    /// Executes the chain like `run`, and also returns the parameters after the last step.
+    ///
+    /// Steps record what they learn about the run in the parameters, such as the prompt version a `RegistryStep`
+    /// selected, so they can be logged with the output.
+    pub async fn run_with_parameters(
+        &self,
+        parameters: Parameters,
+        executor: &E,
+    ) -> Result<(E::Output, Parameters), SequentialChainError<E::Error>> {
         if self.steps.is_empty() {
             return Err(SequentialChainError::NoSteps);
         }
+        let (parameters, output) = self
+            .run_steps(&self.steps, parameters, executor, &CancellationToken::new())
+            .await?;
+        Ok((output.ok_or(SequentialChainError::NoOutput)?, parameters))
+    }
+
+    /// Executes the chain like `run`, stopping when `token` is cancelled.
+    ///
+    /// The token is checked before every step, and a step that is running when the token is cancelled is abandoned.
+    /// A cancelled run returns `SequentialChainError::Cancelled` with the reason and the text outputs of the steps
+    /// that completed.
+    pub async fn run_with_cancellation(
+        &self,
+        parameters: Parameters,
+        executor: &E,
+        token: &CancellationToken,
+    ) -> Result<E::Output, SequentialChainError<E::Error>> {
+        if self.steps.is_empty() {
+            return Err(SequentialChainError::NoSteps);
+        }
+        let (_, output) = self
+            .run_steps(&self.steps, parameters, executor, token)
+            .await?;
+        output.ok_or(SequentialChainError::NoOutput)
+    }
+
+    /// Executes the chain like `run`, streaming the output of the last step to the caller as it is generated.
+    ///
+    /// The steps before the last one are run as usual. A last prompt step is streamed with
+    /// `Executor::execute_stream`, while a last custom step is run to completion and its output yielded as a single
+    /// chunk. Errors of the steps run before the stream starts are returned directly; errors of the executor while
+    /// streaming are items of the stream.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut stream = chain.run_stream(parameters!("your input text here"), &executor).await?;
+    /// while let Some(chunk) = stream.next().await {
+    ///     print!("{}", chunk?.delta);
+    /// }
+    /// ```
+    pub async fn run_stream<'a>(
+        &'a self,
+        parameters: Parameters,
+        executor: &'a E,
+    ) -> Result<OutputStream<'a, E::Error>, SequentialChainError<E::Error>>
+    where
+        E: Sync,
+    {
+        let Some((last, steps)) = self.steps.split_last() else {
+            return Err(SequentialChainError::NoSteps);
+        };
+        let (parameters, output) = self
+            .run_steps(steps, parameters, executor, &CancellationToken::new())
+            .await?;
+        match last {
+            ChainStep::Prompt(step) => {
+                Ok(Frame::new(executor, step).format_and_stream(&parameters)?)
+            }
+            ChainStep::Custom(step) => {
+                let outcome = step
+                    .run(&parameters, executor)
+                    .await
+                    .map_err(SequentialChainError::CustomStep)?;
+                let output = outcome
+                    .output
+                    .or(output)
+                    .ok_or(SequentialChainError::NoOutput)?;
+                let chunk = StreamChunk::from_output(&output).await;
+                Ok(stream::once(async move { Ok(chunk) }).boxed())
+            }
+        }
+    }
+
+    /// Runs `steps`, a prefix of the steps of the chain, returning the parameters for the next step and the output
+    /// of the last step producing one.
+    async fn run_steps(
+        &self,
+        steps: &[ChainStep<E>],
+        parameters: Parameters,
+        executor: &E,
+        token: &CancellationToken,
+    ) -> Result<(Parameters, Option<E::Output>), SequentialChainError<E::Error>> {
         let mut current_params = parameters;
         let mut output: Option<E::Output> = None;
-        for (i, step) in self.steps.iter().enumerate() {
-            let frame = Frame::new(executor, step);
-            let res = frame.format_and_execute(&current_params).await?;
-            let is_streaming_and_last_step =
-                step.is_streaming() == Some(true) && i == self.steps.len() - 1;
-            if !is_streaming_and_last_step {
-                current_params = current_params.with_text_from_output(&res).await;
+        let mut partial_outputs: Vec<String> = Vec::new();
+        let cancelled = |reason, partial_outputs| {
+            SequentialChainError::Cancelled(Cancelled {
+                reason,
+                partial_outputs,
+            })
+        };
+        for (i, step) in steps.iter().enumerate() {
+            match step {
+                ChainStep::Prompt(step) => {
+                    let frame = Frame::new(executor, step);
+                    let res = match token
+                        .run_until_cancelled(frame.format_and_execute(&current_params))
+                        .await
+                    {
+                        Ok(res) => res?,
+                        Err(reason) => return Err(cancelled(reason, partial_outputs)),
+                    };
+                    let is_streaming_and_last_step =
+                        step.is_streaming() == Some(true) && i == self.steps.len() - 1;
+                    if !is_streaming_and_last_step {
+                        current_params = current_params.with_text_from_output(&res).await;
+                        partial_outputs.push(current_params.get_text().unwrap_or_default());
+                    }
+                    output = Some(res);
+                }
+                ChainStep::Custom(step) => {
+                    let outcome = match token
+                        .run_until_cancelled(step.run(&current_params, executor))
+                        .await
+                    {
+                        Ok(outcome) => outcome.map_err(SequentialChainError::CustomStep)?,
+                        Err(reason) => return Err(cancelled(reason, partial_outputs)),
+                    };
+                    current_params = outcome.parameters;
+                    if let Some(res) = outcome.output {
+                        current_params = current_params.with_text_from_output(&res).await;
+                        partial_outputs.push(current_params.get_text().unwrap_or_default());
+                        output = Some(res);
+                    }
+                }
+            }
+        }
+        Ok((current_params, output))
+    }
+
+    /// Executes the chain once for every set of parameters in `inputs`, running at most `max_concurrency` of them at
+    /// the same time.
+    ///
+    /// A failing input doesn't stop the others: the result of every input is returned, in the order of `inputs`.
+    /// The results are kept in memory until every run is done; `run_all_into` sends them to a sink instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `inputs` - The parameters to run the chain with.
+    /// * `executor` - A reference to an executor that implements the `Executor` trait.
+    /// * `max_concurrency` - The maximum number of runs at the same time. Zero is treated as one.
+    pub async fn run_all(
+        &self,
+        inputs: Vec<Parameters>,
+        executor: &E,
+        max_concurrency: usize,
+    ) -> Vec<Result<E::Output, SequentialChainError<E::Error>>> {
+        stream::iter(
+            inputs
+                .into_iter()
+                .map(|parameters| self.run(parameters, executor)),
+        )
+        .buffered(max_concurrency.max(1))
+        .collect()
+        .await
+    }
+
+    /// Executes the chain once for every set of parameters in `inputs` like `run_all`, sending the result of each run
+    /// to `sink` as soon as it finishes instead of keeping the results in memory.
+    ///
+    /// Results are sent in the order the runs finish, with the position of their input. The sink is flushed once
+    /// every result is sent. The first error of the sink stops the batch.
+    #[cfg(feature = "async")]
+    pub async fn run_all_into<S>(
+        &self,
+        inputs: Vec<Parameters>,
+        executor: &E,
+        max_concurrency: usize,
+        sink: &S,
+    ) -> Result<(), SinkError>
+    where
+        S: ResultSink<BatchResult> + ?Sized,
+    {
+        let mut results = stream::iter(inputs.into_iter().enumerate().map(
+            |(index, parameters)| async move {
+                match self.run(parameters, executor).await {
+                    Ok(output) => BatchResult {
+                        index,
+                        output: Some(output.primary_textual_output().await.unwrap_or_default()),
+                        error: None,
+                    },
+                    Err(e) => BatchResult {
+                        index,
+                        output: None,
+                        error: Some(e.to_string()),
+                    },
+                }
+            },
+        ))
+        .buffer_unordered(max_concurrency.max(1));
+        while let Some(result) = results.next().await {
+            sink.send(result).await?;
+        }
+        sink.flush().await
+    }
+
+    /// Executes the chain like `run`, then shuts down the executor whether or not the chain succeeded.
+    ///
+    /// Use this when the executor was created for this chain alone. An error from the chain takes precedence over
+    /// an error from shutting down.
+    pub async fn run_and_shutdown(
+        &self,
+        parameters: Parameters,
+        executor: &E,
+    ) -> Result<E::Output, SequentialChainError<E::Error>>
+    where
+        E: Sync,
+    {
+        let result = self.run(parameters, executor).await;
+        let shutdown = executor.shutdown().await;
+        let output = result?;
+        shutdown.map_err(SequentialChainError::Shutdown)?;
+        Ok(output)
+    }
+}
+
+/// A custom step running a whole sequential chain, so that chains can be nested in other chains.
+///
+/// The nested chain receives the current parameters. By default its output becomes the output of the step, like the
+/// output of a prompt step; with `with_output_key`, the text of its output is written to another parameter instead,
+/// and `text` is left unchanged for the following steps. Changes the nested chain makes to other parameters are not
+/// passed on.
+///
+/// Only sequential chains are supported. The other chains take different inputs, like the documents of a map-reduce
+/// chain or the history of a conversation, so there is no single way to run them from parameters; nest one by
+/// implementing `CustomStep` for a type that builds its input from the parameters and runs it.
+///
+/// # Example
+///
+/// ```ignore
+/// let research = Chain::new(vec![search_step, summarize_step]);
+/// let chain = Chain::from_steps(vec![
+///     ChainStep::custom(SubChain::new(research).with_output_key("research")),
+///     answer_step.into(),
+/// ]);
+/// ```
+pub struct SubChain<E: Executor> {
+    chain: Chain<E>,
+    output_key: Option<String>,
+}
+
+impl<E: Executor> SubChain<E> {
+    /// Wraps `chain` so it can be used as a step.
+    pub fn new(chain: Chain<E>) -> Self {
+        Self {
+            chain,
+            output_key: None,
+        }
+    }
+
+    /// Writes the text of the nested chain's output to the parameter `key` instead of returning the output.
+    pub fn with_output_key<K: Into<String>>(mut self, key: K) -> Self {
+        self.output_key = Some(key.into());
+        self
+    }
+}
+
+#[async_trait]
+impl<E> CustomStep<E> for SubChain<E>
+where
+    E: Executor + Sync,
+    E::Error: Send + Sync + 'static,
+    E::Output: Send,
+    E::PerInvocationOptions: Sync,
+{
+    async fn run(
+        &self,
+        parameters: &Parameters,
+        executor: &E,
+    ) -> Result<StepOutcome<E::Output>, CustomStepError> {
+        let output = self
+            .chain
+            .run(parameters.clone(), executor)
+            .await
+            .map_err(Box::new)?;
+        match &self.output_key {
+            None => Ok(StepOutcome {
+                parameters: parameters.clone(),
+                output: Some(output),
+            }),
+            Some(key) => {
+                let text = output.primary_textual_output().await.unwrap_or_default();
+                Ok(StepOutcome::parameters(parameters.with(key.as_str(), text)))
             }
-            output = Some(res);
         }
-        Ok(output.expect("No output from chain"))
     }
 }
 
@@ -129,8 +635,18 @@ impl<E: Executor> Serialize for Chain<E> {
     where
         S: Serializer,
     {
+        let steps = self
+            .steps
+            .iter()
+            .map(|step| match step {
+                ChainStep::Prompt(step) => Ok(step),
+                ChainStep::Custom(_) => Err(serde::ser::Error::custom(
+                    "chains containing custom steps can't be serialized",
+                )),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
         let mut map = serializer.serialize_map(Some(1))?;
-        map.serialize_entry("steps", &self.steps)?;
+        map.serialize_entry("steps", &steps)?;
         map.end()
     }
 }
@@ -160,8 +676,8 @@ impl<'de, E: Executor> serde::de::Visitor<'de> for ChainVisitor<E> {
                 _ => return Err(serde::de::Error::unknown_field(key, &["steps"])),
             }
         }
-        let steps = steps.ok_or_else(|| serde::de::Error::missing_field("steps"))?;
-        Ok(Chain { steps })
+        let steps: Vec<Step<E>> = steps.ok_or_else(|| serde::de::Error::missing_field("steps"))?;
+        Ok(Chain::new(steps))
     }
 }
 
@@ -173,3 +689,258 @@ impl<'de, E: Executor> Deserialize<'de> for Chain<E> {
         deserializer.deserialize_map(ChainVisitor(std::marker::PhantomData))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Chain, ChainParameterError, ChainStep, SequentialChainError, SubChain};
+    use crate::agents::mock::{MockExecutor, MockOutput};
+    use crate::output::Output;
+    use crate::step::{CustomStep, CustomStepError, MissingParameterError, StepOutcome};
+    use crate::{prompt, step::Step, Parameters};
+    use async_trait::async_trait;
+    use futures::executor::block_on;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::Poll;
+
+    /// Echoes the text, yielding once in between, and fails on `fail`. Tracks how many runs are in flight.
+    #[derive(Default)]
+    struct EchoStep {
+        running: AtomicUsize,
+        max_running: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl CustomStep<MockExecutor> for EchoStep {
+        async fn run(
+            &self,
+            parameters: &Parameters,
+            _: &MockExecutor,
+        ) -> Result<StepOutcome<MockOutput>, CustomStepError> {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(running, Ordering::SeqCst);
+            let mut yielded = false;
+            futures::future::poll_fn(|cx| {
+                if yielded {
+                    Poll::Ready(())
+                } else {
+                    yielded = true;
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                }
+            })
+            .await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            let text = parameters.get_text().unwrap_or_default();
+            if text == "fail" {
+                return Err("failed".into());
+            }
+            Ok(StepOutcome {
+                parameters: parameters.clone(),
+                output: Some(MockOutput::text(&text)),
+            })
+        }
+    }
+
+    #[test]
+    fn run_all_returns_results_in_input_order_with_bounded_concurrency() {
+        let step = Arc::new(EchoStep::default());
+        let chain = Chain::from_steps(vec![ChainStep::Custom(step.clone())]);
+        let executor = MockExecutor::new(Vec::new());
+        let inputs = ["a", "fail", "c", "d", "e"]
+            .map(Parameters::new_with_text)
+            .to_vec();
+        let results = block_on(chain.run_all(inputs, &executor, 2));
+        assert_eq!(results.len(), 5);
+        let texts: Vec<_> = results
+            .iter()
+            .map(|result| {
+                result
+                    .as_ref()
+                    .ok()
+                    .map(|output| block_on(output.primary_textual_output()).unwrap())
+            })
+            .collect();
+        let expected = [Some("a"), None, Some("c"), Some("d"), Some("e")];
+        assert_eq!(texts, expected.map(|text| text.map(String::from)));
+        assert!(matches!(
+            results[1],
+            Err(SequentialChainError::CustomStep(_))
+        ));
+        assert_eq!(step.max_running.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn run_all_treats_zero_concurrency_as_one() {
+        let step = Arc::new(EchoStep::default());
+        let chain = Chain::from_steps(vec![ChainStep::Custom(step.clone())]);
+        let executor = MockExecutor::new(Vec::new());
+        let inputs = ["a", "b", "c"].map(Parameters::new_with_text).to_vec();
+        let results = block_on(chain.run_all(inputs, &executor, 0));
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(step.max_running.load(Ordering::SeqCst), 1);
+        assert!(block_on(chain.run_all(Vec::new(), &executor, 4)).is_empty());
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn run_all_into_sends_every_result_to_the_sink() {
+        use crate::sinks::{BatchResult, ChannelSink};
+        use futures::StreamExt;
+
+        let chain = Chain::new(vec![Step::for_prompt_template(prompt!("Say {{text}}"))]);
+        // The executor has no output left for the third run, which fails.
+        let executor = MockExecutor::new(vec![MockOutput::text("one"), MockOutput::text("two")]);
+        let (sink, receiver) = ChannelSink::new(10);
+        let inputs = ["a", "b", "c"].map(Parameters::new_with_text).to_vec();
+        block_on(chain.run_all_into(inputs, &executor, 1, &sink)).unwrap();
+        sink.close();
+        let mut results: Vec<BatchResult> = block_on(receiver.collect());
+        results.sort_by_key(|result| result.index);
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].output.as_deref(), Some("one"));
+        assert_eq!(results[1].output.as_deref(), Some("two"));
+        assert!(results[2].output.is_none());
+        assert!(results[2].error.is_some());
+    }
+
+    fn prompts(executor: &MockExecutor) -> Vec<String> {
+        executor
+            .prompts
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|prompt| prompt.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn then_passes_the_output_on_in_text() {
+        let chain = Step::for_prompt_template(prompt!("Summarize: {{text}}")).then(
+            Step::for_prompt_template(prompt!("Tweet {{text}} for {{audience}}")),
+        );
+        let executor = MockExecutor::new(vec![MockOutput::text("rust"), MockOutput::text("tweet")]);
+        let parameters = Parameters::new_with_text("A long article").with("audience", "devs");
+        let (output, parameters) =
+            block_on(chain.run_with_parameters(parameters, &executor)).unwrap();
+        assert_eq!(output.0.as_deref(), Some("tweet"));
+        assert_eq!(parameters.get_text().as_deref(), Some("tweet"));
+        assert_eq!(parameters.get("audience").as_deref(), Some("devs"));
+        assert_eq!(
+            prompts(&executor),
+            vec!["Summarize: A long article", "Tweet rust for devs"]
+        );
+    }
+
+    #[test]
+    fn piped_chains_receive_remapped_parameters() {
+        let summarize = Chain::of_one(Step::for_prompt_template(prompt!("Summarize: {{text}}")))
+            .remap([("text", "summary")]);
+        let title = Chain::of_one(Step::for_prompt_template(prompt!(
+            "Title for {{summary}} from {{text}}"
+        )));
+        let chain = summarize.pipe(title);
+        assert_eq!(chain.steps().len(), 3);
+        let executor = MockExecutor::new(vec![MockOutput::text("rust"), MockOutput::text("Rust")]);
+        let (_, parameters) = block_on(
+            chain.run_with_parameters(Parameters::new_with_text("A long article"), &executor),
+        )
+        .unwrap();
+        // The remapped parameter stays available under both names.
+        assert_eq!(parameters.get("summary").as_deref(), Some("rust"));
+        assert_eq!(
+            prompts(&executor),
+            vec!["Summarize: A long article", "Title for rust from rust"]
+        );
+    }
+
+    #[test]
+    fn remapping_a_missing_parameter_fails() {
+        let chain = Chain::of_one(Step::for_prompt_template(prompt!("Summarize: {{text}}")))
+            .remap([("summary", "text")]);
+        let executor = MockExecutor::new(vec![MockOutput::text("rust")]);
+        let result = block_on(chain.run(Parameters::new_with_text("A long article"), &executor));
+        let Err(SequentialChainError::CustomStep(error)) = result else {
+            panic!("the remap step should fail");
+        };
+        assert!(error.is::<MissingParameterError>());
+    }
+
+    #[test]
+    fn nested_chains_pass_their_output_to_the_next_step() {
+        let nested = Chain::new(vec![
+            Step::for_prompt_template(prompt!("Outline {{text}}")),
+            Step::for_prompt_template(prompt!("Draft from {{text}}")),
+        ]);
+        let chain = Chain::from_steps(vec![
+            nested.into_step(),
+            Step::for_prompt_template(prompt!("Polish {{text}}")).into(),
+        ]);
+        let executor = MockExecutor::new(vec![
+            MockOutput::text("outline"),
+            MockOutput::text("draft"),
+            MockOutput::text("essay"),
+        ]);
+        let output = block_on(chain.run(Parameters::new_with_text("rust"), &executor)).unwrap();
+        assert_eq!(output.0.as_deref(), Some("essay"));
+        assert_eq!(
+            prompts(&executor),
+            vec!["Outline rust", "Draft from outline", "Polish draft"]
+        );
+    }
+
+    #[test]
+    fn nested_chains_with_an_output_key_leave_the_text_unchanged() {
+        let research = Chain::of_one(Step::for_prompt_template(prompt!("Research {{text}}")));
+        let chain = Chain::from_steps(vec![
+            ChainStep::custom(SubChain::new(research).with_output_key("notes")),
+            Step::for_prompt_template(prompt!("Answer {{text}} using {{notes}}")).into(),
+        ]);
+        let executor =
+            MockExecutor::new(vec![MockOutput::text("facts"), MockOutput::text("answer")]);
+        let output = block_on(chain.run(Parameters::new_with_text("why"), &executor)).unwrap();
+        assert_eq!(output.0.as_deref(), Some("answer"));
+        assert_eq!(
+            prompts(&executor),
+            vec!["Research why", "Answer why using facts"]
+        );
+    }
+
+    #[test]
+    fn validates_the_declared_parameters_when_built() {
+        let chain = Step::<MockExecutor>::for_prompt_template(prompt!(
+            "Summarize {{text}} for {{audience}}"
+        ))
+        .then(Step::for_prompt_template(prompt!(
+            "Tweet {{text}} as {{usre}}"
+        )));
+        assert!(chain.validate_declared(&["audience", "usre"]).is_ok());
+        let Err(ChainParameterError { step, source }) =
+            chain.validate_declared(&["audience", "user"])
+        else {
+            panic!("the second step uses an undeclared parameter");
+        };
+        assert_eq!(step, 1);
+        assert_eq!(source.missing, vec!["usre"]);
+        assert_eq!(source.unused, vec!["user"]);
+
+        // Parameters set by custom steps must be declared.
+        let remapped =
+            Chain::<MockExecutor>::of_one(Step::for_prompt_template(prompt!("Summarize {{text}}")))
+                .remap([("text", "summary")])
+                .then(Step::for_prompt_template(prompt!("Title for {{summary}}")));
+        assert_eq!(remapped.validate_declared::<&str>(&[]).unwrap_err().step, 2);
+        assert!(remapped.validate_declared(&["summary"]).is_ok());
+
+        let error =
+            Step::<MockExecutor>::for_prompt_with_parameters(prompt!("Hello {{nmae}}"), &["name"])
+                .err()
+                .unwrap();
+        assert_eq!(error.missing, vec!["nmae"]);
+        assert!(Step::<MockExecutor>::for_prompt_with_parameters(
+            prompt!("Hi {{name}}"),
+            &["name"]
+        )
+        .is_ok());
+    }
+}
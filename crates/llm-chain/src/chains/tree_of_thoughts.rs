@@ -0,0 +1,365 @@
+//! The `tree_of_thoughts` module contains an experimental `Chain` exploring several lines of reasoning.
+//!
+//! Tree of thoughts solves a problem one thought at a time. At every depth the chain asks the model for several
+//! candidate next thoughts of every line of reasoning it is exploring, scores the resulting lines with an
+//! evaluator, and keeps only the best ones for the next depth: a beam search over reasoning paths. The best line
+//! found is returned once the maximum depth, a target score or the budget is reached.
+//!
+//! The evaluator is pluggable: `StepEvaluator` asks the model to rate a line of reasoning, and any closure taking the
+//! parameters and the thoughts can score them with a heuristic instead.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let propose = Step::for_prompt_template(prompt!(
+//!     "Problem: {{text}}\n\nReasoning so far:\n{{thoughts}}\n\nWrite the next step of the reasoning only."
+//! ));
+//! let rate = Step::for_prompt_template(prompt!(
+//!     "Problem: {{text}}\n\nReasoning:\n{{thoughts}}\n\nRate how likely this reasoning leads to a correct solution, from 0 to 10. Answer with the number only."
+//! ));
+//! let chain = tree_of_thoughts::Chain::new(propose, StepEvaluator::new(rate))
+//!     .with_max_depth(3)
+//!     .with_branching(3)
+//!     .with_beam_width(2);
+//! let output = chain.run(parameters!(problem), &executor).await?;
+//! println!("{}", output.best.thoughts.join("\n"));
+//! ```
+use async_trait::async_trait;
+use futures::future::try_join_all;
+use thiserror::Error;
+
+use crate::{
+    frame::{FormatAndExecuteError, Frame},
+    output::Output,
+    step::Step,
+    traits::{Executor, ExecutorError},
+    Parameters,
+};
+
+/// The error type returned by evaluators.
+pub type EvaluatorError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The `TreeOfThoughtsError` enum represents errors that can occur when executing a tree-of-thoughts chain.
+#[derive(Error, Debug)]
+pub enum TreeOfThoughtsError<Err: ExecutorError> {
+    #[error("FormatAndExecuteError: {0}")]
+    FormatAndExecuteError(#[from] FormatAndExecuteError<Err>),
+    #[error("The model returned no text")]
+    NoTextOutput,
+    #[error("Error evaluating thoughts: {0}")]
+    Evaluator(EvaluatorError),
+}
+
+/// Formats thoughts as a numbered list, the way they are given to prompts in the `thoughts` parameter.
+fn format_thoughts(thoughts: &[String]) -> String {
+    thoughts
+        .iter()
+        .enumerate()
+        .map(|(i, thought)| format!("{}. {}", i + 1, thought.trim()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Scores lines of reasoning for a tree-of-thoughts chain.
+#[async_trait]
+pub trait Evaluator<E: Executor>: Send + Sync {
+    /// Returns the score of `thoughts`, a line of reasoning about the problem described by `parameters`. Higher
+    /// scores are better.
+    async fn evaluate(
+        &self,
+        parameters: &Parameters,
+        thoughts: &[String],
+        executor: &E,
+    ) -> Result<f32, EvaluatorError>;
+}
+
+#[async_trait]
+impl<E, F> Evaluator<E> for F
+where
+    E: Executor + Sync,
+    F: Fn(&Parameters, &[String]) -> f32 + Send + Sync,
+{
+    async fn evaluate(
+        &self,
+        parameters: &Parameters,
+        thoughts: &[String],
+        _executor: &E,
+    ) -> Result<f32, EvaluatorError> {
+        Ok(self(parameters, thoughts))
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("The evaluator's answer contains no score: {0:?}")]
+pub struct NoScoreError(pub String);
+
+/// Returns the first number in `text`, such as `7` in "Score: 7/10".
+///
+/// ```
+/// use llm_chain::chains::tree_of_thoughts::parse_score;
+/// assert_eq!(parse_score("Score: 7.5/10"), Some(7.5));
+/// assert_eq!(parse_score("-2"), Some(-2.0));
+/// assert_eq!(parse_score("no idea"), None);
+/// ```
+pub fn parse_score(text: &str) -> Option<f32> {
+    let start = text.find(|c: char| c.is_ascii_digit())?;
+    let start = match text[..start].strip_suffix('-') {
+        Some(before) => before.len(),
+        None => start,
+    };
+    let rest = &text[start..];
+    let mut end = rest
+        .char_indices()
+        .skip(1)
+        .find(|&(_, c)| !c.is_ascii_digit() && c != '.')
+        .map_or(rest.len(), |(i, _)| i);
+    // A trailing period ends a sentence rather than the number.
+    if rest[..end].ends_with('.') {
+        end -= 1;
+    }
+    rest[..end].parse().ok()
+}
+
+/// An evaluator asking the model to rate a line of reasoning.
+///
+/// The step receives the chain's parameters and `thoughts`, the thoughts numbered from 1, one per line. The first
+/// number in its output, as found by `parse_score`, is the score.
+pub struct StepEvaluator<E: Executor> {
+    step: Step<E>,
+}
+
+impl<E: Executor> StepEvaluator<E> {
+    pub fn new(step: Step<E>) -> Self {
+        Self { step }
+    }
+}
+
+#[async_trait]
+impl<E> Evaluator<E> for StepEvaluator<E>
+where
+    E: Executor + Sync,
+    E::Error: Send + Sync + 'static,
+    E::Output: Send,
+    E::PerInvocationOptions: Sync,
+{
+    async fn evaluate(
+        &self,
+        parameters: &Parameters,
+        thoughts: &[String],
+        executor: &E,
+    ) -> Result<f32, EvaluatorError> {
+        let parameters = parameters.with("thoughts", format_thoughts(thoughts));
+        let output = self
+            .step
+            .run(&parameters, executor)
+            .await
+            .map_err(Box::new)?;
+        let text = output.primary_textual_output().await.unwrap_or_default();
+        parse_score(&text).ok_or_else(|| Box::new(NoScoreError(text)) as EvaluatorError)
+    }
+}
+
+/// A line of reasoning explored by a tree-of-thoughts chain.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThoughtPath {
+    /// The thoughts, from the first to the last.
+    pub thoughts: Vec<String>,
+    /// The score given by the evaluator.
+    pub score: f32,
+}
+
+/// Why a tree-of-thoughts chain stopped exploring.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExplorationEnd {
+    /// The paths reached the maximum depth.
+    MaxDepth,
+    /// A path reached the target score.
+    TargetScore,
+    /// The maximum number of thoughts was generated.
+    Budget,
+}
+
+/// The output of a tree-of-thoughts chain.
+#[derive(Debug, Clone)]
+pub struct ThoughtTreeOutput {
+    /// The best path found.
+    pub best: ThoughtPath,
+    /// Every path that was scored, in the order they were generated.
+    pub explored: Vec<ThoughtPath>,
+    /// The number of thoughts generated.
+    pub expansions: usize,
+    /// Why the exploration ended.
+    pub end: ExplorationEnd,
+}
+
+/// Keeps the `beam_width` best paths, best first. Ties keep the order of the paths.
+fn prune(mut paths: Vec<ThoughtPath>, beam_width: usize) -> Vec<ThoughtPath> {
+    paths.sort_by(|a, b| b.score.total_cmp(&a.score));
+    paths.truncate(beam_width);
+    paths
+}
+
+/// An experimental chain exploring several lines of reasoning with a beam search.
+///
+/// The expansion step proposes the next thought of a line of reasoning. It receives the chain's parameters,
+/// `thoughts`, the thoughts so far numbered from 1, one per line (empty at the first depth), and `depth`, the number
+/// of the thought to propose starting at 1. Its output is the new thought. It is run `branching` times for every
+/// path kept, so it should sample with a nonzero temperature to propose different thoughts.
+pub struct Chain<E: Executor, V> {
+    expand: Step<E>,
+    evaluator: V,
+    max_depth: usize,
+    branching: usize,
+    beam_width: usize,
+    max_expansions: Option<usize>,
+    target_score: Option<f32>,
+}
+
+impl<E: Executor, V: Evaluator<E>> Chain<E, V> {
+    /// Creates a chain proposing thoughts with `expand` and scoring them with `evaluator`, exploring 3 thoughts
+    /// deep with 3 candidates per path and keeping the 2 best paths at every depth.
+    pub fn new(expand: Step<E>, evaluator: V) -> Self {
+        Self {
+            expand,
+            evaluator,
+            max_depth: 3,
+            branching: 3,
+            beam_width: 2,
+            max_expansions: None,
+            target_score: None,
+        }
+    }
+
+    /// Sets the number of thoughts in a complete line of reasoning.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth.max(1);
+        self
+    }
+
+    /// Sets the number of candidate next thoughts proposed for every path.
+    pub fn with_branching(mut self, branching: usize) -> Self {
+        self.branching = branching.max(1);
+        self
+    }
+
+    /// Sets the number of paths kept at every depth.
+    pub fn with_beam_width(mut self, beam_width: usize) -> Self {
+        self.beam_width = beam_width.max(1);
+        self
+    }
+
+    /// Stops exploring once `max_expansions` thoughts were generated. Every thought takes one call to the model, plus
+    /// the calls made by the evaluator.
+    pub fn with_max_expansions(mut self, max_expansions: usize) -> Self {
+        self.max_expansions = Some(max_expansions.max(1));
+        self
+    }
+
+    /// Stops exploring as soon as a path scores at least `target_score`.
+    pub fn with_target_score(mut self, target_score: f32) -> Self {
+        self.target_score = Some(target_score);
+        self
+    }
+
+    /// Proposes the next thought of `path` and scores the extended path.
+    async fn expand_path(
+        &self,
+        parameters: &Parameters,
+        path: &ThoughtPath,
+        executor: &E,
+    ) -> Result<ThoughtPath, TreeOfThoughtsError<E::Error>> {
+        let depth = path.thoughts.len() + 1;
+        let step_parameters = parameters
+            .with("thoughts", format_thoughts(&path.thoughts))
+            .with("depth", depth.to_string());
+        let output = Frame::new(executor, &self.expand)
+            .format_and_execute(&step_parameters)
+            .await?;
+        let thought = output
+            .primary_textual_output()
+            .await
+            .ok_or(TreeOfThoughtsError::NoTextOutput)?;
+        let mut thoughts = path.thoughts.clone();
+        thoughts.push(thought.trim().to_string());
+        let score = self
+            .evaluator
+            .evaluate(parameters, &thoughts, executor)
+            .await
+            .map_err(TreeOfThoughtsError::Evaluator)?;
+        Ok(ThoughtPath { thoughts, score })
+    }
+
+    /// Explores lines of reasoning about the problem described by `parameters` and returns the best one.
+    pub async fn run(
+        &self,
+        parameters: Parameters,
+        executor: &E,
+    ) -> Result<ThoughtTreeOutput, TreeOfThoughtsError<E::Error>> {
+        let mut frontier = vec![ThoughtPath {
+            thoughts: Vec::new(),
+            score: f32::NEG_INFINITY,
+        }];
+        let mut explored = Vec::new();
+        let mut expansions = 0;
+        let mut end = ExplorationEnd::MaxDepth;
+        for _ in 0..self.max_depth {
+            let mut expandable: Vec<&ThoughtPath> = frontier
+                .iter()
+                .flat_map(|path| (0..self.branching).map(move |_| path))
+                .collect();
+            if let Some(max_expansions) = self.max_expansions {
+                expandable.truncate(max_expansions.saturating_sub(expansions));
+            }
+            if expandable.is_empty() {
+                end = ExplorationEnd::Budget;
+                break;
+            }
+            expansions += expandable.len();
+            let candidates = try_join_all(
+                expandable
+                    .into_iter()
+                    .map(|path| self.expand_path(&parameters, path, executor)),
+            )
+            .await?;
+            explored.extend(candidates.iter().cloned());
+            frontier = prune(candidates, self.beam_width);
+            if let (Some(target), Some(best)) = (self.target_score, frontier.first()) {
+                if best.score >= target {
+                    end = ExplorationEnd::TargetScore;
+                    break;
+                }
+            }
+        }
+        let best = frontier
+            .into_iter()
+            .next()
+            .expect("the frontier always has a path");
+        Ok(ThoughtTreeOutput {
+            best,
+            explored,
+            expansions,
+            end,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_score, prune, ThoughtPath};
+
+    #[test]
+    fn keeps_the_best_scored_paths() {
+        let path = |thought: &str, score: &str| ThoughtPath {
+            thoughts: vec![thought.to_string()],
+            score: parse_score(score).unwrap(),
+        };
+        let paths = vec![
+            path("a", "Score: 3/10"),
+            path("b", "8."),
+            path("c", "I'd say 8"),
+            path("d", "-1"),
+        ];
+        let kept = prune(paths, 2);
+        assert_eq!(kept, vec![path("b", "8"), path("c", "8")]);
+    }
+}
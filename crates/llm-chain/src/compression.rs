@@ -0,0 +1,381 @@
+//! Prompt compression: shortening long context to a token budget before it is put into a prompt.
+//!
+//! Retrieved documents, transcripts and tool outputs are often longer than the room left for them in a prompt, and
+//! much of their text doesn't matter to the question asked. `PromptCompressor` is a custom step shrinking a parameter
+//! to a target number of tokens, either extractively, keeping the most informative sentences in their original order
+//! as LLMLingua does with tokens, or by asking the model to summarize it. Sentences that must survive compression,
+//! such as the ones containing an order number, can be marked so they are always kept word for word.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let chain = Chain::from_steps(vec![
+//!     ChainStep::custom(
+//!         PromptCompressor::new(500)
+//!             .with_input_key("context")
+//!             .with_query_key("question")
+//!             .with_must_keep("Order #"),
+//!     ),
+//!     answer_step.into(),
+//! ]);
+//! ```
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use thiserror::Error;
+
+use crate::{
+    frame::FormatAndExecuteError,
+    output::Output,
+    parameters::TEXT_KEY,
+    prompt,
+    retrieval::{Analyzer, Language, LanguageAnalyzer},
+    step::{CustomStep, CustomStepError, Step, StepOutcome},
+    text_splitter::sentences,
+    tokens::{Tokenizer, TokenizerError},
+    traits::{Executor, ExecutorError},
+    Parameters,
+};
+
+const SUMMARY_SYSTEM_PROMPT: &str = "You compress texts used as context by other prompts. Keep facts, names and numbers, and drop repetition and filler. Reply with the compressed text only.";
+const SUMMARY_USER_PROMPT: &str = "Compress the following text to at most {{target_tokens}} tokens.{% if must_keep %} Keep these sentences word for word:\n\n{{must_keep}}{% endif %}\n\nText:\n\n{{text}}";
+
+#[derive(Debug, Error)]
+pub enum PromptCompressionError<Err: ExecutorError> {
+    #[error("FormatAndExecuteError: {0}")]
+    FormatAndExecuteError(#[from] FormatAndExecuteError<Err>),
+    #[error("TokenizerError: {0}")]
+    Tokenizer(#[from] TokenizerError),
+    #[error("The model returned no text")]
+    NoTextOutput,
+}
+
+/// How a `PromptCompressor` shortens text.
+pub enum CompressionMethod<E: Executor> {
+    /// Keeps the sentences with the most informative words, and those sharing words with the query, in their
+    /// original order. This doesn't call the model.
+    Extractive,
+    /// Asks the model to compress the text with a step whose prompt receives it in `text`, the budget in
+    /// `target_tokens` and the sentences to keep in `must_keep`. Must-keep sentences the model dropped are put back in
+    /// front of its output, and outputs still over the budget are compressed extractively.
+    Summarize(Step<E>),
+}
+
+impl<E: Executor> CompressionMethod<E> {
+    /// Summarizes with a default prompt.
+    pub fn summarize() -> Self {
+        Self::Summarize(Step::for_prompt_template(prompt!(
+            SUMMARY_SYSTEM_PROMPT,
+            SUMMARY_USER_PROMPT
+        )))
+    }
+}
+
+/// A predicate selecting the sentences a `PromptCompressor` always keeps.
+pub type MustKeep = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// A custom step compressing a parameter to a target number of tokens of the executor's tokenizer.
+///
+/// By default the `text` parameter is compressed in place, extractively. Text already within the budget is left
+/// unchanged. Must-keep sentences are kept even when they alone exceed the budget.
+pub struct PromptCompressor<E: Executor> {
+    target_tokens: usize,
+    method: CompressionMethod<E>,
+    input_key: String,
+    output_key: Option<String>,
+    query_key: Option<String>,
+    must_keep: Vec<MustKeep>,
+    language: Language,
+}
+
+impl<E: Executor> PromptCompressor<E> {
+    /// Creates a step compressing `text` extractively to `target_tokens` tokens.
+    pub fn new(target_tokens: usize) -> Self {
+        Self {
+            target_tokens,
+            method: CompressionMethod::Extractive,
+            input_key: TEXT_KEY.to_string(),
+            output_key: None,
+            query_key: None,
+            must_keep: Vec::new(),
+            language: Language::English,
+        }
+    }
+
+    pub fn with_method(mut self, method: CompressionMethod<E>) -> Self {
+        self.method = method;
+        self
+    }
+
+    /// Sets the parameter to compress. Defaults to `text`.
+    pub fn with_input_key<S: Into<String>>(mut self, key: S) -> Self {
+        self.input_key = key.into();
+        self
+    }
+
+    /// Writes the compressed text to `key` instead of replacing the input parameter.
+    pub fn with_output_key<S: Into<String>>(mut self, key: S) -> Self {
+        self.output_key = Some(key.into());
+        self
+    }
+
+    /// Favors the sentences sharing words with the parameter `key`, usually the question the context is for.
+    pub fn with_query_key<S: Into<String>>(mut self, key: S) -> Self {
+        self.query_key = Some(key.into());
+        self
+    }
+
+    /// Always keeps the sentences containing `phrase`, ignoring case.
+    pub fn with_must_keep<S: Into<String>>(self, phrase: S) -> Self {
+        let phrase = phrase.into().to_lowercase();
+        self.with_must_keep_when(move |sentence| sentence.to_lowercase().contains(&phrase))
+    }
+
+    /// Always keeps the sentences for which `must_keep` returns true.
+    pub fn with_must_keep_when<F>(mut self, must_keep: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.must_keep.push(Arc::new(must_keep));
+        self
+    }
+
+    /// Sets the language whose stop words are ignored when scoring sentences. Defaults to English.
+    pub fn with_language(mut self, language: Language) -> Self {
+        self.language = language;
+        self
+    }
+
+    /// Compresses `text` to the target number of tokens. `query`, if given, favors the sentences relevant to it.
+    pub async fn compress(
+        &self,
+        text: &str,
+        query: Option<&str>,
+        executor: &E,
+    ) -> Result<String, PromptCompressionError<E::Error>> {
+        // The tokenizer isn't necessarily `Send`, so it isn't kept across the call to the model.
+        let count = |text: &str| Ok(executor.get_tokenizer(None)?.tokenize_str(text)?.len());
+        if count(text)? <= self.target_tokens {
+            return Ok(text.to_string());
+        }
+        let step = match &self.method {
+            CompressionMethod::Extractive => return Ok(self.extract(text, query, count)?),
+            CompressionMethod::Summarize(step) => step,
+        };
+        let kept: Vec<&str> = sentences(text)
+            .into_iter()
+            .map(|range| &text[range])
+            .filter(|sentence| self.is_must_keep(sentence))
+            .collect();
+        let parameters = Parameters::new_with_text(text)
+            .with("target_tokens", self.target_tokens.to_string())
+            .with("must_keep", kept.join("\n"));
+        let output = step.run(&parameters, executor).await?;
+        let summary = output
+            .primary_textual_output()
+            .await
+            .ok_or(PromptCompressionError::NoTextOutput)?;
+        let mut compressed: Vec<&str> = kept
+            .into_iter()
+            .filter(|sentence| !summary.contains(sentence))
+            .collect();
+        compressed.push(summary.trim());
+        let compressed = compressed.join("\n");
+        if count(&compressed)? <= self.target_tokens {
+            Ok(compressed)
+        } else {
+            Ok(self.extract(&compressed, query, count)?)
+        }
+    }
+
+    fn is_must_keep(&self, sentence: &str) -> bool {
+        self.must_keep.iter().any(|must_keep| must_keep(sentence))
+    }
+
+    fn extract<F>(
+        &self,
+        text: &str,
+        query: Option<&str>,
+        count: F,
+    ) -> Result<String, TokenizerError>
+    where
+        F: Fn(&str) -> Result<usize, TokenizerError>,
+    {
+        let analyzer = LanguageAnalyzer::new(self.language);
+        let must_keep = |sentence: &str| self.is_must_keep(sentence);
+        extract(
+            text,
+            self.target_tokens,
+            query,
+            &must_keep,
+            &analyzer,
+            count,
+        )
+    }
+}
+
+/// Keeps the best sentences of `text` that fit in `target_tokens`, counting tokens with `count`, in their original
+/// order.
+fn extract<F>(
+    text: &str,
+    target_tokens: usize,
+    query: Option<&str>,
+    must_keep: &dyn Fn(&str) -> bool,
+    analyzer: &dyn Analyzer,
+    count: F,
+) -> Result<String, TokenizerError>
+where
+    F: Fn(&str) -> Result<usize, TokenizerError>,
+{
+    let ranges = sentences(text);
+    let terms: Vec<HashSet<String>> = ranges
+        .iter()
+        .map(|range| analyzer.analyze(&text[range.clone()]).into_iter().collect())
+        .collect();
+    let query: HashSet<String> = query
+        .map(|query| analyzer.analyze(query).into_iter().collect())
+        .unwrap_or_default();
+    let scores = score_sentences(&terms, &query);
+
+    // Must-keep sentences go first, then the others from the highest score down.
+    let mut order: Vec<usize> = (0..ranges.len()).collect();
+    let must_keep: Vec<bool> = ranges
+        .iter()
+        .map(|range| must_keep(&text[range.clone()]))
+        .collect();
+    order.sort_by(|&a, &b| {
+        must_keep[b]
+            .cmp(&must_keep[a])
+            .then(scores[b].total_cmp(&scores[a]))
+    });
+    let mut kept = vec![false; ranges.len()];
+    let mut tokens = 0;
+    for i in order {
+        let length = count(&text[ranges[i].clone()])?;
+        if must_keep[i] || tokens + length <= target_tokens {
+            kept[i] = true;
+            tokens += length;
+        }
+    }
+
+    let mut compressed = String::new();
+    let mut previous_end = None;
+    for (range, _) in ranges.iter().zip(kept).filter(|(_, kept)| *kept) {
+        if let Some(end) = previous_end {
+            // Line breaks between sentences are kept, so paragraphs and lists stay recognizable.
+            let gap = &text[end..range.start];
+            compressed.push_str(if gap.contains('\n') { "\n" } else { " " });
+        }
+        compressed.push_str(&text[range.clone()]);
+        previous_end = Some(range.end);
+    }
+    Ok(compressed)
+}
+
+/// Scores sentences by the average rarity of their words within the text, plus the share of the query's words they
+/// contain. Words appearing in every sentence carry no information and score 0.
+fn score_sentences(terms: &[HashSet<String>], query: &HashSet<String>) -> Vec<f32> {
+    let mut frequencies: HashMap<&str, usize> = HashMap::new();
+    for sentence in terms {
+        for term in sentence {
+            *frequencies.entry(term.as_str()).or_default() += 1;
+        }
+    }
+    let sentence_count = terms.len() as f32;
+    terms
+        .iter()
+        .map(|sentence| {
+            if sentence.is_empty() {
+                return 0.0;
+            }
+            let rarity: f32 = sentence
+                .iter()
+                .map(|term| (sentence_count / frequencies[term.as_str()] as f32).ln())
+                .sum::<f32>()
+                / sentence.len() as f32;
+            let relevance = if query.is_empty() {
+                0.0
+            } else {
+                sentence.intersection(query).count() as f32 / query.len() as f32
+            };
+            rarity + 2.0 * relevance
+        })
+        .collect()
+}
+
+#[async_trait]
+impl<E> CustomStep<E> for PromptCompressor<E>
+where
+    E: Executor + Sync,
+    E::Error: Send + Sync + 'static,
+    E::Output: Send,
+    E::PerInvocationOptions: Sync,
+{
+    async fn run(
+        &self,
+        parameters: &Parameters,
+        executor: &E,
+    ) -> Result<StepOutcome<E::Output>, CustomStepError> {
+        let Some(text) = parameters.get(&self.input_key) else {
+            return Ok(StepOutcome::parameters(parameters.clone()));
+        };
+        let query = self.query_key.as_ref().and_then(|key| parameters.get(key));
+        let compressed = self
+            .compress(&text, query.as_deref(), executor)
+            .await
+            .map_err(Box::new)?;
+        let key = self.output_key.as_ref().unwrap_or(&self.input_key);
+        Ok(StepOutcome::parameters(
+            parameters.with(key.as_str(), compressed),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(text: &str) -> Result<usize, TokenizerError> {
+        Ok(text.split_whitespace().count())
+    }
+
+    #[test]
+    fn keeps_relevant_and_must_keep_sentences() {
+        let text =
+            "The weather was nice. The weather was nice again.\nThe invoice total is 420 euros. \
+                    The customer lives in Lyon. Order #1234 was shipped.";
+        let analyzer = LanguageAnalyzer::new(Language::English);
+        let order = |sentence: &str| sentence.contains("Order #");
+        let nothing = |_: &str| false;
+
+        let compressed = extract(
+            text,
+            10,
+            Some("What is the invoice total?"),
+            &order,
+            &analyzer,
+            words,
+        )
+        .unwrap();
+        assert_eq!(
+            compressed,
+            "The invoice total is 420 euros. Order #1234 was shipped."
+        );
+
+        let compressed = extract(
+            text,
+            5,
+            Some("Where does the customer live?"),
+            &nothing,
+            &analyzer,
+            words,
+        )
+        .unwrap();
+        assert_eq!(compressed, "The customer lives in Lyon.");
+
+        // Without a query, the repeated sentences score lowest.
+        let compressed = extract(text, 15, None, &nothing, &analyzer, words).unwrap();
+        assert!(!compressed.contains("weather"));
+    }
+}
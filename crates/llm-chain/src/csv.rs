@@ -0,0 +1,117 @@
+//! A CSV parser, shared by the CSV loader and the HTTP tool.
+use std::ops::Range;
+
+/// An error in CSV data, on the line the record starts on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CsvError {
+    pub(crate) line: usize,
+    pub(crate) message: String,
+}
+
+/// A row of a CSV file, with the line it starts on and its byte range in the file.
+pub(crate) struct CsvRow {
+    pub(crate) line: usize,
+    pub(crate) range: Range<usize>,
+    pub(crate) values: Vec<String>,
+}
+
+/// Splits CSV data into rows of fields, following RFC 4180: fields may be quoted, quotes in quoted fields are
+/// doubled, and quoted fields may span lines.
+pub(crate) fn parse_csv(data: &str, delimiter: char) -> Result<Vec<CsvRow>, CsvError> {
+    let mut rows = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut line = 1;
+    let mut record_line = 1;
+    let mut record_start = 0;
+    let mut chars = data.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek().is_some_and(|&(_, next)| next == '"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => in_quotes = false,
+                _ => {
+                    line += usize::from(c == '\n');
+                    field.push(c);
+                }
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() => in_quotes = true,
+            c if c == delimiter => fields.push(std::mem::take(&mut field)),
+            '\r' if chars.peek().is_some_and(|&(_, next)| next == '\n') => {}
+            '\n' => {
+                fields.push(std::mem::take(&mut field));
+                let end = data[..i].trim_end_matches('\r').len();
+                if fields.iter().any(|f| !f.is_empty()) {
+                    rows.push(CsvRow {
+                        line: record_line,
+                        range: record_start..end,
+                        values: std::mem::take(&mut fields),
+                    });
+                }
+                fields.clear();
+                line += 1;
+                record_line = line;
+                record_start = i + 1;
+            }
+            _ => field.push(c),
+        }
+    }
+    if in_quotes {
+        return Err(CsvError {
+            line: record_line,
+            message: "unterminated quoted field".to_string(),
+        });
+    }
+    fields.push(field);
+    if fields.iter().any(|f| !f.is_empty()) {
+        rows.push(CsvRow {
+            line: record_line,
+            range: record_start..data.len(),
+            values: fields,
+        });
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_csv;
+
+    fn values(data: &str, delimiter: char) -> Vec<Vec<String>> {
+        parse_csv(data, delimiter)
+            .unwrap()
+            .into_iter()
+            .map(|row| row.values)
+            .collect()
+    }
+
+    #[test]
+    fn parses_quoted_fields() {
+        assert_eq!(
+            values("a,b\r\n\"x, \"\"y\"\"\",z\r\n\r\n", ','),
+            [vec!["a", "b"], vec!["x, \"y\"", "z"]]
+        );
+        assert_eq!(values("a;\"b;c\"", ';'), [vec!["a", "b;c"]]);
+        assert_eq!(values("\"\"\"\"", ','), [vec!["\""]]);
+    }
+
+    #[test]
+    fn quoted_fields_span_lines() {
+        let data = "id,text\n1,\"one\ntwo\"\n2,three";
+        let rows = parse_csv(data, ',').unwrap();
+        assert_eq!(rows[1].values, ["1", "one\ntwo"]);
+        assert_eq!(rows[1].line, 2);
+        assert_eq!(&data[rows[1].range.clone()], "1,\"one\ntwo\"");
+        assert_eq!(rows[2].line, 4);
+
+        let error = parse_csv("id,text\n1,\"open\n2,x", ',').err().unwrap();
+        assert_eq!(error.line, 2);
+    }
+}
@@ -0,0 +1,408 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::thread;
+
+use async_trait::async_trait;
+use futures::channel::oneshot;
+use thiserror::Error;
+
+use crate::hash::stable_hash_hex;
+use crate::traits::{EmbeddingPurpose, Embeddings, EmbeddingsError, TruncationPolicy};
+
+/// Storage for cached embedding vectors.
+///
+/// `CachedEmbeddings` reads and writes the vectors of a call in one batch with `get_many` and `put_many`, which
+/// default to `get` and `put` for each key. Stores doing blocking I/O should do it off the executor.
+#[async_trait]
+pub trait EmbeddingStore: Send + Sync {
+    async fn get(&self, key: &str) -> io::Result<Option<Vec<f32>>>;
+    async fn put(&self, key: &str, embedding: &[f32]) -> io::Result<()>;
+
+    /// Returns the cached vectors of `keys`, in order.
+    async fn get_many(&self, keys: &[String]) -> io::Result<Vec<Option<Vec<f32>>>> {
+        let mut embeddings = Vec::with_capacity(keys.len());
+        for key in keys {
+            embeddings.push(self.get(key).await?);
+        }
+        Ok(embeddings)
+    }
+
+    /// Stores each vector under its key.
+    async fn put_many(&self, entries: Vec<(String, Vec<f32>)>) -> io::Result<()> {
+        for (key, embedding) in &entries {
+            self.put(key, embedding).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Keeps cached embeddings in memory for the lifetime of the store.
+#[derive(Debug, Default)]
+pub struct InMemoryEmbeddingStore {
+    embeddings: RwLock<HashMap<String, Vec<f32>>>,
+}
+
+impl InMemoryEmbeddingStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.embeddings
+            .read()
+            .expect("embedding store lock poisoned")
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[async_trait]
+impl EmbeddingStore for InMemoryEmbeddingStore {
+    async fn get(&self, key: &str) -> io::Result<Option<Vec<f32>>> {
+        Ok(self
+            .embeddings
+            .read()
+            .expect("embedding store lock poisoned")
+            .get(key)
+            .cloned())
+    }
+
+    async fn put(&self, key: &str, embedding: &[f32]) -> io::Result<()> {
+        self.embeddings
+            .write()
+            .expect("embedding store lock poisoned")
+            .insert(key.to_string(), embedding.to_vec());
+        Ok(())
+    }
+}
+
+/// Keeps cached embeddings on disk, one file per vector, so they survive between runs.
+///
+/// Files are written to a temporary name and then renamed, so several processes can share a directory without
+/// reading half-written vectors. Files are read and written on a thread of their own, leaving the executor free in
+/// the meantime.
+#[derive(Debug, Clone)]
+pub struct FileEmbeddingStore {
+    directory: PathBuf,
+}
+
+impl FileEmbeddingStore {
+    /// Creates a store in `directory`, creating the directory if it doesn't exist.
+    pub fn open<P: Into<PathBuf>>(directory: P) -> io::Result<Self> {
+        let directory = directory.into();
+        fs::create_dir_all(&directory)?;
+        Ok(Self { directory })
+    }
+
+    /// Runs `f` with the directory of the store on a new thread.
+    async fn blocking<T, F>(&self, f: F) -> io::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Path) -> io::Result<T> + Send + 'static,
+    {
+        let directory = self.directory.clone();
+        let (sender, receiver) = oneshot::channel();
+        thread::spawn(move || sender.send(f(&directory)));
+        receiver
+            .await
+            .map_err(|_| io::Error::other("the embedding store thread panicked"))?
+    }
+}
+
+fn path(directory: &Path, key: &str) -> PathBuf {
+    // Spread the files over subdirectories so no single directory grows too large.
+    let prefix = key.get(..2).unwrap_or(key);
+    directory.join(prefix).join(format!("{}.f32", key))
+}
+
+fn read_embedding(directory: &Path, key: &str) -> io::Result<Option<Vec<f32>>> {
+    let bytes = match fs::read(path(directory, key)) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if bytes.len() % 4 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("cached embedding {} is corrupt", key),
+        ));
+    }
+    Ok(Some(
+        bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect(),
+    ))
+}
+
+fn write_embedding(directory: &Path, key: &str, embedding: &[f32]) -> io::Result<()> {
+    let path = path(directory, key);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let bytes: Vec<u8> = embedding.iter().flat_map(|v| v.to_le_bytes()).collect();
+    let temporary = path.with_extension(format!("{}.tmp", uuid::Uuid::new_v4()));
+    fs::write(&temporary, bytes)?;
+    fs::rename(&temporary, &path)
+}
+
+#[async_trait]
+impl EmbeddingStore for FileEmbeddingStore {
+    async fn get(&self, key: &str) -> io::Result<Option<Vec<f32>>> {
+        let key = key.to_string();
+        self.blocking(move |directory| read_embedding(directory, &key))
+            .await
+    }
+
+    async fn put(&self, key: &str, embedding: &[f32]) -> io::Result<()> {
+        let (key, embedding) = (key.to_string(), embedding.to_vec());
+        self.blocking(move |directory| write_embedding(directory, &key, &embedding))
+            .await
+    }
+
+    async fn get_many(&self, keys: &[String]) -> io::Result<Vec<Option<Vec<f32>>>> {
+        let keys = keys.to_vec();
+        self.blocking(move |directory| {
+            keys.iter()
+                .map(|key| read_embedding(directory, key))
+                .collect()
+        })
+        .await
+    }
+
+    async fn put_many(&self, entries: Vec<(String, Vec<f32>)>) -> io::Result<()> {
+        self.blocking(move |directory| {
+            entries
+                .iter()
+                .try_for_each(|(key, embedding)| write_embedding(directory, key, embedding))
+        })
+        .await
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum CachedEmbeddingsError<E: std::error::Error> {
+    #[error(transparent)]
+    Embeddings(E),
+    #[error("Error accessing the embedding cache: {0}")]
+    Store(#[from] io::Error),
+    #[error("The embeddings provider returned {actual} embeddings for {expected} texts")]
+    CountMismatch { expected: usize, actual: usize },
+}
+
+impl<E: std::error::Error> EmbeddingsError for CachedEmbeddingsError<E> {}
+
+/// An `Embeddings` provider that caches the vectors computed by another provider.
+///
+/// Entries are keyed by the model name, the purpose of the embedding and a hash of the text. The model name isn't
+/// known to the `Embeddings` trait, so it has to be given when creating the cache: use a different name whenever the
+/// provider is configured to produce different vectors, or cached vectors of the old model will be returned.
+///
+/// # Example
+///
+/// ```ignore
+/// let embeddings = CachedEmbeddings::new(
+///     llm_chain_openai::embeddings::Embeddings::default(),
+///     "text-embedding-ada-002",
+///     FileEmbeddingStore::open(".embeddings-cache")?,
+/// );
+/// ```
+pub struct CachedEmbeddings<E, S = InMemoryEmbeddingStore>
+where
+    E: Embeddings,
+    S: EmbeddingStore,
+{
+    inner: E,
+    model: String,
+    store: S,
+}
+
+impl<E> CachedEmbeddings<E>
+where
+    E: Embeddings,
+{
+    /// Caches the embeddings of `inner` in memory.
+    pub fn in_memory<M: Into<String>>(inner: E, model: M) -> Self {
+        Self::new(inner, model, InMemoryEmbeddingStore::new())
+    }
+}
+
+impl<E, S> CachedEmbeddings<E, S>
+where
+    E: Embeddings,
+    S: EmbeddingStore,
+{
+    pub fn new<M: Into<String>>(inner: E, model: M, store: S) -> Self {
+        Self {
+            inner,
+            model: model.into(),
+            store,
+        }
+    }
+
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    fn key(&self, purpose: EmbeddingPurpose, text: &str) -> String {
+        stable_hash_hex(&[&self.model, &format!("{:?}", purpose), text])
+    }
+}
+
+#[async_trait]
+impl<E, S> Embeddings for CachedEmbeddings<E, S>
+where
+    E: Embeddings + Send + Sync,
+    S: EmbeddingStore,
+{
+    type Error = CachedEmbeddingsError<E::Error>;
+
+    async fn embed_texts(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Self::Error> {
+        self.embed_texts_for(texts, EmbeddingPurpose::Document)
+            .await
+    }
+
+    async fn embed_query(&self, query: String) -> Result<Vec<f32>, Self::Error> {
+        let mut embeddings = self
+            .embed_texts_for(vec![query], EmbeddingPurpose::Query)
+            .await?;
+        embeddings
+            .pop()
+            .ok_or(CachedEmbeddingsError::CountMismatch {
+                expected: 1,
+                actual: 0,
+            })
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        self.inner.dimensions()
+    }
+
+    fn truncation_policy(&self) -> Option<TruncationPolicy> {
+        self.inner.truncation_policy()
+    }
+
+    /// Returns the cached vectors of `texts` and embeds the others with the inner provider in a single call.
+    async fn embed_texts_for(
+        &self,
+        texts: Vec<String>,
+        purpose: EmbeddingPurpose,
+    ) -> Result<Vec<Vec<f32>>, Self::Error> {
+        let keys: Vec<String> = texts.iter().map(|text| self.key(purpose, text)).collect();
+        let mut embeddings = self.store.get_many(&keys).await?;
+
+        // Embed every missing text once, even if it appears several times.
+        let mut missing: Vec<usize> = Vec::new();
+        for (i, embedding) in embeddings.iter().enumerate() {
+            if embedding.is_none() && !missing.iter().any(|&j| keys[j] == keys[i]) {
+                missing.push(i);
+            }
+        }
+        if missing.is_empty() {
+            return Ok(embeddings.into_iter().flatten().collect());
+        }
+        let computed = self
+            .inner
+            .embed_texts_for(missing.iter().map(|&i| texts[i].clone()).collect(), purpose)
+            .await
+            .map_err(CachedEmbeddingsError::Embeddings)?;
+        if computed.len() != missing.len() {
+            return Err(CachedEmbeddingsError::CountMismatch {
+                expected: missing.len(),
+                actual: computed.len(),
+            });
+        }
+        let computed: HashMap<&str, Vec<f32>> = missing
+            .iter()
+            .map(|&i| keys[i].as_str())
+            .zip(computed)
+            .collect();
+        self.store
+            .put_many(
+                computed
+                    .iter()
+                    .map(|(key, embedding)| (key.to_string(), embedding.clone()))
+                    .collect(),
+            )
+            .await?;
+        Ok(embeddings
+            .iter_mut()
+            .zip(&keys)
+            .map(|(embedding, key)| {
+                embedding
+                    .take()
+                    .unwrap_or_else(|| computed[key.as_str()].clone())
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CachedEmbeddings, FileEmbeddingStore};
+    use crate::test_support::NoError;
+    use crate::traits::Embeddings;
+    use async_trait::async_trait;
+    use futures::executor::block_on;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Embeds a text as its length, counting how many texts it embedded.
+    #[derive(Default)]
+    struct CountingEmbeddings {
+        embedded: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Embeddings for CountingEmbeddings {
+        type Error = NoError;
+        async fn embed_texts(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, NoError> {
+            self.embedded.fetch_add(texts.len(), Ordering::SeqCst);
+            Ok(texts.iter().map(|t| vec![t.len() as f32, 1.0]).collect())
+        }
+        async fn embed_query(&self, query: String) -> Result<Vec<f32>, NoError> {
+            self.embedded.fetch_add(1, Ordering::SeqCst);
+            Ok(vec![query.len() as f32, -1.0])
+        }
+    }
+
+    #[test]
+    fn embeds_each_text_once() {
+        let cache = CachedEmbeddings::in_memory(CountingEmbeddings::default(), "model");
+        block_on(async {
+            let texts = vec!["a".to_string(), "bb".to_string(), "a".to_string()];
+            let first = cache.embed_texts(texts.clone()).await.unwrap();
+            assert_eq!(first, vec![vec![1.0, 1.0], vec![2.0, 1.0], vec![1.0, 1.0]]);
+            assert_eq!(cache.embed_texts(texts).await.unwrap(), first);
+            assert_eq!(cache.inner().embedded.load(Ordering::SeqCst), 2);
+
+            // Queries are cached separately from documents.
+            assert_eq!(
+                cache.embed_query("a".to_string()).await.unwrap(),
+                vec![1.0, -1.0]
+            );
+            assert_eq!(cache.inner().embedded.load(Ordering::SeqCst), 3);
+        });
+
+        let directory = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let store = FileEmbeddingStore::open(&directory).unwrap();
+        let cache = CachedEmbeddings::new(CountingEmbeddings::default(), "model", store.clone());
+        block_on(cache.embed_texts(vec!["abc".to_string()])).unwrap();
+        let reopened = CachedEmbeddings::new(CountingEmbeddings::default(), "model", store);
+        assert_eq!(
+            block_on(reopened.embed_texts(vec!["abc".to_string()])).unwrap(),
+            vec![vec![3.0, 1.0]]
+        );
+        assert_eq!(reopened.inner().embedded.load(Ordering::SeqCst), 0);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,14 @@
+//! Utilities that work with any `Embeddings` provider.
+//!
+//! - `CachedEmbeddings`: wraps a provider and stores every vector it computes, so that re-indexing unchanged documents
+//!   or repeating a query doesn't pay for the same embedding twice. Vectors are kept in an `EmbeddingStore`, either
+//!   in memory with `InMemoryEmbeddingStore` or on disk with `FileEmbeddingStore`.
+//! - `TransformedEmbeddings`: shortens vectors (Matryoshka dimension reduction) and scales them to unit length.
+mod cache;
+mod transform;
+
+pub use cache::{
+    CachedEmbeddings, CachedEmbeddingsError, EmbeddingStore, FileEmbeddingStore,
+    InMemoryEmbeddingStore,
+};
+pub use transform::{normalize, TransformedEmbeddings};
@@ -0,0 +1,127 @@
+use async_trait::async_trait;
+
+use crate::traits::{EmbeddingPurpose, Embeddings, TruncationPolicy};
+
+/// Scales `vector` to unit length. Zero vectors are left unchanged.
+pub fn normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+}
+
+/// An `Embeddings` provider that post-processes the vectors of another provider.
+///
+/// - `with_dimensions` shortens vectors to their first dimensions. This is the Matryoshka dimension reduction
+///   supported by models trained for it, such as OpenAI's `text-embedding-3` family: smaller vectors cost less to
+///   store and search at a small loss of quality. Other models lose most of their quality when shortened.
+/// - `with_normalization` scales vectors to unit length, so that dot product and cosine similarity agree. Shortened
+///   vectors are normalized by default, since they aren't of unit length anymore.
+pub struct TransformedEmbeddings<E: Embeddings> {
+    inner: E,
+    dimensions: Option<usize>,
+    normalize: bool,
+}
+
+impl<E: Embeddings> TransformedEmbeddings<E> {
+    pub fn new(inner: E) -> Self {
+        Self {
+            inner,
+            dimensions: None,
+            normalize: false,
+        }
+    }
+
+    /// Keeps only the first `dimensions` dimensions of every vector, and turns on normalization.
+    pub fn with_dimensions(mut self, dimensions: usize) -> Self {
+        self.dimensions = Some(dimensions);
+        self.normalize = true;
+        self
+    }
+
+    /// Sets whether vectors are scaled to unit length.
+    pub fn with_normalization(mut self, normalize: bool) -> Self {
+        self.normalize = normalize;
+        self
+    }
+
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+
+    fn transform(&self, mut vector: Vec<f32>) -> Vec<f32> {
+        if let Some(dimensions) = self.dimensions {
+            vector.truncate(dimensions);
+        }
+        if self.normalize {
+            normalize(&mut vector);
+        }
+        vector
+    }
+}
+
+#[async_trait]
+impl<E> Embeddings for TransformedEmbeddings<E>
+where
+    E: Embeddings + Send + Sync,
+{
+    type Error = E::Error;
+
+    async fn embed_texts(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Self::Error> {
+        let vectors = self.inner.embed_texts(texts).await?;
+        Ok(vectors.into_iter().map(|v| self.transform(v)).collect())
+    }
+
+    async fn embed_query(&self, query: String) -> Result<Vec<f32>, Self::Error> {
+        Ok(self.transform(self.inner.embed_query(query).await?))
+    }
+
+    async fn embed_texts_for(
+        &self,
+        texts: Vec<String>,
+        purpose: EmbeddingPurpose,
+    ) -> Result<Vec<Vec<f32>>, Self::Error> {
+        let vectors = self.inner.embed_texts_for(texts, purpose).await?;
+        Ok(vectors.into_iter().map(|v| self.transform(v)).collect())
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        match (self.dimensions, self.inner.dimensions()) {
+            (Some(reduced), Some(full)) => Some(reduced.min(full)),
+            (reduced, full) => reduced.or(full),
+        }
+    }
+
+    fn truncation_policy(&self) -> Option<TruncationPolicy> {
+        self.inner.truncation_policy()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TransformedEmbeddings;
+    use crate::test_support::FnEmbeddings;
+    use crate::traits::Embeddings;
+    use futures::executor::block_on;
+
+    /// Embeds every text as the vector `[3, 4, 12]`, of norm 13.
+    fn fixed() -> FnEmbeddings<fn(&str) -> Vec<f32>> {
+        FnEmbeddings(|_| vec![3.0, 4.0, 12.0])
+    }
+
+    #[test]
+    fn reduces_and_normalizes() {
+        let reduced = TransformedEmbeddings::new(fixed()).with_dimensions(2);
+        assert_eq!(
+            block_on(reduced.embed_query("q".to_string())).unwrap(),
+            vec![0.6, 0.8]
+        );
+        assert_eq!(block_on(reduced.detect_dimensions()).unwrap(), 2);
+
+        let normalized = TransformedEmbeddings::new(fixed()).with_normalization(true);
+        let vectors = block_on(normalized.embed_texts(vec!["a".to_string()])).unwrap();
+        assert_eq!(vectors[0].len(), 3);
+        assert!((vectors[0].iter().map(|v| v * v).sum::<f32>() - 1.0).abs() < 1e-6);
+        assert_eq!(block_on(fixed().detect_dimensions()).unwrap(), 3);
+    }
+}
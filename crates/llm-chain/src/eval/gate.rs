@@ -0,0 +1,369 @@
+//! A promotion gate comparing two versions of a chain on an evaluation suite.
+//!
+//! Before a new version of a chain replaces the current one, both are run over the same pinned `EvalSuite` and their
+//! outputs are scored by a `Judge`. The gate fails if the new version scores worse than the old one by more than a
+//! threshold, overall or on a single case.
+//!
+//! A run is recorded in a `Cassette`, which can be saved as YAML. The cassette keeps the output of the chain for each
+//! case, the prompt versions selected by `RegistryStep`s, and every exchange with the model, recorded by a
+//! `ReplayExecutor`. The cassette of the current version can be recorded once, when it is promoted, and used for every
+//! later comparison, so that only the candidate has to be run against the model. `Cassette::replay` runs the chain
+//! again on the recorded exchanges, without the model, to reproduce the outputs of a gate run.
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{EvalCase, EvalSuite};
+use crate::{
+    chains::sequential::{self, SequentialChainError},
+    output::Output,
+    prompt::PROMPT_VERSION_KEY,
+    replay::{Exchange, ReplayError, ReplayExecutor},
+    traits::Executor,
+};
+
+/// Scores the output of a chain for an evaluation case.
+#[async_trait]
+pub trait Judge: Send + Sync {
+    type Error: std::error::Error + Send;
+
+    /// Returns a score between 0 and 1, higher being better.
+    async fn score(&self, case: &EvalCase, output: &str) -> Result<f32, Self::Error>;
+}
+
+/// A `Judge` scoring an output by the share of the case's checkable expectations it meets.
+///
+/// Cases without checkable expectations score 1. Judges asking a model to grade outputs can be written by implementing
+/// `Judge`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExpectationJudge;
+
+#[async_trait]
+impl Judge for ExpectationJudge {
+    type Error = Infallible;
+
+    async fn score(&self, case: &EvalCase, output: &str) -> Result<f32, Self::Error> {
+        let checks: Vec<bool> = case
+            .expected
+            .iter()
+            .filter_map(|expectation| expectation.check(output))
+            .collect();
+        if checks.is_empty() {
+            return Ok(1.0);
+        }
+        Ok(checks.iter().filter(|&&met| met).count() as f32 / checks.len() as f32)
+    }
+}
+
+/// The recorded run of one version of a chain over an evaluation suite.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cassette {
+    /// Identifies the version of the chain that produced the outputs.
+    pub label: String,
+    /// The outputs of the chain, by case name.
+    pub outputs: BTreeMap<String, String>,
+    /// The prompt versions selected for each case, by case name, for chains with a `RegistryStep`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub prompt_versions: BTreeMap<String, String>,
+    /// The prompts sent to the model and its answers, in the order they were made.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exchanges: Vec<Exchange>,
+}
+
+impl Cassette {
+    pub fn new<S: Into<String>>(label: S) -> Self {
+        Self {
+            label: label.into(),
+            outputs: BTreeMap::new(),
+            prompt_versions: BTreeMap::new(),
+            exchanges: Vec::new(),
+        }
+    }
+
+    /// Records the output of a case.
+    pub fn record<K: Into<String>, V: Into<String>>(&mut self, case: K, output: V) {
+        self.outputs.insert(case.into(), output.into());
+    }
+
+    /// Runs `chain` on every case of `suite` and records the outputs, the prompt versions selected and the exchanges
+    /// with the model.
+    ///
+    /// Prompts already recorded by `executor`, for example with `ReplayExecutor::with_exchanges`, are answered from
+    /// the recording instead of the model.
+    pub async fn record_chain<S, E>(
+        label: S,
+        suite: &EvalSuite,
+        chain: &sequential::Chain<ReplayExecutor<E>>,
+        executor: &ReplayExecutor<E>,
+    ) -> Result<Self, SequentialChainError<ReplayError<E::Error>>>
+    where
+        S: Into<String>,
+        E: Executor + Send + Sync,
+        E::Error: Send,
+    {
+        let mut cassette = Self::new(label);
+        for case in &suite.cases {
+            let (output, parameters) = chain
+                .run_with_parameters(case.parameters(), executor)
+                .await?;
+            cassette.record(
+                case.name.as_str(),
+                output.primary_textual_output().await.unwrap_or_default(),
+            );
+            if let Some(version) = parameters.get(PROMPT_VERSION_KEY) {
+                cassette.prompt_versions.insert(case.name.clone(), version);
+            }
+        }
+        cassette.exchanges = executor.exchanges();
+        Ok(cassette)
+    }
+
+    /// Runs `chain` on every case of `suite` again, answering its prompts with the recorded exchanges only. The
+    /// replayed cassette equals this one if the chain and the suite are the ones it was recorded with; a prompt that
+    /// wasn't recorded fails with `ReplayError::NotRecorded`.
+    ///
+    /// `executor` is only used to tokenize prompts and is never invoked.
+    pub async fn replay<E>(
+        &self,
+        suite: &EvalSuite,
+        chain: &sequential::Chain<ReplayExecutor<E>>,
+        executor: E,
+    ) -> Result<Self, SequentialChainError<ReplayError<E::Error>>>
+    where
+        E: Executor + Send + Sync,
+        E::Error: Send,
+    {
+        let executor = ReplayExecutor::new(executor)
+            .with_exchanges(self.exchanges.clone())
+            .strict();
+        Self::record_chain(self.label.clone(), suite, chain, &executor).await
+    }
+
+    pub fn to_yaml(&self) -> Result<String, serde_yaml::Error> {
+        serde_yaml::to_string(self)
+    }
+
+    pub fn from_yaml(yaml: &str) -> Result<Self, serde_yaml::Error> {
+        serde_yaml::from_str(yaml)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum GateError<J: std::error::Error> {
+    #[error("The cassette {label:?} has no output for the case {case:?}")]
+    MissingOutput { label: String, case: String },
+    #[error("Error judging an output: {0}")]
+    Judge(J),
+}
+
+/// The scores of both versions on one case.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CaseScores {
+    pub case: String,
+    pub baseline: f32,
+    pub candidate: f32,
+}
+
+/// The outcome of a promotion gate.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GateReport {
+    pub cases: Vec<CaseScores>,
+    /// The mean score of the current version.
+    pub baseline_score: f32,
+    /// The mean score of the new version.
+    pub candidate_score: f32,
+    /// The cases on which the new version scores worse than allowed.
+    pub regressions: Vec<String>,
+    pub passed: bool,
+}
+
+/// Compares the outputs of two versions of a chain and decides whether the new one may replace the old one.
+pub struct PromotionGate<J: Judge> {
+    judge: J,
+    max_drop: f32,
+    max_case_drop: Option<f32>,
+}
+
+impl Default for PromotionGate<ExpectationJudge> {
+    fn default() -> Self {
+        Self::new(ExpectationJudge)
+    }
+}
+
+impl<J: Judge> PromotionGate<J> {
+    /// Creates a gate scoring outputs with `judge`, allowing the mean score to drop by at most 0.05.
+    pub fn new(judge: J) -> Self {
+        Self {
+            judge,
+            max_drop: 0.05,
+            max_case_drop: None,
+        }
+    }
+
+    /// Sets how much the mean score may drop.
+    pub fn with_max_drop(mut self, max_drop: f32) -> Self {
+        self.max_drop = max_drop;
+        self
+    }
+
+    /// Also fails the gate if the score of any single case drops by more than `max_case_drop`. By default only the
+    /// mean score is compared.
+    pub fn with_max_case_drop(mut self, max_case_drop: f32) -> Self {
+        self.max_case_drop = Some(max_case_drop);
+        self
+    }
+
+    /// Scores both cassettes on every case of `suite` and compares them.
+    pub async fn evaluate(
+        &self,
+        suite: &EvalSuite,
+        baseline: &Cassette,
+        candidate: &Cassette,
+    ) -> Result<GateReport, GateError<J::Error>> {
+        let mut cases = Vec::with_capacity(suite.cases.len());
+        for case in &suite.cases {
+            cases.push(CaseScores {
+                case: case.name.clone(),
+                baseline: self.score(case, baseline).await?,
+                candidate: self.score(case, candidate).await?,
+            });
+        }
+        let mean = |score: fn(&CaseScores) -> f32| {
+            cases.iter().map(score).sum::<f32>() / cases.len().max(1) as f32
+        };
+        let baseline_score = mean(|c| c.baseline);
+        let candidate_score = mean(|c| c.candidate);
+        let regressions: Vec<String> = match self.max_case_drop {
+            Some(max_case_drop) => cases
+                .iter()
+                .filter(|c| c.baseline - c.candidate > max_case_drop)
+                .map(|c| c.case.clone())
+                .collect(),
+            None => Vec::new(),
+        };
+        let passed = baseline_score - candidate_score <= self.max_drop && regressions.is_empty();
+        Ok(GateReport {
+            cases,
+            baseline_score,
+            candidate_score,
+            regressions,
+            passed,
+        })
+    }
+
+    async fn score(
+        &self,
+        case: &EvalCase,
+        cassette: &Cassette,
+    ) -> Result<f32, GateError<J::Error>> {
+        let output = cassette
+            .outputs
+            .get(&case.name)
+            .ok_or_else(|| GateError::MissingOutput {
+                label: cassette.label.clone(),
+                case: case.name.clone(),
+            })?;
+        self.judge
+            .score(case, output)
+            .await
+            .map_err(GateError::Judge)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cassette, PromotionGate};
+    use crate::agents::mock::{MockExecutor, MockOutput};
+    use crate::chains::sequential::Chain;
+    use crate::chains::sequential::{ChainStep, SequentialChainError};
+    use crate::eval::{EvalCase, EvalSuite, Expectation};
+    use crate::prompt::{PromptRegistry, RegistryStep};
+    use crate::replay::ReplayExecutor;
+    use futures::executor::block_on;
+    use std::fs;
+    use std::sync::Arc;
+
+    #[test]
+    fn fails_when_scores_drop() {
+        let case = |name: &str| EvalCase {
+            name: name.to_string(),
+            inputs: Default::default(),
+            expected: vec![
+                Expectation::ValidJson,
+                Expectation::Contains {
+                    text: "answer".to_string(),
+                },
+            ],
+        };
+        let suite = EvalSuite {
+            inputs: vec![],
+            cases: vec![case("a"), case("b")],
+        };
+        let mut baseline = Cassette::new("v1");
+        baseline.record("a", r#"{"answer": 1}"#);
+        baseline.record("b", r#"{"answer": 2}"#);
+        let mut candidate = Cassette::new("v2");
+        candidate.record("a", r#"{"answer": 1}"#);
+        candidate.record("b", "answer: 2");
+
+        let gate = PromotionGate::default().with_max_drop(0.3);
+        let report = block_on(gate.evaluate(&suite, &baseline, &candidate)).unwrap();
+        assert_eq!(report.candidate_score, 0.75);
+        assert!(report.passed);
+
+        let gate = gate.with_max_case_drop(0.2);
+        let report = block_on(gate.evaluate(&suite, &baseline, &candidate)).unwrap();
+        assert_eq!(report.regressions, vec!["b"]);
+        assert!(!report.passed);
+
+        candidate.outputs.remove("b");
+        assert!(block_on(gate.evaluate(&suite, &baseline, &candidate)).is_err());
+    }
+
+    #[test]
+    fn recorded_runs_can_be_replayed() {
+        let root = std::env::temp_dir().join(format!("llm-chain-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("greet@v1.txt"), "Hi {{name}}").unwrap();
+        fs::write(root.join("greet@v2.txt"), "Hello {{name}}").unwrap();
+        let registry = Arc::new(PromptRegistry::from_dir(&root).unwrap());
+        let chain = Chain::from_steps(vec![ChainStep::custom(RegistryStep::new(
+            registry, "greet",
+        ))]);
+        let case = |name: &str| EvalCase {
+            name: name.to_string(),
+            inputs: [("name".to_string(), name.to_string())].into(),
+            expected: vec![],
+        };
+        let suite = EvalSuite {
+            inputs: vec!["name".to_string()],
+            cases: vec![case("Ada"), case("Alan")],
+        };
+        let executor = ReplayExecutor::new(MockExecutor::new(vec![
+            MockOutput::text("Hello!"),
+            MockOutput::text("Hi!"),
+        ]));
+        let cassette = block_on(Cassette::record_chain("v1", &suite, &chain, &executor)).unwrap();
+        assert_eq!(cassette.outputs["Alan"], "Hi!");
+        assert_eq!(cassette.prompt_versions["Ada"], "v2");
+        assert_eq!(cassette.exchanges[1].prompt, "Hello Alan");
+
+        let cassette = Cassette::from_yaml(&cassette.to_yaml().unwrap()).unwrap();
+        let replayed =
+            block_on(cassette.replay(&suite, &chain, MockExecutor::new(vec![]))).unwrap();
+        assert_eq!(replayed, cassette);
+
+        let suite = EvalSuite {
+            cases: vec![case("Grace")],
+            ..suite
+        };
+        assert!(matches!(
+            block_on(cassette.replay(&suite, &chain, MockExecutor::new(vec![]))),
+            Err(SequentialChainError::CustomStep(error)) if error.to_string().contains("Hello Grace")
+        ));
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,208 @@
+//! Skeleton evaluation cases for existing chains.
+//!
+//! Writing the first tests for a pipeline is mostly bookkeeping: finding out which parameters it needs and writing a
+//! case for each. `EvalSuite::for_sequential_chain` does that bookkeeping by analyzing the variables of the chain's
+//! prompt templates. It generates a typical case and one case per input left empty, with placeholder values and
+//! expectation stubs to fill in. Suites serialize to YAML, so they can be generated once and then edited by hand.
+//!
+//! # Example
+//!
+//! ```
+//! use llm_chain::eval::EvalSuite;
+//! use llm_chain::prompt;
+//!
+//! let summarize = prompt!("Summarize this {{ language }} text as JSON: {{ text }}");
+//! let suite = EvalSuite::for_prompts(&[&summarize]);
+//! assert_eq!(suite.inputs, vec!["language", "text"]);
+//! assert_eq!(suite.cases.len(), 3);
+//! println!("{}", suite.to_yaml().unwrap());
+//! ```
+//!
+//! The `gate` module compares two versions of a chain on a suite before the new one is deployed.
+pub mod gate;
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chains::sequential::{self, ChainStep},
+    prompt::PromptTemplate,
+    traits::Executor,
+    Parameters,
+};
+
+/// A property the output of a chain is expected to have.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "check", rename_all = "snake_case")]
+pub enum Expectation {
+    /// The output is not blank.
+    NonEmpty,
+    /// The output contains `text`.
+    Contains { text: String },
+    /// The output parses as JSON.
+    ValidJson,
+    /// A property still to be written, described in words.
+    Todo { description: String },
+}
+
+impl Expectation {
+    fn todo<S: Into<String>>(description: S) -> Self {
+        Self::Todo {
+            description: description.into(),
+        }
+    }
+
+    /// Checks `output` against this expectation. Returns `None` for expectations that can't be checked yet.
+    pub fn check(&self, output: &str) -> Option<bool> {
+        match self {
+            Self::NonEmpty => Some(!output.trim().is_empty()),
+            Self::Contains { text } => Some(output.contains(text.as_str())),
+            Self::ValidJson => Some(serde_json::from_str::<serde_json::Value>(output).is_ok()),
+            Self::Todo { .. } => None,
+        }
+    }
+}
+
+/// A single evaluation case: the inputs of a chain run and the properties its output should have.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvalCase {
+    pub name: String,
+    pub inputs: BTreeMap<String, String>,
+    pub expected: Vec<Expectation>,
+}
+
+impl EvalCase {
+    /// Returns the inputs of the case as `Parameters` for running the chain.
+    pub fn parameters(&self) -> Parameters {
+        self.inputs
+            .iter()
+            .fold(Parameters::new(), |parameters, (key, value)| {
+                parameters.with(key.as_str(), value.as_str())
+            })
+    }
+}
+
+/// A set of evaluation cases for a chain, together with the inputs the chain needs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvalSuite {
+    pub inputs: Vec<String>,
+    pub cases: Vec<EvalCase>,
+}
+
+impl EvalSuite {
+    /// Generates cases for a sequential chain.
+    ///
+    /// Only prompt steps are analyzed. Parameters that custom steps add for the steps after them are treated as
+    /// inputs of the chain, so the generated inputs may need trimming for chains with custom steps.
+    pub fn for_sequential_chain<E: Executor>(chain: &sequential::Chain<E>) -> Self {
+        let prompts: Vec<&PromptTemplate> = chain
+            .steps()
+            .iter()
+            .filter_map(|step| match step {
+                ChainStep::Prompt(step) => Some(step.prompt()),
+                ChainStep::Custom(_) => None,
+            })
+            .collect();
+        Self::for_prompts(&prompts)
+    }
+
+    /// Generates cases for prompts that are run in sequence, each receiving the output of the previous one as `text`.
+    pub fn for_prompts(prompts: &[&PromptTemplate]) -> Self {
+        let inputs = chain_inputs(prompts);
+        let asks_for_json = prompts
+            .last()
+            .is_some_and(|prompt| prompt.to_string().to_lowercase().contains("json"));
+
+        let typical: BTreeMap<String, String> = inputs
+            .iter()
+            .map(|name| {
+                (
+                    name.clone(),
+                    format!("TODO: a typical value for `{}`", name),
+                )
+            })
+            .collect();
+        let mut expected = vec![Expectation::NonEmpty];
+        if asks_for_json {
+            expected.push(Expectation::ValidJson);
+        }
+        expected.push(Expectation::todo("describe what a good output contains"));
+
+        let mut cases = vec![EvalCase {
+            name: "typical".to_string(),
+            inputs: typical.clone(),
+            expected,
+        }];
+        for name in &inputs {
+            let mut inputs = typical.clone();
+            inputs.insert(name.clone(), String::new());
+            cases.push(EvalCase {
+                name: format!("empty_{}", name),
+                inputs,
+                expected: vec![Expectation::todo(format!(
+                    "describe how the chain should handle an empty `{}`",
+                    name
+                ))],
+            });
+        }
+        Self { inputs, cases }
+    }
+
+    /// Serializes the suite to YAML.
+    pub fn to_yaml(&self) -> Result<String, serde_yaml::Error> {
+        serde_yaml::to_string(self)
+    }
+
+    /// Reads a suite from YAML.
+    pub fn from_yaml(yaml: &str) -> Result<Self, serde_yaml::Error> {
+        serde_yaml::from_str(yaml)
+    }
+}
+
+/// Returns the parameters that have to be provided to run `prompts` in sequence.
+///
+/// The first prompt needs all of its variables. The following prompts receive the output of the previous one as
+/// `text`, so only their other variables are inputs of the chain.
+pub fn chain_inputs(prompts: &[&PromptTemplate]) -> Vec<String> {
+    let mut inputs: Vec<String> = Vec::new();
+    for (i, prompt) in prompts.iter().enumerate() {
+        for name in prompt.variables() {
+            let provided = i > 0 && name == crate::parameters::TEXT_KEY;
+            if !provided && !inputs.contains(&name) {
+                inputs.push(name);
+            }
+        }
+    }
+    inputs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{chain_inputs, EvalSuite, Expectation};
+    use crate::prompt;
+
+    #[test]
+    fn later_steps_receive_text() {
+        let summarize = prompt!(
+            "You summarize {% if style %}in a {{ style }} style{% endif %}.",
+            "{{ text }}"
+        );
+        let tweet = prompt!(
+            "Write a tweet about {{ text }} for {{ audience | default(value='everyone') }}"
+        );
+        assert_eq!(
+            chain_inputs(&[&summarize, &tweet]),
+            vec!["style", "text", "audience"]
+        );
+
+        let suite = EvalSuite::for_prompts(&[&tweet]);
+        assert_eq!(suite.cases[1].name, "empty_text");
+        assert_eq!(suite.cases[1].parameters().get("text").unwrap(), "");
+        assert_eq!(
+            EvalSuite::from_yaml(&suite.to_yaml().unwrap()).unwrap(),
+            suite
+        );
+        assert_eq!(Expectation::NonEmpty.check(" "), Some(false));
+    }
+}
@@ -0,0 +1,580 @@
+//! Sharing a single executor between interactive and batch workloads.
+//!
+//! Most providers enforce a quota per API key, so an application that serves chat requests while running a large
+//! map-reduce job in the background ends up with both workloads competing for the same capacity. The `ExecutorPool`
+//! limits how many invocations are in flight at once and hands out free slots by priority class: waiting
+//! [`Priority::Interactive`] requests are always admitted before waiting [`Priority::Batch`] requests, except that a
+//! batch request is let through after a configurable number of consecutive interactive admissions so batch work
+//! can't be starved indefinitely.
+//!
+//! Once the pool is shut down, invocations fail with [`PooledExecutorError::ShutDown`].
+//!
+//! # Example
+//!
+//! ```ignore
+//! let pool = ExecutorPool::new(executor, 8);
+//! // Hand the interactive handle to the chat endpoint...
+//! let chat_exec = pool.handle(Priority::Interactive);
+//! // ...and the batch handle to the summarization job.
+//! let batch_exec = pool.handle(Priority::Batch);
+//! ```
+use std::collections::VecDeque;
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use futures::channel::oneshot;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use thiserror::Error;
+
+use crate::json_schema::JsonSchema;
+use crate::lifecycle::{BoxedShutdownError, Shutdown};
+use crate::output::OutputStream;
+use crate::prompt::Prompt;
+use crate::tokens::{PromptTokensError, TokenCount, TokenizerError};
+use crate::tools::ToolDefinition;
+use crate::traits::{self, ExecutorCreationError, ExecutorError};
+
+/// The default number of concurrent invocations when a pool is created through `Executor::new_with_options`.
+const DEFAULT_MAX_CONCURRENT: usize = 4;
+/// The default number of consecutive interactive admissions after which a waiting batch request is admitted.
+const DEFAULT_STARVATION_LIMIT: usize = 8;
+
+/// The priority class of an invocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// Latency sensitive work, such as a user waiting for a chat response.
+    Interactive,
+    /// Throughput oriented work, such as map-reduce over a large corpus.
+    Batch,
+}
+
+struct LimiterState {
+    available: usize,
+    interactive: VecDeque<oneshot::Sender<Permit>>,
+    batch: VecDeque<oneshot::Sender<Permit>>,
+    interactive_streak: usize,
+}
+
+impl LimiterState {
+    /// Picks the next waiter to hand a free slot to, if any.
+    fn next_waiter(&mut self, starvation_limit: usize) -> Option<oneshot::Sender<Permit>> {
+        let batch_is_starving =
+            !self.batch.is_empty() && self.interactive_streak >= starvation_limit;
+        if !batch_is_starving {
+            if let Some(waiter) = self.interactive.pop_front() {
+                self.interactive_streak += 1;
+                return Some(waiter);
+            }
+        }
+        let waiter = self.batch.pop_front()?;
+        self.interactive_streak = 0;
+        Some(waiter)
+    }
+}
+
+/// A concurrency limiter that admits waiting requests by priority class.
+///
+/// Slots are acquired with [`PriorityLimiter::acquire`] and returned when the resulting [`Permit`] is dropped.
+/// The limiter does not depend on any particular async runtime.
+pub struct PriorityLimiter {
+    state: Mutex<LimiterState>,
+    max_concurrent: usize,
+    starvation_limit: usize,
+}
+
+impl PriorityLimiter {
+    /// Creates a limiter that allows at most `max_concurrent` permits to be held at once.
+    pub fn new(max_concurrent: usize) -> Self {
+        Self {
+            state: Mutex::new(LimiterState {
+                available: max_concurrent,
+                interactive: VecDeque::new(),
+                batch: VecDeque::new(),
+                interactive_streak: 0,
+            }),
+            max_concurrent,
+            starvation_limit: DEFAULT_STARVATION_LIMIT,
+        }
+    }
+
+    /// Sets how many interactive requests may be admitted in a row while batch requests are waiting.
+    ///
+    /// A value of `0` admits every waiting batch request before any waiting interactive one.
+    pub fn with_starvation_limit(mut self, starvation_limit: usize) -> Self {
+        self.starvation_limit = starvation_limit;
+        self
+    }
+
+    /// Waits for a free slot for a request of the given priority.
+    pub async fn acquire(self: &Arc<Self>, priority: Priority) -> Permit {
+        let receiver = {
+            let mut state = self.state.lock().expect("limiter lock poisoned");
+            let nobody_waiting = state.interactive.is_empty() && state.batch.is_empty();
+            if state.available > 0 && nobody_waiting {
+                state.available -= 1;
+                if priority == Priority::Interactive {
+                    state.interactive_streak += 1;
+                } else {
+                    state.interactive_streak = 0;
+                }
+                return Permit::new(self.clone());
+            }
+            let (sender, receiver) = oneshot::channel();
+            match priority {
+                Priority::Interactive => state.interactive.push_back(sender),
+                Priority::Batch => state.batch.push_back(sender),
+            }
+            receiver
+        };
+        // The sender is only dropped without sending when the limiter itself is dropped, which can't happen
+        // while we hold an `Arc` to it.
+        receiver.await.expect("limiter dropped while waiting")
+    }
+
+    /// Waits until every request admitted or queued before the call has finished, and returns permits for all
+    /// slots. No other request is admitted until the permits are dropped.
+    pub async fn acquire_all(self: &Arc<Self>) -> Vec<Permit> {
+        let mut permits = Vec::with_capacity(self.max_concurrent);
+        for _ in 0..self.max_concurrent {
+            permits.push(self.acquire(Priority::Batch).await);
+        }
+        permits
+    }
+
+    /// Returns the number of requests currently waiting for the given priority class.
+    pub fn waiting(&self, priority: Priority) -> usize {
+        let state = self.state.lock().expect("limiter lock poisoned");
+        match priority {
+            Priority::Interactive => state.interactive.len(),
+            Priority::Batch => state.batch.len(),
+        }
+    }
+
+    fn release(self: &Arc<Self>) {
+        loop {
+            let waiter = {
+                let mut state = self.state.lock().expect("limiter lock poisoned");
+                match state.next_waiter(self.starvation_limit) {
+                    Some(waiter) => waiter,
+                    None => {
+                        state.available += 1;
+                        return;
+                    }
+                }
+            };
+            // If the waiting future was dropped we get the permit back, and try the next waiter without releasing it.
+            match waiter.send(Permit::new(self.clone())) {
+                Ok(()) => return,
+                Err(mut returned) => returned.limiter = None,
+            }
+        }
+    }
+}
+
+/// A slot in a [`PriorityLimiter`]. The slot is released when the permit is dropped.
+pub struct Permit {
+    /// The limiter to release the slot to, or `None` once the slot has been handed to another permit.
+    limiter: Option<Arc<PriorityLimiter>>,
+}
+
+impl Permit {
+    fn new(limiter: Arc<PriorityLimiter>) -> Self {
+        Self {
+            limiter: Some(limiter),
+        }
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(limiter) = self.limiter.take() {
+            limiter.release();
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum PooledExecutorError<E: Error> {
+    #[error(transparent)]
+    Executor(E),
+    #[error("The executor pool has been shut down")]
+    ShutDown,
+}
+
+impl<E: Error> ExecutorError for PooledExecutorError<E> {}
+
+/// How far the shutdown of a pool has gone.
+#[derive(Default)]
+struct ShutdownState {
+    /// Set when the shutdown starts, after which no new invocation is accepted.
+    started: AtomicBool,
+    /// Set once the executor is shut down, after which not even the invocations queued before can run.
+    done: AtomicBool,
+}
+
+/// A pool sharing one executor, and its quota, between requests of different priorities.
+pub struct ExecutorPool<E> {
+    executor: Arc<E>,
+    limiter: Arc<PriorityLimiter>,
+    shutdown: Arc<ShutdownState>,
+}
+
+impl<E> Clone for ExecutorPool<E> {
+    fn clone(&self) -> Self {
+        Self {
+            executor: self.executor.clone(),
+            limiter: self.limiter.clone(),
+            shutdown: self.shutdown.clone(),
+        }
+    }
+}
+
+impl<E> ExecutorPool<E> {
+    /// Waits for a slot for an invocation, unless the pool is shut down.
+    async fn admit<X: Error>(&self, priority: Priority) -> Result<Permit, PooledExecutorError<X>> {
+        if self.shutdown.started.load(Ordering::SeqCst) {
+            return Err(PooledExecutorError::ShutDown);
+        }
+        let permit = self.limiter.acquire(priority).await;
+        if self.shutdown.done.load(Ordering::SeqCst) {
+            return Err(PooledExecutorError::ShutDown);
+        }
+        Ok(permit)
+    }
+}
+
+impl<E: traits::Executor> ExecutorPool<E> {
+    /// Creates a pool that allows at most `max_concurrent` invocations of `executor` at once.
+    pub fn new(executor: E, max_concurrent: usize) -> Self {
+        Self::with_limiter(executor, PriorityLimiter::new(max_concurrent))
+    }
+
+    /// Creates a pool using a preconfigured limiter.
+    pub fn with_limiter(executor: E, limiter: PriorityLimiter) -> Self {
+        Self {
+            executor: Arc::new(executor),
+            limiter: Arc::new(limiter),
+            shutdown: Arc::default(),
+        }
+    }
+
+    /// Returns an executor that runs invocations through this pool with the given priority.
+    pub fn handle(&self, priority: Priority) -> PooledExecutor<E> {
+        PooledExecutor {
+            pool: self.clone(),
+            priority,
+        }
+    }
+
+    /// Returns the limiter used by this pool.
+    pub fn limiter(&self) -> &Arc<PriorityLimiter> {
+        &self.limiter
+    }
+
+    /// Stops accepting invocations, waits for in-flight and queued ones to finish, then shuts down the underlying
+    /// executor.
+    pub async fn shutdown(&self) -> Result<(), E::Error>
+    where
+        E: Sync,
+    {
+        self.shutdown.started.store(true, Ordering::SeqCst);
+        let _permits = self.limiter.acquire_all().await;
+        let result = self.executor.shutdown().await;
+        self.shutdown.done.store(true, Ordering::SeqCst);
+        result
+    }
+}
+
+#[async_trait]
+impl<E> Shutdown for ExecutorPool<E>
+where
+    E: traits::Executor + Send + Sync,
+    E::Error: Send + Sync + 'static,
+{
+    async fn shutdown(&self) -> Result<(), BoxedShutdownError> {
+        ExecutorPool::shutdown(self).await.map_err(Into::into)
+    }
+}
+
+/// An executor handle obtained from an [`ExecutorPool`].
+///
+/// Every call to `execute` waits for a slot in the pool before invoking the underlying executor, and fails once the
+/// pool is shut down. All other methods are forwarded directly.
+pub struct PooledExecutor<E> {
+    pool: ExecutorPool<E>,
+    priority: Priority,
+}
+
+impl<E> Clone for PooledExecutor<E> {
+    fn clone(&self) -> Self {
+        Self {
+            pool: self.pool.clone(),
+            priority: self.priority,
+        }
+    }
+}
+
+impl<E> PooledExecutor<E> {
+    /// Returns the priority class used by this handle.
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+}
+
+#[async_trait]
+impl<E> traits::Executor for PooledExecutor<E>
+where
+    E: traits::Executor + Send + Sync,
+{
+    type PerInvocationOptions = E::PerInvocationOptions;
+    type PerExecutorOptions = E::PerExecutorOptions;
+    type Output = E::Output;
+    type Error = PooledExecutorError<E::Error>;
+    type Token = E::Token;
+    type StepTokenizer<'a>
+        = E::StepTokenizer<'a>
+    where
+        Self: 'a;
+    type TextSplitter<'a>
+        = E::TextSplitter<'a>
+    where
+        Self: 'a;
+
+    /// Creates a new pool around a freshly created executor and returns an interactive handle to it.
+    fn new_with_options(
+        executor_options: Option<Self::PerExecutorOptions>,
+        invocation_options: Option<Self::PerInvocationOptions>,
+    ) -> Result<Self, ExecutorCreationError> {
+        let executor = E::new_with_options(executor_options, invocation_options)?;
+        Ok(ExecutorPool::new(executor, DEFAULT_MAX_CONCURRENT).handle(Priority::Interactive))
+    }
+
+    async fn execute(
+        &self,
+        options: Option<&Self::PerInvocationOptions>,
+        prompt: &Prompt,
+        is_streaming: Option<bool>,
+    ) -> Result<Self::Output, Self::Error> {
+        let _permit = self.pool.admit(self.priority).await?;
+        self.pool
+            .executor
+            .execute(options, prompt, is_streaming)
+            .await
+            .map_err(PooledExecutorError::Executor)
+    }
+
+    /// Holds the slot until the stream is dropped, since the invocation runs for as long as it is consumed.
+    fn execute_stream<'a>(
+        &'a self,
+        options: Option<&Self::PerInvocationOptions>,
+        prompt: &Prompt,
+    ) -> OutputStream<'a, Self::Error>
+    where
+        Self: Sync,
+    {
+        let options = options.cloned();
+        let prompt = prompt.clone();
+        stream::once(async move {
+            let permit = self.pool.admit(self.priority).await?;
+            Ok::<_, Self::Error>(
+                self.pool
+                    .executor
+                    .execute_stream(options.as_ref(), &prompt)
+                    .map(move |chunk| {
+                        let _permit = &permit;
+                        chunk.map_err(PooledExecutorError::Executor)
+                    }),
+            )
+        })
+        .try_flatten()
+        .boxed()
+    }
+
+    fn tokens_used(
+        &self,
+        options: Option<&Self::PerInvocationOptions>,
+        prompt: &Prompt,
+    ) -> Result<TokenCount, PromptTokensError> {
+        self.pool.executor.tokens_used(options, prompt)
+    }
+
+    fn max_tokens_allowed(&self, options: Option<&Self::PerInvocationOptions>) -> i32 {
+        self.pool.executor.max_tokens_allowed(options)
+    }
+
+    fn answer_prefix(&self, prompt: &Prompt) -> Option<String> {
+        self.pool.executor.answer_prefix(prompt)
+    }
+
+    fn supports_images(&self) -> bool {
+        self.pool.executor.supports_images()
+    }
+
+    fn json_schema_options(
+        &self,
+        options: Option<&Self::PerInvocationOptions>,
+        schema: &JsonSchema,
+    ) -> Option<Self::PerInvocationOptions> {
+        self.pool.executor.json_schema_options(options, schema)
+    }
+
+    fn tool_options(
+        &self,
+        options: Option<&Self::PerInvocationOptions>,
+        tools: &[ToolDefinition],
+    ) -> Option<Self::PerInvocationOptions> {
+        self.pool.executor.tool_options(options, tools)
+    }
+
+    fn get_tokenizer(
+        &self,
+        options: Option<&Self::PerInvocationOptions>,
+    ) -> Result<Self::StepTokenizer<'_>, TokenizerError> {
+        self.pool.executor.get_tokenizer(options)
+    }
+
+    fn get_text_splitter(
+        &self,
+        options: Option<&Self::PerInvocationOptions>,
+    ) -> Result<Self::TextSplitter<'_>, Self::Error> {
+        self.pool
+            .executor
+            .get_text_splitter(options)
+            .map_err(PooledExecutorError::Executor)
+    }
+
+    /// Shuts down the whole pool, see `ExecutorPool::shutdown`.
+    async fn shutdown(&self) -> Result<(), Self::Error> {
+        self.pool
+            .shutdown()
+            .await
+            .map_err(PooledExecutorError::Executor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ExecutorPool, PooledExecutorError, Priority, PriorityLimiter};
+    use crate::agents::mock::{MockExecutor, MockOutput};
+    use crate::output::Output;
+    use crate::prompt::Prompt;
+    use crate::traits::Executor;
+    use futures::FutureExt;
+    use std::sync::Arc;
+
+    #[test]
+    fn interactive_requests_are_admitted_before_batch() {
+        let limiter = Arc::new(PriorityLimiter::new(1));
+        let held = limiter.acquire(Priority::Batch).now_or_never().unwrap();
+
+        let mut batch = Box::pin(limiter.acquire(Priority::Batch));
+        assert!((&mut batch).now_or_never().is_none());
+        let mut interactive = Box::pin(limiter.acquire(Priority::Interactive));
+        assert!((&mut interactive).now_or_never().is_none());
+
+        drop(held);
+        let permit = (&mut interactive)
+            .now_or_never()
+            .expect("interactive admitted");
+        assert!((&mut batch).now_or_never().is_none());
+        drop(permit);
+        assert!(batch.now_or_never().is_some());
+    }
+
+    #[test]
+    fn batch_requests_are_not_starved() {
+        let limiter = Arc::new(PriorityLimiter::new(1).with_starvation_limit(2));
+        let mut current = limiter
+            .acquire(Priority::Interactive)
+            .now_or_never()
+            .unwrap();
+
+        let mut batch = Box::pin(limiter.acquire(Priority::Batch));
+        assert!((&mut batch).now_or_never().is_none());
+
+        // One interactive request has already been admitted, so only one more can jump the queue.
+        let mut interactive = Box::pin(limiter.acquire(Priority::Interactive));
+        assert!((&mut interactive).now_or_never().is_none());
+        drop(current);
+        current = (&mut interactive).now_or_never().unwrap();
+
+        let mut interactive = Box::pin(limiter.acquire(Priority::Interactive));
+        assert!((&mut interactive).now_or_never().is_none());
+        drop(current);
+        let _batch_permit = (&mut batch).now_or_never().expect("batch admitted");
+        assert!(interactive.now_or_never().is_none());
+        assert_eq!(limiter.waiting(Priority::Interactive), 1);
+    }
+
+    #[test]
+    fn releasing_permits_keeps_no_reference_to_the_limiter() {
+        let limiter = Arc::new(PriorityLimiter::new(1));
+        let held = limiter.acquire(Priority::Batch).now_or_never().unwrap();
+        let mut abandoned = Box::pin(limiter.acquire(Priority::Batch));
+        assert!((&mut abandoned).now_or_never().is_none());
+        let mut waiting = Box::pin(limiter.acquire(Priority::Batch));
+        assert!((&mut waiting).now_or_never().is_none());
+        drop(abandoned);
+
+        // The slot skips the abandoned request.
+        drop(held);
+        drop(waiting.now_or_never().unwrap());
+        drop(
+            limiter
+                .acquire(Priority::Interactive)
+                .now_or_never()
+                .unwrap(),
+        );
+        assert_eq!(Arc::strong_count(&limiter), 1);
+    }
+
+    #[test]
+    fn a_starvation_limit_of_zero_admits_batch_requests_first() {
+        let limiter = Arc::new(PriorityLimiter::new(1).with_starvation_limit(0));
+        let held = limiter.acquire(Priority::Batch).now_or_never().unwrap();
+        let mut interactive = Box::pin(limiter.acquire(Priority::Interactive));
+        assert!((&mut interactive).now_or_never().is_none());
+        let mut batch = Box::pin(limiter.acquire(Priority::Batch));
+        assert!((&mut batch).now_or_never().is_none());
+
+        drop(held);
+        assert!((&mut interactive).now_or_never().is_none());
+        drop(batch.now_or_never().expect("batch admitted"));
+        assert!(interactive.now_or_never().is_some());
+    }
+
+    #[test]
+    fn shutdown_drains_queued_invocations_and_rejects_new_ones() {
+        let pool = ExecutorPool::new(MockExecutor::new(vec![MockOutput::text("queued")]), 1);
+        let handle = pool.handle(Priority::Interactive);
+        let prompt = Prompt::text("Hi".to_string());
+        let in_flight = pool
+            .limiter()
+            .acquire(Priority::Batch)
+            .now_or_never()
+            .unwrap();
+        let mut queued = Box::pin(handle.execute(None, &prompt, None));
+        assert!((&mut queued).now_or_never().is_none());
+
+        let mut shutdown = Box::pin(pool.shutdown());
+        assert!((&mut shutdown).now_or_never().is_none());
+        assert!(matches!(
+            handle.execute(None, &prompt, None).now_or_never(),
+            Some(Err(PooledExecutorError::ShutDown))
+        ));
+
+        drop(in_flight);
+        let output = queued.now_or_never().unwrap().unwrap();
+        assert_eq!(
+            output.primary_textual_output().now_or_never().unwrap(),
+            Some("queued".to_string())
+        );
+        assert!(shutdown.now_or_never().unwrap().is_ok());
+        assert!(matches!(
+            handle.execute(None, &prompt, None).now_or_never(),
+            Some(Err(PooledExecutorError::ShutDown))
+        ));
+    }
+}
@@ -7,11 +7,20 @@
 //! The `Frame` struct is generic over the `Step` and `Executor` types, ensuring that it can work with any
 //! combination of types that implement the required traits.
 
-use crate::step::Step;
+use crate::json_schema::{self, SchemaValidationError};
+use crate::output::{Output, OutputStream};
+use crate::prompt::{Data, Prompt, StringTemplate};
+use crate::step::{feedback_prompt, Step};
 use crate::traits;
 use crate::traits::ExecutorError;
 use crate::Parameters;
 
+/// A formatted prompt, with the options to execute it with.
+type PreparedPrompt<E> = (
+    Prompt,
+    Option<<E as traits::Executor>::PerInvocationOptions>,
+);
+
 /// The `Frame` struct represents a combination of a `Step` and an `Executor`.
 ///
 /// It is designed to provide a simple interface for working with different chain types and handling common
@@ -40,15 +49,103 @@ where
     ///
     /// This function takes a reference to a `Parameters` struct, formats the step with the provided parameters,
     /// and executes it using the associated executor. The result of the execution is returned as `E::Output`.
+    ///
+    /// If the step has a JSON Schema, it constrains generation through the options returned by
+    /// `Executor::json_schema_options`, or is added to the prompt for executors returning none; the output is then
+    /// validated against the schema.
     pub async fn format_and_execute(
         &self,
         parameters: &Parameters,
     ) -> Result<E::Output, FormatAndExecuteError<E::Error>> {
+        let (prompt, options) = self.prepare(parameters)?;
+        self.execute(&prompt, options.as_ref(), self.step.is_streaming())
+            .await
+    }
+
+    /// Formats and executes the step again, with `answer`, an invalid output of the step, and a request to fix
+    /// `error` appended to the prompt.
+    ///
+    /// The JSON Schema of the step is applied and checked as in `format_and_execute`, so that repair attempts run
+    /// with the same options and instructions as the first one. The output is not streamed.
+    pub(crate) async fn format_and_execute_with_feedback(
+        &self,
+        parameters: &Parameters,
+        answer: &str,
+        error: &str,
+    ) -> Result<E::Output, FormatAndExecuteError<E::Error>> {
+        let (prompt, options) = self.prepare(parameters)?;
+        let prompt = feedback_prompt(&prompt, answer, error);
+        self.execute(&prompt, options.as_ref(), None).await
+    }
+
+    /// Executes `prompt`, validating the output against the JSON Schema of the step if it has one.
+    async fn execute(
+        &self,
+        prompt: &Prompt,
+        options: Option<&E::PerInvocationOptions>,
+        is_streaming: Option<bool>,
+    ) -> Result<E::Output, FormatAndExecuteError<E::Error>> {
+        let output = self.executor.execute(options, prompt, is_streaming).await?;
+        if let Some(schema) = self.step.json_schema() {
+            let text = output.primary_textual_output().await.unwrap_or_default();
+            json_schema::validate_text(schema, &text)
+                .map_err(|source| FormatAndExecuteError::SchemaValidation { text, source })?;
+        }
+        Ok(output)
+    }
+
+    /// Formats the step with the provided parameters and streams its execution with `Executor::execute_stream`.
+    ///
+    /// The JSON Schema of the step is applied as in `format_and_execute`, but the output can't be validated against
+    /// it before it has been streamed to the caller.
+    pub fn format_and_stream(
+        &self,
+        parameters: &Parameters,
+    ) -> Result<OutputStream<'l, E::Error>, FormatAndExecuteError<E::Error>>
+    where
+        E: Sync,
+    {
+        let (prompt, options) = self.prepare(parameters)?;
+        Ok(self.executor.execute_stream(options.as_ref(), &prompt))
+    }
+
+    /// Returns the prompt to execute and the options to execute it with, taking the JSON Schema of the step into
+    /// account.
+    fn prepare(
+        &self,
+        parameters: &Parameters,
+    ) -> Result<PreparedPrompt<E>, FormatAndExecuteError<E::Error>> {
+        let options = self.step.options();
+        let Some(schema) = self.step.json_schema() else {
+            return Ok((self.format(parameters)?, options.cloned()));
+        };
+        match self.executor.json_schema_options(options, schema) {
+            Some(options) => Ok((self.format(parameters)?, Some(options))),
+            None => {
+                let instructions = Data::Text(StringTemplate::static_string(schema.instructions()));
+                let prompt = self
+                    .step
+                    .prompt()
+                    .append(&instructions)
+                    .format(parameters)?;
+                self.check_images(&prompt)?;
+                Ok((prompt, options.cloned()))
+            }
+        }
+    }
+
+    /// Formats the prompt of the step, checking that the executor supports its contents.
+    fn format(&self, parameters: &Parameters) -> Result<Prompt, FormatAndExecuteError<E::Error>> {
         let prompt = self.step.format(parameters)?;
-        Ok(self
-            .executor
-            .execute(self.step.options(), &prompt, self.step.is_streaming())
-            .await?)
+        self.check_images(&prompt)?;
+        Ok(prompt)
+    }
+
+    fn check_images(&self, prompt: &Prompt) -> Result<(), FormatAndExecuteError<E::Error>> {
+        if prompt.has_images() && !self.executor.supports_images() {
+            return Err(FormatAndExecuteError::UnsupportedImages);
+        }
+        Ok(())
     }
 }
 
@@ -59,4 +156,13 @@ pub enum FormatAndExecuteError<E: ExecutorError> {
     Format(#[from] crate::prompt::StringTemplateError),
     #[error("Error executing: {0}")]
     Execute(#[from] E),
+    #[error("The prompt contains images, but the executor only supports text")]
+    UnsupportedImages,
+    /// The output doesn't match the JSON Schema of the step. `text` is the raw output of the model.
+    #[error("The output doesn't match the JSON Schema of the step: {source}\nOutput: {text}")]
+    SchemaValidation {
+        text: String,
+        #[source]
+        source: SchemaValidationError,
+    },
 }
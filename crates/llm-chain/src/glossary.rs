@@ -0,0 +1,269 @@
+//! Glossaries of domain terminology, injected into prompts as needed.
+//!
+//! A `Glossary` maps terms to their definitions and preferred translations. Putting a whole glossary into every
+//! prompt wastes tokens and distracts the model, so a `GlossaryInjector` looks for the terms in the input parameters
+//! and adds only the entries that appear to a parameter the prompt can include.
+//!
+//! # Example
+//!
+//! ```
+//! use llm_chain::glossary::{Glossary, GlossaryEntry, GlossaryInjector};
+//! use llm_chain::parameters;
+//!
+//! let glossary = Glossary::new()
+//!     .with_entry(GlossaryEntry::new("SLA", "Service level agreement").with_translation("SLA"))
+//!     .with_entry(GlossaryEntry::new("churn", "Customers cancelling their subscription").with_translation("Kundenabwanderung"));
+//! let injector = GlossaryInjector::new(glossary);
+//! let parameters = injector.inject(&parameters!("Translate to German: our churn is down"));
+//! assert_eq!(
+//!     parameters.get("glossary").unwrap(),
+//!     "- churn: Customers cancelling their subscription (preferred translation: Kundenabwanderung)"
+//! );
+//! ```
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::parameters::{Parameters, TEXT_KEY};
+
+/// A term of a glossary.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GlossaryEntry {
+    pub term: String,
+    pub definition: String,
+    /// Other spellings of the term that should also match it.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    /// The translation of the term the output should use.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub translation: Option<String>,
+}
+
+impl GlossaryEntry {
+    pub fn new<T: Into<String>, D: Into<String>>(term: T, definition: D) -> Self {
+        Self {
+            term: term.into(),
+            definition: definition.into(),
+            aliases: Vec::new(),
+            translation: None,
+        }
+    }
+
+    pub fn with_alias<S: Into<String>>(mut self, alias: S) -> Self {
+        self.aliases.push(alias.into());
+        self
+    }
+
+    pub fn with_translation<S: Into<String>>(mut self, translation: S) -> Self {
+        self.translation = Some(translation.into());
+        self
+    }
+
+    fn spellings(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.term.as_str()).chain(self.aliases.iter().map(String::as_str))
+    }
+}
+
+impl fmt::Display for GlossaryEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.term, self.definition)?;
+        if let Some(translation) = &self.translation {
+            write!(f, " (preferred translation: {})", translation)?;
+        }
+        Ok(())
+    }
+}
+
+/// A collection of glossary entries.
+///
+/// Terms match whole words, ignoring case unless `with_case_sensitivity` is set, so that "API" matches "the api" but
+/// not "rapid".
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Glossary {
+    pub entries: Vec<GlossaryEntry>,
+    #[serde(default)]
+    pub case_sensitive: bool,
+}
+
+impl Glossary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_entry(mut self, entry: GlossaryEntry) -> Self {
+        self.add(entry);
+        self
+    }
+
+    /// Makes terms only match with the same case.
+    pub fn with_case_sensitivity(mut self, case_sensitive: bool) -> Self {
+        self.case_sensitive = case_sensitive;
+        self
+    }
+
+    /// Adds an entry, replacing any entry for the same term.
+    pub fn add(&mut self, entry: GlossaryEntry) {
+        self.entries.retain(|e| e.term != entry.term);
+        self.entries.push(entry);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn from_yaml(yaml: &str) -> Result<Self, serde_yaml::Error> {
+        serde_yaml::from_str(yaml)
+    }
+
+    pub fn to_yaml(&self) -> Result<String, serde_yaml::Error> {
+        serde_yaml::to_string(self)
+    }
+
+    /// Returns the entries whose term or an alias appears in `text`, in the order they first appear.
+    pub fn relevant(&self, text: &str) -> Vec<&GlossaryEntry> {
+        let text = self.normalize(text);
+        let mut found: Vec<(usize, &GlossaryEntry)> = self
+            .entries
+            .iter()
+            .filter_map(|entry| {
+                entry
+                    .spellings()
+                    .filter_map(|spelling| find_word(&text, &self.normalize(spelling)))
+                    .min()
+                    .map(|position| (position, entry))
+            })
+            .collect();
+        found.sort_by_key(|(position, _)| *position);
+        found.into_iter().map(|(_, entry)| entry).collect()
+    }
+
+    fn normalize(&self, text: &str) -> String {
+        if self.case_sensitive {
+            text.to_string()
+        } else {
+            text.to_lowercase()
+        }
+    }
+}
+
+/// Returns the position of the first occurrence of `word` in `text` that isn't part of a longer word.
+fn find_word(text: &str, word: &str) -> Option<usize> {
+    if word.is_empty() {
+        return None;
+    }
+    text.match_indices(word).map(|(i, _)| i).find(|&i| {
+        let before = text[..i].chars().next_back();
+        let after = text[i + word.len()..].chars().next();
+        !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+    })
+}
+
+/// Adds the glossary entries relevant to the input parameters to a parameter of their own.
+///
+/// By default the terms are looked for in the `text` parameter and the entries are written to the `glossary`
+/// parameter as a list, one `- term: definition` line per entry. When no term appears, the parameter is empty, so
+/// prompts can leave the glossary out with `{% if glossary %}`.
+#[derive(Debug, Clone)]
+pub struct GlossaryInjector {
+    glossary: Glossary,
+    input_keys: Vec<String>,
+    output_key: String,
+    max_entries: Option<usize>,
+}
+
+impl GlossaryInjector {
+    pub fn new(glossary: Glossary) -> Self {
+        Self {
+            glossary,
+            input_keys: vec![TEXT_KEY.to_string()],
+            output_key: "glossary".to_string(),
+            max_entries: None,
+        }
+    }
+
+    /// Sets the parameters in which terms are looked for.
+    pub fn with_input_keys<K: Into<String>, I: IntoIterator<Item = K>>(mut self, keys: I) -> Self {
+        self.input_keys = keys.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the parameter the entries are written to.
+    pub fn with_output_key<K: Into<String>>(mut self, key: K) -> Self {
+        self.output_key = key.into();
+        self
+    }
+
+    /// Limits the number of entries injected, keeping those that appear first.
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    pub fn glossary(&self) -> &Glossary {
+        &self.glossary
+    }
+
+    /// Returns the entries relevant to `parameters`.
+    pub fn relevant_entries(&self, parameters: &Parameters) -> Vec<&GlossaryEntry> {
+        let text = self
+            .input_keys
+            .iter()
+            .filter_map(|key| parameters.get(key))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut entries = self.glossary.relevant(&text);
+        if let Some(max_entries) = self.max_entries {
+            entries.truncate(max_entries);
+        }
+        entries
+    }
+
+    /// Returns a copy of `parameters` with the relevant entries added.
+    pub fn inject(&self, parameters: &Parameters) -> Parameters {
+        let glossary = self
+            .relevant_entries(parameters)
+            .iter()
+            .map(|entry| format!("- {}", entry))
+            .collect::<Vec<_>>()
+            .join("\n");
+        parameters.with(self.output_key.as_str(), glossary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Glossary, GlossaryEntry};
+
+    #[test]
+    fn matches_whole_words_and_aliases() {
+        let glossary = Glossary::new()
+            .with_entry(GlossaryEntry::new(
+                "API",
+                "Application programming interface",
+            ))
+            .with_entry(GlossaryEntry::new("pull request", "A proposed change").with_alias("PR"))
+            .with_entry(GlossaryEntry::new("unused", "Not in the text"));
+        let terms = |text: &str| {
+            glossary
+                .relevant(text)
+                .iter()
+                .map(|e| e.term.clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(terms("Open a PR for the api."), vec!["pull request", "API"]);
+        assert!(terms("Rapid progress, improved").is_empty());
+
+        let yaml = glossary.to_yaml().unwrap();
+        assert_eq!(Glossary::from_yaml(&yaml).unwrap(), glossary);
+        assert!(glossary
+            .clone()
+            .with_case_sensitivity(true)
+            .relevant("the api")
+            .is_empty());
+    }
+}
@@ -0,0 +1,255 @@
+//! Guardrails check the output of a chain and correct or reject it.
+//!
+//! A guardrail is a custom step placed after the step whose output it checks. `LanguageGuardrail` makes sure the
+//! answer is written in the language of the requested locale: models often drift into the language of their sources
+//! or of the system prompt in multilingual deployments.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let chain = Chain::from_steps(vec![
+//!     answer_step.into(),
+//!     ChainStep::custom(LanguageGuardrail::new().with_fallback(LanguageFallback::Reprompt { max_attempts: 2 })),
+//! ]);
+//! let output = chain
+//!     .run(parameters!("question" => question, "locale" => "de-DE"), &executor)
+//!     .await?;
+//! ```
+use async_trait::async_trait;
+use thiserror::Error;
+
+use crate::{
+    language::{locale_name, Language},
+    output::Output,
+    prompt,
+    step::{CustomStep, CustomStepError, Step, StepOutcome},
+    traits::Executor,
+    Parameters,
+};
+
+const REPROMPT_SYSTEM_PROMPT: &str = "You rewrite texts in another language, keeping their content, tone and formatting. Reply with the rewritten text only.";
+const REPROMPT_USER_PROMPT: &str = "The following answer must be written in {{language}}, but it isn't. Rewrite it in {{language}}.\n\n{{text}}";
+
+#[derive(Debug, Error)]
+pub enum LanguageGuardrailError {
+    #[error("The response is in {detected:?}, but the locale {locale:?} was requested")]
+    Mismatch { locale: String, detected: Language },
+    #[error("The model returned no text")]
+    NoTextOutput,
+}
+
+/// What a `LanguageGuardrail` does with a response in the wrong language.
+pub enum LanguageFallback<E: Executor> {
+    /// Asks the model to rewrite the response in the requested language, checking the result again, up to
+    /// `max_attempts` times.
+    Reprompt { max_attempts: usize },
+    /// Runs a translation step once and accepts its output. The step receives the response in `text`, the requested
+    /// locale in the guardrail's locale parameter and the name of its language in `language`.
+    Translate(Step<E>),
+    /// Fails the chain with `LanguageGuardrailError::Mismatch`.
+    Fail,
+}
+
+/// A custom step checking that the response in the `text` parameter is written in the language of the requested
+/// locale.
+///
+/// The locale is read from the `locale` parameter. The check is skipped when there is no locale, when the locale's
+/// language isn't one `Language` can detect, or when the response's language can't be determined, so short or
+/// ambiguous answers pass. Responses in the wrong language are handled by the fallback, `Reprompt` with 2 attempts
+/// by default.
+pub struct LanguageGuardrail<E: Executor> {
+    locale_key: String,
+    fallback: LanguageFallback<E>,
+}
+
+impl<E: Executor> Default for LanguageGuardrail<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E: Executor> LanguageGuardrail<E> {
+    pub fn new() -> Self {
+        Self {
+            locale_key: "locale".to_string(),
+            fallback: LanguageFallback::Reprompt { max_attempts: 2 },
+        }
+    }
+
+    /// Sets the parameter holding the requested locale. Defaults to `locale`.
+    pub fn with_locale_key<S: Into<String>>(mut self, key: S) -> Self {
+        self.locale_key = key.into();
+        self
+    }
+
+    pub fn with_fallback(mut self, fallback: LanguageFallback<E>) -> Self {
+        self.fallback = fallback;
+        self
+    }
+
+    /// Returns the language `text` was detected in if it doesn't match `locale`.
+    fn mismatch(locale: &str, text: &str) -> Option<Language> {
+        let expected = Language::from_locale(locale)?;
+        Language::detect(text).filter(|&detected| detected != expected)
+    }
+}
+
+#[async_trait]
+impl<E> CustomStep<E> for LanguageGuardrail<E>
+where
+    E: Executor + Sync,
+    E::Error: Send + Sync + 'static,
+    E::Output: Send,
+    E::PerInvocationOptions: Sync,
+{
+    async fn run(
+        &self,
+        parameters: &Parameters,
+        executor: &E,
+    ) -> Result<StepOutcome<E::Output>, CustomStepError> {
+        let unchanged = || Ok(StepOutcome::parameters(parameters.clone()));
+        let (Some(locale), Some(text)) = (parameters.get(&self.locale_key), parameters.get_text())
+        else {
+            return unchanged();
+        };
+        let Some(mut detected) = Self::mismatch(&locale, &text) else {
+            return unchanged();
+        };
+        let language = locale_name(&locale).unwrap_or(&locale).to_string();
+        let parameters = parameters.with("language", language);
+        let max_attempts = match &self.fallback {
+            LanguageFallback::Fail => 0,
+            LanguageFallback::Reprompt { max_attempts } => *max_attempts,
+            LanguageFallback::Translate(step) => {
+                let output = step.run(&parameters, executor).await.map_err(Box::new)?;
+                return Ok(StepOutcome {
+                    parameters,
+                    output: Some(output),
+                });
+            }
+        };
+        let reprompt: Step<E> =
+            Step::for_prompt_template(prompt!(REPROMPT_SYSTEM_PROMPT, REPROMPT_USER_PROMPT));
+        let mut current = parameters;
+        for _ in 0..max_attempts {
+            let output = reprompt.run(&current, executor).await.map_err(Box::new)?;
+            let text = output
+                .primary_textual_output()
+                .await
+                .ok_or(LanguageGuardrailError::NoTextOutput)?;
+            match Self::mismatch(&locale, &text) {
+                None => {
+                    return Ok(StepOutcome {
+                        parameters: current,
+                        output: Some(output),
+                    })
+                }
+                Some(language) => detected = language,
+            }
+            current = current.with_text(text);
+        }
+        Err(Box::new(LanguageGuardrailError::Mismatch {
+            locale,
+            detected,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LanguageFallback, LanguageGuardrail, LanguageGuardrailError};
+    use crate::agents::mock::{MockExecutor, MockOutput};
+    use crate::output::Output;
+    use crate::prompt;
+    use crate::step::{CustomStep, Step, StepOutcome};
+    use crate::Parameters;
+    use futures::executor::block_on;
+
+    const GERMAN: &str = "Die Antwort steht im zweiten Kapitel der Anleitung und ist kurz.";
+    const ENGLISH: &str = "The answer is in the second chapter of the manual.";
+
+    fn answer(text: &str, locale: &str) -> Parameters {
+        Parameters::new_with_text(text).with("locale", locale)
+    }
+
+    fn output_text(outcome: &StepOutcome<MockOutput>) -> Option<String> {
+        let output = outcome.output.as_ref()?;
+        block_on(output.primary_textual_output())
+    }
+
+    #[test]
+    fn passes_answers_in_the_requested_language() {
+        let guardrail = LanguageGuardrail::new();
+        let executor = MockExecutor::new(vec![]);
+        for parameters in [
+            answer(ENGLISH, "en-US"),
+            answer(GERMAN, "pt-BR"),
+            answer("OK", "de"),
+            Parameters::new_with_text(GERMAN),
+        ] {
+            let outcome = block_on(guardrail.run(&parameters, &executor)).unwrap();
+            assert!(outcome.output.is_none());
+            assert_eq!(outcome.parameters.get_text(), parameters.get_text());
+        }
+        assert!(executor.prompts.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn reprompts_until_the_language_matches() {
+        let guardrail = LanguageGuardrail::new();
+        let executor = MockExecutor::new(vec![
+            MockOutput::text("Die Antwort ist im zweiten Kapitel, und sie ist kurz."),
+            MockOutput::text(ENGLISH),
+        ]);
+        let outcome = block_on(guardrail.run(&answer(GERMAN, "en-GB"), &executor)).unwrap();
+        assert_eq!(output_text(&outcome).as_deref(), Some(ENGLISH));
+        let prompts = executor.prompts.lock().unwrap();
+        assert_eq!(prompts.len(), 2);
+        assert!(prompts[0]
+            .to_string()
+            .contains("must be written in English"));
+        assert!(prompts[1]
+            .to_string()
+            .contains("im zweiten Kapitel, und sie"));
+    }
+
+    #[test]
+    fn fails_once_the_attempts_are_used() {
+        let guardrail =
+            LanguageGuardrail::new().with_fallback(LanguageFallback::Reprompt { max_attempts: 1 });
+        let executor = MockExecutor::new(vec![MockOutput::text(GERMAN)]);
+        let error = block_on(guardrail.run(&answer(GERMAN, "en"), &executor))
+            .err()
+            .unwrap();
+        assert!(matches!(
+            error.downcast_ref::<LanguageGuardrailError>(),
+            Some(LanguageGuardrailError::Mismatch { locale, .. }) if locale == "en"
+        ));
+
+        let guardrail = LanguageGuardrail::new().with_fallback(LanguageFallback::Fail);
+        let executor = MockExecutor::new(vec![]);
+        let error = block_on(guardrail.run(&answer(ENGLISH, "de-DE"), &executor))
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "The response is in English, but the locale \"de-DE\" was requested"
+        );
+        assert!(executor.prompts.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn translates_once() {
+        let translate = Step::for_prompt_template(prompt!("Translate to {{language}}: {{text}}"));
+        let guardrail =
+            LanguageGuardrail::new().with_fallback(LanguageFallback::Translate(translate));
+        let executor = MockExecutor::new(vec![MockOutput::text("Ein Text")]);
+        let outcome = block_on(guardrail.run(&answer(ENGLISH, "de"), &executor)).unwrap();
+        // The translation is accepted without being checked.
+        assert_eq!(output_text(&outcome).as_deref(), Some("Ein Text"));
+        assert_eq!(
+            executor.prompts.lock().unwrap()[0].to_string(),
+            format!("Translate to German: {}", ENGLISH)
+        );
+    }
+}
@@ -0,0 +1,60 @@
+//! A hash that is stable across Rust releases and platforms.
+//!
+//! The standard library's hasher may change between Rust versions, so values derived from it must not be stored or
+//! compared between builds. Cache keys on disk, content ids and traffic assignments use `stable_hash` instead.
+
+/// Returns the 128-bit FNV-1a hash of `parts`.
+pub(crate) fn stable_hash(parts: &[&str]) -> u128 {
+    const OFFSET: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+    let mut hash = OFFSET;
+    for part in parts {
+        // Hash the length first so that ("ab", "c") and ("a", "bc") differ.
+        for byte in (part.len() as u64)
+            .to_le_bytes()
+            .iter()
+            .chain(part.as_bytes())
+        {
+            hash ^= *byte as u128;
+            hash = hash.wrapping_mul(PRIME);
+        }
+    }
+    hash
+}
+
+/// Returns the hash of `parts` as 32 hexadecimal digits.
+pub(crate) fn stable_hash_hex(parts: &[&str]) -> String {
+    format!("{:032x}", stable_hash(parts))
+}
+
+/// Returns the hash of `parts` as a UUID in its dashed form, for ids of records in stores that only accept UUIDs and
+/// return them dashed, like Qdrant.
+pub(crate) fn stable_uuid(parts: &[&str]) -> String {
+    uuid::Uuid::from_u128(stable_hash(parts))
+        .hyphenated()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{stable_hash, stable_hash_hex, stable_uuid};
+
+    #[test]
+    fn hashes_are_stable_and_separate_parts() {
+        assert_eq!(
+            stable_hash_hex(&["llm-chain"]),
+            stable_hash_hex(&["llm-chain"])
+        );
+        assert_eq!(stable_hash_hex(&[]), "6c62272e07bb014262b821756295c58d");
+        assert_ne!(stable_hash(&["ab", "c"]), stable_hash(&["a", "bc"]));
+    }
+
+    #[test]
+    fn uuids_are_dashed_and_round_trip() {
+        let id = stable_uuid(&["doc", "0"]);
+        assert_eq!(id.len(), 36);
+        assert_eq!(id.replace('-', ""), stable_hash_hex(&["doc", "0"]));
+        let parsed = uuid::Uuid::parse_str(&id).unwrap();
+        assert_eq!(parsed.hyphenated().to_string(), id);
+    }
+}
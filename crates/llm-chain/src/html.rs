@@ -0,0 +1,126 @@
+//! Helpers for reading HTML, shared by the HTML loader and the HTTP tool.
+use std::borrow::Cow;
+
+/// Returns the value of the attribute `name` in the attributes of a tag, or in a whole tag, such as `a href="/"`.
+///
+/// The value is returned as written; character references in it aren't decoded.
+pub(crate) fn attribute<'a>(attributes: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = attributes;
+    while let Some(i) = rest.find('=') {
+        let key = rest[..i]
+            .rsplit(|c: char| c.is_whitespace())
+            .next()
+            .unwrap_or("")
+            .trim();
+        let value = rest[i + 1..].trim_start();
+        let (found, remaining) = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let end = value[1..].find(quote).map_or(value.len(), |end| end + 1);
+                (&value[1..end], value.get(end + 1..).unwrap_or(""))
+            }
+            _ => {
+                let end = value.find(char::is_whitespace).unwrap_or(value.len());
+                (&value[..end], &value[end..])
+            }
+        };
+        if key.eq_ignore_ascii_case(name) {
+            return Some(found);
+        }
+        rest = remaining;
+    }
+    None
+}
+
+/// Returns the position of `needle` in `haystack`, ignoring the case of ASCII letters.
+pub(crate) fn find_ascii_case_insensitive(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    if let Some(code) = entity.strip_prefix('#') {
+        let code = match code.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => code.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+    Some(match entity {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "ndash" => '–',
+        "mdash" => '—',
+        "hellip" => '…',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "copy" => '©',
+        _ => return None,
+    })
+}
+
+/// Replaces character references such as `&amp;` and `&#233;` by the characters they stand for.
+pub(crate) fn decode_entities(text: &str) -> Cow<'_, str> {
+    if !text.contains('&') {
+        return Cow::Borrowed(text);
+    }
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('&') {
+        decoded.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let entity = rest
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| decode_entity(&rest[1..end]).map(|c| (c, end)));
+        match entity {
+            Some((c, end)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    Cow::Owned(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{attribute, decode_entities, find_ascii_case_insensitive};
+
+    #[test]
+    fn reads_attributes() {
+        assert_eq!(
+            attribute(r#"a href="/docs" class=nav"#, "href"),
+            Some("/docs")
+        );
+        assert_eq!(
+            attribute(r#"a href="/docs" class=nav"#, "class"),
+            Some("nav")
+        );
+        assert_eq!(attribute("a data-href='/x' HREF='/y'", "href"), Some("/y"));
+        assert_eq!(attribute("a title=\"a=b\"", "href"), None);
+        assert_eq!(attribute("a", "href"), None);
+    }
+
+    #[test]
+    fn decodes_entities() {
+        assert_eq!(decode_entities("plain"), "plain");
+        assert_eq!(
+            decode_entities("Rust &amp; LLMs &#233;&#x41; &lt;b&gt; &#39;&unknown; & so"),
+            "Rust & LLMs éA <b> '&unknown; & so"
+        );
+        assert_eq!(find_ascii_case_insensitive("<DIV></Div>", "</div"), Some(5));
+    }
+}
@@ -0,0 +1,310 @@
+//! Indexing large corpora into vector stores.
+//!
+//! An `IndexingPipeline` streams documents through splitting, embedding and insertion into a `VectorStore`. Chunks
+//! are inserted in batches, several batches at a time, and new documents are only read from the input stream when
+//! there is room for more work, so memory use stays bounded however large the corpus is. A progress callback is
+//! called after every batch.
+//!
+//! Chunks are upserted with ids derived from their document and position, so indexing a document again replaces its
+//! chunks instead of adding copies, and `IndexObserver`s such as an `AnswerCache` are told about the chunks replaced.
+//!
+//! `IndexingPipeline::recursive` creates a pipeline splitting documents with a `RecursiveSplitter`, the default
+//! chunking strategy. Any other `TextSplitter`, such as the tokenizer of the embedding model, can be used with
+//! `IndexingPipeline::new`.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let pipeline = IndexingPipeline::recursive(1000)
+//!     .with_chunk_overlap(200)
+//!     .with_batch_size(100)
+//!     .with_concurrency(4)
+//!     .with_progress(|progress| println!("{} chunks indexed", progress.chunks_indexed));
+//! let report = pipeline.run(&store, futures::stream::iter(documents)).await?;
+//! ```
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use futures::{stream, Stream, StreamExt};
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+use crate::{
+    hash::stable_uuid,
+    retrieval::{content_hash, IndexObserver},
+    schema::Document,
+    text_splitter::{split_document, RecursiveSplitter},
+    tokens::TokenizerError,
+    traits::{Embeddings, VectorStore},
+    TextSplitter,
+};
+
+#[derive(Debug, Error)]
+pub enum IndexingError<V: std::error::Error> {
+    #[error("Error splitting a document: {0}")]
+    Tokenizer(#[from] TokenizerError),
+    #[error("Error inserting a batch into the vector store: {0}")]
+    VectorStore(V),
+}
+
+/// How far an indexing run has come.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct IndexingProgress {
+    /// The number of documents read from the input.
+    pub documents_read: usize,
+    /// The number of chunks inserted into the vector store.
+    pub chunks_indexed: usize,
+    /// The number of batches inserted into the vector store.
+    pub batches_completed: usize,
+}
+
+type ProgressCallback = Box<dyn Fn(&IndexingProgress) + Send + Sync>;
+
+/// Splits documents into chunks and inserts them into a vector store, with bounded concurrency.
+///
+/// Chunks keep the metadata of their document and record their provenance. Documents without an id get the hash of
+/// their content as their source. The id of a chunk is derived from the source and its position, so a document
+/// indexed again replaces its chunks; if it now has fewer chunks, the extra chunks of the previous version stay in
+/// the store until they are deleted. The run stops at the first error; batches inserted before are kept.
+pub struct IndexingPipeline<S = RecursiveSplitter, T = char>
+where
+    S: TextSplitter<T>,
+    T: Clone,
+{
+    splitter: S,
+    max_tokens_per_chunk: usize,
+    chunk_overlap: usize,
+    batch_size: usize,
+    concurrency: usize,
+    progress: Option<ProgressCallback>,
+    observers: Vec<Arc<dyn IndexObserver + Send + Sync>>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl IndexingPipeline {
+    /// Creates a pipeline splitting documents with a `RecursiveSplitter` into chunks of at most `chunk_size`
+    /// characters, on paragraph, sentence and word boundaries.
+    pub fn recursive(chunk_size: usize) -> Self {
+        Self::new(RecursiveSplitter::default(), chunk_size)
+    }
+}
+
+impl<S, T> IndexingPipeline<S, T>
+where
+    S: TextSplitter<T>,
+    T: Clone,
+{
+    /// Creates a pipeline splitting documents into chunks of at most `max_tokens_per_chunk` tokens.
+    pub fn new(splitter: S, max_tokens_per_chunk: usize) -> Self {
+        Self {
+            splitter,
+            max_tokens_per_chunk,
+            chunk_overlap: 0,
+            batch_size: 64,
+            concurrency: 4,
+            progress: None,
+            observers: Vec::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Sets the number of tokens shared by consecutive chunks. Defaults to 0.
+    pub fn with_chunk_overlap(mut self, chunk_overlap: usize) -> Self {
+        self.chunk_overlap = chunk_overlap;
+        self
+    }
+
+    /// Sets the number of chunks inserted into the vector store at once. Defaults to 64.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Sets the maximum number of batches being embedded and inserted at the same time. Defaults to 4.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Sets a callback called after every batch is inserted.
+    pub fn with_progress<F>(mut self, progress: F) -> Self
+    where
+        F: Fn(&IndexingProgress) + Send + Sync + 'static,
+    {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Adds an observer told about the ids of the chunks of every batch upserted. Observers given to an
+    /// `ObservedVectorStore` are already told by the store.
+    pub fn with_observer<O: IndexObserver + Send + Sync + 'static>(mut self, observer: O) -> Self {
+        self.observers.push(Arc::new(observer));
+        self
+    }
+
+    /// Indexes every document of `documents` into `store`. Returns the final progress.
+    pub async fn run<E, M, V, D>(
+        &self,
+        store: &V,
+        documents: D,
+    ) -> Result<IndexingProgress, IndexingError<V::Error>>
+    where
+        E: Embeddings,
+        M: Serialize + DeserializeOwned + Clone,
+        V: VectorStore<E, M>,
+        D: Stream<Item = Document<M>>,
+    {
+        let documents_read = AtomicUsize::new(0);
+        let batches = documents
+            .map(|document| {
+                documents_read.fetch_add(1, Ordering::Relaxed);
+                let source_id = document
+                    .id
+                    .clone()
+                    .unwrap_or_else(|| content_hash(&document.page_content));
+                let chunks: Vec<Result<Document<M>, TokenizerError>> = match split_document(
+                    &self.splitter,
+                    &document,
+                    &source_id,
+                    self.max_tokens_per_chunk,
+                    self.chunk_overlap,
+                ) {
+                    Ok(chunks) => chunks
+                        .into_iter()
+                        .enumerate()
+                        .map(|(index, chunk)| {
+                            let id = stable_uuid(&[&source_id, &index.to_string()]);
+                            Ok(chunk.with_id(id))
+                        })
+                        .collect(),
+                    Err(e) => vec![Err(e)],
+                };
+                stream::iter(chunks)
+            })
+            .flatten()
+            .chunks(self.batch_size)
+            .map(|batch| async move {
+                let batch = batch.into_iter().collect::<Result<Vec<_>, _>>()?;
+                let ids = store
+                    .upsert_documents(batch)
+                    .await
+                    .map_err(IndexingError::VectorStore)?;
+                for observer in &self.observers {
+                    observer.chunks_updated(&ids);
+                }
+                Ok::<_, IndexingError<V::Error>>(ids.len())
+            })
+            .buffer_unordered(self.concurrency);
+        futures::pin_mut!(batches);
+
+        let mut progress = IndexingProgress::default();
+        while let Some(size) = batches.next().await {
+            progress.chunks_indexed += size?;
+            progress.batches_completed += 1;
+            progress.documents_read = documents_read.load(Ordering::Relaxed);
+            if let Some(callback) = &self.progress {
+                callback(&progress);
+            }
+        }
+        progress.documents_read = documents_read.load(Ordering::Relaxed);
+        Ok(progress)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IndexingPipeline;
+    use crate::retrieval::{AnswerCache, IndexObserver};
+    use crate::schema::Document;
+    use crate::test_support::FnEmbeddings;
+    use crate::traits::VectorStore;
+    use crate::vectorstores::InMemoryVectorStore;
+    use crate::NaiveWhitespaceSplitter;
+    use futures::executor::block_on;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn indexes_in_batches_and_reports_progress() {
+        let store = InMemoryVectorStore::<_, serde_json::Value>::new(FnEmbeddings(|text: &str| {
+            vec![text.len() as f32, 1.0]
+        }));
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let recorded = reports.clone();
+        let pipeline = IndexingPipeline::new(NaiveWhitespaceSplitter, 2)
+            .with_batch_size(3)
+            .with_concurrency(2)
+            .with_progress(move |progress| recorded.lock().unwrap().push(*progress));
+        let documents = (0..4).map(|i| Document::new(format!("doc {} has five words", i)));
+        let progress = block_on(pipeline.run(&store, futures::stream::iter(documents))).unwrap();
+
+        assert_eq!(progress.documents_read, 4);
+        assert_eq!(progress.chunks_indexed, 12);
+        assert_eq!(progress.batches_completed, 4);
+        assert_eq!(store.len(), 12);
+        assert_eq!(reports.lock().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn indexing_again_replaces_chunks_and_notifies_observers() {
+        let store = InMemoryVectorStore::<_, serde_json::Value>::new(FnEmbeddings(|text: &str| {
+            vec![text.len() as f32, 1.0]
+        }));
+        let cache = AnswerCache::new();
+        let pipeline =
+            IndexingPipeline::new(NaiveWhitespaceSplitter, 2).with_observer(cache.clone());
+        let documents = || {
+            futures::stream::iter(vec![
+                Document::new("one two three".to_string()).with_id("doc"),
+                Document::new("no id".to_string()),
+            ])
+        };
+        block_on(pipeline.run(&store, documents())).unwrap();
+        assert_eq!(store.len(), 3);
+
+        let results = block_on(store.similarity_search("one two".to_string(), 1)).unwrap();
+        let chunk_id = results[0].id.clone().unwrap();
+        cache.insert(vec![1.0], [chunk_id.clone()], "answer");
+        block_on(pipeline.run(&store, documents())).unwrap();
+        assert_eq!(store.len(), 3);
+        assert_eq!(block_on(store.get_by_ids(&[chunk_id])).unwrap().len(), 1);
+        assert!(cache.is_empty());
+    }
+
+    struct RecordIds(Arc<Mutex<Vec<String>>>);
+
+    impl IndexObserver for RecordIds {
+        fn chunks_updated(&self, ids: &[String]) {
+            self.0.lock().unwrap().extend_from_slice(ids);
+        }
+
+        fn index_updated(&self) {}
+    }
+
+    #[test]
+    fn chunk_ids_are_dashed_uuids_reported_to_observers() {
+        let store = InMemoryVectorStore::<_, serde_json::Value>::new(FnEmbeddings(|text: &str| {
+            vec![text.len() as f32, 1.0]
+        }));
+        let updated = Arc::new(Mutex::new(Vec::new()));
+        let pipeline = IndexingPipeline::new(NaiveWhitespaceSplitter, 2)
+            .with_observer(RecordIds(updated.clone()));
+        let documents = futures::stream::iter(vec![
+            Document::new("one two three".to_string()).with_id("doc")
+        ]);
+        block_on(pipeline.run(&store, documents)).unwrap();
+
+        let updated = updated.lock().unwrap().clone();
+        assert_eq!(updated.len(), 2);
+        for id in &updated {
+            let uuid = uuid::Uuid::parse_str(id).unwrap();
+            assert_eq!(&uuid.hyphenated().to_string(), id);
+        }
+        let stored = block_on(store.get_by_ids(&updated)).unwrap();
+        let mut stored: Vec<_> = stored.into_iter().filter_map(|d| d.id).collect();
+        stored.sort();
+        let mut expected = updated;
+        expected.sort();
+        assert_eq!(stored, expected);
+    }
+}
@@ -0,0 +1,447 @@
+//! JSON Schemas constraining the output of steps.
+//!
+//! A step given a `JsonSchema` with `Step::with_json_schema` produces JSON matching the schema. Executors able to
+//! constrain generation, such as APIs with a structured output mode or local models with grammars, return the options
+//! doing it from `Executor::json_schema_options`. For the others, the schema is added to the prompt. In both cases the
+//! output is validated against the schema before it is returned.
+//!
+//! With the `schemars` feature, the schema of a Rust type is derived with `JsonSchema::for_type`.
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A named JSON Schema.
+///
+/// # Examples
+///
+/// ```
+/// use llm_chain::json_schema::JsonSchema;
+/// use serde_json::json;
+/// let schema = JsonSchema::new(
+///     "review",
+///     json!({
+///         "type": "object",
+///         "properties": {"score": {"type": "integer", "minimum": 1, "maximum": 5}},
+///         "required": ["score"]
+///     }),
+/// );
+/// assert!(schema.validate(&json!({"score": 4})).is_ok());
+/// let error = schema.validate(&json!({"score": 9})).unwrap_err();
+/// assert_eq!(error.violations[0].path, "/score");
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonSchema {
+    name: String,
+    schema: Value,
+}
+
+impl JsonSchema {
+    /// Creates a schema. Some APIs require the name, which should be made of letters, digits, `_` and `-`.
+    pub fn new<S: Into<String>>(name: S, schema: Value) -> Self {
+        Self {
+            name: name.into(),
+            schema,
+        }
+    }
+
+    /// Derives the schema of `T`.
+    ///
+    /// ```
+    /// use llm_chain::json_schema::JsonSchema;
+    /// use serde_json::json;
+    /// #[derive(schemars::JsonSchema)]
+    /// struct Review {
+    ///     score: u8,
+    ///     summary: String,
+    /// }
+    /// let schema = JsonSchema::for_type::<Review>();
+    /// assert_eq!(schema.name(), "Review");
+    /// assert!(schema.validate(&json!({"score": 4, "summary": "Fine."})).is_ok());
+    /// assert!(schema.validate(&json!({"score": "4"})).is_err());
+    /// ```
+    #[cfg(feature = "schemars")]
+    pub fn for_type<T: schemars::JsonSchema>() -> Self {
+        let schema = schemars::schema_for!(T);
+        Self::new(
+            T::schema_name(),
+            serde_json::to_value(schema).expect("schemas always serialize"),
+        )
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn schema(&self) -> &Value {
+        &self.schema
+    }
+
+    /// Returns the instructions added to the prompt when the executor can't constrain generation to the schema.
+    pub fn instructions(&self) -> String {
+        format!(
+            "Reply with a single JSON value matching the following JSON Schema, without any explanation before or \
+             after it.\n{}",
+            serde_json::to_string_pretty(&self.schema).expect("JSON values always serialize")
+        )
+    }
+
+    /// Checks that `value` matches the schema.
+    ///
+    /// The keywords describing the structure of values are supported: `type`, `enum`, `const`, `properties`,
+    /// `required`, `additionalProperties`, `items`, `minItems`, `maxItems`, `minLength`, `maxLength`, `minimum`,
+    /// `maximum`, `exclusiveMinimum`, `exclusiveMaximum`, `allOf`, `anyOf`, `oneOf` and local `$ref`s. Other keywords,
+    /// such as `pattern` or `format`, are ignored.
+    pub fn validate(&self, value: &Value) -> Result<(), SchemaValidationError> {
+        let mut violations = Vec::new();
+        Validator { root: &self.schema }.check(&self.schema, value, "", &mut violations);
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(SchemaValidationError { violations })
+        }
+    }
+}
+
+/// A part of a value not matching a schema.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{path}: {message}")]
+pub struct SchemaViolation {
+    /// The JSON pointer to the part, empty for the whole value.
+    pub path: String,
+    pub message: String,
+}
+
+/// The error returned when a value doesn't match a schema.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("the value doesn't match the schema: {}", .violations.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+pub struct SchemaValidationError {
+    pub violations: Vec<SchemaViolation>,
+}
+
+impl SchemaValidationError {
+    fn new<S: Into<String>>(message: S) -> Self {
+        Self {
+            violations: vec![SchemaViolation {
+                path: String::new(),
+                message: message.into(),
+            }],
+        }
+    }
+}
+
+/// Finds the JSON value in `text` with `parsing::find_json`, and checks that it matches `schema`.
+pub(crate) fn validate_text(schema: &JsonSchema, text: &str) -> Result<(), SchemaValidationError> {
+    let value: Value = crate::parsing::find_json(text)
+        .map_err(|e| SchemaValidationError::new(format!("the output contains no JSON: {}", e)))?;
+    schema.validate(&value)
+}
+
+struct Validator<'a> {
+    root: &'a Value,
+}
+
+impl<'a> Validator<'a> {
+    fn check(
+        &self,
+        schema: &'a Value,
+        value: &Value,
+        path: &str,
+        violations: &mut Vec<SchemaViolation>,
+    ) {
+        let schema = match schema {
+            Value::Bool(true) => return,
+            Value::Bool(false) => {
+                return violate(violations, path, "no value is allowed here".to_string())
+            }
+            Value::Object(schema) => schema,
+            _ => return,
+        };
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            match self.resolve(reference) {
+                Some(target) => self.check(target, value, path, violations),
+                None => violate(violations, path, format!("unknown reference {}", reference)),
+            }
+        }
+        if let Some(types) = schema.get("type") {
+            let allowed: Vec<&str> = match types {
+                Value::String(name) => vec![name.as_str()],
+                Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+                _ => Vec::new(),
+            };
+            if !allowed.is_empty() && !allowed.iter().any(|name| has_type(value, name)) {
+                return violate(
+                    violations,
+                    path,
+                    format!(
+                        "expected {}, found {}",
+                        allowed.join(" or "),
+                        type_name(value)
+                    ),
+                );
+            }
+        }
+        if let Some(Value::Array(options)) = schema.get("enum") {
+            if !options.contains(value) {
+                violate(
+                    violations,
+                    path,
+                    format!("{} is not one of {}", value, Value::Array(options.clone())),
+                );
+            }
+        }
+        if let Some(constant) = schema.get("const") {
+            if constant != value {
+                violate(
+                    violations,
+                    path,
+                    format!("expected {}, found {}", constant, value),
+                );
+            }
+        }
+        match value {
+            Value::Object(fields) => self.check_object(schema, fields, path, violations),
+            Value::Array(items) => self.check_array(schema, items, path, violations),
+            Value::String(text) => {
+                let length = text.chars().count() as f64;
+                check_bound(schema, "minLength", length, path, violations, |l, b| l >= b);
+                check_bound(schema, "maxLength", length, path, violations, |l, b| l <= b);
+            }
+            Value::Number(number) => {
+                let number = number.as_f64().unwrap_or_default();
+                check_bound(schema, "minimum", number, path, violations, |n, b| n >= b);
+                check_bound(schema, "maximum", number, path, violations, |n, b| n <= b);
+                check_bound(
+                    schema,
+                    "exclusiveMinimum",
+                    number,
+                    path,
+                    violations,
+                    |n, b| n > b,
+                );
+                check_bound(
+                    schema,
+                    "exclusiveMaximum",
+                    number,
+                    path,
+                    violations,
+                    |n, b| n < b,
+                );
+            }
+            _ => {}
+        }
+        if let Some(Value::Array(schemas)) = schema.get("allOf") {
+            for schema in schemas {
+                self.check(schema, value, path, violations);
+            }
+        }
+        if let Some(Value::Array(schemas)) = schema.get("anyOf") {
+            if !schemas.iter().any(|schema| self.matches(schema, value)) {
+                violate(
+                    violations,
+                    path,
+                    "the value matches none of the allowed schemas".to_string(),
+                );
+            }
+        }
+        if let Some(Value::Array(schemas)) = schema.get("oneOf") {
+            let matching = schemas
+                .iter()
+                .filter(|schema| self.matches(schema, value))
+                .count();
+            if matching != 1 {
+                violate(
+                    violations,
+                    path,
+                    format!(
+                        "the value matches {} of the schemas instead of exactly one",
+                        matching
+                    ),
+                );
+            }
+        }
+    }
+
+    fn check_object(
+        &self,
+        schema: &'a serde_json::Map<String, Value>,
+        fields: &serde_json::Map<String, Value>,
+        path: &str,
+        violations: &mut Vec<SchemaViolation>,
+    ) {
+        let properties = schema.get("properties").and_then(Value::as_object);
+        if let Some(Value::Array(required)) = schema.get("required") {
+            for name in required.iter().filter_map(Value::as_str) {
+                if !fields.contains_key(name) {
+                    violate(
+                        violations,
+                        path,
+                        format!("missing required property {}", name),
+                    );
+                }
+            }
+        }
+        for (name, field) in fields {
+            let field_path = format!("{}/{}", path, name.replace('~', "~0").replace('/', "~1"));
+            match properties.and_then(|properties| properties.get(name)) {
+                Some(property) => self.check(property, field, &field_path, violations),
+                None => match schema.get("additionalProperties") {
+                    Some(Value::Bool(false)) => {
+                        violate(violations, path, format!("unexpected property {}", name))
+                    }
+                    Some(additional) => self.check(additional, field, &field_path, violations),
+                    None => {}
+                },
+            }
+        }
+    }
+
+    fn check_array(
+        &self,
+        schema: &'a serde_json::Map<String, Value>,
+        items: &[Value],
+        path: &str,
+        violations: &mut Vec<SchemaViolation>,
+    ) {
+        let length = items.len() as f64;
+        check_bound(schema, "minItems", length, path, violations, |l, b| l >= b);
+        check_bound(schema, "maxItems", length, path, violations, |l, b| l <= b);
+        if let Some(item_schema) = schema.get("items") {
+            for (i, item) in items.iter().enumerate() {
+                self.check(item_schema, item, &format!("{}/{}", path, i), violations);
+            }
+        }
+    }
+
+    fn matches(&self, schema: &'a Value, value: &Value) -> bool {
+        let mut violations = Vec::new();
+        self.check(schema, value, "", &mut violations);
+        violations.is_empty()
+    }
+
+    /// Resolves a reference to a part of the root schema, such as `#/definitions/Review`.
+    fn resolve(&self, reference: &str) -> Option<&'a Value> {
+        let pointer = reference.strip_prefix('#')?;
+        self.root.pointer(pointer)
+    }
+}
+
+fn violate(violations: &mut Vec<SchemaViolation>, path: &str, message: String) {
+    violations.push(SchemaViolation {
+        path: path.to_string(),
+        message,
+    });
+}
+
+/// Checks `actual` against the bound set by `keyword`, if any.
+fn check_bound(
+    schema: &serde_json::Map<String, Value>,
+    keyword: &str,
+    actual: f64,
+    path: &str,
+    violations: &mut Vec<SchemaViolation>,
+    within: fn(f64, f64) -> bool,
+) {
+    if let Some(bound) = schema.get(keyword).and_then(Value::as_f64) {
+        if !within(actual, bound) {
+            violate(
+                violations,
+                path,
+                format!(
+                    "{} is {}, breaking {} {}",
+                    type_measure(keyword),
+                    actual,
+                    keyword,
+                    bound
+                ),
+            );
+        }
+    }
+}
+
+/// Names what a bound keyword measures, for error messages.
+fn type_measure(keyword: &str) -> &'static str {
+    match keyword {
+        "minLength" | "maxLength" => "the length",
+        "minItems" | "maxItems" => "the number of items",
+        _ => "the value",
+    }
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "integer" => value.as_f64().is_some_and(|n| n.fract() == 0.0),
+        "number" => value.is_number(),
+        other => type_name(value) == other,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn validates_nested_values() {
+        let schema = JsonSchema::new(
+            "order",
+            json!({
+                "type": "object",
+                "properties": {
+                    "status": {"enum": ["open", "closed"]},
+                    "items": {"type": "array", "items": {"$ref": "#/definitions/Item"}, "minItems": 1},
+                    "note": {"type": ["string", "null"], "maxLength": 5}
+                },
+                "required": ["status", "items"],
+                "additionalProperties": false,
+                "definitions": {
+                    "Item": {
+                        "type": "object",
+                        "properties": {"name": {"type": "string"}, "quantity": {"type": "integer", "exclusiveMinimum": 0}},
+                        "required": ["name"]
+                    }
+                }
+            }),
+        );
+        let order =
+            json!({"status": "open", "items": [{"name": "tea", "quantity": 2}], "note": null});
+        assert_eq!(schema.validate(&order), Ok(()));
+
+        let order = json!({"status": "lost", "items": [{"quantity": 0.5}, "tea"], "note": "urgent", "x": 1});
+        let mut paths: Vec<_> = schema
+            .validate(&order)
+            .unwrap_err()
+            .violations
+            .into_iter()
+            .map(|v| v.path)
+            .collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            [
+                "",
+                "/items/0",
+                "/items/0/quantity",
+                "/items/1",
+                "/note",
+                "/status"
+            ]
+        );
+
+        assert!(validate_text(
+            &schema,
+            "Sure:\n```json\n{\"status\": \"closed\", \"items\": [{\"name\": \"tea\"}]}\n```"
+        )
+        .is_ok());
+        assert!(validate_text(&schema, "no JSON here").is_err());
+    }
+}
@@ -0,0 +1,175 @@
+//! Detecting the language of texts and locales.
+//!
+//! `Language` is shared by the keyword search of `retrieval`, which removes the stop words of a language and stems
+//! its words, and by `guardrails`, which checks that answers are written in the requested language.
+
+/// The languages with built-in stop words and stemming, which can be detected in texts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Language {
+    English,
+    German,
+    French,
+    Spanish,
+    Swedish,
+    /// Chinese, Japanese and Korean. Text is split into overlapping pairs of characters, since these languages
+    /// don't separate words with spaces.
+    Cjk,
+}
+
+const ENGLISH_STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "from", "has", "have", "he",
+    "her", "his", "i", "if", "in", "into", "is", "it", "its", "of", "on", "or", "our", "she", "so",
+    "such", "that", "the", "their", "then", "there", "these", "they", "this", "to", "was", "we",
+    "were", "what", "which", "who", "will", "with", "you",
+];
+const GERMAN_STOP_WORDS: &[&str] = &[
+    "aber", "als", "am", "an", "auch", "auf", "aus", "bei", "bin", "bis", "das", "dass", "dem",
+    "den", "der", "des", "die", "du", "ein", "eine", "einem", "einen", "einer", "er", "es", "für",
+    "hat", "ich", "im", "in", "ist", "mit", "nach", "nicht", "noch", "oder", "sich", "sie", "sind",
+    "so", "und", "von", "vor", "war", "wie", "wir", "zu", "zum", "zur",
+];
+const FRENCH_STOP_WORDS: &[&str] = &[
+    "au", "aux", "avec", "ce", "ces", "dans", "de", "des", "du", "elle", "en", "et", "est", "il",
+    "ils", "je", "la", "le", "les", "leur", "lui", "ma", "mais", "me", "mes", "ne", "nous", "on",
+    "ou", "par", "pas", "pour", "qu", "que", "qui", "sa", "se", "ses", "son", "sur", "ta", "te",
+    "tu", "un", "une", "vous",
+];
+const SPANISH_STOP_WORDS: &[&str] = &[
+    "a", "al", "como", "con", "de", "del", "el", "ella", "en", "es", "esta", "este", "la", "las",
+    "le", "lo", "los", "más", "me", "mi", "no", "nos", "o", "para", "pero", "por", "que", "se",
+    "si", "sin", "su", "sus", "te", "tu", "un", "una", "y", "ya", "yo",
+];
+const SWEDISH_STOP_WORDS: &[&str] = &[
+    "alla", "att", "av", "de", "den", "det", "din", "du", "där", "efter", "ej", "en", "er", "ett",
+    "för", "han", "har", "hon", "i", "jag", "kan", "man", "med", "men", "mig", "min", "när", "och",
+    "om", "på", "sig", "sin", "som", "så", "till", "under", "upp", "ut", "var", "vi", "vid", "är",
+];
+
+impl Language {
+    /// Returns the language of a locale such as `de`, `sv-SE` or `ja_JP`, if it is one of the supported languages.
+    pub fn from_locale(locale: &str) -> Option<Language> {
+        match primary_subtag(locale).as_str() {
+            "en" => Some(Language::English),
+            "de" => Some(Language::German),
+            "fr" => Some(Language::French),
+            "es" => Some(Language::Spanish),
+            "sv" => Some(Language::Swedish),
+            "zh" | "ja" | "ko" => Some(Language::Cjk),
+            _ => None,
+        }
+    }
+
+    /// Guesses the language of `text` from its script and the stop words it contains.
+    ///
+    /// Returns `None` if the text is too short or too ambiguous to tell, for example when it contains as many stop
+    /// words of two languages.
+    pub fn detect(text: &str) -> Option<Language> {
+        let letters = text.chars().filter(|c| c.is_alphabetic()).count();
+        if letters == 0 {
+            return None;
+        }
+        if text.chars().filter(|&c| is_cjk(c)).count() * 3 >= letters {
+            return Some(Language::Cjk);
+        }
+        let words: Vec<String> = text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .map(str::to_lowercase)
+            .collect();
+        let mut hits: Vec<(Language, usize)> = [
+            Language::English,
+            Language::German,
+            Language::French,
+            Language::Spanish,
+            Language::Swedish,
+        ]
+        .into_iter()
+        .map(|language| {
+            let stop_words = language.stop_words();
+            let count = words
+                .iter()
+                .filter(|w| stop_words.contains(&w.as_str()))
+                .count();
+            (language, count)
+        })
+        .collect();
+        hits.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        match hits[..] {
+            [(language, best), (_, second), ..] if best >= 2 && best > second => Some(language),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn stop_words(self) -> &'static [&'static str] {
+        match self {
+            Language::English => ENGLISH_STOP_WORDS,
+            Language::German => GERMAN_STOP_WORDS,
+            Language::French => FRENCH_STOP_WORDS,
+            Language::Spanish => SPANISH_STOP_WORDS,
+            Language::Swedish => SWEDISH_STOP_WORDS,
+            Language::Cjk => &[],
+        }
+    }
+}
+
+/// Returns the language subtag of a locale such as `sv-SE` or `ja_JP`, in lowercase.
+fn primary_subtag(locale: &str) -> String {
+    locale
+        .split(['-', '_'])
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+/// Returns the English name of the language of `locale`, for use in prompts, if it is a known language.
+pub fn locale_name(locale: &str) -> Option<&'static str> {
+    Some(match primary_subtag(locale).as_str() {
+        "en" => "English",
+        "de" => "German",
+        "fr" => "French",
+        "es" => "Spanish",
+        "sv" => "Swedish",
+        "zh" => "Chinese",
+        "ja" => "Japanese",
+        "ko" => "Korean",
+        _ => return None,
+    })
+}
+
+pub(crate) fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30ff}' // Hiragana and Katakana
+        | '\u{3400}'..='\u{4dbf}' // CJK Unified Ideographs Extension A
+        | '\u{4e00}'..='\u{9fff}' // CJK Unified Ideographs
+        | '\u{ac00}'..='\u{d7af}' // Hangul Syllables
+        | '\u{f900}'..='\u{faff}' // CJK Compatibility Ideographs
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{locale_name, Language};
+
+    #[test]
+    fn detects_languages() {
+        let detect = Language::detect;
+        assert_eq!(
+            detect("The answer is in the second chapter of the manual."),
+            Some(Language::English)
+        );
+        assert_eq!(
+            detect("Die Antwort steht im zweiten Kapitel der Anleitung und ist kurz."),
+            Some(Language::German)
+        );
+        assert_eq!(
+            detect("Svaret finns i andra kapitlet av manualen och det är kort."),
+            Some(Language::Swedish)
+        );
+        assert_eq!(detect("答案在手册的第二章。"), Some(Language::Cjk));
+        assert_eq!(detect("OK"), None);
+        assert_eq!(Language::from_locale("sv-SE"), Some(Language::Swedish));
+        assert_eq!(Language::from_locale("pt_BR"), None);
+        assert_eq!(locale_name("ja_JP"), Some("Japanese"));
+        assert_eq!(locale_name("pt-BR"), None);
+    }
+}
@@ -23,20 +23,45 @@
 
 // Core components
 pub mod agents;
+pub mod budget;
+pub mod cancellation;
 pub mod chains;
+pub mod compression;
+pub(crate) mod csv;
+pub mod embeddings;
+pub mod eval;
 pub mod executor;
+pub mod executor_pool;
 pub mod frame;
+pub mod glossary;
+pub mod guardrails;
+pub(crate) mod hash;
+pub(crate) mod html;
+pub mod indexing;
+pub mod json_schema;
+pub mod language;
+pub mod lifecycle;
+pub mod loaders;
+pub mod options;
 pub mod output;
+pub mod output_parser;
 pub mod parameters;
 pub mod parsing;
 pub mod prompt;
+pub mod replay;
+pub mod retrieval;
 pub mod schema;
 pub mod serialization;
+#[cfg(feature = "async")]
+pub mod sinks;
 pub mod step;
+#[cfg(test)]
+pub(crate) mod test_support;
 pub mod text_splitter;
 pub mod tokens;
 pub mod tools;
 pub mod traits;
+pub mod vectorstores;
 
 // Utilities and tools
 pub mod summarization;
@@ -0,0 +1,69 @@
+//! Graceful shutdown of resources held by executors.
+//!
+//! Executors may hold resources that should be released explicitly rather than whenever they happen to be dropped:
+//! connections, telemetry buffers that need flushing, or model contexts occupying GPU memory. `Executor::shutdown`
+//! releases the resources of a single executor. Long-lived services can additionally register their executors and
+//! pools here and call `shutdown_all` once when they stop.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let pool = Arc::new(ExecutorPool::new(executor, 8));
+//! lifecycle::register(&pool);
+//! // ...
+//! lifecycle::shutdown_all().await?;
+//! ```
+use std::error::Error;
+use std::sync::{Arc, Mutex, Weak};
+
+use async_trait::async_trait;
+use futures::future::join_all;
+use thiserror::Error;
+
+/// The error type returned when shutting down a resource.
+pub type BoxedShutdownError = Box<dyn Error + Send + Sync>;
+
+/// A resource that can be shut down.
+#[async_trait]
+pub trait Shutdown: Send + Sync {
+    async fn shutdown(&self) -> Result<(), BoxedShutdownError>;
+}
+
+/// The errors of the resources that failed to shut down in `shutdown_all`.
+#[derive(Debug, Error)]
+#[error("{} resource(s) failed to shut down", .0.len())]
+pub struct ShutdownError(pub Vec<BoxedShutdownError>);
+
+static REGISTRY: Mutex<Vec<Weak<dyn Shutdown>>> = Mutex::new(Vec::new());
+
+/// Registers a resource to be shut down by `shutdown_all`.
+///
+/// Only a weak reference is kept, so registering a resource doesn't keep it alive.
+pub fn register<S: Shutdown + 'static>(resource: &Arc<S>) {
+    let resource: Arc<dyn Shutdown> = resource.clone();
+    let mut registry = REGISTRY.lock().expect("shutdown registry lock poisoned");
+    registry.retain(|r| r.strong_count() > 0);
+    registry.push(Arc::downgrade(&resource));
+}
+
+/// Shuts down every registered resource that is still alive and clears the registry.
+///
+/// All resources are shut down concurrently; the errors of those that failed are collected into a `ShutdownError`.
+pub async fn shutdown_all() -> Result<(), ShutdownError> {
+    let resources: Vec<Arc<dyn Shutdown>> = REGISTRY
+        .lock()
+        .expect("shutdown registry lock poisoned")
+        .drain(..)
+        .filter_map(|r| r.upgrade())
+        .collect();
+    let errors: Vec<BoxedShutdownError> = join_all(resources.iter().map(|r| r.shutdown()))
+        .await
+        .into_iter()
+        .filter_map(Result::err)
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ShutdownError(errors))
+    }
+}
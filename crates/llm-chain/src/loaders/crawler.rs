@@ -0,0 +1,572 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use futures::stream::{self, FuturesUnordered, Stream, StreamExt};
+use reqwest::header::{CONTENT_TYPE, USER_AGENT};
+use reqwest::Url;
+use thiserror::Error;
+use tokio::time::Instant;
+
+use super::html::{extract_html, extract_links, HtmlMetadata};
+use super::DocumentLoader;
+use crate::html::decode_entities;
+use crate::schema::{Document, Provenance};
+
+#[derive(Debug, Error)]
+pub enum CrawlerError {
+    #[error("Invalid URL: {0}")]
+    InvalidUrl(String),
+    #[error("Unable to fetch {url}: {source}")]
+    Request { url: Url, source: reqwest::Error },
+}
+
+/// The longest crawl delay honored, in seconds: sites asking for longer delays are crawled at this pace.
+const MAX_CRAWL_DELAY: f64 = 60.0;
+
+/// Parses the value of a `Crawl-delay` line, ignoring values that aren't durations.
+fn parse_crawl_delay(value: &str) -> Option<Duration> {
+    let seconds: f64 = value.parse().ok()?;
+    (seconds.is_finite() && seconds >= 0.0)
+        .then(|| Duration::from_secs_f64(seconds.min(MAX_CRAWL_DELAY)))
+}
+
+/// The rules of a robots.txt file that apply to the crawler.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Robots {
+    /// Whether each rule allows or disallows, with its path pattern.
+    rules: Vec<(bool, String)>,
+    crawl_delay: Option<Duration>,
+}
+
+impl Robots {
+    /// Parses a robots.txt file, keeping the rules of the groups matching `user_agent`, or of the `*` group if none
+    /// do.
+    fn parse(text: &str, user_agent: &str) -> Self {
+        let user_agent = user_agent.to_ascii_lowercase();
+        let mut specific = Robots::default();
+        let mut any = Robots::default();
+        let mut matched = false;
+        let mut agents: Vec<String> = Vec::new();
+        let mut in_rules = false;
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("");
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let (key, value) = (key.trim().to_ascii_lowercase(), value.trim());
+            if key == "user-agent" {
+                if in_rules {
+                    agents.clear();
+                    in_rules = false;
+                }
+                agents.push(value.to_ascii_lowercase());
+                continue;
+            }
+            in_rules = true;
+            let is_any = agents.iter().any(|agent| agent == "*");
+            let is_specific = agents
+                .iter()
+                .any(|agent| agent != "*" && user_agent.contains(agent.as_str()));
+            let mut targets = Vec::new();
+            if is_specific {
+                matched = true;
+                targets.push(&mut specific);
+            } else if is_any {
+                targets.push(&mut any);
+            }
+            for robots in targets {
+                match key.as_str() {
+                    "allow" | "disallow" if !value.is_empty() => {
+                        robots.rules.push((key == "allow", value.to_string()))
+                    }
+                    "crawl-delay" => robots.crawl_delay = parse_crawl_delay(value),
+                    _ => {}
+                }
+            }
+        }
+        if matched {
+            specific
+        } else {
+            any
+        }
+    }
+
+    /// Returns whether `path`, with its query, may be fetched: the longest matching rule wins, and allow rules win
+    /// ties.
+    fn allows(&self, path: &str) -> bool {
+        let rule = self
+            .rules
+            .iter()
+            .filter(|(_, pattern)| rule_matches(pattern, path))
+            .max_by_key(|(allow, pattern)| (pattern.len(), *allow));
+        match rule {
+            Some((allow, _)) => *allow,
+            None => true,
+        }
+    }
+}
+
+/// Matches a robots.txt path pattern, where `*` matches any characters and a final `$` anchors it at the end.
+fn rule_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let Some(mut rest) = path.strip_prefix(parts.next().unwrap_or("")) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        if anchored && i == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(j) => rest = &rest[j + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+/// Returns whether a sitemap is a sitemap index, and the locations it lists.
+fn parse_sitemap(xml: &str) -> (bool, Vec<String>) {
+    let locations = xml
+        .split("<loc>")
+        .skip(1)
+        .filter_map(|rest| rest.split_once("</loc>").map(|(loc, _)| loc.trim()))
+        .map(|loc| {
+            let loc = loc
+                .strip_prefix("<![CDATA[")
+                .and_then(|loc| loc.strip_suffix("]]>"))
+                .unwrap_or(loc);
+            decode_entities(loc.trim()).into_owned()
+        })
+        .collect();
+    (xml.contains("<sitemapindex"), locations)
+}
+
+enum CrawlSource {
+    Url(String),
+    Sitemap(String),
+}
+
+/// The outcome of fetching a page: the URL it was queued as, its depth, and its final URL and HTML if it is a page.
+type Fetched = (Url, usize, Result<Option<(Url, String)>, reqwest::Error>);
+
+struct CrawlState {
+    started: bool,
+    queue: VecDeque<(Url, usize)>,
+    seen: HashSet<Url>,
+    domains: Vec<String>,
+    robots: HashMap<String, Robots>,
+    next_start: HashMap<String, Instant>,
+    fetches: usize,
+    in_flight: FuturesUnordered<BoxFuture<'static, Fetched>>,
+    errors: VecDeque<CrawlerError>,
+}
+
+impl CrawlState {
+    fn enqueue(&mut self, mut url: Url, depth: usize) {
+        url.set_fragment(None);
+        let allowed = url.host_str().is_some_and(|host| {
+            self.domains
+                .iter()
+                .any(|domain| host == domain || host.ends_with(&format!(".{}", domain)))
+        });
+        if allowed && self.seen.insert(url.clone()) {
+            self.queue.push_back((url, depth));
+        }
+    }
+}
+
+/// Crawls a website, loading its pages as documents.
+///
+/// The crawl starts from a seed page, following links up to `with_max_depth` links away, or from a sitemap, loading
+/// the pages it lists. Only pages on the domains of the seed or sitemap, and their subdomains, are fetched unless
+/// `with_allowed_domains` is set, and at most `with_max_pages` pages are fetched.
+///
+/// The crawler is polite: it fetches at most `with_concurrency` pages at a time, follows the rules of the robots.txt
+/// file of each site for its user agent, including its crawl delay, and skips links marked `nofollow`. Pages are
+/// reduced to their main content like with `HtmlLoader`.
+///
+/// `crawl` yields the documents as a stream as soon as their pages are fetched, so they can be indexed while the
+/// crawl goes on.
+///
+/// # Example
+///
+/// ```no_run
+/// use futures::StreamExt;
+/// use llm_chain::loaders::Crawler;
+///
+/// # async fn example() {
+/// let crawler = Crawler::from_url("https://docs.example.com/")
+///     .with_max_depth(3)
+///     .with_max_pages(500);
+/// let mut pages = Box::pin(crawler.crawl());
+/// while let Some(page) = pages.next().await {
+///     match page {
+///         Ok(document) => println!("{}", document.page_content),
+///         Err(error) => eprintln!("{}", error),
+///     }
+/// }
+/// # }
+/// ```
+pub struct Crawler {
+    source: CrawlSource,
+    client: reqwest::Client,
+    user_agent: String,
+    allowed_domains: Option<Vec<String>>,
+    max_depth: usize,
+    max_pages: usize,
+    concurrency: usize,
+    delay: Duration,
+    respect_robots: bool,
+    remove_boilerplate: bool,
+}
+
+impl Crawler {
+    /// Creates a crawler starting from the page at `url`, following links up to 2 links away by default.
+    pub fn from_url<S: Into<String>>(url: S) -> Self {
+        Self::new(CrawlSource::Url(url.into()), 2)
+    }
+
+    /// Creates a crawler loading the pages listed in the sitemap or sitemap index at `url`. By default, links in
+    /// those pages aren't followed.
+    pub fn from_sitemap<S: Into<String>>(url: S) -> Self {
+        Self::new(CrawlSource::Sitemap(url.into()), 0)
+    }
+
+    fn new(source: CrawlSource, max_depth: usize) -> Self {
+        Self {
+            source,
+            client: reqwest::Client::new(),
+            user_agent: "llm-chain".to_string(),
+            allowed_domains: None,
+            max_depth,
+            max_pages: 100,
+            concurrency: 4,
+            delay: Duration::ZERO,
+            respect_robots: true,
+            remove_boilerplate: true,
+        }
+    }
+
+    /// Sets the client used to fetch pages, for example to set a timeout.
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Sets the user agent sent with requests and looked up in robots.txt files. Defaults to `llm-chain`.
+    pub fn with_user_agent<S: Into<String>>(mut self, user_agent: S) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// Sets the domains whose pages are fetched, along with their subdomains.
+    pub fn with_allowed_domains<S: Into<String>>(mut self, domains: Vec<S>) -> Self {
+        self.allowed_domains = Some(domains.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Sets how many links away from the seed page or the sitemap pages are followed.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Sets the maximum number of pages fetched. Defaults to 100.
+    pub fn with_max_pages(mut self, max_pages: usize) -> Self {
+        self.max_pages = max_pages;
+        self
+    }
+
+    /// Sets the maximum number of pages fetched at the same time. Defaults to 4.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Sets the minimum delay between the starts of two requests to the same site. A longer crawl delay in the
+    /// robots.txt file of the site takes precedence.
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Sets whether robots.txt files are followed. Defaults to `true`; only disable it for sites you own.
+    pub fn with_robots_txt(mut self, respect_robots: bool) -> Self {
+        self.respect_robots = respect_robots;
+        self
+    }
+
+    /// Sets whether boilerplate is removed from pages, see `HtmlLoader::with_boilerplate_removal`.
+    pub fn with_boilerplate_removal(mut self, remove_boilerplate: bool) -> Self {
+        self.remove_boilerplate = remove_boilerplate;
+        self
+    }
+
+    /// Crawls the site, yielding a document for every page with text, or an error for every page or sitemap that
+    /// couldn't be fetched. Errors don't stop the crawl.
+    pub fn crawl(
+        &self,
+    ) -> impl Stream<Item = Result<Document<HtmlMetadata>, CrawlerError>> + Send + '_ {
+        let state = CrawlState {
+            started: false,
+            queue: VecDeque::new(),
+            seen: HashSet::new(),
+            domains: self.allowed_domains.clone().unwrap_or_default(),
+            robots: HashMap::new(),
+            next_start: HashMap::new(),
+            fetches: 0,
+            in_flight: FuturesUnordered::new(),
+            errors: VecDeque::new(),
+        };
+        stream::unfold(state, move |mut state| async move {
+            if !state.started {
+                state.started = true;
+                self.start(&mut state).await;
+            }
+            loop {
+                if let Some(error) = state.errors.pop_front() {
+                    return Some((Err(error), state));
+                }
+                while state.in_flight.len() < self.concurrency && state.fetches < self.max_pages {
+                    let Some((url, depth)) = state.queue.pop_front() else {
+                        break;
+                    };
+                    if self.allowed(&mut state, &url).await {
+                        self.fetch(&mut state, url, depth);
+                    }
+                }
+                let (url, depth, result) = state.in_flight.next().await?;
+                let (url, html) = match result {
+                    Ok(Some(page)) => page,
+                    Ok(None) => continue,
+                    Err(source) => {
+                        return Some((Err(CrawlerError::Request { url, source }), state))
+                    }
+                };
+                state.seen.insert(url.clone());
+                if depth < self.max_depth {
+                    for link in extract_links(&html, &url) {
+                        state.enqueue(link, depth + 1);
+                    }
+                }
+                let extracted = extract_html(&html, self.remove_boilerplate);
+                if extracted.text.is_empty() {
+                    continue;
+                }
+                let provenance = Provenance::new(url.as_str(), extracted.text.len());
+                let document = Document::new(extracted.text)
+                    .with_metadata(HtmlMetadata {
+                        url: url.to_string(),
+                        title: extracted.title,
+                    })
+                    .with_provenance(provenance);
+                return Some((Ok(document), state));
+            }
+        })
+    }
+
+    /// Queues the seed page, or the pages listed in the sitemap and the sitemaps it refers to.
+    async fn start(&self, state: &mut CrawlState) {
+        let (url, is_sitemap) = match &self.source {
+            CrawlSource::Url(url) => (url, false),
+            CrawlSource::Sitemap(url) => (url, true),
+        };
+        let Ok(url) = Url::parse(url) else {
+            state
+                .errors
+                .push_back(CrawlerError::InvalidUrl(url.clone()));
+            return;
+        };
+        if self.allowed_domains.is_none() {
+            state.domains.extend(url.host_str().map(str::to_string));
+        }
+        if !is_sitemap {
+            state.enqueue(url, 0);
+            return;
+        }
+        let mut sitemaps = VecDeque::from([url]);
+        let mut seen_sitemaps = HashSet::new();
+        while let Some(sitemap) = sitemaps.pop_front() {
+            if !seen_sitemaps.insert(sitemap.clone()) {
+                continue;
+            }
+            let xml = match self.get(sitemap.clone()).await {
+                Ok(response) => response.text().await,
+                Err(source) => Err(source),
+            };
+            let xml = match xml {
+                Ok(xml) => xml,
+                Err(source) => {
+                    state.errors.push_back(CrawlerError::Request {
+                        url: sitemap,
+                        source,
+                    });
+                    continue;
+                }
+            };
+            let (is_index, locations) = parse_sitemap(&xml);
+            for location in locations {
+                match sitemap.join(&location) {
+                    Ok(url) if is_index => sitemaps.push_back(url),
+                    Ok(url) => state.enqueue(url, 0),
+                    Err(_) => state.errors.push_back(CrawlerError::InvalidUrl(location)),
+                }
+            }
+        }
+    }
+
+    async fn get(&self, url: Url) -> Result<reqwest::Response, reqwest::Error> {
+        self.client
+            .get(url)
+            .header(USER_AGENT, &self.user_agent)
+            .send()
+            .await?
+            .error_for_status()
+    }
+
+    /// Returns whether the robots.txt file of the site of `url` allows fetching it, fetching the file if needed.
+    /// Sites whose robots.txt file can't be fetched are crawled without restrictions.
+    async fn allowed(&self, state: &mut CrawlState, url: &Url) -> bool {
+        if !self.respect_robots {
+            return true;
+        }
+        let origin = url.origin().ascii_serialization();
+        if !state.robots.contains_key(&origin) {
+            let robots = match url.join("/robots.txt") {
+                Ok(robots_url) => match self.get(robots_url).await {
+                    Ok(response) => response.text().await.unwrap_or_default(),
+                    Err(_) => String::new(),
+                },
+                Err(_) => String::new(),
+            };
+            state
+                .robots
+                .insert(origin.clone(), Robots::parse(&robots, &self.user_agent));
+        }
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        state.robots[&origin].allows(&path)
+    }
+
+    /// Starts fetching `url`, after the delay between requests to its site.
+    fn fetch(&self, state: &mut CrawlState, url: Url, depth: usize) {
+        let origin = url.origin().ascii_serialization();
+        let crawl_delay = state
+            .robots
+            .get(&origin)
+            .and_then(|robots| robots.crawl_delay);
+        let delay = crawl_delay.map_or(self.delay, |crawl_delay| crawl_delay.max(self.delay));
+        let now = Instant::now();
+        let start = state
+            .next_start
+            .get(&origin)
+            .map_or(now, |&next| next.max(now));
+        state.next_start.insert(origin, start + delay);
+        state.fetches += 1;
+
+        let request = self
+            .client
+            .get(url.clone())
+            .header(USER_AGENT, &self.user_agent);
+        state.in_flight.push(Box::pin(async move {
+            tokio::time::sleep_until(start).await;
+            let result = async {
+                let response = request.send().await?.error_for_status()?;
+                let is_html = response
+                    .headers()
+                    .get(CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok())
+                    .is_some_and(|value| value.contains("html"));
+                if !is_html && response.headers().contains_key(CONTENT_TYPE) {
+                    return Ok(None);
+                }
+                let url = response.url().clone();
+                Ok(Some((url, response.text().await?)))
+            }
+            .await;
+            (url, depth, result)
+        }));
+    }
+}
+
+#[async_trait]
+impl DocumentLoader for Crawler {
+    type Metadata = HtmlMetadata;
+    type Error = CrawlerError;
+
+    /// Crawls the site and returns the documents of all the pages that could be fetched.
+    async fn load(&self) -> Result<Vec<Document<HtmlMetadata>>, CrawlerError> {
+        Ok(self
+            .crawl()
+            .filter_map(|page| async move { page.ok() })
+            .collect()
+            .await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extract_links, parse_sitemap, Robots};
+    use reqwest::Url;
+    use std::time::Duration;
+
+    #[test]
+    fn parses_robots_and_sitemaps() {
+        let robots = "User-agent: *\nDisallow: /\n\nUser-agent: llm-chain\nUser-agent: other\nDisallow: /private\nAllow: /private/docs\nDisallow: /*.pdf$\nCrawl-delay: 2 # seconds\n";
+        let robots = Robots::parse(robots, "llm-chain/0.11");
+        assert!(robots.allows("/"));
+        assert!(!robots.allows("/private/keys"));
+        assert!(robots.allows("/private/docs/intro"));
+        assert!(!robots.allows("/files/report.pdf"));
+        assert!(robots.allows("/files/report.pdf?page=2"));
+        assert_eq!(robots.crawl_delay.map(|d| d.as_secs()), Some(2));
+        assert!(!Robots::parse("User-agent: *\nDisallow: /\n", "llm-chain").allows("/a"));
+        let delay = |value: &str| {
+            Robots::parse(
+                &format!("User-agent: *\nCrawl-delay: {}\n", value),
+                "llm-chain",
+            )
+            .crawl_delay
+        };
+        assert_eq!(delay("0.5"), Some(Duration::from_millis(500)));
+        assert_eq!(delay("-1"), None);
+        assert_eq!(delay("inf"), None);
+        assert_eq!(delay("NaN"), None);
+        assert_eq!(delay("1e300"), Some(Duration::from_secs(60)));
+
+        let html = r#"<a href="/docs#intro">Docs</a> <a href='guide.html'>Guide</a> <a href="mailto:a@b.c">Mail</a> <a rel="nofollow" href="/login">Login</a>"#;
+        let base = Url::parse("https://example.com/blog/post").unwrap();
+        let links: Vec<String> = extract_links(html, &base)
+            .iter()
+            .map(Url::to_string)
+            .collect();
+        assert_eq!(
+            links,
+            vec![
+                "https://example.com/docs",
+                "https://example.com/blog/guide.html"
+            ]
+        );
+
+        let sitemap = "<?xml version=\"1.0\"?>\n<urlset><url><loc> https://example.com/a?x=1&amp;y=2 </loc></url><url><loc><![CDATA[https://example.com/b]]></loc></url></urlset>";
+        assert_eq!(
+            parse_sitemap(sitemap),
+            (
+                false,
+                vec![
+                    "https://example.com/a?x=1&y=2".to_string(),
+                    "https://example.com/b".to_string()
+                ]
+            )
+        );
+    }
+}
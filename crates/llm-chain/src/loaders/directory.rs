@@ -0,0 +1,373 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::DocumentLoader;
+use crate::schema::{Document, Provenance};
+
+const MARKDOWN_EXTENSIONS: &[&str] = &["md", "markdown", "mdx"];
+const TEXT_EXTENSIONS: &[&str] = &["txt", "text", "rst", "adoc", "org"];
+const CODE_EXTENSIONS: &[&str] = &[
+    "rs", "py", "pyi", "js", "jsx", "mjs", "cjs", "ts", "tsx", "mts", "cts", "go", "java", "kt",
+    "scala", "c", "h", "cc", "cpp", "hpp", "cs", "rb", "php", "swift", "sh", "sql",
+];
+
+/// How a file is loaded, and which splitter suits its documents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileKind {
+    Text,
+    Markdown,
+    Code,
+    /// Loaded with `PdfLoader`, one document per page. PDF files are skipped without the `pdf` feature.
+    Pdf,
+    /// Loaded with `DocxLoader`, as Markdown. DOCX files are skipped without the `docx` feature.
+    Docx,
+}
+
+#[derive(Debug, Error)]
+pub enum DirectoryLoaderError {
+    #[error("Unable to read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Invalid glob pattern: {0}")]
+    Glob(#[from] globset::Error),
+    #[cfg(feature = "pdf")]
+    #[error("Unable to load {path}: {source}")]
+    Pdf {
+        path: PathBuf,
+        source: super::PdfLoaderError,
+    },
+    #[cfg(feature = "docx")]
+    #[error("Unable to load {path}: {source}")]
+    Docx {
+        path: PathBuf,
+        source: super::DocxLoaderError,
+    },
+}
+
+/// The metadata of a document loaded from a file of a directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileMetadata {
+    /// The path of the file relative to the directory, with `/` separators.
+    pub path: String,
+    pub kind: FileKind,
+    /// When the file was last modified, in seconds since the Unix epoch, if the platform records it.
+    pub modified: Option<u64>,
+    /// The number of the page, starting at 1, for documents loaded from pages of a PDF.
+    pub page: Option<u32>,
+}
+
+/// Loads the files of a directory and its subdirectories as documents.
+///
+/// Files are selected with glob patterns matched against their path relative to the directory: `*` doesn't match
+/// `/`, so `*.md` only matches files at the top of the directory while `**/*.md` matches them everywhere. Hidden
+/// files and directories are skipped unless `with_hidden_files` is set.
+///
+/// Each file is loaded according to the kind of its extension: text, Markdown, code and Word files as one document
+/// each, and PDFs one document per page. Files with other extensions are skipped. The kind is recorded in the metadata so
+/// documents can be sent to the right splitter, such as `MarkdownSplitter` for Markdown.
+///
+/// Documents are identified by the relative path of their file, and record its modification time, so a later run
+/// can reindex only what changed with `with_modified_since`.
+pub struct DirectoryLoader {
+    root: PathBuf,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    kinds: HashMap<String, FileKind>,
+    hidden_files: bool,
+    modified_since: Option<SystemTime>,
+}
+
+impl DirectoryLoader {
+    /// Creates a loader for the files of the directory at `root`.
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        let kinds = [
+            (MARKDOWN_EXTENSIONS, FileKind::Markdown),
+            (TEXT_EXTENSIONS, FileKind::Text),
+            (CODE_EXTENSIONS, FileKind::Code),
+            (&["pdf"][..], FileKind::Pdf),
+            (&["docx"][..], FileKind::Docx),
+        ]
+        .iter()
+        .flat_map(|(extensions, kind)| extensions.iter().map(|ext| (ext.to_string(), *kind)))
+        .collect();
+        Self {
+            root: root.as_ref().to_path_buf(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            kinds,
+            hidden_files: false,
+            modified_since: None,
+        }
+    }
+
+    /// Only loads files matching one of `patterns`. By default, every file with a known extension is loaded.
+    pub fn with_include<S: Into<String>>(mut self, patterns: Vec<S>) -> Self {
+        self.include = patterns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Skips files matching one of `patterns`, such as `target/**`.
+    pub fn with_exclude<S: Into<String>>(mut self, patterns: Vec<S>) -> Self {
+        self.exclude = patterns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Loads files with the extension `extension`, without the leading dot, as `kind`.
+    pub fn with_extension<S: Into<String>>(mut self, extension: S, kind: FileKind) -> Self {
+        self.kinds
+            .insert(extension.into().to_ascii_lowercase(), kind);
+        self
+    }
+
+    /// Sets whether hidden files and directories, whose names start with a dot, are loaded. Defaults to `false`.
+    pub fn with_hidden_files(mut self, hidden_files: bool) -> Self {
+        self.hidden_files = hidden_files;
+        self
+    }
+
+    /// Only loads files modified after `time`, such as the time of the previous indexing run.
+    pub fn with_modified_since(mut self, time: SystemTime) -> Self {
+        self.modified_since = Some(time);
+        self
+    }
+
+    /// Returns the paths of the files to load, relative to the directory, with their kinds, sorted by path.
+    pub fn files(&self) -> Result<Vec<(String, FileKind)>, DirectoryLoaderError> {
+        let include = glob_set(&self.include)?;
+        let exclude = glob_set(&self.exclude)?;
+        let mut files = Vec::new();
+        let mut directories = vec![self.root.clone()];
+        while let Some(directory) = directories.pop() {
+            let entries = std::fs::read_dir(&directory).map_err(|source| io(&directory, source))?;
+            for entry in entries {
+                let entry = entry.map_err(|source| io(&directory, source))?;
+                let path = entry.path();
+                let name = entry.file_name();
+                if !self.hidden_files && name.to_string_lossy().starts_with('.') {
+                    continue;
+                }
+                let Ok(relative) = path.strip_prefix(&self.root) else {
+                    continue;
+                };
+                let relative: Vec<_> = relative
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect();
+                let relative = relative.join("/");
+                let file_type = entry.file_type().map_err(|source| io(&path, source))?;
+                if file_type.is_dir() {
+                    if !exclude.is_match(&relative) {
+                        directories.push(path);
+                    }
+                    continue;
+                }
+                let kind = path
+                    .extension()
+                    .and_then(|ext| self.kinds.get(&ext.to_string_lossy().to_ascii_lowercase()));
+                let Some(kind) = kind else {
+                    continue;
+                };
+                let included = self.include.is_empty() || include.is_match(&relative);
+                if included && !exclude.is_match(&relative) {
+                    files.push((relative, *kind));
+                }
+            }
+        }
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(files)
+    }
+
+    /// Loads the files of the directory.
+    pub fn load_files(&self) -> Result<Vec<Document<FileMetadata>>, DirectoryLoaderError> {
+        let mut documents = Vec::new();
+        for (relative, kind) in self.files()? {
+            let path = self.root.join(&relative);
+            let modified = std::fs::metadata(&path)
+                .and_then(|metadata| metadata.modified())
+                .ok();
+            if let (Some(since), Some(modified)) = (self.modified_since, modified) {
+                if modified <= since {
+                    continue;
+                }
+            }
+            let metadata = FileMetadata {
+                path: relative.clone(),
+                kind,
+                modified: modified
+                    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                    .map(|duration| duration.as_secs()),
+                page: None,
+            };
+            let text = match kind {
+                FileKind::Pdf => {
+                    documents.extend(self.load_pdf(&path, metadata)?);
+                    continue;
+                }
+                FileKind::Docx => self.load_docx(&path)?,
+                _ => {
+                    let bytes = std::fs::read(&path).map_err(|source| io(&path, source))?;
+                    String::from_utf8_lossy(&bytes).into_owned()
+                }
+            };
+            if text.trim().is_empty() {
+                continue;
+            }
+            let provenance = Provenance::new(relative.as_str(), text.len());
+            documents.push(
+                Document::new(text)
+                    .with_id(relative)
+                    .with_metadata(metadata)
+                    .with_provenance(provenance),
+            );
+        }
+        Ok(documents)
+    }
+
+    #[cfg(feature = "pdf")]
+    fn load_pdf(
+        &self,
+        path: &Path,
+        metadata: FileMetadata,
+    ) -> Result<Vec<Document<FileMetadata>>, DirectoryLoaderError> {
+        let pages = super::PdfLoader::from_path(path)
+            .load_pages()
+            .map_err(|source| DirectoryLoaderError::Pdf {
+                path: path.to_path_buf(),
+                source,
+            })?;
+        Ok(pages
+            .into_iter()
+            .map(|page| {
+                let number = page.metadata.as_ref().map(|m| m.page);
+                let provenance = page.provenance.map(|provenance| Provenance {
+                    source_id: metadata.path.clone(),
+                    ..provenance
+                });
+                Document {
+                    id: number.map(|n| format!("{}#page={}", metadata.path, n)),
+                    page_content: page.page_content,
+                    metadata: Some(FileMetadata {
+                        page: number,
+                        ..metadata.clone()
+                    }),
+                    provenance,
+                }
+            })
+            .collect())
+    }
+
+    #[cfg(not(feature = "pdf"))]
+    fn load_pdf(
+        &self,
+        _path: &Path,
+        _metadata: FileMetadata,
+    ) -> Result<Vec<Document<FileMetadata>>, DirectoryLoaderError> {
+        Ok(Vec::new())
+    }
+
+    #[cfg(feature = "docx")]
+    fn load_docx(&self, path: &Path) -> Result<String, DirectoryLoaderError> {
+        let bytes = std::fs::read(path).map_err(|source| io(path, source))?;
+        super::extract_docx(&bytes)
+            .map(|extracted| extracted.text)
+            .map_err(|source| DirectoryLoaderError::Docx {
+                path: path.to_path_buf(),
+                source,
+            })
+    }
+
+    #[cfg(not(feature = "docx"))]
+    fn load_docx(&self, _path: &Path) -> Result<String, DirectoryLoaderError> {
+        Ok(String::new())
+    }
+}
+
+fn glob_set(patterns: &[String]) -> Result<GlobSet, globset::Error> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(glob(pattern)?);
+    }
+    builder.build()
+}
+
+fn glob(pattern: &str) -> Result<Glob, globset::Error> {
+    GlobBuilder::new(pattern).literal_separator(true).build()
+}
+
+fn io(path: &Path, source: std::io::Error) -> DirectoryLoaderError {
+    DirectoryLoaderError::Io {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+#[async_trait]
+impl DocumentLoader for DirectoryLoader {
+    type Metadata = FileMetadata;
+    type Error = DirectoryLoaderError;
+
+    async fn load(&self) -> Result<Vec<Document<FileMetadata>>, DirectoryLoaderError> {
+        self.load_files()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DirectoryLoader, FileKind};
+    use std::fs;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn loads_matching_files() {
+        let root = std::env::temp_dir().join(format!("llm-chain-{}", uuid::Uuid::new_v4()));
+        for (path, content) in [
+            ("README.md", "# Readme"),
+            ("docs/guide.md", "# Guide"),
+            ("src/lib.rs", "fn main() {}"),
+            ("target/debug/out.rs", "// generated"),
+            (".git/HEAD", "ref: main"),
+            ("notes.txt", "notes"),
+            ("image.png", "not text"),
+        ] {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+
+        let loader = DirectoryLoader::new(&root).with_exclude(vec!["target/**"]);
+        let files = loader.files().unwrap();
+        assert_eq!(
+            files,
+            vec![
+                ("README.md".to_string(), FileKind::Markdown),
+                ("docs/guide.md".to_string(), FileKind::Markdown),
+                ("notes.txt".to_string(), FileKind::Text),
+                ("src/lib.rs".to_string(), FileKind::Code),
+            ]
+        );
+
+        let documents = loader.with_include(vec!["**/*.md"]).load_files().unwrap();
+        assert_eq!(documents.len(), 2);
+        assert_eq!(documents[1].id.as_deref(), Some("docs/guide.md"));
+        let metadata = documents[1].metadata.as_ref().unwrap();
+        assert_eq!(metadata.kind, FileKind::Markdown);
+        assert!(metadata.modified.is_some());
+
+        let later = SystemTime::now() + Duration::from_secs(60);
+        let unchanged = DirectoryLoader::new(&root)
+            .with_modified_since(later)
+            .load_files()
+            .unwrap();
+        assert!(unchanged.is_empty());
+        fs::remove_dir_all(root).unwrap();
+    }
+}
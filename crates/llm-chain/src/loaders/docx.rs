@@ -0,0 +1,297 @@
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+use async_trait::async_trait;
+use roxmltree::Node;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+use super::DocumentLoader;
+use crate::schema::{Document, Provenance};
+
+/// The namespace of the elements of the main part of a Word document.
+const WORD: &str = "http://schemas.openxmlformats.org/wordprocessingml/2006/main";
+/// The namespace of the Dublin Core elements of the document properties.
+const DUBLIN_CORE: &str = "http://purl.org/dc/elements/1.1/";
+
+#[derive(Debug, Error)]
+pub enum DocxLoaderError {
+    #[error("Unable to read the DOCX file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Unable to unzip the DOCX file: {0}")]
+    Zip(#[from] ZipError),
+    #[error("Unable to parse the XML of the DOCX file: {0}")]
+    Xml(#[from] roxmltree::Error),
+    #[error("The file has no word/document.xml, it isn't a Word document")]
+    NotAWordDocument,
+}
+
+/// The metadata of a document loaded from a DOCX file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DocxMetadata {
+    /// The path or other identifier of the file.
+    pub source: String,
+    /// The title in the properties of the file, or its first heading if it has none.
+    pub title: Option<String>,
+}
+
+/// The text extracted from a DOCX file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtractedDocx {
+    pub title: Option<String>,
+    /// The text of the document as Markdown: headings start with `#`, list items with `-`, and table rows are
+    /// written with their cells separated by `|`.
+    pub text: String,
+}
+
+fn is_word(node: &Node, name: &str) -> bool {
+    node.tag_name().name() == name && node.tag_name().namespace() == Some(WORD)
+}
+
+fn word_attribute<'a>(node: &Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.attribute((WORD, name))
+}
+
+/// Returns the heading level of a paragraph from its style or outline level, if it is a heading.
+fn heading_level(properties: Node) -> Option<usize> {
+    let style = properties
+        .children()
+        .find(|n| is_word(n, "pStyle"))
+        .and_then(|n| word_attribute(&n, "val"))
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    if style == "title" {
+        return Some(1);
+    }
+    let level = match style.strip_prefix("heading") {
+        Some(level) => level.trim().parse::<usize>().ok(),
+        None => properties
+            .children()
+            .find(|n| is_word(n, "outlineLvl"))
+            .and_then(|n| word_attribute(&n, "val"))
+            .and_then(|level| level.parse::<usize>().ok())
+            .map(|level| level + 1),
+    };
+    level
+        .filter(|level| (1..=9).contains(level))
+        .map(|level| level.min(6))
+}
+
+/// Returns the text of the runs of a paragraph.
+fn paragraph_text(paragraph: Node) -> String {
+    let mut text = String::new();
+    for node in paragraph.descendants() {
+        if is_word(&node, "t") {
+            text.push_str(node.text().unwrap_or(""));
+        } else if is_word(&node, "tab") {
+            text.push('\t');
+        } else if is_word(&node, "br") || is_word(&node, "cr") {
+            text.push('\n');
+        }
+    }
+    text.trim().to_string()
+}
+
+/// Appends the paragraphs and tables of a block container, such as the body or a content control, to `blocks`.
+fn extract_blocks(container: Node, blocks: &mut Vec<String>, first_heading: &mut Option<String>) {
+    for node in container.children() {
+        if is_word(&node, "p") {
+            let text = paragraph_text(node);
+            if text.is_empty() {
+                continue;
+            }
+            let properties = node.children().find(|n| is_word(n, "pPr"));
+            let heading = properties.and_then(heading_level);
+            let list_item = properties.is_some_and(|p| p.children().any(|n| is_word(&n, "numPr")));
+            blocks.push(match (heading, list_item) {
+                (Some(level), _) => {
+                    first_heading.get_or_insert_with(|| text.clone());
+                    format!("{} {}", "#".repeat(level), text.replace('\n', " "))
+                }
+                (None, true) => format!("- {}", text),
+                (None, false) => text,
+            });
+        } else if is_word(&node, "tbl") {
+            let rows: Vec<String> = node
+                .children()
+                .filter(|n| is_word(n, "tr"))
+                .map(|row| {
+                    row.children()
+                        .filter(|n| is_word(n, "tc"))
+                        .map(|cell| {
+                            cell.descendants()
+                                .filter(|n| is_word(n, "p"))
+                                .map(paragraph_text)
+                                .filter(|text| !text.is_empty())
+                                .collect::<Vec<_>>()
+                                .join(" ")
+                                .replace('\n', " ")
+                        })
+                        .collect::<Vec<_>>()
+                        .join(" | ")
+                })
+                .filter(|row| row.chars().any(|c| c != '|' && !c.is_whitespace()))
+                .collect();
+            if !rows.is_empty() {
+                blocks.push(rows.join("\n"));
+            }
+        } else if is_word(&node, "sdt") {
+            if let Some(content) = node.children().find(|n| is_word(n, "sdtContent")) {
+                extract_blocks(content, blocks, first_heading);
+            }
+        }
+    }
+}
+
+fn read_entry<R: Read + std::io::Seek>(
+    archive: &mut ZipArchive<R>,
+    name: &str,
+) -> Result<Option<String>, DocxLoaderError> {
+    match archive.by_name(name) {
+        Ok(mut file) => {
+            let mut text = String::new();
+            file.read_to_string(&mut text)?;
+            Ok(Some(text))
+        }
+        Err(ZipError::FileNotFound) => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Extracts the title and the text of the paragraphs, headings and tables of a DOCX file.
+pub fn extract_docx(bytes: &[u8]) -> Result<ExtractedDocx, DocxLoaderError> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+    let xml =
+        read_entry(&mut archive, "word/document.xml")?.ok_or(DocxLoaderError::NotAWordDocument)?;
+    let document = roxmltree::Document::parse(&xml)?;
+    let mut blocks = Vec::new();
+    let mut first_heading = None;
+    if let Some(body) = document
+        .root_element()
+        .children()
+        .find(|n| is_word(n, "body"))
+    {
+        extract_blocks(body, &mut blocks, &mut first_heading);
+    }
+
+    let mut title = None;
+    if let Some(core) = read_entry(&mut archive, "docProps/core.xml")? {
+        title = roxmltree::Document::parse(&core)?
+            .descendants()
+            .find(|n| {
+                n.tag_name().name() == "title" && n.tag_name().namespace() == Some(DUBLIN_CORE)
+            })
+            .and_then(|n| n.text())
+            .map(|title| title.trim().to_string())
+            .filter(|title| !title.is_empty());
+    }
+    Ok(ExtractedDocx {
+        title: title.or(first_heading),
+        text: blocks.join("\n\n"),
+    })
+}
+
+/// Loads the text of a Word document (.docx) as a document.
+///
+/// Paragraphs, headings, lists and tables are extracted in order. The text is written as Markdown, so it can be split
+/// along its headings with `MarkdownSplitter`. Parsing runs on the calling task, like for `PdfLoader`.
+pub struct DocxLoader {
+    source: String,
+    bytes: Option<Vec<u8>>,
+}
+
+impl DocxLoader {
+    /// Creates a loader for the DOCX file at `path`, which is read when the loader is run.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            source: path.as_ref().display().to_string(),
+            bytes: None,
+        }
+    }
+
+    /// Creates a loader for a DOCX file already in memory, identified by `source`.
+    pub fn from_bytes<S: Into<String>>(source: S, bytes: Vec<u8>) -> Self {
+        Self {
+            source: source.into(),
+            bytes: Some(bytes),
+        }
+    }
+
+    /// Loads the text of the file, or nothing if it has none.
+    pub fn load_text(&self) -> Result<Option<Document<DocxMetadata>>, DocxLoaderError> {
+        let extracted = match &self.bytes {
+            Some(bytes) => extract_docx(bytes)?,
+            None => extract_docx(&std::fs::read(&self.source)?)?,
+        };
+        if extracted.text.is_empty() {
+            return Ok(None);
+        }
+        let provenance = Provenance::new(self.source.as_str(), extracted.text.len());
+        Ok(Some(
+            Document::new(extracted.text)
+                .with_metadata(DocxMetadata {
+                    source: self.source.clone(),
+                    title: extracted.title,
+                })
+                .with_provenance(provenance),
+        ))
+    }
+}
+
+#[async_trait]
+impl DocumentLoader for DocxLoader {
+    type Metadata = DocxMetadata;
+    type Error = DocxLoaderError;
+
+    async fn load(&self) -> Result<Vec<Document<DocxMetadata>>, DocxLoaderError> {
+        Ok(self.load_text()?.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extract_docx, DocxLoaderError};
+    use std::io::{Cursor, Write};
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    const DOCUMENT: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+  <w:body>
+    <w:p><w:pPr><w:pStyle w:val="Heading1"/></w:pPr><w:r><w:t>Onboarding</w:t></w:r></w:p>
+    <w:p><w:r><w:t xml:space="preserve">Welcome to </w:t></w:r><w:r><w:rPr><w:b/></w:rPr><w:t>the team</w:t></w:r><w:r><w:t>.</w:t></w:r></w:p>
+    <w:p><w:pPr><w:numPr><w:ilvl w:val="0"/><w:numId w:val="1"/></w:numPr></w:pPr><w:r><w:t>Get a laptop</w:t></w:r></w:p>
+    <w:p><w:pPr><w:pStyle w:val="Heading2"/></w:pPr><w:r><w:t>Contacts</w:t></w:r></w:p>
+    <w:tbl>
+      <w:tr><w:tc><w:p><w:r><w:t>Role</w:t></w:r></w:p></w:tc><w:tc><w:p><w:r><w:t>Name</w:t></w:r></w:p></w:tc></w:tr>
+      <w:tr><w:tc><w:p><w:r><w:t>Manager</w:t></w:r></w:p></w:tc><w:tc><w:p><w:r><w:t>Ada</w:t></w:r></w:p></w:tc></w:tr>
+    </w:tbl>
+    <w:p/>
+  </w:body>
+</w:document>"#;
+
+    fn docx(entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in entries {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn extracts_paragraphs_headings_and_tables() {
+        let extracted = extract_docx(&docx(&[("word/document.xml", DOCUMENT)])).unwrap();
+        assert_eq!(extracted.title.as_deref(), Some("Onboarding"));
+        assert_eq!(
+            extracted.text,
+            "# Onboarding\n\nWelcome to the team.\n\n- Get a laptop\n\n## Contacts\n\nRole | Name\nManager | Ada"
+        );
+        assert!(matches!(
+            extract_docx(&docx(&[("content.xml", "<a/>")])),
+            Err(DocxLoaderError::NotAWordDocument)
+        ));
+    }
+}
@@ -0,0 +1,446 @@
+use std::borrow::Cow;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::DocumentLoader;
+use crate::html::{attribute, decode_entities};
+use crate::schema::{Document, Provenance};
+
+/// Elements whose content is never text of the page.
+const HIDDEN_TAGS: &[&str] = &["noscript", "template", "svg", "iframe", "select", "button"];
+/// Elements that hold navigation and other boilerplate rather than content.
+const BOILERPLATE_TAGS: &[&str] = &["nav", "header", "footer", "aside", "form", "menu", "dialog"];
+/// Words in the class or id of an element that mark it as boilerplate.
+const BOILERPLATE_HINTS: &[&str] = &[
+    "nav",
+    "menu",
+    "footer",
+    "sidebar",
+    "cookie",
+    "banner",
+    "breadcrumb",
+    "share",
+    "social",
+    "comment",
+    "advert",
+    "promo",
+    "related",
+    "subscribe",
+    "newsletter",
+];
+const BLOCK_TAGS: &[&str] = &[
+    "p",
+    "div",
+    "br",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "li",
+    "ul",
+    "ol",
+    "dl",
+    "dt",
+    "dd",
+    "tr",
+    "td",
+    "th",
+    "table",
+    "section",
+    "article",
+    "main",
+    "blockquote",
+    "pre",
+    "figcaption",
+];
+const VOID_TAGS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+const HEADING_TAGS: &[&str] = &["h1", "h2", "h3", "h4", "h5", "h6"];
+
+#[derive(Debug, Error)]
+pub enum HtmlLoaderError {
+    #[error("Unable to fetch the page: {0}")]
+    Request(#[from] reqwest::Error),
+}
+
+/// The metadata of a document loaded from a web page.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HtmlMetadata {
+    /// The URL of the page after redirects, or the identifier given with the HTML.
+    pub url: String,
+    pub title: Option<String>,
+}
+
+/// The text extracted from an HTML page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtractedHtml {
+    /// The `<title>` of the page, or its first `<h1>` if it has none.
+    pub title: Option<String>,
+    /// The text of the page, with blocks such as paragraphs separated by blank lines.
+    pub text: String,
+}
+
+enum HtmlSource {
+    Url(String),
+    Html { url: String, html: String },
+}
+
+/// Loads the main content of a web page as a document.
+///
+/// The page is reduced to its readable text: scripts, styles and markup are dropped, and unless
+/// `with_boilerplate_removal(false)` is set, so is boilerplate such as navigation, headers, footers, sidebars and
+/// cookie banners. When the page marks its content with `<article>` or `<main>`, only that content is kept. Blocks that
+/// are mostly links, such as lists of related articles, are dropped too.
+pub struct HtmlLoader {
+    source: HtmlSource,
+    client: reqwest::Client,
+    remove_boilerplate: bool,
+}
+
+impl HtmlLoader {
+    /// Creates a loader fetching the page at `url` when it is run.
+    pub fn from_url<S: Into<String>>(url: S) -> Self {
+        Self::new(HtmlSource::Url(url.into()))
+    }
+
+    /// Creates a loader for HTML already at hand, such as a saved page, identified by `url`.
+    pub fn from_html<U: Into<String>, H: Into<String>>(url: U, html: H) -> Self {
+        Self::new(HtmlSource::Html {
+            url: url.into(),
+            html: html.into(),
+        })
+    }
+
+    fn new(source: HtmlSource) -> Self {
+        Self {
+            source,
+            client: reqwest::Client::new(),
+            remove_boilerplate: true,
+        }
+    }
+
+    /// Sets the client used to fetch pages, for example to set a user agent or a timeout.
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Sets whether boilerplate is removed. Defaults to `true`.
+    pub fn with_boilerplate_removal(mut self, remove_boilerplate: bool) -> Self {
+        self.remove_boilerplate = remove_boilerplate;
+        self
+    }
+}
+
+#[async_trait]
+impl DocumentLoader for HtmlLoader {
+    type Metadata = HtmlMetadata;
+    type Error = HtmlLoaderError;
+
+    async fn load(&self) -> Result<Vec<Document<HtmlMetadata>>, HtmlLoaderError> {
+        let (url, html) = match &self.source {
+            HtmlSource::Url(url) => {
+                let response = self.client.get(url).send().await?.error_for_status()?;
+                let url = response.url().to_string();
+                (url, Cow::Owned(response.text().await?))
+            }
+            HtmlSource::Html { url, html } => (url.clone(), Cow::Borrowed(html.as_str())),
+        };
+        let extracted = extract_html(&html, self.remove_boilerplate);
+        if extracted.text.is_empty() {
+            return Ok(Vec::new());
+        }
+        let provenance = Provenance::new(url.as_str(), extracted.text.len());
+        Ok(vec![Document::new(extracted.text)
+            .with_metadata(HtmlMetadata {
+                url,
+                title: extracted.title,
+            })
+            .with_provenance(provenance)])
+    }
+}
+
+enum Token<'a> {
+    Open {
+        name: String,
+        attributes: &'a str,
+        self_closing: bool,
+    },
+    Close(String),
+    Text(&'a str),
+}
+
+/// Returns the position of the closing tag of `name` in `html`, ignoring case.
+fn find_closing_tag(html: &str, name: &str) -> Option<usize> {
+    html.match_indices("</").map(|(i, _)| i).find(|&i| {
+        html.get(i + 2..i + 2 + name.len())
+            .is_some_and(|tag| tag.eq_ignore_ascii_case(name))
+    })
+}
+
+/// Splits HTML into tags and text. The content of scripts and styles is dropped, as are comments and declarations.
+fn tokenize(html: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        let after = &rest[start + 1..];
+        let is_markup = after
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || matches!(c, '/' | '!' | '?'));
+        if !is_markup {
+            tokens.push(Token::Text(&rest[..start + 1]));
+            rest = after;
+            continue;
+        }
+        if start > 0 {
+            tokens.push(Token::Text(&rest[..start]));
+        }
+        if let Some(comment) = after.strip_prefix("!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let Some(end) = after.find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = &after[..end];
+        rest = &after[end + 1..];
+        if let Some(name) = tag.strip_prefix('/') {
+            tokens.push(Token::Close(name.trim().to_ascii_lowercase()));
+            continue;
+        }
+        if tag.starts_with('!') || tag.starts_with('?') {
+            continue;
+        }
+        let name_end = tag
+            .find(|c: char| c.is_whitespace() || c == '/')
+            .unwrap_or(tag.len());
+        let name = tag[..name_end].to_ascii_lowercase();
+        if name == "script" || name == "style" {
+            rest = find_closing_tag(rest, &name)
+                .and_then(|i| rest[i..].find('>').map(|end| &rest[i + end + 1..]))
+                .unwrap_or("");
+            continue;
+        }
+        tokens.push(Token::Open {
+            self_closing: tag.ends_with('/'),
+            attributes: &tag[name_end..],
+            name,
+        });
+    }
+    if !rest.is_empty() {
+        tokens.push(Token::Text(rest));
+    }
+    tokens
+}
+
+fn is_boilerplate(name: &str, attributes: &str) -> bool {
+    if BOILERPLATE_TAGS.contains(&name) {
+        return true;
+    }
+    ["class", "id", "role"].iter().any(|key| {
+        attribute(attributes, key).is_some_and(|value| {
+            let value = value.to_ascii_lowercase();
+            BOILERPLATE_HINTS.iter().any(|hint| value.contains(hint))
+        })
+    })
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[derive(Default)]
+struct Block {
+    text: String,
+    link_text: String,
+    in_main: bool,
+    heading: bool,
+}
+
+impl Block {
+    fn link_density(&self) -> f32 {
+        let text = self.text.chars().filter(|c| !c.is_whitespace()).count();
+        let links = self
+            .link_text
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .count();
+        links as f32 / text.max(1) as f32
+    }
+}
+
+/// Extracts the title and the readable text of an HTML page, removing boilerplate if `remove_boilerplate` is set.
+///
+/// See `HtmlLoader` for what is considered boilerplate.
+pub fn extract_html(html: &str, remove_boilerplate: bool) -> ExtractedHtml {
+    let mut stack: Vec<(String, bool)> = Vec::new();
+    let mut blocks = Vec::new();
+    let mut block = Block::default();
+    let mut title = String::new();
+    let mut first_heading = String::new();
+    let mut flush = |block: &mut Block| {
+        let taken = std::mem::take(block);
+        if !taken.text.trim().is_empty() {
+            blocks.push(taken);
+        }
+    };
+
+    for token in tokenize(html) {
+        match token {
+            Token::Open {
+                name,
+                attributes,
+                self_closing,
+            } => {
+                if BLOCK_TAGS.contains(&name.as_str()) {
+                    flush(&mut block);
+                }
+                if self_closing || VOID_TAGS.contains(&name.as_str()) {
+                    continue;
+                }
+                let skipped = HIDDEN_TAGS.contains(&name.as_str())
+                    || (remove_boilerplate && is_boilerplate(&name, attributes));
+                stack.push((name, skipped));
+            }
+            Token::Close(name) => {
+                if BLOCK_TAGS.contains(&name.as_str()) {
+                    flush(&mut block);
+                }
+                if let Some(i) = stack.iter().rposition(|(open, _)| *open == name) {
+                    stack.truncate(i);
+                }
+            }
+            Token::Text(text) => {
+                let inside =
+                    |tags: &[&str]| stack.iter().any(|(open, _)| tags.contains(&open.as_str()));
+                let text = decode_entities(text);
+                if inside(&["title"]) {
+                    title.push_str(&text);
+                    continue;
+                }
+                if stack.iter().any(|(_, skipped)| *skipped) {
+                    continue;
+                }
+                if inside(&["h1"]) && title.trim().is_empty() {
+                    first_heading.push_str(&text);
+                }
+                block.text.push_str(&text);
+                if inside(&["a"]) {
+                    block.link_text.push_str(&text);
+                }
+                block.in_main |= inside(&["article", "main"]);
+                block.heading |= inside(HEADING_TAGS);
+            }
+        }
+    }
+    flush(&mut block);
+
+    if remove_boilerplate {
+        if blocks.iter().any(|block| block.in_main) {
+            blocks.retain(|block| block.in_main);
+        }
+        blocks.retain(|block| block.heading || block.link_density() <= 0.5);
+    }
+    let title = [title, first_heading]
+        .iter()
+        .map(|title| collapse_whitespace(title))
+        .find(|title| !title.is_empty());
+    ExtractedHtml {
+        title,
+        text: blocks
+            .iter()
+            .map(|block| collapse_whitespace(&block.text))
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+    }
+}
+
+/// Returns the targets of the HTTP links of a page, resolved against `base` and without fragments. Links marked
+/// `rel="nofollow"` are skipped, and so are all links if the page has a `nofollow` robots meta tag.
+#[cfg(feature = "async")]
+pub(super) fn extract_links(html: &str, base: &reqwest::Url) -> Vec<reqwest::Url> {
+    let mut base = base.clone();
+    let mut links = Vec::new();
+    for token in tokenize(html) {
+        let Token::Open {
+            name, attributes, ..
+        } = token
+        else {
+            continue;
+        };
+        let value = |key| attribute(attributes, key).map(|value| decode_entities(value));
+        let nofollow = || value("content").is_some_and(|content| content.contains("nofollow"));
+        match name.as_str() {
+            "meta"
+                if value("name").is_some_and(|name| name.eq_ignore_ascii_case("robots"))
+                    && nofollow() =>
+            {
+                return Vec::new();
+            }
+            "base" => {
+                if let Some(url) = value("href").and_then(|href| base.join(href.trim()).ok()) {
+                    base = url;
+                }
+            }
+            "a" if !value("rel").is_some_and(|rel| rel.contains("nofollow")) => {
+                let Some(mut url) = value("href").and_then(|href| base.join(href.trim()).ok())
+                else {
+                    continue;
+                };
+                url.set_fragment(None);
+                if matches!(url.scheme(), "http" | "https") && !links.contains(&url) {
+                    links.push(url);
+                }
+            }
+            _ => {}
+        }
+    }
+    links
+}
+
+#[cfg(test)]
+mod tests {
+    use super::extract_html;
+
+    const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head><title>Rust &amp; LLMs</title><style>p { color: red; }</style></head>
+<body>
+  <nav><a href="/">Home</a> <a href="/blog">Blog</a></nav>
+  <div class="cookie-banner">We use cookies.</div>
+  <main>
+    <article>
+      <h1>Chains in Rust</h1>
+      <p>Chains run <b>several</b> prompts in a row.<br>Each step feeds the next.</p>
+      <script>track("view");</script>
+      <p>See <a href="/docs">the docs</a> for the details of every step type.</p>
+      <ul><li><a href="/a">Related one</a></li><li><a href="/b">Related two</a></li></ul>
+    </article>
+  </main>
+  <footer>&copy; 2023 Example</footer>
+</body>
+</html>"#;
+
+    #[test]
+    fn keeps_the_main_content() {
+        let extracted = extract_html(PAGE, true);
+        assert_eq!(extracted.title.as_deref(), Some("Rust & LLMs"));
+        assert_eq!(
+            extracted.text,
+            "Chains in Rust\n\nChains run several prompts in a row.\n\nEach step feeds the next.\n\nSee the docs for the details of every step type."
+        );
+
+        let everything = extract_html(PAGE, false).text;
+        assert!(everything.starts_with("Home Blog\n\nWe use cookies."));
+        assert!(everything.ends_with("© 2023 Example"));
+        assert!(!everything.contains("track"));
+    }
+}
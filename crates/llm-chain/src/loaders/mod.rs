@@ -0,0 +1,48 @@
+//! Document loaders turn files and other sources into `Document`s.
+//!
+//! Loaders are the first step of indexing: the documents they produce carry their provenance and metadata, and can
+//! be fed to a `TextSplitter`, an `IndexingPipeline` or a `VectorStore` directly.
+//!
+//! `DirectoryLoader` loads the files of a directory matching glob patterns, dispatching them by extension.
+//! `HtmlLoader` loads web pages, keeping only their main content. `CsvLoader` and `JsonlLoader` load the rows of
+//! tabular files, with selected columns as content and the other columns as metadata.
+//!
+//! Loaders needing extra dependencies are behind features:
+//!
+//! - `async`: `Crawler`, crawling a website from a seed page or a sitemap.
+//! - `docx`: `DocxLoader`, extracting the paragraphs, headings and tables of Word documents.
+//! - `pdf`: `PdfLoader`, producing one document per page.
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::schema::Document;
+
+#[cfg(feature = "async")]
+mod crawler;
+mod directory;
+#[cfg(feature = "docx")]
+mod docx;
+mod html;
+#[cfg(feature = "pdf")]
+mod pdf;
+mod records;
+
+#[cfg(feature = "async")]
+pub use crawler::{Crawler, CrawlerError};
+pub use directory::{DirectoryLoader, DirectoryLoaderError, FileKind, FileMetadata};
+#[cfg(feature = "docx")]
+pub use docx::{extract_docx, DocxLoader, DocxLoaderError, DocxMetadata, ExtractedDocx};
+pub use html::{extract_html, ExtractedHtml, HtmlLoader, HtmlLoaderError, HtmlMetadata};
+#[cfg(feature = "pdf")]
+pub use pdf::{PdfLoader, PdfLoaderError, PdfMetadata};
+pub use records::{CsvLoader, JsonlLoader, RecordLoaderError, RecordMetadata};
+
+/// A source of documents.
+#[async_trait]
+pub trait DocumentLoader {
+    type Metadata: Serialize + DeserializeOwned + Send;
+    type Error: std::error::Error + Send;
+
+    /// Loads all the documents of the source.
+    async fn load(&self) -> Result<Vec<Document<Self::Metadata>>, Self::Error>;
+}
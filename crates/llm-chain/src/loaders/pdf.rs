@@ -0,0 +1,222 @@
+use std::path::Path;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::DocumentLoader;
+use crate::schema::{Document, Provenance};
+
+#[derive(Debug, Error)]
+pub enum PdfLoaderError {
+    #[error("Unable to read the PDF file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Unable to parse the PDF: {0}")]
+    Pdf(#[from] lopdf::Error),
+    #[error("Unable to extract the text of page {page}: {source}")]
+    Page { page: u32, source: lopdf::Error },
+}
+
+/// The metadata of a page loaded from a PDF.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PdfMetadata {
+    /// The path or other identifier of the PDF.
+    pub source: String,
+    /// The number of the page, starting at 1.
+    pub page: u32,
+    pub total_pages: u32,
+}
+
+/// Loads the text of a PDF, one document per page.
+///
+/// Every document records its page in its metadata and provenance, so that answers can cite the page they come from.
+/// Pages without text, such as scanned images, are skipped, and so are pages whose text can't be extracted; use
+/// `pages` to find out which pages failed. Parsing is CPU-bound and runs on the calling task, so
+/// large PDFs are best loaded with `spawn_blocking` or from a dedicated thread.
+pub struct PdfLoader {
+    source: String,
+    bytes: Option<Vec<u8>>,
+}
+
+impl PdfLoader {
+    /// Creates a loader for the PDF file at `path`, which is read when the loader is run.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            source: path.as_ref().display().to_string(),
+            bytes: None,
+        }
+    }
+
+    /// Creates a loader for a PDF already in memory, identified by `source`.
+    pub fn from_bytes<S: Into<String>>(source: S, bytes: Vec<u8>) -> Self {
+        Self {
+            source: source.into(),
+            bytes: Some(bytes),
+        }
+    }
+
+    /// Loads the pages of the PDF, skipping the pages whose text can't be extracted.
+    pub fn load_pages(&self) -> Result<Vec<Document<PdfMetadata>>, PdfLoaderError> {
+        Ok(self.pages()?.into_iter().filter_map(Result::ok).collect())
+    }
+
+    /// Loads the pages of the PDF, with an error for every page whose text can't be extracted.
+    ///
+    /// Fails only if the PDF itself can't be read or parsed; a page failing doesn't stop the others from loading.
+    pub fn pages(
+        &self,
+    ) -> Result<Vec<Result<Document<PdfMetadata>, PdfLoaderError>>, PdfLoaderError> {
+        let pdf = match &self.bytes {
+            Some(bytes) => lopdf::Document::load_mem(bytes)?,
+            None => lopdf::Document::load_mem(&std::fs::read(&self.source)?)?,
+        };
+        let pages = pdf.get_pages();
+        let total_pages = pages.len() as u32;
+        let mut documents = Vec::new();
+        for page in pages.keys().copied() {
+            let text = match pdf.extract_text(&[page]) {
+                Ok(text) => text,
+                Err(source) => {
+                    documents.push(Err(PdfLoaderError::Page { page, source }));
+                    continue;
+                }
+            };
+            let text = text.trim();
+            if text.is_empty() {
+                continue;
+            }
+            let provenance = Provenance::new(self.source.as_str(), text.len()).with_page(page);
+            documents.push(Ok(Document::new(text.to_string())
+                .with_metadata(PdfMetadata {
+                    source: self.source.clone(),
+                    page,
+                    total_pages,
+                })
+                .with_provenance(provenance)));
+        }
+        Ok(documents)
+    }
+}
+
+#[async_trait]
+impl DocumentLoader for PdfLoader {
+    type Metadata = PdfMetadata;
+    type Error = PdfLoaderError;
+
+    async fn load(&self) -> Result<Vec<Document<PdfMetadata>>, PdfLoaderError> {
+        self.load_pages()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PdfLoader, PdfLoaderError};
+    use lopdf::content::{Content, Operation};
+    use lopdf::{dictionary, Object, Stream};
+
+    // Builds a PDF with a page per content, each a list of text operations in the Courier font.
+    fn pdf(pages: &[Vec<Operation>]) -> Vec<u8> {
+        let mut pdf = lopdf::Document::with_version("1.5");
+        let pages_id = pdf.new_object_id();
+        let font_id = pdf.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Courier",
+        });
+        let resources_id = pdf.add_object(dictionary! {
+            "Font" => dictionary! { "F1" => font_id },
+        });
+        let kids: Vec<Object> = pages
+            .iter()
+            .map(|operations| {
+                let content = Content {
+                    operations: operations.clone(),
+                };
+                let content_id =
+                    pdf.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
+                pdf.add_object(dictionary! {
+                    "Type" => "Page",
+                    "Parent" => pages_id,
+                    "Contents" => content_id,
+                })
+                .into()
+            })
+            .collect();
+        let count = kids.len() as i64;
+        pdf.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => kids,
+                "Count" => count,
+                "Resources" => resources_id,
+                "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+            }),
+        );
+        let catalog_id = pdf.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        pdf.trailer.set("Root", catalog_id);
+        let mut bytes = Vec::new();
+        pdf.save_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn text(text: &str) -> Vec<Operation> {
+        vec![
+            Operation::new("BT", vec![]),
+            Operation::new("Tf", vec!["F1".into(), 12.into()]),
+            Operation::new("Td", vec![72.into(), 720.into()]),
+            Operation::new("Tj", vec![Object::string_literal(text)]),
+            Operation::new("ET", vec![]),
+        ]
+    }
+
+    #[test]
+    fn loads_a_document_per_page() {
+        let bytes = pdf(&[text("Introduction"), vec![], text("Conclusion")]);
+        let documents = PdfLoader::from_bytes("report.pdf", bytes)
+            .load_pages()
+            .unwrap();
+        let pages: Vec<_> = documents
+            .iter()
+            .map(|document| {
+                let metadata = document.metadata.as_ref().unwrap();
+                (
+                    document.page_content.as_str(),
+                    metadata.page,
+                    metadata.total_pages,
+                )
+            })
+            .collect();
+        // The page without text is skipped.
+        assert_eq!(pages, vec![("Introduction", 1, 3), ("Conclusion", 3, 3)]);
+        assert_eq!(documents[1].metadata.as_ref().unwrap().source, "report.pdf");
+    }
+
+    #[test]
+    fn reports_the_pages_that_fail() {
+        // A font selection without a font name can't be decoded.
+        let broken = vec![
+            Operation::new("BT", vec![]),
+            Operation::new("Tf", vec![12.into(), 12.into()]),
+            Operation::new("ET", vec![]),
+        ];
+        let loader = PdfLoader::from_bytes("report.pdf", pdf(&[text("Introduction"), broken]));
+        let pages = loader.pages().unwrap();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].as_ref().unwrap().page_content, "Introduction");
+        assert!(matches!(
+            pages[1],
+            Err(PdfLoaderError::Page { page: 2, .. })
+        ));
+        assert_eq!(loader.load_pages().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn fails_on_invalid_pdfs() {
+        let loader = PdfLoader::from_bytes("report.pdf", b"not a pdf".to_vec());
+        assert!(matches!(loader.load_pages(), Err(PdfLoaderError::Pdf(_))));
+    }
+}
@@ -0,0 +1,340 @@
+use std::ops::Range;
+use std::path::Path;
+
+use async_trait::async_trait;
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+use super::DocumentLoader;
+use crate::csv::{parse_csv, CsvRow};
+use crate::schema::{Document, Provenance};
+
+/// The fields of a record that aren't part of the content of its document.
+pub type RecordMetadata = Map<String, Value>;
+
+#[derive(Debug, Error)]
+pub enum RecordLoaderError {
+    #[error("Unable to read the file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid CSV on line {line}: {message}")]
+    Csv { line: usize, message: String },
+    #[error("Invalid JSON on line {line}: {source}")]
+    Json {
+        line: usize,
+        source: serde_json::Error,
+    },
+    #[error("The record on line {line} is not a JSON object")]
+    NotAnObject { line: usize },
+    #[error("No record has the column {0:?}")]
+    MissingColumn(String),
+}
+
+/// A record read from a file, with its byte range in the file.
+struct Record {
+    range: Range<usize>,
+    fields: Map<String, Value>,
+}
+
+/// Where records are read from, and which of their fields make up the content of the documents.
+struct RecordSource {
+    source: String,
+    data: Option<String>,
+    content_columns: Option<Vec<String>>,
+    id_column: Option<String>,
+}
+
+impl RecordSource {
+    fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            source: path.as_ref().display().to_string(),
+            data: None,
+            content_columns: None,
+            id_column: None,
+        }
+    }
+
+    fn from_string(source: String, data: String) -> Self {
+        Self {
+            data: Some(data),
+            ..Self::from_path(source)
+        }
+    }
+
+    fn read(&self) -> Result<String, RecordLoaderError> {
+        match &self.data {
+            Some(data) => Ok(data.clone()),
+            None => Ok(std::fs::read_to_string(&self.source)?),
+        }
+    }
+
+    /// Turns records into documents, checking that the selected columns exist.
+    fn documents(
+        &self,
+        records: Vec<Record>,
+    ) -> Result<Vec<Document<RecordMetadata>>, RecordLoaderError> {
+        let selected = self.content_columns.iter().flatten().chain(&self.id_column);
+        for column in selected {
+            if !records.iter().any(|r| r.fields.contains_key(column)) {
+                return Err(RecordLoaderError::MissingColumn(column.clone()));
+            }
+        }
+        Ok(records
+            .into_iter()
+            .map(|record| self.document(record))
+            .collect())
+    }
+
+    fn document(&self, record: Record) -> Document<RecordMetadata> {
+        let mut fields = record.fields;
+        let id = self
+            .id_column
+            .as_ref()
+            .and_then(|column| fields.remove(column))
+            .map(|value| value_to_string(&value));
+        let content: Vec<(String, Value)> = match &self.content_columns {
+            Some(columns) => columns
+                .iter()
+                .filter_map(|column| fields.remove(column).map(|value| (column.clone(), value)))
+                .collect(),
+            None => std::mem::take(&mut fields).into_iter().collect(),
+        };
+        let page_content = match &content[..] {
+            [(_, value)] => value_to_string(value),
+            _ => content
+                .iter()
+                .map(|(column, value)| format!("{}: {}", column, value_to_string(value)))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        };
+        let provenance = Provenance::new(self.source.as_str(), 0)
+            .sub_range(record.range.start, record.range.end);
+        let document = Document::new(page_content)
+            .with_metadata(fields)
+            .with_provenance(provenance);
+        match id {
+            Some(id) => document.with_id(id),
+            None => document,
+        }
+    }
+}
+
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// Loads the rows of a CSV file as documents.
+///
+/// The first row holds the column names. By default the content of a document is made of every column of its row;
+/// `with_content_columns` selects the columns that make up the content, and the other columns are kept as metadata.
+/// A single content column is used as is, several are written as `column: value` lines.
+pub struct CsvLoader {
+    source: RecordSource,
+    delimiter: char,
+}
+
+impl CsvLoader {
+    /// Creates a loader for the CSV file at `path`, which is read when the loader is run.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            source: RecordSource::from_path(path),
+            delimiter: ',',
+        }
+    }
+
+    /// Creates a loader for CSV data already in memory, identified by `source`.
+    pub fn from_string<S: Into<String>, D: Into<String>>(source: S, data: D) -> Self {
+        Self {
+            source: RecordSource::from_string(source.into(), data.into()),
+            delimiter: ',',
+        }
+    }
+
+    /// Sets the columns making up the content of the documents.
+    pub fn with_content_columns<S: Into<String>>(mut self, columns: Vec<S>) -> Self {
+        self.source.content_columns = Some(columns.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Uses the values of `column` as the ids of the documents.
+    pub fn with_id_column<S: Into<String>>(mut self, column: S) -> Self {
+        self.source.id_column = Some(column.into());
+        self
+    }
+
+    /// Sets the character separating fields. Defaults to `,`.
+    pub fn with_delimiter(mut self, delimiter: char) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Loads the rows of the file.
+    pub fn load_rows(&self) -> Result<Vec<Document<RecordMetadata>>, RecordLoaderError> {
+        let data = self.source.read()?;
+        let mut rows = parse_csv(&data, self.delimiter)
+            .map_err(|error| RecordLoaderError::Csv {
+                line: error.line,
+                message: error.message,
+            })?
+            .into_iter();
+        let Some(CsvRow { values: header, .. }) = rows.next() else {
+            return Ok(Vec::new());
+        };
+        let records = rows
+            .map(
+                |CsvRow {
+                     line,
+                     range,
+                     values,
+                 }| {
+                    if values.len() != header.len() {
+                        return Err(RecordLoaderError::Csv {
+                            line,
+                            message: format!(
+                                "expected {} fields, found {}",
+                                header.len(),
+                                values.len()
+                            ),
+                        });
+                    }
+                    let fields = header
+                        .iter()
+                        .cloned()
+                        .zip(values.into_iter().map(Value::String))
+                        .collect();
+                    Ok(Record { range, fields })
+                },
+            )
+            .collect::<Result<Vec<_>, _>>()?;
+        self.source.documents(records)
+    }
+}
+
+#[async_trait]
+impl DocumentLoader for CsvLoader {
+    type Metadata = RecordMetadata;
+    type Error = RecordLoaderError;
+
+    async fn load(&self) -> Result<Vec<Document<RecordMetadata>>, RecordLoaderError> {
+        self.load_rows()
+    }
+}
+
+/// Loads the records of a JSON Lines file, one JSON object per line, as documents.
+///
+/// Content and metadata are selected from the top-level fields of the objects like for `CsvLoader`. String values
+/// are used as is, other values as JSON. Blank lines are skipped.
+pub struct JsonlLoader {
+    source: RecordSource,
+}
+
+impl JsonlLoader {
+    /// Creates a loader for the JSONL file at `path`, which is read when the loader is run.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            source: RecordSource::from_path(path),
+        }
+    }
+
+    /// Creates a loader for JSONL data already in memory, identified by `source`.
+    pub fn from_string<S: Into<String>, D: Into<String>>(source: S, data: D) -> Self {
+        Self {
+            source: RecordSource::from_string(source.into(), data.into()),
+        }
+    }
+
+    /// Sets the fields making up the content of the documents.
+    pub fn with_content_columns<S: Into<String>>(mut self, columns: Vec<S>) -> Self {
+        self.source.content_columns = Some(columns.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Uses the values of the field `column` as the ids of the documents.
+    pub fn with_id_column<S: Into<String>>(mut self, column: S) -> Self {
+        self.source.id_column = Some(column.into());
+        self
+    }
+
+    /// Loads the records of the file.
+    pub fn load_records(&self) -> Result<Vec<Document<RecordMetadata>>, RecordLoaderError> {
+        let data = self.source.read()?;
+        let mut records = Vec::new();
+        let mut start = 0;
+        for (i, text) in data.split_inclusive('\n').enumerate() {
+            let range = start..start + text.trim_end().len();
+            start += text.len();
+            if text.trim().is_empty() {
+                continue;
+            }
+            let line = i + 1;
+            let value: Value = serde_json::from_str(text)
+                .map_err(|source| RecordLoaderError::Json { line, source })?;
+            let Value::Object(fields) = value else {
+                return Err(RecordLoaderError::NotAnObject { line });
+            };
+            records.push(Record { range, fields });
+        }
+        self.source.documents(records)
+    }
+}
+
+#[async_trait]
+impl DocumentLoader for JsonlLoader {
+    type Metadata = RecordMetadata;
+    type Error = RecordLoaderError;
+
+    async fn load(&self) -> Result<Vec<Document<RecordMetadata>>, RecordLoaderError> {
+        self.load_records()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CsvLoader, JsonlLoader, RecordLoaderError};
+    use serde_json::json;
+
+    #[test]
+    fn loads_csv_and_jsonl_records() {
+        let csv = "id,question,answer,topic\r\n1,What is Rust?,\"A language, \"\"fast\"\" and safe\",lang\r\n2,\"Multi\nline?\",Yes,misc\r\n";
+        let documents = CsvLoader::from_string("faq.csv", csv)
+            .with_id_column("id")
+            .with_content_columns(vec!["question", "answer"])
+            .load_rows()
+            .unwrap();
+        assert_eq!(documents.len(), 2);
+        assert_eq!(documents[0].id.as_deref(), Some("1"));
+        assert_eq!(
+            documents[0].page_content,
+            "question: What is Rust?\nanswer: A language, \"fast\" and safe"
+        );
+        assert_eq!(
+            serde_json::to_value(&documents[1].metadata).unwrap(),
+            json!({"topic": "misc"})
+        );
+        let provenance = documents[1].provenance.as_ref().unwrap();
+        assert_eq!(
+            &csv[provenance.start..provenance.end],
+            "2,\"Multi\nline?\",Yes,misc"
+        );
+
+        let jsonl =
+            "{\"text\": \"First\", \"stars\": 5}\n\n{\"text\": \"Second\", \"tags\": [\"a\"]}\n";
+        let documents = JsonlLoader::from_string("reviews.jsonl", jsonl)
+            .with_content_columns(vec!["text"])
+            .load_records()
+            .unwrap();
+        assert_eq!(documents[1].page_content, "Second");
+        assert_eq!(
+            serde_json::to_value(&documents[0].metadata).unwrap(),
+            json!({"stars": 5})
+        );
+
+        let missing = JsonlLoader::from_string("reviews.jsonl", jsonl)
+            .with_content_columns(vec!["body"])
+            .load_records();
+        assert!(matches!(missing, Err(RecordLoaderError::MissingColumn(_))));
+    }
+}
@@ -0,0 +1,98 @@
+//! Named presets for the sampling options of a model.
+//!
+//! Choosing a temperature, `top_p` and penalties that work well together takes some experimentation, and the values
+//! that work for a hosted API don't carry over to a local model. A `Preset` names the intent instead:
+//!
+//! - `Creative`: varied, surprising output, for brainstorming and writing.
+//! - `Precise`: focused output that still reads naturally, for question answering and summaries.
+//! - `Deterministic`: the same output every time, for extraction, classification and tests.
+//!
+//! Executors implement `FromPreset` for their per-invocation options, mapping each preset to the values that suit
+//! their provider family.
+//!
+//! # Example
+//!
+//! ```
+//! use llm_chain::options::{Preset, ProviderFamily};
+//!
+//! let sampling = Preset::Deterministic.sampling(ProviderFamily::Api);
+//! assert_eq!(sampling.temperature, 0.0);
+//! ```
+use serde::{Deserialize, Serialize};
+
+/// A named combination of sampling options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Preset {
+    /// Varied output favoring diversity over accuracy.
+    Creative,
+    /// Focused output with little randomness.
+    Precise,
+    /// Greedy decoding, producing the same output for the same prompt.
+    Deterministic,
+}
+
+/// The kind of provider a preset is applied to. Providers of the same family interpret sampling options alike.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ProviderFamily {
+    /// Hosted APIs in the style of OpenAI, with a temperature between `0.0` and `2.0` and additive penalties.
+    Api,
+    /// Local models in the style of llama.cpp, with `top_k` sampling and a multiplicative repeat penalty.
+    Local,
+}
+
+/// The sampling options a preset stands for. Options a provider family doesn't use are `None`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sampling {
+    pub temperature: f32,
+    pub top_p: f32,
+    pub top_k: Option<u32>,
+    pub frequency_penalty: Option<f32>,
+    pub presence_penalty: Option<f32>,
+    pub repeat_penalty: Option<f32>,
+}
+
+impl Preset {
+    /// Returns the sampling options of this preset for the given provider family.
+    pub fn sampling(self, family: ProviderFamily) -> Sampling {
+        match family {
+            ProviderFamily::Api => {
+                let (temperature, top_p, frequency_penalty, presence_penalty) = match self {
+                    Preset::Creative => (1.0, 0.95, 0.5, 0.6),
+                    Preset::Precise => (0.2, 0.9, 0.0, 0.0),
+                    Preset::Deterministic => (0.0, 1.0, 0.0, 0.0),
+                };
+                Sampling {
+                    temperature,
+                    top_p,
+                    top_k: None,
+                    frequency_penalty: Some(frequency_penalty),
+                    presence_penalty: Some(presence_penalty),
+                    repeat_penalty: None,
+                }
+            }
+            ProviderFamily::Local => {
+                let (temperature, top_p, top_k, repeat_penalty) = match self {
+                    Preset::Creative => (1.0, 0.95, 100, 1.15),
+                    Preset::Precise => (0.3, 0.9, 40, 1.1),
+                    Preset::Deterministic => (0.0, 1.0, 1, 1.0),
+                };
+                Sampling {
+                    temperature,
+                    top_p,
+                    top_k: Some(top_k),
+                    frequency_penalty: None,
+                    presence_penalty: None,
+                    repeat_penalty: Some(repeat_penalty),
+                }
+            }
+        }
+    }
+}
+
+/// Options that can be created from a `Preset`.
+///
+/// Implemented by the per-invocation options of executors, so that `PerInvocation::preset(Preset::Precise)` gives
+/// options suited to the executor's provider. The result can be refined further with the options' own setters.
+pub trait FromPreset: Sized {
+    fn preset(preset: Preset) -> Self;
+}
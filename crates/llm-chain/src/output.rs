@@ -1,8 +1,16 @@
 //! Module modeling the outputs from LLMs
 //!
 //! The `output` module contains the `Output` trait, which represents the output of a Large Language Model (LLM). It provides methods for retrieving and combining textual outputs from different models.
+//!
+//! Besides the text, outputs carry the metadata reported by the executor: the token `Usage` of the invocation, the
+//! model that produced it and, for each `Choice`, the reason generation stopped and the tools the model asked to call.
+//! These are available through the trait, without downcasting to the output type of an executor, so that costs can
+//! be tracked and truncated outputs detected with any executor.
+use std::ops::{Add, AddAssign};
+
 use async_trait::async_trait;
-use futures::stream::StreamExt;
+use futures::stream::{BoxStream, StreamExt};
+use serde::{Deserialize, Serialize};
 
 use crate::prompt::ChatRole;
 
@@ -34,6 +42,58 @@ pub trait Output: Send + Clone + Sync {
         Some(ChatRole::Assistant)
     }
 
+    /// Gets the tools the model asked to call, in the first choice. Models with function calling return them
+    /// instead of, or along with, a textual output.
+    ///
+    /// The default implementation returns no calls.
+    async fn tool_calls(&self) -> Vec<ToolCall> {
+        Vec::new()
+    }
+
+    /// Gets the reason the model stopped generating the first choice, if the executor reports it.
+    ///
+    /// The default implementation returns `None`.
+    async fn finish_reason(&self) -> Option<FinishReason> {
+        None
+    }
+
+    /// Returns true if the first choice was cut because the maximum number of tokens was reached.
+    async fn is_truncated(&self) -> bool {
+        self.finish_reason().await == Some(FinishReason::Length)
+    }
+
+    /// Gets every choice generated, with its metadata.
+    ///
+    /// The default implementation returns the textual outputs, giving the first one the finish reason and the tool
+    /// calls of the output.
+    async fn choices(&self) -> Vec<Choice> {
+        let mut choices: Vec<Choice> = self
+            .primary_textual_output_choices()
+            .await
+            .into_iter()
+            .map(Choice::new)
+            .collect();
+        if let Some(first) = choices.first_mut() {
+            first.finish_reason = self.finish_reason().await;
+            first.tool_calls = self.tool_calls().await;
+        }
+        choices
+    }
+
+    /// Gets the number of tokens used by the invocation, if the executor reports it.
+    ///
+    /// The default implementation returns `None`.
+    async fn usage(&self) -> Option<Usage> {
+        None
+    }
+
+    /// Gets the name of the model that produced the output, if the executor reports it.
+    ///
+    /// The default implementation returns `None`.
+    async fn model(&self) -> Option<String> {
+        None
+    }
+
     /// Combines the primary textual outputs from multiple instances implementing the `Output` trait.
     /// The outputs are joined using the `OUTPUT_JOINER_SEQUENCE` separator.
     async fn combine_primary_textual_outputs(outputs: &[&Self]) -> String {
@@ -64,3 +124,182 @@ pub trait Output: Send + Clone + Sync {
         }
     }
 }
+
+/// A call of a tool requested by a model.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolCall {
+    /// The identifier of the call, if the model gives one. It identifies the call when its result is sent back.
+    pub id: Option<String>,
+    /// The name of the tool.
+    pub name: String,
+    /// The arguments of the call, as produced by the model. They should be JSON, but models sometimes produce
+    /// invalid JSON, so they are kept as text.
+    pub arguments: String,
+}
+
+impl ToolCall {
+    pub fn new<N: Into<String>, A: Into<String>>(name: N, arguments: A) -> Self {
+        Self {
+            id: None,
+            name: name.into(),
+            arguments: arguments.into(),
+        }
+    }
+
+    pub fn with_id<S: Into<String>>(mut self, id: S) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Deserializes the arguments of the call.
+    ///
+    /// ```
+    /// use llm_chain::output::ToolCall;
+    /// #[derive(serde::Deserialize)]
+    /// struct Weather {
+    ///     city: String,
+    /// }
+    /// let call = ToolCall::new("get_weather", r#"{"city": "Oslo"}"#);
+    /// assert_eq!(call.parse_arguments::<Weather>().unwrap().city, "Oslo");
+    /// ```
+    pub fn parse_arguments<T: serde::de::DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        serde_json::from_str(&self.arguments)
+    }
+}
+
+/// The reason a model stopped generating.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FinishReason {
+    /// The model finished its answer, or produced a stop sequence.
+    Stop,
+    /// The maximum number of tokens was reached, so the output is truncated.
+    Length,
+    /// The model stopped to call tools.
+    ToolCalls,
+    /// The output was cut by a content filter.
+    ContentFilter,
+    /// A reason without a variant, as named by the provider.
+    Other(String),
+}
+
+impl From<&str> for FinishReason {
+    /// Converts the names used by common APIs, such as `stop`, `length` or `tool_calls`.
+    fn from(reason: &str) -> Self {
+        match reason {
+            "stop" | "end_turn" | "stop_sequence" | "eos" => Self::Stop,
+            "length" | "max_tokens" => Self::Length,
+            "tool_calls" | "function_call" | "tool_use" => Self::ToolCalls,
+            "content_filter" => Self::ContentFilter,
+            other => Self::Other(other.to_string()),
+        }
+    }
+}
+
+/// A choice generated by a model, with its metadata.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Choice {
+    pub text: String,
+    pub finish_reason: Option<FinishReason>,
+    pub tool_calls: Vec<ToolCall>,
+}
+
+impl Choice {
+    pub fn new<S: Into<String>>(text: S) -> Self {
+        Self {
+            text: text.into(),
+            finish_reason: None,
+            tool_calls: Vec::new(),
+        }
+    }
+
+    pub fn with_finish_reason(mut self, finish_reason: FinishReason) -> Self {
+        self.finish_reason = Some(finish_reason);
+        self
+    }
+
+    pub fn with_tool_calls(mut self, tool_calls: Vec<ToolCall>) -> Self {
+        self.tool_calls = tool_calls;
+        self
+    }
+}
+
+/// The number of tokens used by an invocation. Usages add up, to get the usage of a chain.
+///
+/// ```
+/// use llm_chain::output::Usage;
+/// let usage = Usage::new(120, 30) + Usage::new(80, 20);
+/// assert_eq!(usage, Usage::new(200, 50));
+/// assert_eq!(usage.total_tokens(), 250);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct Usage {
+    /// The number of tokens of the prompt.
+    pub prompt_tokens: u32,
+    /// The number of tokens generated.
+    pub completion_tokens: u32,
+}
+
+impl Usage {
+    pub fn new(prompt_tokens: u32, completion_tokens: u32) -> Self {
+        Self {
+            prompt_tokens,
+            completion_tokens,
+        }
+    }
+
+    pub fn total_tokens(&self) -> u32 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+impl Add for Usage {
+    type Output = Usage;
+
+    fn add(self, other: Usage) -> Usage {
+        Usage::new(
+            self.prompt_tokens + other.prompt_tokens,
+            self.completion_tokens + other.completion_tokens,
+        )
+    }
+}
+
+impl AddAssign for Usage {
+    fn add_assign(&mut self, other: Usage) {
+        *self = *self + other;
+    }
+}
+
+/// A stream of the chunks of an output, failing with `Err`.
+pub type OutputStream<'a, Err> = BoxStream<'a, Result<StreamChunk, Err>>;
+
+/// A part of an output streamed by `Executor::execute_stream`. The text of the output is the concatenation of the
+/// deltas of its chunks.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct StreamChunk {
+    /// The text generated since the previous chunk.
+    pub delta: String,
+    /// The reason generation stopped, set on the last chunk if the executor reports it.
+    pub finish_reason: Option<FinishReason>,
+}
+
+impl StreamChunk {
+    pub fn new<S: Into<String>>(delta: S) -> Self {
+        Self {
+            delta: delta.into(),
+            finish_reason: None,
+        }
+    }
+
+    pub fn with_finish_reason(mut self, finish_reason: FinishReason) -> Self {
+        self.finish_reason = Some(finish_reason);
+        self
+    }
+
+    /// Creates a chunk holding the whole primary textual output of `output`, for outputs that weren't streamed.
+    pub async fn from_output<O: Output>(output: &O) -> Self {
+        Self {
+            delta: output.primary_textual_output().await.unwrap_or_default(),
+            finish_reason: output.finish_reason().await,
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use super::{OutputParser, OutputParserError};
+use crate::parsing::code_blocks;
+use crate::Parameters;
+
+/// A code block of a model output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeBlock {
+    /// The language tag of the block, such as `rust` in ```` ```rust ````, if it has one.
+    pub language: Option<String>,
+    pub code: String,
+}
+
+/// The code blocks extracted from a model output, and the prose around them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeBlocks {
+    pub blocks: Vec<CodeBlock>,
+    /// The output without the extracted blocks, usually the explanations of the model.
+    pub prose: String,
+}
+
+impl CodeBlocks {
+    /// Returns the code of the blocks, separated by blank lines.
+    pub fn code(&self) -> String {
+        self.blocks
+            .iter()
+            .map(|block| block.code.as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+/// Sets `code` to the code of the blocks, and `prose` to the prose around them.
+impl From<CodeBlocks> for Parameters {
+    fn from(blocks: CodeBlocks) -> Self {
+        Parameters::new()
+            .with("code", blocks.code())
+            .with("prose", blocks.prose)
+    }
+}
+
+/// A parser extracting the fenced code blocks of a model output, along with the prose around them.
+///
+/// Blocks can be filtered by language, in which case the blocks of other languages are left in the prose. Parsing
+/// fails if no block is found, so that the model can be asked to answer with code.
+///
+/// # Examples
+///
+/// ```
+/// use llm_chain::output_parser::{CodeBlockParser, OutputParser};
+/// let output = "Here is the function:\n\n```rust\nfn add(a: i32, b: i32) -> i32 {\n    a + b\n}\n```\n\nUse it \
+///               like this:\n\n```sh\ncargo run\n```\n";
+/// let blocks = CodeBlockParser::new().with_language("rust").parse(output).unwrap();
+/// assert_eq!(blocks.code(), "fn add(a: i32, b: i32) -> i32 {\n    a + b\n}");
+/// assert_eq!(blocks.prose, "Here is the function:\n\nUse it like this:\n\n```sh\ncargo run\n```");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CodeBlockParser {
+    languages: Vec<String>,
+}
+
+impl CodeBlockParser {
+    /// Creates a parser extracting the blocks of every language.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only extracts the blocks tagged with `language`, ignoring case. Can be called several times to accept
+    /// several languages, such as `rust` and `rs`.
+    pub fn with_language<S: Into<String>>(mut self, language: S) -> Self {
+        self.languages.push(language.into().to_lowercase());
+        self
+    }
+
+    fn accepts(&self, language: Option<&str>) -> bool {
+        self.languages.is_empty()
+            || language.is_some_and(|language| self.languages.contains(&language.to_lowercase()))
+    }
+}
+
+impl OutputParser for CodeBlockParser {
+    type Output = CodeBlocks;
+
+    fn parse(&self, text: &str) -> Result<CodeBlocks, OutputParserError> {
+        let mut blocks = Vec::new();
+        let mut prose = String::new();
+        let mut last = 0;
+        for block in code_blocks(text) {
+            let Some(position) = block.position else {
+                continue;
+            };
+            if !self.accepts(block.lang.as_deref()) {
+                continue;
+            }
+            prose.push_str(&text[last..position.start.offset]);
+            last = position.end.offset;
+            blocks.push(CodeBlock {
+                language: block.lang,
+                code: block.value,
+            });
+        }
+        if blocks.is_empty() {
+            return Err(OutputParserError::new(match self.languages.first() {
+                Some(language) => format!("The output contains no `{}` code block", language),
+                None => "The output contains no code block".to_string(),
+            }));
+        }
+        prose.push_str(&text[last..]);
+        Ok(CodeBlocks {
+            blocks,
+            prose: collapse_blank_lines(&prose),
+        })
+    }
+
+    fn instructions(&self) -> Option<String> {
+        Some(match self.languages.first() {
+            Some(language) => format!(
+                "Put the code in a fenced code block tagged with its language, starting with ```{}.",
+                language
+            ),
+            None => "Put the code in a fenced code block, starting with ```.".to_string(),
+        })
+    }
+}
+
+/// Trims `text` and collapses the runs of blank lines left by the removed blocks into single blank lines.
+fn collapse_blank_lines(text: &str) -> String {
+    let mut collapsed = Vec::new();
+    for line in text.trim().lines() {
+        let line = line.trim_end();
+        if line.is_empty() && collapsed.last().is_some_and(|last: &&str| last.is_empty()) {
+            continue;
+        }
+        collapsed.push(line);
+    }
+    collapsed.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_blocks_and_prose() {
+        let output = "Two versions:\n```Python\nprint(1)\n```\nand\n```\nx = 1\n```";
+        let blocks = CodeBlockParser::new().parse(output).unwrap();
+        assert_eq!(
+            blocks.blocks,
+            [
+                CodeBlock {
+                    language: Some("Python".to_string()),
+                    code: "print(1)".to_string()
+                },
+                CodeBlock {
+                    language: None,
+                    code: "x = 1".to_string()
+                }
+            ]
+        );
+        assert_eq!(blocks.prose, "Two versions:\n\nand");
+        let parameters: Parameters = CodeBlockParser::new()
+            .with_language("python")
+            .parse(output)
+            .unwrap()
+            .into();
+        assert_eq!(parameters.get("code").unwrap(), "print(1)");
+        assert!(CodeBlockParser::new()
+            .with_language("rust")
+            .parse(output)
+            .is_err());
+    }
+}
@@ -0,0 +1,257 @@
+use serde::de::{self, DeserializeOwned, Deserializer, Visitor};
+
+use super::{OutputParser, OutputParserError};
+
+/// A parser mapping the output of a model onto one of a closed set of labels, to route inputs or moderate them.
+///
+/// Models don't always answer with the label alone, so the output is normalized first: case, whitespace, punctuation
+/// and the difference between `_`, `-` and spaces are ignored. The label is then found by, in order:
+/// - matching the whole output, as in `Positive.`,
+/// - finding the only label mentioned in the output, as in `The sentiment is positive`,
+/// - taking the closest label, if it is within `with_max_distance` edits of the output, as in `positiv`.
+///
+/// Outputs matching no label fail to parse, unless there is a fallback set with `with_other`.
+///
+/// # Examples
+///
+/// ```
+/// use llm_chain::output_parser::{LabelParser, OutputParser};
+/// let parser = LabelParser::new(["spam", "needs_review", "ok"]).with_other("needs_review");
+/// assert_eq!(parser.parse("**Needs review**").unwrap(), "needs_review");
+/// assert_eq!(parser.parse("I'd say this is spam.").unwrap(), "spam");
+/// assert_eq!(parser.parse("I can't tell").unwrap(), "needs_review");
+/// ```
+///
+/// Parsing into an enum, whose labels are the names of its variants as deserialized by serde:
+///
+/// ```
+/// use llm_chain::output_parser::{LabelParser, OutputParser};
+/// #[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+/// #[serde(rename_all = "snake_case")]
+/// enum Route {
+///     Billing,
+///     TechnicalSupport,
+///     Other,
+/// }
+/// let parser = LabelParser::<Route>::for_enum().with_other(Route::Other);
+/// assert_eq!(parser.parse("Technical support").unwrap(), Route::TechnicalSupport);
+/// assert_eq!(parser.parse("Sales").unwrap(), Route::Other);
+/// ```
+#[derive(Debug, Clone)]
+pub struct LabelParser<T = String> {
+    labels: Vec<(String, T)>,
+    other: Option<T>,
+    max_distance: usize,
+}
+
+impl LabelParser<String> {
+    /// Creates a parser for `labels`, producing the label matched.
+    pub fn new<S: Into<String>, I: IntoIterator<Item = S>>(labels: I) -> Self {
+        Self::for_values(labels.into_iter().map(|label| {
+            let label = label.into();
+            (label.clone(), label)
+        }))
+    }
+}
+
+impl<T: Clone + Send + Sync> LabelParser<T> {
+    /// Creates a parser for the labels of `values`, producing the value of the label matched.
+    pub fn for_values<S: Into<String>, I: IntoIterator<Item = (S, T)>>(values: I) -> Self {
+        Self {
+            labels: values
+                .into_iter()
+                .map(|(label, value)| (label.into(), value))
+                .collect(),
+            other: None,
+            max_distance: 2,
+        }
+    }
+
+    /// Sets the value produced when the output matches no label.
+    pub fn with_other<V: Into<T>>(mut self, other: V) -> Self {
+        self.other = Some(other.into());
+        self
+    }
+
+    /// Sets how many edits the closest label may be from the output. Defaults to 2; 0 disables closest matches.
+    pub fn with_max_distance(mut self, max_distance: usize) -> Self {
+        self.max_distance = max_distance;
+        self
+    }
+
+    fn find(&self, text: &str) -> Option<&T> {
+        let text = normalize(text);
+        let labels: Vec<(String, &T)> = self
+            .labels
+            .iter()
+            .map(|(label, value)| (normalize(label), value))
+            .collect();
+        if let Some((_, value)) = labels.iter().find(|(label, _)| *label == text) {
+            return Some(value);
+        }
+        let padded = format!(" {} ", text);
+        let mentioned: Vec<&(String, &T)> = labels
+            .iter()
+            .filter(|(label, _)| !label.is_empty() && padded.contains(&format!(" {} ", label)))
+            .collect();
+        // A label mentioned as part of a longer one, such as "positive" in "very positive", doesn't count.
+        let mentioned: Vec<_> = mentioned
+            .iter()
+            .filter(|(label, _)| {
+                !mentioned
+                    .iter()
+                    .any(|(other, _)| other != label && other.contains(label.as_str()))
+            })
+            .collect();
+        if let [(_, value)] = mentioned.as_slice() {
+            return Some(value);
+        }
+        let mut distances: Vec<(usize, &T)> = labels
+            .iter()
+            .map(|(label, value)| (edit_distance(label, &text), *value))
+            .collect();
+        distances.sort_by_key(|(distance, _)| *distance);
+        match distances.as_slice() {
+            [(best, value), rest @ ..]
+                if *best <= self.max_distance && rest.first().map(|(d, _)| d) != Some(best) =>
+            {
+                Some(value)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<T: Clone + DeserializeOwned + Send + Sync> LabelParser<T> {
+    /// Creates a parser for the unit variants of the enum `T`, labeled with their names as deserialized by serde, so
+    /// that `#[serde(rename)]` and `#[serde(rename_all)]` are taken into account.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `T` isn't an enum with unit variants.
+    pub fn for_enum() -> Self {
+        let mut variants: &'static [&'static str] = &[];
+        let _ = T::deserialize(VariantNames(&mut variants));
+        let values: Vec<(&str, T)> = variants
+            .iter()
+            .filter_map(|variant| {
+                let value = serde_json::from_value(serde_json::Value::String(variant.to_string()));
+                value.ok().map(|value| (*variant, value))
+            })
+            .collect();
+        assert!(
+            !values.is_empty(),
+            "LabelParser::for_enum requires an enum with unit variants"
+        );
+        Self::for_values(values)
+    }
+}
+
+impl<T: Clone + Send + Sync> OutputParser for LabelParser<T> {
+    type Output = T;
+
+    fn parse(&self, text: &str) -> Result<T, OutputParserError> {
+        self.find(text)
+            .or(self.other.as_ref())
+            .cloned()
+            .ok_or_else(|| {
+                OutputParserError::new(format!(
+                    "The output isn't one of the labels {}",
+                    self.label_list()
+                ))
+            })
+    }
+
+    fn instructions(&self) -> Option<String> {
+        Some(format!(
+            "Answer with exactly one of the labels {}, and nothing else.",
+            self.label_list()
+        ))
+    }
+}
+
+impl<T> LabelParser<T> {
+    fn label_list(&self) -> String {
+        self.labels
+            .iter()
+            .map(|(label, _)| format!("`{}`", label))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Lowercases `text`, replaces `_` and `-` with spaces, drops other punctuation and collapses whitespace.
+fn normalize(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '_' | '-' => ' ',
+            c => c,
+        })
+        .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+        .collect::<String>()
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Returns the Levenshtein distance between `a` and `b`, in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// A deserializer recording the names of the variants of the enum deserialized with it.
+struct VariantNames<'a>(&'a mut &'static [&'static str]);
+
+impl<'de, 'a> Deserializer<'de> for VariantNames<'a> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not an enum"))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.0 = variants;
+        Err(de::Error::custom("only the variants are needed"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option unit
+        unit_struct newtype_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_outputs_onto_labels() {
+        let parser = LabelParser::new(["positive", "very positive", "negative"]);
+        assert_eq!(parser.parse("  Very-Positive!").unwrap(), "very positive");
+        assert_eq!(
+            parser.parse("Label: very positive").unwrap(),
+            "very positive"
+        );
+        assert_eq!(parser.parse("negatve").unwrap(), "negative");
+        // Both labels are mentioned, so the output is ambiguous.
+        assert!(parser.parse("positive, not negative").is_err());
+        assert!(parser.with_max_distance(0).parse("negatve").is_err());
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+}
@@ -0,0 +1,198 @@
+use super::{Map, OutputParser, OutputParserError};
+use crate::Parameters;
+
+/// A parser splitting an output into a list of items, such as the ideas or questions a model was asked to list.
+///
+/// Items are separated by newlines, or by commas when the output is a single line, unless a separator is set with
+/// `with_separator`. When some lines are list items, such as `- item` or `2. item`, the other lines are considered
+/// prose around the list and dropped. Items are trimmed of whitespace and list markers, empty items are dropped and,
+/// unless `with_deduplication(false)` is set, repeated items are only kept once, ignoring case.
+///
+/// # Examples
+///
+/// ```
+/// use llm_chain::output_parser::{ListParser, OutputParser};
+/// let parser = ListParser::new().with_max_items(3);
+/// let output = "Here are some names:\n1. Rex\n2. Fido\n3. rex\n\nI hope they help!";
+/// assert_eq!(parser.parse(output).unwrap(), ["Rex", "Fido"]);
+/// assert_eq!(parser.parse("red, green , blue").unwrap(), ["red", "green", "blue"]);
+/// assert!(parser.parse("a, b, c, d").is_err());
+/// ```
+///
+/// The items can then be run through the map step of a map-reduce chain, one document per item:
+///
+/// ```ignore
+/// let ideas = ListParser::new().parse(&output)?;
+/// let documents = ideas.into_iter().map(Parameters::new_with_text).collect();
+/// let result = map_reduce.run(documents, Parameters::new(), &executor).await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct ListParser {
+    separator: Option<String>,
+    deduplicate: bool,
+    max_items: Option<usize>,
+}
+
+impl Default for ListParser {
+    fn default() -> Self {
+        Self {
+            separator: None,
+            deduplicate: true,
+            max_items: None,
+        }
+    }
+}
+
+impl ListParser {
+    /// Creates a parser for lists separated by newlines or commas.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Splits the output on `separator` only, such as `;` or `|`.
+    pub fn with_separator<S: Into<String>>(mut self, separator: S) -> Self {
+        self.separator = Some(separator.into());
+        self
+    }
+
+    /// Sets whether repeated items are dropped. Defaults to true.
+    pub fn with_deduplication(mut self, deduplicate: bool) -> Self {
+        self.deduplicate = deduplicate;
+        self
+    }
+
+    /// Makes lists of more than `max_items` items fail to parse, so that the model can be asked for a shorter list.
+    pub fn with_max_items(mut self, max_items: usize) -> Self {
+        self.max_items = Some(max_items);
+        self
+    }
+
+    /// Creates a parser producing parameters with the list under `key`, as an array that templates can loop over,
+    /// e.g. `{% for idea in ideas %}`.
+    pub fn into_parameters<K: Into<String>>(
+        self,
+        key: K,
+    ) -> Map<Self, impl Fn(Vec<String>) -> Parameters + Send + Sync> {
+        let key = key.into();
+        self.map(move |items| Parameters::new().with_value(key.as_str(), items.into()))
+    }
+
+    fn split<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        if let Some(separator) = &self.separator {
+            return text.split(separator.as_str()).collect();
+        }
+        let lines: Vec<&str> = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .collect();
+        if lines.len() == 1 {
+            return lines[0].split(',').collect();
+        }
+        if lines.iter().any(|line| strip_marker(line).is_some()) {
+            return lines.into_iter().filter_map(strip_marker).collect();
+        }
+        lines
+    }
+}
+
+impl OutputParser for ListParser {
+    type Output = Vec<String>;
+
+    fn parse(&self, text: &str) -> Result<Vec<String>, OutputParserError> {
+        let mut items: Vec<String> = Vec::new();
+        for item in self.split(text) {
+            let item = strip_marker(item).unwrap_or(item);
+            let item = item.trim().trim_end_matches([',', ';']).trim_end();
+            if item.is_empty() {
+                continue;
+            }
+            if self.deduplicate
+                && items
+                    .iter()
+                    .any(|existing| existing.to_lowercase() == item.to_lowercase())
+            {
+                continue;
+            }
+            items.push(item.to_string());
+        }
+        if items.is_empty() {
+            return Err(OutputParserError::new("The output contains no list items"));
+        }
+        match self.max_items {
+            Some(max_items) if items.len() > max_items => Err(OutputParserError::new(format!(
+                "The list has {} items, but at most {} are allowed",
+                items.len(),
+                max_items
+            ))),
+            _ => Ok(items),
+        }
+    }
+
+    fn instructions(&self) -> Option<String> {
+        let separator = match self.separator.as_deref() {
+            Some(separator) => format!("separated by `{}`", separator),
+            None => "one per line".to_string(),
+        };
+        Some(match self.max_items {
+            Some(max_items) => format!(
+                "Answer with a list of at most {} items, {}, and nothing else.",
+                max_items, separator
+            ),
+            None => format!(
+                "Answer with a list of items, {}, and nothing else.",
+                separator
+            ),
+        })
+    }
+}
+
+/// Returns the item of a line starting with a list marker, such as `-`, `*` or `1.`, or `None` if it doesn't.
+fn strip_marker(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    if let Some(rest) = line.strip_prefix(['-', '*', '+', '•']) {
+        return rest.starts_with(char::is_whitespace).then_some(rest);
+    }
+    let rest = line.trim_start_matches(|c: char| c.is_ascii_digit());
+    if rest.len() == line.len() {
+        return None;
+    }
+    rest.strip_prefix(['.', ')'])
+        .filter(|rest| rest.starts_with(char::is_whitespace))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_lists() {
+        let parser = ListParser::new();
+        assert_eq!(
+            parser
+                .parse("- Apples\n* pears;\n+ apples\n- 3 plums")
+                .unwrap(),
+            ["Apples", "pears", "3 plums"]
+        );
+        assert_eq!(
+            parser.parse("Oslo\nBergen\n\nOslo").unwrap(),
+            ["Oslo", "Bergen"]
+        );
+        assert_eq!(
+            parser.with_deduplication(false).parse("a, a, ,b").unwrap(),
+            ["a", "a", "b"]
+        );
+        assert_eq!(
+            ListParser::new()
+                .with_separator("|")
+                .parse("x, y | z")
+                .unwrap(),
+            ["x, y", "z"]
+        );
+        assert!(ListParser::new().parse(" \n, ,").is_err());
+        let parameters = ListParser::new()
+            .into_parameters("ideas")
+            .parse("a, b")
+            .unwrap();
+        assert_eq!(parameters.get("ideas").unwrap(), r#"["a","b"]"#);
+    }
+}
@@ -0,0 +1,260 @@
+//! Output parsers turn the text produced by a model into structured values.
+//!
+//! An `OutputParser` parses text into a value, and fails with an `OutputParserError` describing what is wrong with
+//! the text. Parsers are composed with `OutputParser::map`, which transforms the parsed value, and `OutputParser::or`,
+//! which falls back to another parser when the first one fails.
+//!
+//! The parsers provided are:
+//! - `RegexParser`, extracting the named groups of a regular expression into `Parameters`,
+//! - `LabelParser`, mapping the output onto one of a closed set of labels, or onto an enum,
+//! - `CodeBlockParser`, extracting the fenced code blocks of the output from the prose around them,
+//! - `ListParser`, splitting comma or newline separated lists into their items,
+//! - `XmlTagParser`, extracting sections wrapped in tags such as `<answer>…</answer>` into `Parameters`,
+//! - `PartialJsonParser`, parsing JSON values that are still being streamed.
+//!
+//! Parsers producing `Parameters` can be added to a sequential chain with `ParsingStep`, so that the following steps
+//! can use the fields extracted from the output of a step in their prompts. Outputs that can't be parsed can be given
+//! back to the model to be fixed, with `ParsingStep::with_max_attempts` or `parse_with_retries`.
+use async_trait::async_trait;
+
+use crate::step::{CustomStep, CustomStepError, Step, StepOutcome};
+use crate::{traits, Parameters};
+
+mod code_block;
+mod label;
+mod list;
+mod partial_json;
+mod regex_parser;
+mod retry;
+mod xml_tags;
+
+pub use code_block::{CodeBlock, CodeBlockParser, CodeBlocks};
+pub use label::LabelParser;
+pub use list::ListParser;
+pub use partial_json::PartialJsonParser;
+pub use regex_parser::RegexParser;
+pub use retry::{parse_with_retries, ParseRetryError};
+pub use xml_tags::XmlTagParser;
+
+/// The error returned when a parser can't parse a text.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{message}")]
+pub struct OutputParserError {
+    message: String,
+}
+
+impl OutputParserError {
+    pub fn new<S: Into<String>>(message: S) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+
+    /// Describes what is wrong with the text, in a way that can be given back to the model.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+/// A parser of the text produced by a model.
+pub trait OutputParser: Send + Sync {
+    /// The value produced by the parser.
+    type Output;
+
+    /// Parses `text`.
+    fn parse(&self, text: &str) -> Result<Self::Output, OutputParserError>;
+
+    /// Describes the format the parser expects. It is given to the model along with the parse error when it is asked
+    /// to fix an output.
+    ///
+    /// The default implementation returns `None`.
+    fn instructions(&self) -> Option<String> {
+        None
+    }
+
+    /// Creates a parser transforming the values produced by this parser with `f`.
+    fn map<F, T>(self, f: F) -> Map<Self, F>
+    where
+        Self: Sized,
+        F: Fn(Self::Output) -> T + Send + Sync,
+    {
+        Map { parser: self, f }
+    }
+
+    /// Creates a parser trying `other` when this parser fails. If both fail, the error of `other` is returned.
+    fn or<P>(self, other: P) -> Or<Self, P>
+    where
+        Self: Sized,
+        P: OutputParser<Output = Self::Output>,
+    {
+        Or {
+            first: self,
+            second: other,
+        }
+    }
+}
+
+/// The parser returned by `OutputParser::map`.
+#[derive(Debug, Clone)]
+pub struct Map<P, F> {
+    parser: P,
+    f: F,
+}
+
+impl<P, F, T> OutputParser for Map<P, F>
+where
+    P: OutputParser,
+    F: Fn(P::Output) -> T + Send + Sync,
+{
+    type Output = T;
+
+    fn parse(&self, text: &str) -> Result<T, OutputParserError> {
+        self.parser.parse(text).map(&self.f)
+    }
+
+    fn instructions(&self) -> Option<String> {
+        self.parser.instructions()
+    }
+}
+
+/// The parser returned by `OutputParser::or`.
+#[derive(Debug, Clone)]
+pub struct Or<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A, B> OutputParser for Or<A, B>
+where
+    A: OutputParser,
+    B: OutputParser<Output = A::Output>,
+{
+    type Output = A::Output;
+
+    fn parse(&self, text: &str) -> Result<A::Output, OutputParserError> {
+        self.first.parse(text).or_else(|_| self.second.parse(text))
+    }
+
+    fn instructions(&self) -> Option<String> {
+        self.first
+            .instructions()
+            .or_else(|| self.second.instructions())
+    }
+}
+
+/// A custom step running a step and parsing its output into parameters.
+///
+/// The parameters produced by the parser are added to the current parameters, along with the output of the step in
+/// `text`, as for any other step. By default, the chain fails if the output can't be parsed; with
+/// `with_max_attempts`, the model is asked to fix it first, as in `parse_with_retries`.
+///
+/// # Example
+///
+/// ```ignore
+/// let grade = Step::for_prompt_template(prompt!("Grade this essay, then write `Score: <1-10>`.\n\n{{text}}"));
+/// let chain = Chain::from_steps(vec![
+///     ChainStep::custom(ParsingStep::new(grade, RegexParser::new(r"Score:\s*(?P<score>\d+)")?)),
+///     Step::for_prompt_template(prompt!("Explain why the essay was given a score of {{score}}.")).into(),
+/// ]);
+/// ```
+pub struct ParsingStep<E: traits::Executor, P> {
+    step: Step<E>,
+    parser: P,
+    max_attempts: usize,
+}
+
+impl<E: traits::Executor, P: OutputParser<Output = Parameters>> ParsingStep<E, P> {
+    pub fn new(step: Step<E>, parser: P) -> Self {
+        Self {
+            step,
+            parser,
+            max_attempts: 1,
+        }
+    }
+
+    /// Sets the maximum number of calls, including the first one. Defaults to 1; at least one call is made.
+    pub fn with_max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+}
+
+#[async_trait]
+impl<E, P> CustomStep<E> for ParsingStep<E, P>
+where
+    E: traits::Executor + Sync,
+    E::Error: Send + Sync + 'static,
+    E::Output: Send,
+    E::PerInvocationOptions: Sync,
+    P: OutputParser<Output = Parameters>,
+{
+    async fn run(
+        &self,
+        parameters: &Parameters,
+        executor: &E,
+    ) -> Result<StepOutcome<E::Output>, CustomStepError> {
+        let (parsed, output) = parse_with_retries(
+            &self.step,
+            &self.parser,
+            parameters,
+            executor,
+            self.max_attempts,
+        )
+        .await
+        .map_err(Box::new)?;
+        Ok(StepOutcome {
+            parameters: parameters.combine(&parsed),
+            output: Some(output),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn composes_parsers() {
+        let score = RegexParser::new(r"Score: (?P<score>\d+)").unwrap();
+        let grade = RegexParser::new(r"Grade: (?P<score>[A-F])").unwrap();
+        let parser = score.or(grade).map(|p| p.get("score").unwrap());
+        assert_eq!(parser.parse("Score: 7").unwrap(), "7");
+        assert_eq!(parser.parse("Grade: B").unwrap(), "B");
+        assert_eq!(
+            parser.parse("Nope").unwrap_err().message(),
+            "The output doesn't match the pattern Grade: (?P<score>[A-F])"
+        );
+        assert_eq!(
+            parser.instructions().unwrap(),
+            "The answer must match the regular expression `Score: (?P<score>\\d+)`."
+        );
+    }
+
+    #[test]
+    fn parsing_step_retries_and_adds_the_parsed_parameters() {
+        use crate::agents::mock::{MockExecutor, MockOutput};
+        use crate::prompt;
+        use futures::executor::block_on;
+
+        let grade = || Step::for_prompt_template(prompt!("Grade {{text}}"));
+        let parser = RegexParser::new(r"Score: (?P<score>\d+)").unwrap();
+        let executor = MockExecutor::new(vec![
+            MockOutput::text("Seven"),
+            MockOutput::text("Score: 7"),
+        ]);
+        let parameters = Parameters::new_with_text("the essay");
+
+        let step = ParsingStep::new(grade(), parser.clone());
+        assert!(block_on(step.run(&parameters, &executor)).is_err());
+
+        let step = ParsingStep::new(grade(), parser).with_max_attempts(2);
+        let executor = MockExecutor::new(vec![
+            MockOutput::text("Seven"),
+            MockOutput::text("Score: 7"),
+        ]);
+        let outcome = block_on(step.run(&parameters, &executor)).unwrap();
+        assert_eq!(outcome.parameters.get("score").as_deref(), Some("7"));
+        assert_eq!(outcome.parameters.get_text().as_deref(), Some("the essay"));
+        assert_eq!(outcome.output.unwrap().0.as_deref(), Some("Score: 7"));
+    }
+}
@@ -0,0 +1,293 @@
+use std::marker::PhantomData;
+
+use futures::future;
+use futures::stream::{BoxStream, StreamExt};
+use serde::de::DeserializeOwned;
+
+use super::{OutputParser, OutputParserError};
+use crate::output::OutputStream;
+use crate::step::JSON_INSTRUCTIONS;
+
+/// A parser for JSON outputs that may not be complete yet, such as the text streamed so far, producing values of
+/// type `T`.
+///
+/// The JSON value is completed by closing its unfinished string and containers, and dropping the member being
+/// written if it can't be completed, such as a key without its value or a number that may still grow. If the
+/// completed value doesn't deserialize into `T`, for example because the last element of an array is missing
+/// required fields, the unfinished element is dropped as well, so that arrays grow one complete element at a time.
+/// Text before the JSON value, such as the opening of a code block, and text after it are ignored.
+///
+/// # Examples
+///
+/// ```
+/// use llm_chain::output_parser::{OutputParser, PartialJsonParser};
+/// #[derive(Debug, PartialEq, serde::Deserialize)]
+/// struct Item {
+///     name: String,
+///     price: u32,
+/// }
+/// let parser = PartialJsonParser::<Vec<Item>>::new();
+/// let items = parser.parse(r#"```json\n[{"name": "tea", "price": 3}, {"name": "cof"#).unwrap();
+/// assert_eq!(items, [Item { name: "tea".to_string(), price: 3 }]);
+///
+/// let value = PartialJsonParser::<serde_json::Value>::new().parse(r#"{"title": "Stre"#).unwrap();
+/// assert_eq!(value, serde_json::json!({"title": "Stre"}));
+/// ```
+///
+/// Streaming the values of a streamed output, for example to render them as they grow:
+///
+/// ```ignore
+/// let mut items = PartialJsonParser::<Vec<Item>>::new().parse_stream(chain.run_stream(parameters, &exec).await?);
+/// while let Some(items) = items.next().await {
+///     render(&items?);
+/// }
+/// ```
+pub struct PartialJsonParser<T> {
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<T> Default for PartialJsonParser<T> {
+    fn default() -> Self {
+        Self {
+            _value: PhantomData,
+        }
+    }
+}
+
+impl<T> Clone for PartialJsonParser<T> {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl<T> std::fmt::Debug for PartialJsonParser<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PartialJsonParser").finish()
+    }
+}
+
+impl<T: DeserializeOwned> PartialJsonParser<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses the text of `chunks` as it is streamed, yielding a value every time the text parsed so far produces a
+    /// different one. Errors of the stream are forwarded.
+    pub fn parse_stream<'a, Err: Send + 'a>(
+        self,
+        chunks: OutputStream<'a, Err>,
+    ) -> BoxStream<'a, Result<T, Err>>
+    where
+        T: Clone + PartialEq + Send + 'a,
+    {
+        let mut text = String::new();
+        let mut last: Option<T> = None;
+        chunks
+            .filter_map(move |chunk| {
+                future::ready(match chunk {
+                    Err(err) => Some(Err(err)),
+                    Ok(chunk) => {
+                        text.push_str(&chunk.delta);
+                        match self.parse(&text) {
+                            Ok(value) if last.as_ref() != Some(&value) => {
+                                last = Some(value.clone());
+                                Some(Ok(value))
+                            }
+                            _ => None,
+                        }
+                    }
+                })
+            })
+            .boxed()
+    }
+}
+
+impl<T: DeserializeOwned> OutputParser for PartialJsonParser<T> {
+    type Output = T;
+
+    fn parse(&self, text: &str) -> Result<T, OutputParserError> {
+        let candidates = completions(text);
+        if candidates.is_empty() {
+            return Err(OutputParserError::new("The output contains no JSON value"));
+        }
+        candidates
+            .iter()
+            .find_map(|candidate| serde_json::from_str(candidate).ok())
+            .ok_or_else(|| {
+                OutputParserError::new("The JSON value doesn't have the expected format")
+            })
+    }
+
+    fn instructions(&self) -> Option<String> {
+        Some(JSON_INSTRUCTIONS.to_string())
+    }
+}
+
+/// An array or object that isn't closed yet.
+struct Open {
+    object: bool,
+    /// The end of the last complete member, or of the opening bracket: the container can be closed there.
+    safe: usize,
+    expect_key: bool,
+}
+
+/// A string that isn't closed yet.
+struct OpenString {
+    is_key: bool,
+    /// The start of an unfinished escape sequence, and the number of characters it still needs.
+    escape: Option<(usize, usize)>,
+}
+
+/// The state of the JSON value at the end of a text.
+struct Scan {
+    start: usize,
+    stack: Vec<Open>,
+    string: Option<OpenString>,
+    end: Option<usize>,
+}
+
+/// Scans the JSON array or object starting at the first bracket of `text`.
+fn scan(text: &str) -> Option<Scan> {
+    let start = text.find(['{', '['])?;
+    let mut scan = Scan {
+        start,
+        stack: Vec::new(),
+        string: None,
+        end: None,
+    };
+    let mut in_scalar = false;
+    for (i, c) in text[start..].char_indices() {
+        let i = start + i;
+        if let Some(string) = &mut scan.string {
+            match string.escape {
+                Some((at, _)) if c == 'u' && i == at + 1 => string.escape = Some((at, 4)),
+                Some((at, remaining)) => {
+                    string.escape = (remaining > 1).then_some((at, remaining - 1))
+                }
+                None if c == '\\' => string.escape = Some((i, 1)),
+                None if c == '"' => {
+                    if !string.is_key {
+                        if let Some(open) = scan.stack.last_mut() {
+                            open.safe = i + 1;
+                        }
+                    }
+                    scan.string = None;
+                }
+                None => {}
+            }
+            continue;
+        }
+        if in_scalar && (c.is_whitespace() || matches!(c, ',' | '}' | ']')) {
+            in_scalar = false;
+            if let Some(open) = scan.stack.last_mut() {
+                open.safe = i;
+            }
+        }
+        match c {
+            '{' | '[' => scan.stack.push(Open {
+                object: c == '{',
+                safe: i + 1,
+                expect_key: c == '{',
+            }),
+            '}' | ']' => {
+                scan.stack.pop();
+                match scan.stack.last_mut() {
+                    Some(parent) => parent.safe = i + 1,
+                    None => {
+                        scan.end = Some(i + 1);
+                        break;
+                    }
+                }
+            }
+            '"' => {
+                let is_key = scan
+                    .stack
+                    .last()
+                    .is_some_and(|open| open.object && open.expect_key);
+                scan.string = Some(OpenString {
+                    is_key,
+                    escape: None,
+                });
+            }
+            ',' | ':' => {
+                if let Some(open) = scan.stack.last_mut() {
+                    open.expect_key = c == ',' && open.object;
+                }
+            }
+            c if c.is_whitespace() => {}
+            _ => in_scalar = true,
+        }
+    }
+    Some(scan)
+}
+
+/// Returns the ways to complete the JSON value of `text`, from the one keeping the most of the text to the one
+/// keeping the least.
+fn completions(text: &str) -> Vec<String> {
+    let Some(scan) = scan(text) else {
+        return Vec::new();
+    };
+    if let Some(end) = scan.end {
+        return vec![text[scan.start..end].to_string()];
+    }
+    let closing = |stack: &[Open]| -> String {
+        stack
+            .iter()
+            .rev()
+            .map(|open| if open.object { '}' } else { ']' })
+            .collect()
+    };
+    let mut completions = Vec::new();
+    if let Some(string) = scan.string.as_ref().filter(|string| !string.is_key) {
+        let end = match string.escape {
+            Some((at, _)) => at,
+            None => text.len(),
+        };
+        completions.push(format!(
+            "{}\"{}",
+            &text[scan.start..end],
+            closing(&scan.stack)
+        ));
+    }
+    for depth in (0..scan.stack.len()).rev() {
+        completions.push(format!(
+            "{}{}",
+            &text[scan.start..scan.stack[depth].safe],
+            closing(&scan.stack[..=depth])
+        ));
+    }
+    completions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn completes_json_prefixes() {
+        let parser = PartialJsonParser::<Value>::new();
+        let parse = |text: &str| parser.parse(text).unwrap();
+        assert_eq!(parse(r#"{"a": [1, 2"#), json!({"a": [1]}));
+        assert_eq!(parse(r#"{"a": [1, 2]"#), json!({"a": [1, 2]}));
+        assert_eq!(parse(r#"{"a": "x\"y\u00"#), json!({"a": "x\"y"}));
+        assert_eq!(parse(r#"{"a": true, "b"#), json!({"a": true}));
+        assert_eq!(parse(r#"{"a": {"b": nul"#), json!({"a": {}}));
+        assert_eq!(parse(r#"[{"a": 1}] and more"#), json!([{"a": 1}]));
+        assert!(parser.parse("no JSON yet").is_err());
+    }
+
+    #[test]
+    fn streams_growing_values() {
+        let chunks = ["[\"a", "b\", \"c", "\"]"]
+            .into_iter()
+            .map(|delta| Ok::<_, ()>(crate::output::StreamChunk::new(delta)));
+        let values: Vec<Vec<String>> = futures::executor::block_on(
+            PartialJsonParser::new()
+                .parse_stream(futures::stream::iter(chunks).boxed())
+                .map(Result::unwrap)
+                .collect(),
+        );
+        assert_eq!(values, [vec!["a"], vec!["ab", "c"]]);
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+use regex::Regex;
+
+use super::{OutputParser, OutputParserError};
+use crate::Parameters;
+
+/// A parser extracting the named capture groups of a regular expression into parameters.
+///
+/// The first match of the expression in the text is used, and the text of each named group is trimmed and set as the
+/// parameter of the same name. Groups that didn't take part in the match are left out, unless they have a default.
+///
+/// # Examples
+///
+/// ```
+/// use llm_chain::output_parser::{OutputParser, RegexParser};
+/// let parser = RegexParser::new(r"(?i)verdict:\s*(?P<verdict>pass|fail)(?:\s*\((?P<reason>[^)]*)\))?")
+///     .unwrap()
+///     .with_default("reason", "none given");
+/// let parameters = parser.parse("Looks good.\nVerdict: PASS").unwrap();
+/// assert_eq!(parameters.get("verdict").unwrap(), "PASS");
+/// assert_eq!(parameters.get("reason").unwrap(), "none given");
+/// ```
+#[derive(Debug, Clone)]
+pub struct RegexParser {
+    regex: Regex,
+    defaults: HashMap<String, String>,
+}
+
+impl RegexParser {
+    /// Creates a parser for `pattern`, failing if it isn't a valid regular expression.
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        Ok(Self::from_regex(Regex::new(pattern)?))
+    }
+
+    pub fn from_regex(regex: Regex) -> Self {
+        Self {
+            regex,
+            defaults: HashMap::new(),
+        }
+    }
+
+    /// Sets the value of the parameter `name` when its group doesn't take part in the match.
+    pub fn with_default<K: Into<String>, V: Into<String>>(mut self, name: K, value: V) -> Self {
+        self.defaults.insert(name.into(), value.into());
+        self
+    }
+}
+
+impl OutputParser for RegexParser {
+    type Output = Parameters;
+
+    fn parse(&self, text: &str) -> Result<Parameters, OutputParserError> {
+        let captures = self.regex.captures(text).ok_or_else(|| {
+            OutputParserError::new(format!(
+                "The output doesn't match the pattern {}",
+                self.regex.as_str()
+            ))
+        })?;
+        let mut parameters = Parameters::new();
+        for name in self.regex.capture_names().flatten() {
+            let value = match captures.name(name) {
+                Some(group) => group.as_str().trim(),
+                None => match self.defaults.get(name) {
+                    Some(value) => value,
+                    None => continue,
+                },
+            };
+            parameters = parameters.with(name, value);
+        }
+        Ok(parameters)
+    }
+
+    fn instructions(&self) -> Option<String> {
+        Some(format!(
+            "The answer must match the regular expression `{}`.",
+            self.regex.as_str()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_named_groups() {
+        let parser =
+            RegexParser::new(r"Score: (?P<score>\d+)/10(?:, verdict: (?P<verdict>\w+))?(.*)")
+                .unwrap();
+        let parameters = parser.parse("Score: 8/10, verdict: keep ").unwrap();
+        assert_eq!(parameters.keys().collect::<Vec<_>>(), ["score", "verdict"]);
+        assert_eq!(parameters.get("verdict").unwrap(), "keep");
+        let parameters = parser.parse("Score: 3/10").unwrap();
+        assert_eq!(parameters.keys().collect::<Vec<_>>(), ["score"]);
+        assert!(parser.parse("Score: high").is_err());
+    }
+}
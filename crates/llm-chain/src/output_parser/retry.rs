@@ -0,0 +1,137 @@
+use super::{OutputParser, OutputParserError};
+use crate::frame::FormatAndExecuteError;
+use crate::step::{run_with_feedback, FeedbackError, Step};
+use crate::traits::{self, ExecutorError};
+use crate::Parameters;
+
+/// The error returned by `parse_with_retries`.
+#[derive(Debug, thiserror::Error)]
+pub enum ParseRetryError<Err: ExecutorError> {
+    #[error("FormatAndExecuteError: {0}")]
+    FormatAndExecuteError(#[from] FormatAndExecuteError<Err>),
+    #[error("The model returned no text")]
+    NoTextOutput,
+    /// The last output still couldn't be parsed. `text` is the raw output of the model.
+    #[error("The output couldn't be parsed after {attempts} attempts: {source}\nOutput: {text}")]
+    Invalid {
+        attempts: usize,
+        text: String,
+        #[source]
+        source: OutputParserError,
+    },
+}
+
+/// Runs `step` and parses its output with `parser`, asking the model to fix outputs that can't be parsed.
+///
+/// When parsing fails, the step is run again with the malformed output, the parse error and the instructions of the
+/// parser appended to its prompt, up to `max_attempts` calls in total. As with `SelfHealingStep`, only the latest
+/// output is appended, and the retries are not streamed. Returns the parsed value along with the output it was
+/// parsed from.
+///
+/// # Example
+///
+/// ```ignore
+/// let parser = RegexParser::new(r"Score: (?P<score>\d+)")?;
+/// let (parameters, _) = parse_with_retries(&grade_step, &parser, &parameters!(essay), &executor, 3).await?;
+/// ```
+pub async fn parse_with_retries<E, P>(
+    step: &Step<E>,
+    parser: &P,
+    parameters: &Parameters,
+    executor: &E,
+    max_attempts: usize,
+) -> Result<(P::Output, E::Output), ParseRetryError<E::Error>>
+where
+    E: traits::Executor,
+    P: OutputParser,
+{
+    let attempts = max_attempts.max(1);
+    let feedback = |error: &OutputParserError| match parser.instructions() {
+        Some(instructions) => format!("{}\n\n{}", error.message(), instructions),
+        None => error.message().to_string(),
+    };
+    run_with_feedback(
+        step,
+        parameters,
+        executor,
+        attempts,
+        |text| parser.parse(text),
+        feedback,
+    )
+    .await
+    .map_err(|error| match error {
+        FeedbackError::Run(e) => ParseRetryError::FormatAndExecuteError(e),
+        FeedbackError::NoTextOutput => ParseRetryError::NoTextOutput,
+        FeedbackError::Invalid { text, error } => ParseRetryError::Invalid {
+            attempts,
+            text,
+            source: error,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_with_retries, ParseRetryError};
+    use crate::agents::mock::{MockExecutor, MockOutput};
+    use crate::output_parser::RegexParser;
+    use crate::prompt::ChatRole;
+    use crate::{prompt, step::Step, Parameters};
+    use futures::executor::block_on;
+
+    fn grade() -> Step<MockExecutor> {
+        Step::for_prompt_template(prompt!("Grade {{text}}"))
+    }
+
+    #[test]
+    fn asks_the_model_to_fix_malformed_output() {
+        let parser = RegexParser::new(r"Score: (?P<score>\d+)").unwrap();
+        let executor = MockExecutor::new(vec![
+            MockOutput::text("Seven"),
+            MockOutput::text("Score: 7"),
+        ]);
+        let parameters = Parameters::new_with_text("the essay");
+        let (parsed, output) = block_on(parse_with_retries(
+            &grade(),
+            &parser,
+            &parameters,
+            &executor,
+            3,
+        ))
+        .unwrap();
+        assert_eq!(parsed.get("score").as_deref(), Some("7"));
+        assert_eq!(output.0.as_deref(), Some("Score: 7"));
+
+        let prompts = executor.prompts.lock().unwrap();
+        assert_eq!(prompts.len(), 2);
+        let chat = prompts[1].to_chat();
+        let roles: Vec<_> = chat.iter().map(|m| m.role().clone()).collect();
+        assert_eq!(roles, [ChatRole::User, ChatRole::Assistant, ChatRole::User]);
+        assert_eq!(chat.get_message(0).unwrap().body(), "Grade the essay");
+        assert_eq!(chat.get_message(1).unwrap().body(), "Seven");
+        let feedback = chat.get_message(2).unwrap().body();
+        assert!(feedback.contains("The output doesn't match the pattern Score: (?P<score>\\d+)"));
+        assert!(feedback.contains("The answer must match the regular expression"));
+    }
+
+    #[test]
+    fn reports_the_attempts_when_the_output_stays_malformed() {
+        let parser = RegexParser::new(r"Score: (?P<score>\d+)").unwrap();
+        let executor =
+            MockExecutor::new(vec![MockOutput::text("Seven"), MockOutput::text("Eight")]);
+        let parameters = Parameters::new_with_text("the essay");
+        let result = block_on(parse_with_retries(
+            &grade(),
+            &parser,
+            &parameters,
+            &executor,
+            2,
+        ));
+        let Err(ParseRetryError::Invalid { attempts, text, .. }) = result else {
+            panic!("the output should stay invalid");
+        };
+        assert_eq!(attempts, 2);
+        assert_eq!(text, "Eight");
+        assert_eq!(executor.prompts.lock().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,150 @@
+use regex::Regex;
+
+use super::{OutputParser, OutputParserError};
+use crate::Parameters;
+
+/// A parser extracting the sections of an output wrapped in XML-style tags, such as
+/// `<thinking>…</thinking><answer>…</answer>`, into parameters named after the tags.
+///
+/// Tags are matched ignoring case and whitespace, as in `< Answer >`, and their contents are trimmed. A section
+/// whose closing tag is missing ends at the next known tag, or at the end of the output, so that truncated outputs
+/// still parse. When a tag appears several times, its first section is used. Sections of missing tags are left out,
+/// unless the tag is required with `with_required`.
+///
+/// # Examples
+///
+/// ```
+/// use llm_chain::output_parser::{OutputParser, XmlTagParser};
+/// let parser = XmlTagParser::new(["thinking", "answer"]).with_required("answer");
+/// let output = "<thinking>\nThe user wants a number.\n</thinking>\n< ANSWER >42</answer >";
+/// let parameters = parser.parse(output).unwrap();
+/// assert_eq!(parameters.get("thinking").unwrap(), "The user wants a number.");
+/// assert_eq!(parameters.get("answer").unwrap(), "42");
+/// assert!(parser.parse("<thinking>Hmm").is_err());
+/// ```
+#[derive(Debug, Clone)]
+pub struct XmlTagParser {
+    tags: Vec<Tag>,
+}
+
+#[derive(Debug, Clone)]
+struct Tag {
+    name: String,
+    parameter: String,
+    required: bool,
+    regex: Regex,
+}
+
+impl XmlTagParser {
+    /// Creates a parser for `tags`, setting the contents of each tag to the parameter of the same name.
+    pub fn new<S: Into<String>, I: IntoIterator<Item = S>>(tags: I) -> Self {
+        let parser = Self { tags: Vec::new() };
+        tags.into_iter().fold(parser, |parser, tag| {
+            let tag = tag.into();
+            parser.with_tag(tag.clone(), tag)
+        })
+    }
+
+    /// Adds `tag`, setting its contents to the parameter `parameter`.
+    pub fn with_tag<T: Into<String>, P: Into<String>>(mut self, tag: T, parameter: P) -> Self {
+        let name = tag.into();
+        self.tags
+            .retain(|tag| !tag.name.eq_ignore_ascii_case(&name));
+        self.tags.push(Tag {
+            regex: Regex::new("").expect("the empty pattern is valid"),
+            parameter: parameter.into(),
+            required: false,
+            name,
+        });
+        self.compile();
+        self
+    }
+
+    /// Makes parsing fail when `tag` is missing from the output.
+    pub fn with_required(mut self, tag: &str) -> Self {
+        for known in self.tags.iter_mut() {
+            if known.name.eq_ignore_ascii_case(tag) {
+                known.required = true;
+            }
+        }
+        self
+    }
+
+    /// Builds the expressions matching the sections of the tags. A section ends at its closing tag, at the opening
+    /// of any known tag, or at the end of the output.
+    fn compile(&mut self) {
+        let names: Vec<String> = self
+            .tags
+            .iter()
+            .map(|tag| regex::escape(&tag.name))
+            .collect();
+        let any_opening = format!(r"<\s*(?:{})\s*>", names.join("|"));
+        for tag in self.tags.iter_mut() {
+            let name = regex::escape(&tag.name);
+            let pattern = format!(
+                r"(?is)<\s*{name}\s*>(.*?)(?:<\s*/\s*{name}\s*>|{any_opening}|\z)",
+                name = name,
+                any_opening = any_opening
+            );
+            tag.regex = Regex::new(&pattern).expect("tag names are escaped");
+        }
+    }
+}
+
+impl OutputParser for XmlTagParser {
+    type Output = Parameters;
+
+    fn parse(&self, text: &str) -> Result<Parameters, OutputParserError> {
+        let mut parameters = Parameters::new();
+        for tag in &self.tags {
+            match tag.regex.captures(text) {
+                Some(captures) => {
+                    parameters = parameters.with(tag.parameter.as_str(), captures[1].trim());
+                }
+                None if tag.required => {
+                    return Err(OutputParserError::new(format!(
+                        "The output has no <{0}>…</{0}> section",
+                        tag.name
+                    )))
+                }
+                None => {}
+            }
+        }
+        Ok(parameters)
+    }
+
+    fn instructions(&self) -> Option<String> {
+        let sections: Vec<String> = self
+            .tags
+            .iter()
+            .map(|tag| format!("<{0}>…</{0}>", tag.name))
+            .collect();
+        Some(format!(
+            "Write your answer in the sections {}, in this order.",
+            sections.join(", ")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_tagged_sections() {
+        let parser = XmlTagParser::new(["thinking"]).with_tag("answer", "text");
+        let parameters = parser
+            .parse("<thinking>a <b>bold</b> idea<answer> yes </answer><answer>no</answer>")
+            .unwrap();
+        assert_eq!(parameters.get("thinking").unwrap(), "a <b>bold</b> idea");
+        assert_eq!(parameters.get_text().unwrap(), "yes");
+        let parameters = parser.parse("No tags at all").unwrap();
+        assert_eq!(parameters.keys().count(), 0);
+        let parameters = parser.parse("<answer>cut off").unwrap();
+        assert_eq!(parameters.get_text().unwrap(), "cut off");
+        assert_eq!(
+            parser.instructions().unwrap(),
+            "Write your answer in the sections <thinking>…</thinking>, <answer>…</answer>, in this order."
+        );
+    }
+}
@@ -3,6 +3,7 @@
 //! Parameters are used to pass data between steps of the chain. They are used to fill in the prompt template, and are also filled in by the output of the previous step. Parameters have a special key, `text`, which is used as a default key for simple use cases.
 use crate::output::Output;
 use std::{
+    any::Any,
     collections::{BTreeMap, HashMap},
     fmt::Debug,
 };
@@ -58,6 +59,12 @@ impl PartialEq for Parameters {
 
 pub trait Param: Send + Sync {
     fn get(&self) -> String;
+
+    /// Returns the value given to prompt templates. Defaults to the string returned by `get`, parameters holding
+    /// structured data return it here so templates can access its fields.
+    fn to_value(&self) -> serde_json::Value {
+        serde_json::Value::String(self.get())
+    }
 }
 
 /// This trait is used to implement a dynamic parameter this shouldn't be used but exists only for internal purposes.
@@ -65,6 +72,8 @@ pub trait Param: Send + Sync {
 pub trait ParamFull: Param + Debug + Send + Sync {
     #[doc(hidden)]
     fn boxed_clone(&self) -> Box<dyn ParamFull + Send>;
+    #[doc(hidden)]
+    fn as_any(&self) -> &dyn Any;
 }
 
 impl<T: Param + Debug + Clone + 'static> ParamFull for T {
@@ -72,6 +81,10 @@ impl<T: Param + Debug + Clone + 'static> ParamFull for T {
     fn boxed_clone(&self) -> Box<dyn ParamFull + Send> {
         Box::new(self.clone())
     }
+    #[doc(hidden)]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 #[derive(Debug, Clone)]
 struct StringParam {
@@ -90,7 +103,27 @@ impl Param for StringParam {
     }
 }
 
-const TEXT_KEY: &str = "text";
+/// A parameter holding structured data, such as the metadata of a document.
+#[derive(Debug, Clone)]
+struct ValueParam {
+    value: serde_json::Value,
+}
+
+impl Param for ValueParam {
+    /// Returns strings as they are and other values serialized as JSON.
+    fn get(&self) -> String {
+        match &self.value {
+            serde_json::Value::String(value) => value.clone(),
+            value => value.to_string(),
+        }
+    }
+
+    fn to_value(&self) -> serde_json::Value {
+        self.value.clone()
+    }
+}
+
+pub(crate) const TEXT_KEY: &str = "text";
 
 impl Parameters {
     /// Creates a new empty set of parameters.
@@ -121,6 +154,36 @@ impl Parameters {
         copy
     }
 
+    /// Copies the parameters and adds a key with a structured value. Templates can access the fields of objects and
+    /// loop over arrays, e.g. `{{doc.source}}` or `{% for doc in documents %}`, while `get` returns the value
+    /// serialized as JSON.
+    ///
+    /// ```
+    /// use llm_chain::Parameters;
+    /// let p = Parameters::new().with_value("doc", serde_json::json!({"source": "foo.pdf", "page": 3}));
+    /// assert_eq!(p.get("doc").unwrap(), r#"{"page":3,"source":"foo.pdf"}"#);
+    /// assert_eq!(p.get_value("doc").unwrap()["page"], 3);
+    /// ```
+    pub fn with_value<K: Into<String>>(&self, key: K, value: serde_json::Value) -> Parameters {
+        self.with_dynamic(key, ValueParam { value })
+    }
+
+    /// Copies the parameters and makes the value of `from` also available under `to`. Returns `None` if `from` does
+    /// not exist.
+    ///
+    /// ```
+    /// use llm_chain::Parameters;
+    /// let p = Parameters::new_with_text("A short summary").with_alias("text", "summary").unwrap();
+    /// assert_eq!(p.get("summary").unwrap(), "A short summary");
+    /// assert_eq!(p.get_text().unwrap(), "A short summary");
+    /// ```
+    pub fn with_alias<K: Into<String>>(&self, from: &str, to: K) -> Option<Parameters> {
+        let value = self.map.get(from)?.boxed_clone();
+        let mut copy = self.clone();
+        copy.map.insert(to.into(), value);
+        Some(copy)
+    }
+
     /// Copies the parameters and adds a new key-value pair with the key `text`, which is the default key.
     pub fn with_text<K: Into<String>>(&self, text: K) -> Parameters {
         self.with(TEXT_KEY, text)
@@ -139,11 +202,27 @@ impl Parameters {
         }
         copy
     }
+    /// Returns the keys of the parameters, in alphabetical order.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.map.keys().map(String::as_str)
+    }
+
     /// Returns the value of the given key, or `None` if the key does not exist.
     pub fn get(&self, key: &str) -> Option<String> {
         self.map.get(key).map(|param| param.get())
     }
 
+    /// Returns the value of the given key as given to prompt templates, or `None` if the key does not exist.
+    pub fn get_value(&self, key: &str) -> Option<serde_json::Value> {
+        self.map.get(key).map(|param| param.to_value())
+    }
+
+    /// Returns the dynamic parameter stored under the given key, or `None` if the key does not exist or holds a
+    /// value of a different type.
+    pub fn get_dynamic<T: 'static>(&self, key: &str) -> Option<&T> {
+        self.map.get(key)?.as_any().downcast_ref()
+    }
+
     pub fn get_text(&self) -> Option<String> {
         self.get(TEXT_KEY)
     }
@@ -151,11 +230,19 @@ impl Parameters {
     pub(crate) fn to_tera(&self) -> tera::Context {
         let mut context = tera::Context::new();
         for (key, value) in self.map.iter() {
-            context.insert(key, &value.get());
+            context.insert(key, &value.to_value());
         }
         context
     }
 
+    #[cfg(feature = "handlebars")]
+    pub(crate) fn to_json(&self) -> serde_json::Value {
+        self.map
+            .iter()
+            .map(|(key, value)| (key.clone(), value.to_value()))
+            .collect()
+    }
+
     fn from_seq<K, V, M>(m: M) -> Self
     where
         K: Into<String>,
@@ -307,3 +307,123 @@ fn format_value(s: String) -> String {
         .trim_start()
         .to_owned()
 }
+
+/// Finds a JSON value in `text` and deserializes it into the specified type.
+///
+/// Models asked for JSON often wrap it in a Markdown code block or in a sentence. The whole text is tried first, then
+/// its code blocks, then every span from an opening `{` or `[` to its closing bracket, in order. If none of them
+/// deserializes, the error of a candidate that is valid JSON of the wrong shape is preferred over syntax errors, as
+/// it is usually the more useful one.
+///
+/// # Examples
+///
+/// ```
+/// #[derive(Debug, serde::Deserialize)]
+/// struct Verdict {
+///     score: u8,
+/// }
+/// use llm_chain::parsing::find_json;
+/// let verdict: Verdict = find_json("Sure! Here it is: {\"score\": 4}. Anything else?").unwrap();
+/// assert_eq!(verdict.score, 4);
+/// let scores: Vec<u8> = find_json("Scores:\n```json\n[1, 2]\n```\n").unwrap();
+/// assert_eq!(scores, vec![1, 2]);
+/// let error = find_json::<Verdict>("{\"score\": \"high\"}").unwrap_err();
+/// assert!(error.is_data());
+/// ```
+pub fn find_json<T: DeserializeOwned>(text: &str) -> Result<T, serde_json::Error> {
+    let mut error: Option<serde_json::Error> = None;
+    for candidate in json_candidates(text) {
+        match serde_json::from_str(candidate) {
+            Ok(value) => return Ok(value),
+            Err(e) => {
+                let better = match &error {
+                    None => true,
+                    Some(previous) => !previous.is_data() && e.is_data(),
+                };
+                if better {
+                    error = Some(e);
+                }
+            }
+        }
+    }
+    // The whole text is always a candidate, so there is always an error.
+    Err(error.expect("find_json tries at least one candidate"))
+}
+
+/// Returns the fenced and indented code blocks of the Markdown `text`, in order.
+pub(crate) fn code_blocks(text: &str) -> Vec<Code> {
+    let ast = to_mdast(text, &ParseOptions::default()).expect("markdown parsing can't fail");
+    let mut nodes = vec![ast];
+    let mut blocks = Vec::new();
+    while let Some(node) = nodes.pop() {
+        if let Some(children) = node.children() {
+            nodes.extend(children.iter().rev().cloned());
+        }
+        if let Node::Code(code) = node {
+            blocks.push(code);
+        }
+    }
+    blocks
+}
+
+/// Returns the parts of `text` that may be JSON, in the order `find_json` tries them.
+fn json_candidates(text: &str) -> Vec<&str> {
+    let mut candidates = vec![text.trim()];
+    for position in code_blocks(text)
+        .into_iter()
+        .filter_map(|block| block.position)
+    {
+        // The position covers the fences, which are skipped to get to the content.
+        let block = &text[position.start.offset..position.end.offset];
+        let content = block
+            .split_once('\n')
+            .map(|(_, rest)| rest)
+            .unwrap_or_default();
+        let content = content.trim_end().trim_end_matches(['`', '~']).trim();
+        if !content.is_empty() {
+            candidates.push(content);
+        }
+    }
+    let mut start = 0;
+    while let Some(offset) = text[start..].find(['{', '[']) {
+        let open = start + offset;
+        match closing_bracket(&text[open..]) {
+            Some(length) => {
+                candidates.push(&text[open..open + length]);
+                start = open + length;
+            }
+            None => start = open + 1,
+        }
+    }
+    candidates
+}
+
+/// Returns the length of the bracketed span `text` starts with, skipping brackets inside JSON strings.
+fn closing_bracket(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => depth += 1,
+            '}' | ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
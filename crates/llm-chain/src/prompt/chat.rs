@@ -2,23 +2,26 @@ use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 use std::fmt;
 
+use crate::output::ToolCall;
 use crate::tokens::{Tokenizer, TokenizerError};
 
-use super::{StringTemplate, StringTemplateError};
+use super::{ImagePart, StringTemplate, StringTemplateError};
 use crate::Parameters;
 
 /// The `ChatRole` enum represents the role of a chat message sender in a conversation.
 ///
-/// It has four variants:
+/// It has five variants:
 /// - `User`: Represents a message sent by a user.
 /// - `Assistant`: Represents a message sent by an AI assistant.
 /// - `System`: Represents a message sent by a system or service.
+/// - `Tool`: Represents the result of a tool call requested by the assistant.
 /// - `Other`: Represents a message sent by any other role, specified by a string.
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub enum ChatRole {
     User,
     Assistant,
     System,
+    Tool,
     Other(String),
 }
 
@@ -41,6 +44,7 @@ impl fmt::Display for ChatRole {
             ChatRole::User => write!(f, "User"),
             ChatRole::Assistant => write!(f, "Assistant"),
             ChatRole::System => write!(f, "System"),
+            ChatRole::Tool => write!(f, "Tool"),
             ChatRole::Other(s) => write!(f, "{}", s),
         }
     }
@@ -48,12 +52,22 @@ impl fmt::Display for ChatRole {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 /// The `ChatMessage` struct represents a chat message.
-/// It has two fields:
+/// It has three fields:
 /// - `role`: The role of the message sender.
 /// - `body`: The body of the message.
+/// - `images`: The images sent along with the body, usually in a user message.
+///
+/// Messages of the assistant may also carry the tools it asked to call, and messages of tools the identifier of the
+/// call they answer.
 pub struct ChatMessage<Body> {
     role: ChatRole,
     body: Body,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    images: Vec<ImagePart>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<ToolCall>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>,
 }
 
 impl<Body> ChatMessage<Body> {
@@ -63,7 +77,52 @@ impl<Body> ChatMessage<Body> {
     /// * `role` - The role of the message sender.
     /// * `body` - The body of the message.
     pub fn new(role: ChatRole, body: Body) -> Self {
-        Self { role, body }
+        Self {
+            role,
+            body,
+            images: Vec::new(),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+        }
+    }
+
+    /// Adds an image to the message, sent after its body.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use llm_chain::prompt::{ChatMessage, ImagePart};
+    /// let msg = ChatMessage::user("What's in this picture?")
+    ///     .with_image(ImagePart::url("https://example.com/cat.png"));
+    ///
+    /// assert_eq!(msg.images().len(), 1);
+    /// ```
+    pub fn with_image(mut self, image: ImagePart) -> Self {
+        self.images.push(image);
+        self
+    }
+
+    /// Sets the tools the assistant asked to call in this message.
+    pub fn with_tool_calls(mut self, tool_calls: Vec<ToolCall>) -> Self {
+        self.tool_calls = tool_calls;
+        self
+    }
+
+    /// Creates a message with the role of `Tool`, holding the result of the call identified by `call_id`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use llm_chain::prompt::{ChatMessage, ChatRole};
+    /// let msg = ChatMessage::tool_result(Some("call_1".to_string()), "12°C and sunny");
+    ///
+    /// assert_eq!(msg.role(), &ChatRole::Tool);
+    /// assert_eq!(msg.tool_call_id(), Some("call_1"));
+    /// ```
+    pub fn tool_result(call_id: Option<String>, body: Body) -> Self {
+        let mut message = Self::new(ChatRole::Tool, body);
+        message.tool_call_id = call_id;
+        message
     }
 
     /// Creates a new chat message with the role of `Assistant`.
@@ -136,6 +195,9 @@ impl<Body> ChatMessage<Body> {
         ChatMessage {
             role,
             body: f(&self.body),
+            images: self.images.clone(),
+            tool_calls: self.tool_calls.clone(),
+            tool_call_id: self.tool_call_id.clone(),
         }
     }
 
@@ -147,7 +209,13 @@ impl<Body> ChatMessage<Body> {
     pub fn try_map<U, E, F: Fn(&Body) -> Result<U, E>>(&self, f: F) -> Result<ChatMessage<U>, E> {
         let body = f(&self.body)?;
         let role = self.role.clone();
-        Ok(ChatMessage { role, body })
+        Ok(ChatMessage {
+            role,
+            body,
+            images: self.images.clone(),
+            tool_calls: self.tool_calls.clone(),
+            tool_call_id: self.tool_call_id.clone(),
+        })
     }
 
     /// Returns a reference to the role of the message sender.
@@ -159,6 +227,21 @@ impl<Body> ChatMessage<Body> {
     pub fn body(&self) -> &Body {
         &self.body
     }
+
+    /// Returns the images sent along with the body of the message.
+    pub fn images(&self) -> &[ImagePart] {
+        &self.images
+    }
+
+    /// Returns the tools the assistant asked to call in this message.
+    pub fn tool_calls(&self) -> &[ToolCall] {
+        &self.tool_calls
+    }
+
+    /// Returns the identifier of the tool call this message is the result of.
+    pub fn tool_call_id(&self) -> Option<&str> {
+        self.tool_call_id.as_deref()
+    }
 }
 
 impl<T: fmt::Display> fmt::Display for ChatMessage<T> {
@@ -483,6 +566,87 @@ impl ChatMessageCollection<StringTemplate> {
     }
 }
 
+/// A builder for chat prompt templates, in which every message body is its own template.
+///
+/// Bodies can be given as strings, which are tera templates, or as any `StringTemplate`, so that messages holding
+/// untrusted or brace-heavy text can be static. A conversation can be spliced in with `with_conversation`; its
+/// messages are never templated.
+///
+/// # Example
+///
+/// ```
+/// use llm_chain::prompt::{ChatMessageCollection, ChatPromptBuilder, StringTemplate};
+/// use llm_chain::parameters;
+/// let history = ChatMessageCollection::new()
+///     .with_user("Hi! What does {{x}} do in tera?".to_string())
+///     .with_assistant("It prints the variable x.".to_string());
+/// let prompt = ChatPromptBuilder::new()
+///     .with_system("You are a {{role}}.")
+///     .with_conversation(&history)
+///     .with_user("{{question}}")
+///     .render(&parameters!("role" => "helpful assistant", "question" => "And {% if %}?"))
+///     .unwrap();
+/// assert_eq!(
+///     prompt.to_string(),
+///     "System: You are a helpful assistant.\nUser: Hi! What does {{x}} do in tera?\nAssistant: It prints the variable x.\nUser: And {% if %}?\n"
+/// );
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ChatPromptBuilder {
+    messages: ChatMessageCollection<StringTemplate>,
+}
+
+impl ChatPromptBuilder {
+    /// Creates a builder without messages.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a message with the given role whose body is rendered from `template`.
+    pub fn with_message<T: Into<StringTemplate>>(mut self, role: ChatRole, template: T) -> Self {
+        self.messages
+            .add_message(ChatMessage::new(role, template.into()));
+        self
+    }
+
+    /// Adds a system message whose body is rendered from `template`.
+    pub fn with_system<T: Into<StringTemplate>>(self, template: T) -> Self {
+        self.with_message(ChatRole::System, template)
+    }
+
+    /// Adds a user message whose body is rendered from `template`.
+    pub fn with_user<T: Into<StringTemplate>>(self, template: T) -> Self {
+        self.with_message(ChatRole::User, template)
+    }
+
+    /// Adds an assistant message whose body is rendered from `template`.
+    pub fn with_assistant<T: Into<StringTemplate>>(self, template: T) -> Self {
+        self.with_message(ChatRole::Assistant, template)
+    }
+
+    /// Adds the messages of `conversation` as they are, without templating them.
+    pub fn with_conversation(mut self, conversation: &ChatMessageCollection<String>) -> Self {
+        self.messages.append(
+            conversation
+                .map(|message| message.map(|body| StringTemplate::static_string(body.as_str()))),
+        );
+        self
+    }
+
+    /// Returns the chat prompt template.
+    pub fn build(self) -> super::PromptTemplate {
+        super::Data::Chat(self.messages)
+    }
+
+    /// Renders every message with `parameters` into a chat prompt.
+    pub fn render(&self, parameters: &Parameters) -> Result<super::Prompt, StringTemplateError> {
+        Ok(super::Data::Chat(
+            self.messages
+                .try_map(|template| template.format(parameters))?,
+        ))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -530,4 +694,26 @@ mod tests {
             "Hi there! (mapped)"
         );
     }
+
+    #[test]
+    fn test_images_are_kept_and_serialized() {
+        let msg = ChatMessage::user("What's this?")
+            .with_image(ImagePart::url("https://example.com/cat.png"));
+        let formatted = msg.map(|body| body.to_string());
+        assert_eq!(formatted.images(), msg.images());
+        assert!(
+            crate::prompt::Data::Chat(ChatMessageCollection::for_vector(vec![formatted]))
+                .has_images()
+        );
+
+        let json = serde_json::to_string(&msg).unwrap();
+        assert_eq!(
+            json,
+            r#"{"role":"User","body":"What's this?","images":[{"url":"https://example.com/cat.png"}]}"#
+        );
+        let text_only = serde_json::to_string(&ChatMessage::user("Hi")).unwrap();
+        assert_eq!(text_only, r#"{"role":"User","body":"Hi"}"#);
+        let parsed: ChatMessage<String> = serde_json::from_str(text_only.as_str()).unwrap();
+        assert!(parsed.images().is_empty());
+    }
 }
@@ -0,0 +1,427 @@
+//! Dynamic few-shot prompting.
+//!
+//! Instead of hard-coding a fixed list of examples into a prompt, an `ExampleStore` embeds a pool of labeled
+//! examples and picks the ones most similar to the current input, so the context window is spent on the shots that
+//! are actually relevant. A `LengthBasedSelector` picks examples in order, as many as fit in a length budget,
+//! without embeddings.
+//!
+//! Both implement `ExampleSelector`, so they can be used by a `FewShotStep`, which selects examples for the current
+//! input when a chain runs and passes them rendered to the following step.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let chain = Chain::from_steps(vec![
+//!     ChainStep::custom(FewShotStep::new(store, 3)),
+//!     Step::for_prompt_template(prompt!("Classify the sentiment.\n\n{{examples}}\n\nInput: {{text}}\nOutput:")).into(),
+//! ]);
+//! ```
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{ChatMessage, ChatMessageCollection, StringTemplate, StringTemplateError};
+use crate::retrieval::cosine_similarity;
+use crate::step::{CustomStep, CustomStepError, StepOutcome};
+use crate::traits::{Embeddings, Executor};
+use crate::{parameters, Parameters};
+
+const DEFAULT_EXAMPLE_TEMPLATE: &str = "Input: {{input}}\nOutput: {{output}}";
+const EXAMPLE_SEPARATOR: &str = "\n\n";
+
+/// A labeled example: an input and the output the model is expected to produce for it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Example {
+    pub input: String,
+    pub output: String,
+}
+
+impl Example {
+    /// Creates a new example.
+    pub fn new<I: Into<String>, O: Into<String>>(input: I, output: O) -> Self {
+        Self {
+            input: input.into(),
+            output: output.into(),
+        }
+    }
+}
+
+impl<I: Into<String>, O: Into<String>> From<(I, O)> for Example {
+    fn from((input, output): (I, O)) -> Self {
+        Example::new(input, output)
+    }
+}
+
+/// Renders `examples` with `template`, which has access to the `input` and `output` parameters, separated by blank
+/// lines.
+fn render_examples<'a, I>(
+    template: &StringTemplate,
+    examples: I,
+) -> Result<String, StringTemplateError>
+where
+    I: IntoIterator<Item = &'a Example>,
+{
+    let rendered = examples
+        .into_iter()
+        .map(|example| {
+            template.format(&parameters! {
+                "input" => example.input.as_str(),
+                "output" => example.output.as_str(),
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rendered.join(EXAMPLE_SEPARATOR))
+}
+
+/// An error that occurs when selecting or rendering examples.
+#[derive(Debug, Error)]
+pub enum ExampleStoreError<E: std::error::Error> {
+    #[error(transparent)]
+    Embeddings(E),
+    #[error(transparent)]
+    Template(#[from] StringTemplateError),
+}
+
+/// A store of labeled examples that retrieves the most relevant ones for an input.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut store = ExampleStore::new(embeddings);
+/// store
+///     .add_examples(vec![
+///         Example::new("I loved it", "positive"),
+///         Example::new("Never again", "negative"),
+///     ])
+///     .await?;
+/// let params = store.with_examples(&parameters!("What a great movie"), 1).await?;
+/// let prompt = prompt!("Classify the sentiment.\n\n{{examples}}\n\nInput: {{text}}\nOutput:");
+/// ```
+pub struct ExampleStore<E: Embeddings> {
+    embeddings: E,
+    examples: Vec<(Example, Vec<f32>)>,
+    example_template: StringTemplate,
+}
+
+impl<E: Embeddings> ExampleStore<E> {
+    /// Creates an empty store that embeds examples using `embeddings`.
+    pub fn new(embeddings: E) -> Self {
+        Self {
+            embeddings,
+            examples: Vec::new(),
+            example_template: StringTemplate::tera(DEFAULT_EXAMPLE_TEMPLATE),
+        }
+    }
+
+    /// Sets the template used to render a single example. The template has access to the `input` and `output`
+    /// parameters, and defaults to `"Input: {{input}}\nOutput: {{output}}"`.
+    pub fn with_example_template(mut self, template: StringTemplate) -> Self {
+        self.example_template = template;
+        self
+    }
+
+    /// Embeds the inputs of `examples` and adds them to the store.
+    pub async fn add_examples(&mut self, examples: Vec<Example>) -> Result<(), E::Error> {
+        let inputs = examples.iter().map(|e| e.input.clone()).collect();
+        let vectors = self.embeddings.embed_texts(inputs).await?;
+        self.examples.extend(examples.into_iter().zip(vectors));
+        Ok(())
+    }
+
+    /// Returns the number of examples in the store.
+    pub fn len(&self) -> usize {
+        self.examples.len()
+    }
+
+    /// Returns `true` if the store contains no examples.
+    pub fn is_empty(&self) -> bool {
+        self.examples.is_empty()
+    }
+
+    /// Returns the `k` examples whose inputs are most similar to `input`, most similar first.
+    pub async fn select(&self, input: &str, k: usize) -> Result<Vec<&Example>, E::Error> {
+        if self.examples.is_empty() || k == 0 {
+            return Ok(vec![]);
+        }
+        let query = self.embeddings.embed_query(input.to_string()).await?;
+        let mut scored: Vec<(f32, &Example)> = self
+            .examples
+            .iter()
+            .map(|(example, vector)| (cosine_similarity(&query, vector), example))
+            .collect();
+        scored.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        Ok(scored.into_iter().take(k).map(|(_, e)| e).collect())
+    }
+
+    /// Selects the `k` most relevant examples for `input` and renders them with the example template.
+    pub async fn render(
+        &self,
+        input: &str,
+        k: usize,
+    ) -> Result<String, ExampleStoreError<E::Error>> {
+        let selected = self
+            .select(input, k)
+            .await
+            .map_err(ExampleStoreError::Embeddings)?;
+        Ok(render_examples(&self.example_template, selected)?)
+    }
+
+    /// Selects the `k` most relevant examples for the `text` parameter and returns a copy of `parameters` with
+    /// the rendered examples stored under the `examples` key.
+    pub async fn with_examples(
+        &self,
+        parameters: &Parameters,
+        k: usize,
+    ) -> Result<Parameters, ExampleStoreError<E::Error>> {
+        let input = parameters.get_text().unwrap_or_default();
+        let examples = self.render(&input, k).await?;
+        Ok(parameters.with("examples", examples))
+    }
+
+    /// Selects the `k` most relevant examples for `input` as alternating user and assistant messages, ready to be
+    /// placed in front of the user's message in a chat prompt.
+    pub async fn select_as_chat(
+        &self,
+        input: &str,
+        k: usize,
+    ) -> Result<ChatMessageCollection<String>, E::Error> {
+        let selected = self.select(input, k).await?;
+        let mut messages = ChatMessageCollection::new();
+        for example in selected {
+            messages.add_message(ChatMessage::user(example.input.clone()));
+            messages.add_message(ChatMessage::assistant(example.output.clone()));
+        }
+        Ok(messages)
+    }
+}
+
+/// The error type returned by example selectors.
+pub type ExampleSelectorError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Selects the examples to show the model for an input.
+#[async_trait]
+pub trait ExampleSelector: Send + Sync {
+    /// Returns at most `k` examples for `input`, in the order they should appear in the prompt.
+    async fn select_examples(
+        &self,
+        input: &str,
+        k: usize,
+    ) -> Result<Vec<Example>, ExampleSelectorError>;
+}
+
+#[async_trait]
+impl<E> ExampleSelector for ExampleStore<E>
+where
+    E: Embeddings + Send + Sync,
+    E::Error: Sync + 'static,
+{
+    async fn select_examples(
+        &self,
+        input: &str,
+        k: usize,
+    ) -> Result<Vec<Example>, ExampleSelectorError> {
+        let selected = self.select(input, k).await.map_err(Box::new)?;
+        Ok(selected.into_iter().cloned().collect())
+    }
+}
+
+/// Returns the number of words in `text`, the length measure of `LengthBasedSelector`.
+fn word_count(text: &str) -> usize {
+    text.split_whitespace().count()
+}
+
+/// An example selector picking examples in the order they were added, as many as fit in a length budget.
+///
+/// Lengths are measured in words. The budget covers the input along with the examples rendered with the example
+/// template, so long inputs get fewer examples.
+#[derive(Debug, Clone)]
+pub struct LengthBasedSelector {
+    examples: Vec<Example>,
+    max_words: usize,
+    example_template: StringTemplate,
+}
+
+impl LengthBasedSelector {
+    /// Creates a selector choosing among `examples` with a budget of `max_words` words.
+    pub fn new(examples: Vec<Example>, max_words: usize) -> Self {
+        Self {
+            examples,
+            max_words,
+            example_template: StringTemplate::tera(DEFAULT_EXAMPLE_TEMPLATE),
+        }
+    }
+
+    /// Sets the template used to measure an example. It should be the template the examples are rendered with, and
+    /// defaults to `"Input: {{input}}\nOutput: {{output}}"`.
+    pub fn with_example_template(mut self, template: StringTemplate) -> Self {
+        self.example_template = template;
+        self
+    }
+
+    /// Returns the first examples fitting in the budget along with `input`, at most `k` of them.
+    pub fn select(&self, input: &str, k: usize) -> Result<Vec<&Example>, StringTemplateError> {
+        let mut remaining = self.max_words.saturating_sub(word_count(input));
+        let mut selected = Vec::new();
+        for example in self.examples.iter().take(k) {
+            let length = word_count(&render_examples(&self.example_template, [example])?);
+            if length > remaining {
+                break;
+            }
+            remaining -= length;
+            selected.push(example);
+        }
+        Ok(selected)
+    }
+}
+
+#[async_trait]
+impl ExampleSelector for LengthBasedSelector {
+    async fn select_examples(
+        &self,
+        input: &str,
+        k: usize,
+    ) -> Result<Vec<Example>, ExampleSelectorError> {
+        Ok(self.select(input, k)?.into_iter().cloned().collect())
+    }
+}
+
+/// A custom step selecting examples for the `text` parameter and passing them, rendered, to the following steps in
+/// the `examples` parameter.
+pub struct FewShotStep<S: ExampleSelector> {
+    selector: S,
+    k: usize,
+    example_template: StringTemplate,
+    input_key: Option<String>,
+}
+
+impl<S: ExampleSelector> FewShotStep<S> {
+    /// Creates a step selecting up to `k` examples with `selector`.
+    pub fn new(selector: S, k: usize) -> Self {
+        Self {
+            selector,
+            k,
+            example_template: StringTemplate::tera(DEFAULT_EXAMPLE_TEMPLATE),
+            input_key: None,
+        }
+    }
+
+    /// Sets the template used to render a single example. The template has access to the `input` and `output`
+    /// parameters, and defaults to `"Input: {{input}}\nOutput: {{output}}"`.
+    pub fn with_example_template(mut self, template: StringTemplate) -> Self {
+        self.example_template = template;
+        self
+    }
+
+    /// Selects examples for the parameter `key` instead of `text`.
+    pub fn with_input_key<K: Into<String>>(mut self, key: K) -> Self {
+        self.input_key = Some(key.into());
+        self
+    }
+}
+
+#[async_trait]
+impl<E, S> CustomStep<E> for FewShotStep<S>
+where
+    E: Executor + Sync,
+    S: ExampleSelector,
+{
+    async fn run(
+        &self,
+        parameters: &Parameters,
+        _executor: &E,
+    ) -> Result<StepOutcome<E::Output>, CustomStepError> {
+        let input = match &self.input_key {
+            Some(key) => parameters.get(key),
+            None => parameters.get_text(),
+        }
+        .unwrap_or_default();
+        let selected = self.selector.select_examples(&input, self.k).await?;
+        let examples = render_examples(&self.example_template, &selected)?;
+        Ok(StepOutcome::parameters(
+            parameters.with("examples", examples),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Example, ExampleStore, LengthBasedSelector};
+    use crate::prompt::{ChatRole, StringTemplate};
+    use crate::test_support::LetterEmbeddings;
+    use crate::Parameters;
+    use futures::executor::block_on;
+
+    fn store() -> ExampleStore<LetterEmbeddings> {
+        let mut store = ExampleStore::new(LetterEmbeddings);
+        block_on(store.add_examples(vec![
+            Example::new("aaa", "mostly a"),
+            Example::new("bbb", "mostly b"),
+            Example::new("ccc", "mostly c"),
+        ]))
+        .unwrap();
+        store
+    }
+
+    #[test]
+    fn selects_the_most_similar_examples_first() {
+        let store = store();
+        assert_eq!(store.len(), 3);
+        let selected = block_on(store.select("abb", 2)).unwrap();
+        let inputs: Vec<_> = selected.iter().map(|e| e.input.as_str()).collect();
+        assert_eq!(inputs, vec!["bbb", "aaa"]);
+        assert!(block_on(store.select("abb", 0)).unwrap().is_empty());
+        assert!(
+            block_on(ExampleStore::new(LetterEmbeddings).select("abb", 2))
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn renders_the_selected_examples() {
+        let store = store().with_example_template(StringTemplate::tera("{{input}} => {{output}}"));
+        assert_eq!(
+            block_on(store.render("cca", 2)).unwrap(),
+            "ccc => mostly c\n\naaa => mostly a"
+        );
+        let parameters = block_on(store.with_examples(&Parameters::new_with_text("a"), 1)).unwrap();
+        assert_eq!(
+            parameters.get("examples").as_deref(),
+            Some("aaa => mostly a")
+        );
+        assert_eq!(parameters.get_text().as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn selects_examples_as_chat_messages() {
+        let messages = block_on(store().select_as_chat("b", 1)).unwrap();
+        let messages: Vec<_> = messages
+            .iter()
+            .map(|m| (m.role().clone(), m.body().clone()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (ChatRole::User, "bbb".to_string()),
+                (ChatRole::Assistant, "mostly b".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn length_based_selection_fits_the_budget() {
+        let selector = LengthBasedSelector::new(
+            vec![
+                Example::new("I loved it", "positive"),
+                Example::new("Never again, what a waste of an evening", "negative"),
+                Example::new("Fine", "neutral"),
+            ],
+            12,
+        );
+        // "Input: I loved it Output: positive" is 6 words, the second example 11.
+        let selected = selector.select("Great movie", 3).unwrap();
+        assert_eq!(selected.len(), 1);
+        assert_eq!(selector.select("Great", 1).unwrap().len(), 1);
+        assert!(selector.select("a b c d e f g h", 3).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,124 @@
+use std::path::{Path, PathBuf};
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// An error loading the image of an `ImagePart`.
+#[derive(Error, Debug)]
+pub enum ImageError {
+    #[error("Unable to read the image {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("Unable to tell the media type of the image {0}")]
+    UnknownMediaType(PathBuf),
+}
+
+/// An image included in a chat message, alongside its text.
+///
+/// Images are only sent to executors supporting them; formatting a prompt containing images for a text-only executor
+/// fails with `FormatAndExecuteError::UnsupportedImages`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImagePart {
+    /// An image the model fetches from a URL.
+    Url(String),
+    /// An image encoded in base64, with its media type, such as `image/png`.
+    Base64 { media_type: String, data: String },
+    /// An image in a local file, read when the prompt is sent.
+    File(PathBuf),
+}
+
+impl ImagePart {
+    /// Creates an image fetched from `url`.
+    pub fn url<S: Into<String>>(url: S) -> Self {
+        Self::Url(url.into())
+    }
+
+    /// Creates an image from its bytes and media type, encoding them in base64.
+    pub fn from_bytes<S: Into<String>>(media_type: S, bytes: &[u8]) -> Self {
+        Self::Base64 {
+            media_type: media_type.into(),
+            data: base64::engine::general_purpose::STANDARD.encode(bytes),
+        }
+    }
+
+    /// Creates an image read from the file at `path` when the prompt is sent.
+    pub fn file<P: Into<PathBuf>>(path: P) -> Self {
+        Self::File(path.into())
+    }
+
+    /// Reads the image of a `File` part, and returns it as a `Base64` part. Other parts are returned unchanged.
+    ///
+    /// The media type is guessed from the extension of the file.
+    pub fn load(&self) -> Result<ImagePart, ImageError> {
+        let Self::File(path) = self else {
+            return Ok(self.clone());
+        };
+        let media_type =
+            media_type_of(path).ok_or_else(|| ImageError::UnknownMediaType(path.clone()))?;
+        let bytes = std::fs::read(path).map_err(|source| ImageError::Io {
+            path: path.clone(),
+            source,
+        })?;
+        Ok(Self::from_bytes(media_type, &bytes))
+    }
+
+    /// Returns the image as a URL: the URL of a `Url` part, and a `data:` URL otherwise, which is what most vision
+    /// APIs accept.
+    ///
+    /// ```
+    /// use llm_chain::prompt::ImagePart;
+    /// let image = ImagePart::from_bytes("image/png", b"png");
+    /// assert_eq!(image.to_url().unwrap(), "data:image/png;base64,cG5n");
+    /// ```
+    pub fn to_url(&self) -> Result<String, ImageError> {
+        match self.load()? {
+            Self::Url(url) => Ok(url),
+            Self::Base64 { media_type, data } => Ok(format!("data:{};base64,{}", media_type, data)),
+            Self::File(_) => unreachable!("load returns no File parts"),
+        }
+    }
+}
+
+/// Guesses the media type of an image from the extension of its file.
+fn media_type_of(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_files_as_base64() {
+        let dir = std::env::temp_dir().join(format!("llm-chain-image-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("pixel.PNG");
+        std::fs::write(&path, b"png").unwrap();
+
+        assert_eq!(
+            ImagePart::file(&path).load().unwrap(),
+            ImagePart::from_bytes("image/png", b"png")
+        );
+        assert!(matches!(
+            ImagePart::file(dir.join("notes.txt")).load(),
+            Err(ImageError::UnknownMediaType(_))
+        ));
+        assert!(matches!(
+            ImagePart::file(dir.join("missing.png")).load(),
+            Err(ImageError::Io { .. })
+        ));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -2,14 +2,35 @@
 //! Contains the `prompt!` macro, Prompts and PromptTemplates.
 
 mod chat;
+mod few_shot;
+mod image;
 mod model;
+mod registry;
+mod sanitize;
 mod serialization;
 mod string_template;
 
-pub use string_template::{StringTemplate, StringTemplateError};
+pub use string_template::{
+    clear_warning_handler, register_filter, register_function, register_partial, remove_partial,
+    set_warning_handler, with_warning_handler, RenderMode, StringTemplate, StringTemplateError,
+    TemplateFilter, TemplateFunction, TemplateWarning, TemplateWarningHandler,
+};
 
-pub use chat::{ChatMessage, ChatMessageCollection, ChatRole};
-pub use model::Data;
+pub use chat::{ChatMessage, ChatMessageCollection, ChatPromptBuilder, ChatRole};
+pub use few_shot::{
+    Example, ExampleSelector, ExampleSelectorError, ExampleStore, ExampleStoreError, FewShotStep,
+    LengthBasedSelector,
+};
+pub use image::{ImageError, ImagePart};
+pub use model::{Data, ParameterConflict, ParameterValidationError, TemplateCompositionError};
+pub use registry::{
+    PromptRegistry, PromptRegistryError, RegisteredPrompt, RegistryStep, VersionPolicy,
+    PROMPT_NAME_KEY, PROMPT_VERSION_KEY,
+};
+pub use sanitize::{
+    escape_template_syntax, sanitize, strip_role_markers, Sanitizer, Untrusted,
+    DEFAULT_INJECTION_PHRASES,
+};
 
 /// A prompt template.
 ///
@@ -1,7 +1,43 @@
 use serde::{Deserialize, Serialize};
 
+use std::collections::BTreeMap;
 use std::fmt;
 
+use thiserror::Error;
+
+/// The error returned by `PromptTemplate::validate` and `PromptTemplate::validate_declared` when parameters used by
+/// the template are missing.
+///
+/// Parameters that were given but aren't used are reported alongside, since a missing parameter and an unused one
+/// usually mean a misspelt name.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Missing parameters {missing:?} (unused parameters: {unused:?})")]
+pub struct ParameterValidationError {
+    /// The parameters the template uses that weren't given.
+    pub missing: Vec<String>,
+    /// The parameters that were given but aren't used by the template.
+    pub unused: Vec<String>,
+}
+
+/// A parameter used by both templates of a composition with different example values, a sign that the templates
+/// mean different things by it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParameterConflict {
+    /// The name of the parameter.
+    pub name: String,
+    /// The example value declared by the first template.
+    pub first: String,
+    /// The example value declared by the second template.
+    pub second: String,
+}
+
+/// The error returned by `PromptTemplate::compose` when the templates use parameters in conflicting ways.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("The templates declare different examples for the parameters {conflicts:?}")]
+pub struct TemplateCompositionError {
+    pub conflicts: Vec<ParameterConflict>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 /// An enum representing either a collection of chat messages or a single text.
 pub enum Data<T> {
@@ -51,6 +87,14 @@ impl<T> Data<T> {
             Self::Text(text) => Ok(Data::Text(f(text)?)),
         }
     }
+
+    /// Returns true if any message of the prompt contains an image. Text prompts never do.
+    pub fn has_images(&self) -> bool {
+        match self {
+            Self::Chat(chat) => chat.iter().any(|msg| !msg.images().is_empty()),
+            Self::Text(_) => false,
+        }
+    }
 }
 
 impl<T: fmt::Display> fmt::Display for Data<T> {
@@ -62,6 +106,256 @@ impl<T: fmt::Display> fmt::Display for Data<T> {
     }
 }
 
+impl Data<StringTemplate> {
+    /// Returns the names of the parameters used by the templates of the prompt, in order of first use.
+    pub fn variables(&self) -> Vec<String> {
+        let mut variables: Vec<String> = Vec::new();
+        let templates: Vec<&StringTemplate> = match self {
+            Self::Chat(chat) => chat.iter().map(|message| message.body()).collect(),
+            Self::Text(text) => vec![text],
+        };
+        for name in templates.into_iter().flat_map(StringTemplate::variables) {
+            if !variables.contains(&name) {
+                variables.push(name);
+            }
+        }
+        variables
+    }
+
+    /// Sets how every template of the prompt handles missing parameters. See `StringTemplate::with_render_mode`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use llm_chain::{parameters, prompt, prompt::RenderMode};
+    /// let template = prompt!("Write a story{% if topic %} about {{topic}}{% endif %}.")
+    ///     .with_render_mode(RenderMode::Lenient);
+    /// assert_eq!(template.format(&parameters!()).unwrap().to_text(), "Write a story.");
+    /// ```
+    pub fn with_render_mode(&self, mode: RenderMode) -> Self {
+        self.map(|template| template.clone().with_render_mode(mode))
+    }
+
+    /// Returns the names of the parameters that must be set to format the prompt, in order of first use.
+    ///
+    /// These are the variables of the templates, found by scanning them rather than rendering them, so a variable
+    /// only used in a branch that isn't taken, or guarded by `is defined`, is still required.
+    pub fn required_parameters(&self) -> Vec<String> {
+        self.variables()
+    }
+
+    /// Returns the keys of `parameters` that the prompt doesn't use, in alphabetical order.
+    pub fn unused_parameters(&self, parameters: &Parameters) -> Vec<String> {
+        let variables = self.variables();
+        parameters
+            .keys()
+            .filter(|key| !variables.iter().any(|variable| variable == key))
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Checks that `parameters` sets every parameter the prompt uses, so that a misspelt variable is caught before
+    /// the prompt is sent.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use llm_chain::{parameters, prompt};
+    /// let template = prompt!("You are a {{role}}.", "{{usre_input}}");
+    /// let error = template
+    ///     .validate(&parameters!("role" => "poet", "user_input" => "Write a haiku."))
+    ///     .unwrap_err();
+    /// assert_eq!(error.missing, vec!["usre_input"]);
+    /// assert_eq!(error.unused, vec!["user_input"]);
+    /// ```
+    pub fn validate(&self, parameters: &Parameters) -> Result<(), ParameterValidationError> {
+        let missing: Vec<String> = self
+            .variables()
+            .into_iter()
+            .filter(|variable| parameters.get(variable).is_none())
+            .collect();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(ParameterValidationError {
+                missing,
+                unused: self.unused_parameters(parameters),
+            })
+        }
+    }
+
+    /// Checks that every parameter the prompt uses is one of `declared`, the names of the parameters it will be
+    /// given. Unlike `validate`, this doesn't need their values, so a misspelt variable is caught when the step or the
+    /// chain is built rather than when it runs.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use llm_chain::prompt;
+    /// let template = prompt!("You are a {{role}}.", "{{usre_input}}");
+    /// let error = template.validate_declared(&["role", "user_input"]).unwrap_err();
+    /// assert_eq!(error.missing, vec!["usre_input"]);
+    /// assert_eq!(error.unused, vec!["user_input"]);
+    /// ```
+    pub fn validate_declared<S: AsRef<str>>(
+        &self,
+        declared: &[S],
+    ) -> Result<(), ParameterValidationError> {
+        let variables = self.variables();
+        let missing: Vec<String> = variables
+            .iter()
+            .filter(|variable| !declared.iter().any(|name| name.as_ref() == *variable))
+            .cloned()
+            .collect();
+        if missing.is_empty() {
+            return Ok(());
+        }
+        let mut unused: Vec<String> = declared
+            .iter()
+            .map(|name| name.as_ref().to_string())
+            .filter(|name| !variables.contains(name))
+            .collect();
+        unused.sort();
+        unused.dedup();
+        Err(ParameterValidationError { missing, unused })
+    }
+
+    /// Returns the parameters used by both `self` and `other`, in order of first use in `self`.
+    pub fn shared_parameters(&self, other: &Self) -> Vec<String> {
+        let others = other.variables();
+        self.variables()
+            .into_iter()
+            .filter(|variable| others.contains(variable))
+            .collect()
+    }
+
+    /// Returns the shared parameters for which `self` and `other` declare different example values.
+    pub fn parameter_conflicts(&self, other: &Self) -> Vec<ParameterConflict> {
+        let (examples, others) = (self.examples(), other.examples());
+        self.shared_parameters(other)
+            .into_iter()
+            .filter_map(|name| match (examples.get(&name), others.get(&name)) {
+                (Some(first), Some(second)) if first != second => Some(ParameterConflict {
+                    first: first.clone(),
+                    second: second.clone(),
+                    name,
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Appends `other` to the prompt, so that a reusable template, such as instructions on the output format, can
+    /// be added to any task template. The composed template requires the parameters of both.
+    ///
+    /// Two text templates are joined with a blank line. A text template appended to a chat is added to its last
+    /// message if that is a user message, and sent as a user message of its own otherwise; a text template followed
+    /// by a chat is sent as a user message before it. Two chats are concatenated.
+    ///
+    /// Fails if a parameter used by both templates has different example values in each, see
+    /// `parameter_conflicts`. Use `append` to compose the templates anyway.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use llm_chain::{parameters, prompt};
+    /// let task = prompt!("You are a helpful assistant.", "List the capitals of {{countries}}.");
+    /// let format = prompt!("Answer in {{format}}.");
+    /// let composed = task.compose(&format).unwrap();
+    /// assert_eq!(composed.required_parameters(), vec!["countries", "format"]);
+    /// let prompt = composed
+    ///     .format(&parameters!("countries" => "France and Peru", "format" => "JSON"))
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     prompt.to_string(),
+    ///     "System: You are a helpful assistant.\nUser: List the capitals of France and Peru.\n\nAnswer in JSON.\n"
+    /// );
+    /// ```
+    pub fn compose(&self, other: &Self) -> Result<Self, TemplateCompositionError> {
+        let conflicts = self.parameter_conflicts(other);
+        if conflicts.is_empty() {
+            Ok(self.append(other))
+        } else {
+            Err(TemplateCompositionError { conflicts })
+        }
+    }
+
+    /// Appends `other` to the prompt like `compose`, without checking for conflicting parameters.
+    pub fn append(&self, other: &Self) -> Self {
+        let join = |first: &StringTemplate, second: &StringTemplate| {
+            StringTemplate::combine(vec![
+                first.clone(),
+                StringTemplate::static_string("\n\n"),
+                second.clone(),
+            ])
+        };
+        match (self, other) {
+            (Self::Text(first), Self::Text(second)) => Self::Text(join(first, second)),
+            (Self::Chat(chat), Self::Text(text)) => {
+                let mut messages: Vec<ChatMessage<StringTemplate>> = chat.iter().cloned().collect();
+                match messages.pop() {
+                    Some(last) if *last.role() == ChatRole::User => {
+                        messages.push(last.map(|body| join(body, text)));
+                    }
+                    last => {
+                        messages.extend(last);
+                        messages.push(ChatMessage::user(text.clone()));
+                    }
+                }
+                Self::Chat(ChatMessageCollection::for_vector(messages))
+            }
+            (Self::Text(text), Self::Chat(chat)) => {
+                let mut composed = ChatMessageCollection::new().with_user(text.clone());
+                composed.append(chat.clone());
+                Self::Chat(composed)
+            }
+            (Self::Chat(first), Self::Chat(second)) => {
+                let mut composed = first.clone();
+                composed.append(second.clone());
+                Self::Chat(composed)
+            }
+        }
+    }
+
+    /// Returns the example values declared in the templates of the prompt. See `StringTemplate::examples`.
+    ///
+    /// Examples apply to the whole prompt, so an example declared in the system message is also used for the user
+    /// message.
+    pub fn examples(&self) -> BTreeMap<String, String> {
+        let mut examples = BTreeMap::new();
+        let templates: Vec<&StringTemplate> = match self {
+            Self::Chat(chat) => chat.iter().map(|message| message.body()).collect(),
+            Self::Text(text) => vec![text],
+        };
+        for (name, value) in templates.into_iter().flat_map(StringTemplate::examples) {
+            examples.entry(name).or_insert(value);
+        }
+        examples
+    }
+
+    /// Renders the prompt with its example values, so that it can be reviewed without running a chain.
+    ///
+    /// Variables without an example are rendered as `<name>`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use llm_chain::prompt;
+    /// let template = prompt!(
+    ///     "You answer questions about {{ topic }}.{# example topic: astronomy #}",
+    ///     "{{ question }}"
+    /// );
+    /// let preview = template.preview().unwrap();
+    /// assert_eq!(
+    ///     preview.to_string(),
+    ///     "System: You answer questions about astronomy.\nUser: <question>\n"
+    /// );
+    /// ```
+    pub fn preview(&self) -> Result<Data<String>, StringTemplateError> {
+        self.format(&preview_parameters(&self.variables(), &self.examples()))
+    }
+}
+
 impl Data<String> {
     pub fn to_chat(&self) -> ChatMessageCollection<String> {
         match self {
@@ -131,12 +425,13 @@ impl<T> From<ChatMessage<T>> for Data<T> {
 }
 
 use crate::frame::FormatAndExecuteError;
-use crate::prompt::{StringTemplate, StringTemplateError};
+use crate::prompt::{RenderMode, StringTemplate, StringTemplateError};
 use crate::step::Step;
 use crate::traits::Executor;
 use crate::Parameters;
 
 use super::chat::ChatMessageCollection;
+use super::string_template::preview_parameters;
 use super::{ChatMessage, ChatRole};
 
 impl Data<StringTemplate> {
@@ -162,3 +457,66 @@ impl Data<StringTemplate> {
         self.try_map(|x| x.format(parameters))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn composition_reports_conflicting_parameters() {
+        let task = Data::Text(StringTemplate::tera(
+            "{# example format: a table #}Summarize {{text}} as {{format}}.",
+        ));
+        let suffix = Data::Text(StringTemplate::tera(
+            "{# example format: JSON #}Reply in {{format}}.",
+        ));
+        assert_eq!(task.shared_parameters(&suffix), vec!["format"]);
+        let error = task.compose(&suffix).unwrap_err();
+        assert_eq!(
+            error.conflicts,
+            vec![ParameterConflict {
+                name: "format".to_string(),
+                first: "a table".to_string(),
+                second: "JSON".to_string(),
+            }]
+        );
+
+        let composed = task.append(&suffix);
+        assert_eq!(composed.required_parameters(), vec!["text", "format"]);
+
+        let chat = Data::Chat(
+            ChatMessageCollection::new().with_assistant(StringTemplate::static_string("Hi")),
+        );
+        let Data::Chat(composed) = chat.append(&suffix) else {
+            panic!("expected a chat");
+        };
+        let roles: Vec<_> = composed.iter().map(|m| m.role().clone()).collect();
+        assert_eq!(roles, vec![ChatRole::Assistant, ChatRole::User]);
+    }
+
+    #[test]
+    fn validation_reports_missing_and_unused_parameters() {
+        let template = Data::Chat(
+            ChatMessageCollection::new()
+                .with_system(StringTemplate::tera("You are a {{role}}."))
+                .with_user(StringTemplate::tera("{{usre_input}} {{role}}")),
+        );
+        assert_eq!(template.required_parameters(), vec!["role", "usre_input"]);
+
+        let parameters: Parameters = vec![("role", "poet"), ("user_input", "Hi")].into();
+        let error = template.validate(&parameters).unwrap_err();
+        assert_eq!(error.missing, vec!["usre_input"]);
+        assert_eq!(error.unused, vec!["user_input"]);
+        let parameters: Parameters = vec![("role", "poet"), ("usre_input", "Hi")].into();
+        assert!(template.validate(&parameters).is_ok());
+
+        let error = template
+            .validate_declared(&["user_input", "role", "user_input"])
+            .unwrap_err();
+        assert_eq!(error.missing, vec!["usre_input"]);
+        assert_eq!(error.unused, vec!["user_input"]);
+        assert!(template
+            .validate_declared(&["role", "usre_input", "extra"])
+            .is_ok());
+    }
+}
@@ -0,0 +1,646 @@
+//! Prompt templates loaded from a directory at runtime.
+//!
+//! A `PromptRegistry` reads every file of a directory as a prompt template, named after its path relative to the
+//! directory without the extension, so `summarize.txt` becomes `summarize` and `agents/plan.md` becomes
+//! `agents/plan`. Prompts can then be edited without recompiling the application, and reloaded while it runs.
+//!
+//! A file may start with YAML front matter between `---` lines, declaring the parameters the prompt requires, a
+//! description, an optional system message, which makes it a chat prompt, and the template engine:
+//!
+//! ```text
+//! ---
+//! description: Summarizes a document for a given audience.
+//! parameters: [text, audience]
+//! system: You write concise summaries.
+//! ---
+//! Summarize the following text for {{audience}}:
+//!
+//! {{text}}
+//! ```
+//!
+//! # Versions
+//!
+//! A prompt can have several versions, written as `name@version` files such as `summarize@v1.txt` and
+//! `summarize@v2.txt`. Which version is used is decided by the prompt's `VersionPolicy`: the latest version by
+//! default, or, when versions declare a `weight` in their front matter, a traffic split between them. Experiments can
+//! therefore be started, rebalanced and ended by editing files. A policy set in code overrides the files.
+//!
+//! Versions are assigned randomly, or consistently for an assignment key such as a user ID. `RegistryStep` runs the
+//! selected version in a chain and records it in the `prompt_version` parameter, which `Chain::run_with_parameters`
+//! returns with the run's output.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let registry = PromptRegistry::from_dir("prompts")?;
+//! let step = Step::for_prompt_template(registry.template("summarize")?);
+//!
+//! let registry = Arc::new(registry.with_policy("classify", VersionPolicy::Pinned("v3".to_string())));
+//! let chain = Chain::from_steps(vec![ChainStep::custom(
+//!     RegistryStep::new(registry, "summarize").with_assignment_key("user_id"),
+//! )]);
+//! ```
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use thiserror::Error;
+
+use super::{
+    ChatMessageCollection, Data, Prompt, PromptTemplate, StringTemplate, StringTemplateError,
+};
+use crate::hash::stable_hash;
+use crate::step::{CustomStep, CustomStepError, StepOutcome};
+use crate::traits::Executor;
+use crate::Parameters;
+
+/// The parameter `RegistryStep` records the name of the prompt in.
+pub const PROMPT_NAME_KEY: &str = "prompt_name";
+/// The parameter `RegistryStep` records the selected version in. It's empty for unversioned prompts.
+pub const PROMPT_VERSION_KEY: &str = "prompt_version";
+
+/// An error that occurs when loading or using a prompt registry.
+#[derive(Debug, Error)]
+pub enum PromptRegistryError {
+    #[error("Unable to read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Invalid front matter in {path}: {source}")]
+    FrontMatter {
+        path: PathBuf,
+        source: serde_yaml::Error,
+    },
+    #[error("Unsupported template engine {engine:?} in {path}")]
+    UnsupportedEngine { path: PathBuf, engine: String },
+    #[error("Several files define the prompt {0:?}")]
+    DuplicateName(String),
+    #[error("No prompt named {0:?}")]
+    NotFound(String),
+    #[error("The prompt {name:?} has no version {version:?}")]
+    UnknownVersion { name: String, version: String },
+    #[error("The prompt {name:?} requires the parameters {missing:?}")]
+    MissingParameters { name: String, missing: Vec<String> },
+    #[error(transparent)]
+    Template(#[from] StringTemplateError),
+}
+
+/// The front matter of a prompt file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FrontMatter {
+    description: Option<String>,
+    parameters: Vec<String>,
+    system: Option<String>,
+    engine: Option<String>,
+    weight: Option<u32>,
+}
+
+/// Splits `contents` into its front matter, if it has any, and its body.
+fn split_front_matter(contents: &str) -> (Option<&str>, &str) {
+    let Some(rest) = contents
+        .strip_prefix("---\n")
+        .or_else(|| contents.strip_prefix("---\r\n"))
+    else {
+        return (None, contents);
+    };
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == "---" {
+            return (Some(&rest[..offset]), &rest[offset + line.len()..]);
+        }
+        offset += line.len();
+    }
+    (None, contents)
+}
+
+/// A prompt template loaded by a `PromptRegistry`.
+#[derive(Debug, Clone)]
+pub struct RegisteredPrompt {
+    name: String,
+    version: Option<String>,
+    weight: Option<u32>,
+    description: Option<String>,
+    parameters: Vec<String>,
+    template: PromptTemplate,
+    path: PathBuf,
+}
+
+impl RegisteredPrompt {
+    /// Parses the prompt file at `path`, registered as `name` and `version`.
+    fn parse(
+        name: String,
+        version: Option<String>,
+        path: PathBuf,
+        contents: &str,
+    ) -> Result<Self, PromptRegistryError> {
+        let (front_matter, body) = split_front_matter(contents);
+        let front_matter: FrontMatter = match front_matter {
+            Some(yaml) if !yaml.trim().is_empty() => {
+                serde_yaml::from_str(yaml).map_err(|source| PromptRegistryError::FrontMatter {
+                    path: path.clone(),
+                    source,
+                })?
+            }
+            _ => FrontMatter::default(),
+        };
+        let engine: fn(String) -> StringTemplate = match front_matter.engine.as_deref() {
+            None | Some("tera") => StringTemplate::tera,
+            #[cfg(feature = "handlebars")]
+            Some("handlebars") => StringTemplate::handlebars,
+            Some(engine) => {
+                return Err(PromptRegistryError::UnsupportedEngine {
+                    path,
+                    engine: engine.to_string(),
+                })
+            }
+        };
+        let template = match front_matter.system {
+            None => Data::Text(engine(body.to_string())),
+            Some(system) => Data::Chat(
+                ChatMessageCollection::new()
+                    .with_system(engine(system))
+                    .with_user(engine(body.to_string())),
+            ),
+        };
+        Ok(Self {
+            name,
+            version,
+            weight: front_matter.weight,
+            description: front_matter.description,
+            parameters: front_matter.parameters,
+            template,
+            path,
+        })
+    }
+
+    /// Returns the name of the prompt.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the version of the prompt, or `None` if its file isn't versioned.
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    /// Returns the share of traffic declared in the front matter, if any.
+    pub fn weight(&self) -> Option<u32> {
+        self.weight
+    }
+
+    /// Returns the description declared in the front matter, if any.
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// Returns the parameters the front matter declares as required.
+    pub fn parameters(&self) -> &[String] {
+        &self.parameters
+    }
+
+    /// Returns the template of the prompt.
+    pub fn template(&self) -> &PromptTemplate {
+        &self.template
+    }
+
+    /// Returns the path of the file the prompt was loaded from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the required parameters missing from `parameters`.
+    pub fn missing_parameters(&self, parameters: &Parameters) -> Vec<String> {
+        self.parameters
+            .iter()
+            .filter(|name| parameters.get(name).is_none())
+            .cloned()
+            .collect()
+    }
+
+    /// Formats the prompt with `parameters`, after checking that every required parameter is set.
+    pub fn format(&self, parameters: &Parameters) -> Result<Prompt, PromptRegistryError> {
+        let missing = self.missing_parameters(parameters);
+        if !missing.is_empty() {
+            return Err(PromptRegistryError::MissingParameters {
+                name: self.name.clone(),
+                missing,
+            });
+        }
+        Ok(self.template.format(parameters)?)
+    }
+}
+
+/// Returns the sort key of a version: its dot-separated parts, compared as numbers where they are numbers, ignoring
+/// a leading `v`. This sorts `v2` before `v10`.
+fn version_order(version: Option<&str>) -> Option<Vec<(bool, u64, String)>> {
+    version.map(|version| {
+        version
+            .split('.')
+            .map(|part| {
+                let part = part.strip_prefix('v').unwrap_or(part);
+                match part.parse::<u64>() {
+                    Ok(number) => (false, number, String::new()),
+                    Err(_) => (true, 0, part.to_string()),
+                }
+            })
+            .collect()
+    })
+}
+
+/// How a `PromptRegistry` picks the version of a prompt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VersionPolicy {
+    /// Uses the latest version, comparing versions as numbers where they are numbers.
+    Latest,
+    /// Uses the given version.
+    Pinned(String),
+    /// Splits traffic between versions in proportion to their weights.
+    Split(Vec<(String, u32)>),
+}
+
+/// A set of prompt templates loaded from a directory, retrievable by name.
+#[derive(Debug, Clone)]
+pub struct PromptRegistry {
+    root: PathBuf,
+    prompts: BTreeMap<String, Vec<RegisteredPrompt>>,
+    policies: BTreeMap<String, VersionPolicy>,
+}
+
+impl PromptRegistry {
+    /// Loads every file under `root`, including subdirectories. Hidden files and directories are skipped.
+    pub fn from_dir<P: Into<PathBuf>>(root: P) -> Result<Self, PromptRegistryError> {
+        let mut registry = Self {
+            root: root.into(),
+            prompts: BTreeMap::new(),
+            policies: BTreeMap::new(),
+        };
+        registry.reload()?;
+        Ok(registry)
+    }
+
+    /// Loads the directory again, picking up edited, added and removed prompts. Policies set in code are kept. If
+    /// loading fails, the registry is left unchanged.
+    pub fn reload(&mut self) -> Result<(), PromptRegistryError> {
+        let io = |path: &Path, source| PromptRegistryError::Io {
+            path: path.to_path_buf(),
+            source,
+        };
+        let mut prompts: BTreeMap<String, Vec<RegisteredPrompt>> = BTreeMap::new();
+        let mut directories = vec![self.root.clone()];
+        while let Some(directory) = directories.pop() {
+            let entries = std::fs::read_dir(&directory).map_err(|source| io(&directory, source))?;
+            for entry in entries {
+                let entry = entry.map_err(|source| io(&directory, source))?;
+                let path = entry.path();
+                if entry.file_name().to_string_lossy().starts_with('.') {
+                    continue;
+                }
+                let file_type = entry.file_type().map_err(|source| io(&path, source))?;
+                if file_type.is_dir() {
+                    directories.push(path);
+                    continue;
+                }
+                let Ok(relative) =
+                    path.with_extension("")
+                        .strip_prefix(&self.root)
+                        .map(|relative| {
+                            relative
+                                .components()
+                                .map(|c| c.as_os_str().to_string_lossy())
+                                .collect::<Vec<_>>()
+                                .join("/")
+                        })
+                else {
+                    continue;
+                };
+                let (name, version) = match relative.rsplit_once('@') {
+                    Some((name, version)) => (name.to_string(), Some(version.to_string())),
+                    None => (relative.clone(), None),
+                };
+                let contents =
+                    std::fs::read_to_string(&path).map_err(|source| io(&path, source))?;
+                let prompt = RegisteredPrompt::parse(name.clone(), version, path, &contents)?;
+                let versions = prompts.entry(name).or_default();
+                if versions.iter().any(|p| p.version == prompt.version) {
+                    return Err(PromptRegistryError::DuplicateName(relative));
+                }
+                versions.push(prompt);
+            }
+        }
+        for versions in prompts.values_mut() {
+            versions.sort_by_key(|prompt| version_order(prompt.version()));
+        }
+        self.prompts = prompts;
+        Ok(())
+    }
+
+    /// Sets how the version of the prompt named `name` is picked, overriding the weights of its files.
+    pub fn with_policy<N: Into<String>>(mut self, name: N, policy: VersionPolicy) -> Self {
+        self.set_policy(name, policy);
+        self
+    }
+
+    /// Sets how the version of the prompt named `name` is picked, overriding the weights of its files.
+    pub fn set_policy<N: Into<String>>(&mut self, name: N, policy: VersionPolicy) {
+        self.policies.insert(name.into(), policy);
+    }
+
+    /// Returns the policy picking the version of the prompt named `name`: the one set in code if there is one, a
+    /// split between the versions declaring a weight if any does, and `Latest` otherwise.
+    pub fn policy(&self, name: &str) -> VersionPolicy {
+        if let Some(policy) = self.policies.get(name) {
+            return policy.clone();
+        }
+        let weights: Vec<(String, u32)> = self
+            .versions(name)
+            .iter()
+            .filter_map(|prompt| Some((prompt.version.clone()?, prompt.weight?)))
+            .collect();
+        if weights.is_empty() {
+            VersionPolicy::Latest
+        } else {
+            VersionPolicy::Split(weights)
+        }
+    }
+
+    /// Returns the latest version of the prompt named `name`, if there is one.
+    pub fn get(&self, name: &str) -> Option<&RegisteredPrompt> {
+        self.versions(name).last()
+    }
+
+    /// Returns the given version of the prompt named `name`, if there is one.
+    pub fn get_version(&self, name: &str, version: &str) -> Option<&RegisteredPrompt> {
+        self.versions(name)
+            .iter()
+            .find(|prompt| prompt.version() == Some(version))
+    }
+
+    /// Returns the versions of the prompt named `name`, oldest first.
+    pub fn versions(&self, name: &str) -> &[RegisteredPrompt] {
+        self.prompts.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    /// Picks a version of the prompt named `name` with its policy.
+    ///
+    /// Traffic splits assign versions consistently for the same `assignment_key`, such as a user ID, and randomly
+    /// without one.
+    pub fn select(
+        &self,
+        name: &str,
+        assignment_key: Option<&str>,
+    ) -> Result<&RegisteredPrompt, PromptRegistryError> {
+        let not_found = || PromptRegistryError::NotFound(name.to_string());
+        let version = |version: &str| {
+            self.get_version(name, version)
+                .ok_or_else(|| PromptRegistryError::UnknownVersion {
+                    name: name.to_string(),
+                    version: version.to_string(),
+                })
+        };
+        match self.policy(name) {
+            VersionPolicy::Latest => self.get(name).ok_or_else(not_found),
+            VersionPolicy::Pinned(pinned) => version(&pinned),
+            VersionPolicy::Split(weights) => {
+                let total: u64 = weights.iter().map(|(_, weight)| u64::from(*weight)).sum();
+                if total == 0 {
+                    return self.get(name).ok_or_else(not_found);
+                }
+                // The hash is stable across releases, so assignments don't change when the application is rebuilt.
+                let draw = match assignment_key {
+                    Some(key) => stable_hash(&[name, key]),
+                    None => uuid::Uuid::new_v4().as_u128(),
+                };
+                let mut point = (draw % u128::from(total)) as u64;
+                for (pinned, weight) in &weights {
+                    if point < u64::from(*weight) {
+                        return version(pinned);
+                    }
+                    point -= u64::from(*weight);
+                }
+                unreachable!("the draw is below the total weight")
+            }
+        }
+    }
+
+    /// Returns the template of the prompt named `name`, in the version its policy picks.
+    pub fn template(&self, name: &str) -> Result<PromptTemplate, PromptRegistryError> {
+        self.select(name, None)
+            .map(|prompt| prompt.template.clone())
+    }
+
+    /// Formats the prompt named `name` with `parameters`, in the version its policy picks, after checking that
+    /// every required parameter is set.
+    pub fn format(
+        &self,
+        name: &str,
+        parameters: &Parameters,
+    ) -> Result<Prompt, PromptRegistryError> {
+        self.select(name, None)?.format(parameters)
+    }
+
+    /// Returns the names of the prompts, in alphabetical order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.prompts.keys().map(String::as_str)
+    }
+
+    /// Returns the number of prompts in the registry, counting all the versions of a prompt once.
+    pub fn len(&self) -> usize {
+        self.prompts.len()
+    }
+
+    /// Returns `true` if the registry contains no prompts.
+    pub fn is_empty(&self) -> bool {
+        self.prompts.is_empty()
+    }
+}
+
+/// A custom step running a prompt of a `PromptRegistry`, in the version its policy picks for the run.
+///
+/// The name of the prompt and the selected version are recorded in the `prompt_name` and `prompt_version`
+/// parameters, which are passed on to the following steps and returned by `Chain::run_with_parameters`.
+pub struct RegistryStep {
+    registry: Arc<PromptRegistry>,
+    name: String,
+    assignment_key: Option<String>,
+}
+
+impl RegistryStep {
+    /// Creates a step running the prompt named `name` of `registry`.
+    pub fn new<N: Into<String>>(registry: Arc<PromptRegistry>, name: N) -> Self {
+        Self {
+            registry,
+            name: name.into(),
+            assignment_key: None,
+        }
+    }
+
+    /// Assigns versions consistently for the value of the parameter `key`, such as a user ID, rather than randomly.
+    pub fn with_assignment_key<K: Into<String>>(mut self, key: K) -> Self {
+        self.assignment_key = Some(key.into());
+        self
+    }
+}
+
+#[async_trait]
+impl<E> CustomStep<E> for RegistryStep
+where
+    E: Executor + Sync,
+    E::Error: Send + Sync + 'static,
+    E::Output: Send,
+    E::PerInvocationOptions: Sync,
+{
+    async fn run(
+        &self,
+        parameters: &Parameters,
+        executor: &E,
+    ) -> Result<StepOutcome<E::Output>, CustomStepError> {
+        let key = self
+            .assignment_key
+            .as_ref()
+            .and_then(|key| parameters.get(key));
+        let prompt = self.registry.select(&self.name, key.as_deref())?;
+        let formatted = prompt.format(parameters)?;
+        let output = executor
+            .execute(None, &formatted, None)
+            .await
+            .map_err(Box::new)?;
+        Ok(StepOutcome {
+            parameters: parameters
+                .with(PROMPT_NAME_KEY, self.name.as_str())
+                .with(PROMPT_VERSION_KEY, prompt.version().unwrap_or_default()),
+            output: Some(output),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        PromptRegistry, PromptRegistryError, RegistryStep, VersionPolicy, PROMPT_VERSION_KEY,
+    };
+    use crate::agents::mock::{MockExecutor, MockOutput};
+    use crate::chains::sequential::{Chain, ChainStep};
+    use crate::{parameters, Parameters};
+    use futures::executor::block_on;
+    use std::fs;
+    use std::sync::Arc;
+
+    #[test]
+    fn loads_prompts_with_front_matter() {
+        let root = std::env::temp_dir().join(format!("llm-chain-{}", uuid::Uuid::new_v4()));
+        for (path, content) in [
+            ("greet.txt", "Hello {{name}}!"),
+            (
+                "agents/summarize.md",
+                "---\ndescription: Summarizes a text.\nparameters: [text]\nsystem: Be brief.\n---\nSummarize: {{text}}",
+            ),
+            (".drafts/ignored.txt", "ignored"),
+        ] {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+
+        let registry = PromptRegistry::from_dir(&root).unwrap();
+        assert_eq!(
+            registry.names().collect::<Vec<_>>(),
+            ["agents/summarize", "greet"]
+        );
+        let summarize = registry.get("agents/summarize").unwrap();
+        assert_eq!(summarize.description(), Some("Summarizes a text."));
+        assert_eq!(
+            registry
+                .format(
+                    "agents/summarize",
+                    &Parameters::new_with_text("A long text")
+                )
+                .unwrap()
+                .to_string(),
+            "System: Be brief.\nUser: Summarize: A long text\n"
+        );
+        assert!(matches!(
+            registry.format("agents/summarize", &parameters!()),
+            Err(PromptRegistryError::MissingParameters { missing, .. }) if missing == ["text"]
+        ));
+        assert_eq!(
+            registry
+                .format("greet", &parameters!("name" => "Ada"))
+                .unwrap()
+                .to_string(),
+            "Hello Ada!"
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn selects_versions_by_policy() {
+        let root = std::env::temp_dir().join(format!("llm-chain-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&root).unwrap();
+        for (path, content) in [
+            ("answer@v2.txt", "v2"),
+            ("answer@v10.txt", "v10"),
+            ("answer@v9.txt", "v9"),
+            ("greet@a.txt", "---\nweight: 1\n---\nHi"),
+            ("greet@b.txt", "---\nweight: 3\n---\nHello"),
+        ] {
+            fs::write(root.join(path), content).unwrap();
+        }
+        let registry = PromptRegistry::from_dir(&root).unwrap();
+        assert_eq!(registry.names().collect::<Vec<_>>(), ["answer", "greet"]);
+
+        let latest = registry.select("answer", None).unwrap();
+        assert_eq!(latest.version(), Some("v10"));
+        let pinned = registry
+            .clone()
+            .with_policy("answer", VersionPolicy::Pinned("v9".to_string()));
+        assert_eq!(pinned.select("answer", None).unwrap().version(), Some("v9"));
+        assert!(matches!(
+            registry
+                .clone()
+                .with_policy("answer", VersionPolicy::Pinned("v1".to_string()))
+                .select("answer", None),
+            Err(PromptRegistryError::UnknownVersion { .. })
+        ));
+
+        assert_eq!(
+            registry.policy("greet"),
+            VersionPolicy::Split(vec![("a".to_string(), 1), ("b".to_string(), 3)])
+        );
+        let assigned = |user: &str| {
+            registry
+                .select("greet", Some(user))
+                .unwrap()
+                .version()
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(assigned("user-1"), assigned("user-1"));
+        let b = (0..400)
+            .filter(|i| assigned(&format!("user-{}", i)) == "b")
+            .count();
+        assert!((240..360).contains(&b), "{} of 400 users got b", b);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn runs_report_the_selected_version() {
+        let root = std::env::temp_dir().join(format!("llm-chain-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("greet@v1.txt"), "Hi {{name}}").unwrap();
+        fs::write(root.join("greet@v2.txt"), "Hello {{name}}").unwrap();
+        let registry = Arc::new(PromptRegistry::from_dir(&root).unwrap());
+        let chain = Chain::from_steps(vec![ChainStep::custom(RegistryStep::new(
+            registry, "greet",
+        ))]);
+        let executor = MockExecutor::new(vec![MockOutput::text("Hello Ada")]);
+        let (_, parameters) =
+            block_on(chain.run_with_parameters(parameters!("name" => "Ada"), &executor)).unwrap();
+        assert_eq!(parameters.get(PROMPT_VERSION_KEY).as_deref(), Some("v2"));
+        assert_eq!(executor.prompts.lock().unwrap()[0].to_string(), "Hello Ada");
+        fs::remove_dir_all(root).unwrap();
+    }
+}
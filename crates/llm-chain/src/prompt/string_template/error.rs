@@ -4,6 +4,9 @@ use thiserror::Error;
 pub enum StringTemplateErrorImpl {
     #[error("Tera error: {0}")]
     Tera(String),
+    #[cfg(feature = "handlebars")]
+    #[error("Handlebars error: {0}")]
+    Handlebars(String),
     #[error("Unable to load file: {0}")]
     UnableToLoadFile(String),
     #[error("Unable to parse template: {0}")]
@@ -22,6 +25,13 @@ impl From<tera::Error> for StringTemplateErrorImpl {
     }
 }
 
+#[cfg(feature = "handlebars")]
+impl From<handlebars::RenderError> for StringTemplateErrorImpl {
+    fn from(error: handlebars::RenderError) -> Self {
+        StringTemplateErrorImpl::Handlebars(error.to_string())
+    }
+}
+
 #[derive(Error, Debug, Clone)]
 #[error(transparent)]
 /// An error that can occur when formatting a prompt template.
@@ -0,0 +1,88 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+
+use serde_json::Value;
+
+/// A filter for tera prompt templates. It receives the filtered value and the filter's named arguments, and returns
+/// the filtered value or an error message.
+pub type TemplateFilter =
+    Arc<dyn Fn(&Value, &HashMap<String, Value>) -> Result<Value, String> + Send + Sync>;
+
+/// A function for tera prompt templates. It receives the function's named arguments, and returns a value or an
+/// error message.
+pub type TemplateFunction =
+    Arc<dyn Fn(&HashMap<String, Value>) -> Result<Value, String> + Send + Sync>;
+
+static FILTERS: RwLock<BTreeMap<String, TemplateFilter>> = RwLock::new(BTreeMap::new());
+static FUNCTIONS: RwLock<BTreeMap<String, TemplateFunction>> = RwLock::new(BTreeMap::new());
+
+/// Registers a filter named `name`, available in all tera prompt templates as `{{ value | name(arg=...) }}`.
+/// Registering a filter under an existing name replaces it, including tera's built-in filters.
+///
+/// # Examples
+/// ```
+/// use llm_chain::prompt::{register_filter, StringTemplate};
+/// use llm_chain::Parameters;
+/// register_filter("bullet_list", |value, _args| {
+///     let items = value.as_array().ok_or("bullet_list expects an array")?;
+///     let lines: Vec<String> = items
+///         .iter()
+///         .map(|item| format!("- {}", item.as_str().unwrap_or_default()))
+///         .collect();
+///     Ok(lines.join("\n").into())
+/// });
+/// let template = StringTemplate::tera("Rules:\n{{ rules | bullet_list }}");
+/// let parameters = Parameters::new().with_value("rules", serde_json::json!(["Be brief", "Cite sources"]));
+/// assert_eq!(template.format(&parameters).unwrap(), "Rules:\n- Be brief\n- Cite sources");
+/// ```
+pub fn register_filter<N, F>(name: N, filter: F)
+where
+    N: Into<String>,
+    F: Fn(&Value, &HashMap<String, Value>) -> Result<Value, String> + Send + Sync + 'static,
+{
+    FILTERS
+        .write()
+        .expect("template filter registry lock poisoned")
+        .insert(name.into(), Arc::new(filter));
+}
+
+/// Registers a function named `name`, available in all tera prompt templates as `{{ name(arg=...) }}`.
+/// Registering a function under an existing name replaces it, including tera's built-in functions.
+///
+/// # Examples
+/// ```
+/// use llm_chain::prompt::{register_function, StringTemplate};
+/// use llm_chain::Parameters;
+/// register_function("separator", |args| {
+///     let width = args.get("width").and_then(|w| w.as_u64()).unwrap_or(3);
+///     Ok("-".repeat(width as usize).into())
+/// });
+/// let template = StringTemplate::tera("{{ separator(width=5) }}");
+/// assert_eq!(template.format(&Parameters::new()).unwrap(), "-----");
+/// ```
+pub fn register_function<N, F>(name: N, function: F)
+where
+    N: Into<String>,
+    F: Fn(&HashMap<String, Value>) -> Result<Value, String> + Send + Sync + 'static,
+{
+    FUNCTIONS
+        .write()
+        .expect("template function registry lock poisoned")
+        .insert(name.into(), Arc::new(function));
+}
+
+/// Returns every registered filter, by name.
+pub(crate) fn filters() -> BTreeMap<String, TemplateFilter> {
+    FILTERS
+        .read()
+        .expect("template filter registry lock poisoned")
+        .clone()
+}
+
+/// Returns every registered function, by name.
+pub(crate) fn functions() -> BTreeMap<String, TemplateFunction> {
+    FUNCTIONS
+        .read()
+        .expect("template function registry lock poisoned")
+        .clone()
+}
@@ -1,15 +1,60 @@
+mod extensions;
+mod partials;
 mod tera;
+mod warnings;
+
+#[cfg(feature = "handlebars")]
+mod handlebars;
 
 mod error;
 pub use error::StringTemplateError;
 use error::StringTemplateErrorImpl;
+pub use extensions::{register_filter, register_function, TemplateFilter, TemplateFunction};
+pub use partials::{register_partial, remove_partial};
 use std::fmt;
+pub use warnings::{
+    clear_warning_handler, set_warning_handler, with_warning_handler, TemplateWarning,
+    TemplateWarningHandler,
+};
 mod io;
 
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
 use crate::Parameters;
 
+/// Returns parameters for previewing templates using `variables`: the example values where there are some, and a
+/// `<name>` placeholder for the other variables.
+pub(crate) fn preview_parameters(
+    variables: &[String],
+    examples: &BTreeMap<String, String>,
+) -> Parameters {
+    variables
+        .iter()
+        .fold(Parameters::new(), |parameters, name| {
+            let value = examples
+                .get(name)
+                .cloned()
+                .unwrap_or_else(|| format!("<{}>", name));
+            parameters.with(name.as_str(), value)
+        })
+}
+
+/// How a template handles the parameters it uses that weren't given.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RenderMode {
+    /// Formatting fails. This is the default.
+    #[default]
+    Strict,
+    /// The missing parameters are rendered as empty strings, and reported as a
+    /// `TemplateWarning::MissingParameters` warning. This suits templates with many optional slots.
+    ///
+    /// Only the parameters themselves are filled in: a template reading a field of a missing parameter, such as
+    /// `{{ user.name }}`, still fails.
+    Lenient,
+}
+
 /// A template for a prompt. This is a string that can be formatted with a set of parameters.
 ///
 /// # Examples
@@ -72,7 +117,31 @@ impl StringTemplate {
         StringTemplateImpl::tera(template.into()).into()
     }
 
+    /// Creates a prompt template that uses the Handlebars templating engine.
+    ///
+    /// Handlebars templates are an alternative to tera for prompts containing literal braces, which can be escaped
+    /// with a backslash: `\{{` renders as `{{`. Output isn't HTML-escaped, and using a variable that isn't set is an
+    /// error, as with tera. This is only available if the `handlebars` feature is enabled.
+    /// # Examples
+    ///
+    /// ```rust
+    /// use llm_chain::prompt::StringTemplate;
+    /// use llm_chain::Parameters;
+    /// let template = StringTemplate::handlebars(r#"Reply with {"greeting": "Hello {{name}}!"} as \{{json}}."#);
+    /// let parameters: Parameters = vec![("name", "World")].into();
+    /// assert_eq!(
+    ///     template.format(&parameters).unwrap(),
+    ///     r#"Reply with {"greeting": "Hello World!"} as {{json}}."#
+    /// );
+    /// assert_eq!(template.variables(), vec!["name"]);
+    /// ```
+    #[cfg(feature = "handlebars")]
+    pub fn handlebars<K: Into<String>>(template: K) -> StringTemplate {
+        StringTemplateImpl::handlebars(template.into()).into()
+    }
+
     /// Creates a prompt template from a file. The file should be a text file containing the template as a tera template.
+    /// It may declare example values for its variables, see `examples`.
     /// # Examples
     /// ```no_run
     /// use llm_chain::prompt::StringTemplate;
@@ -98,6 +167,72 @@ impl StringTemplate {
         let res: Vec<StringTemplateImpl> = parts.into_iter().map(|p| p.0).collect();
         StringTemplateImpl::combine(res).into()
     }
+
+    /// Sets how the template handles missing parameters. Templates are `RenderMode::Strict` by default.
+    pub fn with_render_mode(self, mode: RenderMode) -> StringTemplate {
+        let template = match self.0 {
+            StringTemplateImpl::Lenient(template) => *template,
+            template => template,
+        };
+        match mode {
+            RenderMode::Strict => template.into(),
+            RenderMode::Lenient => StringTemplateImpl::Lenient(Box::new(template)).into(),
+        }
+    }
+
+    /// Returns how the template handles missing parameters.
+    pub fn render_mode(&self) -> RenderMode {
+        match self.0 {
+            StringTemplateImpl::Lenient(_) => RenderMode::Lenient,
+            _ => RenderMode::Strict,
+        }
+    }
+
+    /// Returns the names of the parameters the template uses, in order of first use.
+    ///
+    /// Variables introduced by the template itself, such as loop variables, are not included.
+    /// # Examples
+    /// ```
+    /// use llm_chain::prompt::StringTemplate;
+    /// let template = StringTemplate::tera("{% for d in documents %}{{ d.title }}{% endfor %} {{ question | upper }}");
+    /// assert_eq!(template.variables(), vec!["documents", "question"]);
+    /// ```
+    pub fn variables(&self) -> Vec<String> {
+        let mut variables = Vec::new();
+        self.0.collect_variables(&mut variables);
+        variables
+    }
+
+    /// Returns the example values declared in the template for its variables.
+    ///
+    /// Examples are written as tera comments of the form `{# example name: value #}`, or in Handlebars templates as
+    /// `{{!-- example name: value --}}`, which aren't rendered, so prompt files can carry realistic values for
+    /// reviewers without changing the prompt. If a variable has several
+    /// examples, the first one is used.
+    /// # Examples
+    /// ```
+    /// use llm_chain::prompt::StringTemplate;
+    /// let template = StringTemplate::tera("{# example name: Ada #}Hello {{ name }}!");
+    /// assert_eq!(template.examples()["name"], "Ada");
+    /// ```
+    pub fn examples(&self) -> BTreeMap<String, String> {
+        let mut examples = BTreeMap::new();
+        self.0.collect_examples(&mut examples);
+        examples
+    }
+
+    /// Renders the template with its example values, so that it can be reviewed without running a chain.
+    ///
+    /// Variables without an example are rendered as `<name>`.
+    /// # Examples
+    /// ```
+    /// use llm_chain::prompt::StringTemplate;
+    /// let template = StringTemplate::tera("{# example name: Ada #}Hello {{ name }}, {{ greeting }}!");
+    /// assert_eq!(template.preview().unwrap(), "Hello Ada, <greeting>!");
+    /// ```
+    pub fn preview(&self) -> Result<String, error::StringTemplateError> {
+        self.format(&preview_parameters(&self.variables(), &self.examples()))
+    }
 }
 
 impl fmt::Display for StringTemplate {
@@ -111,7 +246,10 @@ impl fmt::Display for StringTemplate {
 enum StringTemplateImpl {
     Static(String),
     Tera(String),
+    #[cfg(feature = "handlebars")]
+    Handlebars(String),
     Combined(Vec<StringTemplateImpl>),
+    Lenient(Box<StringTemplateImpl>),
 }
 
 impl StringTemplateImpl {
@@ -119,6 +257,10 @@ impl StringTemplateImpl {
         match self {
             Self::Static(template) => Ok(template.clone()),
             Self::Tera(template) => tera::render(template, parameters).map_err(|e| e.into()),
+            #[cfg(feature = "handlebars")]
+            Self::Handlebars(template) => {
+                handlebars::render(template, parameters).map_err(|e| e.into())
+            }
             Self::Combined(templates) => {
                 let mut result = String::new();
                 for template in templates {
@@ -127,6 +269,19 @@ impl StringTemplateImpl {
                 }
                 Ok(result)
             }
+            Self::Lenient(template) => {
+                let mut missing = Vec::new();
+                template.collect_variables(&mut missing);
+                missing.retain(|name| parameters.get(name).is_none());
+                if missing.is_empty() {
+                    return template.format(parameters);
+                }
+                let filled = missing.iter().fold(parameters.clone(), |filled, name| {
+                    filled.with(name.as_str(), "")
+                });
+                warnings::warn(warnings::TemplateWarning::MissingParameters(missing));
+                template.format(&filled)
+            }
         }
     }
 
@@ -138,9 +293,64 @@ impl StringTemplateImpl {
         Self::Tera(template)
     }
 
+    #[cfg(feature = "handlebars")]
+    pub fn handlebars(template: String) -> Self {
+        Self::Handlebars(template)
+    }
+
     pub fn combine(templates: Vec<Self>) -> Self {
         Self::Combined(templates)
     }
+
+    fn collect_variables(&self, variables: &mut Vec<String>) {
+        match self {
+            Self::Static(_) => {}
+            Self::Tera(template) => {
+                for name in tera::variables(template) {
+                    if !variables.contains(&name) {
+                        variables.push(name);
+                    }
+                }
+            }
+            #[cfg(feature = "handlebars")]
+            Self::Handlebars(template) => {
+                for name in handlebars::variables(template) {
+                    if !variables.contains(&name) {
+                        variables.push(name);
+                    }
+                }
+            }
+            Self::Combined(templates) => {
+                for template in templates {
+                    template.collect_variables(variables);
+                }
+            }
+            Self::Lenient(template) => template.collect_variables(variables),
+        }
+    }
+
+    fn collect_examples(&self, examples: &mut BTreeMap<String, String>) {
+        match self {
+            Self::Static(_) => {}
+            Self::Tera(template) => {
+                for (name, value) in tera::examples(template) {
+                    examples.entry(name).or_insert(value);
+                }
+            }
+            #[cfg(feature = "handlebars")]
+            Self::Handlebars(template) => {
+                for (name, value) in handlebars::examples(template) {
+                    examples.entry(name).or_insert(value);
+                }
+            }
+            Self::Combined(templates) => {
+                for template in templates {
+                    template.collect_examples(examples);
+                }
+            }
+            Self::Lenient(template) => template.collect_examples(examples),
+        }
+    }
 }
 
 impl fmt::Display for StringTemplateImpl {
@@ -148,12 +358,15 @@ impl fmt::Display for StringTemplateImpl {
         match self {
             Self::Static(s) => write!(f, "{}", s),
             Self::Tera(template) => write!(f, "{}", template),
+            #[cfg(feature = "handlebars")]
+            Self::Handlebars(template) => write!(f, "{}", template),
             Self::Combined(templates) => {
                 for template in templates {
                     write!(f, "{}", template)?;
                 }
                 Ok(())
             }
+            Self::Lenient(template) => write!(f, "{}", template),
         }
     }
 }
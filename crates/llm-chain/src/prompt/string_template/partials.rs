@@ -0,0 +1,145 @@
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+use tera::Tera;
+
+use super::error::StringTemplateErrorImpl;
+use super::StringTemplateError;
+
+/// The registered partials, along with a tera instance holding the ones that are tera templates, parsed once.
+struct Registry {
+    templates: BTreeMap<String, String>,
+    tera: Tera,
+}
+
+lazy_static! {
+    static ref PARTIALS: RwLock<Registry> = RwLock::new(Registry {
+        templates: BTreeMap::new(),
+        tera: empty_tera(),
+    });
+}
+
+/// Registers a partial template named `name`, which prompt templates can include: tera templates with
+/// `{% include "name" %}` and Handlebars templates with `{{> name}}`.
+///
+/// Partials hold prompt fragments shared by many steps, such as a system preamble or output format instructions.
+/// A partial is rendered by the engine of the template including it, with the same parameters. Registering a partial
+/// under an existing name replaces it.
+///
+/// The partial is parsed when it is registered, rather than each time a template is rendered. Fails if it isn't a
+/// valid template, in which case the registered partials are left unchanged. A partial extending another one must be
+/// registered after it.
+///
+/// # Examples
+/// ```
+/// use llm_chain::prompt::{register_partial, StringTemplate};
+/// use llm_chain::Parameters;
+/// register_partial("answer_format", "Answer in {{language}}, in one sentence.").unwrap();
+/// let template = StringTemplate::tera("{{question}}\n{% include \"answer_format\" %}");
+/// let parameters: Parameters = vec![("question", "Why is the sky blue?"), ("language", "French")].into();
+/// assert_eq!(
+///     template.format(&parameters).unwrap(),
+///     "Why is the sky blue?\nAnswer in French, in one sentence."
+/// );
+/// assert_eq!(template.variables(), vec!["question", "language"]);
+/// assert!(register_partial("broken", "{% if language %}").is_err());
+/// ```
+pub fn register_partial<N: Into<String>, T: Into<String>>(
+    name: N,
+    template: T,
+) -> Result<(), StringTemplateError> {
+    let (name, template) = (name.into(), template.into());
+    let mut registry = PARTIALS.write().expect("partial registry lock poisoned");
+    let mut tera = registry.tera.clone();
+    match tera.add_raw_template(&name, &template) {
+        Ok(()) => registry.tera = tera,
+        // Partials for Handlebars templates needn't be tera templates.
+        #[cfg(feature = "handlebars")]
+        Err(_) if handlebars::Template::compile(&template).is_ok() => {
+            if registry.tera.get_template_names().any(|n| n == name) {
+                let mut templates = registry.templates.clone();
+                templates.remove(&name);
+                registry.tera = tera_for(&templates);
+            }
+        }
+        Err(error) => return Err(parse_error(error).into()),
+    }
+    registry.templates.insert(name, template);
+    Ok(())
+}
+
+/// Removes the partial template named `name`, returning its template if it was registered.
+pub fn remove_partial(name: &str) -> Option<String> {
+    let mut registry = PARTIALS.write().expect("partial registry lock poisoned");
+    let template = registry.templates.remove(name)?;
+    registry.tera = tera_for(&registry.templates);
+    Some(template)
+}
+
+/// Returns the template of the partial named `name`.
+pub(crate) fn get(name: &str) -> Option<String> {
+    PARTIALS
+        .read()
+        .expect("partial registry lock poisoned")
+        .templates
+        .get(name)
+        .cloned()
+}
+
+/// Returns every registered partial, by name.
+#[cfg(feature = "handlebars")]
+pub(crate) fn all() -> BTreeMap<String, String> {
+    PARTIALS
+        .read()
+        .expect("partial registry lock poisoned")
+        .templates
+        .clone()
+}
+
+/// Returns a tera instance holding the partials that are tera templates, to add the rendered template to.
+pub(crate) fn tera() -> Tera {
+    PARTIALS
+        .read()
+        .expect("partial registry lock poisoned")
+        .tera
+        .clone()
+}
+
+fn empty_tera() -> Tera {
+    let mut tera = Tera::default();
+    tera.autoescape_on(vec![]);
+    tera
+}
+
+/// Returns a tera instance holding the `templates` that are tera templates. Templates that can't be added, such as
+/// ones extending a partial that was removed, are left out.
+fn tera_for(templates: &BTreeMap<String, String>) -> Tera {
+    let mut tera = empty_tera();
+    let all = templates
+        .iter()
+        .map(|(name, template)| (name.as_str(), template.as_str()));
+    if tera.add_raw_templates(all).is_ok() {
+        return tera;
+    }
+    let mut tera = empty_tera();
+    for (name, template) in templates {
+        let mut with_template = tera.clone();
+        if with_template.add_raw_template(name, template).is_ok() {
+            tera = with_template;
+        }
+    }
+    tera
+}
+
+/// Returns the error for a partial tera couldn't add, with the reasons tera gives.
+fn parse_error(error: tera::Error) -> StringTemplateErrorImpl {
+    let mut message = error.to_string();
+    let mut source = std::error::Error::source(&error);
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    StringTemplateErrorImpl::Tera(message)
+}
@@ -1,9 +1,404 @@
-use tera::Tera;
+use std::collections::HashMap;
 
+use tera::ast::{Expr, ExprVal, FunctionCall, Node};
+
+use super::{extensions, partials};
+use crate::prompt::sanitize::{escape_template_syntax, map_strings, strip_role_markers, Sanitizer};
 use crate::Parameters;
 
+// The name the template being rendered is registered under alongside the partials.
+const TEMPLATE_NAME: &str = "__llm_chain_prompt";
+
+// Partials including each other deeper than this are assumed to be recursive when collecting variables.
+const MAX_INCLUDE_DEPTH: usize = 8;
+
 // Renders the given `template` using the `context` provided as `Parameters`.
 // Returns a `Result` with a `String` containing the rendered template or an error.
 pub fn render(template: &str, context: &Parameters) -> Result<String, tera::Error> {
-    Tera::one_off(template, &context.to_tera(), false)
+    // The partials were parsed when they were registered.
+    let mut tera = partials::tera();
+    // The sanitization filters are built in; registered filters may replace them.
+    let sanitizer = Sanitizer::new();
+    tera.register_filter("sanitize", move |value: &tera::Value, _: &_| {
+        Ok(sanitizer.sanitize_value(value))
+    });
+    tera.register_filter("strip_role_markers", |value: &tera::Value, _: &_| {
+        Ok(map_strings(value, &strip_role_markers))
+    });
+    tera.register_filter("escape_template_syntax", |value: &tera::Value, _: &_| {
+        Ok(map_strings(value, &escape_template_syntax))
+    });
+    let filters = extensions::filters();
+    let functions = extensions::functions();
+    for (name, filter) in filters {
+        tera.register_filter(&name, move |value: &tera::Value, args: &_| {
+            filter(value, args).map_err(tera::Error::msg)
+        });
+    }
+    for (name, function) in functions {
+        tera.register_function(&name, move |args: &_| {
+            function(args).map_err(tera::Error::msg)
+        });
+    }
+    tera.add_raw_template(TEMPLATE_NAME, template)?;
+    tera.render(TEMPLATE_NAME, &context.to_tera())
+}
+
+// Variables tera provides to templates, which aren't parameters.
+const BUILTIN_VARIABLES: &[&str] = &["loop", "__tera_context"];
+
+// Returns the names of the variables a tera template reads, in order of first use.
+//
+// The template is parsed and its syntax tree walked, so variables in conditions, loops, filter and function arguments,
+// tests, macros, and included partials are found. Variables introduced by `for`, `set` and macro arguments are left
+// out where they are in scope. A template that doesn't parse has no variables; rendering it reports the error.
+pub fn variables(template: &str) -> Vec<String> {
+    let mut variables = Vec::new();
+    if let Ok(template) = tera::Template::new(TEMPLATE_NAME, None, template) {
+        VariableCollector {
+            variables: &mut variables,
+            locals: Vec::new(),
+            depth: 0,
+        }
+        .nodes(&template.ast);
+    }
+    variables
+}
+
+struct VariableCollector<'a> {
+    variables: &'a mut Vec<String>,
+    // The variables introduced by the template in the current scope.
+    locals: Vec<String>,
+    // How many partials deep the collector is.
+    depth: usize,
+}
+
+impl VariableCollector<'_> {
+    fn nodes(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            self.node(node);
+        }
+    }
+
+    // Walks `nodes` in a scope of their own, so the variables they introduce are forgotten afterwards.
+    fn scoped(&mut self, locals: impl IntoIterator<Item = String>, nodes: &[Node]) {
+        let scope = self.locals.len();
+        self.locals.extend(locals);
+        self.nodes(nodes);
+        self.locals.truncate(scope);
+    }
+
+    fn node(&mut self, node: &Node) {
+        match node {
+            Node::VariableBlock(_, expr) => self.expr(expr),
+            Node::Set(_, set) => {
+                self.expr(&set.value);
+                self.locals.push(set.key.clone());
+            }
+            Node::If(condition, _) => {
+                for (_, expr, body) in &condition.conditions {
+                    self.expr(expr);
+                    self.scoped([], body);
+                }
+                if let Some((_, body)) = &condition.otherwise {
+                    self.scoped([], body);
+                }
+            }
+            Node::Forloop(_, forloop, _) => {
+                self.expr(&forloop.container);
+                let locals = forloop.key.iter().chain([&forloop.value]).cloned();
+                self.scoped(locals, &forloop.body);
+                if let Some(body) = &forloop.empty_body {
+                    self.scoped([], body);
+                }
+            }
+            Node::FilterSection(_, section, _) => {
+                self.function_call(&section.filter);
+                self.scoped([], &section.body);
+            }
+            Node::Block(_, block, _) => self.scoped([], &block.body),
+            Node::MacroDefinition(_, definition, _) => {
+                for default in definition.args.values().flatten() {
+                    self.expr(default);
+                }
+                // Macros only see their arguments.
+                let locals =
+                    std::mem::replace(&mut self.locals, definition.args.keys().cloned().collect());
+                self.nodes(&definition.body);
+                self.locals = locals;
+            }
+            Node::Include(_, names, _) => {
+                if let Some(partial) = names.iter().find_map(|name| partials::get(name)) {
+                    self.partial(&partial);
+                }
+            }
+            Node::Extends(_, name) => {
+                if let Some(partial) = partials::get(name) {
+                    self.partial(&partial);
+                }
+            }
+            Node::Super
+            | Node::Text(_)
+            | Node::ImportMacro(..)
+            | Node::Raw(..)
+            | Node::Break(_)
+            | Node::Continue(_)
+            | Node::Comment(..) => {}
+        }
+    }
+
+    // Adds the variables of an included partial, which sees the variables in scope where it's included.
+    fn partial(&mut self, partial: &str) {
+        if self.depth >= MAX_INCLUDE_DEPTH {
+            return;
+        }
+        if let Ok(template) = tera::Template::new(TEMPLATE_NAME, None, partial) {
+            self.depth += 1;
+            self.scoped([], &template.ast);
+            self.depth -= 1;
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        self.expr_val(&expr.val);
+        for filter in &expr.filters {
+            self.function_call(filter);
+        }
+    }
+
+    fn expr_val(&mut self, val: &ExprVal) {
+        match val {
+            ExprVal::Ident(ident) => self.ident(ident),
+            ExprVal::Math(math) => {
+                self.expr(&math.lhs);
+                self.expr(&math.rhs);
+            }
+            ExprVal::Logic(logic) => {
+                self.expr(&logic.lhs);
+                self.expr(&logic.rhs);
+            }
+            ExprVal::In(in_) => {
+                self.expr(&in_.lhs);
+                self.expr(&in_.rhs);
+            }
+            ExprVal::Test(test) => {
+                self.ident(&test.ident);
+                for arg in &test.args {
+                    self.expr(arg);
+                }
+            }
+            ExprVal::MacroCall(call) => self.args(&call.args),
+            ExprVal::FunctionCall(call) => self.function_call(call),
+            ExprVal::Array(values) => {
+                for value in values {
+                    self.expr(value);
+                }
+            }
+            ExprVal::StringConcat(concat) => {
+                for value in &concat.values {
+                    self.expr_val(value);
+                }
+            }
+            ExprVal::String(_) | ExprVal::Int(_) | ExprVal::Float(_) | ExprVal::Bool(_) => {}
+        }
+    }
+
+    fn function_call(&mut self, call: &FunctionCall) {
+        self.args(&call.args);
+    }
+
+    // Arguments are keyed by name, so they're walked in the order of their names to keep the result stable.
+    fn args(&mut self, args: &HashMap<String, Expr>) {
+        let mut args: Vec<_> = args.iter().collect();
+        args.sort_by_key(|(name, _)| *name);
+        for (_, arg) in args {
+            self.expr(arg);
+        }
+    }
+
+    // Adds the variable an identifier such as `documents`, `document.title` or `documents[index]` starts with, and
+    // the variables it's indexed with.
+    fn ident(&mut self, ident: &str) {
+        if let Some(name) = ident.split(['.', '[']).next() {
+            self.variable(name);
+        }
+        let indices = ident
+            .split('[')
+            .skip(1)
+            .filter_map(|index| index.split(']').next());
+        for index in indices {
+            let is_variable = index.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_');
+            if is_variable {
+                self.ident(index);
+            }
+        }
+    }
+
+    fn variable(&mut self, name: &str) {
+        let is_parameter = !BUILTIN_VARIABLES.contains(&name)
+            && !self.locals.iter().any(|local| local == name)
+            && !self.variables.iter().any(|variable| variable == name);
+        if is_parameter {
+            self.variables.push(name.to_string());
+        }
+    }
+}
+
+// Returns the example values declared in comments of the form `{# example name: value #}`, in order.
+//
+// Comments are dropped when rendering, so the examples don't end up in the prompt. Values run until the end of the
+// comment and may span several lines.
+pub fn examples(template: &str) -> Vec<(String, String)> {
+    let mut examples = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{#") {
+        let Some(end) = rest[start..].find("#}") else {
+            break;
+        };
+        let comment =
+            rest[start + 2..start + end].trim_matches(|c: char| c == '-' || c.is_whitespace());
+        rest = &rest[start + end + 2..];
+        let Some((name, value)) = comment
+            .strip_prefix("example ")
+            .and_then(|declaration| declaration.split_once(':'))
+        else {
+            continue;
+        };
+        let name = name.trim();
+        if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            examples.push((name.to_string(), value.trim().to_string()));
+        }
+    }
+    examples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::variables;
+    use crate::prompt::{register_partial, remove_partial, StringTemplate};
+    use crate::Parameters;
+
+    #[test]
+    fn finds_variables_in_conditions_and_loops() {
+        let template =
+            "{% if verbose and not brief %}{{ style }}{% elif level > threshold %}short{% endif %}\
+            {% for key, document in documents | filter(attribute=\"kind\", value=kind) %}\
+            {{ loop.index }}. {{ key }}: {{ document.title | truncate(length=limit) }}\
+            {% else %}{{ fallback }}{% endfor %}";
+        assert_eq!(
+            variables(template),
+            vec![
+                "verbose",
+                "brief",
+                "style",
+                "level",
+                "threshold",
+                "documents",
+                "kind",
+                "limit",
+                "fallback"
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_variables_in_tests_functions_and_indices() {
+        let template = "{% if answer is defined %}{{ answer }}{% endif %}\
+            {{ examples[index].text }} {{ now(timezone=zone) }} {{ \"Dear \" ~ name }}\
+            {% filter upper %}{{ signature }}{% endfilter %}{% raw %}{{ literal }}{% endraw %}";
+        assert_eq!(
+            variables(template),
+            vec!["answer", "examples", "index", "zone", "name", "signature"]
+        );
+    }
+
+    #[test]
+    fn leaves_out_the_variables_the_template_sets() {
+        let template = "{% set greeting = salutation ~ \" \" ~ name %}{{ greeting }}\
+            {% for item in items %}{% set total = item.price %}{{ total }}{% endfor %}{{ item }}{{ total }}\
+            {% macro line(text, width=80) %}{{ text }} {{ outside }}{% endmacro line %}";
+        assert_eq!(
+            variables(template),
+            vec!["salutation", "name", "items", "item", "total", "outside"]
+        );
+    }
+
+    #[test]
+    fn finds_variables_in_included_partials() {
+        register_partial(
+            "tera_variables_outer",
+            "{% if strict %}{% include \"tera_variables_inner\" %}{% endif %}",
+        )
+        .unwrap();
+        register_partial("tera_variables_inner", "Answer in {{ language }}.").unwrap();
+        register_partial(
+            "tera_variables_recursive",
+            "{{ depth }}{% include \"tera_variables_recursive\" %}",
+        )
+        .unwrap();
+        let template = "{{ question }}{% for language in languages %}{% include \"tera_variables_outer\" %}{% endfor %}\
+            {% include \"tera_variables_recursive\" %}";
+        assert_eq!(
+            variables(template),
+            vec!["question", "languages", "strict", "depth"]
+        );
+        for name in [
+            "tera_variables_outer",
+            "tera_variables_inner",
+            "tera_variables_recursive",
+        ] {
+            remove_partial(name);
+        }
+    }
+
+    #[test]
+    fn finds_no_variables_in_invalid_templates() {
+        assert!(variables("{% if question %}{{ question }}").is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_partials_when_they_are_registered() {
+        let template =
+            StringTemplate::tera("{{ question }} {% include \"tera_partials_format\" %}");
+        let parameters: Parameters = vec![("question", "Why?"), ("language", "French")].into();
+        register_partial("tera_partials_format", "Answer in {{ language }}.").unwrap();
+        assert_eq!(
+            template.format(&parameters).unwrap(),
+            "Why? Answer in French."
+        );
+
+        // A broken partial is rejected, and neither replaces the partial nor breaks other templates.
+        assert!(register_partial("tera_partials_format", "{% if language %}{{ language").is_err());
+        assert!(register_partial("tera_partials_broken", "{{ language").is_err());
+        assert_eq!(
+            template.format(&parameters).unwrap(),
+            "Why? Answer in French."
+        );
+        assert_eq!(
+            StringTemplate::tera("{{ question }}")
+                .format(&parameters)
+                .unwrap(),
+            "Why?"
+        );
+
+        register_partial("tera_partials_format", "Reply in {{ language }}.").unwrap();
+        assert_eq!(
+            template.format(&parameters).unwrap(),
+            "Why? Reply in French."
+        );
+        assert_eq!(
+            remove_partial("tera_partials_format").as_deref(),
+            Some("Reply in {{ language }}.")
+        );
+        assert!(template.format(&parameters).is_err());
+        assert!(remove_partial("tera_partials_broken").is_none());
+
+        // Partials for Handlebars templates are accepted, and tera templates can't include them.
+        #[cfg(feature = "handlebars")]
+        {
+            register_partial("tera_partials_format", "{% if language %}").unwrap();
+            assert!(template.format(&parameters).is_err());
+            remove_partial("tera_partials_format");
+        }
+    }
 }
@@ -0,0 +1,184 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+/// A warning raised while rendering a template, reported to the handler set with `with_warning_handler` or
+/// `set_warning_handler`, or logged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateWarning {
+    /// Parameters used by a lenient template weren't given, and were rendered as empty strings.
+    MissingParameters(Vec<String>),
+}
+
+impl fmt::Display for TemplateWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingParameters(names) => write!(
+                f,
+                "rendered a template without the parameters {}, as empty strings",
+                names.join(", ")
+            ),
+        }
+    }
+}
+
+/// A function receiving the warnings raised while rendering templates.
+pub type TemplateWarningHandler = Arc<dyn Fn(&TemplateWarning) + Send + Sync>;
+
+static HANDLER: RwLock<Option<TemplateWarningHandler>> = RwLock::new(None);
+
+thread_local! {
+    static SCOPED_HANDLER: RefCell<Option<TemplateWarningHandler>> = RefCell::new(None);
+}
+
+/// Sets the function receiving the warnings raised while rendering templates, replacing the previous one. Warnings
+/// are logged with `log::warn!` until a handler is set.
+///
+/// # Examples
+/// ```
+/// use llm_chain::prompt::{set_warning_handler, RenderMode, StringTemplate, TemplateWarning};
+/// use llm_chain::Parameters;
+/// set_warning_handler(|warning| {
+///     if let TemplateWarning::MissingParameters(names) = warning {
+///         eprintln!("rendered without {:?}", names);
+///     }
+/// });
+/// let template = StringTemplate::tera("{{greeting}} {{name}}!").with_render_mode(RenderMode::Lenient);
+/// let parameters: Parameters = vec![("name", "Ada")].into();
+/// assert_eq!(template.format(&parameters).unwrap(), " Ada!");
+/// ```
+pub fn set_warning_handler<F: Fn(&TemplateWarning) + Send + Sync + 'static>(handler: F) {
+    *HANDLER.write().expect("warning handler lock poisoned") = Some(Arc::new(handler));
+}
+
+/// Removes the warning handler, so that warnings are logged again.
+pub fn clear_warning_handler() {
+    *HANDLER.write().expect("warning handler lock poisoned") = None;
+}
+
+/// Runs `f`, reporting the warnings raised on the current thread while it runs to `handler` instead of the handler
+/// set with `set_warning_handler`.
+///
+/// Templates are rendered on the thread formatting them, so `handler` receives the warnings of the templates `f`
+/// formats, and none of the templates formatted elsewhere at the same time.
+///
+/// # Examples
+/// ```
+/// use llm_chain::prompt::{with_warning_handler, RenderMode, StringTemplate, TemplateWarning};
+/// use llm_chain::Parameters;
+/// use std::sync::{Arc, Mutex};
+/// let template = StringTemplate::tera("{{greeting}} {{name}}!").with_render_mode(RenderMode::Lenient);
+/// let parameters: Parameters = vec![("name", "Ada")].into();
+/// let warnings = Arc::new(Mutex::new(Vec::new()));
+/// let received = warnings.clone();
+/// let text = with_warning_handler(
+///     move |warning| received.lock().unwrap().push(warning.clone()),
+///     || template.format(&parameters),
+/// );
+/// assert_eq!(text.unwrap(), " Ada!");
+/// assert_eq!(
+///     *warnings.lock().unwrap(),
+///     vec![TemplateWarning::MissingParameters(vec!["greeting".to_string()])]
+/// );
+/// ```
+pub fn with_warning_handler<H, F, R>(handler: H, f: F) -> R
+where
+    H: Fn(&TemplateWarning) + Send + Sync + 'static,
+    F: FnOnce() -> R,
+{
+    /// Restores the previous scoped handler, even if `f` panics.
+    struct Restore(Option<TemplateWarningHandler>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            SCOPED_HANDLER.with(|scoped| *scoped.borrow_mut() = previous);
+        }
+    }
+
+    let handler: TemplateWarningHandler = Arc::new(handler);
+    let _restore = Restore(SCOPED_HANDLER.with(|scoped| scoped.replace(Some(handler))));
+    f()
+}
+
+/// Reports `warning` to the scoped handler of the current thread, or else to the handler set with
+/// `set_warning_handler`, or else logs it.
+pub(crate) fn warn(warning: TemplateWarning) {
+    let handler = SCOPED_HANDLER
+        .with(|scoped| scoped.borrow().clone())
+        .or_else(|| {
+            HANDLER
+                .read()
+                .expect("warning handler lock poisoned")
+                .clone()
+        });
+    match handler {
+        Some(handler) => handler(&warning),
+        None => log::warn!("{}", warning),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::prompt::{RenderMode, StringTemplate};
+    use crate::Parameters;
+
+    #[test]
+    fn lenient_templates_report_missing_parameters() {
+        let warnings = Arc::new(Mutex::new(Vec::new()));
+        let received = warnings.clone();
+        let template = StringTemplate::tera("{{role}}: {{text}}{{suffix}}");
+        let lenient = with_warning_handler(
+            move |warning| received.lock().unwrap().push(warning.clone()),
+            || {
+                assert!(template.format(&Parameters::new_with_text("Hi")).is_err());
+                let lenient = template.with_render_mode(RenderMode::Lenient);
+                assert_eq!(lenient.render_mode(), RenderMode::Lenient);
+                assert_eq!(
+                    lenient.format(&Parameters::new_with_text("Hi")).unwrap(),
+                    ": Hi"
+                );
+                lenient
+            },
+        );
+        assert_eq!(lenient.variables(), vec!["role", "text", "suffix"]);
+        let strict = lenient.with_render_mode(RenderMode::Strict);
+        assert_eq!(strict.render_mode(), RenderMode::Strict);
+
+        assert_eq!(
+            *warnings.lock().unwrap(),
+            vec![TemplateWarning::MissingParameters(vec![
+                "role".to_string(),
+                "suffix".to_string()
+            ])]
+        );
+    }
+
+    #[test]
+    fn scoped_handlers_are_restored_and_stay_on_their_thread() {
+        let outer = Arc::new(Mutex::new(0));
+        let inner = Arc::new(Mutex::new(0));
+        let lenient = StringTemplate::tera("{{missing}}").with_render_mode(RenderMode::Lenient);
+        let (outer_count, inner_count) = (outer.clone(), inner.clone());
+        with_warning_handler(
+            move |_| *outer_count.lock().unwrap() += 1,
+            || {
+                with_warning_handler(
+                    move |_| *inner_count.lock().unwrap() += 1,
+                    || lenient.format(&Parameters::new()).unwrap(),
+                );
+                lenient.format(&Parameters::new()).unwrap();
+                // Another thread doesn't see the handlers of this one.
+                std::thread::scope(|scope| {
+                    scope.spawn(|| lenient.format(&Parameters::new()).unwrap());
+                });
+            },
+        );
+        lenient.format(&Parameters::new()).unwrap();
+        assert_eq!(*inner.lock().unwrap(), 1);
+        assert_eq!(*outer.lock().unwrap(), 1);
+    }
+}
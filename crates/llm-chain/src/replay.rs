@@ -0,0 +1,350 @@
+//! Recording and replaying the invocations of an executor.
+//!
+//! `ReplayExecutor` wraps an executor and keeps every prompt it executes with the output of the model, as an
+//! `Exchange`. The exchanges can be saved and given to another `ReplayExecutor`, which answers the prompts it has
+//! seen with the recorded outputs instead of invoking the model. Prompts it hasn't seen are executed and recorded,
+//! unless the executor is strict, in which case they fail. Replaying a run is then reproducible and free.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let recorder = ReplayExecutor::new(executor);
+//! chain.run(parameters.clone(), &recorder).await?;
+//! let exchanges = recorder.exchanges();
+//!
+//! // Later, without calling the model:
+//! let replayer = ReplayExecutor::new(executor).with_exchanges(exchanges).strict();
+//! chain.run(parameters, &replayer).await?;
+//! ```
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    json_schema::JsonSchema,
+    output::{Choice, FinishReason, Output, ToolCall, Usage},
+    prompt::Prompt,
+    tokens::{PromptTokensError, TokenCount, TokenizerError},
+    tools::ToolDefinition,
+    traits::{self, ExecutorCreationError, ExecutorError},
+};
+
+/// An output recorded by a `ReplayExecutor`, with its metadata.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct RecordedOutput {
+    pub choices: Vec<Choice>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+impl RecordedOutput {
+    /// Records the choices and the metadata of `output`.
+    pub async fn from_output<O: Output>(output: &O) -> Self {
+        Self {
+            choices: output.choices().await,
+            usage: output.usage().await,
+            model: output.model().await,
+        }
+    }
+}
+
+#[async_trait]
+impl Output for RecordedOutput {
+    async fn primary_textual_output_choices(&self) -> Vec<String> {
+        self.choices
+            .iter()
+            .map(|choice| choice.text.clone())
+            .collect()
+    }
+
+    async fn tool_calls(&self) -> Vec<ToolCall> {
+        self.choices
+            .first()
+            .map(|choice| choice.tool_calls.clone())
+            .unwrap_or_default()
+    }
+
+    async fn finish_reason(&self) -> Option<FinishReason> {
+        self.choices
+            .first()
+            .and_then(|choice| choice.finish_reason.clone())
+    }
+
+    async fn choices(&self) -> Vec<Choice> {
+        self.choices.clone()
+    }
+
+    async fn usage(&self) -> Option<Usage> {
+        self.usage
+    }
+
+    async fn model(&self) -> Option<String> {
+        self.model.clone()
+    }
+}
+
+/// A prompt executed by a `ReplayExecutor` and the output the model answered with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Exchange {
+    pub prompt: String,
+    pub output: RecordedOutput,
+}
+
+#[derive(Debug, Error)]
+pub enum ReplayError<E: std::error::Error> {
+    #[error("No output was recorded for the prompt {0:?}")]
+    NotRecorded(String),
+    #[error(transparent)]
+    Executor(E),
+}
+
+impl<E: std::error::Error> ExecutorError for ReplayError<E> {}
+
+#[derive(Default)]
+struct Recording {
+    exchanges: Vec<Exchange>,
+    /// Whether each of the exchanges given by `with_exchanges` was replayed. Exchanges recorded later aren't replayed.
+    replayed: Vec<bool>,
+}
+
+/// An executor answering the prompts of a recording with the recorded outputs, and executing and recording the
+/// others.
+///
+/// A prompt recorded several times is answered with its outputs in the order they were recorded, then with the last
+/// one. Prompts are matched on their text; the options of the invocation are not part of the match.
+pub struct ReplayExecutor<E> {
+    executor: E,
+    recording: Arc<Mutex<Recording>>,
+    strict: bool,
+}
+
+impl<E> ReplayExecutor<E> {
+    /// Wraps `executor`, recording every prompt it executes.
+    pub fn new(executor: E) -> Self {
+        Self {
+            executor,
+            recording: Default::default(),
+            strict: false,
+        }
+    }
+
+    /// Answers the prompts of `exchanges` with their recorded outputs.
+    pub fn with_exchanges(self, exchanges: Vec<Exchange>) -> Self {
+        *self.recording.lock().unwrap() = Recording {
+            replayed: vec![false; exchanges.len()],
+            exchanges,
+        };
+        self
+    }
+
+    /// Fails with `ReplayError::NotRecorded` on prompts that weren't recorded, instead of executing them.
+    pub fn strict(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+
+    /// Returns the exchanges replayed or recorded so far, with the ones given by `with_exchanges`.
+    pub fn exchanges(&self) -> Vec<Exchange> {
+        self.recording.lock().unwrap().exchanges.clone()
+    }
+
+    /// Returns the wrapped executor.
+    pub fn inner(&self) -> &E {
+        &self.executor
+    }
+
+    fn replay(&self, prompt: &str) -> Option<RecordedOutput> {
+        let mut recording = self.recording.lock().unwrap();
+        let Recording {
+            exchanges,
+            replayed,
+        } = &mut *recording;
+        let matching = |index: &usize| exchanges[*index].prompt == prompt;
+        let index = (0..replayed.len())
+            .filter(matching)
+            .find(|&index| !replayed[index])
+            .or_else(|| (0..replayed.len()).rev().find(matching))?;
+        replayed[index] = true;
+        Some(exchanges[index].output.clone())
+    }
+
+    fn record(&self, exchange: Exchange) {
+        self.recording.lock().unwrap().exchanges.push(exchange);
+    }
+}
+
+#[async_trait]
+impl<E> traits::Executor for ReplayExecutor<E>
+where
+    E: traits::Executor + Send + Sync,
+    E::Error: Send,
+{
+    type PerInvocationOptions = E::PerInvocationOptions;
+    type PerExecutorOptions = E::PerExecutorOptions;
+    type Output = RecordedOutput;
+    type Error = ReplayError<E::Error>;
+    type Token = E::Token;
+    type StepTokenizer<'a>
+        = E::StepTokenizer<'a>
+    where
+        Self: 'a;
+    type TextSplitter<'a>
+        = E::TextSplitter<'a>
+    where
+        Self: 'a;
+
+    /// Creates the wrapped executor with the options, with nothing recorded yet.
+    fn new_with_options(
+        executor_options: Option<Self::PerExecutorOptions>,
+        invocation_options: Option<Self::PerInvocationOptions>,
+    ) -> Result<Self, ExecutorCreationError> {
+        E::new_with_options(executor_options, invocation_options).map(Self::new)
+    }
+
+    async fn execute(
+        &self,
+        options: Option<&Self::PerInvocationOptions>,
+        prompt: &Prompt,
+        is_streaming: Option<bool>,
+    ) -> Result<Self::Output, Self::Error> {
+        let text = prompt.to_string();
+        if let Some(output) = self.replay(&text) {
+            return Ok(output);
+        }
+        if self.strict {
+            return Err(ReplayError::NotRecorded(text));
+        }
+        let output = self
+            .executor
+            .execute(options, prompt, is_streaming)
+            .await
+            .map_err(ReplayError::Executor)?;
+        let output = RecordedOutput::from_output(&output).await;
+        self.record(Exchange {
+            prompt: text,
+            output: output.clone(),
+        });
+        Ok(output)
+    }
+
+    fn tokens_used(
+        &self,
+        options: Option<&Self::PerInvocationOptions>,
+        prompt: &Prompt,
+    ) -> Result<TokenCount, PromptTokensError> {
+        self.executor.tokens_used(options, prompt)
+    }
+
+    fn max_tokens_allowed(&self, options: Option<&Self::PerInvocationOptions>) -> i32 {
+        self.executor.max_tokens_allowed(options)
+    }
+
+    fn answer_prefix(&self, prompt: &Prompt) -> Option<String> {
+        self.executor.answer_prefix(prompt)
+    }
+
+    fn supports_images(&self) -> bool {
+        self.executor.supports_images()
+    }
+
+    fn json_schema_options(
+        &self,
+        options: Option<&Self::PerInvocationOptions>,
+        schema: &JsonSchema,
+    ) -> Option<Self::PerInvocationOptions> {
+        self.executor.json_schema_options(options, schema)
+    }
+
+    fn tool_options(
+        &self,
+        options: Option<&Self::PerInvocationOptions>,
+        tools: &[ToolDefinition],
+    ) -> Option<Self::PerInvocationOptions> {
+        self.executor.tool_options(options, tools)
+    }
+
+    fn get_tokenizer(
+        &self,
+        options: Option<&Self::PerInvocationOptions>,
+    ) -> Result<Self::StepTokenizer<'_>, TokenizerError> {
+        self.executor.get_tokenizer(options)
+    }
+
+    fn get_text_splitter(
+        &self,
+        options: Option<&Self::PerInvocationOptions>,
+    ) -> Result<Self::TextSplitter<'_>, Self::Error> {
+        self.executor
+            .get_text_splitter(options)
+            .map_err(ReplayError::Executor)
+    }
+
+    async fn shutdown(&self) -> Result<(), Self::Error> {
+        self.executor
+            .shutdown()
+            .await
+            .map_err(ReplayError::Executor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ReplayError, ReplayExecutor};
+    use crate::agents::mock::{MockExecutor, MockOutput};
+    use crate::output::Output;
+    use crate::prompt::Prompt;
+    use crate::traits::Executor;
+    use futures::executor::block_on;
+
+    fn prompt(text: &str) -> Prompt {
+        Prompt::Text(text.to_string())
+    }
+
+    async fn answer<E: Executor>(executor: &E, text: &str) -> Result<String, E::Error> {
+        let output = executor.execute(None, &prompt(text), None).await?;
+        Ok(output.primary_textual_output().await.unwrap_or_default())
+    }
+
+    #[test]
+    fn replays_recorded_outputs_in_order() {
+        let recorder = ReplayExecutor::new(MockExecutor::new(vec![
+            MockOutput::text("one"),
+            MockOutput::text("two"),
+            MockOutput::text("three"),
+        ]));
+        assert_eq!(block_on(answer(&recorder, "count")).unwrap(), "one");
+        assert_eq!(block_on(answer(&recorder, "count")).unwrap(), "two");
+        assert_eq!(block_on(answer(&recorder, "other")).unwrap(), "three");
+        let exchanges = recorder.exchanges();
+        assert_eq!(exchanges.len(), 3);
+
+        let replayer = ReplayExecutor::new(MockExecutor::new(vec![]))
+            .with_exchanges(exchanges)
+            .strict();
+        assert_eq!(block_on(answer(&replayer, "other")).unwrap(), "three");
+        assert_eq!(block_on(answer(&replayer, "count")).unwrap(), "one");
+        assert_eq!(block_on(answer(&replayer, "count")).unwrap(), "two");
+        // Once replayed, the last output is repeated.
+        assert_eq!(block_on(answer(&replayer, "count")).unwrap(), "two");
+        assert!(matches!(
+            block_on(answer(&replayer, "new")),
+            Err(ReplayError::NotRecorded(_))
+        ));
+        assert!(replayer.inner().prompts.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn records_prompts_it_has_not_seen() {
+        let recorder = ReplayExecutor::new(MockExecutor::new(vec![MockOutput::text("one")]));
+        block_on(answer(&recorder, "count")).unwrap();
+        let replayer = ReplayExecutor::new(MockExecutor::new(vec![MockOutput::text("new")]))
+            .with_exchanges(recorder.exchanges());
+        assert_eq!(block_on(answer(&replayer, "count")).unwrap(), "one");
+        assert_eq!(block_on(answer(&replayer, "other")).unwrap(), "new");
+        assert_eq!(replayer.exchanges().len(), 2);
+    }
+}
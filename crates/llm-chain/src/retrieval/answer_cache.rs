@@ -0,0 +1,229 @@
+//! Caching answers of retrieval-augmented chains.
+//!
+//! An answer produced by a RAG chain is only valid as long as the chunks it was generated from are unchanged. The
+//! `AnswerCache` therefore keys every entry by the embedding of the question *and* the identifiers of the chunks
+//! that were retrieved for it: a lookup only hits when a sufficiently similar question retrieved exactly the same
+//! chunks, and updating a chunk in the index drops every answer that was built on it.
+use std::collections::BTreeSet;
+use std::sync::{Arc, RwLock};
+
+use super::cosine_similarity;
+use crate::hash::stable_hash_hex;
+
+/// The default minimum cosine similarity between two questions for them to share a cached answer.
+const DEFAULT_SIMILARITY_THRESHOLD: f32 = 0.95;
+
+/// Something that wants to be told when chunks in an index are updated or removed.
+///
+/// Indexers and `ObservedVectorStore`s call `chunks_updated` with the identifiers of every chunk they replace or
+/// delete, so that derived data such as cached answers can be invalidated, and `index_updated` when chunks were
+/// deleted without their identifiers being known, such as by a metadata filter.
+pub trait IndexObserver {
+    fn chunks_updated(&self, chunk_ids: &[String]);
+
+    fn index_updated(&self);
+}
+
+/// Returns a stable identifier for a chunk based on its content, for chunks that don't carry an id of their own. The
+/// identifier is the same across processes and Rust releases.
+pub fn content_hash(content: &str) -> String {
+    stable_hash_hex(&[content])
+}
+
+struct Entry<T> {
+    question_embedding: Vec<f32>,
+    chunk_ids: BTreeSet<String>,
+    answer: T,
+}
+
+/// An in-memory cache of answers keyed by question embedding and retrieved chunk ids.
+///
+/// The cache is cheap to clone; clones share the same entries, so one handle can be given to the chain and another
+/// to the indexer.
+pub struct AnswerCache<T> {
+    entries: Arc<RwLock<Vec<Entry<T>>>>,
+    similarity_threshold: f32,
+    max_entries: Option<usize>,
+}
+
+impl<T> Clone for AnswerCache<T> {
+    fn clone(&self) -> Self {
+        Self {
+            entries: self.entries.clone(),
+            similarity_threshold: self.similarity_threshold,
+            max_entries: self.max_entries,
+        }
+    }
+}
+
+impl<T> Default for AnswerCache<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> AnswerCache<T> {
+    /// Creates an empty, unbounded cache with a similarity threshold of `0.95`.
+    pub fn new() -> Self {
+        Self {
+            entries: Arc::new(RwLock::new(Vec::new())),
+            similarity_threshold: DEFAULT_SIMILARITY_THRESHOLD,
+            max_entries: None,
+        }
+    }
+
+    /// Sets the minimum cosine similarity between a new question and a cached one for a lookup to hit.
+    pub fn with_similarity_threshold(mut self, similarity_threshold: f32) -> Self {
+        self.similarity_threshold = similarity_threshold;
+        self
+    }
+
+    /// Limits the number of cached answers. When the limit is reached the oldest entry is evicted.
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    /// Caches `answer` for a question with the given embedding, built from the given chunks.
+    ///
+    /// An existing entry for the same chunks and a similar question is replaced.
+    pub fn insert<I, S>(&self, question_embedding: Vec<f32>, chunk_ids: I, answer: T)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let chunk_ids: BTreeSet<String> = chunk_ids.into_iter().map(Into::into).collect();
+        let mut entries = self.entries.write().expect("answer cache lock poisoned");
+        entries.retain(|entry| !self.matches(entry, &question_embedding, &chunk_ids));
+        if let Some(max_entries) = self.max_entries {
+            while !entries.is_empty() && entries.len() >= max_entries {
+                entries.remove(0);
+            }
+            if max_entries == 0 {
+                return;
+            }
+        }
+        entries.push(Entry {
+            question_embedding,
+            chunk_ids,
+            answer,
+        });
+    }
+
+    /// Drops every cached answer that was built from any of the given chunks and returns how many were dropped.
+    pub fn invalidate_chunks<I, S>(&self, chunk_ids: I) -> usize
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let chunk_ids: Vec<S> = chunk_ids.into_iter().collect();
+        let mut entries = self.entries.write().expect("answer cache lock poisoned");
+        let before = entries.len();
+        entries.retain(|entry| {
+            !chunk_ids
+                .iter()
+                .any(|id| entry.chunk_ids.contains(id.as_ref()))
+        });
+        before - entries.len()
+    }
+
+    /// Removes all cached answers.
+    pub fn clear(&self) {
+        self.entries
+            .write()
+            .expect("answer cache lock poisoned")
+            .clear();
+    }
+
+    /// Returns the number of cached answers.
+    pub fn len(&self) -> usize {
+        self.entries
+            .read()
+            .expect("answer cache lock poisoned")
+            .len()
+    }
+
+    /// Returns `true` if the cache holds no answers.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn matches(
+        &self,
+        entry: &Entry<T>,
+        question_embedding: &[f32],
+        chunk_ids: &BTreeSet<String>,
+    ) -> bool {
+        entry.chunk_ids == *chunk_ids
+            && cosine_similarity(&entry.question_embedding, question_embedding)
+                >= self.similarity_threshold
+    }
+}
+
+impl<T: Clone> AnswerCache<T> {
+    /// Looks up an answer for a question with the given embedding that retrieved the given chunks.
+    ///
+    /// Only answers built from exactly the same set of chunks are returned; among those the one for the most
+    /// similar question wins.
+    pub fn get<I, S>(&self, question_embedding: &[f32], chunk_ids: I) -> Option<T>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let chunk_ids: BTreeSet<String> = chunk_ids.into_iter().map(Into::into).collect();
+        let entries = self.entries.read().expect("answer cache lock poisoned");
+        entries
+            .iter()
+            .filter(|entry| entry.chunk_ids == chunk_ids)
+            .map(|entry| {
+                (
+                    cosine_similarity(&entry.question_embedding, question_embedding),
+                    entry,
+                )
+            })
+            .filter(|(similarity, _)| *similarity >= self.similarity_threshold)
+            .max_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, entry)| entry.answer.clone())
+    }
+}
+
+impl<T> IndexObserver for AnswerCache<T> {
+    fn chunks_updated(&self, chunk_ids: &[String]) {
+        self.invalidate_chunks(chunk_ids);
+    }
+
+    /// Drops every answer, since any of them may have been built on the chunks removed.
+    fn index_updated(&self) {
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AnswerCache, IndexObserver};
+
+    #[test]
+    fn hits_only_for_similar_questions_over_the_same_chunks() {
+        let cache = AnswerCache::new().with_similarity_threshold(0.9);
+        cache.insert(vec![1.0, 0.0], ["a", "b"], "answer".to_string());
+
+        assert_eq!(
+            cache.get(&[0.99, 0.05], ["b", "a"]),
+            Some("answer".to_string())
+        );
+        assert_eq!(cache.get(&[0.0, 1.0], ["a", "b"]), None);
+        assert_eq!(cache.get(&[1.0, 0.0], ["a"]), None);
+    }
+
+    #[test]
+    fn updating_a_chunk_invalidates_answers_built_on_it() {
+        let cache = AnswerCache::new();
+        cache.insert(vec![1.0, 0.0], ["a", "b"], 1);
+        cache.insert(vec![0.0, 1.0], ["c"], 2);
+
+        cache.chunks_updated(&["b".to_string()]);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(&[1.0, 0.0], ["a", "b"]), None);
+        assert_eq!(cache.get(&[0.0, 1.0], ["c"]), Some(2));
+    }
+}
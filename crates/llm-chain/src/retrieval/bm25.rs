@@ -0,0 +1,373 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::Retriever;
+use crate::language::{is_cjk, Language};
+use crate::schema::{Document, ScoredDocument};
+
+/// Turns text into the terms used for keyword scoring.
+pub trait Analyzer: Send + Sync {
+    fn analyze(&self, text: &str) -> Vec<String>;
+}
+
+impl Language {
+    /// Removes common inflectional suffixes. This is a light stemmer: it conflates the most frequent word forms
+    /// without trying to find linguistic roots.
+    fn stem(self, word: &str) -> String {
+        let (suffixes, min_stem): (&[(&str, &str)], usize) = match self {
+            Language::English => (
+                &[
+                    ("sses", "ss"),
+                    ("ies", "y"),
+                    ("ing", ""),
+                    ("ed", ""),
+                    ("ly", ""),
+                    ("es", ""),
+                    ("s", ""),
+                ],
+                3,
+            ),
+            Language::German => (
+                &[
+                    ("ern", ""),
+                    ("en", ""),
+                    ("er", ""),
+                    ("es", ""),
+                    ("e", ""),
+                    ("s", ""),
+                ],
+                4,
+            ),
+            Language::French => (&[("aux", "al"), ("es", ""), ("s", ""), ("e", "")], 4),
+            Language::Spanish => (&[("ces", "z"), ("es", ""), ("s", "")], 4),
+            Language::Swedish => (
+                &[
+                    ("arna", ""),
+                    ("erna", ""),
+                    ("orna", ""),
+                    ("en", ""),
+                    ("er", ""),
+                    ("ar", ""),
+                    ("or", ""),
+                    ("et", ""),
+                ],
+                3,
+            ),
+            Language::Cjk => (&[], 0),
+        };
+        if self == Language::English && word.ends_with("ss") {
+            return word.to_string();
+        }
+        for (suffix, replacement) in suffixes {
+            if let Some(stem) = word.strip_suffix(suffix) {
+                if stem.chars().count() >= min_stem {
+                    return format!("{}{}", stem, replacement);
+                }
+            }
+        }
+        word.to_string()
+    }
+}
+
+/// Replaces accented Latin letters with their base letter.
+fn fold_diacritic(c: char) -> char {
+    match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' => 'a',
+        'ç' => 'c',
+        'è' | 'é' | 'ê' | 'ë' => 'e',
+        'ì' | 'í' | 'î' | 'ï' => 'i',
+        'ñ' => 'n',
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' => 'o',
+        'ù' | 'ú' | 'û' | 'ü' => 'u',
+        'ý' | 'ÿ' => 'y',
+        c => c,
+    }
+}
+
+/// An `Analyzer` for a natural language: lowercases text, splits it into words, removes stop words and stems.
+///
+/// Words in CJK scripts are split into overlapping pairs of characters whatever the language, so mixed-script text is
+/// handled too.
+#[derive(Debug, Clone)]
+pub struct LanguageAnalyzer {
+    language: Language,
+    stop_words: Vec<String>,
+    stemming: bool,
+    fold_diacritics: bool,
+}
+
+impl LanguageAnalyzer {
+    pub fn new(language: Language) -> Self {
+        Self {
+            language,
+            stop_words: language
+                .stop_words()
+                .iter()
+                .map(|w| w.to_string())
+                .collect(),
+            stemming: true,
+            fold_diacritics: false,
+        }
+    }
+
+    /// Replaces the stop words of the language.
+    pub fn with_stop_words<S: Into<String>>(mut self, stop_words: Vec<S>) -> Self {
+        self.stop_words = stop_words.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets whether words are stemmed. Defaults to `true`.
+    pub fn with_stemming(mut self, stemming: bool) -> Self {
+        self.stemming = stemming;
+        self
+    }
+
+    /// Sets whether accented letters are matched by their unaccented form, so that `café` matches `cafe`. Defaults to
+    /// `false`.
+    pub fn with_diacritic_folding(mut self, fold_diacritics: bool) -> Self {
+        self.fold_diacritics = fold_diacritics;
+        self
+    }
+
+    fn push_word(&self, terms: &mut Vec<String>, word: &str) {
+        if word.is_empty() || self.stop_words.iter().any(|w| w == word) {
+            return;
+        }
+        let word = if self.stemming {
+            self.language.stem(word)
+        } else {
+            word.to_string()
+        };
+        terms.push(if self.fold_diacritics {
+            word.chars().map(fold_diacritic).collect()
+        } else {
+            word
+        });
+    }
+}
+
+impl Analyzer for LanguageAnalyzer {
+    fn analyze(&self, text: &str) -> Vec<String> {
+        let mut terms = Vec::new();
+        let mut word = String::new();
+        let mut cjk_run: Vec<char> = Vec::new();
+        let flush_cjk = |terms: &mut Vec<String>, run: &mut Vec<char>| {
+            match run.len() {
+                0 => {}
+                1 => terms.push(run[0].to_string()),
+                _ => terms.extend(run.windows(2).map(|pair| pair.iter().collect::<String>())),
+            }
+            run.clear();
+        };
+        for c in text.chars().flat_map(char::to_lowercase) {
+            if is_cjk(c) {
+                self.push_word(&mut terms, &std::mem::take(&mut word));
+                cjk_run.push(c);
+            } else if c.is_alphanumeric() {
+                flush_cjk(&mut terms, &mut cjk_run);
+                word.push(c);
+            } else {
+                flush_cjk(&mut terms, &mut cjk_run);
+                self.push_word(&mut terms, &std::mem::take(&mut word));
+            }
+        }
+        flush_cjk(&mut terms, &mut cjk_run);
+        self.push_word(&mut terms, &word);
+        terms
+    }
+}
+
+struct IndexedDocument<M>
+where
+    M: Serialize + DeserializeOwned,
+{
+    document: Document<M>,
+    term_frequencies: HashMap<String, u32>,
+    length: usize,
+}
+
+/// A keyword index ranking documents with BM25.
+///
+/// BM25 scores documents by how often they contain the terms of the query, giving more weight to rare terms and less
+/// to long documents. Terms are produced by an `Analyzer`, so the index works for non-English corpora when given a
+/// matching `LanguageAnalyzer`. It complements embedding search, which can miss exact keywords, names and codes.
+pub struct Bm25Index<M, A = LanguageAnalyzer>
+where
+    M: Serialize + DeserializeOwned,
+    A: Analyzer,
+{
+    analyzer: A,
+    documents: Vec<IndexedDocument<M>>,
+    document_frequencies: HashMap<String, usize>,
+    total_length: usize,
+    k1: f32,
+    b: f32,
+    limit: usize,
+}
+
+impl<M> Bm25Index<M>
+where
+    M: Serialize + DeserializeOwned,
+{
+    /// Creates an index for texts in `language`.
+    pub fn for_language(language: Language) -> Self {
+        Self::new(LanguageAnalyzer::new(language))
+    }
+}
+
+impl<M, A> Bm25Index<M, A>
+where
+    M: Serialize + DeserializeOwned,
+    A: Analyzer,
+{
+    pub fn new(analyzer: A) -> Self {
+        Self {
+            analyzer,
+            documents: Vec::new(),
+            document_frequencies: HashMap::new(),
+            total_length: 0,
+            k1: 1.2,
+            b: 0.75,
+            limit: 4,
+        }
+    }
+
+    /// Sets the BM25 parameters: `k1` controls how quickly repeated terms stop adding to the score, `b` how much long
+    /// documents are penalized. The defaults are `1.2` and `0.75`.
+    pub fn with_parameters(mut self, k1: f32, b: f32) -> Self {
+        self.k1 = k1;
+        self.b = b;
+        self
+    }
+
+    /// Sets the number of documents returned when the index is used as a `Retriever`. Defaults to 4.
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    pub fn add_documents(&mut self, documents: Vec<Document<M>>) {
+        for document in documents {
+            let terms = self.analyzer.analyze(&document.page_content);
+            let mut term_frequencies: HashMap<String, u32> = HashMap::new();
+            for term in &terms {
+                *term_frequencies.entry(term.clone()).or_default() += 1;
+            }
+            for term in term_frequencies.keys() {
+                *self.document_frequencies.entry(term.clone()).or_default() += 1;
+            }
+            self.total_length += terms.len();
+            self.documents.push(IndexedDocument {
+                document,
+                term_frequencies,
+                length: terms.len(),
+            });
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    /// Returns the `limit` documents with the highest BM25 score for `query`. Documents sharing no term with the
+    /// query are left out.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<ScoredDocument<M>>
+    where
+        M: Clone,
+    {
+        let mut terms = self.analyzer.analyze(query);
+        terms.sort();
+        terms.dedup();
+        let count = self.documents.len() as f32;
+        let average_length = self.total_length as f32 / count.max(1.0);
+        let mut scored: Vec<(f32, &IndexedDocument<M>)> = self
+            .documents
+            .iter()
+            .map(|indexed| {
+                let score = terms
+                    .iter()
+                    .filter_map(|term| {
+                        let frequency = *indexed.term_frequencies.get(term)? as f32;
+                        let containing = self.document_frequencies[term] as f32;
+                        let idf = ((count - containing + 0.5) / (containing + 0.5) + 1.0).ln();
+                        let norm = 1.0 - self.b + self.b * indexed.length as f32 / average_length;
+                        Some(idf * frequency * (self.k1 + 1.0) / (frequency + self.k1 * norm))
+                    })
+                    .sum::<f32>();
+                (score, indexed)
+            })
+            .filter(|(score, _)| *score > 0.0)
+            .collect();
+        scored.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        scored
+            .into_iter()
+            .take(limit)
+            .map(|(score, indexed)| ScoredDocument {
+                document: indexed.document.clone(),
+                score,
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl<M, A> Retriever<M> for Bm25Index<M, A>
+where
+    M: Serialize + DeserializeOwned + Clone + Send + Sync,
+    A: Analyzer,
+{
+    type Error = Infallible;
+
+    async fn retrieve(&self, query: String) -> Result<Vec<Document<M>>, Self::Error> {
+        Ok(self
+            .search(&query, self.limit)
+            .into_iter()
+            .map(|scored| scored.document)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Analyzer, Bm25Index, Language, LanguageAnalyzer};
+    use crate::schema::Document;
+
+    #[test]
+    fn analyzers_handle_languages() {
+        let english = LanguageAnalyzer::new(Language::English);
+        assert_eq!(
+            english.analyze("The cats were running to the classes"),
+            vec!["cat", "runn", "class"]
+        );
+        let german = LanguageAnalyzer::new(Language::German).with_diacritic_folding(true);
+        assert_eq!(
+            german.analyze("Die Häuser und der Garten"),
+            vec!["haus", "gart"]
+        );
+        let cjk = LanguageAnalyzer::new(Language::Cjk);
+        assert_eq!(cjk.analyze("東京都 Tokyo"), vec!["東京", "京都", "tokyo"]);
+    }
+
+    #[test]
+    fn ranks_by_rare_terms() {
+        let mut index = Bm25Index::<serde_json::Value>::for_language(Language::English);
+        index.add_documents(vec![
+            Document::new("the rust compiler checks lifetimes".to_string()),
+            Document::new("the compiler of the go language".to_string()),
+            Document::new("a recipe for bread".to_string()),
+        ]);
+        let results = index.search("rust compilers", 3);
+        assert_eq!(results.len(), 2);
+        assert_eq!(
+            results[0].document.page_content,
+            "the rust compiler checks lifetimes"
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::schema::Document;
+
+/// A store of whole documents by id, such as the parents of the chunks indexed by a `ParentDocumentRetriever`.
+///
+/// Vector stores hold embedded chunks; a docstore holds the documents they were taken from, so that they can be
+/// returned in full. Implement it on a database or a key-value store to keep the documents across restarts.
+#[async_trait]
+pub trait DocStore<M>: Send + Sync
+where
+    M: Serialize + DeserializeOwned,
+{
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Stores `documents` under their ids, replacing the documents stored with the same ids. Documents without an id
+    /// are ignored.
+    async fn put(&self, documents: Vec<Document<M>>) -> Result<(), Self::Error>;
+
+    /// Returns the stored documents with the given ids, in the order of `ids`. Unknown ids are skipped.
+    async fn get(&self, ids: &[String]) -> Result<Vec<Document<M>>, Self::Error>;
+
+    /// Removes the documents with the given ids. Unknown ids are ignored.
+    async fn delete(&self, ids: &[String]) -> Result<(), Self::Error>;
+}
+
+/// A `DocStore` keeping documents in memory. Clones share the same documents.
+pub struct InMemoryDocStore<M>
+where
+    M: Serialize + DeserializeOwned,
+{
+    documents: Arc<RwLock<HashMap<String, Document<M>>>>,
+}
+
+impl<M> InMemoryDocStore<M>
+where
+    M: Serialize + DeserializeOwned,
+{
+    pub fn new() -> Self {
+        Self {
+            documents: Default::default(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.documents.read().expect("docstore lock poisoned").len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<M> Default for InMemoryDocStore<M>
+where
+    M: Serialize + DeserializeOwned,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M> Clone for InMemoryDocStore<M>
+where
+    M: Serialize + DeserializeOwned,
+{
+    fn clone(&self) -> Self {
+        Self {
+            documents: self.documents.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl<M> DocStore<M> for InMemoryDocStore<M>
+where
+    M: Serialize + DeserializeOwned + Clone + Send + Sync,
+{
+    type Error = Infallible;
+
+    async fn put(&self, documents: Vec<Document<M>>) -> Result<(), Infallible> {
+        let mut stored = self.documents.write().expect("docstore lock poisoned");
+        for document in documents {
+            if let Some(id) = document.id.clone() {
+                stored.insert(id, document);
+            }
+        }
+        Ok(())
+    }
+
+    async fn get(&self, ids: &[String]) -> Result<Vec<Document<M>>, Infallible> {
+        let stored = self.documents.read().expect("docstore lock poisoned");
+        Ok(ids
+            .iter()
+            .filter_map(|id| stored.get(id).cloned())
+            .collect())
+    }
+
+    async fn delete(&self, ids: &[String]) -> Result<(), Infallible> {
+        let mut stored = self.documents.write().expect("docstore lock poisoned");
+        for id in ids {
+            stored.remove(id);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,110 @@
+//! Maximal Marginal Relevance (MMR) selection.
+//!
+//! MMR picks results that are relevant to the query while being dissimilar to the results already picked, which
+//! keeps a handful of near-duplicate chunks from crowding out everything else in the context window.
+
+/// Computes the cosine similarity between two vectors.
+///
+/// Returns `0.0` if either vector has zero magnitude or the vectors have different lengths.
+///
+/// # Examples
+///
+/// ```
+/// use llm_chain::retrieval::cosine_similarity;
+/// assert_eq!(cosine_similarity(&[1.0, 0.0], &[1.0, 0.0]), 1.0);
+/// assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]), 0.0);
+/// ```
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+/// Selects up to `k` candidates using Maximal Marginal Relevance.
+///
+/// # Arguments
+///
+/// * `query` - The embedding of the query.
+/// * `candidates` - The embeddings of the candidate results.
+/// * `k` - The number of results to select.
+/// * `lambda` - Trade-off between relevance and diversity. `1.0` ranks purely by similarity to the query, `0.0`
+///   purely by dissimilarity to the results selected so far.
+///
+/// # Returns
+///
+/// The indices of the selected candidates, in selection order.
+pub fn maximal_marginal_relevance(
+    query: &[f32],
+    candidates: &[Vec<f32>],
+    k: usize,
+    lambda: f32,
+) -> Vec<usize> {
+    let query_similarity: Vec<f32> = candidates
+        .iter()
+        .map(|candidate| cosine_similarity(query, candidate))
+        .collect();
+
+    let mut selected: Vec<usize> = Vec::with_capacity(k.min(candidates.len()));
+    while selected.len() < k.min(candidates.len()) {
+        let best = (0..candidates.len())
+            .filter(|idx| !selected.contains(idx))
+            .map(|idx| {
+                let redundancy = selected
+                    .iter()
+                    .map(|&chosen| cosine_similarity(&candidates[idx], &candidates[chosen]))
+                    .fold(f32::MIN, f32::max);
+                let redundancy = if selected.is_empty() { 0.0 } else { redundancy };
+                let score = lambda * query_similarity[idx] - (1.0 - lambda) * redundancy;
+                (idx, score)
+            })
+            .max_by(|(_, a), (_, b)| a.total_cmp(b));
+        match best {
+            Some((idx, _)) => selected.push(idx),
+            None => break,
+        }
+    }
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::maximal_marginal_relevance;
+
+    #[test]
+    fn pure_relevance_matches_similarity_order() {
+        let query = vec![1.0, 0.0];
+        let candidates = vec![vec![0.0, 1.0], vec![1.0, 0.1], vec![1.0, 0.5]];
+        assert_eq!(
+            maximal_marginal_relevance(&query, &candidates, 3, 1.0),
+            vec![1, 2, 0]
+        );
+    }
+
+    #[test]
+    fn near_duplicates_are_skipped() {
+        let query = vec![1.0, 0.2];
+        let candidates = vec![vec![1.0, 0.2], vec![1.0, 0.21], vec![0.6, 0.8]];
+        assert_eq!(
+            maximal_marginal_relevance(&query, &candidates, 2, 0.5),
+            vec![0, 2]
+        );
+    }
+
+    #[test]
+    fn k_larger_than_candidates() {
+        let query = vec![1.0];
+        let candidates = vec![vec![1.0]];
+        assert_eq!(
+            maximal_marginal_relevance(&query, &candidates, 5, 0.5),
+            vec![0]
+        );
+    }
+}
@@ -0,0 +1,279 @@
+//! Retrievers find the documents relevant to a query.
+//!
+//! A `Retriever` is the part of a retrieval-augmented pipeline that turns a query into a list of documents. The
+//! `VectorStoreRetriever` provided here works on top of any `VectorStore` and supports two search types:
+//!
+//! - **Similarity**: the `limit` documents closest to the query.
+//! - **Maximal Marginal Relevance**: fetches `fetch_k` candidates and selects `limit` of them that are relevant to
+//!   the query but diverse among themselves, controlled by `lambda`.
+//!
+//! MMR embeds the query once, and compares the candidates using the embeddings they were stored with, so it works
+//! the same way with every vector store backend without embedding the documents again.
+//!
+//! The `AnswerCache` stores answers of retrieval-augmented chains and invalidates them when the chunks they were
+//! built from change, and `rerank` provides rerankers and a `RerankStep` reordering retrieved documents inside a
+//! sequential chain.
+//!
+//! The `ParentDocumentRetriever` indexes small chunks for precise matching, but returns the documents they were taken
+//! from, or a window of neighboring chunks, so that the model sees enough context. The parents are kept in a
+//! `DocStore`. The `MultiQueryRetriever` has the
+//! model rewrite a query several ways and merges what another retriever finds for each version.
+//!
+//! The `Bm25Index` ranks documents by keywords rather than embeddings. Its `LanguageAnalyzer` handles stop words,
+//! stemming and CJK scripts, so keyword scores make sense for non-English corpora too.
+mod answer_cache;
+mod bm25;
+mod docstore;
+mod mmr;
+mod multi_query;
+mod parent_document;
+pub mod rerank;
+
+use std::marker::PhantomData;
+
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+use crate::{
+    schema::{Document, EmptyMetadata, ScoredDocument},
+    traits::{Embeddings, EmbeddingsError, VectorStore, VectorStoreError},
+};
+
+pub use crate::language::Language;
+pub use answer_cache::{content_hash, AnswerCache, IndexObserver};
+pub use bm25::{Analyzer, Bm25Index, LanguageAnalyzer};
+pub use docstore::{DocStore, InMemoryDocStore};
+pub use mmr::{cosine_similarity, maximal_marginal_relevance};
+pub use multi_query::{parse_queries, MultiQueryRetriever, MultiQueryRetrieverError};
+pub use parent_document::{
+    ChunkMetadata, ParentDocumentRetriever, ParentDocumentRetrieverError, ParentScope,
+};
+
+/// A `Retriever` returns the documents relevant to a query.
+#[async_trait]
+pub trait Retriever<M = EmptyMetadata>: Send + Sync
+where
+    M: Serialize + DeserializeOwned,
+{
+    type Error: std::fmt::Debug + std::error::Error + Send;
+
+    /// Retrieves the documents relevant to `query`, most relevant first.
+    async fn retrieve(&self, query: String) -> Result<Vec<Document<M>>, Self::Error>;
+
+    /// Retrieves the documents relevant to `query` like `retrieve`, along with their relevance scores for
+    /// retrievers that know them. The default implementation returns no scores.
+    async fn retrieve_with_scores(
+        &self,
+        query: String,
+    ) -> Result<Vec<(Document<M>, Option<f32>)>, Self::Error> {
+        Ok(self
+            .retrieve(query)
+            .await?
+            .into_iter()
+            .map(|document| (document, None))
+            .collect())
+    }
+}
+
+/// The search strategy used by a `VectorStoreRetriever`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SearchType {
+    /// Return the documents most similar to the query.
+    #[default]
+    Similarity,
+    /// Return documents selected by Maximal Marginal Relevance among the `fetch_k` most similar ones.
+    Mmr {
+        /// Trade-off between relevance (`1.0`) and diversity (`0.0`).
+        lambda: f32,
+        /// The number of candidates fetched from the vector store before selection.
+        fetch_k: u32,
+    },
+}
+
+impl SearchType {
+    /// MMR with a balanced `lambda` of `0.5` and `fetch_k` of `20`.
+    pub fn mmr() -> Self {
+        Self::Mmr {
+            lambda: 0.5,
+            fetch_k: 20,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum VectorStoreRetrieverError<V, E>
+where
+    V: std::fmt::Debug + std::error::Error + VectorStoreError,
+    E: std::fmt::Debug + std::error::Error + EmbeddingsError,
+{
+    #[error(transparent)]
+    VectorStore(V),
+    #[error(transparent)]
+    Embeddings(E),
+}
+
+/// A `Retriever` backed by a `VectorStore`.
+///
+/// The embeddings provider should be the one used to index the store; it is only used to embed the query when
+/// searching with MMR.
+pub struct VectorStoreRetriever<E, M, V>
+where
+    E: Embeddings,
+    V: VectorStore<E, M>,
+    M: Serialize + DeserializeOwned,
+{
+    store: V,
+    embeddings: E,
+    limit: u32,
+    score_threshold: Option<f32>,
+    search_type: SearchType,
+    _marker: PhantomData<M>,
+}
+
+impl<E, M, V> VectorStoreRetriever<E, M, V>
+where
+    E: Embeddings,
+    V: VectorStore<E, M>,
+    M: Serialize + DeserializeOwned,
+{
+    /// Creates a retriever returning at most `limit` documents by similarity.
+    pub fn new(store: V, embeddings: E, limit: u32) -> Self {
+        Self {
+            store,
+            embeddings,
+            limit,
+            score_threshold: None,
+            search_type: SearchType::Similarity,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Sets the search type used by this retriever.
+    pub fn with_search_type(mut self, search_type: SearchType) -> Self {
+        self.search_type = search_type;
+        self
+    }
+
+    /// Leaves out documents whose similarity to the query is below `score_threshold`. When nothing in the store is
+    /// similar enough, the retriever returns no documents.
+    pub fn with_score_threshold(mut self, score_threshold: f32) -> Self {
+        self.score_threshold = Some(score_threshold);
+        self
+    }
+
+    /// Returns the underlying vector store.
+    pub fn store(&self) -> &V {
+        &self.store
+    }
+
+    /// Returns the embeddings provider used by this retriever.
+    pub fn embeddings(&self) -> &E {
+        &self.embeddings
+    }
+}
+
+#[async_trait]
+impl<E, M, V> Retriever<M> for VectorStoreRetriever<E, M, V>
+where
+    E: Embeddings + Send + Sync,
+    V: VectorStore<E, M> + Send + Sync,
+    V::Error: Send,
+    M: Serialize + DeserializeOwned + Send + Sync,
+{
+    type Error = VectorStoreRetrieverError<V::Error, E::Error>;
+
+    async fn retrieve(&self, query: String) -> Result<Vec<Document<M>>, Self::Error> {
+        Ok(self
+            .retrieve_with_scores(query)
+            .await?
+            .into_iter()
+            .map(|(document, _)| document)
+            .collect())
+    }
+
+    /// Returns the similarity of every document to the query, as reported by the vector store.
+    async fn retrieve_with_scores(
+        &self,
+        query: String,
+    ) -> Result<Vec<(Document<M>, Option<f32>)>, Self::Error> {
+        match self.search_type {
+            SearchType::Similarity => Ok(self
+                .store
+                .similarity_search_with_scores(query, self.limit, self.score_threshold)
+                .await
+                .map_err(VectorStoreRetrieverError::VectorStore)?
+                .into_iter()
+                .map(|scored| (scored.document, Some(scored.score)))
+                .collect()),
+            SearchType::Mmr { lambda, fetch_k } => {
+                let query_embedding = self
+                    .embeddings
+                    .embed_query(query)
+                    .await
+                    .map_err(VectorStoreRetrieverError::Embeddings)?;
+                let (candidates, candidate_embeddings): (Vec<_>, Vec<_>) = self
+                    .store
+                    .similarity_search_by_vector(
+                        query_embedding.clone(),
+                        fetch_k.max(self.limit),
+                        self.score_threshold,
+                    )
+                    .await
+                    .map_err(VectorStoreRetrieverError::VectorStore)?
+                    .into_iter()
+                    .unzip();
+                let selected = maximal_marginal_relevance(
+                    &query_embedding,
+                    &candidate_embeddings,
+                    self.limit as usize,
+                    lambda,
+                );
+                let mut candidates: Vec<Option<ScoredDocument<M>>> =
+                    candidates.into_iter().map(Some).collect();
+                Ok(selected
+                    .into_iter()
+                    .filter_map(|idx| candidates[idx].take())
+                    .map(|scored| (scored.document, Some(scored.score)))
+                    .collect())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Retriever, SearchType, VectorStoreRetriever};
+    use crate::schema::EmptyMetadata;
+    use crate::test_support::{letters, FnEmbeddings};
+    use crate::traits::VectorStore;
+    use crate::vectorstores::InMemoryVectorStore;
+    use futures::executor::block_on;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn mmr_embeds_only_the_query() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counting = |calls: Arc<AtomicUsize>| {
+            FnEmbeddings(move |text: &str| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                letters(text)
+            })
+        };
+        let store =
+            InMemoryVectorStore::<_, EmptyMetadata>::new(counting(Arc::new(AtomicUsize::new(0))));
+        block_on(store.add_texts(["aab", "aab ", "cc"].map(String::from).to_vec())).unwrap();
+        let retriever = VectorStoreRetriever::new(store, counting(calls.clone()), 2)
+            .with_search_type(SearchType::Mmr {
+                lambda: 0.5,
+                fetch_k: 3,
+            });
+        let documents = block_on(retriever.retrieve("aab".to_string())).unwrap();
+        let contents: Vec<_> = documents.iter().map(|d| d.page_content.as_str()).collect();
+        // The near duplicate of the best match is skipped for the diverse document.
+        assert_eq!(contents.len(), 2);
+        assert!(contents.contains(&"cc"));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
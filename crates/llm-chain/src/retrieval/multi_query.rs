@@ -0,0 +1,251 @@
+use std::collections::HashSet;
+use std::marker::PhantomData;
+
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+use super::Retriever;
+use crate::{
+    frame::{FormatAndExecuteError, Frame},
+    output::Output,
+    prompt,
+    schema::Document,
+    step::Step,
+    traits::{Executor, ExecutorError},
+    Parameters,
+};
+
+const DEFAULT_SYSTEM_PROMPT: &str = "You are an assistant helping to search a document database. Rewrite the user's question in different ways, so that documents using other words for the same thing are found too. Answer with one rewritten question per line, without numbering or any other text.";
+const DEFAULT_USER_PROMPT: &str =
+    "Write {{count}} different versions of this question:\n\n{{question}}";
+
+#[derive(Debug, Error)]
+pub enum MultiQueryRetrieverError<Err: ExecutorError, R: std::error::Error> {
+    #[error("Error generating queries: {0}")]
+    Generation(#[from] FormatAndExecuteError<Err>),
+    #[error("The model returned no text")]
+    NoTextOutput,
+    #[error("Retrieval failed: {0}")]
+    Retrieval(R),
+}
+
+lazy_static! {
+    /// A list marker at the start of a line: a number followed by `.` or `)`, or a bullet, and whitespace.
+    static ref LIST_MARKER: Regex = Regex::new(r"^\s*(?:\d+[.)]|[-*])\s+").unwrap();
+}
+
+/// Parses the queries generated by the model, one per line, removing list markers and blank lines. Numbers starting
+/// a query, as in `3D printing`, are kept.
+pub fn parse_queries(text: &str, max_queries: usize) -> Vec<String> {
+    text.lines()
+        .map(|line| {
+            LIST_MARKER
+                .replace(line, "")
+                .trim()
+                .trim_matches('"')
+                .to_string()
+        })
+        .filter(|line| !line.is_empty())
+        .take(max_queries)
+        .collect()
+}
+
+/// A `Retriever` that has the model rewrite the query several ways and merges the results of all of them.
+///
+/// Similarity search only finds documents that phrase things like the query does. Searching with several
+/// reformulations finds documents that the original wording misses. The results of every query are merged
+/// round-robin, so that the best matches of each query come first, and documents found by several queries are only
+/// returned once. Documents are considered the same when they have the same id or, without ids, the same content.
+///
+/// The generating step receives the `question` and the number of queries to write as `count`.
+pub struct MultiQueryRetriever<E, R, M>
+where
+    E: Executor,
+    R: Retriever<M>,
+    M: Serialize + DeserializeOwned,
+{
+    retriever: R,
+    executor: E,
+    step: Step<E>,
+    query_count: usize,
+    include_original: bool,
+    limit: Option<usize>,
+    _marker: PhantomData<fn() -> M>,
+}
+
+impl<E, R, M> MultiQueryRetriever<E, R, M>
+where
+    E: Executor,
+    R: Retriever<M>,
+    M: Serialize + DeserializeOwned,
+{
+    /// Creates a retriever searching `retriever` with three reformulations generated by `executor` besides the
+    /// original query.
+    pub fn new(retriever: R, executor: E) -> Self {
+        Self {
+            retriever,
+            executor,
+            step: Step::for_prompt_template(prompt!(DEFAULT_SYSTEM_PROMPT, DEFAULT_USER_PROMPT)),
+            query_count: 3,
+            include_original: true,
+            limit: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Sets the number of reformulations to generate.
+    pub fn with_query_count(mut self, query_count: usize) -> Self {
+        self.query_count = query_count;
+        self
+    }
+
+    /// Sets whether the original query is searched as well as the reformulations. Defaults to `true`.
+    pub fn with_original_query(mut self, include_original: bool) -> Self {
+        self.include_original = include_original;
+        self
+    }
+
+    /// Returns at most `limit` documents. By default every document found is returned.
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Sets the step generating the reformulations. Its prompt should use the `question` and `count` parameters and
+    /// ask for one query per line.
+    pub fn with_step(mut self, step: Step<E>) -> Self {
+        self.step = step;
+        self
+    }
+
+    /// Returns the retriever searched with each query.
+    pub fn retriever(&self) -> &R {
+        &self.retriever
+    }
+
+    /// Generates the queries that are searched for `query`, including `query` itself if the original is searched.
+    pub async fn generate_queries(
+        &self,
+        query: &str,
+    ) -> Result<Vec<String>, MultiQueryRetrieverError<E::Error, R::Error>> {
+        let parameters = Parameters::new_with_text(query)
+            .with("question", query)
+            .with("count", self.query_count.to_string());
+        let output = Frame::new(&self.executor, &self.step)
+            .format_and_execute(&parameters)
+            .await?;
+        let text = output
+            .primary_textual_output()
+            .await
+            .ok_or(MultiQueryRetrieverError::NoTextOutput)?;
+        let mut queries = Vec::new();
+        if self.include_original {
+            queries.push(query.to_string());
+        }
+        for generated in parse_queries(&text, self.query_count) {
+            if !queries.contains(&generated) {
+                queries.push(generated);
+            }
+        }
+        Ok(queries)
+    }
+}
+
+/// Merges ranked result lists round-robin, keeping the first occurrence of every document.
+fn merge_results<M>(results: Vec<Vec<Document<M>>>, limit: Option<usize>) -> Vec<Document<M>>
+where
+    M: Serialize + DeserializeOwned,
+{
+    let mut seen = HashSet::new();
+    let mut merged = Vec::new();
+    let mut iters: Vec<_> = results.into_iter().map(|r| r.into_iter()).collect();
+    let mut exhausted = false;
+    while !exhausted {
+        exhausted = true;
+        for iter in iters.iter_mut() {
+            let Some(document) = iter.next() else {
+                continue;
+            };
+            exhausted = false;
+            let key = match &document.id {
+                Some(id) => (true, id.clone()),
+                None => (false, document.page_content.clone()),
+            };
+            if seen.insert(key) {
+                merged.push(document);
+            }
+        }
+    }
+    if let Some(limit) = limit {
+        merged.truncate(limit);
+    }
+    merged
+}
+
+#[async_trait]
+impl<E, R, M> Retriever<M> for MultiQueryRetriever<E, R, M>
+where
+    E: Executor + Send + Sync,
+    E::Error: Send + 'static,
+    R: Retriever<M> + Send + Sync,
+    M: Serialize + DeserializeOwned + Send + Sync,
+{
+    type Error = MultiQueryRetrieverError<E::Error, R::Error>;
+
+    async fn retrieve(&self, query: String) -> Result<Vec<Document<M>>, Self::Error> {
+        let queries = self.generate_queries(&query).await?;
+        let results = futures::future::try_join_all(
+            queries
+                .into_iter()
+                .map(|query| self.retriever.retrieve(query)),
+        )
+        .await
+        .map_err(MultiQueryRetrieverError::Retrieval)?;
+        Ok(merge_results(results, self.limit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge_results, parse_queries};
+    use crate::schema::Document;
+
+    #[test]
+    fn parses_and_merges_queries() {
+        let text = "1. What is Rust?\n\n- \"Rust language overview\"\n2) Rust features\nextra";
+        assert_eq!(
+            parse_queries(text, 3),
+            vec!["What is Rust?", "Rust language overview", "Rust features"]
+        );
+        assert_eq!(
+            parse_queries(
+                "3D printing materials
+2024 results
+1. 3D printers
+10) 2024 election",
+                4
+            ),
+            vec![
+                "3D printing materials",
+                "2024 results",
+                "3D printers",
+                "2024 election"
+            ]
+        );
+
+        let doc = |content: &str| Document::<serde_json::Value>::new(content.to_string());
+        let merged = merge_results(
+            vec![
+                vec![doc("a"), doc("b"), doc("c")],
+                vec![doc("d"), doc("a")],
+                vec![],
+            ],
+            Some(3),
+        );
+        let contents: Vec<&str> = merged.iter().map(|d| d.page_content.as_str()).collect();
+        assert_eq!(contents, vec!["a", "d", "b"]);
+    }
+}
@@ -0,0 +1,479 @@
+use std::collections::HashSet;
+use std::marker::PhantomData;
+use std::ops::Range;
+
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+use super::{DocStore, InMemoryDocStore, Retriever};
+use crate::{
+    hash::stable_uuid,
+    schema::{Document, MetadataFilter, Provenance},
+    text_splitter::TextSplitter,
+    tokens::TokenizerError,
+    traits::{Embeddings, VectorStore, VectorStoreError},
+};
+
+/// What a `ParentDocumentRetriever` returns for a matching chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParentScope {
+    /// The whole parent document the chunk was taken from.
+    #[default]
+    Parent,
+    /// The matching chunk together with up to this many neighboring chunks on each side, taken from the parent.
+    Window(usize),
+}
+
+#[derive(Debug, Error)]
+pub enum ParentDocumentRetrieverError<V>
+where
+    V: std::fmt::Debug + std::error::Error + VectorStoreError,
+{
+    #[error(transparent)]
+    VectorStore(V),
+    #[error("Docstore error: {0}")]
+    DocStore(Box<dyn std::error::Error + Send + Sync>),
+    #[error(transparent)]
+    Tokenizer(#[from] TokenizerError),
+}
+
+/// The metadata of a chunk indexed by a `ParentDocumentRetriever`: where it was taken from, and the metadata of its
+/// parent.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkMetadata<M> {
+    /// The id of the parent document in the docstore.
+    pub parent_id: String,
+    /// The position of the chunk among the chunks of the parent.
+    pub chunk_index: usize,
+    /// The number of chunks of the parent.
+    pub chunk_count: usize,
+    /// The byte range of the chunk in the content of the parent.
+    pub range: Range<usize>,
+    /// The metadata of the parent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<M>,
+}
+
+/// Returns the id of the chunk of `parent_id` at `index`, which stays the same when the parent is indexed again.
+fn chunk_id(parent_id: &str, index: usize) -> String {
+    stable_uuid(&[parent_id, &index.to_string()])
+}
+
+/// A `Retriever` that matches queries against small chunks but returns the documents they were taken from.
+///
+/// Small chunks give precise similarity matches, while the larger parent documents give the model enough context to
+/// answer from. Documents added through `add_documents` are split into chunks that are indexed in the vector store,
+/// and kept whole in a `DocStore`. Each chunk records the id of its parent in its `ChunkMetadata`, so a retriever
+/// created on a persistent vector store and docstore resolves the parents of chunks indexed by another process. With
+/// `ParentScope::Window` only the neighborhood of each matching chunk is returned instead of the whole parent.
+///
+/// The docstore is in memory by default; `with_docstore` sets another one.
+pub struct ParentDocumentRetriever<E, M, V, S, T, D = InMemoryDocStore<M>>
+where
+    E: Embeddings,
+    V: VectorStore<E, ChunkMetadata<M>>,
+    M: Serialize + DeserializeOwned,
+    S: TextSplitter<T>,
+    T: Clone,
+{
+    store: V,
+    docstore: D,
+    splitter: S,
+    max_tokens_per_chunk: usize,
+    chunk_overlap: usize,
+    limit: u32,
+    fetch_k: u32,
+    scope: ParentScope,
+    _marker: PhantomData<fn() -> (E, T)>,
+    _metadata: PhantomData<fn() -> M>,
+}
+
+impl<E, M, V, S, T> ParentDocumentRetriever<E, M, V, S, T>
+where
+    E: Embeddings,
+    V: VectorStore<E, ChunkMetadata<M>>,
+    M: Serialize + DeserializeOwned + Clone,
+    S: TextSplitter<T>,
+    T: Clone,
+{
+    /// Creates a retriever indexing chunks of at most `max_tokens_per_chunk` tokens in `store` and returning at most
+    /// `limit` parent documents.
+    pub fn new(store: V, splitter: S, max_tokens_per_chunk: usize, limit: u32) -> Self {
+        Self {
+            store,
+            docstore: InMemoryDocStore::new(),
+            splitter,
+            max_tokens_per_chunk,
+            chunk_overlap: 0,
+            limit,
+            fetch_k: limit.saturating_mul(4),
+            scope: ParentScope::Parent,
+            _marker: PhantomData,
+            _metadata: PhantomData,
+        }
+    }
+}
+
+impl<E, M, V, S, T, D> ParentDocumentRetriever<E, M, V, S, T, D>
+where
+    E: Embeddings,
+    V: VectorStore<E, ChunkMetadata<M>>,
+    M: Serialize + DeserializeOwned + Clone,
+    S: TextSplitter<T>,
+    T: Clone,
+    D: DocStore<M>,
+{
+    /// Keeps the parent documents in `docstore`.
+    pub fn with_docstore<D2: DocStore<M>>(
+        self,
+        docstore: D2,
+    ) -> ParentDocumentRetriever<E, M, V, S, T, D2> {
+        ParentDocumentRetriever {
+            store: self.store,
+            docstore,
+            splitter: self.splitter,
+            max_tokens_per_chunk: self.max_tokens_per_chunk,
+            chunk_overlap: self.chunk_overlap,
+            limit: self.limit,
+            fetch_k: self.fetch_k,
+            scope: self.scope,
+            _marker: PhantomData,
+            _metadata: PhantomData,
+        }
+    }
+
+    /// Sets the number of tokens consecutive chunks share.
+    pub fn with_chunk_overlap(mut self, chunk_overlap: usize) -> Self {
+        self.chunk_overlap = chunk_overlap;
+        self
+    }
+
+    /// Sets the number of chunks fetched from the vector store. Several chunks can belong to the same parent, so this
+    /// should be larger than `limit`; it defaults to four times `limit`.
+    pub fn with_fetch_k(mut self, fetch_k: u32) -> Self {
+        self.fetch_k = fetch_k;
+        self
+    }
+
+    /// Sets what is returned for a matching chunk.
+    pub fn with_scope(mut self, scope: ParentScope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// Returns the vector store the chunks are indexed in.
+    pub fn store(&self) -> &V {
+        &self.store
+    }
+
+    /// Returns the docstore the parent documents are kept in.
+    pub fn docstore(&self) -> &D {
+        &self.docstore
+    }
+
+    /// Splits `documents` into chunks, indexes the chunks and keeps the documents as their parents.
+    ///
+    /// Documents without an id get a new one. Adding a document with the id of an existing parent replaces it and its
+    /// chunks. Returns the ids of the parent documents.
+    pub async fn add_documents(
+        &self,
+        documents: Vec<Document<M>>,
+    ) -> Result<Vec<String>, ParentDocumentRetrieverError<V::Error>> {
+        let mut parent_ids = Vec::with_capacity(documents.len());
+        let mut chunk_documents = Vec::new();
+        let mut parents = Vec::with_capacity(documents.len());
+        for document in documents {
+            let parent_id = document
+                .id
+                .clone()
+                .unwrap_or_else(|| Uuid::new_v4().to_string());
+            let base = document
+                .provenance
+                .clone()
+                .unwrap_or_else(|| Provenance::new(&parent_id, document.page_content.len()));
+            let chunks = self.splitter.split_text_with_offsets(
+                &document.page_content,
+                self.max_tokens_per_chunk,
+                self.chunk_overlap,
+            )?;
+            let chunk_count = chunks.len();
+            for (index, (range, content)) in chunks.into_iter().enumerate() {
+                chunk_documents.push(Document {
+                    id: Some(chunk_id(&parent_id, index)),
+                    page_content: content,
+                    metadata: Some(ChunkMetadata {
+                        parent_id: parent_id.clone(),
+                        chunk_index: index,
+                        chunk_count,
+                        range: range.clone(),
+                        metadata: document.metadata.clone(),
+                    }),
+                    provenance: Some(base.sub_range(range.start, range.end)),
+                });
+            }
+            parent_ids.push(parent_id.clone());
+            parents.push(document.with_id(parent_id));
+        }
+
+        self.delete_documents(&parent_ids).await?;
+        self.docstore
+            .put(parents)
+            .await
+            .map_err(|e| ParentDocumentRetrieverError::DocStore(Box::new(e)))?;
+        self.store
+            .upsert_documents(chunk_documents)
+            .await
+            .map_err(ParentDocumentRetrieverError::VectorStore)?;
+        Ok(parent_ids)
+    }
+
+    /// Removes parent documents and deletes their chunks from the vector store.
+    pub async fn delete_documents(
+        &self,
+        parent_ids: &[String],
+    ) -> Result<(), ParentDocumentRetrieverError<V::Error>> {
+        if parent_ids.is_empty() {
+            return Ok(());
+        }
+        let filter = MetadataFilter::In {
+            key: "parent_id".to_string(),
+            values: parent_ids.iter().map(|id| id.as_str().into()).collect(),
+        };
+        self.store
+            .delete_by_filter(&filter)
+            .await
+            .map_err(ParentDocumentRetrieverError::VectorStore)?;
+        self.docstore
+            .delete(parent_ids)
+            .await
+            .map_err(|e| ParentDocumentRetrieverError::DocStore(Box::new(e)))
+    }
+
+    /// Returns the parent document with the given id.
+    pub async fn get_parent(
+        &self,
+        parent_id: &str,
+    ) -> Result<Option<Document<M>>, ParentDocumentRetrieverError<V::Error>> {
+        let mut parents = self
+            .docstore
+            .get(&[parent_id.to_string()])
+            .await
+            .map_err(|e| ParentDocumentRetrieverError::DocStore(Box::new(e)))?;
+        Ok(parents.pop())
+    }
+
+    /// Maps matching chunks, most relevant first, to the parents or windows to return.
+    async fn resolve(
+        &self,
+        chunks: Vec<Document<ChunkMetadata<M>>>,
+    ) -> Result<Vec<Document<M>>, ParentDocumentRetrieverError<V::Error>> {
+        // The parent of each result, the range of its chunks it covers and its number of chunks.
+        let mut selected: Vec<(String, Range<usize>, usize)> = Vec::new();
+        for chunk in chunks {
+            if selected.len() == self.limit as usize {
+                break;
+            }
+            let Some(ChunkMetadata {
+                parent_id,
+                chunk_index,
+                chunk_count,
+                ..
+            }) = chunk.metadata
+            else {
+                continue;
+            };
+            let window = match self.scope {
+                ParentScope::Parent => 0..chunk_count,
+                ParentScope::Window(n) => {
+                    chunk_index.saturating_sub(n)..usize::min(chunk_index + n + 1, chunk_count)
+                }
+            };
+            // Windows of the same parent that touch are merged so that no text is returned twice.
+            let existing = selected.iter_mut().find(|(id, range, _)| {
+                *id == parent_id && window.start <= range.end && range.start <= window.end
+            });
+            if let Some((_, range, _)) = existing {
+                range.start = range.start.min(window.start);
+                range.end = range.end.max(window.end);
+            } else {
+                selected.push((parent_id, window, chunk_count));
+            }
+        }
+
+        let mut ids: Vec<String> = Vec::new();
+        let mut seen = HashSet::new();
+        for (parent_id, _, _) in &selected {
+            if seen.insert(parent_id) {
+                ids.push(parent_id.clone());
+            }
+        }
+        let parents = self
+            .docstore
+            .get(&ids)
+            .await
+            .map_err(|e| ParentDocumentRetrieverError::DocStore(Box::new(e)))?;
+
+        let mut documents = Vec::with_capacity(selected.len());
+        for (parent_id, window, chunk_count) in selected {
+            // Parents missing from the docstore were deleted since their chunks were found.
+            let Some(parent) = parents.iter().find(|p| p.id.as_ref() == Some(&parent_id)) else {
+                continue;
+            };
+            if window.start == 0 && window.end >= chunk_count {
+                documents.push(parent.clone());
+                continue;
+            }
+            let edges = self
+                .store
+                .get_by_ids(&[
+                    chunk_id(&parent_id, window.start),
+                    chunk_id(&parent_id, window.end - 1),
+                ])
+                .await
+                .map_err(ParentDocumentRetrieverError::VectorStore)?;
+            let ranges: Vec<Range<usize>> = edges
+                .into_iter()
+                .filter_map(|chunk| chunk.metadata.map(|metadata| metadata.range))
+                .collect();
+            let (Some(first), Some(last)) = (ranges.first(), ranges.last()) else {
+                continue;
+            };
+            let (start, end) = (first.start, last.end.max(first.start));
+            let base = parent
+                .provenance
+                .clone()
+                .unwrap_or_else(|| Provenance::new(parent_id.as_str(), parent.page_content.len()));
+            documents.push(Document {
+                id: None,
+                page_content: parent.page_content[start..end].to_string(),
+                metadata: parent.metadata.clone(),
+                provenance: Some(base.sub_range(start, end)),
+            });
+        }
+        Ok(documents)
+    }
+}
+
+#[async_trait]
+impl<E, M, V, S, T, D> Retriever<M> for ParentDocumentRetriever<E, M, V, S, T, D>
+where
+    E: Embeddings + Send + Sync,
+    V: VectorStore<E, ChunkMetadata<M>> + Send + Sync,
+    V::Error: Send,
+    M: Serialize + DeserializeOwned + Clone + Send + Sync,
+    S: TextSplitter<T> + Send + Sync,
+    T: Clone,
+    D: DocStore<M>,
+{
+    type Error = ParentDocumentRetrieverError<V::Error>;
+
+    async fn retrieve(&self, query: String) -> Result<Vec<Document<M>>, Self::Error> {
+        let chunks = self
+            .store
+            .similarity_search(query, self.fetch_k.max(self.limit))
+            .await
+            .map_err(ParentDocumentRetrieverError::VectorStore)?;
+        self.resolve(chunks).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{chunk_id, ChunkMetadata, ParentDocumentRetriever, ParentScope};
+    use crate::retrieval::{InMemoryDocStore, Retriever};
+    use crate::schema::Document;
+    use crate::test_support::LetterEmbeddings;
+    use crate::text_splitter::NaiveWhitespaceSplitter;
+    use crate::traits::VectorStore;
+    use crate::vectorstores::InMemoryVectorStore;
+    use futures::executor::block_on;
+
+    type Store = InMemoryVectorStore<LetterEmbeddings, ChunkMetadata<serde_json::Value>>;
+
+    #[test]
+    fn returns_parents_and_windows() {
+        let retriever = ParentDocumentRetriever::new(
+            Store::new(LetterEmbeddings),
+            NaiveWhitespaceSplitter,
+            1,
+            1,
+        );
+        block_on(async {
+            let ids = retriever
+                .add_documents(vec![
+                    Document::new("aa bb cc".to_string()),
+                    Document::new("ab ac".to_string()).with_id("second"),
+                ])
+                .await
+                .unwrap();
+            assert_eq!(ids[1], "second");
+            assert_eq!(retriever.store().len(), 5);
+
+            let found = retriever.retrieve("bb".to_string()).await.unwrap();
+            assert_eq!(found.len(), 1);
+            assert_eq!(found[0].page_content, "aa bb cc");
+            assert_eq!(found[0].id.as_ref(), Some(&ids[0]));
+
+            let retriever = retriever.with_scope(ParentScope::Window(1));
+            let found = retriever.retrieve("cc".to_string()).await.unwrap();
+            assert_eq!(found[0].page_content, "bb cc");
+            let provenance = found[0].provenance.as_ref().unwrap();
+            assert_eq!((provenance.start, provenance.end), (3, 8));
+
+            retriever.delete_documents(&ids[..1]).await.unwrap();
+            assert_eq!(retriever.store().len(), 2);
+            assert!(retriever.get_parent(&ids[0]).await.unwrap().is_none());
+        });
+    }
+
+    #[test]
+    fn resolves_parents_through_the_stores() {
+        let docstore = InMemoryDocStore::new();
+        let retriever = ParentDocumentRetriever::new(
+            Store::new(LetterEmbeddings),
+            NaiveWhitespaceSplitter,
+            1,
+            1,
+        )
+        .with_docstore(docstore.clone());
+        block_on(async {
+            let document = Document::new("aa bb cc".to_string())
+                .with_id("doc")
+                .with_metadata(serde_json::json!({"author": "Ada"}));
+            retriever.add_documents(vec![document]).await.unwrap();
+            // Indexing again replaces the chunks instead of adding new ones.
+            retriever
+                .add_documents(vec![Document::new("aa bb cc".to_string()).with_id("doc")])
+                .await
+                .unwrap();
+            assert_eq!(retriever.store().len(), 3);
+            assert_eq!(docstore.len(), 1);
+
+            let chunk_ids: Vec<String> = (0..3).map(|index| chunk_id("doc", index)).collect();
+            let chunks = retriever.store().get_by_ids(&chunk_ids).await.unwrap();
+            let metadata = chunks[1].metadata.as_ref().unwrap();
+            assert_eq!(metadata.parent_id, "doc");
+            assert_eq!((metadata.chunk_index, metadata.range.clone()), (1, 3..5));
+
+            // A retriever opening the same stores, as after a restart, finds the parents of the chunks.
+            let store = Store::new(LetterEmbeddings);
+            store.upsert_documents(chunks).await.unwrap();
+            let reopened = ParentDocumentRetriever::new(store, NaiveWhitespaceSplitter, 1, 1)
+                .with_docstore(docstore)
+                .with_scope(ParentScope::Window(0));
+            let found = reopened.retrieve("cc".to_string()).await.unwrap();
+            assert_eq!(found[0].page_content, "cc");
+            assert_eq!(
+                reopened
+                    .get_parent("doc")
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .page_content,
+                "aa bb cc"
+            );
+        });
+    }
+}
@@ -0,0 +1,322 @@
+//! Reranking retrieved documents.
+//!
+//! Vector similarity is a cheap but coarse relevance signal. A reranker scores every retrieved document against the
+//! query with a more precise model, such as Cohere's rerank API or a local cross-encoder, so only the best documents
+//! are stuffed into the prompt. `RerankStep` runs a reranker as part of a sequential chain.
+use std::marker::PhantomData;
+
+use async_trait::async_trait;
+use reqwest::Method;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    schema::{Document, EmptyMetadata},
+    step::{CustomStep, CustomStepError, StepOutcome},
+    traits::Executor,
+    Parameters,
+};
+
+const COHERE_RERANK_URL: &str = "https://api.cohere.ai/v1/rerank";
+const DEFAULT_COHERE_MODEL: &str = "rerank-english-v2.0";
+
+/// The relevance of one document to a query, as determined by a `Reranker`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RerankResult {
+    /// The index of the document in the list passed to the reranker.
+    pub index: usize,
+    /// The relevance score; higher is more relevant.
+    pub relevance_score: f32,
+}
+
+/// A `Reranker` orders documents by their relevance to a query.
+#[async_trait]
+pub trait Reranker: Send + Sync {
+    type Error: std::fmt::Debug + std::error::Error + Send + Sync + 'static;
+
+    /// Scores `documents` against `query` and returns the results ordered by decreasing relevance.
+    async fn rerank(
+        &self,
+        query: &str,
+        documents: &[String],
+    ) -> Result<Vec<RerankResult>, Self::Error>;
+}
+
+#[derive(Debug, Error)]
+pub enum CohereRerankError {
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+}
+
+#[derive(Serialize)]
+struct CohereRerankRequest<'a> {
+    model: &'a str,
+    query: &'a str,
+    documents: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct CohereRerankResponse {
+    results: Vec<CohereRerankResult>,
+}
+
+#[derive(Deserialize)]
+struct CohereRerankResult {
+    index: usize,
+    relevance_score: f32,
+}
+
+/// A `Reranker` using Cohere's rerank API.
+pub struct CohereReranker {
+    client: reqwest::Client,
+    api_key: String,
+    model: String,
+}
+
+impl CohereReranker {
+    /// Creates a reranker using the `rerank-english-v2.0` model.
+    pub fn new(api_key: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            api_key,
+            model: DEFAULT_COHERE_MODEL.to_string(),
+        }
+    }
+
+    /// Sets the rerank model to use, e.g. `rerank-multilingual-v2.0`.
+    pub fn with_model<S: Into<String>>(mut self, model: S) -> Self {
+        self.model = model.into();
+        self
+    }
+}
+
+#[async_trait]
+impl Reranker for CohereReranker {
+    type Error = CohereRerankError;
+
+    async fn rerank(
+        &self,
+        query: &str,
+        documents: &[String],
+    ) -> Result<Vec<RerankResult>, Self::Error> {
+        let response = self
+            .client
+            .request(Method::POST, COHERE_RERANK_URL)
+            .bearer_auth(&self.api_key)
+            .json(&CohereRerankRequest {
+                model: &self.model,
+                query,
+                documents,
+            })
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<CohereRerankResponse>()
+            .await?;
+        let mut results: Vec<RerankResult> = response
+            .results
+            .into_iter()
+            .map(|r| RerankResult {
+                index: r.index,
+                relevance_score: r.relevance_score,
+            })
+            .collect();
+        results.sort_by(|a, b| b.relevance_score.total_cmp(&a.relevance_score));
+        Ok(results)
+    }
+}
+
+/// A cross-encoder scores a query and a document together, e.g. a local `ms-marco` model.
+#[async_trait]
+pub trait CrossEncoder: Send + Sync {
+    type Error: std::fmt::Debug + std::error::Error + Send + Sync + 'static;
+
+    /// Returns one relevance score per document, in the order of `documents`.
+    async fn score(&self, query: &str, documents: &[String]) -> Result<Vec<f32>, Self::Error>;
+}
+
+/// A `Reranker` backed by a `CrossEncoder`.
+pub struct CrossEncoderReranker<C> {
+    encoder: C,
+}
+
+impl<C: CrossEncoder> CrossEncoderReranker<C> {
+    pub fn new(encoder: C) -> Self {
+        Self { encoder }
+    }
+}
+
+#[async_trait]
+impl<C: CrossEncoder> Reranker for CrossEncoderReranker<C> {
+    type Error = C::Error;
+
+    async fn rerank(
+        &self,
+        query: &str,
+        documents: &[String],
+    ) -> Result<Vec<RerankResult>, Self::Error> {
+        let scores = self.encoder.score(query, documents).await?;
+        let mut results: Vec<RerankResult> = scores
+            .into_iter()
+            .enumerate()
+            .map(|(index, relevance_score)| RerankResult {
+                index,
+                relevance_score,
+            })
+            .collect();
+        results.sort_by(|a, b| b.relevance_score.total_cmp(&a.relevance_score));
+        Ok(results)
+    }
+}
+
+/// Reorders documents according to `results`, keeping at most `top_n` of them.
+pub fn apply_rerank<M>(
+    documents: Vec<Document<M>>,
+    results: &[RerankResult],
+    top_n: Option<usize>,
+) -> Vec<Document<M>>
+where
+    M: Serialize + DeserializeOwned,
+{
+    let mut documents: Vec<Option<Document<M>>> = documents.into_iter().map(Some).collect();
+    results
+        .iter()
+        .filter_map(|r| documents.get_mut(r.index).and_then(Option::take))
+        .take(top_n.unwrap_or(usize::MAX))
+        .collect()
+}
+
+#[derive(Debug, Error)]
+pub enum RerankStepError {
+    #[error("parameter `{0}` is missing")]
+    MissingParameter(String),
+    #[error("parameter `{0}` does not hold a list of documents")]
+    NotDocuments(String),
+}
+
+/// A chain step that reranks the documents held in a parameter.
+///
+/// The documents are read from the `documents` parameter, which must hold a `Vec<Document<M>>` added with
+/// `Parameters::with_dynamic`, and are scored against the `query` parameter (falling back to `text`). The reordered,
+/// and optionally truncated, documents replace the original ones, so the following prompt step sees the most
+/// relevant documents first.
+///
+/// # Example
+///
+/// ```ignore
+/// let chain = Chain::from_steps(vec![
+///     ChainStep::custom(RerankStep::<_, EmptyMetadata>::new(CohereReranker::new(api_key)).with_top_n(3)),
+///     Step::for_prompt_template(prompt!("Answer {{query}} using:\n{{documents}}")).into(),
+/// ]);
+/// ```
+pub struct RerankStep<R, M = EmptyMetadata> {
+    reranker: R,
+    query_key: String,
+    documents_key: String,
+    top_n: Option<usize>,
+    _marker: PhantomData<fn() -> M>,
+}
+
+impl<R: Reranker, M> RerankStep<R, M>
+where
+    M: Serialize + DeserializeOwned + Clone + std::fmt::Debug + Send + Sync + 'static,
+{
+    pub fn new(reranker: R) -> Self {
+        Self {
+            reranker,
+            query_key: "query".to_string(),
+            documents_key: "documents".to_string(),
+            top_n: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Sets the parameter holding the query. Defaults to `query`.
+    pub fn with_query_key<S: Into<String>>(mut self, key: S) -> Self {
+        self.query_key = key.into();
+        self
+    }
+
+    /// Sets the parameter holding the documents. Defaults to `documents`.
+    pub fn with_documents_key<S: Into<String>>(mut self, key: S) -> Self {
+        self.documents_key = key.into();
+        self
+    }
+
+    /// Keeps only the `top_n` most relevant documents.
+    pub fn with_top_n(mut self, top_n: usize) -> Self {
+        self.top_n = Some(top_n);
+        self
+    }
+
+    /// Reranks the documents held in `parameters` and returns the updated parameters.
+    pub async fn rerank_parameters(
+        &self,
+        parameters: &Parameters,
+    ) -> Result<Parameters, CustomStepError> {
+        let query = parameters
+            .get(&self.query_key)
+            .or_else(|| parameters.get_text())
+            .ok_or_else(|| RerankStepError::MissingParameter(self.query_key.clone()))?;
+        let documents = parameters
+            .get_dynamic::<Vec<Document<M>>>(&self.documents_key)
+            .ok_or_else(|| match parameters.get(&self.documents_key) {
+                Some(_) => RerankStepError::NotDocuments(self.documents_key.clone()),
+                None => RerankStepError::MissingParameter(self.documents_key.clone()),
+            })?
+            .clone();
+        let contents: Vec<String> = documents.iter().map(|d| d.page_content.clone()).collect();
+        let results = self.reranker.rerank(&query, &contents).await?;
+        let reranked = apply_rerank(documents, &results, self.top_n);
+        Ok(parameters.with_dynamic(self.documents_key.clone(), reranked))
+    }
+}
+
+#[async_trait]
+impl<E, R, M> CustomStep<E> for RerankStep<R, M>
+where
+    E: Executor + Sync,
+    R: Reranker,
+    M: Serialize + DeserializeOwned + Clone + std::fmt::Debug + Send + Sync + 'static,
+{
+    async fn run(
+        &self,
+        parameters: &Parameters,
+        _executor: &E,
+    ) -> Result<StepOutcome<E::Output>, CustomStepError> {
+        Ok(StepOutcome::parameters(
+            self.rerank_parameters(parameters).await?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_rerank, RerankResult};
+    use crate::schema::{Document, EmptyMetadata};
+
+    #[test]
+    fn apply_rerank_reorders_and_truncates() {
+        let documents: Vec<Document<EmptyMetadata>> = ["a", "b", "c"]
+            .into_iter()
+            .map(|s| Document::new(s.to_string()))
+            .collect();
+        let results = [
+            RerankResult {
+                index: 2,
+                relevance_score: 0.9,
+            },
+            RerankResult {
+                index: 0,
+                relevance_score: 0.5,
+            },
+            RerankResult {
+                index: 1,
+                relevance_score: 0.1,
+            },
+        ];
+        let reranked = apply_rerank(documents, &results, Some(2));
+        let contents: Vec<_> = reranked.iter().map(|d| d.page_content.as_str()).collect();
+        assert_eq!(contents, ["c", "a"]);
+    }
+}
@@ -1,14 +1,61 @@
 //! Schema for Documents that can be stored in vector stores.
 //!
 //! This schema is used to store documents in vector stores. It is used to store the document's content and metadata.
+//! Documents can also record their provenance: the source they were loaded from and the byte range they cover in
+//! it, so that citations can point to the exact passage a chunk came from.
+use crate::parameters::Param;
 
-#[derive(Debug)]
+/// Where the content of a document comes from.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Provenance {
+    /// The identifier of the source document, such as a path or URL.
+    pub source_id: String,
+    /// The page of the source the content was found on, for paged formats such as PDF.
+    pub page: Option<u32>,
+    /// The byte offset in the source where the content starts.
+    pub start: usize,
+    /// The byte offset in the source where the content ends (exclusive).
+    pub end: usize,
+}
+
+impl Provenance {
+    /// Provenance covering `len` bytes from the start of the source.
+    pub fn new<S: Into<String>>(source_id: S, len: usize) -> Self {
+        Self {
+            source_id: source_id.into(),
+            page: None,
+            start: 0,
+            end: len,
+        }
+    }
+
+    /// Sets the page the content was found on.
+    pub fn with_page(mut self, page: u32) -> Self {
+        self.page = Some(page);
+        self
+    }
+
+    /// Returns the provenance of the byte range `start..end` relative to this one.
+    pub fn sub_range(&self, start: usize, end: usize) -> Self {
+        Self {
+            source_id: self.source_id.clone(),
+            page: self.page,
+            start: self.start + start,
+            end: self.start + end,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Document<M = EmptyMetadata>
 where
     M: serde::Serialize + serde::de::DeserializeOwned,
 {
+    /// The identifier of the document in a vector store, if it has one.
+    pub id: Option<String>,
     pub page_content: String,
     pub metadata: Option<M>,
+    pub provenance: Option<Provenance>,
 }
 
 impl<M> Document<M>
@@ -17,13 +64,115 @@ where
 {
     pub fn new(page_content: String) -> Self {
         Document {
+            id: None,
             page_content,
             metadata: None,
+            provenance: None,
+        }
+    }
+
+    /// Creates a document for the full content of a source.
+    pub fn from_source<S: Into<String>>(source_id: S, page_content: String) -> Self {
+        let provenance = Provenance::new(source_id, page_content.len());
+        Document {
+            id: None,
+            page_content,
+            metadata: None,
+            provenance: Some(provenance),
+        }
+    }
+
+    pub fn with_id<S: Into<String>>(mut self, id: S) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    pub fn with_metadata(mut self, metadata: M) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    pub fn with_provenance(mut self, provenance: Provenance) -> Self {
+        self.provenance = Some(provenance);
+        self
+    }
+}
+
+/// A document returned by a similarity search, together with its similarity to the query.
+#[derive(Debug, Clone)]
+pub struct ScoredDocument<M = EmptyMetadata>
+where
+    M: serde::Serialize + serde::de::DeserializeOwned,
+{
+    pub document: Document<M>,
+    /// The similarity between the document and the query; higher is more similar. The scale depends on the
+    /// distance metric of the vector store, e.g. cosine similarity.
+    pub score: f32,
+}
+
+/// A filter on document metadata, used to select documents in a vector store.
+///
+/// Keys are paths into the metadata serialized as JSON, with nested fields separated by dots, e.g. `author.name`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum MetadataFilter {
+    /// The value at `key` equals `value`.
+    Equals {
+        key: String,
+        value: serde_json::Value,
+    },
+    /// The value at `key` equals any of `values`.
+    In {
+        key: String,
+        values: Vec<serde_json::Value>,
+    },
+    /// All of the filters match.
+    And(Vec<MetadataFilter>),
+    /// Any of the filters matches.
+    Or(Vec<MetadataFilter>),
+    /// The filter doesn't match.
+    Not(Box<MetadataFilter>),
+}
+
+impl MetadataFilter {
+    /// A filter matching documents whose metadata has `value` at `key`.
+    pub fn equals<K: Into<String>, V: Into<serde_json::Value>>(key: K, value: V) -> Self {
+        MetadataFilter::Equals {
+            key: key.into(),
+            value: value.into(),
+        }
+    }
+
+    /// Returns `true` if the filter matches the given metadata.
+    pub fn matches(&self, metadata: &serde_json::Value) -> bool {
+        let lookup = |key: &str| {
+            key.split('.')
+                .try_fold(metadata, |value, field| value.get(field))
+        };
+        match self {
+            MetadataFilter::Equals { key, value } => lookup(key) == Some(value),
+            MetadataFilter::In { key, values } => lookup(key).is_some_and(|v| values.contains(v)),
+            MetadataFilter::And(filters) => filters.iter().all(|f| f.matches(metadata)),
+            MetadataFilter::Or(filters) => filters.iter().any(|f| f.matches(metadata)),
+            MetadataFilter::Not(filter) => !filter.matches(metadata),
         }
     }
 }
 
-#[derive(Debug)]
+/// A list of documents can be passed between steps as a dynamic parameter. Formatted into a prompt, the documents'
+/// contents are separated by blank lines.
+impl<M> Param for Vec<Document<M>>
+where
+    M: serde::Serialize + serde::de::DeserializeOwned + Send + Sync,
+{
+    fn get(&self) -> String {
+        self.iter()
+            .map(|doc| doc.page_content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct EmptyMetadata;
 
 impl From<()> for EmptyMetadata {
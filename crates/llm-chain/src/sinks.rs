@@ -0,0 +1,390 @@
+//! Sinks stream the results of chain runs out as they are produced.
+//!
+//! Batch runs over many inputs shouldn't have to keep every result in memory until the end. A `ResultSink` receives
+//! results one at a time and delivers them somewhere else:
+//!
+//! - `WebhookSink` POSTs each result as JSON to a URL, retrying transient failures.
+//! - `FileSink` appends each result as a line of JSON to a file.
+//! - `ChannelSink` sends results into a bounded channel for the application to consume.
+//!
+//! `QueuedSink` puts a bounded queue in front of any sink, so that producers can go on while results are being
+//! delivered, and are slowed down once the queue is full instead of buffering without limit.
+//!
+//! `Chain::run_all_into` runs a chain over a batch of inputs and sends a `BatchResult` to a sink as each run
+//! finishes.
+//!
+//! This module requires the `async` feature.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let (sink, worker) = QueuedSink::new(WebhookSink::new("https://example.com/results"), 100);
+//! let worker = tokio::spawn(worker);
+//! chain.run_all_into(inputs, &executor, 4, &sink).await?;
+//! sink.close().await;
+//! let report = worker.await?;
+//! ```
+use std::path::Path;
+use std::sync::Mutex as StdMutex;
+use std::task::Poll;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::channel::{mpsc, oneshot};
+use futures::lock::Mutex;
+use futures::{ready, Future, StreamExt};
+use serde::Serialize;
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+
+#[derive(Debug, Error)]
+pub enum SinkError {
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[error("The webhook responded with status {0}")]
+    Status(u16),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+    #[error("The sink is closed")]
+    Closed,
+}
+
+/// The result of one input of a batch run, as sent by `Chain::run_all_into`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BatchResult {
+    /// The position of the input in the batch.
+    pub index: usize,
+    /// The text output of the run, when it succeeded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// The error of the run, when it failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A destination for results.
+#[async_trait]
+pub trait ResultSink<T>: Send + Sync
+where
+    T: Send + 'static,
+{
+    /// Delivers a single result.
+    async fn send(&self, item: T) -> Result<(), SinkError>;
+
+    /// Makes sure the results sent so far have been delivered.
+    async fn flush(&self) -> Result<(), SinkError> {
+        Ok(())
+    }
+}
+
+/// Sends each result as a JSON POST request to a URL.
+///
+/// Requests failing with a connection error, a `429` or a `5xx` status are retried with exponential backoff.
+pub struct WebhookSink {
+    client: reqwest::Client,
+    url: String,
+    max_retries: u32,
+    retry_delay: Duration,
+}
+
+impl WebhookSink {
+    pub fn new<S: Into<String>>(url: S) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.into(),
+            max_retries: 3,
+            retry_delay: Duration::from_millis(500),
+        }
+    }
+
+    /// Uses `client` to send requests, for example to add authentication headers.
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Sets how many times a request is retried, and the delay before the first retry. The delay doubles with every
+    /// retry.
+    pub fn with_retries(mut self, max_retries: u32, retry_delay: Duration) -> Self {
+        self.max_retries = max_retries;
+        self.retry_delay = retry_delay;
+        self
+    }
+}
+
+#[async_trait]
+impl<T> ResultSink<T> for WebhookSink
+where
+    T: Serialize + Send + Sync + 'static,
+{
+    async fn send(&self, item: T) -> Result<(), SinkError> {
+        let mut delay = self.retry_delay;
+        let mut attempt = 0;
+        loop {
+            let error = match self.client.post(&self.url).json(&item).send().await {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) => {
+                    let status = response.status();
+                    if !(status.is_server_error() || status.as_u16() == 429) {
+                        return Err(SinkError::Status(status.as_u16()));
+                    }
+                    SinkError::Status(status.as_u16())
+                }
+                Err(e) if e.is_connect() || e.is_timeout() => e.into(),
+                Err(e) => return Err(e.into()),
+            };
+            if attempt >= self.max_retries {
+                return Err(error);
+            }
+            attempt += 1;
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+    }
+}
+
+/// Appends each result as a line of JSON to a file.
+pub struct FileSink {
+    file: Mutex<tokio::fs::File>,
+}
+
+impl FileSink {
+    /// Opens `path` for appending, creating the file if it doesn't exist.
+    pub async fn open<P: AsRef<Path>>(path: P) -> Result<Self, SinkError> {
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+}
+
+#[async_trait]
+impl<T> ResultSink<T> for FileSink
+where
+    T: Serialize + Send + Sync + 'static,
+{
+    async fn send(&self, item: T) -> Result<(), SinkError> {
+        let mut line = serde_json::to_vec(&item)?;
+        line.push(b'\n');
+        self.file.lock().await.write_all(&line).await?;
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<(), SinkError> {
+        self.file.lock().await.flush().await?;
+        Ok(())
+    }
+}
+
+/// Sends results into a bounded channel. Sending waits while the channel is full.
+pub struct ChannelSink<T> {
+    // Only locked while polling, never across an await, so a send waiting for room doesn't block `close`.
+    sender: StdMutex<mpsc::Sender<T>>,
+}
+
+impl<T> ChannelSink<T> {
+    /// Creates a sink and the receiving end of its channel, which holds up to `capacity` results.
+    pub fn new(capacity: usize) -> (Self, mpsc::Receiver<T>) {
+        let (sender, receiver) = mpsc::channel(capacity);
+        (
+            Self {
+                sender: StdMutex::new(sender),
+            },
+            receiver,
+        )
+    }
+
+    /// Closes the channel. The receiver gets the results already sent, and further sends fail.
+    pub fn close(&self) {
+        self.sender
+            .lock()
+            .expect("channel sink lock poisoned")
+            .close_channel();
+    }
+}
+
+#[async_trait]
+impl<T> ResultSink<T> for ChannelSink<T>
+where
+    T: Send + 'static,
+{
+    async fn send(&self, item: T) -> Result<(), SinkError> {
+        let mut item = Some(item);
+        futures::future::poll_fn(|cx| {
+            let mut sender = self.sender.lock().expect("channel sink lock poisoned");
+            ready!(sender.poll_ready(cx)).map_err(|_| SinkError::Closed)?;
+            let item = item.take().expect("the item is sent once");
+            Poll::Ready(sender.start_send(item).map_err(|_| SinkError::Closed))
+        })
+        .await
+    }
+}
+
+/// The outcome of draining a `QueuedSink`.
+#[derive(Debug, Default)]
+pub struct SinkReport {
+    /// The number of results delivered to the inner sink.
+    pub delivered: usize,
+    /// The errors of the results that could not be delivered.
+    pub errors: Vec<SinkError>,
+}
+
+/// A sink that queues results in a bounded queue and delivers them to another sink in the background.
+///
+/// Sending returns as soon as the result is queued, and waits while the queue is full, so a slow destination slows
+/// the producer down instead of results piling up in memory. The queue is drained by the worker future returned from
+/// `new`, which has to be spawned on the application's runtime. Delivery errors don't stop the worker; they are
+/// collected in the `SinkReport` it returns once the sink is closed and the queue is empty.
+///
+/// Flushing waits for the results queued before to be delivered, then flushes the inner sink.
+pub struct QueuedSink<T> {
+    queue: ChannelSink<Queued<T>>,
+}
+
+/// A message to the worker of a `QueuedSink`.
+enum Queued<T> {
+    Item(T),
+    Flush(oneshot::Sender<Result<(), SinkError>>),
+}
+
+impl<T> QueuedSink<T>
+where
+    T: Send + 'static,
+{
+    /// Creates a sink queueing up to `capacity` results for `inner`, and the worker delivering them.
+    pub fn new<S>(inner: S, capacity: usize) -> (Self, impl Future<Output = SinkReport> + Send)
+    where
+        S: ResultSink<T> + 'static,
+    {
+        let (queue, mut receiver) = ChannelSink::new(capacity);
+        let worker = async move {
+            let mut report = SinkReport::default();
+            while let Some(message) = receiver.next().await {
+                match message {
+                    Queued::Item(item) => match inner.send(item).await {
+                        Ok(()) => report.delivered += 1,
+                        Err(e) => report.errors.push(e),
+                    },
+                    Queued::Flush(done) => {
+                        let _ = done.send(inner.flush().await);
+                    }
+                }
+            }
+            if let Err(e) = inner.flush().await {
+                report.errors.push(e);
+            }
+            report
+        };
+        (Self { queue }, worker)
+    }
+
+    /// Closes the queue. The worker finishes once the results already queued are delivered.
+    pub async fn close(&self) {
+        self.queue.close();
+    }
+}
+
+#[async_trait]
+impl<T> ResultSink<T> for QueuedSink<T>
+where
+    T: Send + 'static,
+{
+    async fn send(&self, item: T) -> Result<(), SinkError> {
+        self.queue.send(Queued::Item(item)).await
+    }
+
+    async fn flush(&self) -> Result<(), SinkError> {
+        let (done, flushed) = oneshot::channel();
+        self.queue.send(Queued::Flush(done)).await?;
+        flushed.await.map_err(|_| SinkError::Closed)?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChannelSink, QueuedSink, ResultSink, SinkError};
+    use async_trait::async_trait;
+    use futures::executor::block_on;
+    use futures::{FutureExt, StreamExt};
+    use std::sync::{Arc, Mutex};
+
+    /// Records the results sent, and how many there were at each flush.
+    #[derive(Clone, Default)]
+    struct RecordingSink {
+        sent: Arc<Mutex<Vec<i32>>>,
+        flushes: Arc<Mutex<Vec<usize>>>,
+    }
+
+    #[async_trait]
+    impl ResultSink<i32> for RecordingSink {
+        async fn send(&self, item: i32) -> Result<(), SinkError> {
+            self.sent.lock().unwrap().push(item);
+            Ok(())
+        }
+
+        async fn flush(&self) -> Result<(), SinkError> {
+            let sent = self.sent.lock().unwrap().len();
+            self.flushes.lock().unwrap().push(sent);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn queued_sink_delivers_in_order() {
+        let (channel, receiver) = ChannelSink::new(10);
+        let (sink, worker) = QueuedSink::new(channel, 2);
+        block_on(async {
+            let produce = async {
+                for i in 0..5 {
+                    sink.send(i).await.unwrap();
+                }
+                sink.close().await;
+            };
+            let (_, report) = futures::join!(produce, worker);
+            assert_eq!(report.delivered, 5);
+            assert!(report.errors.is_empty());
+        });
+        let received: Vec<i32> = block_on(receiver.collect());
+        assert_eq!(received, vec![0, 1, 2, 3, 4]);
+        assert!(sink.send(5).now_or_never().unwrap().is_err());
+    }
+
+    #[test]
+    fn queued_sink_forwards_flush_after_queued_results() {
+        let inner = RecordingSink::default();
+        let (sink, worker) = QueuedSink::new(inner.clone(), 10);
+        block_on(async {
+            let produce = async {
+                sink.send(1).await.unwrap();
+                sink.send(2).await.unwrap();
+                sink.flush().await.unwrap();
+                sink.send(3).await.unwrap();
+                sink.close().await;
+            };
+            let (_, report) = futures::join!(produce, worker);
+            assert_eq!(report.delivered, 3);
+        });
+        // The flush of the sink, then the final flush of the worker.
+        assert_eq!(*inner.flushes.lock().unwrap(), vec![2, 3]);
+    }
+
+    #[test]
+    fn channel_sink_closes_while_a_send_waits() {
+        let (sink, mut receiver) = ChannelSink::new(0);
+        block_on(async {
+            sink.send(1).await.unwrap();
+            let mut waiting = Box::pin(sink.send(2));
+            // The channel is full, so the send waits, without keeping the sink from being closed.
+            assert!((&mut waiting).now_or_never().is_none());
+            sink.close();
+            assert_eq!(receiver.next().await, Some(1));
+            assert!(sink.send(3).await.is_err());
+        });
+    }
+}
@@ -1,12 +1,42 @@
 //! Steps are indivudaul LLM invocations in a chain. They are a combination of a prompt and a configuration.
 //!
 //! Steps are used to set the per-invocation settings for a prompt. Useful when you want to change the settings for a specific prompt in a chain.
+//!
+//! Work that doesn't fit a single prompt, such as reranking retrieved documents, can be added to a sequential chain
+//! by implementing `CustomStep`. A `ConditionalStep` runs or skips a step, or chooses between two steps, depending on
+//! the current parameters, and `ParallelSteps` runs several steps concurrently and joins their outputs.
+use std::sync::Arc;
+
 use crate::frame::{FormatAndExecuteError, Frame};
-use crate::prompt::{Prompt, StringTemplateError};
+use crate::json_schema::JsonSchema;
+use crate::output::Output;
+use crate::prompt::{Prompt, StringTemplate, StringTemplateError};
+use crate::tokens::Tokenizer;
 use crate::{chains::sequential, prompt, traits, Parameters};
+use async_trait::async_trait;
 use derive_builder;
-use serde::de::{Deserialize, Deserializer, MapAccess};
+use serde::de::{Deserialize, DeserializeOwned, Deserializer, MapAccess};
 use serde::ser::{Serialize, SerializeMap, Serializer};
+/// The instructions appended to the prompt of a step run with `Step::run_typed`.
+pub(crate) const JSON_INSTRUCTIONS: &str =
+    "Reply with a single JSON value only, without any explanation before or after it.";
+
+/// The error returned by `Step::run_typed`.
+#[derive(Debug, thiserror::Error)]
+pub enum TypedOutputError<Err: traits::ExecutorError> {
+    #[error("FormatAndExecuteError: {0}")]
+    FormatAndExecuteError(#[from] FormatAndExecuteError<Err>),
+    #[error("The model returned no text")]
+    NoTextOutput,
+    /// The output held no JSON deserializing into the expected type. `text` is the raw output of the model.
+    #[error("The output doesn't match the expected type: {source}\nOutput: {text}")]
+    Parse {
+        text: String,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
 #[derive(derive_builder::Builder, Debug, Clone)]
 /// A step in a chain of LLM invocations. It is a combination of a prompt and a configuration.
 pub struct Step<Executor>
@@ -16,6 +46,8 @@ where
     pub(crate) prompt: prompt::PromptTemplate,
     pub(crate) options: Option<Executor::PerInvocationOptions>,
     pub(crate) is_streaming: Option<bool>,
+    #[builder(default)]
+    pub(crate) json_schema: Option<JsonSchema>,
 }
 
 impl<Executor> Step<Executor>
@@ -27,6 +59,7 @@ where
             prompt,
             options: None,
             is_streaming: None,
+            json_schema: None,
         }
     }
     pub fn for_prompt_with_streaming(prompt: prompt::PromptTemplate) -> Self {
@@ -34,6 +67,7 @@ where
             prompt,
             options: None,
             is_streaming: Some(true),
+            json_schema: None,
         }
     }
     pub fn for_prompt_and_options(
@@ -44,8 +78,18 @@ where
             prompt,
             options: Some(options),
             is_streaming: None,
+            json_schema: None,
         }
     }
+    /// Creates a step for `prompt`, checking that the prompt only uses the `declared` parameters, so that a misspelt
+    /// variable fails when the step is built. See `PromptTemplate::validate_declared`.
+    pub fn for_prompt_with_parameters<S: AsRef<str>>(
+        prompt: prompt::PromptTemplate,
+        declared: &[S],
+    ) -> Result<Self, prompt::ParameterValidationError> {
+        prompt.validate_declared(declared)?;
+        Ok(Self::for_prompt_template(prompt))
+    }
     pub fn prompt(&self) -> &prompt::PromptTemplate {
         &self.prompt
     }
@@ -56,6 +100,19 @@ where
         self.is_streaming
     }
 
+    /// Requires the output of this step to be JSON matching `schema`.
+    ///
+    /// Executors supporting it constrain generation to the schema; for the others, the schema is added to the prompt.
+    /// Running the step fails with `FormatAndExecuteError::SchemaValidation` if the output doesn't match the schema.
+    pub fn with_json_schema(mut self, schema: JsonSchema) -> Self {
+        self.json_schema = Some(schema);
+        self
+    }
+
+    pub fn json_schema(&self) -> Option<&JsonSchema> {
+        self.json_schema.as_ref()
+    }
+
     /// Converts this step into a sequential chain with a single step.
     ///
     /// # Returns
@@ -68,6 +125,22 @@ where
         crate::chains::sequential::Chain::of_one(self)
     }
 
+    /// Creates a sequential chain running this step, then `next` with the output of this step in `text`.
+    pub fn then<S: Into<sequential::ChainStep<Executor>>>(
+        self,
+        next: S,
+    ) -> sequential::Chain<Executor> {
+        self.to_chain().then(next)
+    }
+
+    /// Checks that `parameters` sets every parameter the prompt of this step uses. See `PromptTemplate::validate`.
+    pub fn validate(
+        &self,
+        parameters: &Parameters,
+    ) -> Result<(), prompt::ParameterValidationError> {
+        self.prompt.validate(parameters)
+    }
+
     /// Formats the prompt for this step with the given parameters.
     pub fn format(&self, parameters: &Parameters) -> Result<Prompt, StringTemplateError> {
         self.prompt.format(parameters)
@@ -91,6 +164,548 @@ where
             .format_and_execute(parameters)
             .await
     }
+
+    /// Executes the step, asking the model to reply with JSON, and deserializes the reply into `T`.
+    ///
+    /// An instruction to reply with JSON only is appended to the prompt, which should describe the expected fields.
+    /// The JSON is found with `parsing::find_json`, so replies wrapping it in a code block or in sentences are
+    /// accepted.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// #[derive(serde::Deserialize)]
+    /// struct Review {
+    ///     score: u8,
+    ///     summary: String,
+    /// }
+    /// let step = Step::for_prompt_template(prompt!(
+    ///     "Review this text. Give a `score` from 1 to 5 and a one-sentence `summary`.\n\n{{text}}"
+    /// ));
+    /// let review: Review = step.run_typed(&parameters!(text), &executor).await?;
+    /// ```
+    pub async fn run_typed<T: DeserializeOwned>(
+        &self,
+        parameters: &Parameters,
+        executor: &Executor,
+    ) -> Result<T, TypedOutputError<Executor::Error>> {
+        // The instructions of a schema already ask for JSON.
+        let prompt = match &self.json_schema {
+            Some(_) => self.prompt.clone(),
+            None => self
+                .prompt
+                .append(&prompt::Data::Text(StringTemplate::static_string(
+                    JSON_INSTRUCTIONS,
+                ))),
+        };
+        let step = Self {
+            prompt,
+            options: self.options.clone(),
+            is_streaming: self.is_streaming,
+            json_schema: self.json_schema.clone(),
+        };
+        let output = step.run(parameters, executor).await?;
+        let text = output
+            .primary_textual_output()
+            .await
+            .ok_or(TypedOutputError::NoTextOutput)?;
+        crate::parsing::find_json(&text).map_err(|source| TypedOutputError::Parse { text, source })
+    }
+}
+
+/// The error type returned by custom steps.
+pub type CustomStepError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The result of running a `CustomStep`.
+pub struct StepOutcome<O> {
+    /// The parameters passed on to the next step.
+    pub parameters: Parameters,
+    /// The output of the step, if it invoked the model.
+    pub output: Option<O>,
+}
+
+impl<O> StepOutcome<O> {
+    /// An outcome that only updates the parameters.
+    pub fn parameters(parameters: Parameters) -> Self {
+        Self {
+            parameters,
+            output: None,
+        }
+    }
+}
+
+/// A step with custom behavior that can be part of a sequential chain.
+///
+/// A custom step receives the current parameters and the chain's executor. It may transform the parameters, for
+/// example to reorder retrieved documents, and may invoke the executor to produce an output.
+#[async_trait]
+pub trait CustomStep<E: traits::Executor>: Send + Sync {
+    async fn run(
+        &self,
+        parameters: &Parameters,
+        executor: &E,
+    ) -> Result<StepOutcome<E::Output>, CustomStepError>;
+}
+
+/// The condition of a `ConditionalStep`, evaluated over the current parameters. The chain's executor is passed along
+/// so that conditions can count tokens.
+pub type StepCondition<E> = Arc<dyn Fn(&Parameters, &E) -> bool + Send + Sync>;
+
+/// A custom step running another step only when a condition holds, or choosing between two steps.
+///
+/// When the condition holds, the step runs as if it were part of the chain itself. Otherwise the `otherwise` step
+/// runs if there is one, and if not, the parameters are passed on unchanged and the chain continues with the output
+/// of the previous step.
+///
+/// # Example
+///
+/// ```ignore
+/// let chain = Chain::from_steps(vec![
+///     ChainStep::custom(ConditionalStep::when_text_longer_than(1000, summarize_step)),
+///     answer_step.into(),
+/// ]);
+/// ```
+pub struct ConditionalStep<E: traits::Executor> {
+    condition: StepCondition<E>,
+    then: sequential::ChainStep<E>,
+    otherwise: Option<sequential::ChainStep<E>>,
+}
+
+impl<E: traits::Executor> ConditionalStep<E> {
+    /// Creates a step running `step` when `condition` holds for the current parameters, and skipped otherwise.
+    pub fn new<F, S>(condition: F, step: S) -> Self
+    where
+        F: Fn(&Parameters, &E) -> bool + Send + Sync + 'static,
+        S: Into<sequential::ChainStep<E>>,
+    {
+        Self {
+            condition: Arc::new(condition),
+            then: step.into(),
+            otherwise: None,
+        }
+    }
+
+    /// Creates a step running `step` when the `text` parameter is longer than `max_tokens` tokens of the executor's
+    /// tokenizer. Text that can't be tokenized counts as longer.
+    pub fn when_text_longer_than<S>(max_tokens: usize, step: S) -> Self
+    where
+        S: Into<sequential::ChainStep<E>>,
+    {
+        Self::new(
+            move |parameters: &Parameters, executor: &E| {
+                let text = parameters.get_text().unwrap_or_default();
+                executor
+                    .get_tokenizer(None)
+                    .and_then(|tokenizer| tokenizer.tokenize_str(&text))
+                    .map_or(true, |tokens| tokens.len() > max_tokens)
+            },
+            step,
+        )
+    }
+
+    /// Runs `step` when the condition doesn't hold, instead of skipping.
+    pub fn with_otherwise<S: Into<sequential::ChainStep<E>>>(mut self, step: S) -> Self {
+        self.otherwise = Some(step.into());
+        self
+    }
+}
+
+#[async_trait]
+impl<E> CustomStep<E> for ConditionalStep<E>
+where
+    E: traits::Executor + Sync,
+    E::Error: Send + Sync + 'static,
+    E::Output: Send,
+    E::PerInvocationOptions: Sync,
+{
+    async fn run(
+        &self,
+        parameters: &Parameters,
+        executor: &E,
+    ) -> Result<StepOutcome<E::Output>, CustomStepError> {
+        let branch = if (self.condition)(parameters, executor) {
+            Some(&self.then)
+        } else {
+            self.otherwise.as_ref()
+        };
+        match branch {
+            None => Ok(StepOutcome::parameters(parameters.clone())),
+            Some(sequential::ChainStep::Prompt(step)) => {
+                let output = step.run(parameters, executor).await.map_err(Box::new)?;
+                Ok(StepOutcome {
+                    parameters: parameters.clone(),
+                    output: Some(output),
+                })
+            }
+            Some(sequential::ChainStep::Custom(step)) => step.run(parameters, executor).await,
+        }
+    }
+}
+
+/// The error returned when a step of a `ParallelSteps` group fails.
+#[derive(Debug, thiserror::Error)]
+#[error("The parallel step {key} failed: {source}")]
+pub struct ParallelStepError {
+    pub key: String,
+    #[source]
+    pub source: CustomStepError,
+}
+
+/// A custom step running several steps concurrently on the same parameters and joining their outputs.
+///
+/// Every step receives the current parameters, and the text of its output is written to the parameter of its key,
+/// so the following step can use them all, e.g. `{{summary}}`, `{{keywords}}` and `{{sentiment}}`. The `text`
+/// parameter is left unchanged. When a step fails, the others are abandoned and the chain fails.
+///
+/// # Example
+///
+/// ```ignore
+/// let chain = Chain::from_steps(vec![
+///     ChainStep::custom(
+///         ParallelSteps::new()
+///             .with_step("summary", summarize_step)
+///             .with_step("keywords", keywords_step)
+///             .with_step("sentiment", sentiment_step),
+///     ),
+///     report_step.into(),
+/// ]);
+/// ```
+pub struct ParallelSteps<E: traits::Executor> {
+    steps: Vec<(String, sequential::ChainStep<E>)>,
+}
+
+impl<E: traits::Executor> Default for ParallelSteps<E> {
+    fn default() -> Self {
+        Self { steps: Vec::new() }
+    }
+}
+
+impl<E: traits::Executor> ParallelSteps<E> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a step whose output is written to the parameter `key`.
+    pub fn with_step<K: Into<String>, S: Into<sequential::ChainStep<E>>>(
+        mut self,
+        key: K,
+        step: S,
+    ) -> Self {
+        self.steps.push((key.into(), step.into()));
+        self
+    }
+
+    /// Runs a step of the group and returns the text of its output.
+    async fn run_step(
+        key: &str,
+        step: &sequential::ChainStep<E>,
+        parameters: &Parameters,
+        executor: &E,
+    ) -> Result<String, ParallelStepError>
+    where
+        E: Sync,
+        E::Error: Send + Sync + 'static,
+        E::Output: Send,
+        E::PerInvocationOptions: Sync,
+    {
+        let error = |source: CustomStepError| ParallelStepError {
+            key: key.to_string(),
+            source,
+        };
+        let (output, parameters) = match step {
+            sequential::ChainStep::Prompt(step) => {
+                let output = step
+                    .run(parameters, executor)
+                    .await
+                    .map_err(|e| error(Box::new(e)))?;
+                (Some(output), None)
+            }
+            sequential::ChainStep::Custom(step) => {
+                let outcome = step.run(parameters, executor).await.map_err(error)?;
+                (outcome.output, Some(outcome.parameters))
+            }
+        };
+        let text = match output {
+            Some(output) => output.primary_textual_output().await,
+            None => parameters.and_then(|p| p.get(key).or_else(|| p.get_text())),
+        };
+        Ok(text.unwrap_or_default())
+    }
+}
+
+#[async_trait]
+impl<E> CustomStep<E> for ParallelSteps<E>
+where
+    E: traits::Executor + Sync,
+    E::Error: Send + Sync + 'static,
+    E::Output: Send,
+    E::PerInvocationOptions: Sync,
+{
+    async fn run(
+        &self,
+        parameters: &Parameters,
+        executor: &E,
+    ) -> Result<StepOutcome<E::Output>, CustomStepError> {
+        let texts = futures::future::try_join_all(
+            self.steps
+                .iter()
+                .map(|(key, step)| Self::run_step(key, step, parameters, executor)),
+        )
+        .await?;
+        let joined = self
+            .steps
+            .iter()
+            .zip(texts)
+            .fold(parameters.clone(), |joined, ((key, _), text)| {
+                joined.with(key.as_str(), text)
+            });
+        Ok(StepOutcome::parameters(joined))
+    }
+}
+
+/// The error returned by `RemapParameters` when a parameter to remap is not set.
+#[derive(thiserror::Error, Debug)]
+#[error("Parameter `{0}` is not set")]
+pub struct MissingParameterError(pub String);
+
+/// A custom step making parameters available under other names to the following steps, for example to pass the
+/// output of a step, found in `text`, to a step expecting it in `summary`.
+///
+/// The values stay available under their original names too. A missing parameter fails the chain with a
+/// `MissingParameterError`.
+///
+/// # Example
+///
+/// ```ignore
+/// let chain = summarize_step
+///     .then(RemapParameters::new().with_mapping("text", "summary"))
+///     .then(tweet_step);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct RemapParameters {
+    mapping: Vec<(String, String)>,
+}
+
+impl RemapParameters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes the parameter `from` available as `to`. Mappings are applied in the order they were added.
+    pub fn with_mapping<F: Into<String>, T: Into<String>>(mut self, from: F, to: T) -> Self {
+        self.mapping.push((from.into(), to.into()));
+        self
+    }
+
+    /// Applies the mappings to `parameters`.
+    pub fn apply(&self, parameters: &Parameters) -> Result<Parameters, MissingParameterError> {
+        self.mapping
+            .iter()
+            .try_fold(parameters.clone(), |parameters, (from, to)| {
+                parameters
+                    .with_alias(from, to.as_str())
+                    .ok_or_else(|| MissingParameterError(from.clone()))
+            })
+    }
+}
+
+#[async_trait]
+impl<E> CustomStep<E> for RemapParameters
+where
+    E: traits::Executor + Sync,
+{
+    async fn run(
+        &self,
+        parameters: &Parameters,
+        _executor: &E,
+    ) -> Result<StepOutcome<E::Output>, CustomStepError> {
+        Ok(StepOutcome::parameters(self.apply(parameters)?))
+    }
+}
+
+/// A closure validating the text of an output, returning a description of the problem if it's invalid.
+pub type OutputValidator = Arc<dyn Fn(&str) -> Result<(), String> + Send + Sync>;
+
+/// The `SelfHealingError` enum represents errors that can occur when running a `SelfHealingStep`.
+#[derive(thiserror::Error, Debug)]
+pub enum SelfHealingError {
+    #[error("The output was still invalid after {attempts} attempts: {error}")]
+    Invalid { attempts: usize, error: String },
+    #[error("The model returned no text")]
+    NoTextOutput,
+}
+
+/// Returns `prompt` followed by the invalid `answer` and a message asking the model to fix `error`.
+pub(crate) fn feedback_prompt(prompt: &Prompt, answer: &str, error: &str) -> Prompt {
+    let feedback = prompt::ChatMessageCollection::new()
+        .with_assistant(answer.to_string())
+        .with_user(format!(
+            "Your answer is invalid: {}\n\nAnswer again, fixing the error.",
+            error
+        ));
+    Prompt::Chat(prompt.to_chat()).combine(&Prompt::Chat(feedback))
+}
+
+/// The error returned by `run_with_feedback`.
+pub(crate) enum FeedbackError<Err: traits::ExecutorError, V> {
+    Run(FormatAndExecuteError<Err>),
+    NoTextOutput,
+    /// The last output still failed the check. `text` is the raw output of the model.
+    Invalid {
+        text: String,
+        error: V,
+    },
+}
+
+impl<Err: traits::ExecutorError, V> From<FormatAndExecuteError<Err>> for FeedbackError<Err, V> {
+    fn from(error: FormatAndExecuteError<Err>) -> Self {
+        Self::Run(error)
+    }
+}
+
+/// Runs `step` and checks its output with `check`, asking the model to fix the outputs failing the check.
+///
+/// When the check fails, the step is run again with the output and the `feedback` for the error appended to its
+/// prompt, up to `max_attempts` calls in total. Outputs not matching the JSON Schema of the step are repaired the
+/// same way. Every attempt runs with the schema options or instructions of the step, as the first one does. Only the
+/// latest output is appended, so prompts don't grow with every attempt, and the retries are not streamed. Returns the checked value along with the output it was taken from.
+pub(crate) async fn run_with_feedback<E, T, V>(
+    step: &Step<E>,
+    parameters: &Parameters,
+    executor: &E,
+    max_attempts: usize,
+    check: impl Fn(&str) -> Result<T, V>,
+    feedback: impl Fn(&V) -> String,
+) -> Result<(T, E::Output), FeedbackError<E::Error, V>>
+where
+    E: traits::Executor,
+{
+    let frame = Frame::new(executor, step);
+    let mut result = frame.format_and_execute(parameters).await;
+    let mut attempt = 1;
+    loop {
+        let (text, feedback) = match result {
+            Ok(output) => {
+                let text = output
+                    .primary_textual_output()
+                    .await
+                    .ok_or(FeedbackError::NoTextOutput)?;
+                let error = match check(&text) {
+                    Ok(checked) => return Ok((checked, output)),
+                    Err(error) => error,
+                };
+                if attempt >= max_attempts {
+                    return Err(FeedbackError::Invalid { text, error });
+                }
+                let feedback = feedback(&error);
+                (text, feedback)
+            }
+            // Outputs not matching the schema of the step are repaired like the ones failing the check.
+            Err(FormatAndExecuteError::SchemaValidation { text, source })
+                if attempt < max_attempts =>
+            {
+                (text, source.to_string())
+            }
+            Err(error) => return Err(error.into()),
+        };
+        result = frame
+            .format_and_execute_with_feedback(parameters, &text, &feedback)
+            .await;
+        attempt += 1;
+    }
+}
+
+/// A custom step validating the output of a step, and asking the model to correct invalid outputs.
+///
+/// When the output is invalid, the step is run again with the invalid answer and the validation error appended to
+/// its prompt, up to `max_attempts` calls in total. Only the latest answer is appended, so prompts don't grow with
+/// every attempt. This is how chains producing structured output recover from malformed answers, such as JSON with a
+/// trailing comma. Outputs not matching the JSON Schema of the step are repaired too, and every attempt runs with the
+/// schema like the first one. Outputs are not streamed, since they must be complete to be validated.
+///
+/// # Example
+///
+/// ```ignore
+/// let chain = Chain::from_steps(vec![
+///     ChainStep::custom(SelfHealingStep::for_json::<Recipe>(recipe_step).with_max_attempts(3)),
+///     format_step.into(),
+/// ]);
+/// ```
+pub struct SelfHealingStep<E: traits::Executor> {
+    step: Step<E>,
+    validator: OutputValidator,
+    max_attempts: usize,
+}
+
+impl<E: traits::Executor> SelfHealingStep<E> {
+    /// Creates a step running `step` and validating its output with `validator`, making up to 3 calls.
+    pub fn new<F, Err>(step: Step<E>, validator: F) -> Self
+    where
+        F: Fn(&str) -> Result<(), Err> + Send + Sync + 'static,
+        Err: std::fmt::Display,
+    {
+        Self {
+            step,
+            validator: Arc::new(move |text: &str| validator(text).map_err(|e| e.to_string())),
+            max_attempts: 3,
+        }
+    }
+
+    /// Creates a step requiring the output to be JSON deserializing to `T`, optionally inside a code block.
+    pub fn for_json<T: serde::de::DeserializeOwned>(step: Step<E>) -> Self {
+        Self::new(step, |text: &str| {
+            let text = text.trim();
+            let json = text
+                .strip_prefix("```json")
+                .or_else(|| text.strip_prefix("```"))
+                .and_then(|rest| rest.strip_suffix("```"))
+                .unwrap_or(text);
+            serde_json::from_str::<T>(json).map(|_| ())
+        })
+    }
+
+    /// Sets the maximum number of calls, including the first one. Defaults to 3; at least one call is made.
+    pub fn with_max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+}
+
+#[async_trait]
+impl<E> CustomStep<E> for SelfHealingStep<E>
+where
+    E: traits::Executor + Sync,
+    E::Error: Send + Sync + 'static,
+    E::Output: Send,
+    E::PerInvocationOptions: Sync,
+{
+    async fn run(
+        &self,
+        parameters: &Parameters,
+        executor: &E,
+    ) -> Result<StepOutcome<E::Output>, CustomStepError> {
+        let result = run_with_feedback(
+            &self.step,
+            parameters,
+            executor,
+            self.max_attempts,
+            |text| (self.validator)(text),
+            String::clone,
+        )
+        .await;
+        match result {
+            Ok(((), output)) => Ok(StepOutcome {
+                parameters: parameters.clone(),
+                output: Some(output),
+            }),
+            Err(FeedbackError::Run(e)) => Err(Box::new(e)),
+            Err(FeedbackError::NoTextOutput) => Err(Box::new(SelfHealingError::NoTextOutput)),
+            Err(FeedbackError::Invalid { error, .. }) => Err(Box::new(SelfHealingError::Invalid {
+                attempts: self.max_attempts,
+                error,
+            })),
+        }
+    }
 }
 
 // Your custom Serialize implementation for Step
@@ -99,9 +714,12 @@ impl<E: traits::Executor> Serialize for Step<E> {
     where
         S: Serializer,
     {
-        let mut map = serializer.serialize_map(Some(2))?;
+        let mut map = serializer.serialize_map(Some(2 + self.json_schema.is_some() as usize))?;
         map.serialize_entry("prompt", &self.prompt)?;
         map.serialize_entry("options", &self.options)?;
+        if let Some(json_schema) = &self.json_schema {
+            map.serialize_entry("json_schema", json_schema)?;
+        }
         map.end()
     }
 }
@@ -123,6 +741,7 @@ impl<'de, E: traits::Executor> serde::de::Visitor<'de> for StepVisitor<E> {
         let mut prompt = None;
         let mut options = None;
         let mut is_streaming = None;
+        let mut json_schema = None;
         while let Some(key) = map.next_key()? {
             match key {
                 "prompt" => {
@@ -143,6 +762,12 @@ impl<'de, E: traits::Executor> serde::de::Visitor<'de> for StepVisitor<E> {
                     }
                     is_streaming = Some(map.next_value()?);
                 }
+                "json_schema" => {
+                    if json_schema.is_some() {
+                        return Err(serde::de::Error::duplicate_field("json_schema"));
+                    }
+                    json_schema = Some(map.next_value()?);
+                }
                 _ => return Err(serde::de::Error::unknown_field(key, &["prompt", "options"])),
             }
         }
@@ -152,6 +777,7 @@ impl<'de, E: traits::Executor> serde::de::Visitor<'de> for StepVisitor<E> {
             prompt,
             options,
             is_streaming,
+            json_schema,
         })
     }
 }
@@ -164,3 +790,279 @@ impl<'de, E: traits::Executor> Deserialize<'de> for Step<E> {
         deserializer.deserialize_map(StepVisitor(std::marker::PhantomData))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        feedback_prompt, ConditionalStep, CustomStep, CustomStepError, ParallelStepError,
+        ParallelSteps, SelfHealingStep, Step, StepOutcome, TypedOutputError, JSON_INSTRUCTIONS,
+    };
+    use crate::agents::mock::{MockError, MockExecutor, MockOutput};
+    use crate::chains::sequential::ChainStep;
+    use crate::prompt::{ChatRole, Prompt};
+    use crate::{parameters, prompt, Parameters};
+    use async_trait::async_trait;
+    use futures::executor::block_on;
+    use std::task::Poll;
+
+    fn step(template: &str) -> Step<MockExecutor> {
+        Step::for_prompt_template(prompt!(template))
+    }
+
+    // A step setting `text` after being polled `polls` times, so that steps run together finish in any order.
+    struct Delayed {
+        polls: usize,
+        text: &'static str,
+    }
+
+    #[async_trait]
+    impl CustomStep<MockExecutor> for Delayed {
+        async fn run(
+            &self,
+            parameters: &Parameters,
+            _executor: &MockExecutor,
+        ) -> Result<StepOutcome<MockOutput>, CustomStepError> {
+            let mut polls = self.polls;
+            futures::future::poll_fn(|context| {
+                if polls == 0 {
+                    return Poll::Ready(());
+                }
+                polls -= 1;
+                context.waker().wake_by_ref();
+                Poll::Pending
+            })
+            .await;
+            Ok(StepOutcome::parameters(parameters.with_text(self.text)))
+        }
+    }
+
+    fn is_urgent(parameters: &Parameters, _: &MockExecutor) -> bool {
+        parameters.get("priority").as_deref() == Some("urgent")
+    }
+
+    #[test]
+    fn feedback_follows_the_original_prompt() {
+        let prompt = Prompt::Text("List three colors as JSON.".to_string());
+        let chat = feedback_prompt(&prompt, "[red, green", "EOF while parsing a list").to_chat();
+        let roles: Vec<_> = chat.iter().map(|m| m.role().clone()).collect();
+        assert_eq!(roles, [ChatRole::User, ChatRole::Assistant, ChatRole::User]);
+        assert_eq!(chat.get_message(1).unwrap().body(), "[red, green");
+        assert!(chat
+            .get_message(2)
+            .unwrap()
+            .body()
+            .contains("EOF while parsing a list"));
+    }
+
+    #[test]
+    fn runs_the_step_when_the_condition_holds() {
+        let conditional = ConditionalStep::new(is_urgent, step("Escalate: {{text}}"))
+            .with_otherwise(step("File: {{text}}"));
+        let executor = MockExecutor::new(vec![MockOutput::text("Escalated")]);
+        let parameters = parameters!("text" => "The site is down", "priority" => "urgent");
+        let outcome = block_on(conditional.run(&parameters, &executor)).unwrap();
+        assert_eq!(outcome.output.unwrap().0.as_deref(), Some("Escalated"));
+        assert_eq!(outcome.parameters, parameters);
+        assert_eq!(
+            executor.prompts.lock().unwrap()[0].to_string(),
+            "Escalate: The site is down"
+        );
+    }
+
+    #[test]
+    fn runs_the_otherwise_step_when_the_condition_fails() {
+        let conditional = ConditionalStep::new(is_urgent, step("Escalate: {{text}}"))
+            .with_otherwise(step("File: {{text}}"));
+        let executor = MockExecutor::new(vec![MockOutput::text("Filed")]);
+        let parameters = parameters!("text" => "Typo on the home page", "priority" => "low");
+        let outcome = block_on(conditional.run(&parameters, &executor)).unwrap();
+        assert_eq!(outcome.output.unwrap().0.as_deref(), Some("Filed"));
+        assert_eq!(
+            executor.prompts.lock().unwrap()[0].to_string(),
+            "File: Typo on the home page"
+        );
+    }
+
+    #[test]
+    fn skips_the_step_when_the_condition_fails() {
+        let conditional = ConditionalStep::new(is_urgent, step("Escalate: {{text}}"));
+        let executor = MockExecutor::new(vec![]);
+        let parameters = parameters!("text" => "Typo on the home page");
+        let outcome = block_on(conditional.run(&parameters, &executor)).unwrap();
+        assert!(outcome.output.is_none());
+        assert_eq!(outcome.parameters, parameters);
+        assert!(executor.prompts.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn counts_tokens_of_the_text() {
+        let conditional = ConditionalStep::when_text_longer_than(3, step("Summarize: {{text}}"));
+        let executor = MockExecutor::new(vec![MockOutput::text("Short")]);
+        let short = parameters!("text" => "one two three");
+        assert!(block_on(conditional.run(&short, &executor))
+            .unwrap()
+            .output
+            .is_none());
+        let long = parameters!("text" => "one two three four");
+        assert!(block_on(conditional.run(&long, &executor))
+            .unwrap()
+            .output
+            .is_some());
+    }
+
+    #[test]
+    fn joins_parallel_outputs_under_their_keys() {
+        let parallel = ParallelSteps::new()
+            .with_step(
+                "summary",
+                ChainStep::custom(Delayed {
+                    polls: 3,
+                    text: "Short",
+                }),
+            )
+            .with_step("keywords", step("Keywords of {{text}}"))
+            .with_step(
+                "sentiment",
+                ChainStep::custom(Delayed {
+                    polls: 0,
+                    text: "Positive",
+                }),
+            );
+        let executor = MockExecutor::new(vec![MockOutput::text("rust, llm")]);
+        let parameters = parameters!("text" => "A review");
+        let outcome = block_on(parallel.run(&parameters, &executor)).unwrap();
+        let joined: Vec<_> = ["summary", "keywords", "sentiment", "text"]
+            .iter()
+            .map(|key| outcome.parameters.get(key).unwrap_or_default())
+            .collect();
+        assert_eq!(joined, vec!["Short", "rust, llm", "Positive", "A review"]);
+        assert!(outcome.output.is_none());
+    }
+
+    #[test]
+    fn fails_when_a_parallel_step_fails() {
+        let parallel = ParallelSteps::new()
+            .with_step(
+                "summary",
+                ChainStep::custom(Delayed {
+                    polls: 1,
+                    text: "Short",
+                }),
+            )
+            .with_step("keywords", step("Keywords of {{text}}"));
+        // The executor has no output left for the prompt step.
+        let executor = MockExecutor::new(vec![]);
+        let error = block_on(parallel.run(&parameters!("text" => "A review"), &executor))
+            .err()
+            .unwrap();
+        let error = error.downcast::<ParallelStepError>().unwrap();
+        assert_eq!(error.key, "keywords");
+    }
+
+    #[derive(serde::Deserialize)]
+    #[allow(dead_code)]
+    struct Review {
+        score: u8,
+        summary: String,
+    }
+
+    #[test]
+    fn self_healing_repairs_with_the_schema_of_the_step() {
+        use crate::json_schema::JsonSchema;
+
+        let schema = JsonSchema::new(
+            "review",
+            serde_json::json!({
+                "type": "object",
+                "properties": {"score": {"type": "integer", "maximum": 5}},
+                "required": ["score"]
+            }),
+        );
+        let review = step("Review {{text}}").with_json_schema(schema.clone());
+        let healing = SelfHealingStep::for_json::<Review>(review);
+        let executor = MockExecutor::new(vec![
+            MockOutput::text(r#"{"score": 9}"#),
+            MockOutput::text(r#"{"score": 4}"#),
+            MockOutput::text(r#"{"score": 4, "summary": "Fine."}"#),
+        ]);
+        let outcome = block_on(healing.run(&Parameters::new_with_text("it"), &executor)).unwrap();
+        assert_eq!(
+            outcome.output.unwrap().0.as_deref(),
+            Some(r#"{"score": 4, "summary": "Fine."}"#)
+        );
+
+        let prompts = executor.prompts.lock().unwrap();
+        assert_eq!(prompts.len(), 3);
+        // The mock can't constrain generation, so every attempt carries the instructions of the schema.
+        for prompt in prompts.iter() {
+            let first = prompt.to_chat().get_message(0).unwrap().body().to_string();
+            assert!(first.starts_with("Review it"));
+            assert!(first.contains(&schema.instructions()));
+        }
+        let feedback = |index: usize| {
+            prompts[index]
+                .to_chat()
+                .get_message(2)
+                .unwrap()
+                .body()
+                .to_string()
+        };
+        // The first output breaks the schema, the second one the validator.
+        assert!(feedback(1).contains("/score"));
+        assert!(feedback(2).contains("missing field `summary`"));
+    }
+
+    #[test]
+    fn self_healing_reports_the_attempts() {
+        let healing =
+            SelfHealingStep::for_json::<Review>(step("Review {{text}}")).with_max_attempts(2);
+        let executor = MockExecutor::new(vec![MockOutput::text("good"), MockOutput::text("fine")]);
+        let error = block_on(healing.run(&Parameters::new_with_text("it"), &executor))
+            .err()
+            .unwrap();
+        assert!(error
+            .to_string()
+            .starts_with("The output was still invalid after 2 attempts"));
+        assert_eq!(executor.prompts.lock().unwrap().len(), 2);
+    }
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct Score {
+        score: u8,
+    }
+
+    fn run_typed(output: &str) -> (Result<Score, TypedOutputError<MockError>>, String) {
+        let executor = MockExecutor::new(vec![MockOutput::text(output)]);
+        let result = block_on(
+            step("Grade {{text}}").run_typed::<Score>(&Parameters::new_with_text("it"), &executor),
+        );
+        let prompt = executor.prompts.lock().unwrap()[0].to_string();
+        (result, prompt)
+    }
+
+    #[test]
+    fn run_typed_deserializes_json_replies() {
+        let (result, prompt) = run_typed(r#"{"score": 4}"#);
+        assert_eq!(result.unwrap(), Score { score: 4 });
+        assert!(prompt.starts_with("Grade it"));
+        assert!(prompt.ends_with(JSON_INSTRUCTIONS));
+    }
+
+    #[test]
+    fn run_typed_finds_json_in_code_blocks_and_sentences() {
+        let (result, _) = run_typed("Here you go:\n```json\n{\"score\": 3}\n```\n");
+        assert_eq!(result.unwrap(), Score { score: 3 });
+        let (result, _) = run_typed(r#"Sure! {"score": 5}. Anything else?"#);
+        assert_eq!(result.unwrap(), Score { score: 5 });
+    }
+
+    #[test]
+    fn run_typed_fails_on_json_of_another_type() {
+        let (result, _) = run_typed(r#"{"score": "high"}"#);
+        let Err(TypedOutputError::Parse { text, source }) = result else {
+            panic!("the reply doesn't match the type");
+        };
+        assert_eq!(text, r#"{"score": "high"}"#);
+        assert!(source.is_data());
+    }
+}
@@ -0,0 +1,53 @@
+//! Fixtures shared by the tests of the crate.
+use crate::traits::{Embeddings, EmbeddingsError};
+use async_trait::async_trait;
+
+/// The error of the fake embeddings, which never fail.
+#[derive(Debug, thiserror::Error)]
+#[error("unreachable")]
+pub(crate) struct NoError;
+
+impl EmbeddingsError for NoError {}
+
+/// Embeds texts and queries alike with a function.
+pub(crate) struct FnEmbeddings<F>(pub(crate) F);
+
+#[async_trait]
+impl<F> Embeddings for FnEmbeddings<F>
+where
+    F: Fn(&str) -> Vec<f32> + Send + Sync,
+{
+    type Error = NoError;
+
+    async fn embed_texts(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, NoError> {
+        Ok(texts.iter().map(|text| (self.0)(text)).collect())
+    }
+
+    async fn embed_query(&self, query: String) -> Result<Vec<f32>, NoError> {
+        Ok((self.0)(&query))
+    }
+}
+
+/// Embeds a text as the counts of the letters `a`, `b` and `c`.
+pub(crate) struct LetterEmbeddings;
+
+#[async_trait]
+impl Embeddings for LetterEmbeddings {
+    type Error = NoError;
+
+    async fn embed_texts(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, NoError> {
+        Ok(texts.iter().map(|text| letters(text)).collect())
+    }
+
+    async fn embed_query(&self, query: String) -> Result<Vec<f32>, NoError> {
+        Ok(letters(&query))
+    }
+}
+
+/// Returns the counts of the letters `a`, `b` and `c` in `text`.
+pub(crate) fn letters(text: &str) -> Vec<f32> {
+    ['a', 'b', 'c']
+        .iter()
+        .map(|l| text.chars().filter(|c| c == l).count() as f32)
+        .collect()
+}
@@ -0,0 +1,432 @@
+use std::marker::PhantomData;
+use std::ops::Range;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+use tree_sitter::{Node, Parser};
+
+use super::TextSplitter;
+use crate::schema::{Document, Provenance};
+use crate::tokens::TokenizerError;
+
+/// The programming languages `CodeSplitter` can parse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CodeLanguage {
+    Rust,
+    Python,
+    JavaScript,
+    TypeScript,
+    Tsx,
+}
+
+impl CodeLanguage {
+    /// Returns the language of files with the extension `extension`, without the leading dot.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "rs" => Some(Self::Rust),
+            "py" | "pyi" => Some(Self::Python),
+            "js" | "jsx" | "mjs" | "cjs" => Some(Self::JavaScript),
+            "ts" | "mts" | "cts" => Some(Self::TypeScript),
+            "tsx" => Some(Self::Tsx),
+            _ => None,
+        }
+    }
+
+    fn grammar(&self) -> tree_sitter::Language {
+        match self {
+            Self::Rust => tree_sitter_rust::language(),
+            Self::Python => tree_sitter_python::language(),
+            Self::JavaScript => tree_sitter_javascript::language(),
+            Self::TypeScript => tree_sitter_typescript::language_typescript(),
+            Self::Tsx => tree_sitter_typescript::language_tsx(),
+        }
+    }
+
+    /// The kinds of nodes that are items: functions, classes and the like, whose signature is recorded.
+    fn item_kinds(&self) -> &'static [&'static str] {
+        match self {
+            Self::Rust => &[
+                "function_item",
+                "function_signature_item",
+                "impl_item",
+                "trait_item",
+                "struct_item",
+                "enum_item",
+                "union_item",
+                "mod_item",
+                "macro_definition",
+                "const_item",
+                "static_item",
+                "type_item",
+            ],
+            Self::Python => &[
+                "function_definition",
+                "class_definition",
+                "decorated_definition",
+            ],
+            Self::JavaScript => &[
+                "function_declaration",
+                "generator_function_declaration",
+                "class_declaration",
+                "method_definition",
+                "export_statement",
+            ],
+            Self::TypeScript | Self::Tsx => &[
+                "function_declaration",
+                "generator_function_declaration",
+                "function_signature",
+                "class_declaration",
+                "abstract_class_declaration",
+                "method_definition",
+                "interface_declaration",
+                "type_alias_declaration",
+                "enum_declaration",
+                "module",
+                "internal_module",
+                "export_statement",
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum CodeSplitterError {
+    #[error(transparent)]
+    Tokenizer(#[from] TokenizerError),
+    #[error("Unable to load the grammar: {0}")]
+    Language(#[from] tree_sitter::LanguageError),
+    #[error("Unable to parse the code")]
+    Parse,
+}
+
+/// A chunk of source code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeChunk {
+    /// The text of the chunk, exactly as in the source.
+    pub text: String,
+    /// The byte range of the chunk in the source.
+    pub range: Range<usize>,
+    /// The first and last lines of the chunk, starting from 1.
+    pub lines: (usize, usize),
+    /// The signatures of the items the chunk is part of, from the outermost item in.
+    pub enclosing: Vec<String>,
+    /// The signatures of the items in the chunk.
+    pub items: Vec<String>,
+}
+
+/// The metadata of a chunk split from a source file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CodeMetadata<M> {
+    pub language: CodeLanguage,
+    /// The first and last lines of the chunk, starting from 1.
+    pub lines: (usize, usize),
+    /// The signatures of the items the chunk is part of, from the outermost item in.
+    pub enclosing: Vec<String>,
+    /// The signatures of the items in the chunk.
+    pub items: Vec<String>,
+    /// The metadata of the document the chunk was split from.
+    pub document: Option<M>,
+}
+
+/// A node with the comments and attributes before it.
+struct Block<'t> {
+    range: Range<usize>,
+    node: Option<Node<'t>>,
+}
+
+/// A chunk being filled with blocks.
+struct Pending {
+    range: Range<usize>,
+    items: Vec<String>,
+    tokens: usize,
+}
+
+fn is_leading(kind: &str) -> bool {
+    kind.ends_with("comment") || kind == "attribute_item"
+}
+
+/// Returns the definition wrapped by a decorator or an export, or `node` itself.
+fn definition(node: Node) -> Node {
+    let field = match node.kind() {
+        "decorated_definition" => "definition",
+        "export_statement" => "declaration",
+        _ => return node,
+    };
+    node.child_by_field_name(field).unwrap_or(node)
+}
+
+/// Splits source code into chunks along functions, classes and other items.
+///
+/// The code is parsed with tree-sitter, and chunks are filled with whole top-level items, along with the comments
+/// and attributes before them, as long as they fit in the maximum number of tokens. An item too long for a chunk of
+/// its own is split along the items or statements of its body, recursively, and its signature is recorded in the
+/// `enclosing` path of the resulting chunks so they can be understood on their own. Code that can't be split along
+/// its syntax, like a long expression, is split with the underlying `TextSplitter`.
+///
+/// The text of a chunk is taken from the source unchanged. The code doesn't have to be valid: tree-sitter recovers
+/// from syntax errors.
+pub struct CodeSplitter<S, T>
+where
+    S: TextSplitter<T>,
+    T: Clone,
+{
+    language: CodeLanguage,
+    splitter: S,
+    max_tokens_per_chunk: usize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<S, T> CodeSplitter<S, T>
+where
+    S: TextSplitter<T>,
+    T: Clone,
+{
+    /// Creates a splitter for code in `language`, counting tokens with `splitter`.
+    pub fn new(language: CodeLanguage, splitter: S, max_tokens_per_chunk: usize) -> Self {
+        Self {
+            language,
+            splitter,
+            max_tokens_per_chunk,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn split(&self, code: &str) -> Result<Vec<CodeChunk>, CodeSplitterError> {
+        let mut parser = Parser::new();
+        parser.set_language(self.language.grammar())?;
+        let tree = parser.parse(code, None).ok_or(CodeSplitterError::Parse)?;
+        let mut chunks = Vec::new();
+        let blocks = self.blocks(tree.root_node(), None);
+        self.split_blocks(code, blocks, &[], &mut chunks)?;
+        Ok(chunks)
+    }
+
+    /// Splits a source file into chunks that record their enclosing items and provenance, like `split_document`.
+    pub fn split_document<M>(
+        &self,
+        document: &Document<M>,
+        source_id: &str,
+    ) -> Result<Vec<Document<CodeMetadata<M>>>, CodeSplitterError>
+    where
+        M: Serialize + DeserializeOwned + Clone,
+    {
+        let parent = document
+            .provenance
+            .clone()
+            .unwrap_or_else(|| Provenance::new(source_id, document.page_content.len()));
+        Ok(self
+            .split(&document.page_content)?
+            .into_iter()
+            .map(|chunk| Document {
+                id: None,
+                page_content: chunk.text,
+                metadata: Some(CodeMetadata {
+                    language: self.language,
+                    lines: chunk.lines,
+                    enclosing: chunk.enclosing,
+                    items: chunk.items,
+                    document: document.metadata.clone(),
+                }),
+                provenance: Some(parent.sub_range(chunk.range.start, chunk.range.end)),
+            })
+            .collect())
+    }
+
+    fn is_item(&self, node: Node) -> bool {
+        self.language.item_kinds().contains(&node.kind())
+    }
+
+    /// Returns the signature of an item: its text up to its body, on a single line.
+    fn signature(&self, code: &str, node: Node) -> String {
+        let inner = definition(node);
+        // Decorators aren't part of the signature, but `export` is.
+        let start = if node.kind() == "decorated_definition" {
+            inner.start_byte()
+        } else {
+            node.start_byte()
+        };
+        let end = inner
+            .child_by_field_name("body")
+            .map(|body| body.start_byte())
+            .unwrap_or_else(|| {
+                code[start..node.end_byte()]
+                    .find('\n')
+                    .map_or(node.end_byte(), |i| start + i)
+            });
+        code[start..end]
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .trim_end_matches([':', ';', '{', '='])
+            .trim_end()
+            .to_string()
+    }
+
+    /// Groups the named children of `parent` into blocks, attaching comments and attributes to the node after them.
+    /// `header` is the range of the text before the children, which starts the first block.
+    fn blocks<'t>(&self, parent: Node<'t>, header: Option<Range<usize>>) -> Vec<Block<'t>> {
+        let mut blocks = Vec::new();
+        let mut leading = header;
+        let mut cursor = parent.walk();
+        for child in parent.named_children(&mut cursor) {
+            if is_leading(child.kind()) {
+                let start = leading.map_or(child.start_byte(), |range| range.start);
+                leading = Some(start..child.end_byte());
+                continue;
+            }
+            let start = leading
+                .take()
+                .map_or(child.start_byte(), |range| range.start);
+            blocks.push(Block {
+                range: start..child.end_byte(),
+                node: Some(child),
+            });
+        }
+        if let Some(range) = leading {
+            blocks.push(Block { range, node: None });
+        }
+        blocks
+    }
+
+    fn split_blocks(
+        &self,
+        code: &str,
+        blocks: Vec<Block>,
+        enclosing: &[String],
+        chunks: &mut Vec<CodeChunk>,
+    ) -> Result<(), CodeSplitterError> {
+        let mut current: Option<Pending> = None;
+        for block in blocks {
+            let tokens = self
+                .splitter
+                .tokenize_str(&code[block.range.clone()])?
+                .len();
+            let item = block
+                .node
+                .filter(|&node| self.is_item(node))
+                .map(|node| self.signature(code, node));
+            if let Some(pending) = &mut current {
+                if pending.tokens + tokens <= self.max_tokens_per_chunk {
+                    pending.range.end = block.range.end;
+                    pending.items.extend(item);
+                    pending.tokens += tokens;
+                    continue;
+                }
+            }
+            if let Some(pending) = current.take() {
+                chunks.push(chunk(code, pending.range, enclosing, pending.items));
+            }
+            if tokens > self.max_tokens_per_chunk {
+                self.split_block(code, block, item, enclosing, chunks)?;
+                continue;
+            }
+            current = Some(Pending {
+                range: block.range,
+                items: item.into_iter().collect(),
+                tokens,
+            });
+        }
+        if let Some(pending) = current {
+            chunks.push(chunk(code, pending.range, enclosing, pending.items));
+        }
+        Ok(())
+    }
+
+    /// Splits a block too long for a chunk along the children of its body, or as text if it has none.
+    fn split_block(
+        &self,
+        code: &str,
+        block: Block,
+        item: Option<String>,
+        enclosing: &[String],
+        chunks: &mut Vec<CodeChunk>,
+    ) -> Result<(), CodeSplitterError> {
+        let body = block
+            .node
+            .and_then(|node| definition(node).child_by_field_name("body"))
+            .filter(|body| body.named_child_count() > 0);
+        if let Some(body) = body {
+            let mut enclosing = enclosing.to_vec();
+            enclosing.extend(item);
+            let blocks = self.blocks(body, Some(block.range.start..body.start_byte() + 1));
+            return self.split_blocks(code, blocks, &enclosing, chunks);
+        }
+        let text = &code[block.range.clone()];
+        for (range, _) in
+            self.splitter
+                .split_text_with_offsets(text, self.max_tokens_per_chunk, 0)?
+        {
+            if !range.is_empty() {
+                let start = block.range.start;
+                let items = item.iter().cloned().collect();
+                chunks.push(chunk(
+                    code,
+                    start + range.start..start + range.end,
+                    enclosing,
+                    items,
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn chunk(code: &str, range: Range<usize>, enclosing: &[String], items: Vec<String>) -> CodeChunk {
+    let first = code[..range.start].matches('\n').count() + 1;
+    let last = first + code[range.clone()].matches('\n').count();
+    CodeChunk {
+        text: code[range.clone()].to_string(),
+        range,
+        lines: (first, last),
+        enclosing: enclosing.to_vec(),
+        items,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CodeLanguage, CodeSplitter};
+    use crate::NaiveWhitespaceSplitter;
+
+    #[test]
+    fn splits_along_items() {
+        let code = "use std::fmt;\n\n/// A point.\nstruct Point {\n    x: i32,\n}\n\nimpl Point {\n    /// Creates a point.\n    fn new(x: i32) -> Self {\n        Self { x }\n    }\n\n    fn norm(&self) -> i32 {\n        let x = self.x;\n        x.abs()\n    }\n}\n";
+        let splitter = CodeSplitter::new(CodeLanguage::Rust, NaiveWhitespaceSplitter, 20);
+        let chunks = splitter.split(code).unwrap();
+        let summary: Vec<(&str, Vec<&str>, Vec<&str>)> = chunks
+            .iter()
+            .map(|c| {
+                (
+                    c.text.as_str(),
+                    c.enclosing.iter().map(String::as_str).collect(),
+                    c.items.iter().map(String::as_str).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    "use std::fmt;\n\n/// A point.\nstruct Point {\n    x: i32,\n}",
+                    vec![],
+                    vec!["struct Point"]
+                ),
+                (
+                    "impl Point {\n    /// Creates a point.\n    fn new(x: i32) -> Self {\n        Self { x }\n    }",
+                    vec!["impl Point"],
+                    vec!["fn new(x: i32) -> Self"]
+                ),
+                (
+                    "fn norm(&self) -> i32 {\n        let x = self.x;\n        x.abs()\n    }",
+                    vec!["impl Point"],
+                    vec!["fn norm(&self) -> i32"]
+                ),
+            ]
+        );
+        assert_eq!(chunks[2].lines, (14, 17));
+        for chunk in &chunks {
+            assert_eq!(&code[chunk.range.clone()], chunk.text);
+        }
+    }
+}
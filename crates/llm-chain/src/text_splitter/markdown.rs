@@ -0,0 +1,289 @@
+use std::marker::PhantomData;
+use std::ops::Range;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::TextSplitter;
+use crate::schema::{Document, Provenance};
+use crate::tokens::TokenizerError;
+
+/// A chunk of a Markdown text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarkdownChunk {
+    /// The text of the chunk, exactly as in the source.
+    pub text: String,
+    /// The byte range of the chunk in the source.
+    pub range: Range<usize>,
+    /// The headings the chunk is under, from the top-level heading down.
+    pub heading_path: Vec<String>,
+}
+
+/// The metadata of a chunk split from a Markdown document.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MarkdownMetadata<M> {
+    /// The headings the chunk is under, from the top-level heading down.
+    pub heading_path: Vec<String>,
+    /// The metadata of the document the chunk was split from.
+    pub document: Option<M>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BlockKind {
+    Heading,
+    Code,
+    Text,
+}
+
+/// A part of a Markdown text that is kept together if possible: a heading, a fenced code block, a list item or a
+/// paragraph.
+struct Block {
+    kind: BlockKind,
+    range: Range<usize>,
+    heading_path: Vec<String>,
+}
+
+/// Returns the character and length of the code fence `line` opens or closes, if it is one.
+fn fence_marker(line: &str) -> Option<(char, usize)> {
+    let c = line.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let len = line.chars().take_while(|&d| d == c).count();
+    (len >= 3).then_some((c, len))
+}
+
+/// Returns the level and text of the ATX heading on `line`, if it is one.
+fn heading(line: &str) -> Option<(usize, String)> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    let rest = &line[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with([' ', '\t'])) {
+        return None;
+    }
+    Some((
+        level,
+        rest.trim().trim_end_matches('#').trim_end().to_string(),
+    ))
+}
+
+fn is_list_item(line: &str) -> bool {
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    let marker = if digits > 0 {
+        line[digits..].strip_prefix(['.', ')'])
+    } else {
+        line.strip_prefix(['-', '*', '+'])
+    };
+    marker.is_some_and(|rest| rest.is_empty() || rest.starts_with([' ', '\t']))
+}
+
+fn parse_blocks(markdown: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut headings: Vec<(usize, String)> = Vec::new();
+    let path = |headings: &[(usize, String)]| headings.iter().map(|(_, h)| h.clone()).collect();
+    let mut paragraph: Option<Block> = None;
+    let mut fence: Option<(char, usize, usize)> = None;
+    let mut offset = 0;
+    for line in markdown.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let end = start + line.trim_end().len();
+        let trimmed = line.trim_start();
+        let indented = line.len() - trimmed.len() >= 4;
+
+        if let Some((c, len, fence_start)) = fence {
+            let closes = !indented
+                && fence_marker(trimmed).is_some_and(|(d, n)| d == c && n >= len)
+                && trimmed.trim_start_matches(c).trim().is_empty();
+            if closes {
+                blocks.push(Block {
+                    kind: BlockKind::Code,
+                    range: fence_start..end,
+                    heading_path: path(&headings),
+                });
+                fence = None;
+            }
+            continue;
+        }
+        if let Some((c, len)) = fence_marker(trimmed).filter(|_| !indented) {
+            blocks.extend(paragraph.take());
+            fence = Some((c, len, start));
+            continue;
+        }
+        if trimmed.trim().is_empty() {
+            blocks.extend(paragraph.take());
+            continue;
+        }
+        if let Some((level, text)) = heading(trimmed).filter(|_| !indented) {
+            blocks.extend(paragraph.take());
+            headings.retain(|(l, _)| *l < level);
+            headings.push((level, text));
+            blocks.push(Block {
+                kind: BlockKind::Heading,
+                range: start..end,
+                heading_path: path(&headings),
+            });
+            continue;
+        }
+        match &mut paragraph {
+            Some(block) if !is_list_item(trimmed) => block.range.end = end,
+            _ => {
+                blocks.extend(paragraph.take());
+                paragraph = Some(Block {
+                    kind: BlockKind::Text,
+                    range: start + line.len() - trimmed.len()..end,
+                    heading_path: path(&headings),
+                });
+            }
+        }
+    }
+    if let Some((_, _, fence_start)) = fence {
+        blocks.push(Block {
+            kind: BlockKind::Code,
+            range: fence_start..markdown.trim_end().len(),
+            heading_path: path(&headings),
+        });
+    }
+    blocks.extend(paragraph);
+    blocks
+}
+
+/// Splits Markdown into chunks along its structure.
+///
+/// Chunks start at headings, and are filled with whole paragraphs, list items and code blocks as long as they fit in
+/// the maximum number of tokens. A paragraph or list item too long for a chunk of its own is split with the
+/// underlying `TextSplitter`. Fenced code blocks are never split, even if they are longer than the maximum, so code
+/// examples stay intact. Each chunk records the path of headings it is under, which can be prepended to the chunk or
+/// used for filtering.
+///
+/// The text of a chunk is taken from the source unchanged, so its formatting is preserved.
+pub struct MarkdownSplitter<S, T>
+where
+    S: TextSplitter<T>,
+    T: Clone,
+{
+    splitter: S,
+    max_tokens_per_chunk: usize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<S, T> MarkdownSplitter<S, T>
+where
+    S: TextSplitter<T>,
+    T: Clone,
+{
+    /// Creates a splitter counting tokens with `splitter`.
+    pub fn new(splitter: S, max_tokens_per_chunk: usize) -> Self {
+        Self {
+            splitter,
+            max_tokens_per_chunk,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn split(&self, markdown: &str) -> Result<Vec<MarkdownChunk>, TokenizerError> {
+        let mut chunks = Vec::new();
+        let mut current: Option<(Range<usize>, Vec<String>, usize)> = None;
+        let chunk = |range: Range<usize>, heading_path: Vec<String>| MarkdownChunk {
+            text: markdown[range.clone()].to_string(),
+            range,
+            heading_path,
+        };
+        for block in parse_blocks(markdown) {
+            let text = &markdown[block.range.clone()];
+            let tokens = self.splitter.tokenize_str(text)?.len();
+            if let Some((range, _, count)) = &mut current {
+                if block.kind != BlockKind::Heading && *count + tokens <= self.max_tokens_per_chunk
+                {
+                    range.end = block.range.end;
+                    *count += tokens;
+                    continue;
+                }
+            }
+            if let Some((range, heading_path, _)) = current.take() {
+                chunks.push(chunk(range, heading_path));
+            }
+            if block.kind == BlockKind::Text && tokens > self.max_tokens_per_chunk {
+                for (range, _) in
+                    self.splitter
+                        .split_text_with_offsets(text, self.max_tokens_per_chunk, 0)?
+                {
+                    if !range.is_empty() {
+                        let start = block.range.start;
+                        chunks.push(chunk(
+                            start + range.start..start + range.end,
+                            block.heading_path.clone(),
+                        ));
+                    }
+                }
+                continue;
+            }
+            current = Some((block.range, block.heading_path, tokens));
+        }
+        if let Some((range, heading_path, _)) = current {
+            chunks.push(chunk(range, heading_path));
+        }
+        Ok(chunks)
+    }
+
+    /// Splits a Markdown document into chunks that record their heading path and provenance, like `split_document`.
+    pub fn split_document<M>(
+        &self,
+        document: &Document<M>,
+        source_id: &str,
+    ) -> Result<Vec<Document<MarkdownMetadata<M>>>, TokenizerError>
+    where
+        M: Serialize + DeserializeOwned + Clone,
+    {
+        let parent = document
+            .provenance
+            .clone()
+            .unwrap_or_else(|| Provenance::new(source_id, document.page_content.len()));
+        Ok(self
+            .split(&document.page_content)?
+            .into_iter()
+            .map(|chunk| Document {
+                id: None,
+                page_content: chunk.text,
+                metadata: Some(MarkdownMetadata {
+                    heading_path: chunk.heading_path,
+                    document: document.metadata.clone(),
+                }),
+                provenance: Some(parent.sub_range(chunk.range.start, chunk.range.end)),
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MarkdownSplitter;
+    use crate::NaiveWhitespaceSplitter;
+
+    #[test]
+    fn splits_along_structure() {
+        let markdown = "# Guide\n\nIntro text here.\n\n## Install\n\nRun this:\n\n```sh\n# not a heading\ncargo add llm-chain and many more words\n```\n\n- first item\n- second item\n\n## Usage\n\nCall it.\n";
+        let splitter = MarkdownSplitter::new(NaiveWhitespaceSplitter, 6);
+        let chunks = splitter.split(markdown).unwrap();
+        let summary: Vec<(&str, Vec<&str>)> = chunks
+            .iter()
+            .map(|c| {
+                (
+                    c.text.as_str(),
+                    c.heading_path.iter().map(String::as_str).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("# Guide\n\nIntro text here.", vec!["Guide"]),
+                ("## Install\n\nRun this:", vec!["Guide", "Install"]),
+                (
+                    "```sh\n# not a heading\ncargo add llm-chain and many more words\n```",
+                    vec!["Guide", "Install"]
+                ),
+                ("- first item\n- second item", vec!["Guide", "Install"]),
+                ("## Usage\n\nCall it.", vec!["Guide", "Usage"]),
+            ]
+        );
+        for chunk in &chunks {
+            assert_eq!(&markdown[chunk.range.clone()], chunk.text);
+        }
+    }
+}
@@ -0,0 +1,269 @@
+//! TextSplitters break text small enough parts to be fed to the model.
+//!
+//! TextSplitters are responsible for breaking text into small enough parts to be fed to the model. This means that they work with the token stream of the model.
+//!
+//! Splitting a `Document` keeps track of where each chunk is located in the original source, see `split_document`.
+//!
+//! `RecursiveSplitter` splits text on paragraphs, then sentences, then words, and is a good default for documents.
+//! `MarkdownSplitter` splits Markdown along its headings, paragraphs and code blocks instead of at fixed token counts.
+//! `SemanticSplitter` splits text where its topic changes, according to the embeddings of its sentences.
+//! `CodeSplitter`, behind the `code` feature, splits source code along its functions and classes.
+#[cfg(feature = "code")]
+mod code;
+mod markdown;
+mod recursive;
+mod semantic;
+
+#[cfg(feature = "code")]
+pub use code::{CodeChunk, CodeLanguage, CodeMetadata, CodeSplitter, CodeSplitterError};
+pub use markdown::{MarkdownChunk, MarkdownMetadata, MarkdownSplitter};
+pub use recursive::{Characters, RecursiveSplitter};
+pub(crate) use semantic::sentences;
+pub use semantic::{SemanticSplitter, SemanticSplitterError, SemanticThreshold};
+
+use crate::schema::{Document, Provenance};
+use crate::tokens::{Tokenizer, TokenizerError};
+use serde::{de::DeserializeOwned, Serialize};
+use std::cmp::max;
+use std::ops::Range;
+
+pub trait TextSplitter<TokenType>: Tokenizer<TokenType>
+where
+    TokenType: Clone,
+{
+    fn split_text(
+        &self,
+        doc: &str,
+        max_tokens_per_chunk: usize,
+        chunk_overlap: usize,
+    ) -> Result<Vec<String>, TokenizerError> {
+        let tokens = self.tokenize_str(doc)?;
+        let step_size = max(
+            max_tokens_per_chunk.checked_sub(chunk_overlap).unwrap_or(1),
+            1,
+        );
+
+        debug_assert_ne!(step_size, 0);
+
+        (0..tokens.len())
+            .step_by(step_size)
+            .map(|start_idx| {
+                let end_idx = usize::min(start_idx + max_tokens_per_chunk, tokens.len());
+                self.to_string(tokens[start_idx..end_idx].to_vec())
+            })
+            .collect()
+    }
+
+    /// Splits `doc` like `split_text`, and also returns the byte range in `doc` that each chunk was taken from.
+    ///
+    /// Tokenizers don't have to reproduce whitespace exactly, so chunks are located in `doc` ignoring differences
+    /// in whitespace. A chunk that can't be located gets an empty range at the position of the previous chunk.
+    fn split_text_with_offsets(
+        &self,
+        doc: &str,
+        max_tokens_per_chunk: usize,
+        chunk_overlap: usize,
+    ) -> Result<Vec<(Range<usize>, String)>, TokenizerError> {
+        let chunks = self.split_text(doc, max_tokens_per_chunk, chunk_overlap)?;
+        let haystack: Vec<(usize, char)> = doc
+            .char_indices()
+            .filter(|(_, c)| !c.is_whitespace())
+            .collect();
+        let mut cursor = 0;
+        let mut last_start = 0;
+        Ok(chunks
+            .into_iter()
+            .map(|chunk| {
+                let range = match locate_ignoring_whitespace(&haystack, &chunk, cursor) {
+                    Some((first, last)) => {
+                        cursor = first + 1;
+                        let (start, _) = haystack[first];
+                        let (end, c) = haystack[last];
+                        last_start = start;
+                        start..end + c.len_utf8()
+                    }
+                    None => last_start..last_start,
+                };
+                (range, chunk)
+            })
+            .collect())
+    }
+}
+
+/// Finds `needle` in the non-whitespace characters of a document, starting at index `from`, and returns the indices
+/// of its first and last characters.
+fn locate_ignoring_whitespace(
+    haystack: &[(usize, char)],
+    needle: &str,
+    from: usize,
+) -> Option<(usize, usize)> {
+    let needle: Vec<char> = needle.chars().filter(|c| !c.is_whitespace()).collect();
+    if needle.is_empty() || haystack.len() < needle.len() {
+        return None;
+    }
+    (from..=haystack.len() - needle.len())
+        .find(|&i| {
+            haystack[i..i + needle.len()]
+                .iter()
+                .zip(needle.iter())
+                .all(|((_, a), b)| a == b)
+        })
+        .map(|i| (i, i + needle.len() - 1))
+}
+
+/// Splits a document into chunks that keep its metadata and record their provenance.
+///
+/// If the document has a provenance, the chunks' byte ranges are relative to the document's source; otherwise they
+/// are relative to the document itself, with `source_id` as the source.
+pub fn split_document<T, S, M>(
+    splitter: &S,
+    document: &Document<M>,
+    source_id: &str,
+    max_tokens_per_chunk: usize,
+    chunk_overlap: usize,
+) -> Result<Vec<Document<M>>, TokenizerError>
+where
+    T: Clone,
+    S: TextSplitter<T> + ?Sized,
+    M: Serialize + DeserializeOwned + Clone,
+{
+    let parent = document
+        .provenance
+        .clone()
+        .unwrap_or_else(|| Provenance::new(source_id, document.page_content.len()));
+    Ok(splitter
+        .split_text_with_offsets(&document.page_content, max_tokens_per_chunk, chunk_overlap)?
+        .into_iter()
+        .map(|(range, chunk)| Document {
+            id: None,
+            page_content: chunk,
+            metadata: document.metadata.clone(),
+            provenance: Some(parent.sub_range(range.start, range.end)),
+        })
+        .collect())
+}
+
+pub struct NaiveWhitespaceSplitter;
+
+impl Tokenizer<String> for NaiveWhitespaceSplitter {
+    fn tokenize_str(&self, doc: &str) -> Result<Vec<String>, TokenizerError> {
+        Ok(doc.split_whitespace().map(|t| t.to_string()).collect())
+    }
+
+    fn to_string(&self, tokens: Vec<String>) -> Result<String, TokenizerError> {
+        Ok(tokens
+            .iter()
+            .map(|token| token.to_string())
+            .collect::<Vec<String>>()
+            .join(" "))
+    }
+}
+
+impl TextSplitter<String> for NaiveWhitespaceSplitter {}
+
+#[cfg(test)]
+mod tests {
+    use super::{NaiveWhitespaceSplitter, TextSplitter, TokenizerError};
+
+    #[test]
+    fn whitespace_splitter_offsets_point_into_original() -> Result<(), TokenizerError> {
+        let doc = "This is  a sample\ntext that will be\n\nsplit.";
+        let splitter = NaiveWhitespaceSplitter;
+
+        let chunks = splitter.split_text_with_offsets(doc, 4, 1)?;
+
+        let spans: Vec<&str> = chunks
+            .iter()
+            .map(|(range, _)| &doc[range.clone()])
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                "This is  a sample",
+                "sample\ntext that will",
+                "will be\n\nsplit."
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn whitespace_splitter_no_overlap() -> Result<(), TokenizerError> {
+        let doc = "This is a sample text that will be split into chunks based on tokens.";
+        let max_tokens_per_chunk = 4;
+        let chunk_overlap = 0;
+
+        let splitter = NaiveWhitespaceSplitter;
+
+        let chunks = splitter.split_text(doc, max_tokens_per_chunk, chunk_overlap)?;
+
+        assert_eq!(
+            chunks,
+            vec![
+                "This is a sample",
+                "text that will be",
+                "split into chunks based",
+                "on tokens."
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn whitespace_splitter_1_overlap() -> Result<(), TokenizerError> {
+        let doc = "This is a sample text that will be split into chunks based on tokens.";
+        let max_tokens_per_chunk = 4;
+        let chunk_overlap = 1;
+
+        let splitter = NaiveWhitespaceSplitter;
+
+        let chunks = splitter.split_text(doc, max_tokens_per_chunk, chunk_overlap)?;
+
+        assert_eq!(
+            chunks,
+            vec![
+                "This is a sample",
+                "sample text that will",
+                "will be split into",
+                "into chunks based on",
+                "on tokens."
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn whitespace_splitter_equal_overlap() -> Result<(), TokenizerError> {
+        let doc = "This is a sample text that will be split into chunks based on tokens.";
+        let max_tokens_per_chunk = 4;
+        let chunk_overlap = max_tokens_per_chunk;
+
+        let splitter = NaiveWhitespaceSplitter;
+
+        let chunks = splitter.split_text(doc, max_tokens_per_chunk, chunk_overlap)?;
+
+        assert_eq!(
+            chunks,
+            vec![
+                "This is a sample",
+                "is a sample text",
+                "a sample text that",
+                "sample text that will",
+                "text that will be",
+                "that will be split",
+                "will be split into",
+                "be split into chunks",
+                "split into chunks based",
+                "into chunks based on",
+                "chunks based on tokens.",
+                "based on tokens.",
+                "on tokens.",
+                "tokens."
+            ]
+        );
+
+        Ok(())
+    }
+}
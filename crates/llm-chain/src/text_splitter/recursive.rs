@@ -0,0 +1,219 @@
+use std::collections::VecDeque;
+use std::ops::Range;
+
+use super::TextSplitter;
+use crate::tokens::{Tokenizer, TokenizerError};
+
+/// The separators `RecursiveSplitter` tries in order: paragraphs, lines, sentences, words and finally characters.
+const DEFAULT_SEPARATORS: &[&str] = &["\n\n", "\n", ". ", "? ", "! ", "; ", ", ", " ", ""];
+
+/// A tokenizer whose tokens are characters, for splitting text by length in characters.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Characters;
+
+impl Tokenizer<char> for Characters {
+    fn tokenize_str(&self, doc: &str) -> Result<Vec<char>, TokenizerError> {
+        Ok(doc.chars().collect())
+    }
+
+    fn to_string(&self, tokens: Vec<char>) -> Result<String, TokenizerError> {
+        Ok(tokens.into_iter().collect())
+    }
+}
+
+impl TextSplitter<char> for Characters {}
+
+/// Splits text at the most meaningful boundaries that keep chunks small enough.
+///
+/// The text is split on paragraphs first. Paragraphs that are too long are split on lines, then sentences, then
+/// words, and as a last resort characters. The pieces are then merged back into chunks as large as allowed, and
+/// consecutive chunks share up to `chunk_overlap` tokens of whole pieces, so context isn't lost at chunk boundaries.
+/// Chunks are taken from the text unchanged, apart from surrounding whitespace.
+///
+/// Lengths are counted with the wrapped tokenizer, so chunks can be sized in the tokens of a model: see
+/// `ExecutorTokenCountExt::token_splitter`. `Characters`, the default, counts characters.
+#[derive(Debug, Clone)]
+pub struct RecursiveSplitter<K = Characters> {
+    tokenizer: K,
+    separators: Vec<String>,
+}
+
+impl Default for RecursiveSplitter<Characters> {
+    fn default() -> Self {
+        Self::new(Characters)
+    }
+}
+
+impl<K> RecursiveSplitter<K> {
+    /// Creates a splitter counting lengths with `tokenizer`.
+    pub fn new(tokenizer: K) -> Self {
+        Self {
+            tokenizer,
+            separators: DEFAULT_SEPARATORS.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    /// Sets the separators to try, from the most to the least meaningful. The empty separator splits into
+    /// characters; without it, pieces that can't be split further are kept even if they are too long.
+    pub fn with_separators<S: Into<String>>(mut self, separators: Vec<S>) -> Self {
+        self.separators = separators.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Splits `text`, found at `offset` in the document, into pieces of at most `max_tokens` tokens, each with its
+    /// length.
+    fn pieces<T>(
+        &self,
+        text: &str,
+        offset: usize,
+        separators: &[String],
+        max_tokens: usize,
+        pieces: &mut Vec<(Range<usize>, usize)>,
+    ) -> Result<(), TokenizerError>
+    where
+        T: Clone,
+        K: Tokenizer<T>,
+    {
+        let len = self.tokenizer.tokenize_str(text)?.len();
+        let whole = offset..offset + text.len();
+        let Some(i) = separators
+            .iter()
+            .position(|s| s.is_empty() || text.contains(s.as_str()))
+            .filter(|_| len > max_tokens)
+        else {
+            pieces.push((whole, len));
+            return Ok(());
+        };
+        let parts: Vec<&str> = if separators[i].is_empty() {
+            text.char_indices()
+                .map(|(j, c)| &text[j..j + c.len_utf8()])
+                .collect()
+        } else {
+            text.split_inclusive(separators[i].as_str()).collect()
+        };
+        let mut start = offset;
+        for part in parts {
+            self.pieces(part, start, &separators[i + 1..], max_tokens, pieces)?;
+            start += part.len();
+        }
+        Ok(())
+    }
+}
+
+/// Merges consecutive pieces into chunks of at most `max_tokens` tokens, repeating up to `overlap` tokens of pieces
+/// at the start of the next chunk.
+fn merge(
+    doc: &str,
+    pieces: &[(Range<usize>, usize)],
+    max_tokens: usize,
+    overlap: usize,
+) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut window: VecDeque<&(Range<usize>, usize)> = VecDeque::new();
+    let mut total = 0;
+    let mut push = |window: &VecDeque<&(Range<usize>, usize)>| {
+        if let (Some(first), Some(last)) = (window.front(), window.back()) {
+            let chunk = doc[first.0.start..last.0.end].trim();
+            if !chunk.is_empty() {
+                chunks.push(chunk.to_string());
+            }
+        }
+    };
+    for piece in pieces {
+        if total + piece.1 > max_tokens && !window.is_empty() {
+            push(&window);
+            while total > overlap || (total + piece.1 > max_tokens && !window.is_empty()) {
+                let Some(dropped) = window.pop_front() else {
+                    break;
+                };
+                total -= dropped.1;
+            }
+        }
+        window.push_back(piece);
+        total += piece.1;
+    }
+    push(&window);
+    chunks
+}
+
+impl<T, K> Tokenizer<T> for RecursiveSplitter<K>
+where
+    T: Clone,
+    K: Tokenizer<T>,
+{
+    fn tokenize_str(&self, doc: &str) -> Result<Vec<T>, TokenizerError> {
+        self.tokenizer.tokenize_str(doc)
+    }
+
+    fn to_string(&self, tokens: Vec<T>) -> Result<String, TokenizerError> {
+        self.tokenizer.to_string(tokens)
+    }
+}
+
+impl<T, K> TextSplitter<T> for RecursiveSplitter<K>
+where
+    T: Clone,
+    K: Tokenizer<T>,
+{
+    fn split_text(
+        &self,
+        doc: &str,
+        max_tokens_per_chunk: usize,
+        chunk_overlap: usize,
+    ) -> Result<Vec<String>, TokenizerError> {
+        let max_tokens = max_tokens_per_chunk.max(1);
+        let mut pieces = Vec::new();
+        self.pieces(doc, 0, &self.separators, max_tokens, &mut pieces)?;
+        Ok(merge(doc, &pieces, max_tokens, chunk_overlap))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RecursiveSplitter;
+    use crate::text_splitter::TextSplitter;
+    use crate::NaiveWhitespaceSplitter;
+
+    const TEXT: &str = "Chains run prompts in a row. Each step feeds the next.\n\nSplitters cut long texts. They keep sentences whole when they can.";
+
+    #[test]
+    fn splits_on_the_largest_boundaries_that_fit() {
+        let splitter = RecursiveSplitter::default();
+        assert_eq!(
+            splitter.split_text(TEXT, 70, 0).unwrap(),
+            vec![
+                "Chains run prompts in a row. Each step feeds the next.",
+                "Splitters cut long texts. They keep sentences whole when they can.",
+            ]
+        );
+        assert_eq!(
+            splitter.split_text(TEXT, 40, 0).unwrap(),
+            vec![
+                "Chains run prompts in a row.",
+                "Each step feeds the next.",
+                "Splitters cut long texts.",
+                "They keep sentences whole when they can.",
+            ]
+        );
+        assert_eq!(
+            splitter.split_text("abcdefgh", 3, 1).unwrap(),
+            vec!["abc", "cde", "efg", "gh"]
+        );
+    }
+
+    #[test]
+    fn overlaps_whole_pieces() {
+        let text = "One two three. Four five six. Seven eight nine. Ten eleven twelve.";
+        let splitter = RecursiveSplitter::new(NaiveWhitespaceSplitter);
+        assert_eq!(
+            splitter.split_text(text, 6, 3).unwrap(),
+            vec![
+                "One two three. Four five six.",
+                "Four five six. Seven eight nine.",
+                "Seven eight nine. Ten eleven twelve.",
+            ]
+        );
+        let offsets = splitter.split_text_with_offsets(text, 6, 3).unwrap();
+        assert_eq!(&text[offsets[1].0.clone()], offsets[1].1);
+    }
+}
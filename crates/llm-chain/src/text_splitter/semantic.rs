@@ -0,0 +1,311 @@
+use std::ops::Range;
+
+use thiserror::Error;
+
+use super::{Characters, RecursiveSplitter, TextSplitter};
+use crate::retrieval::cosine_similarity;
+use crate::tokens::{Tokenizer, TokenizerError};
+use crate::traits::Embeddings;
+
+/// Where `SemanticSplitter` starts a new chunk.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SemanticThreshold {
+    /// Between sentences whose embeddings have a cosine similarity below this value.
+    Similarity(f32),
+    /// Between sentences whose similarity is below this percentile, from 0 to 100, of the similarities of all
+    /// adjacent sentences of the text. This adapts to the range of similarities of the embedding model.
+    Percentile(f32),
+}
+
+impl Default for SemanticThreshold {
+    fn default() -> Self {
+        Self::Percentile(10.0)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SemanticSplitterError<E: std::error::Error> {
+    #[error(transparent)]
+    Tokenizer(#[from] TokenizerError),
+    #[error("Error embedding the sentences: {0}")]
+    Embeddings(E),
+}
+
+/// Returns the byte ranges of the sentences of `text`, without surrounding whitespace. Paragraph breaks also end
+/// sentences.
+pub(crate) fn sentences(text: &str) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut push = |range: Range<usize>| {
+        let sentence = &text[range.clone()];
+        let start = range.start + (sentence.len() - sentence.trim_start().len());
+        let end = range.start + sentence.trim_end().len();
+        if start < end {
+            ranges.push(start..end);
+        }
+    };
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let next = chars.peek().map(|&(_, next)| next);
+        let ends = match c {
+            '。' | '？' | '！' => true,
+            '.' | '?' | '!' => next.filter(|n| !n.is_whitespace()).is_none(),
+            '\n' => next == Some('\n'),
+            _ => false,
+        };
+        if ends {
+            let end = i + c.len_utf8();
+            push(start..end);
+            start = end;
+        }
+    }
+    push(start..text.len());
+    ranges
+}
+
+/// Returns the `percentile`th percentile of `values`, interpolating between values.
+fn percentile(values: &[f32], percentile: f32) -> f32 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let position = (percentile.clamp(0.0, 100.0) / 100.0) * (sorted.len() - 1) as f32;
+    let (below, above) = (position.floor() as usize, position.ceil() as usize);
+    sorted[below] + (sorted[above] - sorted[below]) * (position - below as f32)
+}
+
+/// Splits text where its topic changes, according to the embeddings of its sentences.
+///
+/// Every sentence is embedded along with `window` sentences on each side, and a chunk ends between two sentences
+/// when the similarity of their embeddings falls below the threshold. Chunks also end before they get longer than
+/// the maximum number of tokens, and sentences too long for a chunk of their own are split with a
+/// `RecursiveSplitter`. Chunks don't overlap: the overlap passed to `split_text` is ignored.
+///
+/// Embedding is asynchronous, so `split` is the method to call from async code. The splitter also implements
+/// `TextSplitter`, so it can be used wherever other splitters are, such as in an `IndexingPipeline`; the
+/// `TextSplitter` methods block the current thread until the embeddings are computed, and in an async runtime should
+/// be called from a thread where blocking is allowed, such as with `spawn_blocking`.
+pub struct SemanticSplitter<E, K = Characters> {
+    embeddings: E,
+    splitter: RecursiveSplitter<K>,
+    threshold: SemanticThreshold,
+    window: usize,
+}
+
+impl<E: Embeddings> SemanticSplitter<E> {
+    /// Creates a splitter embedding sentences with `embeddings`, counting lengths in characters.
+    pub fn new(embeddings: E) -> Self {
+        Self {
+            embeddings,
+            splitter: RecursiveSplitter::default(),
+            threshold: SemanticThreshold::default(),
+            window: 1,
+        }
+    }
+}
+
+impl<E: Embeddings, K> SemanticSplitter<E, K> {
+    /// Counts lengths with `tokenizer`, such as the tokenizer of a model.
+    pub fn with_tokenizer<K2>(self, tokenizer: K2) -> SemanticSplitter<E, K2> {
+        SemanticSplitter {
+            embeddings: self.embeddings,
+            splitter: RecursiveSplitter::new(tokenizer),
+            threshold: self.threshold,
+            window: self.window,
+        }
+    }
+
+    /// Sets where new chunks start. Defaults to the 10th percentile of the similarities.
+    pub fn with_threshold(mut self, threshold: SemanticThreshold) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Sets the number of sentences on each side embedded along with every sentence, which smooths out short
+    /// sentences. Defaults to 1.
+    pub fn with_window(mut self, window: usize) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Splits `text` into chunks of at most `max_tokens_per_chunk` tokens, returning their byte ranges in `text`.
+    pub async fn split_ranges<T>(
+        &self,
+        text: &str,
+        max_tokens_per_chunk: usize,
+    ) -> Result<Vec<Range<usize>>, SemanticSplitterError<E::Error>>
+    where
+        T: Clone,
+        K: Tokenizer<T>,
+    {
+        let sentences = sentences(text);
+        let similarities = if sentences.len() > 1 {
+            let windows = (0..sentences.len())
+                .map(|i| {
+                    let first = &sentences[i.saturating_sub(self.window)];
+                    let last = &sentences[(i + self.window).min(sentences.len() - 1)];
+                    text[first.start..last.end].to_string()
+                })
+                .collect();
+            let embeddings = self
+                .embeddings
+                .embed_texts(windows)
+                .await
+                .map_err(SemanticSplitterError::Embeddings)?;
+            embeddings
+                .windows(2)
+                .map(|pair| cosine_similarity(&pair[0], &pair[1]))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let threshold = match self.threshold {
+            SemanticThreshold::Similarity(similarity) => similarity,
+            SemanticThreshold::Percentile(_) if similarities.is_empty() => 0.0,
+            SemanticThreshold::Percentile(p) => percentile(&similarities, p),
+        };
+
+        let mut chunks = Vec::new();
+        let mut current: Option<(Range<usize>, usize)> = None;
+        for (i, sentence) in sentences.iter().enumerate() {
+            let tokens = self.splitter.tokenize_str(&text[sentence.clone()])?.len();
+            if let Some((range, count)) = &mut current {
+                let similar = similarities.get(i - 1).copied().unwrap_or(threshold) >= threshold;
+                if similar && *count + tokens <= max_tokens_per_chunk {
+                    range.end = sentence.end;
+                    *count += tokens;
+                    continue;
+                }
+            }
+            chunks.extend(current.take().map(|(range, _)| range));
+            if tokens > max_tokens_per_chunk {
+                let pieces = self.splitter.split_text_with_offsets(
+                    &text[sentence.clone()],
+                    max_tokens_per_chunk,
+                    0,
+                )?;
+                chunks.extend(
+                    pieces
+                        .into_iter()
+                        .filter(|(range, _)| !range.is_empty())
+                        .map(|(range, _)| sentence.start + range.start..sentence.start + range.end),
+                );
+                continue;
+            }
+            current = Some((sentence.clone(), tokens));
+        }
+        chunks.extend(current.map(|(range, _)| range));
+        Ok(chunks)
+    }
+
+    /// Splits `text` into chunks of at most `max_tokens_per_chunk` tokens.
+    pub async fn split<T>(
+        &self,
+        text: &str,
+        max_tokens_per_chunk: usize,
+    ) -> Result<Vec<String>, SemanticSplitterError<E::Error>>
+    where
+        T: Clone,
+        K: Tokenizer<T>,
+    {
+        Ok(self
+            .split_ranges(text, max_tokens_per_chunk)
+            .await?
+            .into_iter()
+            .map(|range| text[range].to_string())
+            .collect())
+    }
+}
+
+impl<T, E, K> Tokenizer<T> for SemanticSplitter<E, K>
+where
+    T: Clone,
+    K: Tokenizer<T>,
+{
+    fn tokenize_str(&self, doc: &str) -> Result<Vec<T>, TokenizerError> {
+        self.splitter.tokenize_str(doc)
+    }
+
+    fn to_string(&self, tokens: Vec<T>) -> Result<String, TokenizerError> {
+        self.splitter.to_string(tokens)
+    }
+}
+
+impl<T, E, K> TextSplitter<T> for SemanticSplitter<E, K>
+where
+    T: Clone,
+    E: Embeddings,
+    K: Tokenizer<T>,
+{
+    fn split_text(
+        &self,
+        doc: &str,
+        max_tokens_per_chunk: usize,
+        chunk_overlap: usize,
+    ) -> Result<Vec<String>, TokenizerError> {
+        Ok(self
+            .split_text_with_offsets(doc, max_tokens_per_chunk, chunk_overlap)?
+            .into_iter()
+            .map(|(_, chunk)| chunk)
+            .collect())
+    }
+
+    fn split_text_with_offsets(
+        &self,
+        doc: &str,
+        max_tokens_per_chunk: usize,
+        _chunk_overlap: usize,
+    ) -> Result<Vec<(Range<usize>, String)>, TokenizerError> {
+        let ranges = futures::executor::block_on(self.split_ranges(doc, max_tokens_per_chunk))
+            .map_err(|e| match e {
+                SemanticSplitterError::Tokenizer(e) => e,
+                SemanticSplitterError::Embeddings(e) => {
+                    TokenizerError::EmbeddingError(e.to_string())
+                }
+            })?;
+        Ok(ranges
+            .into_iter()
+            .map(|range| (range.clone(), doc[range].to_string()))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SemanticSplitter, SemanticThreshold};
+    use crate::test_support::FnEmbeddings;
+    use crate::text_splitter::TextSplitter;
+
+    /// Embeds texts by how much they are about cats and about Rust.
+    fn topic_embeddings() -> FnEmbeddings<fn(&str) -> Vec<f32>> {
+        FnEmbeddings(|text| {
+            vec![
+                text.matches("Cats").count() as f32,
+                text.matches("Rust").count() as f32,
+            ]
+        })
+    }
+
+    const TEXT: &str = "Cats purr. Cats nap all day.\n\nRust compiles. Rust is fast! Rust is safe.";
+
+    #[test]
+    fn splits_where_the_topic_changes() {
+        let splitter = SemanticSplitter::new(topic_embeddings()).with_window(0);
+        assert_eq!(
+            splitter.split_text(TEXT, 100, 0).unwrap(),
+            vec![
+                "Cats purr. Cats nap all day.",
+                "Rust compiles. Rust is fast! Rust is safe."
+            ]
+        );
+        let splitter = splitter.with_threshold(SemanticThreshold::Similarity(0.5));
+        assert_eq!(
+            splitter.split_text(TEXT, 30, 0).unwrap(),
+            vec![
+                "Cats purr. Cats nap all day.",
+                "Rust compiles. Rust is fast!",
+                "Rust is safe."
+            ]
+        );
+        let offsets = splitter.split_text_with_offsets(TEXT, 30, 0).unwrap();
+        assert_eq!(&TEXT[offsets[1].0.clone()], offsets[1].1);
+    }
+}
@@ -5,6 +5,7 @@
 //! prompts stay within the context window size supported by a given model.
 
 use crate::step::Step;
+use crate::text_splitter::RecursiveSplitter;
 use crate::{traits, Parameters, TextSplitter};
 use thiserror::Error;
 
@@ -69,6 +70,30 @@ pub trait ExecutorTokenCountExt<Output, Token: Clone, StepTokenizer>:
             .collect();
         Ok(split_params)
     }
+
+    /// Returns a splitter measuring chunks in the tokens of the model used with `options`.
+    ///
+    /// Unlike the executor's text splitter, which cuts at fixed token counts, the returned splitter keeps paragraphs,
+    /// sentences and words together where it can, while still guaranteeing that chunks fit in the given number of the
+    /// model's tokens.
+    fn token_splitter(
+        &self,
+        options: Option<&Self::PerInvocationOptions>,
+    ) -> Result<RecursiveSplitter<Self::StepTokenizer<'_>>, TokenizerError> {
+        Ok(RecursiveSplitter::new(self.get_tokenizer(options)?))
+    }
+
+    /// Returns the number of tokens left for text in the prompt of `step`, formatted with `base_parameters` and an
+    /// empty `text` parameter. This is the largest chunk size that fits in the context window.
+    fn tokens_available(
+        &self,
+        step: &Step<Self>,
+        base_parameters: &Parameters,
+    ) -> Result<usize, PromptTokensError> {
+        let prompt = step.format(&base_parameters.combine(&Parameters::new_with_text("")))?;
+        let tokens_used = self.tokens_used(step.options(), &prompt)?;
+        Ok(tokens_used.tokens_remaining().max(0) as usize)
+    }
 }
 
 /// Struct representing token count information, including the maximum tokens allowed and the
@@ -93,6 +118,11 @@ impl TokenCount {
         }
     }
 
+    /// Returns the number of tokens used.
+    pub fn tokens_used(&self) -> i32 {
+        self.tokens_used
+    }
+
     /// Returns the number of tokens that could be added to the context window.
     pub fn tokens_remaining(&self) -> i32 {
         self.max_tokens - self.tokens_used
@@ -137,6 +167,8 @@ pub enum TokenizerError {
     ToStringError,
     #[error("Error creating tokenizer")]
     TokenizerCreationError,
+    #[error("Error embedding text to split it: {0}")]
+    EmbeddingError(String),
 }
 
 pub trait Tokenizer<TokenType: Clone> {
@@ -162,3 +194,61 @@ pub trait Tokenizer<TokenType: Clone> {
     /// A `Result` containing a string, or an error if there was a problem.
     fn to_string(&self, tokens: Vec<TokenType>) -> Result<String, TokenizerError>;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ExecutorTokenCountExt, PromptTokensError};
+    use crate::agents::mock::{MockExecutor, MockOutput};
+    use crate::text_splitter::TextSplitter;
+    use crate::{prompt, step::Step, NaiveWhitespaceSplitter, Parameters};
+
+    fn executor(max_tokens: i32) -> MockExecutor {
+        let mut executor = MockExecutor::new(vec![]);
+        executor.max_tokens = max_tokens;
+        executor
+    }
+
+    fn tokens_available(
+        executor: &MockExecutor,
+        step: &Step<MockExecutor>,
+    ) -> Result<usize, PromptTokensError> {
+        ExecutorTokenCountExt::<MockOutput, String, NaiveWhitespaceSplitter>::tokens_available(
+            executor,
+            step,
+            &Parameters::new().with("audience", "new users"),
+        )
+    }
+
+    #[test]
+    fn counts_the_tokens_left_for_text() {
+        let step = Step::for_prompt_template(prompt!("Summarize for {{audience}}: {{text}}"));
+        // The prompt without text takes four tokens.
+        assert_eq!(tokens_available(&executor(10), &step).unwrap(), 6);
+        assert_eq!(tokens_available(&executor(4), &step).unwrap(), 0);
+        // A prompt larger than the window leaves no room rather than a negative count.
+        assert_eq!(tokens_available(&executor(2), &step).unwrap(), 0);
+    }
+
+    #[test]
+    fn token_splitter_fills_the_window() {
+        let executor = executor(10);
+        let step = Step::for_prompt_template(prompt!("Summarize for {{audience}}: {{text}}"));
+        let available = tokens_available(&executor, &step).unwrap();
+        let splitter =
+            ExecutorTokenCountExt::<MockOutput, String, NaiveWhitespaceSplitter>::token_splitter(
+                &executor, None,
+            )
+            .unwrap();
+        let fitting = "one two three four five six";
+        assert_eq!(
+            splitter.split_text(fitting, available, 0).unwrap(),
+            vec![fitting]
+        );
+        assert_eq!(
+            splitter
+                .split_text("one two three four five six seven", available, 0)
+                .unwrap(),
+            vec!["one two three four five six", "seven"]
+        );
+    }
+}
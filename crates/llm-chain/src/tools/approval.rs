@@ -0,0 +1,285 @@
+//! Human-in-the-loop approval of tool calls.
+//!
+//! [`ApprovalTool`] wraps a tool, typically one that writes files, runs commands or calls write APIs, and asks an
+//! [`Approver`] before each call. The approver can let the call run, deny it, or change its arguments. Its decision
+//! ends up in the observation of the call, so it is part of the trace of the agent and the model learns about it:
+//!
+//! - an approved call returns the output of the tool, unchanged;
+//! - a denied call fails with [`ApprovalToolError::Denied`], which agents turn into an `Error: ...` observation;
+//! - a call whose arguments were changed returns the new arguments next to the output.
+//!
+//! Every decision is also kept in an [`ApprovalLog`], which can be shared by several tools to audit a run.
+//!
+//! # Example
+//!
+//! ```
+//! use llm_chain::tools::tools::ShellTool;
+//! use llm_chain::tools::{ApprovalDecision, ApprovalRequest, ApprovalTool, ToolCollection};
+//!
+//! let shell = ShellTool::new().with_allowed_commands(["ls", "rm"]);
+//! let tool = ApprovalTool::new(shell, |request: ApprovalRequest| async move {
+//!     // Ask the user here, in a terminal or a web page.
+//!     if request.arguments["command"].as_str().is_some_and(|c| c.starts_with("rm")) {
+//!         ApprovalDecision::Deny("Files must not be deleted".to_string())
+//!     } else {
+//!         ApprovalDecision::Approve
+//!     }
+//! });
+//! let log = tool.log();
+//! let mut tools = ToolCollection::new();
+//! tools.add_tool(tool);
+//! ```
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::description::{ToolDefinition, ToolDescription};
+use super::tool::{Tool, ToolError};
+
+/// A call waiting for approval.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApprovalRequest {
+    /// The name of the tool called.
+    pub tool: String,
+    /// The arguments the model called the tool with.
+    pub arguments: serde_yaml::Value,
+}
+
+/// The decision of an [`Approver`] about a call.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "decision", content = "value", rename_all = "snake_case")]
+pub enum ApprovalDecision {
+    /// Runs the call as the model made it.
+    Approve,
+    /// Refuses the call, for the given reason, which is shown to the model.
+    Deny(String),
+    /// Runs the call with these arguments instead.
+    Modify(serde_yaml::Value),
+}
+
+/// Decides whether calls may run, usually by asking a user.
+///
+/// Closures taking an [`ApprovalRequest`] and returning a future of an [`ApprovalDecision`] are approvers.
+#[async_trait]
+pub trait Approver: Send + Sync {
+    async fn review(&self, request: &ApprovalRequest) -> ApprovalDecision;
+}
+
+#[async_trait]
+impl<F, Fut> Approver for F
+where
+    F: Fn(ApprovalRequest) -> Fut + Send + Sync,
+    Fut: Future<Output = ApprovalDecision> + Send,
+{
+    async fn review(&self, request: &ApprovalRequest) -> ApprovalDecision {
+        self(request.clone()).await
+    }
+}
+
+/// A call reviewed by an [`Approver`], with its decision.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApprovalRecord {
+    #[serde(flatten)]
+    pub request: ApprovalRequest,
+    #[serde(flatten)]
+    pub decision: ApprovalDecision,
+}
+
+/// The decisions made about the calls of one or several [`ApprovalTool`]s, in the order they were made. Clones share
+/// the same records.
+#[derive(Debug, Clone, Default)]
+pub struct ApprovalLog {
+    records: Arc<Mutex<Vec<ApprovalRecord>>>,
+}
+
+impl ApprovalLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the records made so far.
+    pub fn records(&self) -> Vec<ApprovalRecord> {
+        self.records.lock().unwrap().clone()
+    }
+
+    fn push(&self, record: ApprovalRecord) {
+        self.records.lock().unwrap().push(record);
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ApprovalToolError<E: std::error::Error + 'static> {
+    #[error(transparent)]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("The user denied the call to `{tool}`: {reason}")]
+    Denied { tool: String, reason: String },
+    #[error(transparent)]
+    Tool(E),
+}
+
+impl<E: std::error::Error + 'static> ToolError for ApprovalToolError<E> {}
+
+/// A tool asking an [`Approver`] before each call of the tool it wraps.
+pub struct ApprovalTool<T> {
+    tool: T,
+    approver: Arc<dyn Approver>,
+    log: ApprovalLog,
+}
+
+impl<T: Tool> ApprovalTool<T> {
+    /// Wraps `tool`, asking `approver` before each of its calls.
+    pub fn new<A: Approver + 'static>(tool: T, approver: A) -> Self {
+        Self::with_shared_approver(tool, Arc::new(approver))
+    }
+
+    /// Wraps `tool`, asking an approver shared with other tools before each of its calls.
+    pub fn with_shared_approver(tool: T, approver: Arc<dyn Approver>) -> Self {
+        Self {
+            tool,
+            approver,
+            log: ApprovalLog::new(),
+        }
+    }
+
+    /// Records the decisions in `log`, to share it with other tools.
+    pub fn with_log(mut self, log: ApprovalLog) -> Self {
+        self.log = log;
+        self
+    }
+
+    /// Returns the log recording the decisions about the calls of the tool.
+    pub fn log(&self) -> ApprovalLog {
+        self.log.clone()
+    }
+
+    /// Returns the wrapped tool.
+    pub fn inner(&self) -> &T {
+        &self.tool
+    }
+}
+
+#[async_trait]
+impl<T> Tool for ApprovalTool<T>
+where
+    T: Tool + Send + Sync,
+    T::Error: Send + Sync + 'static,
+{
+    type Input = serde_yaml::Value;
+    type Output = serde_yaml::Value;
+    type Error = ApprovalToolError<T::Error>;
+
+    async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+        let request = ApprovalRequest {
+            tool: self.tool.description().name,
+            arguments: input.clone(),
+        };
+        let decision = self.approver.review(&request).await;
+        self.log.push(ApprovalRecord {
+            request: request.clone(),
+            decision: decision.clone(),
+        });
+        match decision {
+            ApprovalDecision::Approve => self
+                .tool
+                .invoke(request.arguments)
+                .await
+                .map_err(ApprovalToolError::Tool),
+            ApprovalDecision::Deny(reason) => Err(ApprovalToolError::Denied {
+                tool: request.tool,
+                reason,
+            }),
+            ApprovalDecision::Modify(arguments) => {
+                let output = self
+                    .tool
+                    .invoke(arguments.clone())
+                    .await
+                    .map_err(ApprovalToolError::Tool)?;
+                let mut observation = serde_yaml::Mapping::new();
+                observation.insert(
+                    "approval".into(),
+                    "The user changed the arguments of the call".into(),
+                );
+                observation.insert("arguments".into(), arguments);
+                observation.insert("output".into(), output);
+                Ok(observation.into())
+            }
+        }
+    }
+
+    fn description(&self) -> ToolDescription {
+        self.tool.description()
+    }
+
+    fn definition(&self) -> ToolDefinition {
+        self.tool.definition()
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.tool.matches(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::mock::{AddTool, MockExecutor, MockOutput};
+    use crate::agents::tool_calling::ToolCallingAgent;
+    use crate::output::ToolCall;
+    use crate::prompt::ChatRole;
+    use crate::tools::ToolCollection;
+
+    #[test]
+    fn records_decisions_in_the_trace() {
+        let executor = MockExecutor::new(vec![
+            MockOutput(
+                None,
+                vec![
+                    ToolCall::new("add", r#"{"a": 2, "b": 3}"#).with_id("1"),
+                    ToolCall::new("add", r#"{"a": 40, "b": 0}"#).with_id("2"),
+                    ToolCall::new("add", r#"{"a": 1, "b": 1}"#).with_id("3"),
+                ],
+            ),
+            MockOutput::text("done"),
+        ]);
+        let tool = ApprovalTool::new(AddTool, |request: ApprovalRequest| async move {
+            match request.arguments["a"].as_i64() {
+                Some(2) => ApprovalDecision::Approve,
+                Some(40) => {
+                    ApprovalDecision::Modify(serde_yaml::from_str("{a: 40, b: 2}").unwrap())
+                }
+                _ => ApprovalDecision::Deny("Not now".to_string()),
+            }
+        });
+        let log = tool.log();
+        let mut tools = ToolCollection::new();
+        tools.add_tool(tool);
+        let agent = ToolCallingAgent::new(executor, tools);
+        let (_, messages) = futures::executor::block_on(agent.run("Add")).unwrap();
+        let results: Vec<_> = messages
+            .iter()
+            .filter(|message| message.role() == &ChatRole::Tool)
+            .map(|message| message.body().as_str().to_string())
+            .collect();
+        assert_eq!(results[0], "5");
+        assert!(results[1].starts_with("approval: The user changed the arguments"));
+        assert!(results[1].ends_with("output: 42"));
+        assert_eq!(
+            results[2],
+            "Error: The user denied the call to `add`: Not now"
+        );
+
+        let records = log.records();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].request.tool, "add");
+        assert_eq!(records[0].decision, ApprovalDecision::Approve);
+        assert_eq!(
+            records[2].decision,
+            ApprovalDecision::Deny("Not now".to_string())
+        );
+        let record = serde_yaml::to_string(&records[2]).unwrap();
+        assert!(record.contains("decision: deny"));
+    }
+}
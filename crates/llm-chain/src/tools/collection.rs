@@ -1,4 +1,6 @@
+use super::description::ToolDefinition;
 use super::tool::{Tool, ToolError};
+use crate::output::ToolCall;
 use crate::parsing::{find_yaml, ExtractionError};
 use crate::prompt::StringTemplate;
 use serde::{Deserialize, Serialize};
@@ -23,6 +25,8 @@ pub enum ToolUseError<E: ToolError> {
     InvalidFormat(#[from] serde_yaml::Error),
     #[error("Tool invocation failed: {0}")]
     ToolInvocationFailed(String),
+    #[error("The arguments of the call aren't valid JSON: {0}")]
+    InvalidArguments(#[from] serde_json::Error),
     #[error(transparent)]
     ToolError(#[from] E),
 }
@@ -39,6 +43,16 @@ where
         self.tools.push(tool);
     }
 
+    /// Returns the tool matching `name`, if any.
+    pub fn get(&self, name: &str) -> Option<&T> {
+        self.tools.iter().find(|t| t.matches(name))
+    }
+
+    /// Returns the names of the tools, in the order they were added.
+    pub fn names(&self) -> Vec<String> {
+        self.tools.iter().map(|t| t.description().name).collect()
+    }
+
     pub async fn invoke(
         &self,
         name: &str,
@@ -52,6 +66,24 @@ where
         tool.invoke(input.clone()).await.map_err(|e| e.into())
     }
 
+    /// Invokes the tool of a call returned by a model supporting tool calling, with its JSON arguments.
+    pub async fn invoke_call(
+        &self,
+        call: &ToolCall,
+    ) -> Result<serde_yaml::Value, ToolUseError<<T as Tool>::Error>> {
+        let input: serde_yaml::Value = if call.arguments.trim().is_empty() {
+            serde_yaml::Value::Null
+        } else {
+            serde_json::from_str(&call.arguments)?
+        };
+        self.invoke(&call.name, &input).await
+    }
+
+    /// Returns the definitions of the tools, to advertise them to models supporting tool calling.
+    pub fn definitions(&self) -> Vec<ToolDefinition> {
+        self.tools.iter().map(|t| t.definition()).collect()
+    }
+
     pub fn get_tool_invocation(
         &self,
         data: &str,
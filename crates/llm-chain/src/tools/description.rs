@@ -1,4 +1,4 @@
-use serde::{ser::SerializeMap, Serialize, Serializer};
+use serde::{ser::SerializeMap, Deserialize, Serialize, Serializer};
 
 /// Represents a single parameter for a tool.
 #[derive(Clone, Debug)]
@@ -34,6 +34,25 @@ impl Format {
     pub fn new(parts: Vec<FormatPart>) -> Self {
         Format { parts }
     }
+
+    /// Describes the properties of an object JSON Schema, with their descriptions, or their types when they have
+    /// none.
+    pub fn from_json_schema(schema: &serde_json::Value) -> Self {
+        let Some(properties) = schema["properties"].as_object() else {
+            return Format::new(Vec::new());
+        };
+        let parts = properties
+            .iter()
+            .map(|(key, property)| {
+                let purpose = property["description"]
+                    .as_str()
+                    .or_else(|| property["type"].as_str())
+                    .unwrap_or_default();
+                FormatPart::new(key, purpose)
+            })
+            .collect();
+        Format::new(parts)
+    }
 }
 
 impl<T: AsRef<[FormatPart]>> From<T> for Format {
@@ -92,3 +111,49 @@ impl ToolDescription {
         }
     }
 }
+
+/// The definition of a tool advertised to a model supporting tool calling, with a JSON Schema of its input.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    /// The JSON Schema of the arguments of the tool.
+    pub parameters: serde_json::Value,
+}
+
+impl From<&ToolDescription> for ToolDefinition {
+    /// Describes the input of the tool as an object with the keys of its input format, all required. The types of
+    /// the values aren't constrained, since input formats only describe their purpose.
+    fn from(description: &ToolDescription) -> Self {
+        let properties: serde_json::Map<String, serde_json::Value> = description
+            .input_format
+            .parts
+            .iter()
+            .map(|part| {
+                (
+                    part.key.clone(),
+                    serde_json::json!({ "description": part.purpose }),
+                )
+            })
+            .collect();
+        let required: Vec<&str> = description
+            .input_format
+            .parts
+            .iter()
+            .map(|part| part.key.as_str())
+            .collect();
+        let mut text = description.description.clone();
+        if !description.description_context.is_empty() {
+            text = format!("{}\n\n{}", text, description.description_context);
+        }
+        ToolDefinition {
+            name: description.name.clone(),
+            description: text,
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": properties,
+                "required": required,
+            }),
+        }
+    }
+}
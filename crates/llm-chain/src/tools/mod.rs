@@ -5,7 +5,9 @@
 //! The main components of this module are:
 //!
 //! - `Tool`: A struct that represents an individual tool that the LLM can use.
+//! - `TypedTool`: A tool with typed input and output, whose JSON Schemas are derived with the `schemars` feature.
 //! - `ToolCollection`: A collection of `Tool` instances.
+//! - `ApprovalTool`: A wrapper asking a user-supplied callback to approve, deny or change the calls of a tool.
 //! - `create_tool_prompt_segment`: A function to create a prompt that indicates the model should use the provided tools.
 //!
 //! ## Example
@@ -28,15 +30,24 @@
 //!
 //! - `tools`: A submodule that provides a variety of pre-defined tools.
 
+mod approval;
 mod collection;
 mod description;
 #[cfg(feature = "multitool_default")]
 pub mod multitool_default;
-pub use description::{Describe, Format, FormatPart, ToolDescription};
+pub use description::{Describe, Format, FormatPart, ToolDefinition, ToolDescription};
 pub mod multitool;
 mod tool;
 #[allow(clippy::module_inception)]
 pub mod tools;
+#[cfg(feature = "schemars")]
+mod typed;
 
+pub use approval::{
+    ApprovalDecision, ApprovalLog, ApprovalRecord, ApprovalRequest, ApprovalTool,
+    ApprovalToolError, Approver,
+};
 pub use collection::{ToolCollection, ToolInvocationInput, ToolUseError};
 pub use tool::{Tool, ToolError};
+#[cfg(feature = "schemars")]
+pub use typed::{TypedTool, TypedToolError};
@@ -101,6 +101,13 @@ macro_rules! multitool {
                 }
             }
 
+            /// Returns the definition advertising the tool to models supporting tool calling.
+            fn definition(&self) -> $crate::tools::ToolDefinition {
+                match self {
+                    $($multitool::$tool(t) => t.definition()),+
+                }
+            }
+
             /// Invokes the tool with the given YAML-formatted input.
             ///
             /// # Errors
@@ -1,4 +1,4 @@
-use super::description::ToolDescription;
+use super::description::{ToolDefinition, ToolDescription};
 
 use async_trait::async_trait;
 use serde::{de::DeserializeOwned, Serialize};
@@ -21,6 +21,12 @@ pub trait Tool {
     /// Returns the `ToolDescription` containing metadata about the tool.
     fn description(&self) -> ToolDescription;
 
+    /// Returns the definition advertising the tool to models supporting tool calling. Defaults to the one derived
+    /// from the description, whose input schema doesn't constrain the types of the values.
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition::from(&self.description())
+    }
+
     /// Invokes the tool with the given YAML-formatted input.
     ///
     /// # Errors
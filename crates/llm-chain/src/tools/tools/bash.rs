@@ -8,6 +8,9 @@ use std::string::FromUtf8Error;
 use thiserror::Error;
 
 /// A tool that executes a bash command.
+///
+/// The command can do anything the user running the program can do. Outside of a sandbox, use the
+/// [`ShellTool`](super::ShellTool), which only runs allowed commands, with a timeout.
 pub struct BashTool {}
 
 impl BashTool {
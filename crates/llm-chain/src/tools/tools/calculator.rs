@@ -0,0 +1,575 @@
+//! The calculator tool evaluates arithmetic expressions, so that agents compute numbers instead of guessing them.
+//!
+//! Numbers are arbitrary precision decimals: `0.1 + 0.2` is `0.3`, and `2^100` is exact. Expressions support `+`,
+//! `-`, `*`, `/`, `%` (remainder), `^` or `**` (power), `!` (factorial), parentheses, the constants `pi` and `e`, and
+//! the functions `sqrt`, `abs`, `round(x)` or `round(x, digits)`, `floor`, `ceil`, `min`, `max`, `exp`, `ln`,
+//! `log(x)` (base 10) or `log(x, base)`, `log2`, and the trigonometric functions in radians. The functions other
+//! than `sqrt`, `abs`, `round`, `floor`, `ceil`, `min` and `max`, and powers with fractional exponents, are computed
+//! with double precision floats.
+//!
+//! # Example
+//!
+//! ```
+//! use llm_chain::tools::{tools::CalculatorTool, ToolCollection};
+//!
+//! let mut tools = ToolCollection::new();
+//! tools.add_tool(CalculatorTool::new());
+//!
+//! let calculator = CalculatorTool::new().with_precision(10);
+//! assert_eq!(calculator.evaluate("(1 + 2) * 3^2 / 4").unwrap(), "6.75");
+//! assert_eq!(calculator.evaluate("2 / 3").unwrap(), "0.6666666667");
+//! ```
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use bigdecimal::{BigDecimal, FromPrimitive, One, RoundingMode, ToPrimitive, Zero};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::tools::{Describe, Format, Tool, ToolDescription, ToolError};
+
+/// The default number of significant digits of the results that aren't integers.
+const DEFAULT_PRECISION: u64 = 32;
+
+/// The largest number of digits of the integer part of a value, which keeps `9^9^9` from exhausting the memory.
+const MAX_DIGITS: i64 = 10_000;
+
+/// The number of digits kept after the integer part of intermediate values.
+const FRACTION_DIGITS: i64 = 100;
+
+const PI: &str = "3.14159265358979323846264338327950288419716939937510582097494459";
+const E: &str = "2.71828182845904523536028747135266249775724709369995957496696763";
+
+pub struct CalculatorTool {
+    precision: u64,
+}
+
+impl CalculatorTool {
+    /// Creates a calculator tool.
+    pub fn new() -> Self {
+        Self {
+            precision: DEFAULT_PRECISION,
+        }
+    }
+
+    /// Sets the number of significant digits of the results that aren't integers. Integer results are always given
+    /// in full. Defaults to 32.
+    pub fn with_precision(mut self, precision: u64) -> Self {
+        self.precision = precision.max(1);
+        self
+    }
+
+    /// Evaluates `expression`, returning its value as a decimal number.
+    pub fn evaluate(&self, expression: &str) -> Result<String, CalculatorToolError> {
+        let mut parser = Parser {
+            tokens: tokenize(expression)?,
+            position: 0,
+        };
+        let value = parser.expression()?;
+        if let Some(token) = parser.tokens.get(parser.position) {
+            return Err(CalculatorToolError::Syntax(format!(
+                "unexpected {} after the expression",
+                token
+            )));
+        }
+        let precision = integer_digits(&value).max(self.precision as i64) as u64;
+        Ok(value.with_prec(precision).normalized().to_string())
+    }
+}
+
+impl Default for CalculatorTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(BigDecimal),
+    Name(String),
+    Symbol(char),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(number) => write!(f, "number {}", number),
+            Token::Name(name) => write!(f, "name {}", name),
+            Token::Symbol(symbol) => write!(f, "'{}'", symbol),
+        }
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, CalculatorToolError> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            // An exponent, unless the `e` is the constant, as in `2e`.
+            if i < chars.len() && matches!(chars[i], 'e' | 'E') {
+                let mut j = i + 1;
+                if j < chars.len() && matches!(chars[j], '+' | '-') {
+                    j += 1;
+                }
+                if j < chars.len() && chars[j].is_ascii_digit() {
+                    i = j;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            let text: String = chars[start..i].iter().collect();
+            let number = BigDecimal::from_str(&text)
+                .map_err(|_| CalculatorToolError::Syntax(format!("invalid number {}", text)))?;
+            tokens.push(Token::Number(limit(number)?));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let name: String = chars[start..i].iter().collect();
+            tokens.push(Token::Name(name.to_lowercase()));
+        } else if c == '*' && chars.get(i + 1) == Some(&'*') {
+            tokens.push(Token::Symbol('^'));
+            i += 2;
+        } else {
+            let symbol = match c {
+                '+' | '-' | '*' | '/' | '%' | '^' | '!' | '(' | ')' | ',' => c,
+                '×' => '*',
+                '÷' => '/',
+                '−' => '-',
+                _ => {
+                    return Err(CalculatorToolError::Syntax(format!(
+                        "unexpected character '{}'",
+                        c
+                    )))
+                }
+            };
+            tokens.push(Token::Symbol(symbol));
+            i += 1;
+        }
+    }
+    Ok(tokens)
+}
+
+/// Returns the number of digits of the integer part of `value`, which is zero or negative for values below one.
+fn integer_digits(value: &BigDecimal) -> i64 {
+    let (_, scale) = value.as_bigint_and_exponent();
+    value.digits() as i64 - scale
+}
+
+/// Rejects the values too large to compute with, and rounds the digits of the others beyond what a result needs.
+fn limit(value: BigDecimal) -> Result<BigDecimal, CalculatorToolError> {
+    let digits = integer_digits(&value);
+    if digits > MAX_DIGITS {
+        return Err(CalculatorToolError::TooLarge);
+    }
+    if digits < -MAX_DIGITS {
+        return Ok(BigDecimal::zero());
+    }
+    let precision = digits.max(0) + FRACTION_DIGITS;
+    if value.digits() as i64 > precision {
+        Ok(value.with_prec(precision as u64))
+    } else {
+        Ok(value)
+    }
+}
+
+/// Computes `f(x)` with floats, for the functions decimals don't have.
+fn float(
+    name: &str,
+    x: &BigDecimal,
+    f: impl Fn(f64) -> f64,
+) -> Result<BigDecimal, CalculatorToolError> {
+    let result = f(x.to_f64().unwrap_or(f64::NAN));
+    if !result.is_finite() {
+        return Err(CalculatorToolError::Domain(format!("{}({})", name, x)));
+    }
+    BigDecimal::from_f64(result)
+        .map(|result| result.with_prec(15))
+        .ok_or_else(|| CalculatorToolError::Domain(format!("{}({})", name, x)))
+}
+
+fn power(base: &BigDecimal, exponent: &BigDecimal) -> Result<BigDecimal, CalculatorToolError> {
+    if !exponent.is_integer() {
+        let exponent_float = exponent.to_f64().unwrap_or(f64::NAN);
+        return float("pow", base, |base| base.powf(exponent_float));
+    }
+    let exponent = exponent.to_i64().ok_or(CalculatorToolError::TooLarge)?;
+    let mut square = if exponent >= 0 {
+        base.clone()
+    } else if base.is_zero() {
+        return Err(CalculatorToolError::DivisionByZero);
+    } else {
+        BigDecimal::one() / base
+    };
+    let mut result = BigDecimal::one();
+    let mut remaining = exponent.unsigned_abs();
+    while remaining > 0 {
+        if remaining & 1 == 1 {
+            result = limit(&result * &square)?;
+        }
+        remaining >>= 1;
+        if remaining > 0 {
+            square = limit(&square * &square)?;
+        }
+    }
+    Ok(result)
+}
+
+fn factorial(value: &BigDecimal) -> Result<BigDecimal, CalculatorToolError> {
+    let n = value
+        .is_integer()
+        .then(|| value.to_u64())
+        .flatten()
+        .ok_or_else(|| CalculatorToolError::Domain(format!("{}!", value)))?;
+    let mut result = BigDecimal::one();
+    for i in 2..=n {
+        result = limit(result * BigDecimal::from(i))?;
+    }
+    Ok(result)
+}
+
+fn call(name: &str, args: &[BigDecimal]) -> Result<BigDecimal, CalculatorToolError> {
+    let arity = |expected: &'static str, ok: bool| {
+        if ok {
+            Ok(())
+        } else {
+            Err(CalculatorToolError::Arity {
+                function: name.to_string(),
+                expected,
+            })
+        }
+    };
+    match name {
+        "min" | "max" => {
+            arity("at least one argument", !args.is_empty())?;
+            let mut values = args.iter();
+            let first = values.next().expect("there is an argument").clone();
+            Ok(values.fold(first, |result, value| match name {
+                "min" if *value < result => value.clone(),
+                "max" if *value > result => value.clone(),
+                _ => result,
+            }))
+        }
+        "round" => {
+            arity("one or two arguments", matches!(args.len(), 1 | 2))?;
+            // Values keep at most `FRACTION_DIGITS` digits after the point, and have at most `MAX_DIGITS` before it.
+            let digits = match args.get(1) {
+                Some(digits) => digits
+                    .is_integer()
+                    .then(|| digits.to_i64())
+                    .flatten()
+                    .filter(|digits| (-MAX_DIGITS..=FRACTION_DIGITS).contains(digits))
+                    .ok_or_else(|| {
+                        CalculatorToolError::Domain(format!("round with {} digits", digits))
+                    })?,
+                None => 0,
+            };
+            Ok(args[0].with_scale_round(digits, RoundingMode::HalfUp))
+        }
+        "log" if args.len() == 2 => {
+            let (x, base) = (args[0].to_f64(), args[1].to_f64());
+            let (x, base) = (x.unwrap_or(f64::NAN), base.unwrap_or(f64::NAN));
+            float("log", &args[0], |_| x.log(base))
+        }
+        _ => {
+            arity("one argument", args.len() == 1)?;
+            let x = &args[0];
+            match name {
+                "sqrt" => x
+                    .sqrt()
+                    .ok_or_else(|| CalculatorToolError::Domain(format!("sqrt({})", x))),
+                "abs" => Ok(x.abs()),
+                "floor" => Ok(x.with_scale_round(0, RoundingMode::Floor)),
+                "ceil" => Ok(x.with_scale_round(0, RoundingMode::Ceiling)),
+                "exp" => float(name, x, f64::exp),
+                "ln" => float(name, x, f64::ln),
+                "log" => float(name, x, f64::log10),
+                "log2" => float(name, x, f64::log2),
+                "sin" => float(name, x, f64::sin),
+                "cos" => float(name, x, f64::cos),
+                "tan" => float(name, x, f64::tan),
+                "asin" => float(name, x, f64::asin),
+                "acos" => float(name, x, f64::acos),
+                "atan" => float(name, x, f64::atan),
+                _ => Err(CalculatorToolError::UnknownName(name.to_string())),
+            }
+        }
+    }
+}
+
+/// A recursive descent parser evaluating the expression as it goes. Powers bind tighter than signs, so `-2^2` is
+/// `-4`, and are right-associative, so `2^3^2` is `2^9`.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn eat(&mut self, symbol: char) -> bool {
+        let found = self.tokens.get(self.position) == Some(&Token::Symbol(symbol));
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), CalculatorToolError> {
+        if self.eat(symbol) {
+            return Ok(());
+        }
+        Err(CalculatorToolError::Syntax(
+            match self.tokens.get(self.position) {
+                Some(token) => format!("expected '{}', found {}", symbol, token),
+                None => format!("expected '{}' at the end of the expression", symbol),
+            },
+        ))
+    }
+
+    fn expression(&mut self) -> Result<BigDecimal, CalculatorToolError> {
+        let mut value = self.term()?;
+        loop {
+            if self.eat('+') {
+                value = limit(value + self.term()?)?;
+            } else if self.eat('-') {
+                value = limit(value - self.term()?)?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<BigDecimal, CalculatorToolError> {
+        let mut value = self.unary()?;
+        loop {
+            let operator = ['*', '/', '%'].into_iter().find(|&o| self.eat(o));
+            let Some(operator) = operator else {
+                return Ok(value);
+            };
+            let operand = self.unary()?;
+            if operator != '*' && operand.is_zero() {
+                return Err(CalculatorToolError::DivisionByZero);
+            }
+            value = limit(match operator {
+                '*' => value * operand,
+                '/' => value / operand,
+                _ => value % operand,
+            })?;
+        }
+    }
+
+    fn unary(&mut self) -> Result<BigDecimal, CalculatorToolError> {
+        if self.eat('-') {
+            Ok(-self.unary()?)
+        } else if self.eat('+') {
+            self.unary()
+        } else {
+            self.power()
+        }
+    }
+
+    fn power(&mut self) -> Result<BigDecimal, CalculatorToolError> {
+        let base = self.postfix()?;
+        if self.eat('^') {
+            let exponent = self.unary()?;
+            power(&base, &exponent)
+        } else {
+            Ok(base)
+        }
+    }
+
+    fn postfix(&mut self) -> Result<BigDecimal, CalculatorToolError> {
+        let mut value = self.primary()?;
+        while self.eat('!') {
+            value = factorial(&value)?;
+        }
+        Ok(value)
+    }
+
+    fn primary(&mut self) -> Result<BigDecimal, CalculatorToolError> {
+        let token = self.tokens.get(self.position).cloned().ok_or_else(|| {
+            CalculatorToolError::Syntax("unexpected end of the expression".to_string())
+        })?;
+        self.position += 1;
+        match token {
+            Token::Number(number) => Ok(number),
+            Token::Symbol('(') => {
+                let value = self.expression()?;
+                self.expect(')')?;
+                Ok(value)
+            }
+            Token::Name(name) if self.eat('(') => {
+                let mut args = Vec::new();
+                if !self.eat(')') {
+                    loop {
+                        args.push(self.expression()?);
+                        if !self.eat(',') {
+                            break;
+                        }
+                    }
+                    self.expect(')')?;
+                }
+                call(&name, &args)
+            }
+            Token::Name(name) => match name.as_str() {
+                "pi" => Ok(BigDecimal::from_str(PI).expect("pi is a number")),
+                "e" => Ok(BigDecimal::from_str(E).expect("e is a number")),
+                _ => Err(CalculatorToolError::UnknownName(name)),
+            },
+            token => Err(CalculatorToolError::Syntax(format!("unexpected {}", token))),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CalculatorToolInput {
+    pub expression: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CalculatorToolOutput {
+    pub result: String,
+}
+
+impl Describe for CalculatorToolInput {
+    fn describe() -> Format {
+        vec![(
+            "expression",
+            "The arithmetic expression to evaluate, such as (1.5 + 2) * 3^2 or sqrt(2) / 7",
+        )
+            .into()]
+        .into()
+    }
+}
+
+impl Describe for CalculatorToolOutput {
+    fn describe() -> Format {
+        vec![("result", "The value of the expression").into()].into()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum CalculatorToolError {
+    #[error(transparent)]
+    YamlError(#[from] serde_yaml::Error),
+    #[error("Invalid expression: {0}")]
+    Syntax(String),
+    #[error("Unknown constant or function {0}")]
+    UnknownName(String),
+    #[error("The function {function} takes {expected}")]
+    Arity {
+        function: String,
+        expected: &'static str,
+    },
+    #[error("Division by zero")]
+    DivisionByZero,
+    #[error("{0} is undefined")]
+    Domain(String),
+    #[error("The result is too large")]
+    TooLarge,
+}
+
+impl ToolError for CalculatorToolError {}
+
+#[async_trait]
+impl Tool for CalculatorTool {
+    type Input = CalculatorToolInput;
+    type Output = CalculatorToolOutput;
+    type Error = CalculatorToolError;
+
+    async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+        let result = self.evaluate(&input.expression)?;
+        Ok(CalculatorToolOutput { result })
+    }
+
+    fn description(&self) -> ToolDescription {
+        ToolDescription::new(
+            "Calculator",
+            "Evaluates arithmetic expressions exactly.",
+            "Use this for any arithmetic instead of computing in your head. Supports + - * / % ^ !, parentheses, \
+             pi, e, and the functions sqrt, abs, round, floor, ceil, min, max, exp, ln, log, log2, sin, cos, tan, \
+             asin, acos and atan.",
+            CalculatorToolInput::describe(),
+            CalculatorToolOutput::describe(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluates_expressions() {
+        let calculator = CalculatorTool::new();
+        let cases = [
+            ("0.1 + 0.2", "0.3"),
+            ("1 + 2 * 3", "7"),
+            ("(1 + 2) * 3", "9"),
+            ("-2^2", "-4"),
+            ("2^3^2", "512"),
+            ("2 ** -2", "0.25"),
+            ("2^100", "1267650600228229401496703205376"),
+            ("20!", "2432902008176640000"),
+            ("10 % 4", "2"),
+            ("1 / 3", "0.33333333333333333333333333333333"),
+            ("sqrt(16) + abs(-1)", "5"),
+            ("round(2.345, 2) + floor(-1.5) + ceil(1.2)", "2.35"),
+            ("max(1, 7, 3) - min(4, 2)", "5"),
+            ("log(1000) + log(8, 2)", "6"),
+            ("round(pi, 4)", "3.1416"),
+        ];
+        for (expression, expected) in cases {
+            assert_eq!(
+                calculator.evaluate(expression).unwrap(),
+                expected,
+                "{}",
+                expression
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        let calculator = CalculatorTool::new();
+        let error = |expression| calculator.evaluate(expression).unwrap_err();
+        assert!(matches!(
+            error("1 / (2 - 2)"),
+            CalculatorToolError::DivisionByZero
+        ));
+        assert!(matches!(error("1 +"), CalculatorToolError::Syntax(_)));
+        assert!(matches!(error("(1 + 2"), CalculatorToolError::Syntax(_)));
+        assert!(matches!(error("1 2"), CalculatorToolError::Syntax(_)));
+        assert!(matches!(
+            error("foo(1)"),
+            CalculatorToolError::UnknownName(_)
+        ));
+        assert!(matches!(error("sqrt(-1)"), CalculatorToolError::Domain(_)));
+        assert!(matches!(error("ln(0)"), CalculatorToolError::Domain(_)));
+        assert!(matches!(error("9^9^9"), CalculatorToolError::TooLarge));
+        assert!(matches!(error("100000!"), CalculatorToolError::TooLarge));
+        assert!(matches!(
+            error("round(1, 1000000000)"),
+            CalculatorToolError::Domain(_)
+        ));
+        assert!(matches!(
+            error("round(1, -100000)"),
+            CalculatorToolError::Domain(_)
+        ));
+        assert!(matches!(
+            error("round(1, 99999999999999999999999)"),
+            CalculatorToolError::Domain(_)
+        ));
+        assert_eq!(calculator.evaluate("round(12345, -2)").unwrap(), "12300");
+        assert_eq!(calculator.evaluate("round(1, 100)").unwrap(), "1");
+    }
+}
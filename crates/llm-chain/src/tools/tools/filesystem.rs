@@ -0,0 +1,335 @@
+//! The filesystem tool lets agents list, read and write the files of a directory, such as a project checkout for a
+//! coding agent, without reaching anything outside of it.
+//!
+//! Paths given by the model are relative to the root directory of the tool. Paths that are absolute, or that leave
+//! the root with `..` or through a symbolic link, are rejected. Reads are cut to a number of bytes, writes larger than
+//! a limit are rejected, and the tool can be made read-only.
+//!
+//! # Example
+//!
+//! ```
+//! use llm_chain::tools::tools::FileSystemTool;
+//! let tool = FileSystemTool::new(".")
+//!     .with_max_read_bytes(32 * 1024)
+//!     .with_read_only(true);
+//! ```
+use std::path::{Component, Path, PathBuf};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::tools::{Describe, Format, Tool, ToolDescription, ToolError};
+
+/// The default limit on the bytes of a file read by the tool. Roughly 25 thousand tokens.
+const DEFAULT_MAX_READ_BYTES: usize = 100 * 1024;
+
+/// The default limit on the bytes of a file written by the tool.
+const DEFAULT_MAX_WRITE_BYTES: usize = 1024 * 1024;
+
+/// The default limit on the entries of a directory listing.
+const DEFAULT_MAX_ENTRIES: usize = 200;
+
+pub struct FileSystemTool {
+    root: PathBuf,
+    read_only: bool,
+    max_read_bytes: usize,
+    max_write_bytes: usize,
+    max_entries: usize,
+}
+
+impl FileSystemTool {
+    /// Creates a tool giving access to the files under `root`.
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self {
+            root: root.into(),
+            read_only: false,
+            max_read_bytes: DEFAULT_MAX_READ_BYTES,
+            max_write_bytes: DEFAULT_MAX_WRITE_BYTES,
+            max_entries: DEFAULT_MAX_ENTRIES,
+        }
+    }
+
+    /// Sets whether writing files is forbidden. Defaults to false.
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Sets the maximum number of bytes of a file returned by a read. Longer files are cut, and the output is marked
+    /// as truncated. Defaults to 100 KiB.
+    pub fn with_max_read_bytes(mut self, max_read_bytes: usize) -> Self {
+        self.max_read_bytes = max_read_bytes;
+        self
+    }
+
+    /// Sets the maximum number of bytes of a file written by the tool. Defaults to 1 MiB.
+    pub fn with_max_write_bytes(mut self, max_write_bytes: usize) -> Self {
+        self.max_write_bytes = max_write_bytes;
+        self
+    }
+
+    /// Sets the maximum number of entries of a directory listing. Defaults to 200.
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /// Resolves `path` under the root, rejecting the paths leaving it.
+    ///
+    /// The path is first normalized without touching the filesystem, then the part of it that exists is resolved
+    /// with its symbolic links, and must still be under the root.
+    pub fn resolve(&self, path: &str) -> Result<PathBuf, FileSystemToolError> {
+        let outside = || FileSystemToolError::OutsideRoot(path.to_string());
+        let mut relative = PathBuf::new();
+        for component in Path::new(path).components() {
+            match component {
+                Component::Normal(part) => relative.push(part),
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    if !relative.pop() {
+                        return Err(outside());
+                    }
+                }
+                Component::RootDir | Component::Prefix(_) => return Err(outside()),
+            }
+        }
+        let root = self.root.canonicalize()?;
+        let resolved = root.join(&relative);
+        let mut existing = resolved.as_path();
+        // Dangling symbolic links count as existing, so that writes can't create their targets.
+        while existing.symlink_metadata().is_err() {
+            existing = existing.parent().ok_or_else(outside)?;
+        }
+        if !existing.canonicalize()?.starts_with(&root) {
+            return Err(outside());
+        }
+        Ok(resolved)
+    }
+
+    fn list(&self, path: &str) -> Result<FileSystemToolOutput, FileSystemToolError> {
+        let directory = self.resolve(path)?;
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(&directory)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            entries.push(if metadata.is_dir() {
+                format!("{}/", name)
+            } else {
+                format!("{} ({} bytes)", name, metadata.len())
+            });
+        }
+        entries.sort();
+        let truncated = entries.len() > self.max_entries;
+        entries.truncate(self.max_entries);
+        Ok(FileSystemToolOutput {
+            content: entries.join("\n"),
+            truncated,
+        })
+    }
+
+    fn read(&self, path: &str) -> Result<FileSystemToolOutput, FileSystemToolError> {
+        let mut bytes = std::fs::read(self.resolve(path)?)?;
+        let truncated = bytes.len() > self.max_read_bytes;
+        bytes.truncate(self.max_read_bytes);
+        let content = match String::from_utf8(bytes) {
+            Ok(content) => content,
+            // A cut can fall in the middle of a character.
+            Err(error) if truncated && error.utf8_error().error_len().is_none() => {
+                let valid = error.utf8_error().valid_up_to();
+                let mut bytes = error.into_bytes();
+                bytes.truncate(valid);
+                String::from_utf8(bytes).expect("the bytes are valid up to this point")
+            }
+            Err(_) => return Err(FileSystemToolError::Binary(path.to_string())),
+        };
+        Ok(FileSystemToolOutput { content, truncated })
+    }
+
+    fn write(
+        &self,
+        path: &str,
+        content: &str,
+    ) -> Result<FileSystemToolOutput, FileSystemToolError> {
+        if self.read_only {
+            return Err(FileSystemToolError::ReadOnly);
+        }
+        if content.len() > self.max_write_bytes {
+            return Err(FileSystemToolError::TooLarge {
+                size: content.len(),
+                max: self.max_write_bytes,
+            });
+        }
+        let file = self.resolve(path)?;
+        if let Some(parent) = file.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&file, content)?;
+        Ok(FileSystemToolOutput {
+            content: format!("Wrote {} bytes to {}", content.len(), path),
+            truncated: false,
+        })
+    }
+}
+
+/// What the tool is asked to do. Paths are relative to the root of the tool.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum FileSystemToolInput {
+    /// Lists the files and directories of a directory.
+    List {
+        #[serde(default)]
+        path: String,
+    },
+    /// Reads a text file.
+    Read { path: String },
+    /// Writes a text file, creating it and its directories if needed.
+    Write { path: String, content: String },
+}
+
+impl Describe for FileSystemToolInput {
+    fn describe() -> Format {
+        vec![
+            ("action", "One of list, read or write").into(),
+            (
+                "path",
+                "The path of the file or directory, relative to the project root",
+            )
+                .into(),
+            ("content", "The text to write, for write").into(),
+        ]
+        .into()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileSystemToolOutput {
+    /// The listing, the text of the file, or a confirmation of the write.
+    pub content: String,
+    /// Whether part of the file or listing was left out to fit the limits.
+    pub truncated: bool,
+}
+
+impl Describe for FileSystemToolOutput {
+    fn describe() -> Format {
+        vec![
+            (
+                "content",
+                "The directory listing, the text of the file, or a confirmation of the write",
+            )
+                .into(),
+            ("truncated", "true if the content was cut short").into(),
+        ]
+        .into()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum FileSystemToolError {
+    #[error(transparent)]
+    Yaml(#[from] serde_yaml::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("The path {0} is outside of the project root")]
+    OutsideRoot(String),
+    #[error("Files can't be written: the tool is read-only")]
+    ReadOnly,
+    #[error("The content is {size} bytes, but at most {max} bytes can be written")]
+    TooLarge { size: usize, max: usize },
+    #[error("The file {0} isn't a text file")]
+    Binary(String),
+}
+
+impl ToolError for FileSystemToolError {}
+
+#[async_trait]
+impl Tool for FileSystemTool {
+    type Input = FileSystemToolInput;
+    type Output = FileSystemToolOutput;
+    type Error = FileSystemToolError;
+
+    async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+        match input {
+            FileSystemToolInput::List { path } => self.list(path),
+            FileSystemToolInput::Read { path } => self.read(path),
+            FileSystemToolInput::Write { path, content } => self.write(path, content),
+        }
+    }
+
+    fn description(&self) -> ToolDescription {
+        let actions = if self.read_only {
+            "Lists directories and reads text files of the project."
+        } else {
+            "Lists directories, reads text files and writes text files of the project."
+        };
+        ToolDescription::new(
+            "Files",
+            actions,
+            "Use paths relative to the project root, and list directories to find the files you need.",
+            FileSystemToolInput::describe(),
+            FileSystemToolOutput::describe(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stays_under_the_root() {
+        let root = std::env::temp_dir().join(format!("llm-chain-fs-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        let tool = FileSystemTool::new(&root).with_max_read_bytes(4);
+        let run = |input| futures::executor::block_on(tool.invoke_typed(&input));
+
+        let written = run(FileSystemToolInput::Write {
+            path: "src/../notes/a.txt".to_string(),
+            content: "hello".to_string(),
+        });
+        assert!(written.is_ok());
+        let read = run(FileSystemToolInput::Read {
+            path: "./notes/a.txt".to_string(),
+        })
+        .unwrap();
+        assert_eq!(read.content, "hell");
+        assert!(read.truncated);
+        let listing = run(FileSystemToolInput::List {
+            path: String::new(),
+        })
+        .unwrap();
+        assert_eq!(listing.content, "notes/");
+
+        for path in ["../outside.txt", "notes/../../outside.txt", "/etc/passwd"] {
+            let error = run(FileSystemToolInput::Read {
+                path: path.to_string(),
+            })
+            .unwrap_err();
+            assert!(
+                matches!(error, FileSystemToolError::OutsideRoot(_)),
+                "{}",
+                path
+            );
+        }
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(std::env::temp_dir(), root.join("escape")).unwrap();
+            let error = run(FileSystemToolInput::List {
+                path: "escape".to_string(),
+            })
+            .unwrap_err();
+            assert!(matches!(error, FileSystemToolError::OutsideRoot(_)));
+        }
+
+        let read_only = FileSystemTool::new(&root).with_read_only(true);
+        let error =
+            futures::executor::block_on(read_only.invoke_typed(&FileSystemToolInput::Write {
+                path: "b.txt".to_string(),
+                content: String::new(),
+            }))
+            .unwrap_err();
+        assert!(matches!(error, FileSystemToolError::ReadOnly));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,684 @@
+//! The HTTP tool sends GET and POST requests and turns the responses into observations the model can read.
+//!
+//! The representation depends on the content type of the response:
+//!
+//! - JSON is pretty-printed, or compacted when the pretty form doesn't fit the budget.
+//! - HTML is converted to markdown, leaving out scripts, styles and markup.
+//! - CSV is rendered as a markdown table, keeping the header and as many rows as fit.
+//! - Other text is passed through.
+//! - Binary content is summarized by its type and size instead of being included.
+//!
+//! Responses longer than the budget are truncated, and the observation records the content type, the representation
+//! chosen and whether anything was left out. Bodies are read up to a limit in bytes, so that large downloads are
+//! stopped early.
+//!
+//! Requests are only sent to the hosts allowed with `with_allowed_hosts`, which also applies to the redirects followed
+//! by the tool. No host is allowed by default; `allow_any_host` lifts the restriction. Headers set with `with_header` are templates rendered for every request, so that secrets such
+//! as API keys are added to the requests without being shown to the model.
+//!
+//! # Example
+//!
+//! ```
+//! use llm_chain::parameters;
+//! use llm_chain::tools::tools::HttpTool;
+//! let tool = HttpTool::new()
+//!     .with_allowed_hosts(["api.example.com", "*.wikipedia.org"])
+//!     .with_header("Authorization", "Bearer {{token}}")
+//!     .with_header_parameters(parameters!("token" => "secret"))
+//!     .with_max_response_bytes(64 * 1024);
+//! ```
+use async_trait::async_trait;
+use reqwest::header::{CONTENT_TYPE, LOCATION};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::csv::parse_csv;
+use crate::html::{attribute, decode_entities, find_ascii_case_insensitive};
+use crate::prompt::{StringTemplate, StringTemplateError};
+use crate::tools::{Describe, Format, Tool, ToolDescription, ToolError};
+use crate::Parameters;
+
+/// The default budget for the content of an observation, in characters. Roughly a thousand tokens.
+const DEFAULT_MAX_CHARS: usize = 4000;
+
+/// The default limit on the bytes of a response body read by the tool.
+const DEFAULT_MAX_RESPONSE_BYTES: usize = 1024 * 1024;
+
+/// The number of redirects followed before giving up.
+const MAX_REDIRECTS: usize = 10;
+
+pub struct HttpTool {
+    client: reqwest::Client,
+    max_chars: usize,
+    max_response_bytes: usize,
+    allowed_hosts: Vec<String>,
+    allow_any_host: bool,
+    headers: Vec<(String, StringTemplate)>,
+    header_parameters: Parameters,
+}
+
+impl Default for HttpTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HttpTool {
+    pub fn new() -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .redirect(reqwest::redirect::Policy::none())
+                .build()
+                .expect("the default client configuration is valid"),
+            max_chars: DEFAULT_MAX_CHARS,
+            max_response_bytes: DEFAULT_MAX_RESPONSE_BYTES,
+            allowed_hosts: Vec::new(),
+            allow_any_host: false,
+            headers: Vec::new(),
+            header_parameters: Parameters::new(),
+        }
+    }
+
+    /// Uses `client` to send requests, for example to set a user agent or timeouts.
+    ///
+    /// The client shouldn't follow redirects, with `reqwest::redirect::Policy::none()`: the tool follows them itself
+    /// to check their hosts against the allowed hosts.
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Sets the maximum number of characters of content in an observation.
+    pub fn with_max_chars(mut self, max_chars: usize) -> Self {
+        self.max_chars = max_chars;
+        self
+    }
+
+    /// Sets the maximum number of bytes of a response body read by the tool. The rest of the body is left out, and
+    /// the observation is marked as truncated. Defaults to 1 MiB.
+    pub fn with_max_response_bytes(mut self, max_response_bytes: usize) -> Self {
+        self.max_response_bytes = max_response_bytes;
+        self
+    }
+
+    /// Allows requests to `hosts`. A host starting with `*.`, such as `*.example.com`, allows the subdomains of the
+    /// domain. Hosts are compared ignoring case. When no hosts are set, every request is refused.
+    pub fn with_allowed_hosts<S: Into<String>, I: IntoIterator<Item = S>>(
+        mut self,
+        hosts: I,
+    ) -> Self {
+        self.allowed_hosts = hosts
+            .into_iter()
+            .map(|host| host.into().to_ascii_lowercase())
+            .collect();
+        self
+    }
+
+    /// Allows requests to any host, for tools that browse the web. Hosts set with `with_allowed_hosts` are ignored.
+    pub fn allow_any_host(mut self) -> Self {
+        self.allow_any_host = true;
+        self
+    }
+
+    /// Adds the header `name` to every request, with the value rendered from the tera template `value`.
+    ///
+    /// The template is rendered with the parameters set with `with_header_parameters`, along with the `url` and the
+    /// `host` of the request.
+    pub fn with_header<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.headers
+            .push((name.into(), StringTemplate::tera(value.into())));
+        self
+    }
+
+    /// Sets the parameters the header templates are rendered with.
+    pub fn with_header_parameters(mut self, parameters: Parameters) -> Self {
+        self.header_parameters = parameters;
+        self
+    }
+
+    /// Checks that `url` is an HTTP URL to an allowed host.
+    fn check_url(&self, url: &Url) -> Result<(), HttpToolError> {
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(HttpToolError::InvalidUrl(url.to_string()));
+        }
+        let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+        if self.allow_any_host || host_allowed(&self.allowed_hosts, &host) {
+            Ok(())
+        } else {
+            Err(HttpToolError::HostNotAllowed(host))
+        }
+    }
+
+    /// Sends a request to `url`, with the headers of the tool and `body` if any.
+    async fn send(
+        &self,
+        method: HttpMethod,
+        url: &Url,
+        body: Option<&str>,
+    ) -> Result<reqwest::Response, HttpToolError> {
+        self.check_url(url)?;
+        let method = match method {
+            HttpMethod::Get => reqwest::Method::GET,
+            HttpMethod::Post => reqwest::Method::POST,
+        };
+        let mut request = self.client.request(method, url.clone());
+        let parameters = self
+            .header_parameters
+            .with("url", url.as_str())
+            .with("host", url.host_str().unwrap_or_default());
+        for (name, value) in &self.headers {
+            request = request.header(name.as_str(), value.format(&parameters)?);
+        }
+        if let Some(body) = body {
+            let content_type = if serde_json::from_str::<serde_json::Value>(body).is_ok() {
+                "application/json"
+            } else {
+                "text/plain; charset=utf-8"
+            };
+            request = request
+                .header(CONTENT_TYPE, content_type)
+                .body(body.to_string());
+        }
+        Ok(request.send().await?)
+    }
+
+    /// Reads the body of `response` up to the byte limit. Returns the body and whether the rest was left out.
+    async fn read(
+        &self,
+        mut response: reqwest::Response,
+    ) -> Result<(Vec<u8>, bool), HttpToolError> {
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            let room = self.max_response_bytes - body.len();
+            if chunk.len() > room {
+                body.extend_from_slice(&chunk[..room]);
+                // Don't leave half a character at the end of a text.
+                if let Err(error) = std::str::from_utf8(&body) {
+                    if error.error_len().is_none() {
+                        body.truncate(error.valid_up_to());
+                    }
+                }
+                return Ok((body, true));
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok((body, false))
+    }
+}
+
+/// Returns whether `host` is one of `allowed_hosts`, or a subdomain of one of their `*.` patterns.
+fn host_allowed(allowed_hosts: &[String], host: &str) -> bool {
+    allowed_hosts
+        .iter()
+        .any(|allowed| match allowed.strip_prefix("*.") {
+            Some(domain) => host
+                .strip_suffix(domain)
+                .is_some_and(|subdomain| subdomain.ends_with('.')),
+            None => allowed == host,
+        })
+}
+
+/// The methods of the requests the tool can send.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum HttpMethod {
+    #[default]
+    Get,
+    Post,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct HttpToolInput {
+    pub url: String,
+    #[serde(default)]
+    pub method: HttpMethod,
+    /// The body of a POST request, sent as JSON when it is valid JSON, and as text otherwise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+}
+
+impl From<&str> for HttpToolInput {
+    fn from(value: &str) -> Self {
+        Self {
+            url: value.into(),
+            method: HttpMethod::Get,
+            body: None,
+        }
+    }
+}
+
+impl Describe for HttpToolInput {
+    fn describe() -> Format {
+        vec![
+            ("url", "The URL to request").into(),
+            ("method", "GET or POST, defaults to GET").into(),
+            ("body", "The body of a POST request, optional").into(),
+        ]
+        .into()
+    }
+}
+
+/// How the content of a response is represented in an observation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Representation {
+    Json,
+    Markdown,
+    Table,
+    Text,
+    Binary,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HttpToolOutput {
+    pub status: u16,
+    pub content_type: String,
+    pub representation: Representation,
+    /// Whether part of the content was left out to fit the budget.
+    pub truncated: bool,
+    pub content: String,
+}
+
+impl Describe for HttpToolOutput {
+    fn describe() -> Format {
+        vec![
+            ("status", "The HTTP status code of the response").into(),
+            ("content_type", "The content type of the response").into(),
+            (
+                "representation",
+                "How the content is shown: json, markdown, table, text or binary",
+            )
+                .into(),
+            ("truncated", "true if the content was cut short").into(),
+            ("content", "The content of the response").into(),
+        ]
+        .into()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum HttpToolError {
+    #[error(transparent)]
+    Yaml(#[from] serde_yaml::Error),
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+    #[error("Invalid HTTP URL: {0}")]
+    InvalidUrl(String),
+    #[error("Requests to {0} aren't allowed")]
+    HostNotAllowed(String),
+    #[error("More than {} redirects", MAX_REDIRECTS)]
+    TooManyRedirects,
+    #[error("The headers couldn't be rendered: {0}")]
+    Header(#[from] StringTemplateError),
+}
+
+impl ToolError for HttpToolError {}
+
+#[async_trait]
+impl Tool for HttpTool {
+    type Input = HttpToolInput;
+    type Output = HttpToolOutput;
+    type Error = HttpToolError;
+
+    async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+        let mut url =
+            Url::parse(&input.url).map_err(|_| HttpToolError::InvalidUrl(input.url.clone()))?;
+        let mut method = input.method;
+        let mut body = input.body.as_deref().filter(|_| method == HttpMethod::Post);
+        for _ in 0..=MAX_REDIRECTS {
+            let response = self.send(method, &url, body).await?;
+            let location = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|value| value.to_str().ok());
+            if let (true, Some(location)) = (response.status().is_redirection(), location) {
+                url = url
+                    .join(location)
+                    .map_err(|_| HttpToolError::InvalidUrl(location.to_string()))?;
+                // Only 307 and 308 redirects repeat the request as it was.
+                if !matches!(response.status().as_u16(), 307 | 308) {
+                    method = HttpMethod::Get;
+                    body = None;
+                }
+                continue;
+            }
+            let status = response.status().as_u16();
+            let content_type = response
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or("application/octet-stream")
+                .to_string();
+            let (body, cut) = self.read(response).await?;
+            let (representation, content, truncated) =
+                represent(&content_type, &body, self.max_chars);
+            return Ok(HttpToolOutput {
+                status,
+                content_type,
+                representation,
+                truncated: truncated || cut,
+                content,
+            });
+        }
+        Err(HttpToolError::TooManyRedirects)
+    }
+
+    fn description(&self) -> ToolDescription {
+        let hosts = if self.allow_any_host {
+            String::new()
+        } else if self.allowed_hosts.is_empty() {
+            " No hosts can be requested.".to_string()
+        } else {
+            format!(
+                " Only these hosts can be requested: {}.",
+                self.allowed_hosts.join(", ")
+            )
+        };
+        ToolDescription::new(
+            "HTTP",
+            "Useful for reading web pages and calling APIs. Input should be a URL, with a method and a body for POST requests.",
+            &format!("Use this to fetch the content of a URL, or to send data to an API.{}", hosts),
+            HttpToolInput::describe(),
+            HttpToolOutput::describe(),
+        )
+    }
+}
+
+/// Chooses a representation for a response body and renders it in at most `max_chars` characters.
+///
+/// Returns the representation, the rendered content and whether it was truncated.
+pub fn represent(
+    content_type: &str,
+    body: &[u8],
+    max_chars: usize,
+) -> (Representation, String, bool) {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    let text = match std::str::from_utf8(body) {
+        Ok(text) => text,
+        Err(_) => return binary(&mime, body.len()),
+    };
+    if mime == "application/json" || mime.ends_with("+json") {
+        if let Ok(value) = serde_json::from_str::<serde_json::Value>(text) {
+            let pretty = serde_json::to_string_pretty(&value).unwrap_or_default();
+            let content = if pretty.chars().count() <= max_chars {
+                pretty
+            } else {
+                value.to_string()
+            };
+            let (content, truncated) = truncate(content, max_chars);
+            return (Representation::Json, content, truncated);
+        }
+    }
+    if mime == "text/html" || mime == "application/xhtml+xml" {
+        let (content, truncated) = truncate(html_to_markdown(text), max_chars);
+        return (Representation::Markdown, content, truncated);
+    }
+    if mime == "text/csv" {
+        // CSV that doesn't parse, such as a body cut in a quoted field, is passed as text.
+        if let Some((content, truncated)) = csv_to_table(text, max_chars) {
+            return (Representation::Table, content, truncated);
+        }
+    }
+    // JSON that doesn't parse, such as a truncated body, is passed as text.
+    if mime.starts_with("text/")
+        || mime == "application/json"
+        || mime.ends_with("+json")
+        || mime.ends_with("+xml")
+        || mime == "application/xml"
+        || mime == "application/javascript"
+    {
+        let (content, truncated) = truncate(text.to_string(), max_chars);
+        return (Representation::Text, content, truncated);
+    }
+    binary(&mime, body.len())
+}
+
+fn binary(mime: &str, len: usize) -> (Representation, String, bool) {
+    (
+        Representation::Binary,
+        format!("[{} bytes of {} content not shown]", len, mime),
+        false,
+    )
+}
+
+/// Cuts `text` to at most `max_chars` characters.
+fn truncate(text: String, max_chars: usize) -> (String, bool) {
+    match text.char_indices().nth(max_chars) {
+        Some((idx, _)) => (text[..idx].to_string(), true),
+        None => (text, false),
+    }
+}
+
+/// Renders CSV as a markdown table with the header and as many rows as fit in `max_chars` characters, or returns
+/// `None` if the CSV is invalid.
+fn csv_to_table(text: &str, max_chars: usize) -> Option<(String, bool)> {
+    // Cells can't span lines, and pipes would end them.
+    let mut rows = parse_csv(text, ',').ok()?.into_iter().map(|row| {
+        row.values
+            .iter()
+            .map(|value| value.split_whitespace().collect::<Vec<_>>().join(" "))
+            .map(|value| value.replace('|', "\\|"))
+            .collect::<Vec<_>>()
+    });
+    let Some(header) = rows.next() else {
+        return Some((String::new(), false));
+    };
+    let mut table = format!(
+        "| {} |\n|{}|\n",
+        header.join(" | "),
+        vec![" --- "; header.len()].join("|")
+    );
+    if table.chars().count() > max_chars {
+        return Some(truncate(table, max_chars));
+    }
+    let mut length = table.chars().count();
+    for row in rows {
+        let line = format!("| {} |\n", row.join(" | "));
+        length += line.chars().count();
+        if length > max_chars {
+            return Some((table, true));
+        }
+        table.push_str(&line);
+    }
+    Some((table, false))
+}
+
+/// Converts HTML to markdown, keeping headings, paragraphs, lists, links, emphasis and code.
+///
+/// This is not a full HTML parser; it's meant to make the text of web pages readable for a model, not to preserve
+/// every detail of the page.
+pub fn html_to_markdown(html: &str) -> String {
+    let mut out = String::new();
+    let mut links: Vec<Option<String>> = Vec::new();
+    let mut in_pre = false;
+    let mut rest = html;
+    while !rest.is_empty() {
+        let Some(open) = rest.find('<') else {
+            push_text(&mut out, rest, in_pre);
+            break;
+        };
+        push_text(&mut out, &rest[..open], in_pre);
+        rest = &rest[open..];
+        if let Some(after) = rest.strip_prefix("<!--") {
+            rest = after.find("-->").map_or("", |end| &after[end + 3..]);
+            continue;
+        }
+        let Some(close) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[1..close];
+        rest = &rest[close + 1..];
+        let closing = tag.starts_with('/');
+        let name: String = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+        match name.as_str() {
+            "script" | "style" | "head" | "noscript" | "svg" if !closing => {
+                let end = format!("</{}", name);
+                rest = find_ascii_case_insensitive(rest, &end)
+                    .and_then(|idx| rest[idx..].find('>').map(|gt| &rest[idx + gt + 1..]))
+                    .unwrap_or("");
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                out.push_str("\n\n");
+                if !closing {
+                    let level = name[1..].parse().unwrap_or(1);
+                    out.push_str(&"#".repeat(level));
+                    out.push(' ');
+                }
+            }
+            "p" | "div" | "section" | "article" | "header" | "footer" | "main" | "table" | "tr"
+            | "ul" | "ol" | "blockquote" => out.push_str("\n\n"),
+            "br" => out.push('\n'),
+            "li" if !closing => out.push_str("\n- "),
+            "td" | "th" if !closing => out.push(' '),
+            "a" if !closing => {
+                let href = attribute(tag, "href").map(|href| decode_entities(href).into_owned());
+                if href.is_some() {
+                    out.push('[');
+                }
+                links.push(href);
+            }
+            "a" => {
+                if let Some(Some(href)) = links.pop() {
+                    out.push_str(&format!("]({})", href));
+                }
+            }
+            "strong" | "b" => out.push_str("**"),
+            "em" | "i" => out.push('*'),
+            "code" if !in_pre => out.push('`'),
+            "pre" => {
+                in_pre = !closing;
+                out.push_str("\n\n```\n");
+                if closing {
+                    out.push_str("\n\n");
+                }
+            }
+            _ => {}
+        }
+    }
+    tidy(&out)
+}
+
+fn push_text(out: &mut String, text: &str, in_pre: bool) {
+    let text = decode_entities(text);
+    if in_pre {
+        out.push_str(&text);
+        return;
+    }
+    for (i, word) in text.split_whitespace().enumerate() {
+        let starts_with_space = i == 0 && text.starts_with(char::is_whitespace);
+        if (i > 0 || starts_with_space) && !out.ends_with(char::is_whitespace) {
+            out.push(' ');
+        }
+        out.push_str(word);
+    }
+    if text.ends_with(char::is_whitespace) && !text.trim().is_empty() {
+        out.push(' ');
+    }
+}
+
+/// Removes trailing spaces and collapses runs of blank lines.
+fn tidy(markdown: &str) -> String {
+    let mut out = String::new();
+    let mut blank_lines = 0;
+    for line in markdown.lines().map(str::trim_end) {
+        if line.trim().is_empty() {
+            blank_lines += 1;
+            continue;
+        }
+        if !out.is_empty() {
+            out.push_str(if blank_lines > 0 { "\n\n" } else { "\n" });
+        }
+        blank_lines = 0;
+        out.push_str(line.trim_start_matches(' '));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        host_allowed, html_to_markdown, represent, HttpTool, HttpToolError, Representation,
+    };
+    use reqwest::Url;
+
+    #[test]
+    fn hosts_match_allowlist() {
+        let allowed = ["api.example.com".to_string(), "*.wikipedia.org".to_string()];
+        assert!(host_allowed(&allowed, "api.example.com"));
+        assert!(host_allowed(&allowed, "en.wikipedia.org"));
+        assert!(!host_allowed(&allowed, "wikipedia.org"));
+        assert!(!host_allowed(&allowed, "evilwikipedia.org"));
+        assert!(!host_allowed(&allowed, "example.com"));
+    }
+
+    #[test]
+    fn hosts_are_denied_by_default() {
+        let url = Url::parse("https://example.com/page").unwrap();
+        assert!(matches!(
+            HttpTool::new().check_url(&url),
+            Err(HttpToolError::HostNotAllowed(host)) if host == "example.com"
+        ));
+        let tool = HttpTool::new().with_allowed_hosts(Vec::<String>::new());
+        assert!(tool.check_url(&url).is_err());
+        assert!(HttpTool::new().allow_any_host().check_url(&url).is_ok());
+        let tool = HttpTool::new().with_allowed_hosts(["example.com"]);
+        assert!(tool.check_url(&url).is_ok());
+    }
+
+    #[test]
+    fn html_is_converted_to_markdown() {
+        let html = r#"<html><head><title>x</title><script>var a = 1;</script></head>
+            <body><h1>Title</h1><p>Some <b>bold</b> text &amp; a <a href="https://example.com">link</a>.</p>
+            <ul><li>one</li><li>two</li></ul></body></html>"#;
+        assert_eq!(
+            html_to_markdown(html),
+            "# Title\n\nSome **bold** text & a [link](https://example.com).\n\n- one\n- two"
+        );
+    }
+
+    #[test]
+    fn representation_follows_content_type_and_budget() {
+        let (representation, content, truncated) =
+            represent("application/json; charset=utf-8", br#"{"a": [1, 2]}"#, 100);
+        assert_eq!(representation, Representation::Json);
+        assert!(content.contains('\n'));
+        assert!(!truncated);
+
+        let (_, content, truncated) = represent("application/json", br#"{"a": [1, 2]}"#, 12);
+        assert_eq!(content, r#"{"a":[1,2]}"#);
+        assert!(!truncated);
+
+        let csv = b"name,city\n\"Doe, Jane\",Paris\nBob,Rome\n";
+        let (representation, content, truncated) = represent("text/csv", csv, 55);
+        assert_eq!(representation, Representation::Table);
+        assert_eq!(
+            content,
+            "| name | city |\n| --- | --- |\n| Doe, Jane | Paris |\n"
+        );
+        assert!(truncated);
+
+        let csv = b"name,bio\nAda,\"Wrote the first | program,\nin 1843\"\n";
+        let (_, content, _) = represent("text/csv", csv, 100);
+        assert_eq!(
+            content,
+            "| name | bio |\n| --- | --- |\n| Ada | Wrote the first \\| program, in 1843 |\n"
+        );
+        let (representation, _, _) = represent("text/csv", b"name,bio\nAda,\"Wrote", 100);
+        assert_eq!(representation, Representation::Text);
+
+        let (representation, content, _) = represent("image/png", &[0x89, 0x50, 0xff], 100);
+        assert_eq!(representation, Representation::Binary);
+        assert_eq!(content, "[3 bytes of image/png content not shown]");
+    }
+}
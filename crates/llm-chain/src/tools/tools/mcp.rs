@@ -0,0 +1,590 @@
+//! A client of the [Model Context Protocol](https://modelcontextprotocol.io) (MCP), mounting the tools of MCP
+//! servers into a [`ToolCollection`].
+//!
+//! The client connects to a server through a [`McpTransport`]: a [`StdioTransport`] running the server as a
+//! subprocess, or an [`SseTransport`] talking to it over HTTP with server-sent events. Once connected, the tools of
+//! the server are listed with [`McpClient::tools`], as [`McpTool`]s advertising the JSON Schemas of their inputs and
+//! calling the server when invoked.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use llm_chain::tools::tools::McpClient;
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = McpClient::stdio("npx", ["-y", "@modelcontextprotocol/server-filesystem", "."]).await?;
+//! let tools = client.tools().await?;
+//! println!("{:?}", tools.names());
+//! # Ok(())
+//! # }
+//! ```
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::lock::Mutex;
+use reqwest::header::ACCEPT;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+
+use crate::tools::{Format, Tool, ToolCollection, ToolDefinition, ToolDescription, ToolError};
+
+/// The version of the protocol the client speaks.
+const PROTOCOL_VERSION: &str = "2024-11-05";
+
+#[derive(Debug, Error)]
+pub enum McpError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+    #[error("The server closed the connection")]
+    Closed,
+    #[error("The server didn't send the endpoint to post messages to")]
+    NoEndpoint,
+    #[error("The server returned the error {code}: {message}")]
+    Server { code: i64, message: String },
+}
+
+/// A connection to an MCP server, carrying JSON-RPC messages.
+#[async_trait]
+pub trait McpTransport: Send + Sync {
+    /// Sends a message to the server.
+    async fn send(&mut self, message: &Value) -> Result<(), McpError>;
+
+    /// Waits for the next message of the server.
+    async fn receive(&mut self) -> Result<Value, McpError>;
+}
+
+/// Runs an MCP server as a subprocess, exchanging messages on its stdin and stdout, one per line. The server is
+/// killed when the transport is dropped.
+pub struct StdioTransport {
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl StdioTransport {
+    /// Spawns the server run by `command`, whose environment, working directory and stderr can be set before.
+    pub fn spawn(mut command: Command) -> Result<Self, McpError> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        let stdin = child.stdin.take().ok_or(McpError::Closed)?;
+        let stdout = child.stdout.take().ok_or(McpError::Closed)?;
+        Ok(Self {
+            _child: child,
+            stdin,
+            stdout: BufReader::new(stdout),
+        })
+    }
+}
+
+#[async_trait]
+impl McpTransport for StdioTransport {
+    async fn send(&mut self, message: &Value) -> Result<(), McpError> {
+        let mut line = serde_json::to_vec(message)?;
+        line.push(b'\n');
+        self.stdin.write_all(&line).await?;
+        self.stdin.flush().await?;
+        Ok(())
+    }
+
+    async fn receive(&mut self) -> Result<Value, McpError> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.stdout.read_line(&mut line).await? == 0 {
+                return Err(McpError::Closed);
+            }
+            if !line.trim().is_empty() {
+                return Ok(serde_json::from_str(&line)?);
+            }
+        }
+    }
+}
+
+/// An event of a server-sent events stream.
+#[derive(Debug, PartialEq, Eq)]
+struct Event {
+    name: String,
+    data: String,
+}
+
+/// Takes the first complete event out of `buffer`, if it has one.
+fn next_event(buffer: &mut String) -> Option<Event> {
+    loop {
+        let normalized = buffer.replace("\r\n", "\n");
+        let end = normalized.find("\n\n")?;
+        let block = normalized[..end].to_string();
+        *buffer = normalized[end + 2..].to_string();
+        let mut event = Event {
+            name: "message".to_string(),
+            data: String::new(),
+        };
+        let mut has_data = false;
+        for line in block.lines() {
+            let (field, value) = line.split_once(':').unwrap_or((line, ""));
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            match field {
+                "event" => event.name = value.to_string(),
+                "data" => {
+                    if has_data {
+                        event.data.push('\n');
+                    }
+                    event.data.push_str(value);
+                    has_data = true;
+                }
+                _ => {}
+            }
+        }
+        // Blocks of comments only, sent to keep the connection alive, aren't events.
+        if has_data {
+            return Some(event);
+        }
+    }
+}
+
+/// Talks to an MCP server over HTTP: the server sends its messages as server-sent events, and the client posts its
+/// messages to the endpoint given in the first event.
+pub struct SseTransport {
+    client: reqwest::Client,
+    endpoint: reqwest::Url,
+    events: reqwest::Response,
+    pending: Vec<u8>,
+    buffer: String,
+}
+
+impl SseTransport {
+    /// Connects to the server-sent events endpoint `url` of a server, such as `http://localhost:3000/sse`.
+    pub async fn connect(url: &str) -> Result<Self, McpError> {
+        Self::connect_with_client(reqwest::Client::new(), url).await
+    }
+
+    /// Connects with `client`, for instance to send authentication headers.
+    pub async fn connect_with_client(client: reqwest::Client, url: &str) -> Result<Self, McpError> {
+        let events = client
+            .get(url)
+            .header(ACCEPT, "text/event-stream")
+            .send()
+            .await?
+            .error_for_status()?;
+        let stream_url = events.url().clone();
+        let mut transport = Self {
+            client,
+            endpoint: stream_url.clone(),
+            events,
+            pending: Vec::new(),
+            buffer: String::new(),
+        };
+        let event = transport.next_event().await?;
+        if event.name != "endpoint" {
+            return Err(McpError::NoEndpoint);
+        }
+        transport.endpoint = stream_url
+            .join(event.data.trim())
+            .map_err(|_| McpError::NoEndpoint)?;
+        Ok(transport)
+    }
+
+    async fn next_event(&mut self) -> Result<Event, McpError> {
+        loop {
+            if let Some(event) = next_event(&mut self.buffer) {
+                return Ok(event);
+            }
+            let chunk = self.events.chunk().await?.ok_or(McpError::Closed)?;
+            self.pending.extend_from_slice(&chunk);
+            // A chunk can end in the middle of a character, whose end comes with the next one.
+            let valid = match std::str::from_utf8(&self.pending) {
+                Ok(text) => text.len(),
+                Err(error) if error.error_len().is_none() => error.valid_up_to(),
+                Err(_) => self.pending.len(),
+            };
+            let bytes: Vec<u8> = self.pending.drain(..valid).collect();
+            self.buffer.push_str(&String::from_utf8_lossy(&bytes));
+        }
+    }
+}
+
+#[async_trait]
+impl McpTransport for SseTransport {
+    async fn send(&mut self, message: &Value) -> Result<(), McpError> {
+        self.client
+            .post(self.endpoint.clone())
+            .json(message)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn receive(&mut self) -> Result<Value, McpError> {
+        loop {
+            let event = self.next_event().await?;
+            if event.name == "message" {
+                return Ok(serde_json::from_str(&event.data)?);
+            }
+        }
+    }
+}
+
+/// A tool of an MCP server, as listed by the server.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpToolInfo {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// The JSON Schema of the arguments of the tool.
+    pub input_schema: Value,
+}
+
+struct Connection {
+    transport: Mutex<Box<dyn McpTransport>>,
+    next_id: AtomicU64,
+    server_info: Value,
+}
+
+/// A client connected to an MCP server. Clones share the connection.
+#[derive(Clone)]
+pub struct McpClient {
+    connection: Arc<Connection>,
+}
+
+impl McpClient {
+    /// Connects to a server through `transport`, and goes through the initialization of the protocol.
+    pub async fn connect<T: McpTransport + 'static>(transport: T) -> Result<Self, McpError> {
+        let mut client = Self {
+            connection: Arc::new(Connection {
+                transport: Mutex::new(Box::new(transport)),
+                next_id: AtomicU64::new(1),
+                server_info: Value::Null,
+            }),
+        };
+        let params = json!({
+            "protocolVersion": PROTOCOL_VERSION,
+            "capabilities": {},
+            "clientInfo": { "name": "llm-chain", "version": env!("CARGO_PKG_VERSION") },
+        });
+        let result = client.request("initialize", params).await?;
+        client.notify("notifications/initialized").await?;
+        Arc::get_mut(&mut client.connection)
+            .expect("the connection isn't shared yet")
+            .server_info = result["serverInfo"].clone();
+        Ok(client)
+    }
+
+    /// Runs the server `program` with `args`, and connects to it through its stdin and stdout.
+    pub async fn stdio<I, S>(program: &str, args: I) -> Result<Self, McpError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        let mut command = Command::new(program);
+        command.args(args);
+        Self::connect(StdioTransport::spawn(command)?).await
+    }
+
+    /// Connects to the server whose server-sent events endpoint is `url`.
+    pub async fn sse(url: &str) -> Result<Self, McpError> {
+        Self::connect(SseTransport::connect(url).await?).await
+    }
+
+    /// Returns the name and version of the server, as it introduced itself.
+    pub fn server_info(&self) -> &Value {
+        &self.connection.server_info
+    }
+
+    /// Sends a request, and waits for its response. Requests of the server received in the meantime are answered,
+    /// and its notifications ignored.
+    pub async fn request(&self, method: &str, params: Value) -> Result<Value, McpError> {
+        let id = self.connection.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut transport = self.connection.transport.lock().await;
+        let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        transport.send(&request).await?;
+        loop {
+            let message = transport.receive().await?;
+            match (message.get("method"), message.get("id")) {
+                (None, Some(message_id)) if *message_id == json!(id) => {
+                    if let Some(error) = message.get("error") {
+                        return Err(McpError::Server {
+                            code: error["code"].as_i64().unwrap_or_default(),
+                            message: error["message"].as_str().unwrap_or_default().to_string(),
+                        });
+                    }
+                    return Ok(message["result"].clone());
+                }
+                (Some(method), Some(request_id)) => {
+                    let response = if method == "ping" {
+                        json!({ "jsonrpc": "2.0", "id": request_id, "result": {} })
+                    } else {
+                        let error = json!({ "code": -32601, "message": "Method not found" });
+                        json!({ "jsonrpc": "2.0", "id": request_id, "error": error })
+                    };
+                    transport.send(&response).await?;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    async fn notify(&self, method: &str) -> Result<(), McpError> {
+        let notification = json!({ "jsonrpc": "2.0", "method": method });
+        self.connection
+            .transport
+            .lock()
+            .await
+            .send(&notification)
+            .await
+    }
+
+    /// Lists the tools of the server.
+    pub async fn list_tools(&self) -> Result<Vec<McpToolInfo>, McpError> {
+        let mut tools = Vec::new();
+        let mut cursor = None;
+        loop {
+            let params = match cursor {
+                Some(cursor) => json!({ "cursor": cursor }),
+                None => json!({}),
+            };
+            let result = self.request("tools/list", params).await?;
+            tools.extend(serde_json::from_value::<Vec<McpToolInfo>>(
+                result["tools"].clone(),
+            )?);
+            match result["nextCursor"].as_str() {
+                Some(next) => cursor = Some(next.to_string()),
+                None => return Ok(tools),
+            }
+        }
+    }
+
+    /// Calls the tool `name` of the server with `arguments`, returning its result.
+    pub async fn call_tool(&self, name: &str, arguments: Value) -> Result<Value, McpError> {
+        let params = json!({ "name": name, "arguments": arguments });
+        self.request("tools/call", params).await
+    }
+
+    /// Returns the tools of the server, ready to be used by agents.
+    ///
+    /// A `ToolCollection` holds tools of a single type; to use the tools of a server with other tools, combine the
+    /// types with the `multitool!` macro.
+    pub async fn tools(&self) -> Result<ToolCollection<McpTool>, McpError> {
+        let mut tools = ToolCollection::new();
+        for info in self.list_tools().await? {
+            tools.add_tool(McpTool {
+                client: self.clone(),
+                info,
+            });
+        }
+        Ok(tools)
+    }
+}
+
+/// A tool of an MCP server, invoked by calling the server.
+pub struct McpTool {
+    client: McpClient,
+    info: McpToolInfo,
+}
+
+impl McpTool {
+    pub fn info(&self) -> &McpToolInfo {
+        &self.info
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct McpToolOutput {
+    /// The text content returned by the tool. Other contents, such as images, are mentioned by their type.
+    pub content: String,
+}
+
+#[derive(Debug, Error)]
+pub enum McpToolError {
+    #[error(transparent)]
+    Yaml(#[from] serde_yaml::Error),
+    #[error(transparent)]
+    Mcp(#[from] McpError),
+    #[error("The tool failed: {0}")]
+    Failed(String),
+}
+
+impl ToolError for McpToolError {}
+
+/// Turns the contents of the result of a tool call into text.
+fn content_text(result: &Value) -> String {
+    let contents = result["content"].as_array().into_iter().flatten();
+    contents
+        .map(|content| match content["type"].as_str() {
+            Some("text") => content["text"].as_str().unwrap_or_default().to_string(),
+            Some("resource") if content["resource"]["text"].is_string() => {
+                let resource = &content["resource"];
+                format!(
+                    "{}:\n{}",
+                    resource["uri"].as_str().unwrap_or_default(),
+                    resource["text"].as_str().unwrap_or_default()
+                )
+            }
+            kind => format!(
+                "[{} {}]",
+                kind.unwrap_or("content"),
+                content["mimeType"].as_str().unwrap_or_default()
+            ),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[async_trait]
+impl Tool for McpTool {
+    type Input = Value;
+    type Output = McpToolOutput;
+    type Error = McpToolError;
+
+    async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+        let arguments = match input {
+            Value::Null => json!({}),
+            input => input.clone(),
+        };
+        let result = self.client.call_tool(&self.info.name, arguments).await?;
+        let content = content_text(&result);
+        if result["isError"] == json!(true) {
+            return Err(McpToolError::Failed(content));
+        }
+        Ok(McpToolOutput { content })
+    }
+
+    fn description(&self) -> ToolDescription {
+        ToolDescription::new(
+            &self.info.name,
+            &self.info.description,
+            "",
+            Format::from_json_schema(&self.info.input_schema),
+            vec![("content", "The result of the tool").into()].into(),
+        )
+    }
+
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: self.info.name.clone(),
+            description: self.info.description.clone(),
+            parameters: self.info.input_schema.clone(),
+        }
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        name == self.info.name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use futures::executor::block_on;
+
+    use super::*;
+
+    /// A server answering the requests of the client with canned results, after a ping.
+    struct MockServer {
+        sent: Arc<std::sync::Mutex<Vec<Value>>>,
+        queue: VecDeque<Value>,
+    }
+
+    #[async_trait]
+    impl McpTransport for MockServer {
+        async fn send(&mut self, message: &Value) -> Result<(), McpError> {
+            self.sent.lock().unwrap().push(message.clone());
+            let id = message["id"].clone();
+            let result = match message["method"].as_str() {
+                Some("initialize") => json!({ "serverInfo": { "name": "mock" } }),
+                Some("tools/list") if message["params"]["cursor"].is_null() => json!({
+                    "tools": [{ "name": "echo", "description": "Echoes", "inputSchema": {
+                        "type": "object", "properties": { "text": { "type": "string" } } } }],
+                    "nextCursor": "2",
+                }),
+                Some("tools/list") => json!({
+                    "tools": [{ "name": "fail", "inputSchema": { "type": "object" } }],
+                }),
+                Some("tools/call") if message["params"]["name"] == "echo" => json!({
+                    "content": [
+                        { "type": "text", "text": message["params"]["arguments"]["text"] },
+                        { "type": "image", "data": "", "mimeType": "image/png" },
+                    ],
+                }),
+                Some("tools/call") => json!({
+                    "content": [{ "type": "text", "text": "broken" }], "isError": true,
+                }),
+                _ => return Ok(()),
+            };
+            self.queue
+                .push_back(json!({ "jsonrpc": "2.0", "id": "ping", "method": "ping" }));
+            self.queue
+                .push_back(json!({ "jsonrpc": "2.0", "method": "notifications/message" }));
+            self.queue
+                .push_back(json!({ "jsonrpc": "2.0", "id": id, "result": result }));
+            Ok(())
+        }
+
+        async fn receive(&mut self) -> Result<Value, McpError> {
+            self.queue.pop_front().ok_or(McpError::Closed)
+        }
+    }
+
+    #[test]
+    fn mounts_the_tools_of_a_server() {
+        let sent = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let server = MockServer {
+            sent: sent.clone(),
+            queue: VecDeque::new(),
+        };
+        block_on(async {
+            let client = McpClient::connect(server).await.unwrap();
+            assert_eq!(client.server_info()["name"], "mock");
+            let tools = client.tools().await.unwrap();
+            assert_eq!(tools.names(), ["echo", "fail"]);
+            let definition = &tools.definitions()[0];
+            assert_eq!(
+                definition.parameters["properties"]["text"]["type"],
+                "string"
+            );
+
+            let echo = tools.get("echo").unwrap();
+            let output = echo
+                .invoke_typed(&json!({ "text": "hello" }))
+                .await
+                .unwrap();
+            assert_eq!(output.content, "hello\n[image image/png]");
+            let error = tools.get("fail").unwrap().invoke_typed(&Value::Null).await;
+            assert!(matches!(error, Err(McpToolError::Failed(text)) if text == "broken"));
+        });
+        let sent = sent.lock().unwrap();
+        assert_eq!(
+            sent[1],
+            json!({ "jsonrpc": "2.0", "id": "ping", "result": {} })
+        );
+        assert_eq!(sent[2]["method"], "notifications/initialized");
+    }
+
+    #[test]
+    fn parses_server_sent_events() {
+        let mut buffer = ": keep-alive\n\nevent: endpoint\r\ndata: /messages?id=1\r\n\r\ndata: {\"a\":\ndata: 1}\n\nda".to_string();
+        assert_eq!(
+            next_event(&mut buffer),
+            Some(Event {
+                name: "endpoint".to_string(),
+                data: "/messages?id=1".to_string()
+            })
+        );
+        assert_eq!(next_event(&mut buffer).unwrap().data, "{\"a\":\n1}");
+        assert_eq!(next_event(&mut buffer), None);
+        assert_eq!(buffer, "da");
+    }
+}
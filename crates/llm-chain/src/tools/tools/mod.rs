@@ -3,13 +3,60 @@
 
 mod bash;
 mod bing_search;
+mod calculator;
 mod exit;
+mod filesystem;
+mod http;
+#[cfg(feature = "mcp")]
+mod mcp;
+mod openapi;
 mod python;
+mod shell;
+#[cfg(feature = "sql")]
+mod sql;
 mod vectorstore;
+#[cfg(feature = "wasm")]
+mod wasm;
+mod web_search;
+mod wikipedia;
 pub use bash::{BashTool, BashToolError, BashToolInput, BashToolOutput};
 pub use bing_search::{BingSearch, BingSearchError, BingSearchInput, BingSearchOutput};
+pub use calculator::{
+    CalculatorTool, CalculatorToolError, CalculatorToolInput, CalculatorToolOutput,
+};
 pub use exit::{ExitTool, ExitToolError, ExitToolInput, ExitToolOutput};
+pub use filesystem::{
+    FileSystemTool, FileSystemToolError, FileSystemToolInput, FileSystemToolOutput,
+};
+pub use http::{
+    html_to_markdown, represent, HttpMethod, HttpTool, HttpToolError, HttpToolInput,
+    HttpToolOutput, Representation,
+};
+#[cfg(feature = "mcp")]
+pub use mcp::{
+    McpClient, McpError, McpTool, McpToolError, McpToolInfo, McpToolOutput, McpTransport,
+    SseTransport, StdioTransport,
+};
+pub use openapi::{
+    OpenApiAuth, OpenApiError, OpenApiTool, OpenApiToolError, OpenApiToolOutput, OpenApiToolkit,
+};
 pub use python::{PythonTool, PythonToolError, PythonToolInput, PythonToolOutput};
+pub use shell::{ShellTool, ShellToolError, ShellToolInput, ShellToolOutput};
+#[cfg(feature = "sql")]
+pub use sql::{check_read_only, QueryFilter, SqlTool, SqlToolError, SqlToolInput, SqlToolOutput};
 pub use vectorstore::{
-    VectorStoreTool, VectorStoreToolError, VectorStoreToolInput, VectorStoreToolOutput,
+    FoundDocument, VectorStoreTool, VectorStoreToolError, VectorStoreToolInput,
+    VectorStoreToolOutput,
+};
+#[cfg(feature = "wasm")]
+pub use wasm::{
+    WasmInterpreterTool, WasmInterpreterToolError, WasmInterpreterToolInput,
+    WasmInterpreterToolOutput,
+};
+pub use web_search::{
+    Brave, SearXng, SearchBackend, SearchBackendError, SearchResult, SerpApi, Tavily,
+    WebSearchTool, WebSearchToolError, WebSearchToolInput, WebSearchToolOutput,
+};
+pub use wikipedia::{
+    WikipediaSection, WikipediaTool, WikipediaToolError, WikipediaToolInput, WikipediaToolOutput,
 };
@@ -0,0 +1,725 @@
+//! Generates tools from an [OpenAPI 3](https://spec.openapis.org/oas/v3.1.0) document, so that an agent can call an
+//! HTTP API without a tool being written for each of its endpoints.
+//!
+//! The [`OpenApiToolkit`] reads the document and builds a [`ToolCollection`] with an [`OpenApiTool`] per operation.
+//! Each tool is named after the `operationId` of its operation, and takes the path, query and header parameters of
+//! the operation as arguments, plus a `body` argument when the operation has a JSON or form request body. The JSON
+//! Schema of the arguments, advertised to models supporting tool calling, is built from the schemas of the document,
+//! and the arguments are checked against it before the request is sent.
+//!
+//! Credentials are given per security scheme of the document with [`OpenApiToolkit::with_credential`], and are sent
+//! with the operations requiring that scheme, or given with [`OpenApiToolkit::with_auth`] for every operation.
+//!
+//! # Example
+//!
+//! ```
+//! use llm_chain::tools::tools::OpenApiToolkit;
+//!
+//! let spec = r#"
+//! openapi: 3.0.0
+//! info: { title: Pets, version: "1" }
+//! servers: [{ url: "https://pets.example.com/v1" }]
+//! components:
+//!   securitySchemes:
+//!     token: { type: http, scheme: bearer }
+//! security: [{ token: [] }]
+//! paths:
+//!   /pets/{petId}:
+//!     get:
+//!       operationId: getPet
+//!       summary: Gets a pet by its id
+//!       parameters:
+//!         - { name: petId, in: path, required: true, schema: { type: integer } }
+//! "#;
+//! let tools = OpenApiToolkit::from_yaml(spec)
+//!     .unwrap()
+//!     .with_credential("token", "secret")
+//!     .build()
+//!     .unwrap();
+//! assert_eq!(tools.names(), ["getPet"]);
+//! ```
+use std::collections::{HashMap, HashSet};
+
+use async_trait::async_trait;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use thiserror::Error;
+
+use crate::json_schema::{JsonSchema, SchemaValidationError};
+use crate::tools::{
+    Describe, Format, Tool, ToolCollection, ToolDefinition, ToolDescription, ToolError,
+};
+
+/// The default limit on the bytes of a response body returned to the model.
+const DEFAULT_MAX_RESPONSE_BYTES: usize = 64 * 1024;
+
+/// How deep `$ref`s are followed; deeper schemas, which are usually recursive, accept any value.
+const MAX_REF_DEPTH: usize = 8;
+
+/// The HTTP methods of the operations of a path.
+const METHODS: &[&str] = &[
+    "get", "put", "post", "delete", "options", "head", "patch", "trace",
+];
+
+/// Credentials sent with requests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OpenApiAuth {
+    /// An `Authorization: Bearer` token.
+    Bearer(String),
+    /// HTTP basic authentication.
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+    /// A key sent in a header.
+    Header { name: String, value: String },
+    /// A key sent as a query parameter.
+    Query { name: String, value: String },
+}
+
+impl OpenApiAuth {
+    fn apply(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match self {
+            OpenApiAuth::Bearer(token) => request.bearer_auth(token),
+            OpenApiAuth::Basic { username, password } => {
+                request.basic_auth(username, password.as_ref())
+            }
+            OpenApiAuth::Header { name, value } => request.header(name, value),
+            OpenApiAuth::Query { name, value } => request.query(&[(name, value)]),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum OpenApiError {
+    #[error("The document isn't valid YAML or JSON: {0}")]
+    Parse(#[from] serde_yaml::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("The reference {0} can't be resolved")]
+    UnresolvedRef(String),
+    #[error("The document has no absolute server URL; set one with `with_base_url`")]
+    NoServer,
+    #[error("The security scheme {scheme} can't be used: {reason}")]
+    UnsupportedScheme { scheme: String, reason: String },
+}
+
+/// Builds the tools of the operations of an OpenAPI document.
+pub struct OpenApiToolkit {
+    spec: Value,
+    base_url: Option<String>,
+    auth: Vec<OpenApiAuth>,
+    credentials: HashMap<String, String>,
+    operations: Option<HashSet<String>>,
+    client: reqwest::Client,
+    max_response_bytes: usize,
+}
+
+impl OpenApiToolkit {
+    /// Creates a toolkit for the parsed OpenAPI document `spec`.
+    pub fn new(spec: Value) -> Self {
+        Self {
+            spec,
+            base_url: None,
+            auth: Vec::new(),
+            credentials: HashMap::new(),
+            operations: None,
+            client: reqwest::Client::new(),
+            max_response_bytes: DEFAULT_MAX_RESPONSE_BYTES,
+        }
+    }
+
+    /// Creates a toolkit for an OpenAPI document in YAML or JSON.
+    pub fn from_yaml(spec: &str) -> Result<Self, OpenApiError> {
+        let spec: serde_yaml::Value = serde_yaml::from_str(spec)?;
+        Ok(Self::new(serde_json::to_value(spec)?))
+    }
+
+    /// Sends the requests to `base_url` instead of the first server of the document.
+    pub fn with_base_url<S: Into<String>>(mut self, base_url: S) -> Self {
+        self.base_url = Some(base_url.into());
+        self
+    }
+
+    /// Sends `auth` with the requests of every operation, but the ones that require no security or are
+    /// authenticated with a credential given with [`with_credential`](Self::with_credential).
+    pub fn with_auth(mut self, auth: OpenApiAuth) -> Self {
+        self.auth.push(auth);
+        self
+    }
+
+    /// Sets the secret of the security scheme named `scheme` in the document: the token of `bearer` schemes, the
+    /// `username:password` of `basic` schemes, or the key of `apiKey` schemes.
+    pub fn with_credential<S: Into<String>, T: Into<String>>(
+        mut self,
+        scheme: S,
+        secret: T,
+    ) -> Self {
+        self.credentials.insert(scheme.into(), secret.into());
+        self
+    }
+
+    /// Only builds the tools with these names, leaving out the other operations.
+    pub fn with_operations<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.operations = Some(names.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Sets the client sending the requests, for instance to set timeouts or a proxy.
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Sets the maximum number of bytes of a response body returned to the model. Defaults to 64 KiB.
+    pub fn with_max_response_bytes(mut self, max_response_bytes: usize) -> Self {
+        self.max_response_bytes = max_response_bytes;
+        self
+    }
+
+    /// Builds a tool for each operation of the document.
+    pub fn build(&self) -> Result<ToolCollection<OpenApiTool>, OpenApiError> {
+        let base_url = self.base_url()?;
+        let empty = Map::new();
+        let paths = self.spec["paths"].as_object().unwrap_or(&empty);
+        let mut tools = ToolCollection::new();
+        for (path, item) in paths {
+            let item = self.resolve(item, 0)?;
+            for &method in METHODS {
+                let Some(operation) = item.get(method) else {
+                    continue;
+                };
+                let tool = self.tool(&base_url, path, method, &item, operation)?;
+                let selected = self.operations.as_ref();
+                if selected.is_some_and(|names| !names.contains(&tool.name)) {
+                    continue;
+                }
+                tools.add_tool(tool);
+            }
+        }
+        Ok(tools)
+    }
+
+    fn base_url(&self) -> Result<String, OpenApiError> {
+        let url = match &self.base_url {
+            Some(url) => url.clone(),
+            None => {
+                let server = &self.spec["servers"][0];
+                let mut url = server["url"].as_str().unwrap_or_default().to_string();
+                if let Some(variables) = server["variables"].as_object() {
+                    for (name, variable) in variables {
+                        let default = variable["default"].as_str().unwrap_or_default();
+                        url = url.replace(&format!("{{{}}}", name), default);
+                    }
+                }
+                url
+            }
+        };
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(OpenApiError::NoServer);
+        }
+        Ok(url.trim_end_matches('/').to_string())
+    }
+
+    /// Replaces the local `$ref`s of `value` by what they point to.
+    fn resolve(&self, value: &Value, depth: usize) -> Result<Value, OpenApiError> {
+        match value {
+            Value::Object(object) => {
+                if let Some(reference) = object.get("$ref").and_then(Value::as_str) {
+                    if depth >= MAX_REF_DEPTH {
+                        return Ok(json!({}));
+                    }
+                    let target = reference
+                        .strip_prefix('#')
+                        .and_then(|pointer| self.spec.pointer(pointer))
+                        .ok_or_else(|| OpenApiError::UnresolvedRef(reference.to_string()))?;
+                    return self.resolve(target, depth + 1);
+                }
+                let object = object
+                    .iter()
+                    .map(|(key, value)| Ok((key.clone(), self.resolve(value, depth)?)))
+                    .collect::<Result<Map<_, _>, OpenApiError>>()?;
+                Ok(Value::Object(object))
+            }
+            Value::Array(values) => Ok(Value::Array(
+                values
+                    .iter()
+                    .map(|value| self.resolve(value, depth))
+                    .collect::<Result<_, _>>()?,
+            )),
+            value => Ok(value.clone()),
+        }
+    }
+
+    fn tool(
+        &self,
+        base_url: &str,
+        path: &str,
+        method: &str,
+        item: &Value,
+        operation: &Value,
+    ) -> Result<OpenApiTool, OpenApiError> {
+        let operation = self.resolve(operation, 0)?;
+        let name = match operation["operationId"].as_str() {
+            Some(id) => sanitize_name(id),
+            None => {
+                let segments: Vec<_> = path.split('/').filter(|s| !s.is_empty()).collect();
+                sanitize_name(&format!("{}_{}", method, segments.join("_")))
+            }
+        };
+        let description = [&operation["summary"], &operation["description"]]
+            .iter()
+            .filter_map(|text| text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        // The parameters of the operation override the ones of the path with the same name and location.
+        let mut parameters: Vec<Value> = Vec::new();
+        let declared = [&item["parameters"], &operation["parameters"]];
+        for parameter in declared.iter().filter_map(|p| p.as_array()).flatten() {
+            parameters.retain(|p| p["name"] != parameter["name"] || p["in"] != parameter["in"]);
+            parameters.push(parameter.clone());
+        }
+        let mut properties = Map::new();
+        let mut required = Vec::new();
+        let mut locations = Vec::new();
+        for parameter in parameters {
+            let (Some(parameter_name), Some(location)) = (
+                parameter["name"].as_str(),
+                Location::parse(&parameter["in"]),
+            ) else {
+                continue;
+            };
+            let mut schema = parameter
+                .get("schema")
+                .cloned()
+                .unwrap_or_else(|| json!({}));
+            if let (Some(schema), Some(text)) =
+                (schema.as_object_mut(), parameter["description"].as_str())
+            {
+                schema.insert("description".to_string(), json!(text));
+            }
+            properties.insert(parameter_name.to_string(), schema);
+            if parameter["required"] == json!(true) || location == Location::Path {
+                required.push(json!(parameter_name));
+            }
+            locations.push((parameter_name.to_string(), location));
+        }
+        let content = &operation["requestBody"]["content"];
+        let body = [
+            ("application/json", BodyKind::Json),
+            ("application/x-www-form-urlencoded", BodyKind::Form),
+        ]
+        .into_iter()
+        .find(|(media_type, _)| content.get(media_type).is_some());
+        if let Some((media_type, _)) = body {
+            let mut schema = content[media_type]
+                .get("schema")
+                .cloned()
+                .unwrap_or_else(|| json!({}));
+            if let (Some(schema), Some(text)) = (
+                schema.as_object_mut(),
+                operation["requestBody"]["description"].as_str(),
+            ) {
+                schema.insert("description".to_string(), json!(text));
+            }
+            properties.insert("body".to_string(), schema);
+            if operation["requestBody"]["required"] == json!(true) {
+                required.push(json!("body"));
+            }
+        }
+
+        let security = operation.get("security").unwrap_or(&self.spec["security"]);
+        Ok(OpenApiTool {
+            name,
+            description,
+            method: Method::from_bytes(method.to_uppercase().as_bytes())
+                .expect("the methods are valid"),
+            url: format!("{}{}", base_url, path),
+            parameters: locations,
+            body: body.map(|(_, kind)| kind),
+            schema: json!({ "type": "object", "properties": properties, "required": required }),
+            auth: self.auth_for(security)?,
+            client: self.client.clone(),
+            max_response_bytes: self.max_response_bytes,
+        })
+    }
+
+    /// Returns the credentials to send for the security requirements of an operation: the ones of the first
+    /// alternative whose schemes all have a credential, or the credentials given for every operation.
+    fn auth_for(&self, security: &Value) -> Result<Vec<OpenApiAuth>, OpenApiError> {
+        let Some(requirements) = security.as_array() else {
+            return Ok(self.auth.clone());
+        };
+        if requirements.is_empty() {
+            return Ok(Vec::new());
+        }
+        for requirement in requirements {
+            let Some(schemes) = requirement.as_object() else {
+                continue;
+            };
+            if schemes.is_empty() {
+                // An empty requirement makes the authentication optional.
+                return Ok(self.auth.clone());
+            }
+            if schemes
+                .keys()
+                .all(|scheme| self.credentials.contains_key(scheme))
+            {
+                return schemes
+                    .keys()
+                    .map(|scheme| self.scheme_auth(scheme))
+                    .collect();
+            }
+        }
+        Ok(self.auth.clone())
+    }
+
+    fn scheme_auth(&self, scheme: &str) -> Result<OpenApiAuth, OpenApiError> {
+        let secret = self.credentials[scheme].clone();
+        let definition = &self.spec["components"]["securitySchemes"][scheme];
+        let definition = self.resolve(definition, 0)?;
+        let unsupported = |reason: &str| OpenApiError::UnsupportedScheme {
+            scheme: scheme.to_string(),
+            reason: reason.to_string(),
+        };
+        let name = definition["name"].as_str().unwrap_or_default().to_string();
+        match (
+            definition["type"].as_str(),
+            definition["scheme"].as_str().map(str::to_lowercase).as_deref(),
+            definition["in"].as_str(),
+        ) {
+            (Some("http"), Some("bearer"), _) | (Some("oauth2" | "openIdConnect"), _, _) => {
+                Ok(OpenApiAuth::Bearer(secret))
+            }
+            (Some("http"), Some("basic"), _) => {
+                let (username, password) = match secret.split_once(':') {
+                    Some((username, password)) => (username.to_string(), Some(password.to_string())),
+                    None => (secret, None),
+                };
+                Ok(OpenApiAuth::Basic { username, password })
+            }
+            (Some("apiKey"), _, Some("header")) => Ok(OpenApiAuth::Header { name, value: secret }),
+            (Some("apiKey"), _, Some("query")) => Ok(OpenApiAuth::Query { name, value: secret }),
+            (None, _, _) => Err(unsupported("it isn't defined in the document")),
+            _ => Err(unsupported("only bearer, basic, API keys in headers or queries, and OAuth 2 tokens are supported")),
+        }
+    }
+}
+
+/// Makes `name` a valid tool name for the tool calling APIs, which accept at most 64 letters, digits, `_` and `-`.
+fn sanitize_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let name = name.trim_matches('_');
+    name.chars().take(64).collect()
+}
+
+/// Percent-encodes `value` to be a segment of a URL path.
+fn encode_path_segment(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+                (b as char).to_string()
+            } else {
+                format!("%{:02X}", b)
+            }
+        })
+        .collect()
+}
+
+/// Returns a parameter value as text: strings as they are, and other values as JSON.
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Location {
+    Path,
+    Query,
+    Header,
+}
+
+impl Location {
+    /// Parses the `in` of a parameter. Cookie parameters aren't supported.
+    fn parse(value: &Value) -> Option<Self> {
+        match value.as_str()? {
+            "path" => Some(Location::Path),
+            "query" => Some(Location::Query),
+            "header" => Some(Location::Header),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BodyKind {
+    Json,
+    Form,
+}
+
+/// A tool calling an operation of an HTTP API, built by an [`OpenApiToolkit`].
+pub struct OpenApiTool {
+    name: String,
+    description: String,
+    method: Method,
+    url: String,
+    parameters: Vec<(String, Location)>,
+    body: Option<BodyKind>,
+    schema: Value,
+    auth: Vec<OpenApiAuth>,
+    client: reqwest::Client,
+    max_response_bytes: usize,
+}
+
+impl OpenApiTool {
+    /// Builds the request of a call with the arguments `input`, which must match the schema of the tool.
+    pub fn request(&self, input: &Value) -> Result<reqwest::RequestBuilder, OpenApiToolError> {
+        JsonSchema::new(&self.name, self.schema.clone()).validate(input)?;
+        let mut url = self.url.clone();
+        let mut query = Vec::new();
+        let mut headers = Vec::new();
+        for (name, location) in &self.parameters {
+            let Some(value) = input.get(name).filter(|value| !value.is_null()) else {
+                continue;
+            };
+            match location {
+                Location::Path => {
+                    url = url.replace(&format!("{{{}}}", name), &encode_path_segment(&text(value)))
+                }
+                Location::Query => match value {
+                    Value::Array(values) => {
+                        query.extend(values.iter().map(|value| (name.clone(), text(value))))
+                    }
+                    value => query.push((name.clone(), text(value))),
+                },
+                Location::Header => headers.push((name.clone(), text(value))),
+            }
+        }
+        let mut request = self.client.request(self.method.clone(), url).query(&query);
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        for auth in &self.auth {
+            request = auth.apply(request);
+        }
+        match (self.body, input.get("body")) {
+            (Some(BodyKind::Json), Some(body)) => request = request.json(body),
+            (Some(BodyKind::Form), Some(body)) => request = request.form(body),
+            _ => {}
+        }
+        Ok(request)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct OpenApiToolOutput {
+    pub status: u16,
+    /// The response body, parsed when it is JSON.
+    pub body: Value,
+    /// Whether the end of the body was left out to fit the limit.
+    pub truncated: bool,
+}
+
+impl Describe for OpenApiToolOutput {
+    fn describe() -> Format {
+        vec![
+            ("status", "The HTTP status of the response").into(),
+            ("body", "The response body").into(),
+            ("truncated", "true if the body was cut short").into(),
+        ]
+        .into()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum OpenApiToolError {
+    #[error(transparent)]
+    Yaml(#[from] serde_yaml::Error),
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+    #[error("The arguments don't match the parameters of the operation: {0}")]
+    InvalidInput(#[from] SchemaValidationError),
+}
+
+impl ToolError for OpenApiToolError {}
+
+#[async_trait]
+impl Tool for OpenApiTool {
+    type Input = Value;
+    type Output = OpenApiToolOutput;
+    type Error = OpenApiToolError;
+
+    async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+        let input = match input {
+            Value::Null => json!({}),
+            input => input.clone(),
+        };
+        let mut response = self.request(&input)?.send().await?;
+        let status = response.status().as_u16();
+        let mut body = Vec::new();
+        let mut truncated = false;
+        while let Some(chunk) = response.chunk().await? {
+            let room = self.max_response_bytes - body.len();
+            if chunk.len() > room {
+                body.extend_from_slice(&chunk[..room]);
+                truncated = true;
+                break;
+            }
+            body.extend_from_slice(&chunk);
+        }
+        let body = match serde_json::from_slice(&body) {
+            Ok(json) if !truncated => json,
+            _ => Value::String(String::from_utf8_lossy(&body).into_owned()),
+        };
+        Ok(OpenApiToolOutput {
+            status,
+            body,
+            truncated,
+        })
+    }
+
+    fn description(&self) -> ToolDescription {
+        ToolDescription::new(
+            &self.name,
+            &self.description,
+            &format!("Calls {} {}", self.method, self.url),
+            Format::from_json_schema(&self.schema),
+            OpenApiToolOutput::describe(),
+        )
+    }
+
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            parameters: self.schema.clone(),
+        }
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        name == self.name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEC: &str = r##"
+openapi: 3.0.0
+info: { title: Pets, version: "1" }
+servers: [{ url: "https://{region}.pets.example.com/v1", variables: { region: { default: eu } } }]
+security: [{ key: [] }]
+components:
+  securitySchemes:
+    key: { type: apiKey, in: header, name: X-Api-Key }
+  schemas:
+    Pet:
+      type: object
+      required: [name]
+      properties:
+        name: { type: string }
+        parent: { $ref: "#/components/schemas/Pet" }
+paths:
+  /pets/{petId}:
+    parameters:
+      - { name: petId, in: path, schema: { type: integer }, description: The id of the pet }
+    get:
+      operationId: getPet
+      parameters:
+        - { name: fields, in: query, schema: { type: array, items: { type: string } } }
+    put:
+      operationId: update pet!
+      security: []
+      requestBody:
+        required: true
+        content:
+          application/json:
+            schema: { $ref: "#/components/schemas/Pet" }
+  /health:
+    get: {}
+"##;
+
+    #[test]
+    fn builds_tools_from_the_document() {
+        let tools = OpenApiToolkit::from_yaml(SPEC)
+            .unwrap()
+            .with_credential("key", "secret")
+            .build()
+            .unwrap();
+        assert_eq!(tools.names(), ["get_health", "getPet", "update_pet"]);
+        let definitions = tools.definitions();
+        let update = &definitions[2].parameters;
+        assert_eq!(update["required"], json!(["petId", "body"]));
+        assert_eq!(
+            update["properties"]["body"]["properties"]["name"]["type"],
+            "string"
+        );
+        assert_eq!(
+            definitions[1].parameters["properties"]["petId"]["description"],
+            "The id of the pet"
+        );
+
+        let tools = OpenApiToolkit::from_yaml(SPEC)
+            .unwrap()
+            .with_credential("key", "secret")
+            .with_operations(["getPet", "update_pet"])
+            .build()
+            .unwrap();
+        let get = tools.get("getPet").unwrap();
+        let request = get
+            .request(&json!({ "petId": 7, "fields": ["name", "age"] }))
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(
+            request.url().as_str(),
+            "https://eu.pets.example.com/v1/pets/7?fields=name&fields=age"
+        );
+        assert_eq!(request.headers()["X-Api-Key"], "secret");
+        assert!(matches!(
+            get.request(&json!({ "petId": "seven" })),
+            Err(OpenApiToolError::InvalidInput(_))
+        ));
+
+        let update = tools.get("update_pet").unwrap();
+        let request = update
+            .request(&json!({ "petId": 7, "body": { "name": "Rex" } }))
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(request.method(), Method::PUT);
+        assert!(request.headers().get("X-Api-Key").is_none());
+        assert_eq!(
+            request.body().unwrap().as_bytes().unwrap(),
+            br#"{"name":"Rex"}"#
+        );
+    }
+
+    #[test]
+    fn requires_an_absolute_server() {
+        let spec = "openapi: 3.0.0\nservers: [{ url: /v1 }]\npaths: {}";
+        let toolkit = OpenApiToolkit::from_yaml(spec).unwrap();
+        assert!(matches!(toolkit.build(), Err(OpenApiError::NoServer)));
+        let toolkit = toolkit.with_base_url("http://localhost:8000/v1/");
+        assert!(toolkit.build().is_ok());
+    }
+}
@@ -0,0 +1,435 @@
+//! The shell tool runs commands chosen from an allowlist, for agents that need a few programs, such as `grep` or
+//! `cargo test`, without being handed a whole shell like the `BashTool`.
+//!
+//! The command line given by the model is split into words like a shell would, but isn't run by a shell: pipes,
+//! redirections, substitutions and `;` are passed to the program as plain arguments. The program must be in the
+//! allowlist, its arguments can be checked against patterns, and it runs with a cleared environment, in a fresh
+//! temporary directory unless a working directory is set, with a timeout and a cap on the output kept. Commands run on
+//! a thread of their own, leaving the executor free in the meantime.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use llm_chain::tools::tools::ShellTool;
+//! use regex::Regex;
+//!
+//! let tool = ShellTool::new()
+//!     .with_allowed_commands(["echo", "ls", "git"])
+//!     .with_argument_pattern("git", Regex::new("status|log|diff|--oneline|-n|[0-9]+").unwrap())
+//!     .with_timeout(Duration::from_secs(5));
+//! ```
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use futures::channel::oneshot;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::tools::{Describe, Format, Tool, ToolDescription, ToolError};
+
+/// The default time a command can run for.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The default limit on the bytes kept of each of stdout and stderr.
+const DEFAULT_MAX_OUTPUT_BYTES: usize = 64 * 1024;
+
+/// How often a running command is checked for completion.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Clone)]
+pub struct ShellTool {
+    allowed_commands: HashSet<String>,
+    argument_patterns: HashMap<String, Regex>,
+    working_dir: Option<PathBuf>,
+    env: HashMap<String, String>,
+    timeout: Duration,
+    max_output_bytes: usize,
+}
+
+impl ShellTool {
+    /// Creates a tool that runs no command until some are allowed.
+    pub fn new() -> Self {
+        Self {
+            allowed_commands: HashSet::new(),
+            argument_patterns: HashMap::new(),
+            working_dir: None,
+            env: HashMap::new(),
+            timeout: DEFAULT_TIMEOUT,
+            max_output_bytes: DEFAULT_MAX_OUTPUT_BYTES,
+        }
+    }
+
+    /// Allows running `command`, a program looked up in the `PATH`, or a path to a program.
+    pub fn with_allowed_command<S: Into<String>>(mut self, command: S) -> Self {
+        self.allowed_commands.insert(command.into());
+        self
+    }
+
+    /// Allows running each of `commands`.
+    pub fn with_allowed_commands<I, S>(mut self, commands: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.allowed_commands
+            .extend(commands.into_iter().map(Into::into));
+        self
+    }
+
+    /// Requires every argument of `command` to match `pattern` entirely. Arguments of commands without a pattern
+    /// aren't checked.
+    pub fn with_argument_pattern<S: Into<String>>(mut self, command: S, pattern: Regex) -> Self {
+        let anchored = Regex::new(&format!("^(?:{})$", pattern.as_str()))
+            .expect("an anchored valid pattern is valid");
+        self.argument_patterns.insert(command.into(), anchored);
+        self
+    }
+
+    /// Runs the commands in `dir` instead of a fresh temporary directory removed after each command.
+    pub fn with_working_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.working_dir = Some(dir.into());
+        self
+    }
+
+    /// Sets an environment variable of the commands. The environment is otherwise empty but for `PATH`.
+    pub fn with_env<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.env.insert(key.into(), value.into());
+        self
+    }
+
+    /// Sets how long a command can run before it is killed. Defaults to 30 seconds.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the maximum number of bytes kept of each of stdout and stderr. Defaults to 64 KiB.
+    pub fn with_max_output_bytes(mut self, max_output_bytes: usize) -> Self {
+        self.max_output_bytes = max_output_bytes;
+        self
+    }
+
+    /// Splits `command` into words and checks them against the allowlist and the argument patterns.
+    pub fn check(&self, command: &str) -> Result<Vec<String>, ShellToolError> {
+        let words = split_words(command)?;
+        let (program, arguments) = words.split_first().ok_or(ShellToolError::Empty)?;
+        if !self.allowed_commands.contains(program) {
+            return Err(ShellToolError::NotAllowed(program.clone()));
+        }
+        if let Some(pattern) = self.argument_patterns.get(program) {
+            if let Some(argument) = arguments.iter().find(|a| !pattern.is_match(a)) {
+                return Err(ShellToolError::ArgumentNotAllowed {
+                    command: program.clone(),
+                    argument: argument.clone(),
+                });
+            }
+        }
+        Ok(words)
+    }
+
+    /// Runs the command in the working directory, or in a temporary directory removed afterwards.
+    fn run_in_dir(&self, words: &[String]) -> Result<ShellToolOutput, ShellToolError> {
+        if let Some(dir) = &self.working_dir {
+            return self.run(words, dir);
+        }
+        let dir = std::env::temp_dir().join(format!("llm-chain-shell-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir)?;
+        let output = self.run(words, &dir);
+        if let Err(e) = std::fs::remove_dir_all(&dir) {
+            log::warn!(
+                "failed to remove the temporary directory {}: {}",
+                dir.display(),
+                e
+            );
+        }
+        output
+    }
+
+    fn run(&self, words: &[String], dir: &Path) -> Result<ShellToolOutput, ShellToolError> {
+        let mut command = Command::new(&words[0]);
+        command
+            .args(&words[1..])
+            .current_dir(dir)
+            .env_clear()
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Some(path) = std::env::var_os("PATH") {
+            command.env("PATH", path);
+        }
+        command.env("HOME", dir).envs(&self.env);
+        let mut child = command.spawn()?;
+        let max = self.max_output_bytes;
+        let stdout = child.stdout.take().map(|out| capped_reader(out, max));
+        let stderr = child.stderr.take().map(|err| capped_reader(err, max));
+
+        let deadline = Instant::now() + self.timeout;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                child.kill()?;
+                child.wait()?;
+                return Err(ShellToolError::Timeout(self.timeout));
+            }
+            thread::sleep(POLL_INTERVAL);
+        };
+        let join = |reader: Option<thread::JoinHandle<std::io::Result<(String, bool)>>>| {
+            reader.map_or(Ok((String::new(), false)), |reader| {
+                reader.join().expect("the output reader doesn't panic")
+            })
+        };
+        let (stdout, stdout_truncated) = join(stdout)?;
+        let (stderr, stderr_truncated) = join(stderr)?;
+        Ok(ShellToolOutput {
+            status: status
+                .code()
+                .ok_or(ShellToolError::ProcessTerminatedBySignal)?,
+            stdout,
+            stderr,
+            truncated: stdout_truncated || stderr_truncated,
+        })
+    }
+}
+
+impl Default for ShellTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reads `output` on a thread, keeping its first `max` bytes and draining the rest so that the command never blocks
+/// on a full pipe.
+fn capped_reader<R: Read + Send + 'static>(
+    mut output: R,
+    max: usize,
+) -> thread::JoinHandle<std::io::Result<(String, bool)>> {
+    thread::spawn(move || {
+        let mut kept = Vec::new();
+        (&mut output).take(max as u64).read_to_end(&mut kept)?;
+        let truncated = std::io::copy(&mut output, &mut std::io::sink())? > 0;
+        Ok((String::from_utf8_lossy(&kept).into_owned(), truncated))
+    })
+}
+
+/// Splits a command line into words, with the quotes and backslash escapes of POSIX shells.
+fn split_words(line: &str) -> Result<Vec<String>, ShellToolError> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    let unterminated = || ShellToolError::Parse(line.to_string());
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next().ok_or_else(unterminated)? {
+                        '\'' => break,
+                        c => word.push(c),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next().ok_or_else(unterminated)? {
+                        '"' => break,
+                        '\\' => match chars.next().ok_or_else(unterminated)? {
+                            c @ ('"' | '\\' | '$' | '`') => word.push(c),
+                            c => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                        },
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => word
+                .get_or_insert_with(String::new)
+                .push(chars.next().ok_or_else(unterminated)?),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ShellToolInput {
+    pub command: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ShellToolOutput {
+    pub status: i32,
+    pub stdout: String,
+    pub stderr: String,
+    /// Whether part of stdout or stderr was left out to fit the limit.
+    pub truncated: bool,
+}
+
+impl Describe for ShellToolInput {
+    fn describe() -> Format {
+        vec![(
+            "command",
+            "The command line to run: an allowed program and its arguments, without pipes or redirections",
+        )
+            .into()]
+        .into()
+    }
+}
+
+impl Describe for ShellToolOutput {
+    fn describe() -> Format {
+        vec![
+            ("status", "The exit code, 0 for success").into(),
+            ("stdout", "The stdout output of the command").into(),
+            ("stderr", "The stderr output of the command").into(),
+            ("truncated", "true if the output was cut short").into(),
+        ]
+        .into()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ShellToolError {
+    #[error(transparent)]
+    YamlError(#[from] serde_yaml::Error),
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+    #[error("The command line has an unterminated quote or escape: {0}")]
+    Parse(String),
+    #[error("The command line is empty")]
+    Empty,
+    #[error("The command {0} isn't allowed")]
+    NotAllowed(String),
+    #[error("The argument {argument} of {command} isn't allowed")]
+    ArgumentNotAllowed { command: String, argument: String },
+    #[error("The command didn't finish within {0:?} and was killed")]
+    Timeout(Duration),
+    #[error("Received a None status code, which means the program was exited by signal")]
+    ProcessTerminatedBySignal,
+    #[error("The command was canceled before it finished")]
+    Canceled,
+}
+
+impl ToolError for ShellToolError {}
+
+#[async_trait]
+impl Tool for ShellTool {
+    type Input = ShellToolInput;
+    type Output = ShellToolOutput;
+    type Error = ShellToolError;
+
+    async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+        let words = self.check(&input.command)?;
+        let tool = self.clone();
+        let (sender, receiver) = oneshot::channel();
+        thread::spawn(move || sender.send(tool.run_in_dir(&words)));
+        receiver.await.map_err(|_| ShellToolError::Canceled)?
+    }
+
+    fn description(&self) -> ToolDescription {
+        let mut commands: Vec<_> = self.allowed_commands.iter().map(String::as_str).collect();
+        commands.sort_unstable();
+        ToolDescription::new(
+            "Shell",
+            "Runs a command and returns its output.",
+            &format!(
+                "Use this to run one of these programs: {}. Commands aren't run by a shell, so pipes, redirections \
+                 and `;` don't work.",
+                commands.join(", ")
+            ),
+            ShellToolInput::describe(),
+            ShellToolOutput::describe(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_words_like_a_shell() {
+        assert_eq!(
+            split_words(r#"grep -n "a b" 'c "d"' e\ f "g\"h""#).unwrap(),
+            ["grep", "-n", "a b", "c \"d\"", "e f", "g\"h"]
+        );
+        assert_eq!(split_words("echo ''").unwrap(), ["echo", ""]);
+        assert!(split_words("echo 'a").is_err());
+    }
+
+    #[test]
+    fn checks_commands_and_arguments() {
+        let tool = ShellTool::new()
+            .with_allowed_commands(["echo", "git"])
+            .with_argument_pattern("git", Regex::new("status|log").unwrap());
+        assert!(tool.check("echo a; rm -rf /").is_ok());
+        assert!(tool.check("git status").is_ok());
+        assert!(matches!(
+            tool.check("rm -rf /"),
+            Err(ShellToolError::NotAllowed(_))
+        ));
+        assert!(matches!(
+            tool.check("git statuses"),
+            Err(ShellToolError::ArgumentNotAllowed { .. })
+        ));
+        assert!(matches!(tool.check("  "), Err(ShellToolError::Empty)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn runs_with_limits() {
+        let tool = ShellTool::new()
+            .with_allowed_commands(["echo", "sleep"])
+            .with_max_output_bytes(5)
+            .with_timeout(Duration::from_millis(200));
+        let run = |command: &str| {
+            futures::executor::block_on(tool.invoke_typed(&ShellToolInput {
+                command: command.to_string(),
+            }))
+        };
+        let output = run("echo 'hello world' | wc").unwrap();
+        assert_eq!(output.status, 0);
+        assert_eq!(output.stdout, "hello");
+        assert!(output.truncated);
+        let output =
+            futures::executor::block_on(ShellTool::new().with_allowed_command("pwd").invoke_typed(
+                &ShellToolInput {
+                    command: "pwd".to_string(),
+                },
+            ))
+            .unwrap();
+        assert!(output.stdout.contains("llm-chain-shell-"));
+        assert!(matches!(run("sleep 5"), Err(ShellToolError::Timeout(_))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn runs_commands_concurrently() {
+        let tool = ShellTool::new().with_allowed_command("sleep");
+        let input = ShellToolInput {
+            command: "sleep 0.5".to_string(),
+        };
+        let start = Instant::now();
+        let (first, second) = futures::executor::block_on(futures::future::join(
+            tool.invoke_typed(&input),
+            tool.invoke_typed(&input),
+        ));
+        assert_eq!(first.unwrap().status, 0);
+        assert_eq!(second.unwrap().status, 0);
+        assert!(start.elapsed() < Duration::from_millis(900));
+    }
+}
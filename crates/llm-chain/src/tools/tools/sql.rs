@@ -0,0 +1,467 @@
+//! The SQL tool gives agents read access to a database: it lists the tables, describes their columns, and runs
+//! queries, returning the results as markdown tables.
+//!
+//! Queries are checked before they run: only a single `SELECT`, `WITH`, `VALUES` or `EXPLAIN` statement is accepted,
+//! and statements writing data or changing the schema are rejected. Queries that pass are run in a transaction that
+//! is rolled back. These checks are best effort, since SQL dialects have many ways to cause side effects: connecting
+//! with a user that can only read is the way to make sure the agent can't change the database. A `QueryFilter` set
+//! with `with_query_filter` can reject more queries, for example the ones reading tables the agent shouldn't see.
+//!
+//! Results are limited to a number of rows and of characters, and the output says when rows were left out. The
+//! tool uses the `Any` driver of sqlx, which supports SQLite, PostgreSQL and MySQL with the `sql-sqlite`,
+//! `sql-postgres` and `sql-mysql` features. It decodes booleans, numbers, text and blobs: columns of other types,
+//! such as dates, must be cast to text in the query.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let tool = SqlTool::connect("sqlite://shop.db")
+//!     .await?
+//!     .with_max_rows(20)
+//!     .with_query_filter(|sql: &str| {
+//!         if sql.to_lowercase().contains("customers") {
+//!             Err("The customers table can't be read".to_string())
+//!         } else {
+//!             Ok(())
+//!         }
+//!     });
+//! let mut tools = ToolCollection::new();
+//! tools.add_tool(tool);
+//! ```
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use sqlx::any::AnyRow;
+use sqlx::{AnyPool, Column, Row, TypeInfo, ValueRef};
+use thiserror::Error;
+
+use crate::tools::{Describe, Format, Tool, ToolDescription, ToolError};
+
+/// The default maximum number of rows returned by a query.
+const DEFAULT_MAX_ROWS: usize = 50;
+
+/// The default budget for the content of an observation, in characters. Roughly a thousand tokens.
+const DEFAULT_MAX_CHARS: usize = 4000;
+
+/// The keywords starting the statements the tool runs.
+const READ_KEYWORDS: &[&str] = &["select", "with", "values", "explain"];
+
+/// The keywords of statements writing data or changing the schema, rejected anywhere in a query since they can be
+/// nested in reading statements, as in data-modifying `WITH` clauses or `SELECT ... INTO`.
+const WRITE_KEYWORDS: &[&str] = &[
+    "insert", "update", "delete", "merge", "upsert", "create", "alter", "drop", "truncate",
+    "grant", "revoke", "attach", "detach", "pragma", "vacuum", "copy", "call", "exec", "execute",
+    "into",
+];
+
+/// Decides whether the tool may run a query, in addition to the read-only checks.
+pub trait QueryFilter: Send + Sync {
+    /// Returns an error explaining to the model why `sql` can't be run, or `Ok(())` to run it.
+    fn check(&self, sql: &str) -> Result<(), String>;
+}
+
+impl<F> QueryFilter for F
+where
+    F: Fn(&str) -> Result<(), String> + Send + Sync,
+{
+    fn check(&self, sql: &str) -> Result<(), String> {
+        self(sql)
+    }
+}
+
+pub struct SqlTool {
+    pool: AnyPool,
+    max_rows: usize,
+    max_chars: usize,
+    filter: Option<Box<dyn QueryFilter>>,
+}
+
+impl SqlTool {
+    /// Creates a tool querying the database of `pool`.
+    pub fn new(pool: AnyPool) -> Self {
+        Self {
+            pool,
+            max_rows: DEFAULT_MAX_ROWS,
+            max_chars: DEFAULT_MAX_CHARS,
+            filter: None,
+        }
+    }
+
+    /// Connects to the database at `url`, such as `sqlite://data.db` or `postgres://reader@localhost/shop`.
+    pub async fn connect(url: &str) -> Result<Self, SqlToolError> {
+        sqlx::any::install_default_drivers();
+        Ok(Self::new(AnyPool::connect(url).await?))
+    }
+
+    /// Sets the maximum number of rows of the result of a query. Defaults to 50.
+    pub fn with_max_rows(mut self, max_rows: usize) -> Self {
+        self.max_rows = max_rows;
+        self
+    }
+
+    /// Sets the maximum number of characters of content in an observation.
+    pub fn with_max_chars(mut self, max_chars: usize) -> Self {
+        self.max_chars = max_chars;
+        self
+    }
+
+    /// Sets a filter rejecting queries, such as a closure taking the SQL of the query.
+    pub fn with_query_filter<F: QueryFilter + 'static>(mut self, filter: F) -> Self {
+        self.filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Returns the name of the database system, as reported by sqlx.
+    async fn backend(&self) -> Result<String, SqlToolError> {
+        let connection = self.pool.acquire().await?;
+        Ok(connection.backend_name().to_string())
+    }
+
+    async fn list_tables(&self) -> Result<SqlToolOutput, SqlToolError> {
+        let sql = match self.backend().await?.as_str() {
+            "SQLite" => "SELECT name AS table_name, type AS table_type FROM sqlite_master \
+                 WHERE type IN ('table', 'view') AND name NOT LIKE 'sqlite_%' ORDER BY name",
+            "MySQL" => "SELECT table_name, table_type FROM information_schema.tables \
+                 WHERE table_schema = DATABASE() ORDER BY table_name",
+            _ => "SELECT CAST(table_schema AS TEXT) AS table_schema, CAST(table_name AS TEXT) AS table_name, \
+                 CAST(table_type AS TEXT) AS table_type FROM information_schema.tables \
+                 WHERE table_schema NOT IN ('pg_catalog', 'information_schema') ORDER BY table_schema, table_name",
+        };
+        let rows: Vec<AnyRow> = sqlx::query(sql).fetch_all(&self.pool).await?;
+        Ok(self.render(&rows, false))
+    }
+
+    async fn describe_table(&self, table: &str) -> Result<SqlToolOutput, SqlToolError> {
+        let sql = match self.backend().await?.as_str() {
+            "SQLite" => "SELECT name AS column_name, type AS data_type, \
+                 CASE WHEN \"notnull\" = 0 THEN 'YES' ELSE 'NO' END AS is_nullable, \
+                 CASE WHEN pk > 0 THEN 'YES' ELSE 'NO' END AS primary_key \
+                 FROM pragma_table_info(?) ORDER BY cid",
+            "MySQL" => "SELECT column_name, column_type AS data_type, is_nullable, column_key \
+                 FROM information_schema.columns \
+                 WHERE table_schema = DATABASE() AND table_name = ? ORDER BY ordinal_position",
+            _ => "SELECT CAST(column_name AS TEXT) AS column_name, CAST(data_type AS TEXT) AS data_type, \
+                 CAST(is_nullable AS TEXT) AS is_nullable FROM information_schema.columns \
+                 WHERE table_name = $1 ORDER BY ordinal_position",
+        };
+        let rows: Vec<AnyRow> = sqlx::query(sql).bind(table).fetch_all(&self.pool).await?;
+        if rows.is_empty() {
+            return Err(SqlToolError::TableNotFound(table.to_string()));
+        }
+        Ok(self.render(&rows, false))
+    }
+
+    async fn query(&self, sql: &str) -> Result<SqlToolOutput, SqlToolError> {
+        check_read_only(sql).map_err(SqlToolError::QueryRejected)?;
+        if let Some(filter) = &self.filter {
+            filter.check(sql).map_err(SqlToolError::QueryRejected)?;
+        }
+        let mut transaction = self.pool.begin().await?;
+        let mut rows = Vec::new();
+        {
+            let mut stream = sqlx::query(sql).fetch(&mut *transaction);
+            while let Some(row) = stream.try_next().await? {
+                if rows.len() == self.max_rows {
+                    drop(stream);
+                    transaction.rollback().await?;
+                    return Ok(self.render(&rows, true));
+                }
+                rows.push(row);
+            }
+        }
+        transaction.rollback().await?;
+        Ok(self.render(&rows, false))
+    }
+
+    /// Renders `rows` as a markdown table within the budget of characters.
+    fn render(&self, rows: &[AnyRow], more_rows: bool) -> SqlToolOutput {
+        let Some(first) = rows.first() else {
+            return SqlToolOutput {
+                content: "The query returned no rows.".to_string(),
+                truncated: false,
+            };
+        };
+        let header: Vec<String> = first
+            .columns()
+            .iter()
+            .map(|column| escape_cell(column.name()))
+            .collect();
+        let mut table = format!(
+            "| {} |\n|{}|\n",
+            header.join(" | "),
+            vec![" --- "; header.len()].join("|")
+        );
+        let mut truncated = more_rows;
+        for row in rows {
+            let cells: Vec<String> = (0..row.len()).map(|i| cell(row, i)).collect();
+            let line = format!("| {} |\n", cells.join(" | "));
+            if table.chars().count() + line.chars().count() > self.max_chars {
+                truncated = true;
+                break;
+            }
+            table.push_str(&line);
+        }
+        if truncated {
+            table.push_str("\nMore rows were left out.");
+        }
+        SqlToolOutput {
+            content: table.trim_end().to_string(),
+            truncated,
+        }
+    }
+}
+
+/// Renders the value of the column `i` of `row` for a markdown table.
+fn cell(row: &AnyRow, i: usize) -> String {
+    let value = match row.try_get_raw(i) {
+        Ok(value) => value,
+        Err(error) => return format!("[{}]", error),
+    };
+    if value.is_null() || value.type_info().name() == "NULL" {
+        return "NULL".to_string();
+    }
+    let text = match value.type_info().name() {
+        "BOOLEAN" => row.try_get::<bool, _>(i).map(|v| v.to_string()),
+        "SMALLINT" | "INTEGER" | "BIGINT" => row.try_get::<i64, _>(i).map(|v| v.to_string()),
+        "REAL" | "DOUBLE" => row.try_get::<f64, _>(i).map(|v| v.to_string()),
+        "BLOB" => row
+            .try_get::<Vec<u8>, _>(i)
+            .map(|v| format!("[{} bytes]", v.len())),
+        _ => row.try_get::<String, _>(i),
+    };
+    match text {
+        Ok(text) => escape_cell(&text),
+        Err(_) => format!("[{}]", value.type_info().name()),
+    }
+}
+
+fn escape_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace(['\r', '\n'], " ")
+}
+
+/// Checks that `sql` is a single statement reading data, rejecting the keywords of statements writing it. String
+/// literals, quoted identifiers and comments are ignored.
+pub fn check_read_only(sql: &str) -> Result<(), String> {
+    let code = strip_literals(sql);
+    let statements: Vec<&str> = code
+        .split(';')
+        .filter(|statement| !statement.trim().is_empty())
+        .collect();
+    let statement = match statements.as_slice() {
+        [statement] => statement.to_lowercase(),
+        [] => return Err("The query is empty".to_string()),
+        _ => return Err("Only one statement can be run at a time".to_string()),
+    };
+    let mut words = statement
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|word| !word.is_empty());
+    if !words
+        .next()
+        .is_some_and(|first| READ_KEYWORDS.contains(&first))
+    {
+        return Err("Only SELECT queries can be run".to_string());
+    }
+    match words.find(|word| WRITE_KEYWORDS.contains(word)) {
+        Some(keyword) => Err(format!(
+            "The query can't contain {}: only reading data is allowed",
+            keyword.to_uppercase()
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Replaces string literals, quoted identifiers and comments of `sql` with spaces.
+fn strip_literals(sql: &str) -> String {
+    let mut out = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' | '`' => {
+                // A doubled quote inside a literal is an escaped quote, which the loop skips as two literals.
+                for next in chars.by_ref() {
+                    if next == c {
+                        break;
+                    }
+                }
+                out.push(' ');
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                for next in chars.by_ref() {
+                    if next == '\n' {
+                        break;
+                    }
+                }
+                out.push('\n');
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for next in chars.by_ref() {
+                    if previous == '*' && next == '/' {
+                        break;
+                    }
+                    previous = next;
+                }
+                out.push(' ');
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// What the tool is asked to do.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum SqlToolInput {
+    /// Lists the tables and views of the database.
+    ListTables,
+    /// Describes the columns of a table.
+    DescribeTable { table: String },
+    /// Runs a read-only query.
+    Query { sql: String },
+}
+
+impl Describe for SqlToolInput {
+    fn describe() -> Format {
+        vec![
+            ("action", "One of list_tables, describe_table or query").into(),
+            ("table", "The table to describe, for describe_table").into(),
+            ("sql", "The SELECT query to run, for query").into(),
+        ]
+        .into()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SqlToolOutput {
+    /// The result, as a markdown table.
+    pub content: String,
+    /// Whether rows were left out to fit the limits.
+    pub truncated: bool,
+}
+
+impl Describe for SqlToolOutput {
+    fn describe() -> Format {
+        vec![
+            ("content", "The result, as a markdown table").into(),
+            ("truncated", "true if rows were left out").into(),
+        ]
+        .into()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SqlToolError {
+    #[error(transparent)]
+    Yaml(#[from] serde_yaml::Error),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+    #[error("There is no table named {0}")]
+    TableNotFound(String),
+    #[error("The query was rejected: {0}")]
+    QueryRejected(String),
+}
+
+impl ToolError for SqlToolError {}
+
+#[async_trait]
+impl Tool for SqlTool {
+    type Input = SqlToolInput;
+    type Output = SqlToolOutput;
+    type Error = SqlToolError;
+
+    async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+        match input {
+            SqlToolInput::ListTables => self.list_tables().await,
+            SqlToolInput::DescribeTable { table } => self.describe_table(table).await,
+            SqlToolInput::Query { sql } => self.query(sql).await,
+        }
+    }
+
+    fn description(&self) -> ToolDescription {
+        ToolDescription::new(
+            "SQL",
+            "Useful for answering questions from a database. Lists the tables, describes their columns, and runs read-only SQL queries.",
+            "List the tables and describe the ones you need before writing queries. Only SELECT queries can be run, and the results are limited, so aggregate and filter in SQL rather than reading whole tables.",
+            SqlToolInput::describe(),
+            SqlToolOutput::describe(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_reading_queries_pass() {
+        assert!(check_read_only("SELECT name FROM users WHERE note = 'drop table'; ").is_ok());
+        assert!(check_read_only("with t as (select 1) select * from t -- delete").is_ok());
+        assert!(check_read_only("SELECT 1; DROP TABLE users").is_err());
+        assert!(check_read_only("DELETE FROM users").is_err());
+        assert!(
+            check_read_only("WITH t AS (DELETE FROM users RETURNING *) SELECT * FROM t").is_err()
+        );
+        assert!(check_read_only("SELECT * INTO backup FROM users").is_err());
+        assert!(check_read_only("/* select */ update users set a = 1").is_err());
+    }
+
+    #[cfg(feature = "sql-sqlite")]
+    #[test]
+    fn lists_describes_and_queries_sqlite() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            sqlx::any::install_default_drivers();
+            let pool = sqlx::any::AnyPoolOptions::new()
+                .max_connections(1)
+                .connect("sqlite::memory:")
+                .await
+                .unwrap();
+            sqlx::query(
+                "CREATE TABLE pets (id INTEGER PRIMARY KEY, name TEXT NOT NULL, weight REAL);
+                 INSERT INTO pets (name, weight) VALUES ('Rex', 12.5), ('Tom|Cat', NULL), ('Fido', 3)",
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+            let tool = SqlTool::new(pool).with_max_rows(2).with_query_filter(|sql: &str| {
+                if sql.contains("secret") {
+                    Err("no secrets".to_string())
+                } else {
+                    Ok(())
+                }
+            });
+
+            let output = tool.invoke_typed(&SqlToolInput::ListTables).await.unwrap();
+            assert_eq!(
+                output.content,
+                "| table_name | table_type |\n| --- | --- |\n| pets | table |"
+            );
+            let input = SqlToolInput::DescribeTable {
+                table: "pets".to_string(),
+            };
+            let output = tool.invoke_typed(&input).await.unwrap();
+            assert!(output.content.contains("| name | TEXT | NO | NO |"));
+
+            let input = SqlToolInput::Query {
+                sql: "SELECT name, weight FROM pets ORDER BY id".to_string(),
+            };
+            let output = tool.invoke_typed(&input).await.unwrap();
+            assert_eq!(
+                output.content,
+                "| name | weight |\n| --- | --- |\n| Rex | 12.5 |\n| Tom\\|Cat | NULL |\n\nMore rows were left out."
+            );
+            assert!(output.truncated);
+
+            let input = SqlToolInput::Query {
+                sql: "SELECT * FROM secret".to_string(),
+            };
+            let error = tool.invoke_typed(&input).await.unwrap_err();
+            assert!(matches!(error, SqlToolError::QueryRejected(_)));
+        });
+    }
+}
@@ -1,6 +1,20 @@
-//! The vector store tool accessses information from vector stores.
+//! The vector store tool searches a vector store for the documents similar to a query.
 //!
-//! Use it to give your LLM memory or access to semantically searchable information.
+//! Use it to give your LLM memory or access to semantically searchable information. Unlike retrieval-augmented
+//! chains, which retrieve documents before every call and add them to the prompt, an agent given this tool decides
+//! when to search, what to search for, and how many documents it needs, as in `search_docs(query, k)`.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let tool = VectorStoreTool::new(store, "the product manuals", "how to install and repair our products")
+//!     .with_default_k(3)
+//!     .with_max_k(8)
+//!     .with_score_threshold(0.7);
+//! let mut tools = ToolCollection::new();
+//! tools.add_tool(tool);
+//! let agent = ReActAgent::new(executor, tools);
+//! ```
 use std::marker::PhantomData;
 
 use async_trait::async_trait;
@@ -12,6 +26,12 @@ use crate::{
     traits::{Embeddings, EmbeddingsError, VectorStore, VectorStoreError},
 };
 
+/// The number of documents returned when the model doesn't ask for a number.
+const DEFAULT_K: u32 = 4;
+
+/// The largest number of documents the model can ask for.
+const DEFAULT_MAX_K: u32 = 10;
+
 pub struct VectorStoreTool<E, M, V>
 where
     E: Embeddings,
@@ -21,6 +41,10 @@ where
     pub store: V,
     pub topic: String,
     pub topic_context: String,
+    name: String,
+    default_k: u32,
+    max_k: u32,
+    score_threshold: Option<f32>,
     _data1: PhantomData<E>,
     _data2: PhantomData<M>,
 }
@@ -31,15 +55,47 @@ where
     M: Serialize + DeserializeOwned,
     V: VectorStore<E, M>,
 {
+    /// Creates a tool searching `store`, which holds documents about `topic`, to be used for questions about
+    /// `topic_context`.
     pub fn new(store: V, topic: &str, topic_context: &str) -> Self {
         Self {
             store,
             topic: topic.to_string(),
             topic_context: topic_context.to_string(),
+            name: "search_docs".to_string(),
+            default_k: DEFAULT_K,
+            max_k: DEFAULT_MAX_K,
+            score_threshold: None,
             _data1: Default::default(),
             _data2: Default::default(),
         }
     }
+
+    /// Sets the name the model calls the tool by, such as `search_manuals`, to tell several stores apart. Defaults
+    /// to `search_docs`.
+    pub fn with_name<S: Into<String>>(mut self, name: S) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Sets the number of documents returned when the model doesn't give `k`. Defaults to 4.
+    pub fn with_default_k(mut self, k: u32) -> Self {
+        self.default_k = k;
+        self
+    }
+
+    /// Sets the largest number of documents returned, whatever `k` the model asks for. Defaults to 10.
+    pub fn with_max_k(mut self, max_k: u32) -> Self {
+        self.max_k = max_k;
+        self
+    }
+
+    /// Leaves out the documents scoring below `threshold`, so that the model can tell when nothing relevant is
+    /// stored.
+    pub fn with_score_threshold(mut self, threshold: f32) -> Self {
+        self.score_threshold = Some(threshold);
+        self
+    }
 }
 
 #[derive(Debug, Error)]
@@ -65,13 +121,28 @@ where
 
 #[derive(Serialize, Deserialize)]
 pub struct VectorStoreToolInput {
-    query: String,
-    limit: u32,
+    pub query: String,
+    /// The number of documents to return, `limit` in earlier versions of the tool.
+    #[serde(default, alias = "limit", skip_serializing_if = "Option::is_none")]
+    pub k: Option<u32>,
+}
+
+/// A document found by the vector store tool.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FoundDocument {
+    pub text: String,
+    /// The similarity of the document to the query; higher is more similar.
+    pub score: f32,
+    /// Where the document comes from, such as a path or URL, when it is known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_yaml::Value>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct VectorStoreToolOutput {
-    texts: Vec<String>,
+    pub documents: Vec<FoundDocument>,
 }
 
 impl Describe for VectorStoreToolInput {
@@ -79,14 +150,10 @@ impl Describe for VectorStoreToolInput {
         vec![
             (
                 "query",
-                "You can search for texts similar to this one in the vector database.",
-            )
-                .into(),
-            (
-                "limit",
-                "The number of texts that will be returned from the vector database.",
+                "What to search for; documents similar to it are returned.",
             )
                 .into(),
+            ("k", "The number of documents to return, optional.").into(),
         ]
         .into()
     }
@@ -94,14 +161,11 @@ impl Describe for VectorStoreToolInput {
 
 impl Describe for VectorStoreToolOutput {
     fn describe() -> Format {
-        vec![
-            ("texts", "List of texts similar to the query.").into(),
-            (
-                "error_msg",
-                "Error message received when trying to search in the vector database.",
-            )
-                .into(),
-        ]
+        vec![(
+            "documents",
+            "The documents most similar to the query, most similar first, with their text, score and source.",
+        )
+            .into()]
         .into()
     }
 }
@@ -119,31 +183,88 @@ where
     type Error = VectorStoreToolError<<V as VectorStore<E, M>>::Error, <E as Embeddings>::Error>;
 
     async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
-        match self
+        let k = input
+            .k
+            .unwrap_or(self.default_k)
+            .clamp(1, self.max_k.max(1));
+        let found = self
             .store
-            .similarity_search(input.query.clone(), input.limit)
-            .await
-        {
-            Ok(o) => Ok(VectorStoreToolOutput {
-                texts: o.into_iter().map(|d| d.page_content).collect(),
-            }),
-            Err(e) => Err(<<V as VectorStore<E, M>>::Error as Into<Self::Error>>::into(e)),
-        }
+            .similarity_search_with_scores(input.query.clone(), k, self.score_threshold)
+            .await?;
+        let documents = found
+            .into_iter()
+            .map(|scored| {
+                let document = scored.document;
+                Ok(FoundDocument {
+                    text: document.page_content,
+                    score: scored.score,
+                    source: document.provenance.map(|provenance| provenance.source_id),
+                    metadata: document
+                        .metadata
+                        .map(|metadata| serde_yaml::to_value(metadata))
+                        .transpose()?,
+                })
+            })
+            .collect::<Result<_, serde_yaml::Error>>()?;
+        Ok(VectorStoreToolOutput { documents })
     }
 
     fn description(&self) -> crate::tools::ToolDescription {
         ToolDescription::new(
-            "VectorStoreTool",
-            "A tool that retrieves documents based on similarity to a given query.",
+            &self.name,
+            &format!(
+                "Searches documents about {} for the ones most similar to a query.",
+                self.topic
+            ),
             &format!(
-                "Useful for when you need to answer questions about {}. 
-            Whenever you need information about {} 
-            you should ALWAYS use this. 
-            Input should be a fully formed question.",
-                self.topic, self.topic_context
+                "Useful for when you need information about {}. Search with a fully formed question or the keywords you \
+                 expect in the documents, and ask for more documents with `k`, up to {}, when the first ones aren't \
+                 enough.",
+                self.topic_context, self.max_k
             ),
             Self::Input::describe(),
             Self::Output::describe(),
         )
     }
+
+    fn matches(&self, name: &str) -> bool {
+        name == self.name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::schema::{Document, Provenance};
+    use crate::test_support::LetterEmbeddings;
+    use crate::vectorstores::InMemoryVectorStore;
+
+    #[test]
+    fn searches_with_k_and_threshold() {
+        let store = InMemoryVectorStore::<_, serde_json::Value>::new(LetterEmbeddings);
+        block_on(async {
+            store
+                .add_documents(vec![
+                    Document::new("aaa".to_string())
+                        .with_provenance(Provenance::new("a.txt", 3))
+                        .with_metadata(serde_json::json!({ "lang": "a" })),
+                    Document::new("aab".to_string()),
+                    Document::new("bbb".to_string()),
+                ])
+                .await
+                .unwrap();
+            let tool = VectorStoreTool::new(store, "letters", "letters")
+                .with_max_k(2)
+                .with_score_threshold(0.5);
+            assert!(tool.matches("search_docs"));
+            let input = serde_yaml::from_str("{query: a, limit: 5}").unwrap();
+            let output = tool.invoke_typed(&input).await.unwrap();
+            let texts: Vec<_> = output.documents.iter().map(|d| d.text.as_str()).collect();
+            assert_eq!(texts, ["aaa", "aab"]);
+            assert_eq!(output.documents[0].source.as_deref(), Some("a.txt"));
+            assert_eq!(output.documents[0].metadata.as_ref().unwrap()["lang"], "a");
+        });
+    }
 }
@@ -0,0 +1,406 @@
+//! The WASM interpreter tool runs code written by the model with an interpreter compiled to WebAssembly, such as a
+//! WASI build of CPython or QuickJS, in a [wasmtime](https://wasmtime.dev) sandbox.
+//!
+//! Unlike the `PythonTool` and `BashTool`, the code can't reach anything the tool doesn't give it: it sees no
+//! environment variables, no network, and no files but the directories given with
+//! [`WasmInterpreterTool::with_read_only_dir`], such as the standard library of the interpreter. Each run gets a time
+//! limit, a memory limit, optionally a limit on the instructions executed, and caps on its stdout and stderr.
+//!
+//! The code is passed to the interpreter in place of a `{code}` argument, as in `["python", "-c", "{code}"]`, or on
+//! stdin when no argument is `{code}`.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use llm_chain::tools::tools::WasmInterpreterTool;
+//!
+//! let tool = WasmInterpreterTool::from_file("python-3.12.0.wasm")
+//!     .unwrap()
+//!     .with_language("Python")
+//!     .with_args(["python", "-c", "{code}"])
+//!     .with_read_only_dir("python-3.12.0/lib", "/usr/local/lib");
+//! ```
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use wasmtime::{Config, Engine, Linker, Module, Store, StoreLimits, StoreLimitsBuilder, Trap};
+use wasmtime_wasi::pipe::{MemoryInputPipe, MemoryOutputPipe};
+use wasmtime_wasi::preview1::{self, WasiP1Ctx};
+use wasmtime_wasi::{DirPerms, FilePerms, I32Exit, WasiCtxBuilder};
+
+use crate::tools::{Describe, Format, Tool, ToolDescription, ToolError};
+
+/// The default time a program can run for.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The default limit on the memory of a program.
+const DEFAULT_MAX_MEMORY_BYTES: usize = 256 * 1024 * 1024;
+
+/// The default limit on the bytes of each of stdout and stderr.
+const DEFAULT_MAX_OUTPUT_BYTES: usize = 64 * 1024;
+
+/// How often the epoch of the engines advances. Timeouts are counted in epochs, so they are rounded up to it.
+const EPOCH_PERIOD: Duration = Duration::from_millis(10);
+
+/// The argument replaced by the code to run.
+const CODE_ARGUMENT: &str = "{code}";
+
+struct State {
+    wasi: WasiP1Ctx,
+    limits: StoreLimits,
+}
+
+pub struct WasmInterpreterTool {
+    engine: Engine,
+    module: Module,
+    language: String,
+    args: Vec<String>,
+    dirs: Vec<(PathBuf, String)>,
+    timeout: Duration,
+    fuel: Option<u64>,
+    max_memory_bytes: usize,
+    max_output_bytes: usize,
+}
+
+impl WasmInterpreterTool {
+    /// Creates a tool running code with the interpreter compiled to the WASI module `module`, in the binary format.
+    pub fn new(module: &[u8]) -> Result<Self, WasmInterpreterToolError> {
+        let mut config = Config::new();
+        config.consume_fuel(true).epoch_interruption(true);
+        let engine = Engine::new(&config)?;
+        let module = Module::new(&engine, module)?;
+        // Advances the epoch for as long as the engine lives. Each run has its own deadline, a number of epochs after
+        // its start, so that concurrent runs don't interrupt each other.
+        let weak = engine.weak();
+        thread::spawn(move || loop {
+            thread::sleep(EPOCH_PERIOD);
+            match weak.upgrade() {
+                Some(engine) => engine.increment_epoch(),
+                None => break,
+            }
+        });
+        Ok(Self {
+            engine,
+            module,
+            language: "code".to_string(),
+            args: Vec::new(),
+            dirs: Vec::new(),
+            timeout: DEFAULT_TIMEOUT,
+            fuel: None,
+            max_memory_bytes: DEFAULT_MAX_MEMORY_BYTES,
+            max_output_bytes: DEFAULT_MAX_OUTPUT_BYTES,
+        })
+    }
+
+    /// Creates a tool running code with the interpreter compiled to the WASI module at `path`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, WasmInterpreterToolError> {
+        Self::new(&std::fs::read(path)?)
+    }
+
+    /// Sets the language of the code, as told to the model. Defaults to `code`.
+    pub fn with_language<S: Into<String>>(mut self, language: S) -> Self {
+        self.language = language.into();
+        self
+    }
+
+    /// Sets the arguments of the interpreter, starting with its name. An argument `{code}` is replaced by the code;
+    /// without one, the code is given on stdin.
+    pub fn with_args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Lets the programs read the directory `host_dir`, seen as `guest_dir`, such as the standard library of the
+    /// interpreter.
+    pub fn with_read_only_dir<P: Into<PathBuf>, S: Into<String>>(
+        mut self,
+        host_dir: P,
+        guest_dir: S,
+    ) -> Self {
+        self.dirs.push((host_dir.into(), guest_dir.into()));
+        self
+    }
+
+    /// Sets how long a program can run before it is stopped. Defaults to 10 seconds.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Limits the work of a program to `fuel` units, roughly one per WebAssembly instruction, making runs stop at
+    /// the same point whatever the load of the machine. Unlimited by default.
+    pub fn with_fuel(mut self, fuel: u64) -> Self {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    /// Sets the maximum memory of a program; allocations beyond it fail. Defaults to 256 MiB.
+    pub fn with_max_memory_bytes(mut self, max_memory_bytes: usize) -> Self {
+        self.max_memory_bytes = max_memory_bytes;
+        self
+    }
+
+    /// Sets the maximum number of bytes of each of stdout and stderr. Writes beyond it fail, which usually stops the
+    /// program. Defaults to 64 KiB.
+    pub fn with_max_output_bytes(mut self, max_output_bytes: usize) -> Self {
+        self.max_output_bytes = max_output_bytes;
+        self
+    }
+
+    /// Runs `code` to completion, blocking the current thread.
+    pub fn run(&self, code: &str) -> Result<WasmInterpreterToolOutput, WasmInterpreterToolError> {
+        let stdout = MemoryOutputPipe::new(self.max_output_bytes);
+        let stderr = MemoryOutputPipe::new(self.max_output_bytes);
+        let mut wasi = WasiCtxBuilder::new();
+        wasi.stdout(stdout.clone()).stderr(stderr.clone());
+        if self.args.iter().any(|arg| arg == CODE_ARGUMENT) {
+            let args: Vec<&str> = self
+                .args
+                .iter()
+                .map(|arg| if arg == CODE_ARGUMENT { code } else { arg })
+                .collect();
+            wasi.args(&args);
+        } else {
+            wasi.args(&self.args)
+                .stdin(MemoryInputPipe::new(code.to_string()));
+        }
+        for (host_dir, guest_dir) in &self.dirs {
+            wasi.preopened_dir(host_dir, guest_dir, DirPerms::READ, FilePerms::READ)
+                .map_err(WasmInterpreterToolError::Wasm)?;
+        }
+        let state = State {
+            wasi: wasi.build_p1(),
+            limits: StoreLimitsBuilder::new()
+                .memory_size(self.max_memory_bytes)
+                .instances(1)
+                .build(),
+        };
+        let mut store = Store::new(&self.engine, state);
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(self.fuel.unwrap_or(u64::MAX))?;
+        let period = EPOCH_PERIOD.as_nanos();
+        let epochs = self.timeout.as_nanos().div_ceil(period);
+        store.set_epoch_deadline(epochs.clamp(1, u64::MAX as u128) as u64);
+
+        let mut linker = Linker::new(&self.engine);
+        preview1::add_to_linker_sync(&mut linker, |state: &mut State| &mut state.wasi)?;
+        let instance = linker.instantiate(&mut store, &self.module)?;
+        let start = instance.get_typed_func::<(), ()>(&mut store, "_start")?;
+
+        let result = start.call(&mut store, ());
+
+        let exit_code = match result {
+            Ok(()) => 0,
+            Err(error) => match (error.downcast_ref::<I32Exit>(), error.downcast_ref()) {
+                (Some(exit), _) => exit.0,
+                (_, Some(Trap::Interrupt)) => {
+                    return Err(WasmInterpreterToolError::Timeout(self.timeout))
+                }
+                (_, Some(Trap::OutOfFuel)) => return Err(WasmInterpreterToolError::OutOfFuel),
+                _ => return Err(WasmInterpreterToolError::Wasm(error)),
+            },
+        };
+        let text = |pipe: &MemoryOutputPipe| String::from_utf8_lossy(&pipe.contents()).into_owned();
+        Ok(WasmInterpreterToolOutput {
+            exit_code,
+            stdout: text(&stdout),
+            stderr: text(&stderr),
+            truncated: [&stdout, &stderr]
+                .iter()
+                .any(|pipe| pipe.contents().len() >= self.max_output_bytes),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WasmInterpreterToolInput {
+    pub code: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WasmInterpreterToolOutput {
+    pub exit_code: i32,
+    pub stdout: String,
+    pub stderr: String,
+    /// Whether stdout or stderr reached the limit, and later writes were lost.
+    pub truncated: bool,
+}
+
+impl Describe for WasmInterpreterToolInput {
+    fn describe() -> Format {
+        vec![("code", "The code to run").into()].into()
+    }
+}
+
+impl Describe for WasmInterpreterToolOutput {
+    fn describe() -> Format {
+        vec![
+            ("exit_code", "The exit code, 0 for success").into(),
+            ("stdout", "The stdout output of the code").into(),
+            ("stderr", "The stderr output of the code").into(),
+            ("truncated", "true if the output was cut short").into(),
+        ]
+        .into()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum WasmInterpreterToolError {
+    #[error(transparent)]
+    Yaml(#[from] serde_yaml::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Wasm(#[from] anyhow::Error),
+    #[error("The code didn't finish within {0:?} and was stopped")]
+    Timeout(Duration),
+    #[error("The code ran out of fuel and was stopped")]
+    OutOfFuel,
+    #[error("The code was stopped")]
+    Canceled,
+}
+
+impl ToolError for WasmInterpreterToolError {}
+
+#[async_trait]
+impl Tool for WasmInterpreterTool {
+    type Input = WasmInterpreterToolInput;
+    type Output = WasmInterpreterToolOutput;
+    type Error = WasmInterpreterToolError;
+
+    /// Runs the code on a thread of its own, leaving the executor free in the meantime.
+    async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+        let tool = Self {
+            engine: self.engine.clone(),
+            module: self.module.clone(),
+            language: self.language.clone(),
+            args: self.args.clone(),
+            dirs: self.dirs.clone(),
+            ..*self
+        };
+        let code = input.code.clone();
+        let (sender, receiver) = futures::channel::oneshot::channel();
+        thread::spawn(move || sender.send(tool.run(&code)));
+        receiver
+            .await
+            .map_err(|_| WasmInterpreterToolError::Canceled)?
+    }
+
+    fn description(&self) -> ToolDescription {
+        ToolDescription::new(
+            "code_interpreter",
+            &format!(
+                "Runs {} in a sandbox and returns its output.",
+                self.language
+            ),
+            &format!(
+                "Use this to compute, transform data or check your reasoning by running {}. Print the results you \
+                 need. The code has no network or file access, and runs for at most {} seconds.",
+                self.language,
+                self.timeout.as_secs_f32()
+            ),
+            WasmInterpreterToolInput::describe(),
+            WasmInterpreterToolOutput::describe(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A WASI program copying stdin to stdout, then exiting with the code 3.
+    const CAT: &str = r#"
+        (module
+          (import "wasi_snapshot_preview1" "fd_read" (func $read (param i32 i32 i32 i32) (result i32)))
+          (import "wasi_snapshot_preview1" "fd_write" (func $write (param i32 i32 i32 i32) (result i32)))
+          (import "wasi_snapshot_preview1" "proc_exit" (func $exit (param i32)))
+          (memory (export "memory") 1)
+          (func (export "_start")
+            (i32.store (i32.const 0) (i32.const 64))
+            (i32.store (i32.const 4) (i32.const 1024))
+            (block $done
+              (loop $copy
+                (drop (call $read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+                (br_if $done (i32.eqz (i32.load (i32.const 8))))
+                (i32.store (i32.const 16) (i32.const 64))
+                (i32.store (i32.const 20) (i32.load (i32.const 8)))
+                (drop (call $write (i32.const 1) (i32.const 16) (i32.const 1) (i32.const 24)))
+                (br $copy)))
+            (call $exit (i32.const 3))))
+    "#;
+
+    const SPIN: &str = r#"(module (func (export "_start") (loop $spin (br $spin))))"#;
+
+    /// A WASI program spinning forever when stdin starts with `s`, and otherwise sleeping for 700 ms then exiting.
+    const SPIN_OR_SLEEP: &str = r#"
+        (module
+          (import "wasi_snapshot_preview1" "fd_read" (func $read (param i32 i32 i32 i32) (result i32)))
+          (import "wasi_snapshot_preview1" "poll_oneoff" (func $poll (param i32 i32 i32 i32) (result i32)))
+          (memory (export "memory") 1)
+          (func (export "_start")
+            (i32.store (i32.const 0) (i32.const 64))
+            (i32.store (i32.const 4) (i32.const 16))
+            (drop (call $read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+            (if (i32.eq (i32.load8_u (i32.const 64)) (i32.const 115))
+              (then (loop $spin (br $spin))))
+            ;; A subscription to the monotonic clock, 700 ms from now.
+            (i32.store8 (i32.const 136) (i32.const 0))
+            (i32.store (i32.const 144) (i32.const 1))
+            (i64.store (i32.const 152) (i64.const 700000000))
+            (drop (call $poll (i32.const 128) (i32.const 256) (i32.const 1) (i32.const 320)))
+            (loop $count (br_if $count (i32.eqz (i32.const 1))))))
+    "#;
+
+    #[test]
+    fn runs_code_with_limits() {
+        let tool = WasmInterpreterTool::new(&wat::parse_str(CAT).unwrap()).unwrap();
+        let output = futures::executor::block_on(tool.invoke_typed(&WasmInterpreterToolInput {
+            code: "print(1)".to_string(),
+        }))
+        .unwrap();
+        assert_eq!(output.stdout, "print(1)");
+        assert_eq!(output.exit_code, 3);
+        assert!(!output.truncated);
+
+        let spin = wat::parse_str(SPIN).unwrap();
+        let tool = WasmInterpreterTool::new(&spin)
+            .unwrap()
+            .with_timeout(Duration::from_millis(100));
+        assert!(matches!(
+            tool.run(""),
+            Err(WasmInterpreterToolError::Timeout(_))
+        ));
+        let tool = WasmInterpreterTool::new(&spin).unwrap().with_fuel(10_000);
+        assert!(matches!(
+            tool.run(""),
+            Err(WasmInterpreterToolError::OutOfFuel)
+        ));
+    }
+
+    #[test]
+    fn times_out_runs_independently() {
+        let tool = WasmInterpreterTool::new(&wat::parse_str(SPIN_OR_SLEEP).unwrap())
+            .unwrap()
+            .with_timeout(Duration::from_secs(1));
+        thread::scope(|scope| {
+            let spinning = scope.spawn(|| tool.run("s"));
+            // Sleeps across the timeout of the spinning run, finishing within its own.
+            thread::sleep(Duration::from_millis(500));
+            let sleeping = scope.spawn(|| tool.run(""));
+            assert!(matches!(
+                spinning.join().unwrap(),
+                Err(WasmInterpreterToolError::Timeout(_))
+            ));
+            assert_eq!(sleeping.join().unwrap().unwrap().exit_code, 0);
+        });
+    }
+}
@@ -0,0 +1,423 @@
+//! The web search tool searches the web with one of several search APIs, and returns the title, URL and snippet of
+//! each result, so that agents can read the pages they need and cite them.
+//!
+//! The search API is a [`SearchBackend`]. The tool comes with backends for [Tavily](https://tavily.com),
+//! [Brave Search](https://brave.com/search/api/), [SerpApi](https://serpapi.com), and the
+//! [SearXNG](https://docs.searxng.org) metasearch engine, which can be self-hosted and needs no key.
+//!
+//! # Example
+//!
+//! ```
+//! use llm_chain::tools::tools::{Brave, SearXng, WebSearchTool};
+//! use llm_chain::tools::ToolCollection;
+//!
+//! let mut tools = ToolCollection::new();
+//! tools.add_tool(WebSearchTool::new(Brave::new("brave-api-key")).with_max_results(3));
+//! let self_hosted = WebSearchTool::new(SearXng::new("http://localhost:8080"));
+//! ```
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::tools::{Describe, Format, Tool, ToolDescription, ToolError};
+
+/// The default number of results returned.
+const DEFAULT_MAX_RESULTS: usize = 5;
+
+/// A result of a web search.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchResult {
+    pub title: String,
+    pub url: String,
+    /// An excerpt of the page matching the query, as plain text.
+    pub snippet: String,
+}
+
+/// The error returned by search backends.
+pub type SearchBackendError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A search API used by the [`WebSearchTool`].
+#[async_trait]
+pub trait SearchBackend: Send + Sync {
+    /// Searches the web for `query`, returning at most `max_results` results, best first.
+    async fn search(
+        &self,
+        query: &str,
+        max_results: usize,
+    ) -> Result<Vec<SearchResult>, SearchBackendError>;
+}
+
+#[async_trait]
+impl SearchBackend for Box<dyn SearchBackend> {
+    async fn search(
+        &self,
+        query: &str,
+        max_results: usize,
+    ) -> Result<Vec<SearchResult>, SearchBackendError> {
+        self.as_ref().search(query, max_results).await
+    }
+}
+
+lazy_static! {
+    static ref TAG: Regex = Regex::new(r"<[^>]*>").unwrap();
+}
+
+/// Turns a snippet, in which some APIs highlight the query with HTML, into plain text.
+fn plain_text(snippet: &str) -> String {
+    TAG.replace_all(snippet, "")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+        .trim()
+        .to_string()
+}
+
+/// Reads the array `results` of a response, whose objects have a `title`, and their URL and snippet under the keys
+/// `url` and `snippet`. Results without a URL are skipped.
+fn parse_results(
+    results: &Value,
+    url: &str,
+    snippet: &str,
+    max_results: usize,
+) -> Vec<SearchResult> {
+    let field = |result: &Value, key: &str| result[key].as_str().unwrap_or_default().to_string();
+    results
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|result| result[url].is_string())
+        .take(max_results)
+        .map(|result| SearchResult {
+            title: plain_text(&field(result, "title")),
+            url: field(result, url),
+            snippet: plain_text(&field(result, snippet)),
+        })
+        .collect()
+}
+
+/// Searches with the [Tavily](https://tavily.com) API, made for agents.
+pub struct Tavily {
+    api_key: String,
+    client: reqwest::Client,
+}
+
+impl Tavily {
+    pub fn new<S: Into<String>>(api_key: S) -> Self {
+        Self {
+            api_key: api_key.into(),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl SearchBackend for Tavily {
+    async fn search(
+        &self,
+        query: &str,
+        max_results: usize,
+    ) -> Result<Vec<SearchResult>, SearchBackendError> {
+        let response: Value = self
+            .client
+            .post("https://api.tavily.com/search")
+            .bearer_auth(&self.api_key)
+            .json(&json!({ "query": query, "max_results": max_results }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(parse_results(
+            &response["results"],
+            "url",
+            "content",
+            max_results,
+        ))
+    }
+}
+
+/// Searches with the [Brave Search](https://brave.com/search/api/) API.
+pub struct Brave {
+    api_key: String,
+    client: reqwest::Client,
+}
+
+impl Brave {
+    pub fn new<S: Into<String>>(api_key: S) -> Self {
+        Self {
+            api_key: api_key.into(),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl SearchBackend for Brave {
+    async fn search(
+        &self,
+        query: &str,
+        max_results: usize,
+    ) -> Result<Vec<SearchResult>, SearchBackendError> {
+        let response: Value = self
+            .client
+            .get("https://api.search.brave.com/res/v1/web/search")
+            .query(&[("q", query), ("count", &max_results.to_string())])
+            .header("Accept", "application/json")
+            .header("X-Subscription-Token", &self.api_key)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(parse_results(
+            &response["web"]["results"],
+            "url",
+            "description",
+            max_results,
+        ))
+    }
+}
+
+/// Searches Google with [SerpApi](https://serpapi.com).
+pub struct SerpApi {
+    api_key: String,
+    engine: String,
+    client: reqwest::Client,
+}
+
+impl SerpApi {
+    pub fn new<S: Into<String>>(api_key: S) -> Self {
+        Self {
+            api_key: api_key.into(),
+            engine: "google".to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Sets the search engine queried by SerpApi, such as `bing` or `duckduckgo`. Defaults to `google`.
+    pub fn with_engine<S: Into<String>>(mut self, engine: S) -> Self {
+        self.engine = engine.into();
+        self
+    }
+}
+
+#[async_trait]
+impl SearchBackend for SerpApi {
+    async fn search(
+        &self,
+        query: &str,
+        max_results: usize,
+    ) -> Result<Vec<SearchResult>, SearchBackendError> {
+        let response: Value = self
+            .client
+            .get("https://serpapi.com/search.json")
+            .query(&[
+                ("engine", self.engine.as_str()),
+                ("q", query),
+                ("num", &max_results.to_string()),
+                ("api_key", &self.api_key),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(parse_results(
+            &response["organic_results"],
+            "link",
+            "snippet",
+            max_results,
+        ))
+    }
+}
+
+/// Searches with a [SearXNG](https://docs.searxng.org) instance, whose settings must enable the `json` format.
+pub struct SearXng {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl SearXng {
+    /// Creates a backend searching the instance at `base_url`, such as `http://localhost:8080`.
+    pub fn new<S: Into<String>>(base_url: S) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl SearchBackend for SearXng {
+    async fn search(
+        &self,
+        query: &str,
+        max_results: usize,
+    ) -> Result<Vec<SearchResult>, SearchBackendError> {
+        let response: Value = self
+            .client
+            .get(format!("{}/search", self.base_url))
+            .query(&[("q", query), ("format", "json")])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(parse_results(
+            &response["results"],
+            "url",
+            "content",
+            max_results,
+        ))
+    }
+}
+
+/// A tool searching the web with a [`SearchBackend`].
+pub struct WebSearchTool<B: SearchBackend> {
+    backend: B,
+    max_results: usize,
+}
+
+impl<B: SearchBackend> WebSearchTool<B> {
+    pub fn new(backend: B) -> Self {
+        Self {
+            backend,
+            max_results: DEFAULT_MAX_RESULTS,
+        }
+    }
+
+    /// Sets the number of results returned. Defaults to 5.
+    pub fn with_max_results(mut self, max_results: usize) -> Self {
+        self.max_results = max_results.max(1);
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WebSearchToolInput {
+    pub query: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WebSearchToolOutput {
+    pub results: Vec<SearchResult>,
+}
+
+impl Describe for WebSearchToolInput {
+    fn describe() -> Format {
+        vec![("query", "What to search the web for").into()].into()
+    }
+}
+
+impl Describe for WebSearchToolOutput {
+    fn describe() -> Format {
+        vec![(
+            "results",
+            "The results found, best first, with their title, url and snippet",
+        )
+            .into()]
+        .into()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum WebSearchToolError {
+    #[error(transparent)]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("The search failed: {0}")]
+    Backend(SearchBackendError),
+}
+
+impl ToolError for WebSearchToolError {}
+
+#[async_trait]
+impl<B: SearchBackend> Tool for WebSearchTool<B> {
+    type Input = WebSearchToolInput;
+    type Output = WebSearchToolOutput;
+    type Error = WebSearchToolError;
+
+    async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+        let results = self
+            .backend
+            .search(&input.query, self.max_results)
+            .await
+            .map_err(WebSearchToolError::Backend)?;
+        Ok(WebSearchToolOutput { results })
+    }
+
+    fn description(&self) -> ToolDescription {
+        ToolDescription::new(
+            "web_search",
+            "Searches the web, returning the title, url and a snippet of each result.",
+            "Use this to find current or specific information. Cite the url of the results you use.",
+            WebSearchToolInput::describe(),
+            WebSearchToolOutput::describe(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_results() {
+        let brave = json!({ "web": { "results": [
+            { "title": "Rust &amp; Cargo", "url": "https://rust-lang.org", "description": "A <strong>language</strong>" },
+            { "title": "No url" },
+            { "title": "Docs", "url": "https://docs.rs", "description": "Crates" },
+        ]}});
+        let results = parse_results(&brave["web"]["results"], "url", "description", 5);
+        assert_eq!(
+            results[0],
+            SearchResult {
+                title: "Rust & Cargo".to_string(),
+                url: "https://rust-lang.org".to_string(),
+                snippet: "A language".to_string(),
+            }
+        );
+        assert_eq!(results.len(), 2);
+        assert_eq!(
+            parse_results(&brave["web"]["results"], "url", "description", 1).len(),
+            1
+        );
+        assert!(parse_results(&json!({})["results"], "url", "content", 5).is_empty());
+    }
+
+    struct Fixed;
+
+    #[async_trait]
+    impl SearchBackend for Fixed {
+        async fn search(
+            &self,
+            query: &str,
+            max_results: usize,
+        ) -> Result<Vec<SearchResult>, SearchBackendError> {
+            let result = SearchResult {
+                title: query.to_string(),
+                url: "https://example.com".to_string(),
+                snippet: String::new(),
+            };
+            Ok(vec![result; max_results])
+        }
+    }
+
+    #[test]
+    fn searches_with_the_backend() {
+        let backend: Box<dyn SearchBackend> = Box::new(Fixed);
+        let tool = WebSearchTool::new(backend).with_max_results(2);
+        let output = futures::executor::block_on(tool.invoke_typed(&WebSearchToolInput {
+            query: "rust".to_string(),
+        }))
+        .unwrap();
+        assert_eq!(output.results.len(), 2);
+        assert_eq!(output.results[0].title, "rust");
+    }
+}
@@ -0,0 +1,265 @@
+//! The Wikipedia tool looks a topic up on Wikipedia, returning the summary and the first sections of the best
+//! matching article with its URL. It needs no key, which makes it a handy knowledge tool for demos and evaluations.
+//!
+//! # Example
+//!
+//! ```
+//! use llm_chain::tools::tools::WikipediaTool;
+//! use llm_chain::tools::ToolCollection;
+//!
+//! let mut tools = ToolCollection::new();
+//! tools.add_tool(WikipediaTool::new().with_language("de").with_max_sections(2));
+//! ```
+use async_trait::async_trait;
+use reqwest::header::USER_AGENT;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::tools::{Describe, Format, Tool, ToolDescription, ToolError};
+
+/// The default number of sections returned after the summary.
+const DEFAULT_MAX_SECTIONS: usize = 3;
+
+/// The default limit on the characters of the summary and of each section.
+const DEFAULT_MAX_SECTION_CHARS: usize = 2000;
+
+/// The sections listing references and links rather than telling about the topic.
+const SKIPPED_SECTIONS: &[&str] = &[
+    "See also",
+    "Notes",
+    "References",
+    "Sources",
+    "Bibliography",
+    "Further reading",
+    "External links",
+];
+
+pub struct WikipediaTool {
+    language: String,
+    max_sections: usize,
+    max_section_chars: usize,
+    user_agent: String,
+    client: reqwest::Client,
+}
+
+impl WikipediaTool {
+    /// Creates a tool looking topics up on the English Wikipedia.
+    pub fn new() -> Self {
+        Self {
+            language: "en".to_string(),
+            max_sections: DEFAULT_MAX_SECTIONS,
+            max_section_chars: DEFAULT_MAX_SECTION_CHARS,
+            user_agent: "llm-chain (https://github.com/sobelio/llm-chain)".to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Sets the language code of the Wikipedia to search, such as `fr`. Defaults to `en`.
+    pub fn with_language<S: Into<String>>(mut self, language: S) -> Self {
+        self.language = language.into();
+        self
+    }
+
+    /// Sets the number of sections returned after the summary. Defaults to 3.
+    pub fn with_max_sections(mut self, max_sections: usize) -> Self {
+        self.max_sections = max_sections;
+        self
+    }
+
+    /// Sets the maximum number of characters of the summary and of each section. Defaults to 2000.
+    pub fn with_max_section_chars(mut self, max_section_chars: usize) -> Self {
+        self.max_section_chars = max_section_chars;
+        self
+    }
+
+    /// Sets the user agent sent to Wikipedia, whose policy asks for a way to contact the author of the program.
+    pub fn with_user_agent<S: Into<String>>(mut self, user_agent: S) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+}
+
+impl Default for WikipediaTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A section of a Wikipedia article.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WikipediaSection {
+    pub title: String,
+    pub text: String,
+}
+
+/// Returns the first `max_chars` characters of `text`, ending with an ellipsis when it was cut.
+fn cut(text: &str, max_chars: usize) -> String {
+    let text = text.trim();
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}…", text[..end].trim_end()),
+        None => text.to_string(),
+    }
+}
+
+/// Splits the plain text of an article, whose headings are lines such as `== History ==`, into its introduction and
+/// its top-level sections. Subsections stay in the text of their section, under their heading.
+fn split_sections(extract: &str) -> (String, Vec<WikipediaSection>) {
+    let mut introduction = String::new();
+    let mut sections: Vec<WikipediaSection> = Vec::new();
+    for line in extract.lines() {
+        let trimmed = line.trim();
+        let is_top_heading = trimmed.starts_with("== ") && !trimmed.starts_with("===");
+        if is_top_heading && trimmed.ends_with(" ==") && trimmed.len() > 5 {
+            sections.push(WikipediaSection {
+                title: trimmed[3..trimmed.len() - 3].trim().to_string(),
+                text: String::new(),
+            });
+            continue;
+        }
+        let text = match sections.last_mut() {
+            Some(section) => &mut section.text,
+            None => &mut introduction,
+        };
+        let line = trimmed.trim_matches('=').trim();
+        if trimmed.starts_with("===") {
+            // A subsection heading.
+            text.push_str(&format!("{}:\n", line));
+        } else {
+            text.push_str(line);
+            text.push('\n');
+        }
+    }
+    (introduction, sections)
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WikipediaToolInput {
+    pub topic: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WikipediaToolOutput {
+    pub title: String,
+    pub url: String,
+    pub summary: String,
+    pub sections: Vec<WikipediaSection>,
+}
+
+impl Describe for WikipediaToolInput {
+    fn describe() -> Format {
+        vec![("topic", "The topic to look up, such as a name or a concept").into()].into()
+    }
+}
+
+impl Describe for WikipediaToolOutput {
+    fn describe() -> Format {
+        vec![
+            ("title", "The title of the article found").into(),
+            ("url", "The URL of the article").into(),
+            ("summary", "The introduction of the article").into(),
+            ("sections", "The first sections of the article").into(),
+        ]
+        .into()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum WikipediaToolError {
+    #[error(transparent)]
+    Yaml(#[from] serde_yaml::Error),
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+    #[error("No Wikipedia article was found about {0}")]
+    NotFound(String),
+}
+
+impl ToolError for WikipediaToolError {}
+
+#[async_trait]
+impl Tool for WikipediaTool {
+    type Input = WikipediaToolInput;
+    type Output = WikipediaToolOutput;
+    type Error = WikipediaToolError;
+
+    async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+        // Searches for the topic and gets the plain text and URL of the best match in one query.
+        let response: Value = self
+            .client
+            .get(format!("https://{}.wikipedia.org/w/api.php", self.language))
+            .header(USER_AGENT, &self.user_agent)
+            .query(&[
+                ("action", "query"),
+                ("format", "json"),
+                ("formatversion", "2"),
+                ("generator", "search"),
+                ("gsrsearch", input.topic.as_str()),
+                ("gsrlimit", "1"),
+                ("prop", "extracts|info"),
+                ("explaintext", "1"),
+                ("inprop", "url"),
+                ("redirects", "1"),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let page = &response["query"]["pages"][0];
+        let (Some(title), Some(extract)) = (page["title"].as_str(), page["extract"].as_str())
+        else {
+            return Err(WikipediaToolError::NotFound(input.topic.clone()));
+        };
+        let (introduction, sections) = split_sections(extract);
+        let sections = sections
+            .into_iter()
+            .filter(|section| {
+                !section.text.trim().is_empty()
+                    && !SKIPPED_SECTIONS.contains(&section.title.as_str())
+            })
+            .take(self.max_sections)
+            .map(|section| WikipediaSection {
+                text: cut(&section.text, self.max_section_chars),
+                ..section
+            })
+            .collect();
+        Ok(WikipediaToolOutput {
+            title: title.to_string(),
+            url: page["fullurl"].as_str().unwrap_or_default().to_string(),
+            summary: cut(&introduction, self.max_section_chars),
+            sections,
+        })
+    }
+
+    fn description(&self) -> ToolDescription {
+        ToolDescription::new(
+            "wikipedia",
+            "Looks a topic up on Wikipedia, returning the summary and first sections of the best matching article.",
+            "Use this for facts about people, places, events and concepts. Cite the url of the article.",
+            WikipediaToolInput::describe(),
+            WikipediaToolOutput::describe(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_articles_into_sections() {
+        let extract = "Rust is a language.\nIt is fast.\n\n== History ==\nStarted in 2006.\n\n=== Mozilla ===\n\
+                       Sponsored in 2009.\n\n== Syntax ==\nC-like.\n\n== References ==\n";
+        let (introduction, sections) = split_sections(extract);
+        assert_eq!(introduction.trim(), "Rust is a language.\nIt is fast.");
+        assert_eq!(sections.len(), 3);
+        assert_eq!(sections[0].title, "History");
+        assert_eq!(
+            sections[0].text.trim(),
+            "Started in 2006.\n\nMozilla:\nSponsored in 2009."
+        );
+        assert_eq!(sections[2].title, "References");
+        assert_eq!(cut("abcdef", 3), "abc…");
+        assert_eq!(cut("abc", 3), "abc");
+    }
+}
@@ -0,0 +1,205 @@
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use super::description::{Format, ToolDefinition, ToolDescription};
+use super::tool::{Tool, ToolError};
+use crate::json_schema::{JsonSchema, SchemaValidationError};
+
+/// A tool whose input and output are Rust types deriving `schemars::JsonSchema`.
+///
+/// Every `TypedTool` is a `Tool`: its description and definition are generated from the schemas of the types, with
+/// the doc comments of their fields as the purposes of the parameters, and the arguments given by the model are
+/// validated against the schema of the input before being deserialized. Arguments that don't match the schema fail
+/// with `TypedToolError::InvalidInput`, listing every violation so that the model can correct the call.
+///
+/// # Examples
+///
+/// ```
+/// use async_trait::async_trait;
+/// use llm_chain::tools::{Tool, TypedTool};
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Deserialize, schemars::JsonSchema)]
+/// struct WeatherInput {
+///     /// The city to get the weather of.
+///     city: String,
+///     /// The number of days to forecast, at most 3.
+///     #[schemars(range(min = 1, max = 3))]
+///     days: u8,
+/// }
+///
+/// #[derive(Serialize, schemars::JsonSchema)]
+/// struct WeatherOutput {
+///     /// The forecast of each day.
+///     forecast: Vec<String>,
+/// }
+///
+/// struct Weather;
+///
+/// #[async_trait]
+/// impl TypedTool for Weather {
+///     const NAME: &'static str = "weather";
+///     const DESCRIPTION: &'static str = "Forecasts the weather of a city";
+///     type Input = WeatherInput;
+///     type Output = WeatherOutput;
+///     type Error = std::io::Error;
+///
+///     async fn call(&self, input: &WeatherInput) -> Result<WeatherOutput, std::io::Error> {
+///         Ok(WeatherOutput { forecast: vec![format!("Sunny in {}", input.city); input.days.into()] })
+///     }
+/// }
+///
+/// let definition = Weather.definition();
+/// assert_eq!(definition.parameters["required"], serde_json::json!(["city", "days"]));
+/// # futures::executor::block_on(async {
+/// let output = Weather.invoke(serde_yaml::from_str("{city: Oslo, days: 1}").unwrap()).await.unwrap();
+/// assert_eq!(output["forecast"][0], "Sunny in Oslo");
+/// let error = Weather.invoke(serde_yaml::from_str("{city: Oslo, days: 7}").unwrap()).await.unwrap_err();
+/// assert!(error.to_string().contains("/days"));
+/// # });
+/// ```
+#[async_trait]
+pub trait TypedTool: Send + Sync {
+    /// The name the model calls the tool by.
+    const NAME: &'static str;
+    /// What the tool does, for the model to know when to call it.
+    const DESCRIPTION: &'static str;
+
+    type Input: DeserializeOwned + schemars::JsonSchema + Send + Sync;
+    type Output: Serialize + schemars::JsonSchema;
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Calls the tool with arguments matching the schema of its input.
+    async fn call(&self, input: &Self::Input) -> Result<Self::Output, Self::Error>;
+}
+
+/// The error returned by the `Tool` implementation of typed tools.
+#[derive(Debug, Error)]
+pub enum TypedToolError<E: std::error::Error> {
+    #[error("The arguments don't match the input schema of the tool: {0}")]
+    InvalidInput(#[from] SchemaValidationError),
+    #[error("The arguments can't be deserialized: {0}")]
+    InvalidArguments(#[from] serde_json::Error),
+    #[error("Invalid format: {0}")]
+    InvalidFormat(#[from] serde_yaml::Error),
+    #[error(transparent)]
+    Tool(E),
+}
+
+impl<E: std::error::Error> ToolError for TypedToolError<E> {}
+
+/// Returns the schema of `T`, without the `$schema` keyword, which APIs advertising tools don't expect.
+fn schema_of<T: schemars::JsonSchema>() -> JsonSchema {
+    let schema = JsonSchema::for_type::<T>();
+    let mut value = schema.schema().clone();
+    if let Some(object) = value.as_object_mut() {
+        object.remove("$schema");
+    }
+    JsonSchema::new(schema.name(), value)
+}
+
+#[async_trait]
+impl<T: TypedTool> Tool for T {
+    type Input = T::Input;
+    type Output = T::Output;
+    type Error = TypedToolError<T::Error>;
+
+    async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+        self.call(input).await.map_err(TypedToolError::Tool)
+    }
+
+    fn description(&self) -> ToolDescription {
+        ToolDescription::new(
+            T::NAME,
+            T::DESCRIPTION,
+            "",
+            Format::from_json_schema(schema_of::<T::Input>().schema()),
+            Format::from_json_schema(schema_of::<T::Output>().schema()),
+        )
+    }
+
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: T::NAME.to_string(),
+            description: T::DESCRIPTION.to_string(),
+            parameters: schema_of::<T::Input>().schema().clone(),
+        }
+    }
+
+    /// Validates `input` against the schema of the input of the tool before calling it. A missing input is taken as
+    /// an empty object, for tools without arguments.
+    async fn invoke(&self, input: serde_yaml::Value) -> Result<serde_yaml::Value, Self::Error> {
+        let input = match serde_json::to_value(input)? {
+            Value::Null => Value::Object(Default::default()),
+            input => input,
+        };
+        schema_of::<T::Input>().validate(&input)?;
+        let input = serde_json::from_value(input)?;
+        let output = self.invoke_typed(&input).await?;
+        Ok(serde_yaml::to_value(output)?)
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        name == T::NAME
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::output::ToolCall;
+    use crate::tools::{ToolCollection, ToolUseError};
+
+    #[derive(Deserialize, schemars::JsonSchema)]
+    struct Input {
+        /// The words to join.
+        words: Vec<String>,
+        separator: Option<String>,
+    }
+
+    struct Join;
+
+    #[async_trait]
+    impl TypedTool for Join {
+        const NAME: &'static str = "join";
+        const DESCRIPTION: &'static str = "Joins words";
+        type Input = Input;
+        type Output = String;
+        type Error = std::fmt::Error;
+
+        async fn call(&self, input: &Input) -> Result<String, std::fmt::Error> {
+            Ok(input.words.join(input.separator.as_deref().unwrap_or(" ")))
+        }
+    }
+
+    #[test]
+    fn validates_and_deserializes_arguments() {
+        let mut tools = ToolCollection::new();
+        tools.add_tool(Join);
+        let definition = &tools.definitions()[0];
+        assert_eq!(
+            definition.parameters["required"],
+            serde_json::json!(["words"])
+        );
+        assert!(definition.parameters.get("$schema").is_none());
+        let description = Join.description();
+        assert_eq!(
+            description.input_format.parts[1].purpose,
+            "The words to join."
+        );
+
+        let call = ToolCall::new("join", r#"{"words": ["a", "b"], "separator": "-"}"#);
+        let output = futures::executor::block_on(tools.invoke_call(&call)).unwrap();
+        assert_eq!(output, "a-b");
+        let call = ToolCall::new("join", r#"{"words": "a b"}"#);
+        let error = futures::executor::block_on(tools.invoke_call(&call)).unwrap_err();
+        assert!(matches!(
+            error,
+            ToolUseError::ToolError(TypedToolError::InvalidInput(_))
+        ));
+    }
+}
@@ -12,13 +12,16 @@
 use std::{error::Error, fmt::Debug};
 
 use crate::{
-    output::Output,
+    json_schema::JsonSchema,
+    output::{Output, OutputStream, StreamChunk},
     prompt::Prompt,
-    schema::{Document, EmptyMetadata},
+    schema::{Document, EmptyMetadata, MetadataFilter, ScoredDocument},
     tokens::{PromptTokensError, TokenCount, Tokenizer, TokenizerError},
+    tools::ToolDefinition,
     TextSplitter,
 };
 use async_trait::async_trait;
+use futures::stream::{self, StreamExt};
 use serde::{de::DeserializeOwned, Serialize};
 
 #[derive(thiserror::Error, Debug)]
@@ -81,6 +84,28 @@ pub trait Executor: Sized {
         is_streaming: Option<bool>,
     ) -> Result<Self::Output, Self::Error>;
 
+    /// Executes `prompt`, streaming the output as it is generated, so that interactive applications can show it
+    /// incrementally.
+    ///
+    /// The default implementation executes the prompt with `execute` and yields the whole output as a single chunk,
+    /// so every executor can be streamed; executors whose model can stream override it.
+    fn execute_stream<'a>(
+        &'a self,
+        options: Option<&Self::PerInvocationOptions>,
+        prompt: &Prompt,
+    ) -> OutputStream<'a, Self::Error>
+    where
+        Self: Sync,
+    {
+        let options = options.cloned();
+        let prompt = prompt.clone();
+        stream::once(async move {
+            let output = self.execute(options.as_ref(), &prompt, Some(true)).await?;
+            Ok(StreamChunk::from_output(&output).await)
+        })
+        .boxed()
+    }
+
     /// Calculates the number of tokens used by the step given a set of parameters.
     ///
     /// The step and the parameters together are used to form full prompt, which is then tokenized
@@ -121,6 +146,42 @@ pub trait Executor: Sized {
     /// A `Option` containing a String if  prefix exists, or none if there is no prefix
     fn answer_prefix(&self, prompt: &Prompt) -> Option<String>;
 
+    /// Returns true if the executor sends the images of chat messages to the model. Prompts containing images are
+    /// refused for executors that don't, instead of sending the model only their text.
+    ///
+    /// The default implementation returns false.
+    fn supports_images(&self) -> bool {
+        false
+    }
+
+    /// Returns the options constraining generation to JSON matching `schema`, based on `options`, or `None` if the
+    /// executor can't constrain generation, for example with the structured output mode of an API or with a grammar.
+    /// Steps with a schema are executed with these options, or with the schema added to the prompt if there are none.
+    ///
+    /// The default implementation returns `None`.
+    fn json_schema_options(
+        &self,
+        options: Option<&Self::PerInvocationOptions>,
+        schema: &JsonSchema,
+    ) -> Option<Self::PerInvocationOptions> {
+        let _ = (options, schema);
+        None
+    }
+
+    /// Returns the options advertising `tools` to the model, based on `options`, or `None` if the executor doesn't
+    /// support native tool calling. With these options, the model may answer with tool calls, available through
+    /// `Output::tool_calls`, and prompts may contain the results of the calls as messages of the `Tool` role.
+    ///
+    /// The default implementation returns `None`.
+    fn tool_options(
+        &self,
+        options: Option<&Self::PerInvocationOptions>,
+        tools: &[ToolDefinition],
+    ) -> Option<Self::PerInvocationOptions> {
+        let _ = (options, tools);
+        None
+    }
+
     /// Creates a tokenizer, depending on the model used by `step`.
     ///
     /// # Parameters
@@ -147,16 +208,92 @@ pub trait Executor: Sized {
         &self,
         options: Option<&Self::PerInvocationOptions>,
     ) -> Result<Self::TextSplitter<'_>, Self::Error>;
+
+    /// Releases the resources held by the executor, such as connections or model contexts, and flushes any
+    /// buffered data. The executor shouldn't be used after it has been shut down.
+    ///
+    /// The default implementation does nothing.
+    async fn shutdown(&self) -> Result<(), Self::Error> {
+        Ok(())
+    }
 }
 
 /// This marker trait is needed so the concrete VectorStore::Error can have a derived From<Embeddings::Error>
 pub trait EmbeddingsError {}
 
+/// What texts are embedded for.
+///
+/// Some models, such as Cohere's embed v3, embed a text differently depending on how the embedding will be used, and
+/// retrieval works best when documents and queries are embedded as such.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum EmbeddingPurpose {
+    /// Documents stored in a vector store to be searched.
+    #[default]
+    Document,
+    /// Queries searching a vector store.
+    Query,
+    /// Inputs to a text classifier.
+    Classification,
+    /// Texts to be clustered.
+    Clustering,
+}
+
+/// What a provider does with inputs longer than its model accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TruncationPolicy {
+    /// Over-length inputs are rejected with an error.
+    #[default]
+    Error,
+    /// The beginning of the input is kept and the end is dropped.
+    End,
+    /// The end of the input is kept and the beginning is dropped.
+    Start,
+}
+
 #[async_trait]
 pub trait Embeddings {
     type Error: Send + Debug + Error + EmbeddingsError;
     async fn embed_texts(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Self::Error>;
     async fn embed_query(&self, query: String) -> Result<Vec<f32>, Self::Error>;
+
+    /// Returns the length of the vectors this provider produces, if it is known without embedding anything.
+    fn dimensions(&self) -> Option<usize> {
+        None
+    }
+
+    /// Returns what the provider does with over-length inputs, or `None` if that isn't known.
+    fn truncation_policy(&self) -> Option<TruncationPolicy> {
+        None
+    }
+
+    /// Returns the length of the vectors this provider produces, embedding a short text to find out if
+    /// `dimensions` doesn't know it.
+    ///
+    /// Vector stores use this to create collections of the right size.
+    async fn detect_dimensions(&self) -> Result<usize, Self::Error> {
+        match self.dimensions() {
+            Some(dimensions) => Ok(dimensions),
+            None => Ok(self.embed_query("dimensions".to_string()).await?.len()),
+        }
+    }
+
+    /// Embeds texts for the given purpose.
+    ///
+    /// Providers whose models distinguish between purposes override this. By default queries are embedded with
+    /// `embed_query` and all other texts with `embed_texts`.
+    async fn embed_texts_for(
+        &self,
+        texts: Vec<String>,
+        purpose: EmbeddingPurpose,
+    ) -> Result<Vec<Vec<f32>>, Self::Error> {
+        match purpose {
+            EmbeddingPurpose::Query => {
+                futures::future::try_join_all(texts.into_iter().map(|text| self.embed_query(text)))
+                    .await
+            }
+            _ => self.embed_texts(texts).await,
+        }
+    }
 }
 
 /// This marker trait is needed so users of VectorStore can derive From<VectorStore::Error>
@@ -171,9 +308,60 @@ where
     type Error: Debug + Error + VectorStoreError;
     async fn add_texts(&self, texts: Vec<String>) -> Result<Vec<String>, Self::Error>;
     async fn add_documents(&self, documents: Vec<Document<M>>) -> Result<Vec<String>, Self::Error>;
+
+    /// Returns the `limit` documents most similar to `query` with their similarity scores, most similar first.
+    ///
+    /// If `score_threshold` is set, documents scoring below it are left out, so the result may be empty when
+    /// nothing relevant is stored.
+    async fn similarity_search_with_scores(
+        &self,
+        query: String,
+        limit: u32,
+        score_threshold: Option<f32>,
+    ) -> Result<Vec<ScoredDocument<M>>, Self::Error>;
+
+    /// Returns the `limit` documents most similar to the embedding `query`, most similar first, with their similarity
+    /// scores and the embeddings they were stored with.
+    ///
+    /// Callers comparing the results with each other, such as MMR, use the stored embeddings instead of embedding
+    /// the documents again. `score_threshold` works like in `similarity_search_with_scores`.
+    async fn similarity_search_by_vector(
+        &self,
+        query: Vec<f32>,
+        limit: u32,
+        score_threshold: Option<f32>,
+    ) -> Result<Vec<(ScoredDocument<M>, Vec<f32>)>, Self::Error>;
+
+    /// Returns the `limit` documents most similar to `query`, most similar first.
     async fn similarity_search(
         &self,
         query: String,
         limit: u32,
-    ) -> Result<Vec<Document<M>>, Self::Error>;
+    ) -> Result<Vec<Document<M>>, Self::Error>
+    where
+        M: Send,
+    {
+        Ok(self
+            .similarity_search_with_scores(query, limit, None)
+            .await?
+            .into_iter()
+            .map(|scored| scored.document)
+            .collect())
+    }
+
+    /// Inserts documents, replacing any stored documents with the same ids. Documents without an id are assigned a
+    /// new one. Returns the ids of the documents in order.
+    async fn upsert_documents(
+        &self,
+        documents: Vec<Document<M>>,
+    ) -> Result<Vec<String>, Self::Error>;
+
+    /// Fetches the documents with the given ids. Ids that aren't found are skipped.
+    async fn get_by_ids(&self, ids: &[String]) -> Result<Vec<Document<M>>, Self::Error>;
+
+    /// Deletes the documents with the given ids.
+    async fn delete_by_ids(&self, ids: &[String]) -> Result<(), Self::Error>;
+
+    /// Deletes every document whose metadata matches `filter`.
+    async fn delete_by_filter(&self, filter: &MetadataFilter) -> Result<(), Self::Error>;
 }
@@ -0,0 +1,264 @@
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::sync::RwLock;
+
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::{
+    retrieval::cosine_similarity,
+    schema::{Document, EmptyMetadata, MetadataFilter, Provenance, ScoredDocument},
+    traits::{Embeddings, EmbeddingsError, VectorStore, VectorStoreError},
+};
+
+#[derive(Debug, Error)]
+pub enum InMemoryVectorStoreError<E>
+where
+    E: std::fmt::Debug + std::error::Error + EmbeddingsError,
+{
+    #[error(transparent)]
+    Embeddings(#[from] E),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+}
+
+impl<E> VectorStoreError for InMemoryVectorStoreError<E> where
+    E: std::fmt::Debug + std::error::Error + EmbeddingsError
+{
+}
+
+struct Entry {
+    page_content: String,
+    metadata: Option<serde_json::Value>,
+    provenance: Option<Provenance>,
+    embedding: Vec<f32>,
+}
+
+/// A vector store keeping documents and their embeddings in memory.
+///
+/// Searches compare the query against every stored document, so this store is meant for tests, examples and small
+/// corpora rather than production workloads.
+pub struct InMemoryVectorStore<E, M = EmptyMetadata>
+where
+    E: Embeddings,
+    M: Serialize + DeserializeOwned,
+{
+    embeddings: E,
+    entries: RwLock<BTreeMap<String, Entry>>,
+    _marker: PhantomData<fn() -> M>,
+}
+
+impl<E, M> InMemoryVectorStore<E, M>
+where
+    E: Embeddings,
+    M: Serialize + DeserializeOwned,
+{
+    pub fn new(embeddings: E) -> Self {
+        Self {
+            embeddings,
+            entries: RwLock::new(BTreeMap::new()),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the number of stored documents.
+    pub fn len(&self) -> usize {
+        self.entries
+            .read()
+            .expect("vector store lock poisoned")
+            .len()
+    }
+
+    /// Returns `true` if the store contains no documents.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn to_document(
+        id: &str,
+        entry: &Entry,
+    ) -> Result<Document<M>, InMemoryVectorStoreError<E::Error>> {
+        let metadata = match &entry.metadata {
+            Some(metadata) => Some(serde_json::from_value(metadata.clone())?),
+            None => None,
+        };
+        Ok(Document {
+            id: Some(id.to_string()),
+            page_content: entry.page_content.clone(),
+            metadata,
+            provenance: entry.provenance.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl<E, M> VectorStore<E, M> for InMemoryVectorStore<E, M>
+where
+    E: Embeddings + Send + Sync,
+    M: Serialize + DeserializeOwned + Send + Sync,
+{
+    type Error = InMemoryVectorStoreError<E::Error>;
+
+    async fn add_texts(&self, texts: Vec<String>) -> Result<Vec<String>, Self::Error> {
+        self.upsert_documents(texts.into_iter().map(Document::new).collect())
+            .await
+    }
+
+    async fn add_documents(&self, documents: Vec<Document<M>>) -> Result<Vec<String>, Self::Error> {
+        self.upsert_documents(
+            documents
+                .into_iter()
+                .map(|doc| Document { id: None, ..doc })
+                .collect(),
+        )
+        .await
+    }
+
+    async fn similarity_search_with_scores(
+        &self,
+        query: String,
+        limit: u32,
+        score_threshold: Option<f32>,
+    ) -> Result<Vec<ScoredDocument<M>>, Self::Error> {
+        let query = self.embeddings.embed_query(query).await?;
+        Ok(self
+            .similarity_search_by_vector(query, limit, score_threshold)
+            .await?
+            .into_iter()
+            .map(|(scored, _)| scored)
+            .collect())
+    }
+
+    async fn similarity_search_by_vector(
+        &self,
+        query: Vec<f32>,
+        limit: u32,
+        score_threshold: Option<f32>,
+    ) -> Result<Vec<(ScoredDocument<M>, Vec<f32>)>, Self::Error> {
+        let entries = self.entries.read().expect("vector store lock poisoned");
+        let mut scored: Vec<(f32, &String, &Entry)> = entries
+            .iter()
+            .map(|(id, entry)| (cosine_similarity(&query, &entry.embedding), id, entry))
+            .filter(|(score, _, _)| !matches!(score_threshold, Some(t) if *score < t))
+            .collect();
+        scored.sort_by(|(a, _, _), (b, _, _)| b.total_cmp(a));
+        scored
+            .into_iter()
+            .take(limit as usize)
+            .map(|(score, id, entry)| {
+                let scored = ScoredDocument {
+                    document: Self::to_document(id, entry)?,
+                    score,
+                };
+                Ok((scored, entry.embedding.clone()))
+            })
+            .collect()
+    }
+
+    async fn upsert_documents(
+        &self,
+        documents: Vec<Document<M>>,
+    ) -> Result<Vec<String>, Self::Error> {
+        let texts = documents.iter().map(|d| d.page_content.clone()).collect();
+        let embeddings = self.embeddings.embed_texts(texts).await?;
+        let mut new_entries = Vec::with_capacity(documents.len());
+        for (document, embedding) in documents.into_iter().zip(embeddings) {
+            let id = document.id.unwrap_or_else(|| Uuid::new_v4().to_string());
+            let metadata = document
+                .metadata
+                .map(|m| serde_json::to_value(m))
+                .transpose()?;
+            new_entries.push((
+                id,
+                Entry {
+                    page_content: document.page_content,
+                    metadata,
+                    provenance: document.provenance,
+                    embedding,
+                },
+            ));
+        }
+        let ids = new_entries.iter().map(|(id, _)| id.clone()).collect();
+        self.entries
+            .write()
+            .expect("vector store lock poisoned")
+            .extend(new_entries);
+        Ok(ids)
+    }
+
+    async fn get_by_ids(&self, ids: &[String]) -> Result<Vec<Document<M>>, Self::Error> {
+        let entries = self.entries.read().expect("vector store lock poisoned");
+        ids.iter()
+            .filter_map(|id| entries.get(id).map(|entry| Self::to_document(id, entry)))
+            .collect()
+    }
+
+    async fn delete_by_ids(&self, ids: &[String]) -> Result<(), Self::Error> {
+        let mut entries = self.entries.write().expect("vector store lock poisoned");
+        for id in ids {
+            entries.remove(id);
+        }
+        Ok(())
+    }
+
+    async fn delete_by_filter(&self, filter: &MetadataFilter) -> Result<(), Self::Error> {
+        let null = serde_json::Value::Null;
+        self.entries
+            .write()
+            .expect("vector store lock poisoned")
+            .retain(|_, entry| !filter.matches(entry.metadata.as_ref().unwrap_or(&null)));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InMemoryVectorStore;
+    use crate::schema::{Document, MetadataFilter};
+    use crate::test_support::LetterEmbeddings;
+    use crate::traits::VectorStore;
+    use futures::executor::block_on;
+
+    #[test]
+    fn upsert_get_and_delete() {
+        let store = InMemoryVectorStore::<_, serde_json::Value>::new(LetterEmbeddings);
+        block_on(async {
+            let ids = store
+                .upsert_documents(vec![
+                    Document::new("aaa".to_string())
+                        .with_id("a")
+                        .with_metadata(serde_json::json!({ "source": "x" })),
+                    Document::new("bbb".to_string())
+                        .with_metadata(serde_json::json!({ "source": "y" })),
+                ])
+                .await
+                .unwrap();
+            assert_eq!(ids[0], "a");
+
+            store
+                .upsert_documents(vec![Document::new("aab".to_string()).with_id("a")])
+                .await
+                .unwrap();
+            assert_eq!(store.len(), 2);
+            let found = store.get_by_ids(&["a".to_string()]).await.unwrap();
+            assert_eq!(found[0].page_content, "aab");
+
+            let nearest = store.similarity_search("bb".to_string(), 1).await.unwrap();
+            assert_eq!(nearest[0].id.as_ref(), Some(&ids[1]));
+            let relevant = store
+                .similarity_search_with_scores("c".to_string(), 2, Some(0.5))
+                .await
+                .unwrap();
+            assert!(relevant.is_empty());
+
+            store
+                .delete_by_filter(&MetadataFilter::equals("source", "y"))
+                .await
+                .unwrap();
+            store.delete_by_ids(&["a".to_string()]).await.unwrap();
+            assert!(store.is_empty());
+        });
+    }
+}
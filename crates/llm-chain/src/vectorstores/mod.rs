@@ -0,0 +1,13 @@
+//! Vector store backends shipped with llm-chain.
+//!
+//! Backends for external databases live in their own crates, such as `llm-chain-qdrant`. This module contains the
+//! backends that need no external service:
+//!
+//! - `InMemoryVectorStore`: keeps documents and embeddings in memory, useful for tests and small corpora.
+//!
+//! `ObservedVectorStore` wraps any store to tell `IndexObserver`s about the documents it replaces or deletes.
+mod in_memory;
+mod observed;
+
+pub use in_memory::{InMemoryVectorStore, InMemoryVectorStoreError};
+pub use observed::ObservedVectorStore;
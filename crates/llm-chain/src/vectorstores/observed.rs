@@ -0,0 +1,159 @@
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    retrieval::IndexObserver,
+    schema::{Document, MetadataFilter, ScoredDocument},
+    traits::{Embeddings, VectorStore},
+};
+
+/// A vector store telling `IndexObserver`s, such as an `AnswerCache`, about the documents it replaces or deletes.
+///
+/// Observers are told after the operation succeeds: `upsert_documents` and `delete_by_ids` report the ids of the
+/// documents, and `delete_by_filter`, whose documents aren't known, reports that the index was updated.
+pub struct ObservedVectorStore<V, E, M> {
+    store: V,
+    observers: Vec<Arc<dyn IndexObserver + Send + Sync>>,
+    _marker: PhantomData<fn() -> (E, M)>,
+}
+
+impl<V, E, M> ObservedVectorStore<V, E, M>
+where
+    V: VectorStore<E, M>,
+    E: Embeddings,
+    M: Serialize + DeserializeOwned,
+{
+    pub fn new(store: V) -> Self {
+        Self {
+            store,
+            observers: Vec::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Adds an observer told about the documents replaced or deleted.
+    pub fn with_observer<O: IndexObserver + Send + Sync + 'static>(mut self, observer: O) -> Self {
+        self.observers.push(Arc::new(observer));
+        self
+    }
+
+    /// Returns the wrapped store.
+    pub fn inner(&self) -> &V {
+        &self.store
+    }
+
+    fn chunks_updated(&self, ids: &[String]) {
+        for observer in &self.observers {
+            observer.chunks_updated(ids);
+        }
+    }
+}
+
+#[async_trait]
+impl<V, E, M> VectorStore<E, M> for ObservedVectorStore<V, E, M>
+where
+    V: VectorStore<E, M> + Send + Sync,
+    E: Embeddings + Send + Sync,
+    M: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    type Error = V::Error;
+
+    async fn add_texts(&self, texts: Vec<String>) -> Result<Vec<String>, Self::Error> {
+        self.store.add_texts(texts).await
+    }
+
+    async fn add_documents(&self, documents: Vec<Document<M>>) -> Result<Vec<String>, Self::Error> {
+        self.store.add_documents(documents).await
+    }
+
+    async fn similarity_search_with_scores(
+        &self,
+        query: String,
+        limit: u32,
+        score_threshold: Option<f32>,
+    ) -> Result<Vec<ScoredDocument<M>>, Self::Error> {
+        self.store
+            .similarity_search_with_scores(query, limit, score_threshold)
+            .await
+    }
+
+    async fn similarity_search_by_vector(
+        &self,
+        query: Vec<f32>,
+        limit: u32,
+        score_threshold: Option<f32>,
+    ) -> Result<Vec<(ScoredDocument<M>, Vec<f32>)>, Self::Error> {
+        self.store
+            .similarity_search_by_vector(query, limit, score_threshold)
+            .await
+    }
+
+    async fn upsert_documents(
+        &self,
+        documents: Vec<Document<M>>,
+    ) -> Result<Vec<String>, Self::Error> {
+        let ids = self.store.upsert_documents(documents).await?;
+        self.chunks_updated(&ids);
+        Ok(ids)
+    }
+
+    async fn get_by_ids(&self, ids: &[String]) -> Result<Vec<Document<M>>, Self::Error> {
+        self.store.get_by_ids(ids).await
+    }
+
+    async fn delete_by_ids(&self, ids: &[String]) -> Result<(), Self::Error> {
+        self.store.delete_by_ids(ids).await?;
+        self.chunks_updated(ids);
+        Ok(())
+    }
+
+    async fn delete_by_filter(&self, filter: &MetadataFilter) -> Result<(), Self::Error> {
+        self.store.delete_by_filter(filter).await?;
+        for observer in &self.observers {
+            observer.index_updated();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ObservedVectorStore;
+    use crate::retrieval::AnswerCache;
+    use crate::schema::{Document, MetadataFilter};
+    use crate::test_support::LetterEmbeddings;
+    use crate::traits::VectorStore;
+    use crate::vectorstores::InMemoryVectorStore;
+    use futures::executor::block_on;
+
+    #[test]
+    fn invalidates_answers_built_on_replaced_or_deleted_documents() {
+        let cache = AnswerCache::new();
+        let store = ObservedVectorStore::new(InMemoryVectorStore::<_, serde_json::Value>::new(
+            LetterEmbeddings,
+        ))
+        .with_observer(cache.clone());
+        let documents = ["a", "b", "c"]
+            .iter()
+            .map(|id| Document::new(id.to_string()).with_id(*id))
+            .collect();
+        block_on(store.upsert_documents(documents)).unwrap();
+        cache.insert(vec![1.0], ["a"], 1);
+        cache.insert(vec![1.0], ["b"], 2);
+        cache.insert(vec![1.0], ["c"], 3);
+
+        block_on(store.upsert_documents(vec![Document::new("aa".to_string()).with_id("a")]))
+            .unwrap();
+        assert_eq!(cache.get(&[1.0], ["a"]), None);
+        block_on(store.delete_by_ids(&["b".to_string()])).unwrap();
+        assert_eq!(cache.get(&[1.0], ["b"]), None);
+        assert_eq!(cache.get(&[1.0], ["c"]), Some(3));
+
+        let filter = MetadataFilter::equals("lang", "en");
+        block_on(store.delete_by_filter(&filter)).unwrap();
+        assert!(cache.is_empty());
+    }
+}